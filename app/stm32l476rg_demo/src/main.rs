@@ -9,6 +9,7 @@
 // We have to do this if we don't otherwise use it to ensure its vector table
 // gets linked in.
 extern crate stm32l476rg;
+#[cfg(not(feature = "coredump"))]
 extern crate panic_itm;
 
 mod clocks;
@@ -52,6 +52,13 @@ SECTIONS
     __ebss = .;
   } > RAM
 
+  /* ## Format strings of the deferred logs (see userlib::log)
+     Never loaded: the address of each string is its index in the table */
+  .concept_log 0 (INFO) :
+  {
+    *(.concept_log .concept_log.*);
+  }
+
   /* ## Discarded sections */
   /DISCARD/ :
   {
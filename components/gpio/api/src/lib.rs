@@ -0,0 +1,68 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+#![no_std]
+
+use userlib::FromPrimitive;
+
+// Generated from gpio.idl.toml
+include!(concat!(env!("OUT_DIR"), "/client_stub.rs"));
+
+/// Pins of a port
+pub const PINS_PER_PORT: usize = 16;
+
+// Not every board has all of them
+#[derive(Copy, Clone, Debug, PartialEq, FromPrimitive)]
+pub enum Port {
+    A = 0,
+    B = 1,
+    C = 2,
+    D = 3,
+    E = 4,
+    F = 5,
+    G = 6,
+    H = 7,
+    I = 8,
+}
+
+// Values of the MODER fields
+#[derive(Copy, Clone, Debug, PartialEq, FromPrimitive)]
+pub enum Mode {
+    Input = 0,
+    Output = 1,
+    Alternate = 2,
+    Analog = 3,
+}
+
+// Values of the OTYPER fields
+#[derive(Copy, Clone, Debug, PartialEq, FromPrimitive)]
+pub enum OutputType {
+    PushPull = 0,
+    OpenDrain = 1,
+}
+
+// Values of the OSPEEDR fields
+#[derive(Copy, Clone, Debug, PartialEq, FromPrimitive)]
+pub enum Speed {
+    Low = 0,
+    Medium = 1,
+    High = 2,
+    VeryHigh = 3,
+}
+
+// Values of the PUPDR fields
+#[derive(Copy, Clone, Debug, PartialEq, FromPrimitive)]
+pub enum Pull {
+    None = 0,
+    Up = 1,
+    Down = 2,
+}
+
+// Edges notified on an EXTI line
+#[derive(Copy, Clone, Debug, PartialEq, FromPrimitive)]
+pub enum Edge {
+    Rising = 1,
+    Falling = 2,
+    Both = 3,
+}
@@ -0,0 +1,388 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+#![no_std]
+#![no_main]
+
+use gpio_api::*;
+use gpio_pins::{PinError, Pins};
+use rcc_api::{Peripheral, RCC};
+use userlib::*;
+use zerocopy::AsBytes;
+
+// STM32F3
+#[cfg(feature = "board_stm32f303re")]
+use stm32f303re::device;
+
+// STM32L432
+#[cfg(feature = "board_stm32l432kc")]
+use stm32l432kc::device;
+
+// STM32L476
+#[cfg(feature = "board_stm32l476rg")]
+use stm32l476rg::device;
+
+// Generated from gpio.idl.toml
+include!(concat!(env!("OUT_DIR"), "/server_stub.rs"));
+
+/**
+ * Constants
+ */
+// Notification bits of the EXTI interrupts, as in Component.toml. Lines 0 to 4
+// have one each, lines 5 to 9 and 10 to 15 share one.
+const EXTI9_5_IRQ_MASK: u32 = 1 << 5;
+const EXTI15_10_IRQ_MASK: u32 = 1 << 6;
+const EXTI_IRQ_MASK: u32 = 0b111_1111;
+
+// None of the ports share the register block type, even if the fields are the
+// same, so the body is expanded once for each port the board has.
+macro_rules! with_port {
+    ($port:expr, |$gpio:ident| $body:expr) => {
+        match $port {
+            Port::A => {
+                let $gpio = unsafe { &*device::GPIOA::ptr() };
+                $body
+            }
+            Port::B => {
+                let $gpio = unsafe { &*device::GPIOB::ptr() };
+                $body
+            }
+            Port::C => {
+                let $gpio = unsafe { &*device::GPIOC::ptr() };
+                $body
+            }
+            Port::D => {
+                let $gpio = unsafe { &*device::GPIOD::ptr() };
+                $body
+            }
+            Port::E => {
+                let $gpio = unsafe { &*device::GPIOE::ptr() };
+                $body
+            }
+            #[cfg(any(feature = "board_stm32f303re", feature = "board_stm32l476rg"))]
+            Port::F => {
+                let $gpio = unsafe { &*device::GPIOF::ptr() };
+                $body
+            }
+            #[cfg(any(feature = "board_stm32f303re", feature = "board_stm32l476rg"))]
+            Port::G => {
+                let $gpio = unsafe { &*device::GPIOG::ptr() };
+                $body
+            }
+            Port::H => {
+                let $gpio = unsafe { &*device::GPIOH::ptr() };
+                $body
+            }
+            #[cfg(feature = "board_stm32l476rg")]
+            Port::I => {
+                let $gpio = unsafe { &*device::GPIOI::ptr() };
+                $body
+            }
+            #[allow(unreachable_patterns)]
+            _ => Err(GPIOError::InvalidPort),
+        }
+    };
+}
+
+// Replaces the bits of the mask, leaving the others as they are
+macro_rules! update_bits {
+    ($reg:expr, $mask:expr, $bits:expr) => {
+        $reg.modify(|r, w| unsafe { w.bits((r.bits() & !$mask) | $bits) })
+    };
+}
+
+/// Mask and value of the fields of the pins, `width` bits each
+fn fields(pins: u16, width: u32, value: u32) -> (u32, u32) {
+    let mut mask: u32 = 0;
+    let mut bits: u32 = 0;
+    for pin in 0..PINS_PER_PORT as u32 {
+        if pins & (1 << pin) != 0 {
+            mask |= ((1 << width) - 1) << (pin * width);
+            bits |= value << (pin * width);
+        }
+    }
+    return (mask, bits);
+}
+
+fn port_peripheral(port: Port) -> Peripheral {
+    match port {
+        Port::A => Peripheral::GPIOA,
+        Port::B => Peripheral::GPIOB,
+        Port::C => Peripheral::GPIOC,
+        Port::D => Peripheral::GPIOD,
+        Port::E => Peripheral::GPIOE,
+        Port::F => Peripheral::GPIOF,
+        Port::G => Peripheral::GPIOG,
+        Port::H => Peripheral::GPIOH,
+        Port::I => Peripheral::GPIOI,
+    }
+}
+
+/// Notification bit of the interrupt of the EXTI line
+fn line_irq_mask(line: usize) -> u32 {
+    match line {
+        0..=4 => 1 << line,
+        5..=9 => EXTI9_5_IRQ_MASK,
+        _ => EXTI15_10_IRQ_MASK,
+    }
+}
+
+fn pin_error(error: PinError) -> GPIOError {
+    match error {
+        PinError::InvalidPin => GPIOError::InvalidPin,
+        PinError::PinInUse => GPIOError::PinInUse,
+        PinError::PinNotOwned => GPIOError::PinNotOwned,
+        PinError::LineInUse => GPIOError::LineInUse,
+    }
+}
+
+/**
+ * Server
+ */
+#[derive(Clone, Copy)]
+struct Subscription {
+    task: TaskId,
+    notification: u32,
+}
+
+struct Server {
+    rcc: RCC,
+    syscfg: &'static device::syscfg::RegisterBlock,
+    exti: &'static device::exti::RegisterBlock,
+    /// Ports with the clock on, a bit each
+    powered: u16,
+    /// Owners of the pins and subscribers of the EXTI lines
+    pins: Pins<Subscription>,
+}
+
+impl Server {
+    /// Port of the request, its clock is turned on the first time
+    fn port(&mut self, port: u32) -> Result<Port, GPIOError> {
+        let port = Port::from_u32(port).ok_or(GPIOError::InvalidPort)?;
+        if self.powered & (1 << port as u16) == 0 {
+            // Fails for the ports the board does not have
+            let peripheral = port_peripheral(port);
+            self.rcc
+                .enable_clock(peripheral)
+                .map_err(|_| GPIOError::InvalidPort)?;
+            self.rcc
+                .leave_reset(peripheral)
+                .map_err(|_| GPIOError::InvalidPort)?;
+            self.powered |= 1 << port as u16;
+        }
+        Ok(port)
+    }
+
+    /// Pins of the request, all of them owned by the sender
+    fn owned(&self, sender: TaskId, port: Port, pins: u32) -> Result<u16, GPIOError> {
+        self.pins
+            .owned(sender.component_id(), port as usize, pins)
+            .map_err(pin_error)
+    }
+
+    /// Disconnects the EXTI lines, once unrouted
+    fn disable_lines(&mut self, lines: u16) {
+        let mask = lines as u32;
+        update_bits!(self.exti.imr1, mask, 0);
+        update_bits!(self.exti.rtsr1, mask, 0);
+        update_bits!(self.exti.ftsr1, mask, 0);
+    }
+
+    /// Forwards the edges to the subscribers of the lines
+    fn handle_exti(&mut self, bits: u32) {
+        let pending = self.exti.pr1.read().bits() & 0xFFFF;
+        // Cleared by writing 1
+        self.exti.pr1.write(|w| unsafe { w.bits(pending) });
+        for line in 0..PINS_PER_PORT {
+            if pending & (1 << line) == 0 {
+                continue;
+            }
+            if let Some(route) = self.pins.route_of_mut(line) {
+                let subscription = &mut route.subscriber;
+                // The subscriber may have been restarted in the meantime
+                subscription.task = sys_refresh_task_id(subscription.task);
+                sys_post(subscription.task, subscription.notification);
+            }
+        }
+        sys_irq_control(bits & EXTI_IRQ_MASK, true);
+    }
+}
+
+impl GPIOServer for Server {
+    fn claim(&mut self, msg: &ClaimRequest, sender: TaskId) -> Result<(), GPIOError> {
+        let port = self.port(msg.port)?;
+        self.pins
+            .claim(sender.component_id(), port as usize, msg.pins)
+            .map_err(pin_error)
+    }
+
+    fn release(&mut self, msg: &ReleaseRequest, sender: TaskId) -> Result<(), GPIOError> {
+        let port = self.port(msg.port)?;
+        let lines = self
+            .pins
+            .release(sender.component_id(), port as usize, msg.pins)
+            .map_err(pin_error)?;
+        self.disable_lines(lines);
+        Ok(())
+    }
+
+    fn configure(&mut self, msg: &ConfigureRequest, sender: TaskId) -> Result<(), GPIOError> {
+        let port = self.port(msg.port)?;
+        let pins = self.owned(sender, port, msg.pins)?;
+        let mode = Mode::from_u32(msg.mode).ok_or(GPIOError::BadArgument)?;
+        let output_type = OutputType::from_u32(msg.output_type).ok_or(GPIOError::BadArgument)?;
+        let speed = Speed::from_u32(msg.speed).ok_or(GPIOError::BadArgument)?;
+        let pull = Pull::from_u32(msg.pull).ok_or(GPIOError::BadArgument)?;
+        let (mode_mask, mode_bits) = fields(pins, 2, mode as u32);
+        let (type_mask, type_bits) = fields(pins, 1, output_type as u32);
+        let (speed_mask, speed_bits) = fields(pins, 2, speed as u32);
+        let (pull_mask, pull_bits) = fields(pins, 2, pull as u32);
+        with_port!(port, |gpio| {
+            // The mode last, the pin is driven only once it is set up
+            update_bits!(gpio.otyper, type_mask, type_bits);
+            update_bits!(gpio.ospeedr, speed_mask, speed_bits);
+            update_bits!(gpio.pupdr, pull_mask, pull_bits);
+            update_bits!(gpio.moder, mode_mask, mode_bits);
+            Ok(())
+        })
+    }
+
+    fn set_alternate(
+        &mut self,
+        msg: &SetAlternateRequest,
+        sender: TaskId,
+    ) -> Result<(), GPIOError> {
+        let port = self.port(msg.port)?;
+        let pins = self.owned(sender, port, msg.pins)?;
+        if msg.function > 15 {
+            return Err(GPIOError::BadArgument);
+        }
+        // AFRL for the pins 0 to 7, AFRH for the others
+        let (low_mask, low_bits) = fields(pins & 0xFF, 4, msg.function);
+        let (high_mask, high_bits) = fields(pins >> 8, 4, msg.function);
+        let (mode_mask, mode_bits) = fields(pins, 2, Mode::Alternate as u32);
+        with_port!(port, |gpio| {
+            update_bits!(gpio.afrl, low_mask, low_bits);
+            update_bits!(gpio.afrh, high_mask, high_bits);
+            update_bits!(gpio.moder, mode_mask, mode_bits);
+            Ok(())
+        })
+    }
+
+    fn set(&mut self, msg: &SetRequest, sender: TaskId) -> Result<(), GPIOError> {
+        let port = self.port(msg.port)?;
+        let pins = self.owned(sender, port, msg.pins)?;
+        with_port!(port, |gpio| {
+            gpio.bsrr.write(|w| unsafe { w.bits(pins as u32) });
+            Ok(())
+        })
+    }
+
+    fn reset(&mut self, msg: &ResetRequest, sender: TaskId) -> Result<(), GPIOError> {
+        let port = self.port(msg.port)?;
+        let pins = self.owned(sender, port, msg.pins)?;
+        with_port!(port, |gpio| {
+            gpio.bsrr.write(|w| unsafe { w.bits((pins as u32) << 16) });
+            Ok(())
+        })
+    }
+
+    fn toggle(&mut self, msg: &ToggleRequest, sender: TaskId) -> Result<(), GPIOError> {
+        let port = self.port(msg.port)?;
+        let pins = self.owned(sender, port, msg.pins)? as u32;
+        with_port!(port, |gpio| {
+            // Through BSRR, so that the other pins are not touched
+            let high = gpio.odr.read().bits() & pins;
+            gpio.bsrr
+                .write(|w| unsafe { w.bits((high << 16) | (!high & pins)) });
+            Ok(())
+        })
+    }
+
+    fn read(&mut self, msg: &ReadRequest) -> Result<ReadResponse, GPIOError> {
+        let port = self.port(msg.port)?;
+        with_port!(port, |gpio| {
+            Ok(ReadResponse {
+                value: gpio.idr.read().bits() & 0xFFFF,
+            })
+        })
+    }
+
+    fn subscribe(&mut self, msg: &SubscribeRequest, sender: TaskId) -> Result<(), GPIOError> {
+        let port = self.port(msg.port)?;
+        let edge = Edge::from_u32(msg.edge).ok_or(GPIOError::BadArgument)?;
+        if msg.notification == 0 {
+            return Err(GPIOError::BadArgument);
+        }
+        // The line is shared by the pins with the same number on every port
+        let subscription = Subscription {
+            task: sender,
+            notification: msg.notification,
+        };
+        let line = self
+            .pins
+            .route(sender.component_id(), port as usize, msg.pin, subscription)
+            .map_err(pin_error)?;
+        // Select the port of the line in EXTICR1..4, 4 bits each
+        let shift = (line % 4) * 4;
+        let (mask, bits) = (0xF << shift, (port as u32) << shift);
+        match line / 4 {
+            0 => update_bits!(self.syscfg.exticr1, mask, bits),
+            1 => update_bits!(self.syscfg.exticr2, mask, bits),
+            2 => update_bits!(self.syscfg.exticr3, mask, bits),
+            _ => update_bits!(self.syscfg.exticr4, mask, bits),
+        };
+        let line_mask: u32 = 1 << line;
+        let rising = if edge != Edge::Falling { line_mask } else { 0 };
+        let falling = if edge != Edge::Rising { line_mask } else { 0 };
+        update_bits!(self.exti.rtsr1, line_mask, rising);
+        update_bits!(self.exti.ftsr1, line_mask, falling);
+        // Drop an edge left pending by a previous use of the line
+        self.exti.pr1.write(|w| unsafe { w.bits(line_mask) });
+        update_bits!(self.exti.imr1, line_mask, line_mask);
+        sys_irq_control(line_irq_mask(line), true);
+        Ok(())
+    }
+
+    fn unsubscribe(&mut self, msg: &UnsubscribeRequest, sender: TaskId) -> Result<(), GPIOError> {
+        let port = self.port(msg.port)?;
+        let line = self
+            .pins
+            .unroute(sender.component_id(), port as usize, msg.pin)
+            .map_err(pin_error)?;
+        if let Some(line) = line {
+            self.disable_lines(1 << line);
+        }
+        Ok(())
+    }
+}
+
+#[export_name = "main"]
+fn main() -> ! {
+    kipc::activate_task();
+    // SYSCFG selects the port of each EXTI line
+    let mut rcc = RCC::new();
+    rcc.enable_clock(Peripheral::SYSCFG).unwrap();
+    rcc.leave_reset(Peripheral::SYSCFG).unwrap();
+    let mut server = Server {
+        rcc: rcc,
+        syscfg: unsafe { &*device::SYSCFG::ptr() },
+        exti: unsafe { &*device::EXTI::ptr() },
+        powered: 0,
+        pins: Pins::new(),
+    };
+    sys_log!("[GPIO] Online!");
+    // Incoming message buffer, as u32 for the alignment of the requests. The
+    // biggest one is the one of Configure.
+    let mut buffer = [0u32; 6];
+    loop {
+        hl::recv(
+            buffer.as_bytes_mut(),
+            EXTI_IRQ_MASK,
+            &mut server,
+            |server, bits| server.handle_exti(bits),
+            |server, op, msg| server.dispatch(op, msg),
+        );
+    }
+}
@@ -0,0 +1,11 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+#![no_std]
+
+/// Longest transfer in each direction
+pub use i2c_controller::MAX_TRANSFER;
+
+// Generated from i2c.idl.toml
+include!(concat!(env!("OUT_DIR"), "/client_stub.rs"));
@@ -0,0 +1,355 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+#![no_std]
+#![no_main]
+
+use gpio_api::{GPIOError, Mode, OutputType, Port, Pull, Speed, GPIO};
+use i2c_api::*;
+use i2c_controller::{timing, Controller, I2cError, Poll, Register, Registers};
+use rcc_api::{Peripheral, RCCError, RCC};
+use userlib::{hl::Borrow, *};
+use zerocopy::AsBytes;
+
+// STM32F3
+#[cfg(feature = "board_stm32f303re")]
+use stm32f303re::device;
+
+// STM32L432
+#[cfg(feature = "board_stm32l432kc")]
+use stm32l432kc::device;
+
+// STM32L476
+#[cfg(feature = "board_stm32l476rg")]
+use stm32l476rg::device;
+
+// Generated from i2c.idl.toml
+include!(concat!(env!("OUT_DIR"), "/server_stub.rs"));
+
+/**
+ * Constants
+ */
+// Notification bits of the interrupts, as in Component.toml
+const I2C_EV_IRQ_MASK: u32 = 1 << 0;
+const I2C_ER_IRQ_MASK: u32 = 1 << 1;
+const I2C_IRQ_MASK: u32 = I2C_EV_IRQ_MASK | I2C_ER_IRQ_MASK;
+const TIMEOUT_MASK: u32 = 1 << 15;
+
+/// Longest transaction, in kernel ticks, before the bus is given up
+const TIMEOUT_TICKS: u64 = 100;
+
+// DMA channels of this instance (see build.rs)
+include!(concat!(env!("OUT_DIR"), "/config.rs"));
+
+/// Frequency of the bus, TIMINGR is computed from the clock of I2C1
+const I2C_FREQUENCY: u32 = 100_000;
+
+/**
+ * Hardware
+ */
+struct Bus(&'static device::i2c1::RegisterBlock);
+
+impl Registers for Bus {
+    fn read(&mut self, register: Register) -> u32 {
+        match register {
+            Register::Cr1 => self.0.cr1.read().bits(),
+            Register::Cr2 => self.0.cr2.read().bits(),
+            Register::Isr => self.0.isr.read().bits(),
+            Register::Rxdr => self.0.rxdr.read().bits(),
+            // Write only
+            Register::Icr | Register::Txdr => 0,
+        }
+    }
+
+    fn write(&mut self, register: Register, value: u32) {
+        match register {
+            Register::Cr1 => self.0.cr1.write(|w| unsafe { w.bits(value) }),
+            Register::Cr2 => self.0.cr2.write(|w| unsafe { w.bits(value) }),
+            Register::Icr => self.0.icr.write(|w| unsafe { w.bits(value) }),
+            Register::Txdr => self.0.txdr.write(|w| unsafe { w.bits(value) }),
+            // Read only
+            Register::Isr | Register::Rxdr => {}
+        }
+    }
+}
+
+/**
+ * DMA Support (L4 only)
+ * I2C1_RX -> RX_CHANNEL of DMA, I2C1_TX -> TX_CHANNEL of DMA, from the settings
+ * See: RM0394/pag.299 (L432),  RM0351/pag.340 (L476)
+ */
+#[cfg(any(feature = "board_stm32l432kc", feature = "board_stm32l476rg"))]
+const DMA_GIF: u32 = 0b0001;
+
+/// Position of the flags of a channel in ISR and IFCR, and of its request in CSELR
+#[cfg(any(feature = "board_stm32l432kc", feature = "board_stm32l476rg"))]
+fn channel_shift(channel: u32) -> u32 {
+    4 * (channel - 1)
+}
+
+#[cfg(any(feature = "board_stm32l432kc", feature = "board_stm32l476rg"))]
+struct BusDma {
+    dma: &'static device::dma1::RegisterBlock,
+    i2c: &'static device::i2c1::RegisterBlock,
+}
+
+#[cfg(any(feature = "board_stm32l432kc", feature = "board_stm32l476rg"))]
+impl i2c_controller::Dma for BusDma {
+    fn transmit(&mut self, source: *const u8, len: usize) -> bool {
+        tx_ccr!(self.dma).write(|w| unsafe { w.bits(0) });
+        tx_cpar!(self.dma).write(|w| unsafe { w.bits(self.i2c.txdr.as_ptr() as u32) });
+        tx_cmar!(self.dma).write(|w| unsafe { w.bits(source as u32) });
+        tx_cndtr!(self.dma).write(|w| unsafe { w.bits(len as u32) });
+        // Memory to peripheral, the completion is seen on the I2C side
+        tx_ccr!(self.dma).write(|w| w.dir().set_bit().minc().set_bit().en().set_bit());
+        return true;
+    }
+
+    fn receive(&mut self, destination: *mut u8, len: usize) -> bool {
+        rx_ccr!(self.dma).write(|w| unsafe { w.bits(0) });
+        rx_cpar!(self.dma).write(|w| unsafe { w.bits(self.i2c.rxdr.as_ptr() as u32) });
+        rx_cmar!(self.dma).write(|w| unsafe { w.bits(destination as u32) });
+        rx_cndtr!(self.dma).write(|w| unsafe { w.bits(len as u32) });
+        rx_ccr!(self.dma).write(|w| w.dir().clear_bit().minc().set_bit().en().set_bit());
+        return true;
+    }
+
+    fn stop(&mut self) {
+        rx_ccr!(self.dma).modify(|_, w| w.en().clear_bit());
+        tx_ccr!(self.dma).modify(|_, w| w.en().clear_bit());
+        self.dma.ifcr.write(|w| unsafe {
+            w.bits(DMA_GIF << channel_shift(RX_CHANNEL) | DMA_GIF << channel_shift(TX_CHANNEL))
+        });
+    }
+}
+
+#[cfg(feature = "board_stm32f303re")]
+type BusDma = i2c_controller::NoDma;
+
+#[cfg(any(feature = "board_stm32l432kc", feature = "board_stm32l476rg"))]
+fn setup_dma(i2c: &'static device::i2c1::RegisterBlock) -> Result<BusDma, RCCError> {
+    // Not reset, the other channels serve other components
+    let mut rcc = RCC::new();
+    rcc.enable_clock(DMA)?;
+    let dma = dma_registers();
+    // Select I2C1 for both channels. CSELR is shared with the components on
+    // the other channels, that may be setting it up too.
+    let mask = 0b1111 << channel_shift(RX_CHANNEL) | 0b1111 << channel_shift(TX_CHANNEL);
+    let request =
+        DMA_REQUEST << channel_shift(RX_CHANNEL) | DMA_REQUEST << channel_shift(TX_CHANNEL);
+    unsafe { util::modify_shared(dma.cselr.as_ptr(), mask, request) };
+    Ok(BusDma { dma: dma, i2c: i2c })
+}
+
+#[cfg(feature = "board_stm32f303re")]
+fn setup_dma(_: &'static device::i2c1::RegisterBlock) -> Result<BusDma, RCCError> {
+    Ok(i2c_controller::NoDma)
+}
+
+#[cfg(any(feature = "board_stm32f303re", feature = "board_stm32l476rg"))]
+/// I2C1 on GPIOB (SCL pin 8, SDA pin 9)
+fn setup_gpio() -> Result<(), GPIOError> {
+    let pins = 1 << 8 | 1 << 9;
+    let mut gpio = GPIO::new();
+    gpio.claim(Port::B, pins)?;
+    // Still inputs, the lines are driven only once the function is selected
+    gpio.configure(
+        Port::B,
+        pins,
+        Mode::Input,
+        OutputType::OpenDrain,
+        Speed::High,
+        Pull::Up,
+    )?;
+    // Setup Alternate Function 4
+    gpio.set_alternate(Port::B, pins, 4)
+}
+
+#[cfg(feature = "board_stm32l432kc")]
+/// I2C1 on GPIOB (SCL pin 6, SDA pin 7)
+fn setup_gpio() -> Result<(), GPIOError> {
+    let pins = 1 << 6 | 1 << 7;
+    let mut gpio = GPIO::new();
+    gpio.claim(Port::B, pins)?;
+    // Still inputs, the lines are driven only once the function is selected
+    gpio.configure(
+        Port::B,
+        pins,
+        Mode::Input,
+        OutputType::OpenDrain,
+        Speed::High,
+        Pull::Up,
+    )?;
+    // Setup Alternate Function 4
+    gpio.set_alternate(Port::B, pins, 4)
+}
+
+fn setup_i2c(i2c: &device::i2c1::RegisterBlock) -> Result<(), RCCError> {
+    let mut rcc = RCC::new();
+    rcc.enable_clock(Peripheral::I2C1)?;
+    rcc.leave_reset(Peripheral::I2C1)?;
+    // The timing is set with the peripheral disabled, the controller enables it
+    i2c.cr1.write(|w| unsafe { w.bits(0) });
+    let clock = rcc.get_clock_frequency(Peripheral::I2C1)?;
+    let timing = match timing(clock, I2C_FREQUENCY) {
+        Some(timing) => timing,
+        None => panic!("[I2C] No timing for a {} Hz clock", clock),
+    };
+    i2c.timingr.write(|w| unsafe { w.bits(timing) });
+    Ok(())
+}
+
+fn map_error(error: I2cError) -> I2CError {
+    match error {
+        I2cError::BadLength => I2CError::BadLength,
+        I2cError::BadAddress => I2CError::BadAddress,
+        I2cError::Nack => I2CError::Nack,
+        I2cError::BusError => I2CError::BusError,
+        I2cError::ArbitrationLost => I2CError::ArbitrationLost,
+        // Only without clock stretching, which is never disabled here
+        I2cError::Overrun => I2CError::BusError,
+    }
+}
+
+/**
+ * Server
+ */
+struct Server {
+    controller: Controller<Bus, BusDma>,
+    /// Component ID of the one holding the bus lock
+    owner: Option<u16>,
+}
+
+impl Server {
+    fn check_lock(&self, sender: TaskId) -> Result<(), I2CError> {
+        match self.owner {
+            Some(owner) if owner != sender.component_id() => Err(I2CError::BusLocked),
+            _ => Ok(()),
+        }
+    }
+
+    /// Copies the data of the lease to the controller
+    fn load(&mut self, data: &Borrow<'_>, data_len: usize) -> Result<(), I2CError> {
+        if data_len > MAX_TRANSFER {
+            return Err(I2CError::BadLength);
+        }
+        data.read_fully_at(0, &mut self.controller.tx_buffer()[0..data_len])
+            .ok_or(I2CError::BadArgument)
+    }
+
+    /// Runs a transaction to its end, waiting for the interrupts here: the
+    /// other requests are served once the bus is free again
+    fn transfer(
+        &mut self,
+        address: u32,
+        write_len: usize,
+        read_len: usize,
+    ) -> Result<(), I2CError> {
+        if address > 0x7F {
+            return Err(I2CError::BadAddress);
+        }
+        self.controller
+            .start(address as u8, write_len, read_len)
+            .map_err(map_error)?;
+        let deadline = sys_get_timer().now + TIMEOUT_TICKS;
+        sys_set_timer(Some(deadline), TIMEOUT_MASK);
+        loop {
+            let bits = sys_recv_closed(&mut [], I2C_IRQ_MASK | TIMEOUT_MASK, TaskId::KERNEL)
+                .map_or(0, |message| message.operation);
+            if bits & I2C_IRQ_MASK != 0 {
+                let poll = self.controller.on_event();
+                sys_irq_control(bits & I2C_IRQ_MASK, true);
+                if let Poll::Done(result) = poll {
+                    sys_set_timer(None, TIMEOUT_MASK);
+                    return result.map_err(map_error);
+                }
+            }
+            if bits & TIMEOUT_MASK != 0 && sys_get_timer().now >= deadline {
+                // A device holding SDA low, or no pull-ups on the lines
+                self.controller.abort();
+                return Err(I2CError::Timeout);
+            }
+        }
+    }
+}
+
+impl I2CServer for Server {
+    fn write(
+        &mut self,
+        msg: &WriteRequest,
+        sender: TaskId,
+        data: &Borrow<'_>,
+        data_len: usize,
+    ) -> Result<(), I2CError> {
+        self.check_lock(sender)?;
+        self.load(data, data_len)?;
+        self.transfer(msg.address, data_len, 0)
+    }
+
+    fn read(
+        &mut self,
+        msg: &ReadRequest,
+        sender: TaskId,
+        buffer: &Borrow<'_>,
+        buffer_len: usize,
+    ) -> Result<(), I2CError> {
+        self.check_lock(sender)?;
+        self.transfer(msg.address, 0, buffer_len)?;
+        buffer
+            .write_fully_at(0, self.controller.received())
+            .ok_or(I2CError::BadArgument)
+    }
+
+    fn write_read(
+        &mut self,
+        msg: &WriteReadRequest,
+        sender: TaskId,
+        data: &Borrow<'_>,
+        data_len: usize,
+        buffer: &Borrow<'_>,
+        buffer_len: usize,
+    ) -> Result<(), I2CError> {
+        self.check_lock(sender)?;
+        self.load(data, data_len)?;
+        self.transfer(msg.address, data_len, buffer_len)?;
+        buffer
+            .write_fully_at(0, self.controller.received())
+            .ok_or(I2CError::BadArgument)
+    }
+
+    fn lock(&mut self, _msg: &LockRequest, sender: TaskId) -> Result<(), I2CError> {
+        self.check_lock(sender)?;
+        self.owner = Some(sender.component_id());
+        Ok(())
+    }
+
+    fn unlock(&mut self, _msg: &UnlockRequest, sender: TaskId) -> Result<(), I2CError> {
+        if self.owner != Some(sender.component_id()) {
+            return Err(I2CError::NotLocked);
+        }
+        self.owner = None;
+        Ok(())
+    }
+}
+
+#[export_name = "main"]
+fn main() -> ! {
+    kipc::activate_task();
+    let i2c = unsafe { &*device::I2C1::ptr() };
+    setup_gpio().unwrap();
+    setup_i2c(i2c).unwrap();
+    let dma = setup_dma(i2c).unwrap();
+    let mut server = Server {
+        controller: Controller::new(Bus(i2c), dma),
+        owner: None,
+    };
+    sys_irq_control(I2C_IRQ_MASK, true);
+    sys_log!("[I2C] Online!");
+    // Incoming message buffer, as u32 for the alignment of the requests
+    let mut buffer = [0u32; 1];
+    loop {
+        // The interrupts are waited for only during the transactions
+        hl::recv_without_notification(buffer.as_bytes_mut(), |op, msg| server.dispatch(op, msg));
+    }
+}
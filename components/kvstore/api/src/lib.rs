@@ -0,0 +1,11 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+#![no_std]
+
+/// Limits of keys and values
+pub use kv_log::{MAX_KEY_LEN, MAX_VALUE_LEN};
+
+// Generated from kvstore.idl.toml
+include!(concat!(env!("OUT_DIR"), "/client_stub.rs"));
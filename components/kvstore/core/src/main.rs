@@ -0,0 +1,232 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+#![no_std]
+#![no_main]
+
+use kv_log::{Backend, BackendError, KvError};
+use kvstore_api::*;
+use storage_api::{Storage, StorageError};
+use userlib::{flash::BlockType, hl::Borrow, *};
+
+/// Size requested for the block of the store, compacted when full
+const STORE_BLOCK_SIZE: u32 = 2048;
+
+// Generated from kvstore.idl.toml
+include!(concat!(env!("OUT_DIR"), "/server_stub.rs"));
+
+/// DATA blocks through the storage component
+struct StorageBackend {
+    storage: Storage,
+}
+
+impl Backend for StorageBackend {
+    fn allocate(&mut self, size: u32) -> Result<(u32, u32), BackendError> {
+        let response = self.storage.allocate_data(size).map_err(|_| BackendError)?;
+        Ok((response.block_base_address, response.block_size))
+    }
+
+    fn finalize(&mut self, base_address: u32) -> Result<(), BackendError> {
+        match self.storage.finalize_block(base_address) {
+            Ok(()) | Err(StorageError::BlockIsFinalized) => Ok(()),
+            Err(_) => Err(BackendError),
+        }
+    }
+
+    fn deallocate(&mut self, base_address: u32) -> Result<(), BackendError> {
+        self.storage
+            .deallocate_block(base_address)
+            .map_err(|_| BackendError)
+    }
+
+    fn read(&self, base_address: u32, offset: u32, buffer: &mut [u8]) -> Result<(), BackendError> {
+        self.storage
+            .read_stream(base_address, offset, buffer)
+            .map_err(|_| BackendError)
+    }
+
+    fn write(&mut self, base_address: u32, offset: u32, data: &[u8]) -> Result<(), BackendError> {
+        self.storage
+            .write_stream(base_address, offset, data, true)
+            .map_err(|_| BackendError)
+    }
+
+    fn data_block(&self, n: u32) -> Result<Option<(u32, u32)>, BackendError> {
+        // Walk the blocks, counting only the DATA ones
+        let mut block_number: u32 = 0;
+        let mut count: u32 = 0;
+        loop {
+            match self.storage.get_nth_block(block_number) {
+                Ok(block) => {
+                    if block.block_type == BlockType::DATA {
+                        if count == n {
+                            return Ok(Some((block.block_base_address, block.block_size)));
+                        }
+                        count += 1;
+                    }
+                }
+                Err(StorageError::NoBlockAvailable) => return Ok(None),
+                Err(_) => return Err(BackendError),
+            }
+            block_number += 1;
+        }
+    }
+}
+
+/// Handlers of the kvstore operations
+struct Server {
+    store: kv_log::KvStore<StorageBackend>,
+}
+
+impl KvStoreServer for Server {
+    fn get(
+        &mut self,
+        _msg: &GetRequest,
+        sender: TaskId,
+        key: &Borrow<'_>,
+        key_len: usize,
+        value: &Borrow<'_>,
+        value_len: usize,
+    ) -> Result<GetResponse, KvStoreError> {
+        let namespace = namespace_of(sender)?;
+        let mut key_buffer = [0u8; MAX_KEY_LEN];
+        let key = read_key(key, key_len, &mut key_buffer)?;
+        let mut buffer = [0u8; MAX_VALUE_LEN];
+        let len = self
+            .store
+            .get(namespace, key, &mut buffer)
+            .map_err(map_error)?;
+        // Truncated if the lease is smaller
+        let copied = core::cmp::min(len, value_len);
+        value
+            .write_fully_at(0, &buffer[0..copied])
+            .ok_or(KvStoreError::BadArgument)?;
+        Ok(GetResponse {
+            value_len: len as u32,
+        })
+    }
+
+    fn set(
+        &mut self,
+        _msg: &SetRequest,
+        sender: TaskId,
+        key: &Borrow<'_>,
+        key_len: usize,
+        value: &Borrow<'_>,
+        value_len: usize,
+    ) -> Result<(), KvStoreError> {
+        let namespace = namespace_of(sender)?;
+        let mut key_buffer = [0u8; MAX_KEY_LEN];
+        let key = read_key(key, key_len, &mut key_buffer)?;
+        if value_len > MAX_VALUE_LEN {
+            return Err(KvStoreError::ValueTooLarge);
+        }
+        let mut buffer = [0u8; MAX_VALUE_LEN];
+        value
+            .read_fully_at(0, &mut buffer[0..value_len])
+            .ok_or(KvStoreError::BadArgument)?;
+        self.store
+            .set(namespace, key, &buffer[0..value_len])
+            .map_err(map_error)
+    }
+
+    fn delete(
+        &mut self,
+        _msg: &DeleteRequest,
+        sender: TaskId,
+        key: &Borrow<'_>,
+        key_len: usize,
+    ) -> Result<(), KvStoreError> {
+        let namespace = namespace_of(sender)?;
+        let mut key_buffer = [0u8; MAX_KEY_LEN];
+        let key = read_key(key, key_len, &mut key_buffer)?;
+        self.store.delete(namespace, key).map_err(map_error)
+    }
+
+    fn iterate(
+        &mut self,
+        msg: &IterateRequest,
+        sender: TaskId,
+        key: &Borrow<'_>,
+        key_len: usize,
+    ) -> Result<IterateResponse, KvStoreError> {
+        let namespace = namespace_of(sender)?;
+        let mut key_buffer = [0u8; MAX_KEY_LEN];
+        let entry = self
+            .store
+            .next_key(namespace, msg.cursor, &mut key_buffer)
+            .map_err(map_error)?;
+        // Truncated if the lease is smaller
+        let copied = core::cmp::min(entry.key_len, key_len);
+        key.write_fully_at(0, &key_buffer[0..copied])
+            .ok_or(KvStoreError::BadArgument)?;
+        Ok(IterateResponse {
+            next_cursor: entry.next_cursor,
+            key_len: entry.key_len as u32,
+            value_len: entry.value_len as u32,
+        })
+    }
+}
+
+/// The keys of a component are bound to its ID, that is kept across updates.
+/// A new version gets its ID only after `kipc::activate_task`, before that it
+/// cannot use the store.
+fn namespace_of(sender: TaskId) -> Result<u16, KvStoreError> {
+    let component_id = sender.component_id();
+    if component_id == UPDATE_TEMP_ID {
+        return Err(KvStoreError::BadArgument);
+    }
+    Ok(component_id)
+}
+
+fn read_key<'a>(
+    lease: &Borrow<'_>,
+    len: usize,
+    buffer: &'a mut [u8; MAX_KEY_LEN],
+) -> Result<&'a [u8], KvStoreError> {
+    if len == 0 || len > MAX_KEY_LEN {
+        return Err(KvStoreError::InvalidKey);
+    }
+    lease
+        .read_fully_at(0, &mut buffer[0..len])
+        .ok_or(KvStoreError::BadArgument)?;
+    Ok(&buffer[0..len])
+}
+
+fn map_error(error: KvError) -> KvStoreError {
+    match error {
+        KvError::KeyNotFound => KvStoreError::KeyNotFound,
+        KvError::InvalidKey => KvStoreError::InvalidKey,
+        KvError::ValueTooLarge => KvStoreError::ValueTooLarge,
+        KvError::StoreFull => KvStoreError::StoreFull,
+        KvError::EndOfKeys => KvStoreError::EndOfKeys,
+        KvError::StorageError => KvStoreError::StorageError,
+    }
+}
+
+#[export_name = "main"]
+fn main() -> ! {
+    kipc::activate_task();
+    // Open the store, recovering it if a power loss interrupted a change
+    let backend = StorageBackend {
+        storage: Storage::new(),
+    };
+    let store = match kv_log::KvStore::mount(backend, STORE_BLOCK_SIZE) {
+        Ok(store) => store,
+        Err(e) => panic!("[KVSTORE] Mount failed: {:?}", e),
+    };
+    sys_log!("[KVSTORE] Online!");
+    let mut server = Server { store: store };
+    // Incoming message buffer, the biggest request is the one of Iterate
+    let mut buffer: [u8; 4] = [0; 4];
+    loop {
+        hl::recv(
+            &mut buffer,
+            0,
+            &mut server,
+            |_server, _bits| {},
+            |server, op, msg| server.dispatch(op, msg),
+        );
+    }
+}
@@ -0,0 +1,189 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+#![no_std]
+#![no_main]
+
+use log_ring::{Backend, BackendError, LogError};
+use logstore_api::*;
+use storage_api::{Storage, StorageError};
+use userlib::{flash::BlockType, hl::Borrow, *};
+
+/// Size requested for each block of the log
+const SEGMENT_SIZE: u32 = 1024;
+
+/// Blocks reserved for the log, chosen with the features of the component
+#[cfg(not(any(
+    feature = "segments-2",
+    feature = "segments-8",
+    feature = "segments-16"
+)))]
+const LOG_SEGMENTS: usize = 4;
+#[cfg(feature = "segments-2")]
+const LOG_SEGMENTS: usize = 2;
+#[cfg(feature = "segments-8")]
+const LOG_SEGMENTS: usize = 8;
+#[cfg(feature = "segments-16")]
+const LOG_SEGMENTS: usize = 16;
+
+// Generated from logstore.idl.toml
+include!(concat!(env!("OUT_DIR"), "/server_stub.rs"));
+
+/// DATA blocks through the storage component
+struct StorageBackend {
+    storage: Storage,
+}
+
+impl Backend for StorageBackend {
+    fn allocate(&mut self, size: u32) -> Result<(u32, u32), BackendError> {
+        let response = self.storage.allocate_data(size).map_err(|_| BackendError)?;
+        Ok((response.block_base_address, response.block_size))
+    }
+
+    fn finalize(&mut self, base_address: u32) -> Result<(), BackendError> {
+        match self.storage.finalize_block(base_address) {
+            Ok(()) | Err(StorageError::BlockIsFinalized) => Ok(()),
+            Err(_) => Err(BackendError),
+        }
+    }
+
+    fn deallocate(&mut self, base_address: u32) -> Result<(), BackendError> {
+        self.storage
+            .deallocate_block(base_address)
+            .map_err(|_| BackendError)
+    }
+
+    fn read(&self, base_address: u32, offset: u32, buffer: &mut [u8]) -> Result<(), BackendError> {
+        self.storage
+            .read_stream(base_address, offset, buffer)
+            .map_err(|_| BackendError)
+    }
+
+    fn write(&mut self, base_address: u32, offset: u32, data: &[u8]) -> Result<(), BackendError> {
+        self.storage
+            .write_stream(base_address, offset, data, true)
+            .map_err(|_| BackendError)
+    }
+
+    fn data_block(&self, n: u32) -> Result<Option<(u32, u32)>, BackendError> {
+        // Walk the blocks, counting only the DATA ones
+        let mut block_number: u32 = 0;
+        let mut count: u32 = 0;
+        loop {
+            match self.storage.get_nth_block(block_number) {
+                Ok(block) => {
+                    if block.block_type == BlockType::DATA {
+                        if count == n {
+                            return Ok(Some((block.block_base_address, block.block_size)));
+                        }
+                        count += 1;
+                    }
+                }
+                Err(StorageError::NoBlockAvailable) => return Ok(None),
+                Err(_) => return Err(BackendError),
+            }
+            block_number += 1;
+        }
+    }
+}
+
+/// Handlers of the logstore operations
+struct Server {
+    log: log_ring::LogRing<StorageBackend>,
+}
+
+impl LogStoreServer for Server {
+    fn append(
+        &mut self,
+        _msg: &AppendRequest,
+        sender: TaskId,
+        data: &Borrow<'_>,
+        data_len: usize,
+    ) -> Result<AppendResponse, LogStoreError> {
+        if data_len > MAX_DATA_LEN {
+            return Err(LogStoreError::DataTooLarge);
+        }
+        let mut buffer = [0u8; MAX_DATA_LEN];
+        data.read_fully_at(0, &mut buffer[0..data_len])
+            .ok_or(LogStoreError::BadArgument)?;
+        let seq = self
+            .log
+            .append(
+                sender.component_id(),
+                sys_get_timer().now,
+                &buffer[0..data_len],
+            )
+            .map_err(map_error)?;
+        Ok(AppendResponse { seq: seq })
+    }
+
+    fn read(
+        &mut self,
+        msg: &ReadRequest,
+        data: &Borrow<'_>,
+        data_len: usize,
+    ) -> Result<ReadResponse, LogStoreError> {
+        let mut buffer = [0u8; MAX_DATA_LEN];
+        let entry = self.log.read(msg.cursor, &mut buffer).map_err(map_error)?;
+        // Truncated if the lease is smaller
+        let copied = core::cmp::min(entry.len, data_len);
+        data.write_fully_at(0, &buffer[0..copied])
+            .ok_or(LogStoreError::BadArgument)?;
+        Ok(ReadResponse {
+            seq: entry.seq,
+            source: entry.source as u32,
+            len: entry.len as u32,
+            timestamp_low: entry.timestamp as u32,
+            timestamp_high: (entry.timestamp >> 32) as u32,
+        })
+    }
+
+    fn consume(&mut self, msg: &ConsumeRequest) -> Result<(), LogStoreError> {
+        self.log.consume(msg.up_to).map_err(map_error)
+    }
+
+    fn status(&mut self, _msg: &StatusRequest) -> Result<StatusResponse, LogStoreError> {
+        let status = self.log.status();
+        Ok(StatusResponse {
+            first: status.first,
+            consumed: status.consumed,
+            next: status.next,
+        })
+    }
+}
+
+fn map_error(error: LogError) -> LogStoreError {
+    match error {
+        LogError::EndOfLog => LogStoreError::EndOfLog,
+        LogError::DataTooLarge => LogStoreError::DataTooLarge,
+        LogError::InvalidCursor => LogStoreError::InvalidCursor,
+        LogError::BadConfiguration | LogError::StorageError => LogStoreError::StorageError,
+    }
+}
+
+#[export_name = "main"]
+fn main() -> ! {
+    kipc::activate_task();
+    // Open the log, recovering it if a power loss interrupted a record
+    let backend = StorageBackend {
+        storage: Storage::new(),
+    };
+    let log = match log_ring::LogRing::mount(backend, SEGMENT_SIZE, LOG_SEGMENTS) {
+        Ok(log) => log,
+        Err(e) => panic!("[LOGSTORE] Mount failed: {:?}", e),
+    };
+    sys_log!("[LOGSTORE] Online!");
+    let mut server = Server { log: log };
+    // Incoming message buffer, the biggest requests are the ones of Read and Consume
+    let mut buffer: [u8; 4] = [0; 4];
+    loop {
+        hl::recv(
+            &mut buffer,
+            0,
+            &mut server,
+            |_server, _bits| {},
+            |server, op, msg| server.dispatch(op, msg),
+        );
+    }
+}
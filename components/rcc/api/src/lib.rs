@@ -4,10 +4,7 @@
 
 #![no_std]
 
-use core::cell::Cell;
-
-use userlib::{hl, TaskId, FromPrimitive};
-use zerocopy::{AsBytes,FromBytes};
+use userlib::{hl, FromPrimitive};
 
 // Import device-specific constants/structures/functions
 cfg_if::cfg_if! {
@@ -28,46 +25,8 @@ cfg_if::cfg_if! {
     }
 }
 
-/**
- * Constants
- */
-const RCC_TASK_ID: TaskId = TaskId(2);
-
-/**
- * Error Type
- */
-#[derive(Copy, Clone, Debug)]
-#[repr(u32)]
-pub enum RCCError {
-    InvalidPeripheral = 1,
-    BadArgument = 2,
-    ComponentUnavailable = 3
-}
-impl From<u32> for RCCError {
-    fn from(x: u32) -> Self {
-        match x {
-            1 => RCCError::InvalidPeripheral,
-            2 => RCCError::BadArgument,
-            _ => RCCError::ComponentUnavailable
-        }
-    }
-}
-impl From<RCCError> for u32 {
-    fn from(x: RCCError) -> Self {
-        x as u32
-    }
-}
-
-/**
- * Operations
- */
-#[derive(Copy, Clone, Debug, FromPrimitive)]
-pub enum Operation {
-    EnableClock = 1,
-    DisableClock = 2,
-    EnterReset = 3,
-    LeaveReset = 4
-}
+// Generated from rcc.idl.toml
+include!(concat!(env!("OUT_DIR"), "/client_stub.rs"));
 
 // Not complete, just some needed
 #[derive(Copy, Clone, Debug, FromPrimitive)]
@@ -110,59 +69,21 @@ pub enum Peripheral {
     GPIOH = 35,
     GPIOG = 36,
     TIM5 = 37,
+    SYSCFG = 38,
+    GPIOI = 39,
+    LPUART1 = 40,
+    // APB clock of the RTC and backup registers, only gated on the STM32L4
+    RTC = 41,
 }
 
-/// Enable clock
-#[derive(FromBytes, AsBytes)]
-#[repr(C)]
-pub struct EnableClockRequest {
-    pub bus: u32,
-    pub bit: u32
-}
-impl hl::Call for EnableClockRequest {
-    const OP: u16 = Operation::EnableClock as u16;
-    type Response = ();
-    type Err = RCCError;
-}
-
-/// Disable clock
-#[derive(FromBytes, AsBytes)]
-#[repr(C)]
-pub struct DisableClockRequest {
-    pub bus: u32,
-    pub bit: u32
-}
-impl hl::Call for DisableClockRequest {
-    const OP: u16 = Operation::DisableClock as u16;
-    type Response = ();
-    type Err = RCCError;
-}
-
-/// Enter reset
-#[derive(FromBytes, AsBytes)]
-#[repr(C)]
-pub struct EnterResetRequest {
-    pub bus: u32,
-    pub bit: u32
-}
-impl hl::Call for EnterResetRequest {
-    const OP: u16 = Operation::EnterReset as u16;
-    type Response = ();
-    type Err = RCCError;
-}
-
-/// Leave reset
-#[derive(FromBytes, AsBytes)]
-#[repr(C)]
-pub struct LeaveResetRequest {
-    pub bus: u32,
-    pub bit: u32
-}
-impl hl::Call for LeaveResetRequest {
-    const OP: u16 = Operation::LeaveReset as u16;
-    type Response = ();
-    type Err = RCCError;
-}
+// Causes of a reset, as returned by take_reset_flags (more can be set at once)
+pub const RESET_OPTION_BYTES: u32 = 1 << 0;
+pub const RESET_PIN: u32 = 1 << 1;
+pub const RESET_POWER: u32 = 1 << 2;
+pub const RESET_SOFTWARE: u32 = 1 << 3;
+pub const RESET_IWDG: u32 = 1 << 4;
+pub const RESET_WWDG: u32 = 1 << 5;
+pub const RESET_LOW_POWER: u32 = 1 << 6;
 
 // Bus structure
 #[derive(FromPrimitive)]
@@ -171,18 +92,19 @@ pub enum Bus {
     AHB2 = 1,
     AHB3 = 2,
     APB1 = 3,
-    APB2 = 4
+    APB2 = 4,
+    // Second register of APB1, only on the STM32L4
+    APB1_2 = 5
 }
 
-// API Class
-pub struct RCC(Cell<TaskId>);
+// Bus of the peripheral, that gives its clock if it has no clock selector
+pub fn clock_bus(peripheral: Peripheral) -> Result<Bus, RCCError> {
+    let (bus, _) = clock_mapping(peripheral)?;
+    Ok(bus)
+}
 
+// Client methods, the peripheral is mapped to its bus and bit
 impl RCC {
-    pub fn new() -> Self {
-        Self {
-            0: Cell::new(RCC_TASK_ID)
-        }
-    }
     pub fn enable_clock(&mut self, peripheral: Peripheral) -> Result<(),RCCError> {
         let (bus, bit) = clock_mapping(peripheral)?;
         hl::send_with_retry(&self.0, &EnableClockRequest{
@@ -211,4 +133,10 @@ impl RCC {
             bit: bit
         }, &[])
     }
+    pub fn get_clock_frequency(&mut self, peripheral: Peripheral) -> Result<u32,RCCError> {
+        let response: GetClockFrequencyResponse = hl::send_with_retry(&self.0, &GetClockFrequencyRequest{
+            peripheral: peripheral as u32
+        }, &[])?;
+        Ok(response.frequency)
+    }
 }
\ No newline at end of file
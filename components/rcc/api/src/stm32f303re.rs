@@ -29,6 +29,7 @@ pub fn clock_mapping(p: Peripheral) -> Result<(Bus, u32), RCCError> {
         Peripheral::DAC1 => Ok((Bus::APB1, 29)),
         Peripheral::I2C3 => Ok((Bus::APB1, 30)),
         // 9.4.7 APB2 peripheral clock enable register (RCC_APB2ENR)
+        Peripheral::SYSCFG => Ok((Bus::APB2, 0)),
         Peripheral::TIM1 => Ok((Bus::APB2, 11)),
         Peripheral::SPI1 => Ok((Bus::APB2, 12)),
         Peripheral::TIM8 => Ok((Bus::APB2, 13)),
@@ -75,6 +76,7 @@ pub fn reset_mapping(p: Peripheral) -> Result<(Bus, u32), RCCError> {
         Peripheral::DAC1 => Ok((Bus::APB1, 29)),
         Peripheral::I2C3 => Ok((Bus::APB1, 30)),
         // 9.4.4 APB2 peripheral reset register (RCC_APB2RSTR)
+        Peripheral::SYSCFG => Ok((Bus::APB2, 0)),
         Peripheral::TIM1 => Ok((Bus::APB2, 11)),
         Peripheral::SPI1 => Ok((Bus::APB2, 12)),
         Peripheral::TIM8 => Ok((Bus::APB2, 13)),
@@ -24,10 +24,22 @@ pub fn clock_mapping(p: Peripheral) -> Result<(Bus, u32), RCCError> {
         Peripheral::TIM3 => Ok((Bus::APB1, 1)),
         Peripheral::TIM6 => Ok((Bus::APB1, 4)),
         Peripheral::TIM7 => Ok((Bus::APB1, 5)),
+        Peripheral::RTC => Ok((Bus::APB1, 10)),
 
         Peripheral::USART2 => Ok((Bus::APB1, 17)),
         Peripheral::USART3 => Ok((Bus::APB1, 18)),
         Peripheral::UART4 => Ok((Bus::APB1, 19)),
+        Peripheral::I2C1 => Ok((Bus::APB1, 21)),
+        Peripheral::PWR => Ok((Bus::APB1, 28)),
+
+        // 6.4.19 APB1 peripheral clock enable register 2 (RCC_APB1ENR2)
+        Peripheral::LPUART1 => Ok((Bus::APB1_2, 0)),
+
+        // 6.4.20 APB2 peripheral clock enable register (RCC_APB2ENR)
+        Peripheral::SYSCFG => Ok((Bus::APB2, 0)),
+        Peripheral::TIM1 => Ok((Bus::APB2, 11)),
+        Peripheral::SPI1 => Ok((Bus::APB2, 12)),
+        Peripheral::USART1 => Ok((Bus::APB2, 14)),
         _ => Err(RCCError::BadArgument)
     }
 }
@@ -55,6 +67,16 @@ pub fn reset_mapping(p: Peripheral) -> Result<(Bus, u32), RCCError> {
         Peripheral::USART2 => Ok((Bus::APB1, 17)),
         Peripheral::USART3 => Ok((Bus::APB1, 18)),
         Peripheral::UART4 => Ok((Bus::APB1, 19)),
+        Peripheral::I2C1 => Ok((Bus::APB1, 21)),
+
+        // 6.4.13 APB1 peripheral reset register 2 (RCC_APB1RSTR2)
+        Peripheral::LPUART1 => Ok((Bus::APB1_2, 0)),
+
+        // 6.4.14 APB2 peripheral reset register (RCC_APB2RSTR)
+        Peripheral::SYSCFG => Ok((Bus::APB2, 0)),
+        Peripheral::TIM1 => Ok((Bus::APB2, 11)),
+        Peripheral::SPI1 => Ok((Bus::APB2, 12)),
+        Peripheral::USART1 => Ok((Bus::APB2, 14)),
         _ => Err(RCCError::BadArgument)
     }
 }
\ No newline at end of file
@@ -20,6 +20,7 @@ pub fn clock_mapping(p: Peripheral) -> Result<(Bus, u32), RCCError> {
         Peripheral::GPIOF => Ok((Bus::AHB2, 5)),
         Peripheral::GPIOG => Ok((Bus::AHB2, 6)),
         Peripheral::GPIOH => Ok((Bus::AHB2, 7)),
+        Peripheral::GPIOI => Ok((Bus::AHB2, 8)),
 
         // 6.4.19 APB1 peripheral clock enable register 1 (RCC_APB1ENR1)
         Peripheral::TIM2 => Ok((Bus::APB1, 0)),
@@ -28,10 +29,23 @@ pub fn clock_mapping(p: Peripheral) -> Result<(Bus, u32), RCCError> {
         Peripheral::TIM5 => Ok((Bus::APB1, 3)),
         Peripheral::TIM6 => Ok((Bus::APB1, 4)),
         Peripheral::TIM7 => Ok((Bus::APB1, 5)),
+        Peripheral::RTC => Ok((Bus::APB1, 10)),
 
         Peripheral::USART2 => Ok((Bus::APB1, 17)),
         Peripheral::USART3 => Ok((Bus::APB1, 18)),
         Peripheral::UART4 => Ok((Bus::APB1, 19)),
+        Peripheral::UART5 => Ok((Bus::APB1, 20)),
+        Peripheral::I2C1 => Ok((Bus::APB1, 21)),
+        Peripheral::PWR => Ok((Bus::APB1, 28)),
+
+        // 6.4.20 APB1 peripheral clock enable register 2 (RCC_APB1ENR2)
+        Peripheral::LPUART1 => Ok((Bus::APB1_2, 0)),
+
+        // 6.4.21 APB2 peripheral clock enable register (RCC_APB2ENR)
+        Peripheral::SYSCFG => Ok((Bus::APB2, 0)),
+        Peripheral::TIM1 => Ok((Bus::APB2, 11)),
+        Peripheral::SPI1 => Ok((Bus::APB2, 12)),
+        Peripheral::USART1 => Ok((Bus::APB2, 14)),
         _ => Err(RCCError::BadArgument)
     }
 }
@@ -51,6 +65,7 @@ pub fn reset_mapping(p: Peripheral) -> Result<(Bus, u32), RCCError> {
         Peripheral::GPIOF => Ok((Bus::AHB2, 5)),
         Peripheral::GPIOG => Ok((Bus::AHB2, 6)),
         Peripheral::GPIOH => Ok((Bus::AHB2, 7)),
+        Peripheral::GPIOI => Ok((Bus::AHB2, 8)),
 
         // 6.4.13 APB1 peripheral reset register 1 (RCC_APB1RSTR1)
         Peripheral::TIM2 => Ok((Bus::APB1, 0)),
@@ -63,6 +78,17 @@ pub fn reset_mapping(p: Peripheral) -> Result<(Bus, u32), RCCError> {
         Peripheral::USART2 => Ok((Bus::APB1, 17)),
         Peripheral::USART3 => Ok((Bus::APB1, 18)),
         Peripheral::UART4 => Ok((Bus::APB1, 19)),
+        Peripheral::UART5 => Ok((Bus::APB1, 20)),
+        Peripheral::I2C1 => Ok((Bus::APB1, 21)),
+
+        // 6.4.14 APB1 peripheral reset register 2 (RCC_APB1RSTR2)
+        Peripheral::LPUART1 => Ok((Bus::APB1_2, 0)),
+
+        // 6.4.15 APB2 peripheral reset register (RCC_APB2RSTR)
+        Peripheral::SYSCFG => Ok((Bus::APB2, 0)),
+        Peripheral::TIM1 => Ok((Bus::APB2, 11)),
+        Peripheral::SPI1 => Ok((Bus::APB2, 12)),
+        Peripheral::USART1 => Ok((Bus::APB2, 14)),
         _ => Err(RCCError::BadArgument)
     }
 }
\ No newline at end of file
@@ -0,0 +1,128 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use rcc_api::{clock_bus, Bus, Peripheral, RCCError};
+
+use crate::device;
+
+/// Register values of the tree, computed by the clock_tree library. The masks
+/// select the bits that are written.
+pub struct Setup {
+    pub cr: u32,
+    pub cr_mask: u32,
+    pub pll: u32,
+    pub cfgr: u32,
+    pub cfgr_mask: u32,
+    pub sw: u32,
+    pub pll_on: bool,
+    pub kernel: u32,
+    pub kernel_mask: u32,
+    pub latency: u32,
+}
+
+// Generated from the [clocks] section of App.toml
+include!(concat!(env!("OUT_DIR"), "/clock_tree.rs"));
+
+/**
+ * Registers
+ */
+// CR
+#[cfg(feature = "board_stm32f303re")]
+const CR_HSION: u32 = 1 << 0;
+#[cfg(feature = "board_stm32f303re")]
+const CR_HSIRDY: u32 = 1 << 1;
+#[cfg(any(feature = "board_stm32l432kc",feature = "board_stm32l476rg"))]
+const CR_MSIRDY: u32 = 1 << 1;
+#[cfg(any(feature = "board_stm32l432kc",feature = "board_stm32l476rg"))]
+const CR_HSION: u32 = 1 << 8;
+#[cfg(any(feature = "board_stm32l432kc",feature = "board_stm32l476rg"))]
+const CR_HSIRDY: u32 = 1 << 10;
+const CR_HSEON: u32 = 1 << 16;
+const CR_HSERDY: u32 = 1 << 17;
+const CR_PLLON: u32 = 1 << 24;
+const CR_PLLRDY: u32 = 1 << 25;
+// CFGR
+const CFGR_SW_MASK: u32 = 0b11;
+const CFGR_SWS_SHIFT: u32 = 2;
+#[cfg(feature = "board_stm32f303re")]
+const SW_HSI: u32 = 0b00;
+#[cfg(any(feature = "board_stm32l432kc",feature = "board_stm32l476rg"))]
+const SW_HSI: u32 = 0b01;
+// FLASH ACR
+const ACR_LATENCY_MASK: u32 = 0b111;
+#[cfg(feature = "board_stm32f303re")]
+const MAX_LATENCY: u32 = 2;
+#[cfg(any(feature = "board_stm32l432kc",feature = "board_stm32l476rg"))]
+const MAX_LATENCY: u32 = 4;
+
+fn set_latency(flash: &device::flash::RegisterBlock, latency: u32) {
+    flash.acr.modify(|r, w| unsafe { w.bits(r.bits() & !ACR_LATENCY_MASK | latency) });
+    while flash.acr.read().bits() & ACR_LATENCY_MASK != latency {}
+}
+
+fn switch_sysclk(rcc: &device::rcc::RegisterBlock, sw: u32) {
+    rcc.cfgr.modify(|r, w| unsafe { w.bits(r.bits() & !CFGR_SW_MASK | sw) });
+    while (rcc.cfgr.read().bits() >> CFGR_SWS_SHIFT) & CFGR_SW_MASK != sw {}
+}
+
+/// Moves from the tree of the kernel to the one of App.toml. SYSCLK runs on
+/// the HSI while the PLL is changed, with the wait states of the highest speed.
+pub fn apply(rcc: &device::rcc::RegisterBlock, flash: &device::flash::RegisterBlock, setup: &Setup) {
+    // HSI as a safe clock
+    rcc.cr.modify(|r, w| unsafe { w.bits(r.bits() | CR_HSION) });
+    while rcc.cr.read().bits() & CR_HSIRDY == 0 {}
+    set_latency(flash, MAX_LATENCY);
+    switch_sysclk(rcc, SW_HSI);
+    // The PLL can only be configured when stopped
+    rcc.cr.modify(|r, w| unsafe { w.bits(r.bits() & !CR_PLLON) });
+    while rcc.cr.read().bits() & CR_PLLRDY != 0 {}
+    // Oscillators
+    rcc.cr.modify(|r, w| unsafe { w.bits(r.bits() & !setup.cr_mask | setup.cr) });
+    if setup.cr & CR_HSEON != 0 {
+        while rcc.cr.read().bits() & CR_HSERDY == 0 {}
+    }
+    #[cfg(any(feature = "board_stm32l432kc",feature = "board_stm32l476rg"))]
+    while rcc.cr.read().bits() & CR_MSIRDY == 0 {}
+    // PLL and prescalers
+    #[cfg(feature = "board_stm32f303re")]
+    rcc.cfgr2.write(|w| unsafe { w.bits(setup.pll) });
+    #[cfg(any(feature = "board_stm32l432kc",feature = "board_stm32l476rg"))]
+    rcc.pllcfgr.write(|w| unsafe { w.bits(setup.pll) });
+    rcc.cfgr.modify(|r, w| unsafe { w.bits(r.bits() & !setup.cfgr_mask | setup.cfgr) });
+    if setup.pll_on {
+        rcc.cr.modify(|r, w| unsafe { w.bits(r.bits() | CR_PLLON) });
+        while rcc.cr.read().bits() & CR_PLLRDY == 0 {}
+    }
+    switch_sysclk(rcc, setup.sw);
+    set_latency(flash, setup.latency);
+    // Clock selectors of the peripherals
+    #[cfg(feature = "board_stm32f303re")]
+    rcc.cfgr3.modify(|r, w| unsafe { w.bits(r.bits() & !setup.kernel_mask | setup.kernel) });
+    #[cfg(any(feature = "board_stm32l432kc",feature = "board_stm32l476rg"))]
+    rcc.ccipr.modify(|r, w| unsafe { w.bits(r.bits() & !setup.kernel_mask | setup.kernel) });
+}
+
+/// Frequency of the kernel clock of a peripheral, the one of its bus if it has
+/// no clock selector
+pub fn frequency(peripheral: Peripheral) -> Result<u32, RCCError> {
+    if let Some((_, frequency)) = KERNEL_CLOCKS
+        .iter()
+        .find(|(p, _)| *p as u32 == peripheral as u32)
+    {
+        return Ok(*frequency);
+    }
+    let timer = matches!(
+        peripheral,
+        Peripheral::TIM1 | Peripheral::TIM2 | Peripheral::TIM3 | Peripheral::TIM4 |
+        Peripheral::TIM5 | Peripheral::TIM6 | Peripheral::TIM7 | Peripheral::TIM8
+    );
+    match clock_bus(peripheral)? {
+        Bus::AHB1 | Bus::AHB2 | Bus::AHB3 => Ok(HCLK_HZ),
+        // Timers are clocked twice as fast when the bus is divided
+        Bus::APB1 if timer => Ok(APB1_TIMERS_HZ),
+        Bus::APB1 | Bus::APB1_2 => Ok(PCLK1_HZ),
+        Bus::APB2 if timer => Ok(APB2_TIMERS_HZ),
+        Bus::APB2 => Ok(PCLK2_HZ),
+    }
+}
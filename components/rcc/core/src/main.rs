@@ -22,6 +22,8 @@ use stm32l476rg::device as device;
 use userlib::*;
 use zerocopy::AsBytes;
 
+mod clocks;
+
 // None of the registers we interact with have the same types, and they share no
 // useful traits, so we can't extract the bit-setting routine into a function --
 // we have no choice but to use macros.
@@ -40,6 +42,16 @@ macro_rules! clear_bits {
     };
 }
 
+// Reset flags in CSR, from OBLRSTF to LPWRRSTF in the same order on all the
+// boards (and so as the RESET_* bits)
+const CSR_RESET_FLAGS_SHIFT: u32 = 25;
+const CSR_RESET_FLAGS_MASK: u32 = 0x7F;
+// Remove reset flags
+#[cfg(feature = "board_stm32f303re")]
+const CSR_RMVF: u32 = 1 << 24;
+#[cfg(any(feature = "board_stm32l432kc",feature = "board_stm32l476rg"))]
+const CSR_RMVF: u32 = 1 << 23;
+
 #[export_name = "main"]
 fn main() -> ! {
     // Activate task
@@ -51,6 +63,13 @@ fn main() -> ! {
     // concern. Were it literally a static, we could just reference it.
     let rcc = unsafe { &*device::RCC::ptr() };
 
+    // Clock tree of App.toml, before any client gets a frequency
+    if let Some(setup) = &clocks::SETUP {
+        let flash = unsafe { &*device::FLASH::ptr() };
+        clocks::apply(rcc, flash, setup);
+        sys_log!("Clock tree applied, SYSCLK at {} Hz", clocks::SYSCLK_HZ);
+    }
+
     // Message handler
     let recv_handler = |op: Operation, msg: hl::Message| -> Result<(), RCCError> {
         match op {
@@ -87,6 +106,14 @@ fn main() -> ! {
                         #[cfg(any(feature = "board_stm32l432kc",feature = "board_stm32l476rg"))]
                         set_bits!(rcc.apb1enr1, pmask);
                     },
+                    Bus::APB1_2 => {
+                        #[cfg(feature = "board_stm32f303re")]
+                        sys_log!("Wrong bus for f303re!");
+                        #[cfg(feature = "board_stm32f303re")]
+                        panic!(); // The function of mapping should be correct
+                        #[cfg(any(feature = "board_stm32l432kc",feature = "board_stm32l476rg"))]
+                        set_bits!(rcc.apb1enr2, pmask);
+                    },
                     Bus::APB2 => set_bits!(rcc.apb2enr, pmask),
                 };
                 // Respond
@@ -127,6 +154,14 @@ fn main() -> ! {
                         #[cfg(any(feature = "board_stm32l432kc",feature = "board_stm32l476rg"))]
                         clear_bits!(rcc.apb1enr1, pmask);
                     },
+                    Bus::APB1_2 => {
+                        #[cfg(feature = "board_stm32f303re")]
+                        sys_log!("Wrong bus for f303re!");
+                        #[cfg(feature = "board_stm32f303re")]
+                        panic!(); // The function of mapping should be correct
+                        #[cfg(any(feature = "board_stm32l432kc",feature = "board_stm32l476rg"))]
+                        clear_bits!(rcc.apb1enr2, pmask);
+                    },
                     Bus::APB2 => clear_bits!(rcc.apb2enr, pmask),
                 };
                 // Respond
@@ -167,6 +202,14 @@ fn main() -> ! {
                         #[cfg(any(feature = "board_stm32l432kc",feature = "board_stm32l476rg"))]
                         set_bits!(rcc.apb1rstr1, pmask);
                     },
+                    Bus::APB1_2 => {
+                        #[cfg(feature = "board_stm32f303re")]
+                        sys_log!("Wrong bus for f303re!");
+                        #[cfg(feature = "board_stm32f303re")]
+                        panic!(); // The function of mapping should be correct
+                        #[cfg(any(feature = "board_stm32l432kc",feature = "board_stm32l476rg"))]
+                        set_bits!(rcc.apb1rstr2, pmask);
+                    },
                     Bus::APB2 => set_bits!(rcc.apb2rstr, pmask),
                 };
                 // Respond
@@ -207,11 +250,45 @@ fn main() -> ! {
                         #[cfg(any(feature = "board_stm32l432kc",feature = "board_stm32l476rg"))]
                         clear_bits!(rcc.apb1rstr1, pmask);
                     },
+                    Bus::APB1_2 => {
+                        #[cfg(feature = "board_stm32f303re")]
+                        sys_log!("Wrong bus for f303re!");
+                        #[cfg(feature = "board_stm32f303re")]
+                        panic!(); // The function of mapping should be correct
+                        #[cfg(any(feature = "board_stm32l432kc",feature = "board_stm32l476rg"))]
+                        clear_bits!(rcc.apb1rstr2, pmask);
+                    },
                     Bus::APB2 => clear_bits!(rcc.apb2rstr, pmask),
                 };
                 // Respond
                 caller.reply(());
                 Ok(())
+            },
+            Operation::TakeResetFlags => {
+                let (_, caller) = msg
+                    .fixed::<TakeResetFlagsRequest, TakeResetFlagsResponse>()
+                    .ok_or(RCCError::BadArgument)?;
+
+                let csr = rcc.csr.read().bits();
+                // Cleared, so the next boot sees only its own causes
+                set_bits!(rcc.csr, CSR_RMVF);
+                // Respond
+                caller.reply(TakeResetFlagsResponse {
+                    flags: (csr >> CSR_RESET_FLAGS_SHIFT) & CSR_RESET_FLAGS_MASK
+                });
+                Ok(())
+            },
+            Operation::GetClockFrequency => {
+                let (msg, caller) = msg
+                    .fixed::<GetClockFrequencyRequest, GetClockFrequencyResponse>()
+                    .ok_or(RCCError::BadArgument)?;
+
+                let peripheral = Peripheral::from_u32(msg.peripheral).ok_or(RCCError::BadArgument)?;
+                // Respond
+                caller.reply(GetClockFrequencyResponse {
+                    frequency: clocks::frequency(peripheral)?
+                });
+                Ok(())
             }
         }
     };
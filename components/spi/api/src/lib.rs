@@ -0,0 +1,16 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+#![no_std]
+
+/// Port of the chip select pins
+pub use gpio_api::Port;
+/// Longest transaction, the bytes written and read together
+pub use spi_controller::MAX_TRANSFER;
+
+/// Devices the component can keep, from all the clients
+pub const MAX_DEVICES: usize = 8;
+
+// Generated from spi.idl.toml
+include!(concat!(env!("OUT_DIR"), "/client_stub.rs"));
@@ -0,0 +1,93 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use std::{env, fmt::Write, fs, path::PathBuf};
+
+use component_config::structures::ConfigTable;
+
+/// DMA, channel of the RX, channel of the TX and request selecting SPI1 on both
+/// (CSELR). RM0394 Table 41 and 42, RM0351 Table 44 and 45: the same on the
+/// STM32L432KC and on the STM32L476RG.
+const STM32L4: &[(&str, i64, i64, u32)] = &[("dma1", 2, 3, 1), ("dma2", 3, 4, 4)];
+
+fn string_setting<'a>(config: &'a ConfigTable, key: &str) -> &'a str {
+    config
+        .get(key)
+        .and_then(|value| value.as_str())
+        .unwrap_or_else(|| panic!("Setting '{}' must be a string", key))
+}
+
+fn integer_setting(config: &ConfigTable, key: &str) -> i64 {
+    config
+        .get(key)
+        .and_then(|value| value.as_integer())
+        .unwrap_or_else(|| panic!("Setting '{}' must be an integer", key))
+}
+
+fn main() {
+    idl::build_server_support("../api/spi.idl.toml", "server_stub.rs").unwrap();
+
+    let config = component_config::read_instance_config().unwrap();
+    let dma = string_setting(&config, "dma");
+    let rx_channel = integer_setting(&config, "rx_channel");
+    let tx_channel = integer_setting(&config, "tx_channel");
+
+    let mut out = String::new();
+    writeln!(
+        out,
+        "// Generated from the settings of the instance, do not edit"
+    )
+    .unwrap();
+    writeln!(out).unwrap();
+    // The bytes go through the interrupts on the STM32F3
+    if env::var("CARGO_FEATURE_BOARD_STM32F303RE").is_err() {
+        let (_, _, _, request) = STM32L4
+            .iter()
+            .find(|(name, rx, tx, _)| *name == dma && *rx == rx_channel && *tx == tx_channel)
+            .unwrap_or_else(|| {
+                panic!(
+                    "{} channels {} (RX) and {} (TX) cannot serve SPI1",
+                    dma, rx_channel, tx_channel
+                )
+            });
+        writeln!(
+            out,
+            "const DMA: rcc_api::Peripheral = rcc_api::Peripheral::{};",
+            dma.to_uppercase()
+        )
+        .unwrap();
+        writeln!(out, "const RX_CHANNEL: u32 = {};", rx_channel).unwrap();
+        writeln!(out, "const TX_CHANNEL: u32 = {};", tx_channel).unwrap();
+        writeln!(out, "/// Selection of SPI1 on both channels (CSELR)").unwrap();
+        writeln!(out, "const DMA_REQUEST: u32 = {};", request).unwrap();
+        writeln!(out).unwrap();
+        writeln!(
+            out,
+            "fn dma_registers() -> &'static device::dma1::RegisterBlock {{"
+        )
+        .unwrap();
+        writeln!(
+            out,
+            "    unsafe {{ &*device::{}::ptr() }}",
+            dma.to_uppercase()
+        )
+        .unwrap();
+        writeln!(out, "}}").unwrap();
+        writeln!(out).unwrap();
+        writeln!(out, "// Registers of the channels").unwrap();
+        for (side, channel) in [("rx", rx_channel), ("tx", tx_channel)] {
+            for name in ["ccr", "cndtr", "cpar", "cmar"] {
+                writeln!(out, "macro_rules! {}_{} {{", side, name).unwrap();
+                writeln!(out, "    ($dma:expr) => {{").unwrap();
+                writeln!(out, "        $dma.{}{}", name, channel).unwrap();
+                writeln!(out, "    }};").unwrap();
+                writeln!(out, "}}").unwrap();
+            }
+        }
+    }
+
+    let mut path = PathBuf::from(env::var("OUT_DIR").unwrap());
+    path.push("config.rs");
+    fs::write(path, out).unwrap();
+}
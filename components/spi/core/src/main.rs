@@ -0,0 +1,454 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+#![no_std]
+#![no_main]
+
+use gpio_api::{GPIOError, Mode, OutputType, Pull, Speed, GPIO};
+use rcc_api::{Peripheral, RCCError, RCC};
+use spi_api::*;
+use spi_controller::{baud_rate, Controller, Poll, Register, Registers, SpiError};
+use userlib::{hl::Borrow, *};
+use zerocopy::AsBytes;
+
+// STM32F3
+#[cfg(feature = "board_stm32f303re")]
+use stm32f303re::device;
+
+// STM32L432
+#[cfg(feature = "board_stm32l432kc")]
+use stm32l432kc::device;
+
+// STM32L476
+#[cfg(feature = "board_stm32l476rg")]
+use stm32l476rg::device;
+
+// Generated from spi.idl.toml
+include!(concat!(env!("OUT_DIR"), "/server_stub.rs"));
+
+/**
+ * Constants
+ */
+// Notification bits of the interrupts, as in Component.toml
+const SPI_IRQ_MASK: u32 = 1 << 0;
+const DMA_RX_IRQ_MASK: u32 = 1 << 1;
+const IRQ_MASK: u32 = SPI_IRQ_MASK | DMA_RX_IRQ_MASK;
+const TIMEOUT_MASK: u32 = 1 << 15;
+
+/// Longest transaction, in kernel ticks, before the bus is given up
+const TIMEOUT_TICKS: u64 = 100;
+
+// DMA channels of this instance (see build.rs)
+include!(concat!(env!("OUT_DIR"), "/config.rs"));
+
+/// Fastest clock of the bus, the BR field is computed from the one of SPI1
+const SPI_FREQUENCY: u32 = 1_250_000;
+
+/**
+ * Hardware
+ */
+struct Bus(&'static device::spi1::RegisterBlock);
+
+impl Registers for Bus {
+    fn read(&mut self, register: Register) -> u32 {
+        match register {
+            Register::Cr1 => self.0.cr1.read().bits() as u32,
+            Register::Cr2 => self.0.cr2.read().bits() as u32,
+            Register::Sr => self.0.sr.read().bits() as u32,
+            // 8 bit access, a wider one would take two frames from the FIFO
+            Register::Dr => unsafe {
+                core::ptr::read_volatile(self.0.dr.as_ptr() as *const u8) as u32
+            },
+        }
+    }
+
+    fn write(&mut self, register: Register, value: u32) {
+        match register {
+            Register::Cr1 => self.0.cr1.write(|w| unsafe { w.bits(value as _) }),
+            Register::Cr2 => self.0.cr2.write(|w| unsafe { w.bits(value as _) }),
+            Register::Sr => {}
+            // 8 bit access, a wider one would send two frames
+            Register::Dr => unsafe {
+                core::ptr::write_volatile(self.0.dr.as_ptr() as *mut u8, value as u8)
+            },
+        }
+    }
+}
+
+/**
+ * DMA Support (L4 only)
+ * SPI1_RX -> RX_CHANNEL of DMA, SPI1_TX -> TX_CHANNEL of DMA, from the settings
+ * See: RM0394/pag.299 (L432),  RM0351/pag.340 (L476)
+ */
+#[cfg(any(feature = "board_stm32l432kc", feature = "board_stm32l476rg"))]
+const DMA_GIF: u32 = 0b0001;
+#[cfg(any(feature = "board_stm32l432kc", feature = "board_stm32l476rg"))]
+const DMA_TCIF: u32 = 0b0010;
+#[cfg(any(feature = "board_stm32l432kc", feature = "board_stm32l476rg"))]
+const DMA_TEIF: u32 = 0b1000;
+
+/// Position of the flags of a channel in ISR and IFCR, and of its request in CSELR
+#[cfg(any(feature = "board_stm32l432kc", feature = "board_stm32l476rg"))]
+fn channel_shift(channel: u32) -> u32 {
+    4 * (channel - 1)
+}
+
+#[cfg(any(feature = "board_stm32l432kc", feature = "board_stm32l476rg"))]
+struct BusDma {
+    dma: &'static device::dma1::RegisterBlock,
+    spi: &'static device::spi1::RegisterBlock,
+}
+
+#[cfg(any(feature = "board_stm32l432kc", feature = "board_stm32l476rg"))]
+impl spi_controller::Dma for BusDma {
+    fn start(&mut self, source: *const u8, destination: *mut u8, len: usize) -> bool {
+        let dr = self.spi.dr.as_ptr() as u32;
+        // RX first, the transfer is paced by the TX requests. 8 bit on both
+        // sides, the reset value of PSIZE and MSIZE.
+        rx_ccr!(self.dma).write(|w| unsafe { w.bits(0) });
+        rx_cpar!(self.dma).write(|w| unsafe { w.bits(dr) });
+        rx_cmar!(self.dma).write(|w| unsafe { w.bits(destination as u32) });
+        rx_cndtr!(self.dma).write(|w| unsafe { w.bits(len as u32) });
+        // Transfer-complete and Transfer-error interrupts, the last byte is in
+        rx_ccr!(self.dma).write(|w| {
+            w.minc()
+                .set_bit()
+                .tcie()
+                .set_bit()
+                .teie()
+                .set_bit()
+                .en()
+                .set_bit()
+        });
+        tx_ccr!(self.dma).write(|w| unsafe { w.bits(0) });
+        tx_cpar!(self.dma).write(|w| unsafe { w.bits(dr) });
+        tx_cmar!(self.dma).write(|w| unsafe { w.bits(source as u32) });
+        tx_cndtr!(self.dma).write(|w| unsafe { w.bits(len as u32) });
+        tx_ccr!(self.dma).write(|w| w.dir().set_bit().minc().set_bit().en().set_bit());
+        return true;
+    }
+
+    fn finished(&mut self) -> bool {
+        let isr = self.dma.isr.read().bits() >> channel_shift(RX_CHANNEL);
+        if isr & (DMA_TCIF | DMA_TEIF) != 0 {
+            self.dma
+                .ifcr
+                .write(|w| unsafe { w.bits(DMA_GIF << channel_shift(RX_CHANNEL)) });
+            return true;
+        }
+        return false;
+    }
+
+    fn stop(&mut self) {
+        rx_ccr!(self.dma).modify(|_, w| w.en().clear_bit());
+        tx_ccr!(self.dma).modify(|_, w| w.en().clear_bit());
+        self.dma.ifcr.write(|w| unsafe {
+            w.bits(DMA_GIF << channel_shift(RX_CHANNEL) | DMA_GIF << channel_shift(TX_CHANNEL))
+        });
+    }
+}
+
+#[cfg(feature = "board_stm32f303re")]
+type BusDma = spi_controller::NoDma;
+
+#[cfg(any(feature = "board_stm32l432kc", feature = "board_stm32l476rg"))]
+fn setup_dma(spi: &'static device::spi1::RegisterBlock) -> Result<BusDma, RCCError> {
+    // Not reset, the other channels serve other components
+    let mut rcc = RCC::new();
+    rcc.enable_clock(DMA)?;
+    let dma = dma_registers();
+    // Select SPI1 for both channels. CSELR is shared with the components on
+    // the other channels, that may be setting it up too.
+    let mask = 0b1111 << channel_shift(RX_CHANNEL) | 0b1111 << channel_shift(TX_CHANNEL);
+    let request =
+        DMA_REQUEST << channel_shift(RX_CHANNEL) | DMA_REQUEST << channel_shift(TX_CHANNEL);
+    unsafe { util::modify_shared(dma.cselr.as_ptr(), mask, request) };
+    Ok(BusDma { dma: dma, spi: spi })
+}
+
+#[cfg(feature = "board_stm32f303re")]
+fn setup_dma(_: &'static device::spi1::RegisterBlock) -> Result<BusDma, RCCError> {
+    Ok(spi_controller::NoDma)
+}
+
+/// SPI1 on GPIOA (SCK pin 5, MISO pin 6, MOSI pin 7), on every board
+fn setup_gpio() -> Result<(), GPIOError> {
+    let pins = 1 << 5 | 1 << 6 | 1 << 7;
+    let mut gpio = GPIO::new();
+    gpio.claim(Port::A, pins)?;
+    gpio.configure(
+        Port::A,
+        pins,
+        Mode::Input,
+        OutputType::PushPull,
+        Speed::High,
+        Pull::None,
+    )?;
+    // Setup Alternate Function 5
+    gpio.set_alternate(Port::A, pins, 5)
+}
+
+/// Returns the BR field for SPI_FREQUENCY
+fn setup_spi() -> Result<u8, RCCError> {
+    let mut rcc = RCC::new();
+    rcc.enable_clock(Peripheral::SPI1)?;
+    rcc.leave_reset(Peripheral::SPI1)?;
+    // The controller sets it up for each transaction, with the mode of the device
+    let clock = rcc.get_clock_frequency(Peripheral::SPI1)?;
+    Ok(baud_rate(clock, SPI_FREQUENCY))
+}
+
+fn map_error(error: SpiError) -> SPIError {
+    match error {
+        SpiError::BadLength => SPIError::BadLength,
+        SpiError::BadMode => SPIError::BadMode,
+        SpiError::Overrun => SPIError::Overrun,
+    }
+}
+
+/**
+ * Server
+ */
+#[derive(Clone, Copy)]
+struct Device {
+    /// Component ID of the one that added it
+    owner: u16,
+    cs_port: Port,
+    cs_pin: u16,
+    mode: u8,
+}
+
+struct Server {
+    controller: Controller<Bus, BusDma>,
+    gpio: GPIO,
+    devices: [Option<Device>; MAX_DEVICES],
+    /// Device holding the bus, selected until it is unlocked
+    locked: Option<usize>,
+}
+
+impl Server {
+    /// Device of the request, only its owner can use it
+    fn device(&self, sender: TaskId, device: u32) -> Result<Device, SPIError> {
+        match self.devices.get(device as usize) {
+            Some(Some(d)) if d.owner == sender.component_id() => Ok(*d),
+            _ => Err(SPIError::InvalidDevice),
+        }
+    }
+
+    fn check_lock(&self, device: u32) -> Result<(), SPIError> {
+        match self.locked {
+            Some(locked) if locked != device as usize => Err(SPIError::BusLocked),
+            _ => Ok(()),
+        }
+    }
+
+    fn select(&mut self, device: &Device, selected: bool) -> Result<(), SPIError> {
+        let result = if selected {
+            self.gpio.reset(device.cs_port, device.cs_pin)
+        } else {
+            self.gpio.set(device.cs_port, device.cs_pin)
+        };
+        result.map_err(|_| SPIError::ChipSelectUnavailable)
+    }
+
+    /// Copies the data of the lease to the controller
+    fn load(&mut self, data: &Borrow<'_>, data_len: usize) -> Result<(), SPIError> {
+        if data_len > MAX_TRANSFER {
+            return Err(SPIError::BadLength);
+        }
+        data.read_fully_at(0, &mut self.controller.tx_buffer()[0..data_len])
+            .ok_or(SPIError::BadArgument)
+    }
+
+    /// Runs a transaction with the device selected, unless it is already
+    fn transaction(
+        &mut self,
+        sender: TaskId,
+        index: u32,
+        write_len: usize,
+        read_len: usize,
+    ) -> Result<(), SPIError> {
+        let device = self.device(sender, index)?;
+        self.check_lock(index)?;
+        let locked = self.locked.is_some();
+        if !locked {
+            self.select(&device, true)?;
+        }
+        let result = self.transfer(device.mode, write_len, read_len);
+        if !locked {
+            self.select(&device, false)?;
+        }
+        result
+    }
+
+    /// Runs a transaction to its end, waiting for the interrupts here: the
+    /// other requests are served once the bus is free again
+    fn transfer(&mut self, mode: u8, write_len: usize, read_len: usize) -> Result<(), SPIError> {
+        self.controller
+            .start(mode, write_len, read_len)
+            .map_err(map_error)?;
+        let deadline = sys_get_timer().now + TIMEOUT_TICKS;
+        sys_set_timer(Some(deadline), TIMEOUT_MASK);
+        loop {
+            let bits = sys_recv_closed(&mut [], IRQ_MASK | TIMEOUT_MASK, TaskId::KERNEL)
+                .map_or(0, |message| message.operation);
+            if bits & IRQ_MASK != 0 {
+                let poll = self.controller.on_event();
+                sys_irq_control(bits & IRQ_MASK, true);
+                if let Poll::Done(result) = poll {
+                    sys_set_timer(None, TIMEOUT_MASK);
+                    return result.map_err(map_error);
+                }
+            }
+            if bits & TIMEOUT_MASK != 0 && sys_get_timer().now >= deadline {
+                self.controller.abort();
+                return Err(SPIError::Timeout);
+            }
+        }
+    }
+}
+
+impl SPIServer for Server {
+    fn add_device(
+        &mut self,
+        msg: &AddDeviceRequest,
+        sender: TaskId,
+    ) -> Result<AddDeviceResponse, SPIError> {
+        let cs_port = Port::from_u32(msg.cs_port).ok_or(SPIError::BadArgument)?;
+        if msg.cs_pin >= 16 {
+            return Err(SPIError::BadArgument);
+        }
+        if msg.mode > 3 {
+            return Err(SPIError::BadMode);
+        }
+        let owner = sender.component_id();
+        let cs_pin = 1 << msg.cs_pin;
+        let mut free = None;
+        for (index, slot) in self.devices.iter_mut().enumerate() {
+            match slot {
+                Some(d) if d.cs_port == cs_port && d.cs_pin == cs_pin => {
+                    if d.owner != owner {
+                        return Err(SPIError::ChipSelectUnavailable);
+                    }
+                    d.mode = msg.mode as u8;
+                    return Ok(AddDeviceResponse {
+                        device: index as u32,
+                    });
+                }
+                None if free.is_none() => free = Some(index),
+                _ => {}
+            }
+        }
+        let index = free.ok_or(SPIError::TooManyDevices)?;
+        // High before it becomes an output, the device is not selected
+        self.gpio
+            .claim(cs_port, cs_pin)
+            .map_err(|_| SPIError::ChipSelectUnavailable)?;
+        self.gpio
+            .set(cs_port, cs_pin)
+            .map_err(|_| SPIError::ChipSelectUnavailable)?;
+        self.gpio
+            .configure(
+                cs_port,
+                cs_pin,
+                Mode::Output,
+                OutputType::PushPull,
+                Speed::High,
+                Pull::None,
+            )
+            .map_err(|_| SPIError::ChipSelectUnavailable)?;
+        self.devices[index] = Some(Device {
+            owner: owner,
+            cs_port: cs_port,
+            cs_pin: cs_pin,
+            mode: msg.mode as u8,
+        });
+        Ok(AddDeviceResponse {
+            device: index as u32,
+        })
+    }
+
+    fn write(
+        &mut self,
+        msg: &WriteRequest,
+        sender: TaskId,
+        data: &Borrow<'_>,
+        data_len: usize,
+    ) -> Result<(), SPIError> {
+        self.load(data, data_len)?;
+        self.transaction(sender, msg.device, data_len, 0)
+    }
+
+    fn read(
+        &mut self,
+        msg: &ReadRequest,
+        sender: TaskId,
+        buffer: &Borrow<'_>,
+        buffer_len: usize,
+    ) -> Result<(), SPIError> {
+        self.transaction(sender, msg.device, 0, buffer_len)?;
+        buffer
+            .write_fully_at(0, self.controller.received())
+            .ok_or(SPIError::BadArgument)
+    }
+
+    fn write_read(
+        &mut self,
+        msg: &WriteReadRequest,
+        sender: TaskId,
+        data: &Borrow<'_>,
+        data_len: usize,
+        buffer: &Borrow<'_>,
+        buffer_len: usize,
+    ) -> Result<(), SPIError> {
+        self.load(data, data_len)?;
+        self.transaction(sender, msg.device, data_len, buffer_len)?;
+        buffer
+            .write_fully_at(0, self.controller.received())
+            .ok_or(SPIError::BadArgument)
+    }
+
+    fn lock(&mut self, msg: &LockRequest, sender: TaskId) -> Result<(), SPIError> {
+        let device = self.device(sender, msg.device)?;
+        self.check_lock(msg.device)?;
+        if self.locked.is_none() {
+            self.select(&device, true)?;
+            self.locked = Some(msg.device as usize);
+        }
+        Ok(())
+    }
+
+    fn unlock(&mut self, msg: &UnlockRequest, sender: TaskId) -> Result<(), SPIError> {
+        let device = self.device(sender, msg.device)?;
+        if self.locked != Some(msg.device as usize) {
+            return Err(SPIError::NotLocked);
+        }
+        self.locked = None;
+        self.select(&device, false)
+    }
+}
+
+#[export_name = "main"]
+fn main() -> ! {
+    kipc::activate_task();
+    let spi = unsafe { &*device::SPI1::ptr() };
+    setup_gpio().unwrap();
+    let baud_rate = setup_spi().unwrap();
+    let dma = setup_dma(spi).unwrap();
+    let mut server = Server {
+        controller: Controller::new(Bus(spi), dma, baud_rate),
+        gpio: GPIO::new(),
+        devices: [None; MAX_DEVICES],
+        locked: None,
+    };
+    sys_irq_control(IRQ_MASK, true);
+    sys_log!("[SPI] Online!");
+    // Incoming message buffer, as u32 for the alignment of the requests. The
+    // biggest one is the one of AddDevice.
+    let mut buffer = [0u32; 3];
+    loop {
+        // The interrupts are waited for only during the transactions
+        hl::recv_without_notification(buffer.as_bytes_mut(), |op, msg| server.dispatch(op, msg));
+    }
+}
@@ -24,14 +24,128 @@ use flash::FlashInterface;
 use flash_allocator::flash::{walker::FlashWalkerImpl, FlashAllocatorImpl, FlashMethods};
 use ram_allocator::{AllocatorError, RAMAllocator, RAMAllocatorImpl};
 use storage_api::{
-    AllocateComponentRequest, AllocateComponentResponse, DeallocateBlockRequest,
-    FinalizeBlockRequest, GetNthBlockRequest, GetNthBlockResponse, Operation, ReadStreamRequest,
-    ReportStatusRequest, ReportStatusResponse, StorageError, WriteStreamRequest,
+    AllocateComponentRequest, AllocateComponentResponse, AllocateDataRequest, AllocateDataResponse,
+    AllocateSessionRequest, AllocateSessionResponse, DeallocateBlockRequest, FinalizeBlockRequest, GetNthBlockRequest, GetNthBlockResponse,
+    Operation, ReadStreamRequest, ReportStatusRequest, ReportStatusResponse, StorageError,
+    WriteStreamRequest,
+};
+use userlib::{
+    flash::{BlockType, SESSION_AREA_SIZE},
+    hl::Borrow,
+    *,
 };
-use userlib::{flash::BlockType, hl::Borrow, *};
 
 const STORAGE_ANALYZE_MASK: u32 = 1;
 
+// Generated from storage.idl.toml
+include!(concat!(env!("OUT_DIR"), "/server_stub.rs"));
+
+/// Handlers of the storage operations
+struct Server;
+
+impl StorageServer for Server {
+    fn allocate_component(
+        &mut self,
+        msg: &AllocateComponentRequest,
+    ) -> Result<AllocateComponentResponse, StorageError> {
+        // Allocate Flash segment
+        let (flash_base_addr, flash_size) = flash_allocate(msg.flash_size, BlockType::COMPONENT)?;
+        // Allocate RAM (+ mark as component)
+        let (ram_base_addr, ram_size) = ram_allocate(msg.ram_size, flash_base_addr)?;
+        // Respond with data
+        Ok(AllocateComponentResponse {
+            flash_base_address: flash_base_addr,
+            flash_size: flash_size,
+            ram_base_address: ram_base_addr,
+            ram_size: ram_size,
+        })
+    }
+
+    fn allocate_data(
+        &mut self,
+        msg: &AllocateDataRequest,
+    ) -> Result<AllocateDataResponse, StorageError> {
+        // Allocate Flash segment, no RAM is needed
+        let (block_base_addr, block_size) = flash_allocate(msg.size, BlockType::DATA)?;
+        // Respond with data
+        Ok(AllocateDataResponse {
+            block_base_address: block_base_addr,
+            block_size: block_size,
+        })
+    }
+
+    fn allocate_session(
+        &mut self,
+        _msg: &AllocateSessionRequest,
+    ) -> Result<AllocateSessionResponse, StorageError> {
+        // Room for all the records of the session
+        let (block_base_addr, block_size) =
+            flash_allocate(SESSION_AREA_SIZE as u32, BlockType::SESSION)?;
+        Ok(AllocateSessionResponse {
+            block_base_address: block_base_addr,
+            block_size: block_size,
+        })
+    }
+
+    fn deallocate_block(&mut self, msg: &DeallocateBlockRequest) -> Result<(), StorageError> {
+        // TODO: if block is of a component, ask kernel if the component is stopped
+        //       before continuing. Otherwise fail.
+        flash_deallocate(msg.block_base_address)
+    }
+
+    fn write_stream(
+        &mut self,
+        msg: &WriteStreamRequest,
+        data: &Borrow<'_>,
+        data_len: usize,
+    ) -> Result<(), StorageError> {
+        flash_write_stream(
+            msg.block_base_address,
+            msg.offset,
+            data,
+            data_len,
+            msg.flush_after > 0,
+        )
+    }
+
+    fn read_stream(
+        &mut self,
+        msg: &ReadStreamRequest,
+        buffer: &Borrow<'_>,
+        buffer_len: usize,
+    ) -> Result<(), StorageError> {
+        flash_read_stream(msg.block_base_address, msg.offset, buffer, buffer_len)
+    }
+
+    fn finalize_block(&mut self, msg: &FinalizeBlockRequest) -> Result<(), StorageError> {
+        flash_finalize_block(msg.block_base_address)
+    }
+
+    fn report_status(
+        &mut self,
+        _msg: &ReportStatusRequest,
+    ) -> Result<ReportStatusResponse, StorageError> {
+        generate_status()
+    }
+
+    fn get_nth_block(
+        &mut self,
+        msg: &GetNthBlockRequest,
+    ) -> Result<GetNthBlockResponse, StorageError> {
+        // Search for block
+        let (flash_base_addr, flash_size, sram_base_addr, block_type, finalized) =
+            get_nth_block(msg.block_number)?;
+        // Respond with data
+        Ok(GetNthBlockResponse {
+            block_base_address: flash_base_addr,
+            block_size: flash_size,
+            sram_base_address: sram_base_addr,
+            block_type: block_type,
+            finalized: finalized as u32,
+        })
+    }
+}
+
 #[export_name = "main"]
 fn main() -> ! {
     sys_log!("[STORAGE] Hello!");
@@ -40,114 +154,7 @@ fn main() -> ! {
     // Always analyze storage on start-up
     analyze_storage();
     // Message handler
-    let recv_handler = |_s: (), op: Operation, msg: hl::Message| -> Result<(), StorageError> {
-        match op {
-            Operation::AllocateComponent => {
-                // Parse message
-                let (msg, caller) = msg
-                    .fixed::<AllocateComponentRequest, AllocateComponentResponse>()
-                    .ok_or(StorageError::BadArgument)?;
-                // Allocate Flash segment
-                let (flash_base_addr, flash_size) =
-                    flash_allocate(msg.flash_size, BlockType::COMPONENT)?;
-                // Allocate RAM (+ mark as component)
-                let (ram_base_addr, ram_size) = ram_allocate(msg.ram_size, flash_base_addr)?;
-                // Respond with data
-                caller.reply(AllocateComponentResponse {
-                    flash_base_address: flash_base_addr,
-                    flash_size: flash_size,
-                    ram_base_address: ram_base_addr,
-                    ram_size: ram_size,
-                });
-                Ok(())
-            }
-            Operation::DeallocateBlock => {
-                // Parse message
-                let (msg, caller) = msg
-                    .fixed::<DeallocateBlockRequest, ()>()
-                    .ok_or(StorageError::BadArgument)?;
-                // Deallocate block
-                // TODO: if block is of a component, ask kernel if the component is stopped
-                //       before continuing. Otherwise fail.
-                flash_deallocate(msg.block_base_address)?;
-                // Respond okay
-                caller.reply(());
-                Ok(())
-            }
-            Operation::WriteStream => {
-                // Parse message
-                let (msg, caller) = msg
-                    .fixed_with_leases::<WriteStreamRequest, ()>(1)
-                    .ok_or(StorageError::BadArgument)?;
-                // Check lease permissions (readable requested)
-                let borrow = caller.borrow(0);
-                let info = borrow.info().ok_or(StorageError::BadArgument)?;
-                if !info.attributes.contains(LeaseAttributes::READ) {
-                    return Err(StorageError::BadArgument);
-                }
-                // Perform the write
-                flash_write_stream(msg.block_base_address, msg.offset, &borrow, info.len, msg.flush_after > 0)?;
-                // Respond okay
-                caller.reply(());
-                Ok(())
-            }
-            Operation::ReadStream => {
-                // Parse message
-                let (msg, caller) = msg
-                    .fixed_with_leases::<ReadStreamRequest, ()>(1)
-                    .ok_or(StorageError::BadArgument)?;
-                // Check lease permissions (writable requested)
-                let borrow = caller.borrow(0);
-                let info = borrow.info().ok_or(StorageError::BadArgument)?;
-                if !info.attributes.contains(LeaseAttributes::WRITE) {
-                    return Err(StorageError::BadArgument);
-                }
-                // Perform the read
-                flash_read_stream(msg.block_base_address, msg.offset, &borrow, info.len)?;
-                // Respond okay
-                caller.reply(());
-                Ok(())
-            }
-            Operation::FinalizeBlock => {
-                // Parse message
-                let (msg, caller) = msg
-                    .fixed::<FinalizeBlockRequest, ()>()
-                    .ok_or(StorageError::BadArgument)?;
-                // Deallocate block
-                flash_finalize_block(msg.block_base_address)?;
-                // Respond okay
-                caller.reply(());
-                Ok(())
-            }
-            Operation::ReportStatus => {
-                // Parse message
-                let (_, caller) = msg
-                    .fixed::<ReportStatusRequest, ReportStatusResponse>()
-                    .ok_or(StorageError::BadArgument)?;
-                // Generate status
-                let response = generate_status()?;
-                // Respond okay
-                caller.reply(response);
-                Ok(())
-            }
-            Operation::GetNthBlock => {
-                // Parse message
-                let (msg, caller) = msg
-                    .fixed::<GetNthBlockRequest, GetNthBlockResponse>()
-                    .ok_or(StorageError::BadArgument)?;
-                // Search for block
-                let (flash_base_addr, flash_size, block_type) = get_nth_block(msg.block_number)?;
-                // Respond with data
-                caller.reply(GetNthBlockResponse {
-                    block_base_address: flash_base_addr,
-                    block_size: flash_size,
-                    block_type: block_type,
-                });
-                Ok(())
-            }
-        }
-    };
-
+    let mut server = Server;
     // Incoming message buffer
     // Must be as big as the biggest structure of the request
     // In this case at least 3*4 = 12 bytes
@@ -159,15 +166,15 @@ fn main() -> ! {
         hl::recv(
             &mut buffer,
             STORAGE_ANALYZE_MASK,
-            (),
-            |_s, bits| {
+            &mut server,
+            |_server, bits| {
                 // Check we got the right one
                 if bits & STORAGE_ANALYZE_MASK > 0 {
                     // The kernel indirectly asks to erase a block or validate storage
                     analyze_storage();
                 }
             },
-            recv_handler,
+            |server, op, msg| server.dispatch(op, msg),
         );
     }
 }
@@ -463,7 +470,7 @@ fn ram_allocate(
     }
 }
 
-fn get_nth_block(block_number: u32) -> Result<(u32, u32, BlockType), StorageError> {
+fn get_nth_block(block_number: u32) -> Result<(u32, u32, u32, BlockType, bool), StorageError> {
     // Instantiate the flash operators
     let mut flash = FlashInterface::new();
     // Create flash walker
@@ -477,8 +484,23 @@ fn get_nth_block(block_number: u32) -> Result<(u32, u32, BlockType), StorageErro
     let mut count: u32 = 0;
     for b in walker {
         if count == block_number {
-            // Found the block, return data
-            return Ok((b.get_base_address(), b.get_size(), b.get_type()));
+            // Found the block, read the SRAM base placed before the CBF
+            let mut sram_base: u32 = 0;
+            if b.get_type() == BlockType::COMPONENT {
+                let mut sram_base_bytes: [u8; 4] = [0x00; 4];
+                FlashInterface::new()
+                    .read(b.get_base_address(), &mut sram_base_bytes)
+                    .map_err(|_| StorageError::FlashError)?;
+                sram_base = u32::from_le_bytes(sram_base_bytes);
+            }
+            // Return data
+            return Ok((
+                b.get_base_address(),
+                b.get_size(),
+                sram_base,
+                b.get_type(),
+                b.is_finalized(),
+            ));
         }
         count += 1;
     }
@@ -8,6 +8,23 @@
 use test_b_api::*;
 use userlib::*;
 
+// Generated from test_b.idl.toml
+include!(concat!(env!("OUT_DIR"), "/server_stub.rs"));
+
+struct Server {
+    state: u32,
+}
+
+impl TestBServer for Server {
+    fn mock1(&mut self, _msg: &Mock1Request) -> Result<(), BError> {
+        sys_log!("[TEST_B] Got request");
+        // Sleep a bit
+        hl::sleep_for(1000);
+        sys_log!("[TEST_B] Replied");
+        Ok(())
+    }
+}
+
 #[export_name = "main"]
 fn main() -> ! {
     // Read state
@@ -21,34 +38,20 @@ fn main() -> ! {
     // Register callback for state transfer
     kipc::set_update_support(true);
 
-    let recv_handler = |_state, op: Operation, msg: hl::Message| -> Result<(), BError> {
-        match op {
-            Operation::Mock1 => {
-                let (_msg, caller) = msg.fixed::<Mock1Request, ()>().ok_or(BError::BadArgument)?;
-                sys_log!("[TEST_B] Got request");
-                // Sleep a bit
-                hl::sleep_for(1000);
-                sys_log!("[TEST_B] Replied");
-                caller.reply(());
-                Ok(())
-            }
-        }
-    };
-
     sys_log!("[TEST_B] Online!");
 
     let mut buff: [u8; 8] = [0; 8];
-    let state: u32 = 21;
+    let mut server = Server { state: 21 };
     loop {
         hl::recv(
             &mut buff,
             STATE_TRANSFER_REQUESTED_MASK,
-            &state,
-            |state_ref, _bits| {
+            &mut server,
+            |server, _bits| {
                 // State transfer requested
-                update_handler(*state_ref);
+                update_handler(server.state);
             },
-            recv_handler,
+            |server, op, msg| server.dispatch(op, msg),
         );
     }
 }
@@ -0,0 +1,18 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+#![no_std]
+
+/// Duty cycle of 100%, in hundredths of a percent
+pub use hw_timer::DUTY_MAX;
+
+// Generated from timer.idl.toml
+include!(concat!(env!("OUT_DIR"), "/client_stub.rs"));
+
+/// Alarms, on the basic timers TIM6 and TIM7
+pub const ALARMS: usize = 2;
+/// PWM channels, the outputs of TIM1
+pub const PWM_CHANNELS: usize = 4;
+/// Capture channels, the inputs of TIM2 (only 0 and 1 on the L432KC)
+pub const CAPTURE_CHANNELS: usize = 4;
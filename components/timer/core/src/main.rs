@@ -0,0 +1,552 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+#![no_std]
+#![no_main]
+
+use gpio_api::{Mode, OutputType, Port, Pull, Speed, GPIO};
+use hw_timer::{Capture, Timing, TimingError};
+use rcc_api::{Peripheral, RCCError, RCC};
+use timer_api::*;
+use userlib::*;
+use zerocopy::AsBytes;
+
+// STM32F3
+#[cfg(feature = "board_stm32f303re")]
+use stm32f303re::device;
+
+// STM32L432
+#[cfg(feature = "board_stm32l432kc")]
+use stm32l432kc::device;
+
+// STM32L476
+#[cfg(feature = "board_stm32l476rg")]
+use stm32l476rg::device;
+
+// Generated from timer.idl.toml
+include!(concat!(env!("OUT_DIR"), "/server_stub.rs"));
+
+/**
+ * Constants
+ */
+// Notification bits of the interrupts, as in Component.toml
+const TIM2_IRQ_MASK: u32 = 1 << 0;
+const TIM6_IRQ_MASK: u32 = 1 << 1;
+const TIM7_IRQ_MASK: u32 = 1 << 2;
+const ALARM_IRQ_MASKS: [u32; ALARMS] = [TIM6_IRQ_MASK, TIM7_IRQ_MASK];
+
+/// Counter of the capture timer, one tick per microsecond
+const CAPTURE_CLOCK_HZ: u32 = 1_000_000;
+
+// CR1
+const CR1_CEN: u32 = 1 << 0;
+const CR1_URS: u32 = 1 << 2;
+const CR1_OPM: u32 = 1 << 3;
+const CR1_ARPE: u32 = 1 << 7;
+// DIER and SR
+const UIE: u32 = 1 << 0;
+const SR_CCOF_MASK: u32 = 0b1111 << 9;
+// EGR
+const EGR_UG: u32 = 1 << 0;
+// CCMR, each register has two channels of 8 bits
+// PWM mode 1 (OCxM) with the preload (OCxPE)
+const CCMR_PWM1: u32 = 0b110 << 4 | 1 << 3;
+// Input on its own pin (CCxS), filtered on 8 samples (ICxF)
+const CCMR_INPUT_TI: u32 = 0b01 | 0b0011 << 4;
+// Bits of a channel, with the bit 3 of OCxM at 16
+const CCMR_CHANNEL_MASK: u32 = 0x0001_00FF;
+// CCER, each channel has 4 bits (CCxE, rising edges with CCxP clear)
+const CCER_CCE: u32 = 1 << 0;
+const CCER_CHANNEL_MASK: u32 = 0b1111;
+// BDTR
+const BDTR_MOE: u32 = 1 << 15;
+
+/**
+ * Pins
+ * (port, pin, alternate function) of the channels
+ */
+#[cfg(feature = "board_stm32f303re")]
+const PWM_PINS: [(Port, u8, u8); PWM_CHANNELS] = [
+    (Port::A, 8, 6),
+    (Port::A, 9, 6),
+    (Port::A, 10, 6),
+    (Port::A, 11, 11),
+];
+#[cfg(any(feature = "board_stm32l432kc", feature = "board_stm32l476rg"))]
+const PWM_PINS: [(Port, u8, u8); PWM_CHANNELS] = [
+    (Port::A, 8, 1),
+    (Port::A, 9, 1),
+    (Port::A, 10, 1),
+    (Port::A, 11, 1),
+];
+
+#[cfg(any(feature = "board_stm32f303re", feature = "board_stm32l476rg"))]
+const CAPTURE_PINS: [Option<(Port, u8, u8)>; CAPTURE_CHANNELS] = [
+    Some((Port::A, 0, 1)),
+    Some((Port::A, 1, 1)),
+    Some((Port::B, 10, 1)),
+    Some((Port::B, 11, 1)),
+];
+// The other pins of TIM2 are the ones of USART2
+#[cfg(feature = "board_stm32l432kc")]
+const CAPTURE_PINS: [Option<(Port, u8, u8)>; CAPTURE_CHANNELS] =
+    [Some((Port::A, 0, 1)), Some((Port::A, 1, 1)), None, None];
+
+/**
+ * Hardware
+ */
+// The compare registers are an array on some boards, fields on the others
+#[cfg(feature = "board_stm32l432kc")]
+macro_rules! ccr {
+    ($tim:expr, $n:ident) => {
+        $tim.$n
+    };
+}
+#[cfg(any(feature = "board_stm32f303re", feature = "board_stm32l476rg"))]
+macro_rules! ccr {
+    ($tim:expr, $n:ident) => {
+        $tim.$n()
+    };
+}
+
+macro_rules! write_ccr {
+    ($tim:expr, $channel:expr, $value:expr) => {
+        match $channel {
+            0 => ccr!($tim, ccr1).write(|w| unsafe { w.bits($value) }),
+            1 => ccr!($tim, ccr2).write(|w| unsafe { w.bits($value) }),
+            2 => ccr!($tim, ccr3).write(|w| unsafe { w.bits($value) }),
+            _ => ccr!($tim, ccr4).write(|w| unsafe { w.bits($value) }),
+        }
+    };
+}
+
+macro_rules! read_ccr {
+    ($tim:expr, $channel:expr) => {
+        match $channel {
+            0 => ccr!($tim, ccr1).read().bits(),
+            1 => ccr!($tim, ccr2).read().bits(),
+            2 => ccr!($tim, ccr3).read().bits(),
+            _ => ccr!($tim, ccr4).read().bits(),
+        }
+    };
+}
+
+// Channels 0 and 1 in CCMR1, 2 and 3 in CCMR2
+macro_rules! update_ccmr {
+    ($tim:expr, $register:ident, $channel:expr, $value:expr) => {{
+        let shift = ($channel as u32 % 2) * 8;
+        let (mask, bits) = (CCMR_CHANNEL_MASK << shift, $value << shift);
+        if $channel < 2 {
+            paste_ccmr!($tim, 1, $register, mask, bits)
+        } else {
+            paste_ccmr!($tim, 2, $register, mask, bits)
+        }
+    }};
+}
+
+macro_rules! paste_ccmr {
+    ($tim:expr, 1, output, $mask:expr, $bits:expr) => {
+        $tim.ccmr1_output()
+            .modify(|r, w| unsafe { w.bits(r.bits() & !$mask | $bits) })
+    };
+    ($tim:expr, 2, output, $mask:expr, $bits:expr) => {
+        $tim.ccmr2_output()
+            .modify(|r, w| unsafe { w.bits(r.bits() & !$mask | $bits) })
+    };
+    ($tim:expr, 1, input, $mask:expr, $bits:expr) => {
+        $tim.ccmr1_input()
+            .modify(|r, w| unsafe { w.bits(r.bits() & !$mask | $bits) })
+    };
+    ($tim:expr, 2, input, $mask:expr, $bits:expr) => {
+        $tim.ccmr2_input()
+            .modify(|r, w| unsafe { w.bits(r.bits() & !$mask | $bits) })
+    };
+}
+
+/// Returns the clocks of the alarms and of the PWM timer, as set by the rcc
+fn setup_timers() -> Result<(u32, u32), RCCError> {
+    let mut rcc = RCC::new();
+    for peripheral in [
+        Peripheral::TIM1,
+        Peripheral::TIM2,
+        Peripheral::TIM6,
+        Peripheral::TIM7,
+    ] {
+        rcc.enable_clock(peripheral)?;
+        rcc.leave_reset(peripheral)?;
+    }
+    // The capture counter runs freely on all the 32 bits
+    let tim2 = unsafe { &*device::TIM2::ptr() };
+    let capture_clock = rcc.get_clock_frequency(Peripheral::TIM2)?;
+    tim2.psc
+        .write(|w| unsafe { w.bits(capture_clock / CAPTURE_CLOCK_HZ - 1) });
+    tim2.arr.write(|w| unsafe { w.bits(u32::MAX) });
+    tim2.egr.write(|w| unsafe { w.bits(EGR_UG) });
+    tim2.cr1.write(|w| unsafe { w.bits(CR1_URS | CR1_CEN) });
+    // TIM6 and TIM7 are on the same bus
+    Ok((
+        rcc.get_clock_frequency(Peripheral::TIM6)?,
+        rcc.get_clock_frequency(Peripheral::TIM1)?,
+    ))
+}
+
+/// Gives the pin of a channel to its alternate function
+fn claim_pin(pin: (Port, u8, u8), output: bool) -> Result<(), TimerError> {
+    let (port, number, function) = pin;
+    let mut gpio = GPIO::new();
+    gpio.claim(port, 1 << number)
+        .map_err(|_| TimerError::PinUnavailable)?;
+    let pull = if output { Pull::None } else { Pull::Down };
+    gpio.configure(
+        port,
+        1 << number,
+        Mode::Input,
+        OutputType::PushPull,
+        Speed::High,
+        pull,
+    )
+    .and_then(|_| gpio.set_alternate(port, 1 << number, function))
+    .map_err(|_| {
+        let _ = gpio.release(port, 1 << number);
+        TimerError::PinUnavailable
+    })
+}
+
+fn release_pin(pin: (Port, u8, u8)) {
+    let (port, number, _) = pin;
+    // Back to a floating input
+    let _ = GPIO::new().release(port, 1 << number);
+}
+
+/**
+ * Server
+ */
+struct Alarm {
+    timer: &'static device::tim6::RegisterBlock,
+    /// The one that took it, with the bits to post
+    owner: Option<(TaskId, u32)>,
+}
+
+struct Server {
+    alarms: [Alarm; ALARMS],
+    /// Clock of the alarm timers in Hz
+    alarm_clock: u32,
+    pwm: &'static device::tim1::RegisterBlock,
+    /// Clock of the PWM timer in Hz
+    pwm_clock: u32,
+    /// Component ID of the owners of the PWM channels
+    pwm_owners: [Option<u16>; PWM_CHANNELS],
+    /// Frequency and timing shared by the PWM channels in use
+    pwm_timing: Option<(u32, Timing)>,
+    capture: &'static device::tim2::RegisterBlock,
+    capture_owners: [Option<u16>; CAPTURE_CHANNELS],
+    captures: [Capture; CAPTURE_CHANNELS],
+}
+
+impl Server {
+    fn alarm(&mut self, alarm: u32, sender: TaskId) -> Result<&mut Alarm, TimerError> {
+        let alarm = self
+            .alarms
+            .get_mut(alarm as usize)
+            .ok_or(TimerError::InvalidAlarm)?;
+        match alarm.owner {
+            Some((task, _)) if task.component_id() == sender.component_id() => Ok(alarm),
+            _ => Err(TimerError::NotOwner),
+        }
+    }
+
+    fn pwm_channel(&self, channel: u32, sender: TaskId) -> Result<usize, TimerError> {
+        let channel = channel as usize;
+        if channel >= PWM_CHANNELS {
+            return Err(TimerError::InvalidChannel);
+        }
+        if self.pwm_owners[channel] != Some(sender.component_id()) {
+            return Err(TimerError::NotOwner);
+        }
+        Ok(channel)
+    }
+
+    fn capture_channel(&self, channel: u32, sender: TaskId) -> Result<usize, TimerError> {
+        let channel = channel as usize;
+        if channel >= CAPTURE_CHANNELS || CAPTURE_PINS[channel].is_none() {
+            return Err(TimerError::InvalidChannel);
+        }
+        if self.capture_owners[channel] != Some(sender.component_id()) {
+            return Err(TimerError::NotOwner);
+        }
+        Ok(channel)
+    }
+
+    /// Posts the expired alarms to their owners
+    fn handle_alarms(&mut self, bits: u32) {
+        for (alarm, mask) in self.alarms.iter_mut().zip(ALARM_IRQ_MASKS) {
+            if bits & mask == 0 {
+                continue;
+            }
+            alarm.timer.sr.write(|w| unsafe { w.bits(0) });
+            if let Some((task, notification)) = &mut alarm.owner {
+                // The owner may have been restarted in the meantime
+                *task = sys_refresh_task_id(*task);
+                sys_post(*task, *notification);
+            }
+            sys_irq_control(mask, true);
+        }
+    }
+
+    fn handle_capture(&mut self) {
+        let sr = self.capture.sr.read().bits();
+        for channel in 0..CAPTURE_CHANNELS {
+            if sr & (1 << (channel + 1)) != 0 {
+                // Reading the value clears the flag
+                let value = read_ccr!(self.capture, channel);
+                self.captures[channel].on_edge(value);
+            }
+        }
+        // A missed edge only makes a period longer, the next one is right
+        if sr & SR_CCOF_MASK != 0 {
+            self.capture
+                .sr
+                .write(|w| unsafe { w.bits(!(sr & SR_CCOF_MASK)) });
+        }
+        sys_irq_control(TIM2_IRQ_MASK, true);
+    }
+}
+
+fn map_timing_error(error: TimingError, bad_time: TimerError) -> TimerError {
+    match error {
+        TimingError::BadDuty => TimerError::BadDuty,
+        TimingError::TooShort | TimingError::TooLong => bad_time,
+    }
+}
+
+impl TimerServer for Server {
+    fn allocate_alarm(
+        &mut self,
+        msg: &AllocateAlarmRequest,
+        sender: TaskId,
+    ) -> Result<AllocateAlarmResponse, TimerError> {
+        if msg.notification == 0 {
+            return Err(TimerError::BadArgument);
+        }
+        let index = self
+            .alarms
+            .iter()
+            .position(|a| a.owner.is_none())
+            .ok_or(TimerError::NoAlarmAvailable)?;
+        self.alarms[index].owner = Some((sender, msg.notification));
+        Ok(AllocateAlarmResponse {
+            alarm: index as u32,
+        })
+    }
+
+    fn free_alarm(&mut self, msg: &FreeAlarmRequest, sender: TaskId) -> Result<(), TimerError> {
+        let alarm = self.alarm(msg.alarm, sender)?;
+        alarm.timer.cr1.write(|w| unsafe { w.bits(0) });
+        alarm.timer.dier.write(|w| unsafe { w.bits(0) });
+        alarm.owner = None;
+        Ok(())
+    }
+
+    fn start_alarm(&mut self, msg: &StartAlarmRequest, sender: TaskId) -> Result<(), TimerError> {
+        let timing = Timing::for_period(self.alarm_clock, msg.period_us)
+            .map_err(|e| map_timing_error(e, TimerError::BadPeriod))?;
+        let alarm = self.alarm(msg.alarm, sender)?;
+        let timer = alarm.timer;
+        timer.cr1.write(|w| unsafe { w.bits(CR1_URS) });
+        timer.psc.write(|w| unsafe { w.bits(timing.prescaler) });
+        timer.arr.write(|w| unsafe { w.bits(timing.reload) });
+        // Load the prescaler and start from 0, without an interrupt (URS)
+        timer.egr.write(|w| unsafe { w.bits(EGR_UG) });
+        timer.sr.write(|w| unsafe { w.bits(0) });
+        timer.dier.write(|w| unsafe { w.bits(UIE) });
+        // A one-shot timer stops by itself at the update
+        let mode = if msg.periodic != 0 { 0 } else { CR1_OPM };
+        timer
+            .cr1
+            .write(|w| unsafe { w.bits(CR1_URS | mode | CR1_CEN) });
+        Ok(())
+    }
+
+    fn stop_alarm(&mut self, msg: &StopAlarmRequest, sender: TaskId) -> Result<(), TimerError> {
+        let alarm = self.alarm(msg.alarm, sender)?;
+        alarm.timer.cr1.write(|w| unsafe { w.bits(CR1_URS) });
+        alarm.timer.sr.write(|w| unsafe { w.bits(0) });
+        Ok(())
+    }
+
+    fn configure_pwm(
+        &mut self,
+        msg: &ConfigurePwmRequest,
+        sender: TaskId,
+    ) -> Result<(), TimerError> {
+        let channel = msg.channel as usize;
+        if channel >= PWM_CHANNELS {
+            return Err(TimerError::InvalidChannel);
+        }
+        let owner = sender.component_id();
+        match self.pwm_owners[channel] {
+            Some(other) if other != owner => return Err(TimerError::ChannelInUse),
+            _ => {}
+        }
+        let timing = Timing::for_frequency(self.pwm_clock, msg.frequency)
+            .map_err(|e| map_timing_error(e, TimerError::BadFrequency))?;
+        let compare = timing
+            .compare(msg.duty as u16)
+            .map_err(|e| map_timing_error(e, TimerError::BadFrequency))?;
+        // The counter is shared, its frequency is kept while others use it
+        let others = (0..PWM_CHANNELS).any(|c| c != channel && self.pwm_owners[c].is_some());
+        let change = match self.pwm_timing {
+            Some((frequency, _)) if frequency == msg.frequency => false,
+            Some(_) if others => return Err(TimerError::FrequencyInUse),
+            _ => true,
+        };
+        if self.pwm_owners[channel].is_none() {
+            claim_pin(PWM_PINS[channel], true)?;
+            self.pwm_owners[channel] = Some(owner);
+        }
+        let tim1 = self.pwm;
+        if change {
+            tim1.psc.write(|w| unsafe { w.bits(timing.prescaler) });
+            tim1.arr.write(|w| unsafe { w.bits(timing.reload) });
+            tim1.egr.write(|w| unsafe { w.bits(EGR_UG) });
+            self.pwm_timing = Some((msg.frequency, timing));
+        }
+        update_ccmr!(tim1, output, channel, CCMR_PWM1);
+        write_ccr!(tim1, channel, compare);
+        tim1.ccer.modify(|r, w| unsafe {
+            w.bits(r.bits() & !(CCER_CHANNEL_MASK << (channel * 4)) | CCER_CCE << (channel * 4))
+        });
+        tim1.bdtr
+            .modify(|r, w| unsafe { w.bits(r.bits() | BDTR_MOE) });
+        tim1.cr1
+            .write(|w| unsafe { w.bits(CR1_URS | CR1_ARPE | CR1_CEN) });
+        Ok(())
+    }
+
+    fn set_duty(&mut self, msg: &SetDutyRequest, sender: TaskId) -> Result<(), TimerError> {
+        let channel = self.pwm_channel(msg.channel, sender)?;
+        let (_, timing) = self.pwm_timing.ok_or(TimerError::BadArgument)?;
+        let compare = timing
+            .compare(msg.duty as u16)
+            .map_err(|e| map_timing_error(e, TimerError::BadFrequency))?;
+        // Preloaded, it changes at the end of the current period
+        write_ccr!(self.pwm, channel, compare);
+        Ok(())
+    }
+
+    fn release_pwm(&mut self, msg: &ReleasePwmRequest, sender: TaskId) -> Result<(), TimerError> {
+        let channel = self.pwm_channel(msg.channel, sender)?;
+        let tim1 = self.pwm;
+        tim1.ccer
+            .modify(|r, w| unsafe { w.bits(r.bits() & !(CCER_CHANNEL_MASK << (channel * 4))) });
+        write_ccr!(tim1, channel, 0);
+        release_pin(PWM_PINS[channel]);
+        self.pwm_owners[channel] = None;
+        if self.pwm_owners.iter().all(|o| o.is_none()) {
+            tim1.cr1.write(|w| unsafe { w.bits(0) });
+            self.pwm_timing = None;
+        }
+        Ok(())
+    }
+
+    fn start_capture(
+        &mut self,
+        msg: &StartCaptureRequest,
+        sender: TaskId,
+    ) -> Result<(), TimerError> {
+        let channel = msg.channel as usize;
+        let pin = match CAPTURE_PINS.get(channel) {
+            Some(Some(pin)) => *pin,
+            _ => return Err(TimerError::InvalidChannel),
+        };
+        match self.capture_owners[channel] {
+            Some(owner) if owner == sender.component_id() => return Ok(()),
+            Some(_) => return Err(TimerError::ChannelInUse),
+            None => {}
+        }
+        claim_pin(pin, false)?;
+        self.capture_owners[channel] = Some(sender.component_id());
+        self.captures[channel] = Capture::new();
+        let tim2 = self.capture;
+        update_ccmr!(tim2, input, channel, CCMR_INPUT_TI);
+        tim2.ccer.modify(|r, w| unsafe {
+            w.bits(r.bits() & !(CCER_CHANNEL_MASK << (channel * 4)) | CCER_CCE << (channel * 4))
+        });
+        tim2.dier
+            .modify(|r, w| unsafe { w.bits(r.bits() | 1 << (channel + 1)) });
+        Ok(())
+    }
+
+    fn read_capture(
+        &mut self,
+        msg: &ReadCaptureRequest,
+        sender: TaskId,
+    ) -> Result<ReadCaptureResponse, TimerError> {
+        let channel = self.capture_channel(msg.channel, sender)?;
+        let capture = &self.captures[channel];
+        let period = capture.period().ok_or(TimerError::NoCapture)?;
+        Ok(ReadCaptureResponse {
+            period_us: period,
+            edges: capture.edges(),
+        })
+    }
+
+    fn stop_capture(&mut self, msg: &StopCaptureRequest, sender: TaskId) -> Result<(), TimerError> {
+        let channel = self.capture_channel(msg.channel, sender)?;
+        let tim2 = self.capture;
+        tim2.dier
+            .modify(|r, w| unsafe { w.bits(r.bits() & !(1 << (channel + 1))) });
+        tim2.ccer
+            .modify(|r, w| unsafe { w.bits(r.bits() & !(CCER_CHANNEL_MASK << (channel * 4))) });
+        if let Some(pin) = CAPTURE_PINS[channel] {
+            release_pin(pin);
+        }
+        self.capture_owners[channel] = None;
+        Ok(())
+    }
+}
+
+#[export_name = "main"]
+fn main() -> ! {
+    kipc::activate_task();
+    let (alarm_clock, pwm_clock) = setup_timers().unwrap();
+    let mut server = Server {
+        alarms: [
+            Alarm {
+                timer: unsafe { &*device::TIM6::ptr() },
+                owner: None,
+            },
+            Alarm {
+                timer: unsafe { &*device::TIM7::ptr() },
+                owner: None,
+            },
+        ],
+        alarm_clock,
+        pwm: unsafe { &*device::TIM1::ptr() },
+        pwm_clock,
+        pwm_owners: [None; PWM_CHANNELS],
+        pwm_timing: None,
+        capture: unsafe { &*device::TIM2::ptr() },
+        capture_owners: [None; CAPTURE_CHANNELS],
+        captures: [Capture::new(); CAPTURE_CHANNELS],
+    };
+    sys_irq_control(TIM2_IRQ_MASK | TIM6_IRQ_MASK | TIM7_IRQ_MASK, true);
+    sys_log!("[TIMER] Online!");
+    // Incoming message buffer, as u32 for the alignment of the requests
+    let mut buffer = [0u32; 3];
+    loop {
+        hl::recv(
+            buffer.as_bytes_mut(),
+            TIM2_IRQ_MASK | TIM6_IRQ_MASK | TIM7_IRQ_MASK,
+            &mut server,
+            |server, bits| {
+                if bits & TIM2_IRQ_MASK != 0 {
+                    server.handle_capture();
+                }
+                server.handle_alarms(bits);
+            },
+            |server, op, msg| server.dispatch(op, msg),
+        );
+    }
+}
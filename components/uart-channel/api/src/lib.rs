@@ -4,248 +4,110 @@
 
 #![no_std]
 
-use core::cell::Cell;
+// Generated from uart_channel.idl.toml
+include!(concat!(env!("OUT_DIR"), "/client_stub.rs"));
 
-use userlib::{hl, FromPrimitive, Lease, TaskId};
-use zerocopy::{AsBytes, FromBytes};
-
-/**
- * Constants
- */
-const UART_CHANNEL_ID: TaskId = TaskId(3);
-
-/**
- * Error Type
- */
-#[derive(Copy, Clone, Debug, PartialEq, PartialOrd)]
-#[repr(u32)]
-pub enum ChannelError {
-    ChannelBusy = 1,
-    BadArgument = 2,
-    ReadTimeOut = 3,
-    ComponentUnavailable = 4,
-}
-impl From<u32> for ChannelError {
-    fn from(x: u32) -> Self {
-        match x {
-            1 => ChannelError::ChannelBusy,
-            2 => ChannelError::BadArgument,
-            3 => ChannelError::ReadTimeOut,
-            _ => ChannelError::ComponentUnavailable,
-        }
-    }
-}
-impl From<ChannelError> for u32 {
-    fn from(x: ChannelError) -> Self {
-        x as u32
+impl UartChannel {
+    /// Client of another instance of the component, built with the id given
+    /// to it in App.toml
+    pub fn for_component(component_id: u16) -> Self {
+        Self(core::cell::Cell::new(userlib::TaskId(component_id)))
     }
 }
 
 /**
- * Operations
- */
-#[derive(Copy, Clone, Debug, FromPrimitive)]
-pub enum Operation {
-    WriteBlock = 1,
-    ReadBlock = 2,
-    ReadBlockTimed = 3,
-    TransmitTimed = 4,
-}
-
-#[cfg(feature = "multi-support")]
-#[derive(FromBytes, AsBytes)]
-#[repr(C)]
-pub struct WriteBlockRequest {
-    pub channel_id: u32,
-}
-#[cfg(not(feature = "multi-support"))]
-#[derive(FromBytes, AsBytes)]
-#[repr(C)]
-pub struct WriteBlockRequest {}
-
-impl hl::Call for WriteBlockRequest {
-    const OP: u16 = Operation::WriteBlock as u16;
-    type Response = ();
-    type Err = ChannelError;
-}
-
-#[cfg(feature = "multi-support")]
-#[derive(FromBytes, AsBytes)]
-#[repr(C)]
-pub struct ReadBlockRequest {
-    pub channel_id: u32,
-}
-#[cfg(not(feature = "multi-support"))]
-#[derive(FromBytes, AsBytes)]
-#[repr(C)]
-pub struct ReadBlockRequest {}
-
-impl hl::Call for ReadBlockRequest {
-    const OP: u16 = Operation::ReadBlock as u16;
-    type Response = ();
-    type Err = ChannelError;
-}
-
-// ReadBlockTimed
-#[cfg(feature = "multi-support")]
-#[derive(FromBytes, AsBytes)]
-#[repr(C)]
-pub struct ReadBlockTimedRequest {
-    pub timeout_ticks: u32,
-    pub channel_id: u32,
-}
-#[cfg(not(feature = "multi-support"))]
-#[derive(FromBytes, AsBytes)]
-#[repr(C)]
-pub struct ReadBlockTimedRequest {
-    pub timeout_ticks: u32,
-}
-
-impl hl::Call for ReadBlockTimedRequest {
-    const OP: u16 = Operation::ReadBlockTimed as u16;
-    type Response = ();
-    type Err = ChannelError;
-}
-
-// TransmitTimed
-#[cfg(feature = "multi-support")]
-#[derive(FromBytes, AsBytes)]
-#[repr(C)]
-pub struct TransmitTimedRequest {
-    pub timeout_ticks: u32,
-    pub channel_id: u32,
-}
-#[cfg(not(feature = "multi-support"))]
-#[derive(FromBytes, AsBytes)]
-#[repr(C)]
-pub struct TransmitTimedRequest {
-    pub timeout_ticks: u32,
-}
-
-impl hl::Call for TransmitTimedRequest {
-    const OP: u16 = Operation::TransmitTimed as u16;
-    type Response = ();
-    type Err = ChannelError;
-}
-
-/**
- * Single transmitter - Receiver interface
- * for USART2
+ * Update transport
  */
-pub struct UartChannel(Cell<TaskId>);
-
-impl UartChannel {
-    pub fn new() -> Self {
-        Self {
-            0: Cell::new(UART_CHANNEL_ID),
+#[cfg(feature = "update-transport")]
+impl From<ChannelError> for update_transport::TransportError {
+    fn from(x: ChannelError) -> Self {
+        match x {
+            ChannelError::ReadTimeOut => update_transport::TransportError::ReadTimeOut,
+            _ => update_transport::TransportError::ChannelError,
         }
     }
 }
 
-#[cfg(feature = "multi-support")]
-impl UartChannel {
-    pub fn write_block(
-        &mut self,
-        channel_id: u16,
-        data: &[u8],
-    ) -> Result<(), ChannelError> {
-        hl::send_with_retry(
-            &self.0,
-            &WriteBlockRequest { channel_id: channel_id as u32 },
-            &[Lease::read_only(data)],
-        )
+#[cfg(all(feature = "update-transport", not(feature = "multi-support")))]
+impl update_transport::UpdateTransport for UartChannel {
+    fn write_block(&mut self, data: &[u8]) -> Result<(), update_transport::TransportError> {
+        Ok(UartChannel::write_block(self, data)?)
     }
-    pub fn read_block(
-        &mut self,
-        channel_id: u16,
-        data: &mut [u8],
-    ) -> Result<(), ChannelError> {
-        hl::send_with_retry(
-            &self.0,
-            &ReadBlockRequest { channel_id: channel_id as u32 },
-            &[Lease::write_only(data)],
-        )
+    fn read_block(&mut self, data: &mut [u8]) -> Result<(), update_transport::TransportError> {
+        Ok(UartChannel::read_block(self, data)?)
     }
-    pub fn read_block_timed(
+    fn read_block_timed(
         &mut self,
-        channel_id: u16,
         data: &mut [u8],
         timeout_ticks: u32,
-    ) -> Result<(), ChannelError> {
-        hl::send_with_retry(
-            &self.0,
-            &ReadBlockTimedRequest {
-                channel_id: channel_id as u32,
-                timeout_ticks: timeout_ticks,
-            },
-            &[Lease::write_only(data)],
-        )
+    ) -> Result<(), update_transport::TransportError> {
+        Ok(UartChannel::read_block_timed(self, data, timeout_ticks)?)
     }
-    /// New method that allow transmitting data while first setting up the system for reception.
-    /// This is especially useful when dealing with quick responses, that could be missed for
-    /// unlucky context switches that delay the setup of the standard reception buffer
-    pub fn transmit_timed(
+    fn transmit_timed(
         &mut self,
-        channel_id: u16,
         data_out: &[u8],
         data_in: &mut [u8],
         timeout_ticks: u32,
-    ) -> Result<(), ChannelError> {
-        hl::send_with_retry(
-            &self.0,
-            &TransmitTimedRequest {
-                channel_id: channel_id as u32,
-                timeout_ticks: timeout_ticks,
-            },
-            &[Lease::read_only(data_out), Lease::write_only(data_in)],
-        )
+    ) -> Result<(), update_transport::TransportError> {
+        Ok(UartChannel::transmit_timed(
+            self,
+            data_out,
+            data_in,
+            timeout_ticks,
+        )?)
     }
 }
 
-#[cfg(not(feature = "multi-support"))]
-impl UartChannel {
-    pub fn write_block(&mut self, data: &[u8]) -> Result<(), ChannelError> {
-        hl::send_with_retry(
-            &self.0,
-            &WriteBlockRequest {},
-            &[Lease::read_only(data)],
-        )
+/// One of the channels muxed on the serial line, seen as a transport of its own
+#[cfg(all(feature = "update-transport", feature = "multi-support"))]
+pub struct MuxChannel {
+    channel: UartChannel,
+    channel_id: u16,
+}
+
+#[cfg(all(feature = "update-transport", feature = "multi-support"))]
+impl MuxChannel {
+    pub fn new(channel_id: u16) -> Self {
+        Self {
+            channel: UartChannel::new(),
+            channel_id: channel_id,
+        }
+    }
+}
+
+#[cfg(all(feature = "update-transport", feature = "multi-support"))]
+impl update_transport::UpdateTransport for MuxChannel {
+    fn write_block(&mut self, data: &[u8]) -> Result<(), update_transport::TransportError> {
+        Ok(self.channel.write_block(self.channel_id, data)?)
     }
-    pub fn read_block(&mut self, data: &mut [u8]) -> Result<(), ChannelError> {
-        hl::send_with_retry(
-            &self.0,
-            &ReadBlockRequest {},
-            &[Lease::write_only(data)],
-        )
+    fn read_block(&mut self, data: &mut [u8]) -> Result<(), update_transport::TransportError> {
+        Ok(self.channel.read_block(self.channel_id, data)?)
     }
-    pub fn read_block_timed(
+    fn read_block_timed(
         &mut self,
         data: &mut [u8],
         timeout_ticks: u32,
-    ) -> Result<(), ChannelError> {
-        hl::send_with_retry(
-            &self.0,
-            &ReadBlockTimedRequest {
-                timeout_ticks: timeout_ticks,
-            },
-            &[Lease::write_only(data)],
-        )
+    ) -> Result<(), update_transport::TransportError> {
+        Ok(self
+            .channel
+            .read_block_timed(self.channel_id, data, timeout_ticks)?)
     }
-    /// New method that allow transmitting data while first setting up the system for reception.
-    /// This is especially useful when dealing with quick responses, that could be missed for
-    /// unlucky context switches that delay the setup of the standard reception buffer
-    pub fn transmit_timed(
+    fn transmit_timed(
         &mut self,
         data_out: &[u8],
         data_in: &mut [u8],
         timeout_ticks: u32,
-    ) -> Result<(), ChannelError> {
-        hl::send_with_retry(
-            &self.0,
-            &TransmitTimedRequest {
-                timeout_ticks: timeout_ticks,
-            },
-            &[Lease::read_only(data_out), Lease::write_only(data_in)],
-        )
+    ) -> Result<(), update_transport::TransportError> {
+        Ok(self
+            .channel
+            .transmit_timed(self.channel_id, data_out, data_in, timeout_ticks)?)
     }
 }
+
+/// Sink for the deferred logs of userlib, to be registered with
+/// `userlib::log::set_sink(uart_channel_api::log_sink)`.
+/// Frames are dropped when the channel is busy, logging never blocks the caller on errors.
+#[cfg(feature = "multi-support")]
+pub fn log_sink(frame: &[u8]) {
+    let _ = UartChannel::new().write_block(userlib::LOG_CHANNEL_ID, frame);
+}
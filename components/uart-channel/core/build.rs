@@ -0,0 +1,251 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use std::{env, fmt::Write, fs, path::PathBuf};
+
+use component_config::structures::ConfigTable;
+use toml::Value;
+
+/// USART -> (DMA, channel, request) that can receive from it. The request is
+/// the value of CSELR, only on the STM32L4.
+type RxDma = (&'static str, u8, Option<u8>);
+
+// RM0316 Table 78 and 79
+const STM32F303RE: &[(&str, &[RxDma])] = &[
+    ("usart1", &[("dma1", 5, None)]),
+    ("usart2", &[("dma1", 6, None)]),
+    ("usart3", &[("dma1", 3, None)]),
+    ("uart4", &[("dma2", 3, None)]),
+];
+
+// RM0394 Table 41 and 42
+const STM32L432KC: &[(&str, &[RxDma])] = &[
+    ("usart1", &[("dma1", 5, Some(2)), ("dma2", 7, Some(2))]),
+    ("usart2", &[("dma1", 6, Some(2))]),
+    ("lpuart1", &[("dma2", 7, Some(4))]),
+];
+
+// RM0351 Table 44 and 45
+const STM32L476RG: &[(&str, &[RxDma])] = &[
+    ("usart1", &[("dma1", 5, Some(2)), ("dma2", 7, Some(2))]),
+    ("usart2", &[("dma1", 6, Some(2))]),
+    ("usart3", &[("dma1", 3, Some(2))]),
+    ("uart4", &[("dma2", 5, Some(2))]),
+    ("uart5", &[("dma2", 2, Some(2))]),
+    ("lpuart1", &[("dma2", 7, Some(4))]),
+];
+
+fn board() -> (&'static str, &'static [(&'static str, &'static [RxDma])]) {
+    if env::var("CARGO_FEATURE_BOARD_STM32F303RE").is_ok() {
+        ("stm32f303re", STM32F303RE)
+    } else if env::var("CARGO_FEATURE_BOARD_STM32L432KC").is_ok() {
+        ("stm32l432kc", STM32L432KC)
+    } else if env::var("CARGO_FEATURE_BOARD_STM32L476RG").is_ok() {
+        ("stm32l476rg", STM32L476RG)
+    } else {
+        panic!("No board selected");
+    }
+}
+
+fn setting<'a>(config: &'a ConfigTable, key: &str) -> &'a Value {
+    config
+        .get(key)
+        .unwrap_or_else(|| panic!("Missing setting '{}'", key))
+}
+
+fn string_setting<'a>(config: &'a ConfigTable, key: &str) -> &'a str {
+    setting(config, key)
+        .as_str()
+        .unwrap_or_else(|| panic!("Setting '{}' must be a string", key))
+}
+
+fn integer_setting(config: &ConfigTable, key: &str) -> i64 {
+    setting(config, key)
+        .as_integer()
+        .unwrap_or_else(|| panic!("Setting '{}' must be an integer", key))
+}
+
+/// A pin as { pin = "PA2", af = 7 } to (port, pin, alternate function)
+fn pin_setting(config: &ConfigTable, key: &str) -> (char, u8, u8) {
+    let pin = setting(config, key)
+        .as_table()
+        .unwrap_or_else(|| panic!("Setting '{}' must be a table", key));
+    let name = pin
+        .get("pin")
+        .and_then(Value::as_str)
+        .unwrap_or_else(|| panic!("Missing name of the pin '{}'", key));
+    let af = pin
+        .get("af")
+        .and_then(Value::as_integer)
+        .filter(|af| (0..16).contains(af))
+        .unwrap_or_else(|| panic!("Missing alternate function of the pin '{}'", key));
+    let mut chars = name.chars();
+    let (port, number) = match (chars.next(), chars.next(), chars.as_str().parse::<u8>()) {
+        (Some('P'), Some(port @ 'A'..='I'), Ok(number)) if number < 16 => (port, number),
+        _ => panic!("Invalid pin '{}' for '{}'", name, key),
+    };
+    (port, number, af as u8)
+}
+
+/// A pin setting that may be left out, as the pins of the flow control
+fn optional_pin_setting(config: &ConfigTable, key: &str) -> Option<(char, u8, u8)> {
+    config.get(key).map(|_| pin_setting(config, key))
+}
+
+fn pin_constant(pin: Option<(char, u8, u8)>) -> String {
+    match pin {
+        Some((port, number, af)) => format!("Some((Port::{}, {}, {}))", port, number, af),
+        None => String::from("None"),
+    }
+}
+
+fn main() {
+    let config = component_config::read_instance_config().unwrap();
+    let (board, usarts) = board();
+
+    // USART and the DMA serving its RX
+    let usart = string_setting(&config, "usart");
+    let rx_dma = usarts
+        .iter()
+        .find(|(name, _)| *name == usart)
+        .map(|(_, rx_dma)| *rx_dma)
+        .unwrap_or_else(|| panic!("Cannot use {} on {}", usart, board));
+    let dma = string_setting(&config, "dma");
+    let dma_channel = integer_setting(&config, "dma_channel");
+    let (_, _, dma_request) = rx_dma
+        .iter()
+        .find(|(name, channel, _)| *name == dma && *channel as i64 == dma_channel)
+        .unwrap_or_else(|| {
+            panic!(
+                "{} channel {} cannot receive from {}",
+                dma, dma_channel, usart
+            )
+        });
+    let baudrate = integer_setting(&config, "baudrate");
+    if !(1..=u32::MAX as i64).contains(&baudrate) {
+        panic!("Invalid baudrate {}", baudrate);
+    }
+    let tx = pin_setting(&config, "tx");
+    let rx = pin_setting(&config, "rx");
+    let rts = optional_pin_setting(&config, "rts");
+    let cts = optional_pin_setting(&config, "cts");
+
+    // The PAC names the channel registers differently on the STM32F3
+    let (ccr, cndtr, cpar, cmar) = match board {
+        "stm32f303re" => (
+            format!("ch{}.cr", dma_channel),
+            format!("ch{}.ndtr", dma_channel),
+            format!("ch{}.par", dma_channel),
+            format!("ch{}.mar", dma_channel),
+        ),
+        _ => (
+            format!("ccr{}", dma_channel),
+            format!("cndtr{}", dma_channel),
+            format!("cpar{}", dma_channel),
+            format!("cmar{}", dma_channel),
+        ),
+    };
+
+    let mut out = String::new();
+    writeln!(
+        out,
+        "// Generated from the settings of the instance, do not edit"
+    )
+    .unwrap();
+    writeln!(out).unwrap();
+    writeln!(out, "const BAUDRATE: u32 = {};", baudrate).unwrap();
+    writeln!(
+        out,
+        "const USART: rcc_api::Peripheral = rcc_api::Peripheral::{};",
+        usart.to_uppercase()
+    )
+    .unwrap();
+    writeln!(out, "/// The LPUART divides 256 times the clock").unwrap();
+    writeln!(out, "const LPUART: bool = {};", usart.starts_with("lpuart")).unwrap();
+    writeln!(
+        out,
+        "const DMA: rcc_api::Peripheral = rcc_api::Peripheral::{};",
+        dma.to_uppercase()
+    )
+    .unwrap();
+    writeln!(out, "const DMA_CHANNEL: u32 = {};", dma_channel).unwrap();
+    writeln!(out, "/// Selection of the request on the channel (CSELR)").unwrap();
+    writeln!(out, "const DMA_REQUEST: Option<u32> = {:?};", dma_request).unwrap();
+    writeln!(out, "/// Port, pin and alternate function").unwrap();
+    writeln!(
+        out,
+        "const TX_PIN: (Port, u8, u8) = (Port::{}, {}, {});",
+        tx.0, tx.1, tx.2
+    )
+    .unwrap();
+    writeln!(
+        out,
+        "const RX_PIN: (Port, u8, u8) = (Port::{}, {}, {});",
+        rx.0, rx.1, rx.2
+    )
+    .unwrap();
+    writeln!(out, "/// Hardware flow control, each one if given").unwrap();
+    writeln!(
+        out,
+        "const RTS_PIN: Option<(Port, u8, u8)> = {};",
+        pin_constant(rts)
+    )
+    .unwrap();
+    writeln!(
+        out,
+        "const CTS_PIN: Option<(Port, u8, u8)> = {};",
+        pin_constant(cts)
+    )
+    .unwrap();
+    writeln!(out).unwrap();
+    // The LPUART has the registers of the USART used here at the same offsets
+    let cast = match usart.starts_with("lpuart") {
+        true => " as *const device::usart1::RegisterBlock",
+        false => "",
+    };
+    writeln!(
+        out,
+        "fn usart_registers() -> &'static device::usart1::RegisterBlock {{"
+    )
+    .unwrap();
+    writeln!(
+        out,
+        "    unsafe {{ &*(device::{}::ptr(){}) }}",
+        usart.to_uppercase(),
+        cast
+    )
+    .unwrap();
+    writeln!(out, "}}").unwrap();
+    writeln!(out).unwrap();
+    writeln!(
+        out,
+        "fn dma_registers() -> &'static device::dma1::RegisterBlock {{"
+    )
+    .unwrap();
+    writeln!(
+        out,
+        "    unsafe {{ &*device::{}::ptr() }}",
+        dma.to_uppercase()
+    )
+    .unwrap();
+    writeln!(out, "}}").unwrap();
+    writeln!(out).unwrap();
+    writeln!(out, "// Registers of the RX channel").unwrap();
+    for (name, register) in [
+        ("ccr", ccr),
+        ("cndtr", cndtr),
+        ("cpar", cpar),
+        ("cmar", cmar),
+    ] {
+        writeln!(out, "macro_rules! rx_{} {{", name).unwrap();
+        writeln!(out, "    ($dma:expr) => {{").unwrap();
+        writeln!(out, "        $dma.{}", register).unwrap();
+        writeln!(out, "    }};").unwrap();
+        writeln!(out, "}}").unwrap();
+    }
+
+    let mut path = PathBuf::from(env::var("OUT_DIR").unwrap());
+    path.push("config.rs");
+    fs::write(path, out).unwrap();
+}
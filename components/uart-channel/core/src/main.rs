@@ -5,8 +5,10 @@
 #![no_std]
 #![no_main]
 
+use gpio_api::{GPIOError, Port, GPIO};
 use rcc_api::RCCError;
 use uart_channel_api::*;
+use uart_rx::{ByteQueue, DmaRing, RxStats};
 use userlib::{hl::Caller, *};
 
 #[cfg(feature = "board_stm32f303re")]
@@ -18,10 +20,11 @@ use stm32l432kc::device;
 #[cfg(feature = "board_stm32l476rg")]
 use stm32l476rg::device;
 
-// Baudrate used during communication
-const BAUDRATE: u32 = 115_200;
+// USART, DMA channel, pins and baudrate of this instance (see build.rs)
+include!(concat!(env!("OUT_DIR"), "/config.rs"));
+
 const USART_IRQ_MASK: u32 = 0b0000_0000_0000_0001;
-const DMA1_CH6_IRQ_MASK: u32 = 0b0000_0000_0000_0010;
+const DMA_IRQ_MASK: u32 = 0b0000_0000_0000_0010;
 const TIMEOUT_MASK: u32 = 0b1000_0000_0000_0000;
 
 // Configuration of this module
@@ -30,6 +33,7 @@ cfg_if::cfg_if! {
         const MAX_TRANSMITTERS: usize = 2; // Number of components allowed to trasmit simult.
         const MAX_RECEIVERS: usize = 2;    // Number of components allowed to wait for data simult.
         use heapless::Vec;
+        use uart_rx::{crc8_update, ChannelQueues, Deframer, PREAMBLE_BYTE, PREAMBLE_LEN};
     }
 }
 
@@ -54,14 +58,13 @@ cfg_if::cfg_if! {
         }
         struct ReceiverState {
             pub receivers: Vec<Receiver, MAX_RECEIVERS>,
-            pub header_data_buff: [u8; 4],
-            pub header_data_buff_pos: usize,
-            pub preamble_cnt: usize,
-            pub crc8: u8,
-            pub current_read_pos: usize,
-            pub last_channel_id: Option<u16>,
-            pub last_packet_len: u16,
-            pub received_bytes: u16
+            pub ring: DmaRing<RX_BUFFER_SIZE>,
+            pub deframer: Deframer,
+            // A queue for the channel of each reader, kept when it leaves
+            pub queues: ChannelQueues<MAX_RECEIVERS, RX_QUEUE_SIZE>,
+            pub skip: usize,
+            pub paused: bool,
+            pub stats: RxStats
         }
         struct TransmitterState {
             pub transmitters: Vec<Transmitter, MAX_TRANSMITTERS>,
@@ -85,7 +88,11 @@ cfg_if::cfg_if! {
         }
         struct ReceiverState {
             pub pending_receiver: Option<Receiver>,
-            pub current_read_pos: usize,
+            pub ring: DmaRing<RX_BUFFER_SIZE>,
+            pub queue: ByteQueue<RX_QUEUE_SIZE>,
+            pub skip: usize,
+            pub paused: bool,
+            pub stats: RxStats
         }
         struct DriverState {
             pub receiver_state: ReceiverState,
@@ -95,16 +102,14 @@ cfg_if::cfg_if! {
 }
 
 fn update_handler() -> ! {
-    // Deconfigure everything, especially DMA
-    //let usart = unsafe { &*device::USART2::ptr() };
-    //let dma1 = unsafe { &*device::DMA1::ptr() };
-    //dma_stop_receive(dma1, usart);
+    // Deconfigure everything, especially DMA.
+    // The DMA may serve other instances too, so only our channel is stopped
+    let dma = dma_registers();
+    rx_ccr!(dma).modify(|_, w| w.en().clear_bit());
     // Power down and reset everything
     let mut rcc = rcc_api::RCC::new();
-    rcc.enter_reset(rcc_api::Peripheral::DMA1).ok();
-    rcc.enter_reset(rcc_api::Peripheral::USART2).ok();
-    rcc.disable_clock(rcc_api::Peripheral::DMA1).ok();
-    rcc.disable_clock(rcc_api::Peripheral::USART2).ok();
+    rcc.enter_reset(USART).ok();
+    rcc.disable_clock(USART).ok();
     hl::transfer_state(1u32);
 }
 
@@ -122,35 +127,35 @@ fn main() -> ! {
     // Safety: this is needlessly unsafe in the API. The USART is essentially a
     // static, and we access it through a & reference so aliasing is not a
     // concern. Were it literally a static, we could just reference it.
-    let usart = unsafe { &*device::USART2::ptr() };
-    // DMA1
-    let dma1 = unsafe { &*device::DMA1::ptr() };
+    let usart = usart_registers();
+    // DMA serving the RX
+    let dma = dma_registers();
 
     setup_usart(usart).unwrap();
     setup_gpio().unwrap();
-    setup_dma(dma1, usart).unwrap();
+    setup_dma(dma, usart).unwrap();
 
     // Turn on our interrupt. We haven't enabled any interrupt sources at the
     // USART side yet, so this won't trigger notifications yet.
     sys_irq_control(USART_IRQ_MASK, true);
-    sys_irq_control(DMA1_CH6_IRQ_MASK, true);
+    sys_irq_control(DMA_IRQ_MASK, true);
+    // Receive from now on, the bytes wait in the queues for their readers
+    dma_receive_to_idle(dma, usart);
 
     // Construct driver state
     #[cfg(feature = "multi-support")]
     let mut state = DriverState {
         receiver_state: ReceiverState {
             receivers: Vec::new(),
-            current_read_pos: match got_state {
+            ring: DmaRing::new(),
+            deframer: Deframer::new(),
+            queues: ChannelQueues::new(),
+            skip: match got_state {
                 true => 0,
                 false => 1,
             },
-            last_channel_id: None,
-            last_packet_len: 0,
-            header_data_buff: [0x00; 4],
-            header_data_buff_pos: 0,
-            preamble_cnt: 0,
-            crc8: 0,
-            received_bytes: 0,
+            paused: false,
+            stats: RxStats::default(),
         },
         transmitter_state: TransmitterState {
             transmitters: Vec::new(),
@@ -160,10 +165,14 @@ fn main() -> ! {
     let mut state = DriverState {
         receiver_state: ReceiverState {
             pending_receiver: None,
-            current_read_pos: match got_state {
+            ring: DmaRing::new(),
+            queue: ByteQueue::new(),
+            skip: match got_state {
                 true => 0,
                 false => 1,
             }, // Ask for some reason, the first byte we read is 0x00
+            paused: false,
+            stats: RxStats::default(),
         },
         pending_transmitter: None,
     };
@@ -175,7 +184,7 @@ fn main() -> ! {
     loop {
         hl::recv(
             &mut recv_buff,
-            USART_IRQ_MASK | DMA1_CH6_IRQ_MASK | TIMEOUT_MASK | STATE_TRANSFER_REQUESTED_MASK,
+            USART_IRQ_MASK | DMA_IRQ_MASK | TIMEOUT_MASK | STATE_TRANSFER_REQUESTED_MASK,
             &mut state,
             |state_ref, bits| {
                 // Check if state transfer
@@ -212,22 +221,8 @@ fn main() -> ! {
                     }
 
                     if usart_isr.idle().bit_is_set() {
-                        // IDLE, we have to flush RX buffer
-                        // -> get the number of bytes still to be read of DMA
-                        #[cfg(feature = "board_stm32f303re")]
-                        let remaining_rx = dma1.ch6.ndtr.read().bits() as usize;
-                        #[cfg(any(feature = "board_stm32l432kc", feature = "board_stm32l476rg"))]
-                        let remaining_rx = dma1.cndtr6.read().bits() as usize;
-
-                        if remaining_rx > 0 && remaining_rx < RX_BUFFER_SIZE {
-                            // Still something to read (otherwise TC will be called)
-                            dma_receive_callback(
-                                &mut state_ref.receiver_state,
-                                RX_BUFFER_SIZE - remaining_rx,
-                                dma1,
-                                usart,
-                            );
-                        }
+                        // IDLE, take what the DMA wrote so far
+                        dma_receive_callback(state_ref, dma, usart);
                         // Clear bit
                         usart.icr.write(|w| w.idlecf().set_bit());
                     }
@@ -246,47 +241,31 @@ fn main() -> ! {
                         frame_recovery = false;
                     }
 
-                    // Overrun error: happens only if we mess up with the DMA
-                    // otherwise it's impossibile.
+                    // Overrun error: a byte was not read in time, as the DMA
+                    // is paused with no RTS (or the other side ignores it)
                     if usart_isr.ore().bit_is_set() {
-                        // Something happened
-                        panic!("UART Overrun");
+                        let stats = &mut state_ref.receiver_state.stats;
+                        stats.usart_overruns = stats.usart_overruns.saturating_add(1);
+                        usart.icr.write(|w| w.orecf().set_bit());
                     }
 
                     // Enable again interrupts
                     sys_irq_control(USART_IRQ_MASK, true);
                 }
                 // DMA IRQ
-                if bits & DMA1_CH6_IRQ_MASK != 0 {
+                if bits & DMA_IRQ_MASK != 0 {
                     // DMA fired interrupt (RX)
-                    let isr = dma1.isr.read();
-                    if isr.htif6().bit_is_set() {
-                        // Clear the flag
-                        dma1.ifcr.write(|w| w.chtif6().set_bit());
-                        // Half transfer complete!
-                        dma_receive_callback(
-                            &mut state_ref.receiver_state,
-                            RX_BUFFER_SIZE / 2,
-                            dma1,
-                            usart,
-                        );
-                    } else if isr.tcif6().bit_is_set() {
-                        // Clear the flag
-                        dma1.ifcr.write(|w| w.ctcif6().set_bit());
-                        // Full transfer complete
-                        dma_receive_callback(
-                            &mut state_ref.receiver_state,
-                            RX_BUFFER_SIZE,
-                            dma1,
-                            usart,
-                        );
-                    } else if isr.teif6().bit_is_set() {
+                    let isr = dma.isr.read().bits() >> dma_flags_shift();
+                    if isr & DMA_TEIF != 0 {
                         // Error
                         panic!("Got error on DMA");
                     }
+                    // Half or full transfer, the flags are cleared with the
+                    // bytes they stand for
+                    dma_receive_callback(state_ref, dma, usart);
 
                     // Enable again interrupt
-                    sys_irq_control(DMA1_CH6_IRQ_MASK, true);
+                    sys_irq_control(DMA_IRQ_MASK, true);
                 }
             },
             |state_ref, op, msg| match op {
@@ -355,11 +334,11 @@ fn main() -> ! {
 
                     // Perform setup
                     #[cfg(feature = "multi-support")]
-                    setup_read(
-                        state_ref, usart, dma1, caller, info.len, 0, None, channel_id,
-                    )?;
+                    setup_read(state_ref, caller, info.len, 0, None, channel_id)?;
                     #[cfg(not(feature = "multi-support"))]
-                    setup_read(state_ref, usart, dma1, caller, info.len, 0)?;
+                    setup_read(state_ref, caller, info.len, 0)?;
+                    // The bytes may be queued already
+                    deliver(state_ref, usart);
 
                     // We'll do the rest as interrupts arrive.
                     Ok(())
@@ -396,8 +375,6 @@ fn main() -> ! {
                     #[cfg(feature = "multi-support")]
                     setup_timed_read(
                         state_ref,
-                        usart,
-                        dma1,
                         caller,
                         info.len,
                         0,
@@ -405,15 +382,9 @@ fn main() -> ! {
                         channel_id,
                     )?;
                     #[cfg(not(feature = "multi-support"))]
-                    setup_timed_read(
-                        state_ref,
-                        usart,
-                        dma1,
-                        caller,
-                        info.len,
-                        0,
-                        msg.timeout_ticks,
-                    )?;
+                    setup_timed_read(state_ref, caller, info.len, 0, msg.timeout_ticks)?;
+                    // The bytes may be queued already
+                    deliver(state_ref, usart);
 
                     // We'll do the rest as interrupts arrive.
                     Ok(())
@@ -470,8 +441,6 @@ fn main() -> ! {
                         if #[cfg(feature = "multi-support")] {
                             setup_timed_read(
                                 state_ref,
-                                usart,
-                                dma1,
                                 caller.clone(),
                                 info_in.len,
                                 1,
@@ -483,8 +452,6 @@ fn main() -> ! {
                         } else {
                             setup_timed_read(
                                 state_ref,
-                                usart,
-                                dma1,
                                 caller.clone(),
                                 info_in.len,
                                 1,
@@ -495,6 +462,22 @@ fn main() -> ! {
                         }
                     }
                     // We'll do the rest as interrupts arrive.
+                    // The reply waits for the end of the transmission, even if
+                    // the bytes to receive are queued already.
+                    Ok(())
+                }
+                Operation::GetStatistics => {
+                    let (_, caller) = msg
+                        .fixed::<GetStatisticsRequest, GetStatisticsResponse>()
+                        .ok_or(ChannelError::BadArgument)?;
+
+                    let stats = &state_ref.receiver_state.stats;
+                    caller.reply(GetStatisticsResponse {
+                        usart_overruns: stats.usart_overruns,
+                        ring_overruns: stats.ring_overruns,
+                        dropped_bytes: stats.dropped_bytes,
+                        crc_errors: stats.crc_errors,
+                    });
                     Ok(())
                 }
             },
@@ -620,8 +603,6 @@ fn update_deadline(receiver_state: &mut ReceiverState) {
 #[cfg(feature = "multi-support")]
 fn setup_timed_read(
     state_ref: &mut DriverState,
-    usart: &device::usart1::RegisterBlock,
-    dma1: &device::dma1::RegisterBlock,
     caller: Caller<()>,
     rx_len: usize,
     borrow_num: usize,
@@ -633,8 +614,6 @@ fn setup_timed_read(
     // Setup read
     setup_read(
         state_ref,
-        usart,
-        dma1,
         caller,
         rx_len,
         borrow_num,
@@ -656,8 +635,6 @@ fn setup_timed_read(
 #[cfg(not(feature = "multi-support"))]
 fn setup_timed_read(
     state_ref: &mut DriverState,
-    usart: &device::usart1::RegisterBlock,
-    dma1: &device::dma1::RegisterBlock,
     caller: Caller<()>,
     rx_len: usize,
     borrow_num: usize,
@@ -665,7 +642,7 @@ fn setup_timed_read(
 ) -> Result<(), ChannelError> {
     let timer_state = sys_get_timer();
     let deadline = timer_state.now + timeout_ticks as u64 + 1;
-    setup_read(state_ref, usart, dma1, caller, rx_len, borrow_num)?;
+    setup_read(state_ref, caller, rx_len, borrow_num)?;
     sys_set_timer(Some(deadline), TIMEOUT_MASK);
     Ok(())
 }
@@ -673,17 +650,25 @@ fn setup_timed_read(
 #[cfg(feature = "multi-support")]
 fn setup_read(
     state_ref: &mut DriverState,
-    usart: &device::usart1::RegisterBlock,
-    dma1: &device::dma1::RegisterBlock,
     caller: Caller<()>,
     rx_len: usize,
     borrow_num: usize,
     deadline: Option<u64>,
     channel_id: u16,
 ) -> Result<(), ChannelError> {
+    let receiver_state = &mut state_ref.receiver_state;
+    // Give a queue to the channel, if it has none yet: the one of a channel
+    // without readers is taken over, dropping its bytes
+    let receivers = &receiver_state.receivers;
+    let dropped = receiver_state
+        .queues
+        .open(channel_id, |id| receivers.iter().any(|rx| rx.id == id))
+        .ok_or(ChannelError::ChannelBusy)?;
+    let stats = &mut receiver_state.stats;
+    stats.dropped_bytes = stats.dropped_bytes.saturating_add(dropped as u32);
+
     // Prepare for a transfer
-    state_ref
-        .receiver_state
+    receiver_state
         .receivers
         .push(Receiver {
             id: channel_id,
@@ -695,16 +680,11 @@ fn setup_read(
         })
         .unwrap_lite();
 
-    // Enable reception
-    dma_receive_to_idle(dma1, usart);
-
     Ok(())
 }
 #[cfg(not(feature = "multi-support"))]
 fn setup_read(
     state_ref: &mut DriverState,
-    usart: &device::usart1::RegisterBlock,
-    dma1: &device::dma1::RegisterBlock,
     caller: Caller<()>,
     rx_len: usize,
     borrow_num: usize,
@@ -717,9 +697,6 @@ fn setup_read(
         borrow_num: borrow_num,
     });
 
-    // Enable reception
-    dma_receive_to_idle(dma1, usart);
-
     Ok(())
 }
 
@@ -780,27 +757,27 @@ fn setup_usart(usart: &device::usart1::RegisterBlock) -> Result<(), RCCError> {
     // Enable clock and leave reset
     // Turn on clock and leave reset
     let mut rcc = rcc_api::RCC::new();
-    rcc.enable_clock(rcc_api::Peripheral::USART2)?;
-    rcc.leave_reset(rcc_api::Peripheral::USART2)?;
+    rcc.enable_clock(USART)?;
+    rcc.leave_reset(USART)?;
+
+    // Hardware flow control on the pins given, set while the UART is off
+    usart.cr3.modify(|_, w| {
+        w.rtse()
+            .bit(RTS_PIN.is_some())
+            .ctse()
+            .bit(CTS_PIN.is_some())
+    });
 
     // The UART has clock and is out of reset, but isn't actually on until we:
     usart.cr1.write(|w| w.ue().enabled());
 
-    // Work out our baud rate divisor.
-    #[cfg(feature = "board_stm32f303re")]
-    {
-        const CLOCK_HZ: u32 = 36_000_000; // PLCK1
-        usart
-            .brr
-            .write(|w| w.brr().bits((CLOCK_HZ / BAUDRATE) as u16));
-    }
-    #[cfg(any(feature = "board_stm32l432kc", feature = "board_stm32l476rg"))]
-    {
-        const CLOCK_HZ: u32 = 80_000_000; // PLCK1
-        usart
-            .brr
-            .write(|w| w.brr().bits((CLOCK_HZ / BAUDRATE) as u16));
-    }
+    // Work out our baud rate divisor, from the clock given by the rcc
+    let clock = rcc.get_clock_frequency(USART)?;
+    let divisor = match LPUART {
+        true => (256 * clock as u64 / BAUDRATE as u64) as u32,
+        false => clock / BAUDRATE,
+    };
+    usart.brr.write(|w| unsafe { w.bits(divisor) });
     // Enable the transmitter.
     usart.cr1.modify(|_, w| w.te().enabled());
     // Enable the receiver.
@@ -809,46 +786,16 @@ fn setup_usart(usart: &device::usart1::RegisterBlock) -> Result<(), RCCError> {
     Ok(())
 }
 
-#[cfg(any(feature = "board_stm32f303re", feature = "board_stm32l476rg"))]
-/// Write USART2 on GPIOA (pin 2,3)
-fn setup_gpio() -> Result<(), RCCError> {
-    // TODO: the fact that we interact with GPIOA directly here is an expedient
-    // hack, but control of the GPIOs should probably be centralized somewhere.
-    let gpioa = unsafe { &*device::GPIOA::ptr() };
-
-    // Turn on clock and leave reset
-    let mut rcc = rcc_api::RCC::new();
-    rcc.enable_clock(rcc_api::Peripheral::GPIOA)?;
-    rcc.leave_reset(rcc_api::Peripheral::GPIOA)?;
-
-    // Setup Alternate Function 7
-    gpioa
-        .moder
-        .modify(|_, w| w.moder2().alternate().moder3().alternate());
-    gpioa.afrl.modify(|_, w| w.afrl2().af7().afrl3().af7());
-
-    Ok(())
-}
-
-#[cfg(feature = "board_stm32l432kc")]
-/// Write USART2 on GPIOA (pin 2,15)
-fn setup_gpio() -> Result<(), RCCError> {
-    // TODO: the fact that we interact with GPIOA directly here is an expedient
-    // hack, but control of the GPIOs should probably be centralized somewhere.
-    let gpioa = unsafe { &*device::GPIOA::ptr() };
-
-    // Turn on clock and leave reset
-    let mut rcc = rcc_api::RCC::new();
-    rcc.enable_clock(rcc_api::Peripheral::GPIOA)?;
-    rcc.leave_reset(rcc_api::Peripheral::GPIOA)?;
-
-    // Setup Alternate Function
-    gpioa
-        .moder
-        .modify(|_, w| w.moder2().alternate().moder15().alternate());
-    gpioa.afrl.modify(|_, w| w.afrl2().af7());
-    gpioa.afrh.modify(|_, w| w.afrh15().af3());
-
+/// Route the USART to the TX and RX pins of the settings, and RTS and CTS if given
+fn setup_gpio() -> Result<(), GPIOError> {
+    let mut gpio = GPIO::new();
+    for (port, pin, af) in [Some(TX_PIN), Some(RX_PIN), RTS_PIN, CTS_PIN]
+        .into_iter()
+        .flatten()
+    {
+        gpio.claim(port, 1 << pin)?;
+        gpio.set_alternate(port, 1 << pin, af)?;
+    }
     Ok(())
 }
 
@@ -856,114 +803,81 @@ fn setup_gpio() -> Result<(), RCCError> {
 // On cortex-m4, cache seems not to exist (https://stackoverflow.com/questions/57377260/is-there-a-cache-in-the-arm-cortex-m4)
 // On cortex-m7, is 32 bytes
 // For more info, see https://community.st.com/s/question/0D53W00001Z9K9TSAV/maintaining-cpu-data-cache-coherence-for-dma-buffers
+// The DMA writes in it circularly, the bytes are moved to the queues at each
+// half, full transfer and IDLE of the line.
 const RX_BUFFER_SIZE: usize = 128;
 static mut RX_BUFFER: [u8; RX_BUFFER_SIZE] = [0xAA; RX_BUFFER_SIZE];
+// Bytes waiting for a reader (for each channel with multi-support), a window
+// of the update protocol fits in it
+const RX_QUEUE_SIZE: usize = 384;
 
 /**
  * DMA Support
- * USART2_RX -> DMA1 - Channel6
- * USART2_TX -> DMA1 - Channel7
- * (pag 272/1141)
+ * The RX of the USART -> DMA_CHANNEL of DMA, from the settings
+ * Each channel has 4 flags in ISR and IFCR
  */
-#[cfg(any(
-    feature = "board_stm32f303re",
-    feature = "board_stm32l432kc",
-    feature = "board_stm32l476rg"
-))]
+const DMA_GIF: u32 = 0b0001;
+const DMA_TCIF: u32 = 0b0010;
+const DMA_HTIF: u32 = 0b0100;
+const DMA_TEIF: u32 = 0b1000;
+
+fn dma_flags_shift() -> u32 {
+    4 * (DMA_CHANNEL - 1)
+}
+
 fn setup_dma(
-    dma1: &device::dma1::RegisterBlock,
+    dma: &device::dma1::RegisterBlock,
     usart: &device::usart1::RegisterBlock,
 ) -> Result<(), RCCError> {
-    // Turn on clock
+    // Turn on clock. No reset, other instances and components may use the DMA
     let mut rcc = rcc_api::RCC::new();
-    rcc.enable_clock(rcc_api::Peripheral::DMA1)?;
-    #[cfg(any(feature = "board_stm32l432kc", feature = "board_stm32l476rg"))]
-    rcc.enter_reset(rcc_api::Peripheral::DMA1)?;
-    #[cfg(any(feature = "board_stm32l432kc", feature = "board_stm32l476rg"))]
-    rcc.leave_reset(rcc_api::Peripheral::DMA1)?;
+    rcc.enable_clock(DMA)?;
+    rcc.leave_reset(DMA)?;
 
     // Configure DMA
-    configure_dma_rx(dma1, usart);
+    configure_dma_rx(dma, usart);
 
     Ok(())
 }
-#[cfg(feature = "board_stm32f303re")]
-fn configure_dma_rx(dma1: &device::dma1::RegisterBlock, usart: &device::usart1::RegisterBlock) {
+fn configure_dma_rx(dma: &device::dma1::RegisterBlock, usart: &device::usart1::RegisterBlock) {
     // Disable the channel (tbs)
-    dma1.ch6.cr.modify(|_, w| w.en().clear_bit());
+    rx_ccr!(dma).modify(|_, w| w.en().clear_bit());
     // Clear all interrupts
-    dma1.ifcr.write(|w| w.cgif6().set_bit());
-    // Set periph. address (RDR register)
-    dma1.ch6
-        .par
-        .write(|w| unsafe { w.bits(usart.rdr.as_ptr() as u32) });
-    // Set the mem. address (RX_Buffer)
-    dma1.ch6
-        .mar
-        .write(|w| unsafe { w.bits(RX_BUFFER.as_mut_ptr() as u32) });
-    // Set data length (number of elements to be received)
-    dma1.ch6
-        .ndtr
-        .write(|w| unsafe { w.bits(RX_BUFFER_SIZE as u32) });
-    // Set the transfer direction
-    dma1.ch6.cr.modify(|_, w| w.dir().clear_bit());
-    // Set channel priority
-    dma1.ch6.cr.modify(|_, w| w.pl().very_high());
-    // Set circular mode
-    dma1.ch6.cr.modify(|_, w| w.circ().set_bit());
-    // Set data length
-    dma1.ch6.cr.modify(|_, w| w.psize().bits8());
-    dma1.ch6.cr.modify(|_, w| w.msize().bits8());
-    // Set increment mode
-    dma1.ch6.cr.modify(|_, w| w.minc().set_bit());
-
-    // Enable right interrupts (Half-transfer, Transfer-complete, Transfer-error)
-    dma1.ch6
-        .cr
-        .modify(|_, w| w.htie().set_bit().tcie().set_bit().teie().set_bit());
-
-    // Start DMA Channel
-    dma1.ch6.cr.modify(|_, w| w.en().set_bit());
-}
+    dma.ifcr
+        .write(|w| unsafe { w.bits(DMA_GIF << dma_flags_shift()) });
 
-#[cfg(any(feature = "board_stm32l432kc", feature = "board_stm32l476rg"))]
-fn configure_dma_rx(dma1: &device::dma1::RegisterBlock, usart: &device::usart1::RegisterBlock) {
-    // Disable the channel (tbs)
-    dma1.ccr6.modify(|_, w| w.en().clear_bit());
-    // Clear all interrupts
-    dma1.ifcr.write(|w| w.cgif6().set_bit());
-
-    // Select USART2_RX for Channel 6
+    // Select the RX of the USART for the channel
     // See: RM0394/pag.299 (L432),  RM0351/pag.339 (L476)
-    dma1.cselr.modify(|_, w| w.c6s().bits(0b0010));
+    #[cfg(any(feature = "board_stm32l432kc", feature = "board_stm32l476rg"))]
+    // CSELR is shared with the components on the other channels
+    if let Some(request) = DMA_REQUEST {
+        let shift = dma_flags_shift();
+        unsafe { util::modify_shared(dma.cselr.as_ptr(), 0b1111 << shift, request << shift) };
+    }
 
     // Set periph. address (RDR register)
-    dma1.cpar6
-        .write(|w| unsafe { w.bits(usart.rdr.as_ptr() as u32) });
+    rx_cpar!(dma).write(|w| unsafe { w.bits(usart.rdr.as_ptr() as u32) });
     // Set the mem. address (RX_Buffer)
-    dma1.cmar6
-        .write(|w| unsafe { w.bits(RX_BUFFER.as_mut_ptr() as u32) });
+    rx_cmar!(dma).write(|w| unsafe { w.bits(RX_BUFFER.as_mut_ptr() as u32) });
     // Set data length (number of elements to be received)
-    dma1.cndtr6
-        .write(|w| unsafe { w.bits(RX_BUFFER_SIZE as u32) });
+    rx_cndtr!(dma).write(|w| unsafe { w.bits(RX_BUFFER_SIZE as u32) });
     // Set the transfer direction
-    dma1.ccr6.modify(|_, w| w.dir().clear_bit());
+    rx_ccr!(dma).modify(|_, w| w.dir().clear_bit());
     // Set channel priority
-    dma1.ccr6.modify(|_, w| w.pl().very_high());
+    rx_ccr!(dma).modify(|_, w| w.pl().very_high());
     // Set circular mode
-    dma1.ccr6.modify(|_, w| w.circ().set_bit());
+    rx_ccr!(dma).modify(|_, w| w.circ().set_bit());
     // Set data length
-    dma1.ccr6.modify(|_, w| w.psize().bits8());
-    dma1.ccr6.modify(|_, w| w.msize().bits8());
+    rx_ccr!(dma).modify(|_, w| w.psize().bits8());
+    rx_ccr!(dma).modify(|_, w| w.msize().bits8());
     // Set increment mode
-    dma1.ccr6.modify(|_, w| w.minc().set_bit());
+    rx_ccr!(dma).modify(|_, w| w.minc().set_bit());
 
     // Enable right interrupts (Half-transfer, Transfer-complete, Transfer-error)
-    dma1.ccr6
-        .modify(|_, w| w.htie().set_bit().tcie().set_bit().teie().set_bit());
+    rx_ccr!(dma).modify(|_, w| w.htie().set_bit().tcie().set_bit().teie().set_bit());
 
     // Start DMA Channel
-    dma1.ccr6.modify(|_, w| w.en().set_bit());
+    rx_ccr!(dma).modify(|_, w| w.en().set_bit());
 }
 
 fn dma_receive_to_idle(_: &device::dma1::RegisterBlock, usart: &device::usart1::RegisterBlock) {
@@ -980,9 +894,9 @@ fn dma_receive_to_idle(_: &device::dma1::RegisterBlock, usart: &device::usart1::
     usart.cr1.modify(|_, w| w.idleie().set_bit());
 }
 
-/*fn dma_stop_receive(dma1: &device::dma1::RegisterBlock, usart: &device::usart1::RegisterBlock) {
+/*fn dma_stop_receive(dma: &device::dma1::RegisterBlock, usart: &device::usart1::RegisterBlock) {
     // Disable DMA channel
-    dma1.ch6.cr.modify(|_, w| w.en().clear_bit());
+    rx_ccr!(dma).modify(|_, w| w.en().clear_bit());
     // Disable UART parity error interrupt (even if we don't use it now)
     usart.cr1.modify(|_, w| w.peie().clear_bit());
     // Disable UART error interrupt (frame error, noise error, overrun error)
@@ -1000,336 +914,191 @@ fn dma_receive_to_idle(_: &device::dma1::RegisterBlock, usart: &device::usart1::
 */
 
 fn dma_receive_callback(
-    rec_state: &mut ReceiverState,
-    available_up_to: usize,
-    dma1: &device::dma1::RegisterBlock,
+    state_ref: &mut DriverState,
+    dma: &device::dma1::RegisterBlock,
     usart: &device::usart1::RegisterBlock,
 ) {
-    // Something changed?
-    if rec_state.current_read_pos != available_up_to {
-        // Flush cache, as we are not using DMA-marked memory
-        // -> not needed on Cortex-M4 (but on M7 yes!)
-        // From this point on, should be safe to read from the buffer
-        if available_up_to > rec_state.current_read_pos {
-            let received_bytes = available_up_to - rec_state.current_read_pos;
-            // New data on the top to read, simple case
-            rx_update_caller(
-                rec_state,
-                unsafe {
-                    &RX_BUFFER
-                        [rec_state.current_read_pos..rec_state.current_read_pos + received_bytes]
-                },
-                dma1,
-                usart,
-            );
-        } else {
-            // There could be a top part
-            let received_bytes = RX_BUFFER_SIZE - rec_state.current_read_pos;
-            if received_bytes > 0 {
-                rx_update_caller(
-                    rec_state,
-                    unsafe {
-                        &RX_BUFFER[rec_state.current_read_pos
-                            ..rec_state.current_read_pos + received_bytes]
-                    },
-                    dma1,
-                    usart,
-                );
-            }
-            if available_up_to > 0 {
-                // Bottom part available
-                rx_update_caller(
-                    rec_state,
-                    unsafe { &RX_BUFFER[0..available_up_to] },
-                    dma1,
-                    usart,
-                );
-            }
+    // The flags first, then the position of the DMA (see DmaRing::advance)
+    let flags = dma.isr.read().bits() >> dma_flags_shift();
+    let head = RX_BUFFER_SIZE - rx_cndtr!(dma).read().bits() as usize;
+    let rec_state = &mut state_ref.receiver_state;
+    let advance = rec_state
+        .ring
+        .advance(head, flags & DMA_HTIF != 0, flags & DMA_TCIF != 0);
+    let mut clear: u32 = 0;
+    if advance.clear_half {
+        clear |= DMA_HTIF;
+    }
+    if advance.clear_full {
+        clear |= DMA_TCIF;
+    }
+    if clear != 0 {
+        dma.ifcr
+            .write(|w| unsafe { w.bits(clear << dma_flags_shift()) });
+    }
+    if advance.overrun {
+        // The ring holds bytes of two laps, restart from the position of the DMA
+        rec_state.stats.ring_overruns = rec_state.stats.ring_overruns.saturating_add(1);
+        #[cfg(feature = "multi-support")]
+        rec_state.deframer.reset();
+    }
+
+    // Flush cache, as we are not using DMA-marked memory
+    // -> not needed on Cortex-M4 (but on M7 yes!)
+    // From this point on, should be safe to read from the buffer
+    let (top, bottom) = advance.slices(unsafe { &RX_BUFFER });
+    for data in [top, bottom] {
+        // Skip the bytes read at start-up
+        let rec_state = &mut state_ref.receiver_state;
+        let skip = core::cmp::min(rec_state.skip, data.len());
+        rec_state.skip -= skip;
+        if data.len() > skip {
+            rx_update_caller(state_ref, &data[skip..], usart);
         }
-        // Update pointer
-        rec_state.current_read_pos = available_up_to;
     }
 }
 
-#[cfg(feature = "multi-support")]
-const PREAMBLE_BYTE: u8 = 0b10101010;
-#[cfg(feature = "multi-support")]
-const PREAMBLE_LEN: usize = 4;
-
 #[cfg(feature = "multi-support")]
 fn rx_update_caller(
-    receiver_state: &mut ReceiverState,
-    mut data: &[u8],
-    _dma1: &device::dma1::RegisterBlock,
-    _usart: &device::usart1::RegisterBlock,
+    state_ref: &mut DriverState,
+    data: &[u8],
+    usart: &device::usart1::RegisterBlock,
 ) {
-    // We want to receive packets in the format
-    //   +----------+------------+---------------+----------+--------+
-    //   | Preamble | Channel ID | Packet Length |   Data   | CRC-8  |
-    //   +----------+------------+---------------+----------+--------+
-    //   | 4 bytes  | 2 bytes    | 2 bytes       | pl_bytes | 1 byte |
-    //   +----------+------------+---------------+----------+--------+
-    while data.len() > 0 {
-        // Check whether we are currently receiving a packet
-        if receiver_state.last_channel_id.is_none() {
-            // In case we are waiting for a new packet, check whether we already
-            // received the preamble.
-            if receiver_state.preamble_cnt != PREAMBLE_LEN {
-                // Search for the preamble of a packet
-                // As it may come in (at least) two different calls,
-                // we need to keep track of such bytes
-                while data[0] == PREAMBLE_BYTE {
-                    // Advance in data
-                    data = &data[1..];
-                    // Mark as arrived
-                    receiver_state.preamble_cnt += 1;
-                    if receiver_state.preamble_cnt == PREAMBLE_LEN {
-                        // Got it!
-                        break;
-                    }
-                    // If no more data, then wait next data
-                    if data.len() == 0 {
-                        return;
-                    }
-                }
-                // We can get here in two cases: we found all the preamble bytes,
-                // or we mistaken a byte for a preamble byte
-                if receiver_state.preamble_cnt != PREAMBLE_LEN {
-                    // Discard the byte
-                    data = &data[1..];
-                    // Restart from the beginning
-                    receiver_state.preamble_cnt = 0;
-                    continue; // Retry on the remaining data
-                }
-            }
-            // If we get here, then we received at least the preamble.
-            // Now it's time to receive the header (Channel ID + Packet Length)
-            // Compute how many bytes are still missing
-            let missing_header_len = core::cmp::min(
-                receiver_state.header_data_buff.len() - receiver_state.header_data_buff_pos,
-                data.len(),
-            );
-            // Copy the missing bytes in the buffer.
-            // We need this buffer as such header could arrive fragmented in two
-            // different calls.
-            for i in 0..missing_header_len {
-                receiver_state.header_data_buff[receiver_state.header_data_buff_pos + i] = data[i];
-            }
-            receiver_state.header_data_buff_pos += missing_header_len;
-            data = &data[missing_header_len..]; // Skip these bytes
+    // The data of the packets goes to the queue of its channel, once the CRC
+    // is checked (see uart_rx::Deframer)
+    let receiver_state = &mut state_ref.receiver_state;
+    receiver_state
+        .deframer
+        .receive(data, &mut receiver_state.queues, &mut receiver_state.stats);
+    // Then to the readers waiting for it
+    deliver(state_ref, usart);
+}
 
-            // Check whether we got the whole header
-            if receiver_state.header_data_buff_pos < receiver_state.header_data_buff.len() {
-                // Surely here data.len() == 0, so wait for more data
-                return;
-            }
+#[cfg(not(feature = "multi-support"))]
+fn rx_update_caller(
+    state_ref: &mut DriverState,
+    data: &[u8],
+    usart: &device::usart1::RegisterBlock,
+) {
+    // Queue the data, what does not fit is lost
+    let receiver_state = &mut state_ref.receiver_state;
+    let kept = receiver_state.queue.push(data);
+    receiver_state.queue.commit();
+    let stats = &mut receiver_state.stats;
+    stats.dropped_bytes = stats
+        .dropped_bytes
+        .saturating_add((data.len() - kept) as u32);
+    // Then to the reader waiting for it
+    deliver(state_ref, usart);
+}
 
-            // It's now time to decode such header
-            receiver_state.last_channel_id = Some(u16::from_be_bytes(
-                receiver_state.header_data_buff[0..2]
-                    .try_into()
-                    .unwrap_lite(),
-            ));
-            receiver_state.last_packet_len = u16::from_be_bytes(
-                receiver_state.header_data_buff[2..4]
-                    .try_into()
-                    .unwrap_lite(),
-            ) + 1; // +1 for the CRC-8
-
-            // Initialize structure fields for this packet
-            receiver_state.header_data_buff_pos = 0;
-            receiver_state.received_bytes = 0;
-            receiver_state.crc8 = 0;
-
-            // Start computing CRC on header
-            for i in 0..4 {
-                crc8_update(&mut receiver_state.crc8, receiver_state.header_data_buff[i]);
-            }
-            continue; // Redo the check, as data.len() could be 0 now
-        }
-        // At this point, we are receiving the data bytes associated with the packet
-        // Let's initialize some variables
-        let c_id = receiver_state.last_channel_id.unwrap_lite(); // Channel ID
-        let mut update_deadlines: bool = false; // Whether we removed some component
-        let mut consumed_bytes: usize = 0; // Number of bytes of the packet given to the component
-        let last_packet_slice: bool; // Marked when the crc of the packet is in view
-        let mut packet_failed: bool = false; // In case we have a CRC failure
-
-        // Compute the bytes we still need to receive for this packet (included CRC field)
-        let to_be_received =
-            (receiver_state.last_packet_len - receiver_state.received_bytes) as usize;
-
-        // Only some of these are available in this round, so let's compute those
-        let available_bytes = core::cmp::min(data.len(), to_be_received);
-
-        // Check if we have the CRC in range.
-        // In this case, we are ready to close the packet
-        if to_be_received - available_bytes == 0 {
-            // Finish computing the CRC on the bytes,
-            // in case we have more than just the CRC field
-            if available_bytes > 1 {
-                for i in 0..(available_bytes - 1) {
-                    crc8_update(&mut receiver_state.crc8, data[i]);
-                }
-            }
-            // The CRC will always be the last byte of the available ones
-            // for this packet.
-            let crc8 = data[available_bytes - 1];
-
-            // Check whether the packet is valid
-            if crc8 != receiver_state.crc8 {
-                // Failed validation!
-                // This slice of the packet is discarded and marked as consumed.
-                packet_failed = true;
-                data = &data[available_bytes..];
-                consumed_bytes += available_bytes;
-            }
-            // In any case, mark this is the last slice of the packet
-            last_packet_slice = true;
-            // Search for a new preamble
-            receiver_state.last_channel_id = None;
-            receiver_state.preamble_cnt = 0;
-        } else {
-            // We do not see the end of the packet in this slice,
-            // just receive it and compute the CRC on all the data
-            for i in 0..available_bytes {
-                crc8_update(&mut receiver_state.crc8, data[i]);
-            }
-            last_packet_slice = false;
-        }
-        // Send this data to components, eventually removing them
-        // from the listeners if they completed reception or they fail.
-        receiver_state.receivers.retain_mut(|rx| {
-            if rx.id == c_id {
-                // Manage basic recovery for simple packet.
-                if packet_failed {
-                    // Let's reason on the possible outcomes:
-                    // -> Part of the packet was already consumed by a prev. call of this component.
-                    //    In this case, let's hope it has an higher level CRC.
-                    // -> All data of this packet was copied to an active receiver.
-                    //    We might have copied data here the prev. round. Just decrement the received bytes
-                    //    in rx.pos, exploiting the fact received_bytes is incremented only after this loop
-                    //    so it represents the bytes consumed up to the prev. round.
-                    let discard_many =
-                        core::cmp::min(rx.pos, receiver_state.received_bytes as usize);
-                    // -1 on receiver_state.received_bytes is not needed,
-                    // as surely in the prev. round we did not reached the CRC field
-                    rx.pos -= discard_many;
-                    // Skip this round, keeping the listener
-                    return true;
-                }
-                // Compute how much data of the packet the component actually requested.
-                // We admit waiting for less than the packet size, and even for more.
-                // In the first case, the remaining packet is discarded, in the last multiple
-                // packets are joined to fill the buffer
-                let need_bytes = core::cmp::min(
-                    rx.len - rx.pos,
-                    match last_packet_slice {
-                        true => available_bytes - 1,
-                        false => available_bytes,
-                    },
-                );
-                // Copy the data to the buffer of the listener
-                if rx
-                    .caller
-                    .borrow(rx.borrow_num)
-                    .write_fully_at(rx.pos, &data[0..need_bytes])
-                    .is_some()
-                {
-                    // Consume this data
-                    rx.pos += need_bytes;
-                    data = &data[need_bytes..];
-                    consumed_bytes += need_bytes;
-                    // Check whether we completed the reception
-                    if rx.pos == rx.len {
-                        // Signal success
-                        rx.caller.clone().reply(());
-                        update_deadlines = true;
-                        // Remove this listener
-                        return false;
-                    }
-                    return true;
-                } else {
-                    // An exception occurred!
-                    // Discard this data (and later the packet!)
-                    data = &data[need_bytes..];
-                    consumed_bytes += need_bytes;
-                    // Signal failure
-                    rx.caller.clone().reply_fail(ChannelError::BadArgument);
-                    update_deadlines = true;
-                    // Remove this listener
-                    return false;
-                }
-            }
-            // Otherwise this is not the component we are looking for,
-            // so keep it!
+/// Moves the queued bytes to the readers, then resumes the reception if the
+/// queues have room again
+#[cfg(feature = "multi-support")]
+fn deliver(state_ref: &mut DriverState, usart: &device::usart1::RegisterBlock) {
+    let transmitters = &state_ref.transmitter_state.transmitters;
+    let receiver_state = &mut state_ref.receiver_state;
+    let queues = &mut receiver_state.queues;
+    let mut update_deadlines: bool = false; // Whether we removed some component
+    receiver_state.receivers.retain_mut(|rx| {
+        // Transmit mode: the reply waits for the end of the transmission
+        if transmitters
+            .iter()
+            .any(|tx| tx.caller.task_id() == rx.caller.task_id())
+        {
             return true;
-        });
-        // In any case, consume the packet data in order not to lose the header tracking.
-        if consumed_bytes < available_bytes {
-            match last_packet_slice {
-                false => {
-                    data = &data[(available_bytes - consumed_bytes)..];
-                }
-                true => {
-                    data = &data[(available_bytes - consumed_bytes - 1)..];
-                }
-            }
         }
-        // Increment the counter of the consumed bytes
-        receiver_state.received_bytes += available_bytes as u16;
-        // Update deadlines
-        if update_deadlines {
-            update_deadline(receiver_state);
+        let queue = match queues.queue(rx.id) {
+            Some(queue) => queue,
+            None => return true,
+        };
+        match fill_receiver(&rx.caller, rx.borrow_num, rx.len, &mut rx.pos, queue) {
+            Some(false) => return true,
+            // Signal success
+            Some(true) => rx.caller.clone().reply(()),
+            // An exception occurred! The data stays for the next reader
+            None => rx.caller.clone().reply_fail(ChannelError::BadArgument),
         }
+        update_deadlines = true;
+        // Remove this listener
+        false
+    });
+    if update_deadlines {
+        update_deadline(receiver_state);
     }
+    update_flow(receiver_state, usart);
 }
 
 #[cfg(not(feature = "multi-support"))]
-fn rx_update_caller(
-    receiver_state: &mut ReceiverState,
-    data: &[u8],
-    dma1: &device::dma1::RegisterBlock,
-    usart: &device::usart1::RegisterBlock,
-) {
+fn deliver(state_ref: &mut DriverState, usart: &device::usart1::RegisterBlock) {
     // Handler for every end of reception
-    fn end_reception(
-        _: &device::dma1::RegisterBlock,
-        _: &device::usart1::RegisterBlock,
-        receiver: &mut Option<Receiver>,
-    ) -> hl::Caller<()> {
+    fn end_reception(receiver: &mut Option<Receiver>) -> hl::Caller<()> {
         // Cancel timer, or the new settings won't stick.
         sys_set_timer(None, TIMEOUT_MASK);
-        // For now, avoid disabling interrupts. Otherwise we have problems with overrun
         // Return the caller
         core::mem::replace(receiver, None).unwrap().caller
     }
 
+    let receiver_state = &mut state_ref.receiver_state;
     let receiver = &mut receiver_state.pending_receiver;
+    if let Some(rx) = receiver {
+        // Transmit mode: the reply waits for the end of the transmission
+        let transmitting = match &state_ref.pending_transmitter {
+            Some(tx) => tx.caller.task_id() == rx.caller.task_id(),
+            None => false,
+        };
+        if !transmitting {
+            let queue = &mut receiver_state.queue;
+            match fill_receiver(&rx.caller, rx.borrow_num, rx.len, &mut rx.pos, queue) {
+                Some(false) => {}
+                // Success
+                Some(true) => end_reception(receiver).reply(()),
+                None => end_reception(receiver).reply_fail(ChannelError::BadArgument),
+            }
+        }
+    }
+    update_flow(receiver_state, usart);
+}
 
-    // Get the receiver, or ignore
-    let rx = if let Some(rx) = receiver {
-        rx
-    } else {
-        return;
-    };
+/// Copies the queued bytes in the lease of a reader, Some(true) once it is
+/// full. None when the lease cannot be written.
+fn fill_receiver(
+    caller: &Caller<()>,
+    borrow_num: usize,
+    len: usize,
+    pos: &mut usize,
+    queue: &mut ByteQueue<RX_QUEUE_SIZE>,
+) -> Option<bool> {
+    while *pos < len && !queue.is_empty() {
+        let data = queue.front();
+        let count = core::cmp::min(len - *pos, data.len());
+        caller
+            .borrow(borrow_num)
+            .write_fully_at(*pos, &data[..count])?;
+        *pos += count;
+        queue.consume(count);
+    }
+    Some(*pos == len)
+}
 
-    // Copy data in the buffer of caller
-    let need_bytes = core::cmp::min(rx.len - rx.pos, data.len());
-    // Try write data
-    if rx
-        .caller
-        .borrow(rx.borrow_num)
-        .write_fully_at(rx.pos, &data[0..need_bytes])
-        .is_some()
-    {
-        rx.pos += need_bytes;
-        if rx.pos == rx.len {
-            // Success
-            end_reception(dma1, usart, receiver).reply(());
-        }
-    } else {
-        end_reception(dma1, usart, receiver).reply_fail(ChannelError::BadArgument);
+/// With RTS, the reception pauses while a queue is nearly full: the USART
+/// keeps the next byte and RTS tells the other side to wait. Without RTS the
+/// bytes would be lost all the same, in the USART instead of the queues.
+/// A channel whose reader stops reading holds the whole line.
+fn update_flow(receiver_state: &mut ReceiverState, usart: &device::usart1::RegisterBlock) {
+    if RTS_PIN.is_none() {
+        return;
+    }
+    #[cfg(feature = "multi-support")]
+    let free = receiver_state.queues.min_free();
+    #[cfg(not(feature = "multi-support"))]
+    let free = receiver_state.queue.free();
+    if !receiver_state.paused && free < RX_BUFFER_SIZE {
+        usart.cr3.modify(|_, w| w.dmar().clear_bit());
+        receiver_state.paused = true;
+    } else if receiver_state.paused && free >= 2 * RX_BUFFER_SIZE {
+        usart.cr3.modify(|_, w| w.dmar().set_bit());
+        receiver_state.paused = false;
     }
 }
 
@@ -1408,6 +1177,9 @@ fn step_transmit(usart: &device::usart1::RegisterBlock, state_ref: &mut DriverSt
         if !is_transmit_mode(&caller, &state_ref.receiver_state) {
             // Otherwise tell the caller the operation is finished
             caller.reply(());
+        } else {
+            // The reception may be queued already
+            deliver(state_ref, usart);
         }
         return;
     }
@@ -1477,6 +1249,9 @@ fn step_transmit(usart: &device::usart1::RegisterBlock, state_ref: &mut DriverSt
             if !is_transmit_mode(&caller, receiver) {
                 // Otherwise tell the caller the operation is finished
                 caller.reply(());
+            } else {
+                // The reception may be queued already
+                deliver(state_ref, usart);
             }
         }
     } else {
@@ -1507,18 +1282,3 @@ fn is_transmit_mode(caller: &Caller<()>, receiver: &Option<Receiver>) -> bool {
     }
     return false;
 }
-
-/// Optimized Dallas (now Maxim) iButton 8-bit CRC calculation.
-/// Polynomial: x^8 + x^5 + x^4 + 1 (0x8C)
-/// Initial value: 0x0
-pub fn crc8_update(crc: &mut u8, byte: u8) {
-    let mut tmp = (*crc) ^ byte;
-    for _ in 0..8u8 {
-        if tmp & 0x01 == 1 {
-            tmp = (tmp >> 1) ^ 0x8C;
-        } else {
-            tmp >>= 1;
-        }
-    }
-    *crc = tmp;
-}
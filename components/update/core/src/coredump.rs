@@ -0,0 +1,61 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use storage_api::*;
+use update_transport::UpdateTransport;
+use userlib::coredump::*;
+use userlib::flash::BlockType;
+use userlib::*;
+
+use update_protocol::{messages::*, utils::*};
+
+use crate::utils::stream_flash;
+
+/// Returns (base address, size) of the core dump block, if any
+fn find_coredump_block(storage: &Storage) -> Result<Option<(u32, u32)>, MessageError> {
+    let status = storage.report_status().map_err(|_| MessageError::FlashError)?;
+    for block_num in 0..status.blocks {
+        let block = storage
+            .get_nth_block(block_num)
+            .map_err(|_| MessageError::FlashError)?;
+        if block.block_type == BlockType::COREDUMP {
+            return Ok(Some((block.block_base_address, block.block_size)));
+        }
+    }
+    Ok(None)
+}
+
+/// Reads the size of the dump stored in the block, 0 if there is none
+fn read_dump_size(storage: &Storage, base: u32, size: u32) -> Result<u32, MessageError> {
+    let mut header_buffer: [u8; COREDUMP_HEADER_SIZE] = [0x00; COREDUMP_HEADER_SIZE];
+    storage
+        .read_stream(base, COREDUMP_CONFIG_SIZE as u32, &mut header_buffer)
+        .map_err(|_| MessageError::FlashError)?;
+    let magic = u32_from_le_bytes(&header_buffer[0..4]);
+    let total_size = u32_from_le_bytes(&header_buffer[8..12]);
+    if magic != COREDUMP_MAGIC || total_size + COREDUMP_CONFIG_SIZE as u32 > size {
+        return Ok(0);
+    }
+    Ok(total_size)
+}
+
+pub fn coredump_read<T: UpdateTransport>(channel: &mut T) -> Result<(), MessageError> {
+    let storage = Storage::new();
+    // Step 1: find the dump and send its size
+    let (base, dump_size) = match find_coredump_block(&storage)? {
+        Some((base, size)) => (base, read_dump_size(&storage, base, size)?),
+        None => (0, 0),
+    };
+    channel_write(channel, &CoreDumpSizeMessage::new(dump_size).get_raw())?;
+    // Step 2: stream the dump, each packet followed by its crc
+    stream_flash(channel, &storage, base, COREDUMP_CONFIG_SIZE as u32, dump_size)
+}
+
+pub fn coredump_erase<T: UpdateTransport>(channel: &mut T) -> Result<(), MessageError> {
+    if kipc::erase_coredump().is_err() {
+        return Err(MessageError::FlashError);
+    }
+    sys_log!("[UPDATE] Core dump erased");
+    channel_write_single(channel, CoreDumpResponse::Success as u8)
+}
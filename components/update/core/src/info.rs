@@ -4,13 +4,16 @@
 
 use cbf_lite::CbfFile;
 use storage_api::*;
-use uart_channel_api::*;
+use update_transport::UpdateTransport;
 use userlib::flash::BlockType;
+use watchdog_api::{ResetCause, Watchdog};
 
-use crate::{messages::*, utils::*};
+use update_protocol::{messages::*, utils::*};
 
-pub fn system_info(
-    channel: &mut UartChannel
+use crate::platform::StoragePlatform;
+
+pub fn system_info<T: UpdateTransport>(
+    channel: &mut T
 ) -> Result<(), MessageError> {
     // Get storage
     let storage = Storage::new();
@@ -20,9 +23,11 @@ pub fn system_info(
     for block_num in 0..status.blocks {
         // Get block
         let block = storage.get_nth_block(block_num).unwrap();
-        if block.block_type == BlockType::COMPONENT {
+        // Components of an interrupted update are not installed
+        if block.block_type == BlockType::COMPONENT && block.finalized > 0 {
             // Read cbf
             let flash_reader = FlashReader::from(
+                &StoragePlatform,
                 block.block_base_address,
                 block.block_size,
             );
@@ -37,22 +42,47 @@ pub fn system_info(
                 if wrap_cbf_error(cbf.validate())? {
                     component_status |= ComponentStatus::CBF_VALID;
                 }
-                let msg = ComponentInfoMessage::new(
-                    cbf_base.component_id(),
-                    cbf_base.component_version(),
-                    block.block_size,
-                    cbf_main.component_min_ram(),
-                    component_status,
-                );
+                let msg = ComponentInfoMessage {
+                    component_id: cbf_base.component_id(),
+                    component_version: cbf_base.component_version(),
+                    allocated_flash: block.block_size,
+                    allocated_ram: cbf_main.component_min_ram(),
+                    component_status: component_status,
+                    block_base_address: block.block_base_address,
+                    sram_base_address: block.sram_base_address,
+                    num_dependencies: cbf_base.num_dependencies(),
+                };
                 // Send message
                 let mut buff: [u8; ComponentInfoMessage::get_size()] = [0x00; ComponentInfoMessage::get_size()];
                 msg.write_to_buffer(&mut buff);
                 channel_write(channel, &buff)?;
+                // Then its dependencies, for the host to plan the updates
+                for dep_num in 0..cbf_base.num_dependencies() {
+                    let dep = wrap_cbf_error(cbf.dependency_nth(dep_num))?;
+                    let msg = ComponentDependencyMessage::new(
+                        dep.component_id(),
+                        dep.min_version(),
+                        dep.max_version(),
+                    );
+                    channel_write(channel, &msg.get_raw())?;
+                }
             }
         }
     }
 
     channel_write(channel, &NO_MORE_COMPONENTS.to_le_bytes())?;
+    // Then why the system last reset, unknown without the watchdog
+    let watchdog = Watchdog::new();
+    let last_reset = match watchdog.is_present() {
+        true => watchdog.last_reset().ok(),
+        false => None,
+    };
+    let (cause, component) = match last_reset {
+        Some(last) => (last.cause as u8, last.component as u16),
+        None => (ResetCause::Unknown as u8, 0),
+    };
+    let msg = ResetCauseMessage::new(cause, component);
+    channel_write(channel, &msg.get_raw())?;
 
     Ok(())
 }
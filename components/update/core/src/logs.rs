@@ -0,0 +1,74 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use logstore_api::*;
+use update_transport::UpdateTransport;
+use userlib::*;
+
+use update_protocol::{messages::*, utils::*};
+
+fn map_log_error(error: LogStoreError) -> MessageError {
+    match error {
+        LogStoreError::ComponentUnavailable => MessageError::LogUnavailable,
+        LogStoreError::InvalidCursor => MessageError::InvalidCursor,
+        _ => MessageError::FlashError,
+    }
+}
+
+/// Sends the records of the log store, as requested by the tool one at a time,
+/// and moves the consumed marker once they have been saved
+pub fn log_read<T: UpdateTransport>(channel: &mut T) -> Result<(), MessageError> {
+    let mut log = LogStore::new();
+    // The log store is optional, asking an absent component would fault
+    if !log.is_present() {
+        return Err(MessageError::LogUnavailable);
+    }
+    // Step 1: send where the log starts and ends
+    let status = log.status().map_err(map_log_error)?;
+    let status_msg = LogStatusMessage::new(status.first, status.consumed, status.next);
+    channel_write(channel, &status_msg.get_raw())?;
+    // Step 2: serve the requests
+    let mut data: [u8; MAX_DATA_LEN] = [0x00; MAX_DATA_LEN];
+    let mut buffer: [u8; LogRecordMessage::get_max_size()] =
+        [0x00; LogRecordMessage::get_max_size()];
+    loop {
+        let mut request_buff: [u8; LogRequestMessage::get_size()] =
+            [0x00; LogRequestMessage::get_size()];
+        channel_ask(
+            channel,
+            LogReadCommand::SendRequest as u8,
+            &mut request_buff,
+        )?;
+        let request = LogRequestMessage::from(&request_buff)?;
+        match request.get_action() {
+            LogRequestAction::Read => match log.read(request.get_value(), &mut data) {
+                Ok(record) => {
+                    let len = core::cmp::min(record.len as usize, MAX_DATA_LEN);
+                    let timestamp =
+                        (record.timestamp_high as u64) << 32 | record.timestamp_low as u64;
+                    let msg = LogRecordMessage::new(
+                        record.seq,
+                        record.source as u16,
+                        timestamp,
+                        &data[0..len],
+                    );
+                    let size = msg.write_raw(&mut buffer);
+                    channel_write(channel, &buffer[0..size])?;
+                }
+                Err(LogStoreError::EndOfLog) => {
+                    channel_write_single(channel, LogReadCommand::EndOfLog as u8)?
+                }
+                Err(e) => return Err(map_log_error(e)),
+            },
+            LogRequestAction::Ack => {
+                log.consume(request.get_value()).map_err(map_log_error)?;
+                sys_log!("[UPDATE] Log consumed up to {}", request.get_value());
+                channel_write_single(channel, LogReadResponse::Success as u8)?;
+            }
+            LogRequestAction::Done => {
+                return channel_write_single(channel, LogReadResponse::Success as u8);
+            }
+        }
+    }
+}
@@ -5,23 +5,27 @@
 #![no_std]
 #![no_main]
 
-mod crc;
-mod messages;
-mod update;
 mod utils;
 mod consts;
 mod info;
+mod coredump;
+mod read;
+mod logs;
+mod platform;
+mod transport;
 
-use uart_channel_api::*;
+use update_transport::UpdateTransport;
 use userlib::*;
 
-use messages::*;
-use update::component_add_update;
+use update_protocol::messages::*;
+use update_protocol::utils::{channel_write_single, receive_hello, HelloError};
+use update_protocol::{component_add_update, component_delta_update, component_resume, link_benchmark};
 use info::system_info;
-use utils::channel_write_single;
-
-#[cfg(feature = "multi-support")]
-use crate::consts::CHANNEL_ID;
+use coredump::{coredump_erase, coredump_read};
+use read::component_read;
+use logs::log_read;
+use platform::StoragePlatform;
+use transport::open_transport;
 
 #[export_name = "main"]
 fn main() -> ! {
@@ -34,46 +38,25 @@ fn main() -> ! {
     kipc::activate_task();
     // Immediately set the handler
     kipc::set_update_support(true);
-    // Listen for the initial packet on serial
-    let mut usart = UartChannel::new();
+    // Listen for the initial packet
+    let mut channel = open_transport();
 
     // Main loop
     sys_log!("[UPDATE] Hello");
     loop {
-        // Create a buffer where to store the message
-        let mut hello_buffer: [u8; HelloMessage::get_size()] = [0x00; HelloMessage::get_size()];
-        // Read message
-        #[cfg(feature = "multi-support")]
-        let read_result = usart.read_block(CHANNEL_ID, &mut hello_buffer);
-        #[cfg(not(feature = "multi-support"))]
-        let read_result = usart.read_block(&mut hello_buffer);
-
-        if read_result.is_ok() {
-            // Validate message
-            sys_log!("[UPDATE] Got hello message");
-            let parsed = HelloMessage::from(&hello_buffer);
-            if parsed.is_ok() {
-                let msg = parsed.unwrap_lite();
-                // Respond to the message
-                let response = HelloResponseMessage::new(msg.get_operation());
-                #[cfg(feature = "multi-support")]
-                let wrire_result = usart.write_block(CHANNEL_ID, &response.get_raw());
-                #[cfg(not(feature = "multi-support"))]
-                let wrire_result = usart.write_block(&response.get_raw());
-
-                if wrire_result.is_ok() {
-                    sys_log!("[UPDATE] Wrote hello response");
-                    // Process this message
-                    let result = hello_arrived(&msg, &mut usart);
-                    if result.is_err() {
-                        error_response(result.unwrap_err(), &mut usart);
-                    }
-                } else {
-                    panic!("Cannot write!");
+        // Wait for the hello and respond to it
+        match receive_hello(&mut channel) {
+            Ok(msg) => {
+                sys_log!("[UPDATE] Wrote hello response");
+                // Process this message
+                let result = hello_arrived(&msg, &mut channel);
+                if result.is_err() {
+                    error_response(result.unwrap_err(), &mut channel);
                 }
             }
-        } else {
-            sys_log!("[UPDATE] Read error");
+            Err(HelloError::Read) => sys_log!("[UPDATE] Read error"),
+            Err(HelloError::Invalid) => (), // Ignore
+            Err(HelloError::Write) => panic!("Cannot write!"),
         }
         // Check for update request
         if kipc::is_state_transfer_requested() {
@@ -82,20 +65,27 @@ fn main() -> ! {
     }
 }
 
-fn hello_arrived(msg: &HelloMessage, usart: &mut UartChannel) -> Result<(), MessageError> {
+fn hello_arrived<T: UpdateTransport>(msg: &HelloMessage, channel: &mut T) -> Result<(), MessageError> {
     match msg.get_operation() {
-        OperationType::ComponentUpdate => component_add_update(usart),
-        OperationType::SystemInfo => system_info(usart),
-        OperationType::ComponentErase => Err(MessageError::InvalidOperation), //component_erase(usart),
+        OperationType::ComponentDelta => component_delta_update(channel, &StoragePlatform),
+        OperationType::LinkBenchmark => link_benchmark(channel, &StoragePlatform),
+        OperationType::ComponentUpdate => component_add_update(channel, &StoragePlatform),
+        OperationType::ComponentResume => component_resume(channel, &StoragePlatform),
+        OperationType::SystemInfo => system_info(channel),
+        OperationType::ComponentErase => Err(MessageError::InvalidOperation), //component_erase(channel),
+        OperationType::CoreDumpRead => coredump_read(channel),
+        OperationType::CoreDumpErase => coredump_erase(channel),
+        OperationType::ComponentRead => component_read(channel),
+        OperationType::LogRead => log_read(channel),
     }
 }
 
-fn error_response(error: MessageError, usart: &mut UartChannel) {
+fn error_response<T: UpdateTransport>(error: MessageError, channel: &mut T) {
     match error {
         MessageError::ChannelError => (), // Ignore
         other => {
             // Write back error on the channel
-            channel_write_single(usart, other as u8).ok(); // Ignore error in errors
+            channel_write_single(channel, other as u8).ok(); // Ignore error in errors
         }
     }
 }
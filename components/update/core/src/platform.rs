@@ -0,0 +1,99 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! The update protocol on the device: blocks from the storage component,
+//! components started by the kernel.
+
+use storage_api::*;
+use update_protocol::{Allocation, Block, PlatformError, UpdatePlatform};
+use userlib::sys_log;
+
+pub struct StoragePlatform;
+
+fn platform_error(error: StorageError) -> PlatformError {
+    match error {
+        StorageError::OutOfFlash | StorageError::OutOfRam => PlatformError::OutOfSpace,
+        _ => PlatformError::Storage,
+    }
+}
+
+impl UpdatePlatform for StoragePlatform {
+    fn allocate_component(
+        &self,
+        flash_size: u32,
+        ram_size: u32,
+    ) -> Result<Allocation, PlatformError> {
+        let allocation = Storage::new()
+            .allocate_component(flash_size, ram_size)
+            .map_err(platform_error)?;
+        Ok(Allocation {
+            flash_base_address: allocation.flash_base_address,
+            flash_size: allocation.flash_size,
+            ram_base_address: allocation.ram_base_address,
+            ram_size: allocation.ram_size,
+        })
+    }
+
+    fn allocate_session(&self) -> Result<u32, PlatformError> {
+        let session = Storage::new()
+            .allocate_session()
+            .map_err(platform_error)?;
+        Ok(session.block_base_address)
+    }
+
+    fn deallocate_block(&self, block_base_address: u32) -> Result<(), PlatformError> {
+        Storage::new()
+            .deallocate_block(block_base_address)
+            .map_err(platform_error)
+    }
+
+    fn write_stream(
+        &self,
+        block_base_address: u32,
+        offset: u32,
+        data: &[u8],
+        flush_after: bool,
+    ) -> Result<(), PlatformError> {
+        Storage::new()
+            .write_stream(block_base_address, offset, data, flush_after)
+            .map_err(platform_error)
+    }
+
+    fn read_stream(
+        &self,
+        block_base_address: u32,
+        offset: u32,
+        buffer: &mut [u8],
+    ) -> Result<(), PlatformError> {
+        Storage::new()
+            .read_stream(block_base_address, offset, buffer)
+            .map_err(platform_error)
+    }
+
+    fn blocks(&self) -> Result<u32, PlatformError> {
+        let status = Storage::new().report_status().map_err(platform_error)?;
+        Ok(status.blocks)
+    }
+
+    fn nth_block(&self, block_number: u32) -> Result<Block, PlatformError> {
+        let block = Storage::new()
+            .get_nth_block(block_number)
+            .map_err(platform_error)?;
+        Ok(Block {
+            block_base_address: block.block_base_address,
+            block_size: block.block_size,
+            sram_base_address: block.sram_base_address,
+            block_type: block.block_type,
+            finalized: block.finalized > 0,
+        })
+    }
+
+    fn load_component(&self, block_base_address: u32) -> bool {
+        userlib::kipc::load_component(block_base_address)
+    }
+
+    fn log(&self, message: &str) {
+        sys_log!("{}", message);
+    }
+}
@@ -0,0 +1,144 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use cbf_lite::CbfFile;
+use sha2::{Digest, Sha256};
+use storage_api::*;
+use update_transport::UpdateTransport;
+use userlib::flash::BlockType;
+use userlib::*;
+
+use update_protocol::consts::SOURCE_CHUNK_SIZE;
+use update_protocol::delta_source::SourceReader;
+use update_protocol::{messages::*, utils::*};
+
+use crate::platform::StoragePlatform;
+use crate::utils::stream_flash;
+
+/// Block of an installed component
+struct ComponentBlock {
+    block_base_address: u32,
+    block_size: u32,
+    sram_base_address: u32,
+}
+
+fn find_component_block(
+    storage: &Storage,
+    component_id: u16,
+) -> Result<ComponentBlock, MessageError> {
+    let status = storage
+        .report_status()
+        .map_err(|_| MessageError::FlashError)?;
+    for block_num in 0..status.blocks {
+        let block = storage
+            .get_nth_block(block_num)
+            .map_err(|_| MessageError::FlashError)?;
+        if block.block_type != BlockType::COMPONENT {
+            continue;
+        }
+        let flash_reader =
+            FlashReader::from(&StoragePlatform, block.block_base_address, block.block_size);
+        let cbf = match CbfFile::from_reader(&flash_reader) {
+            Ok(cbf) => cbf,
+            Err(_) => continue,
+        };
+        if wrap_cbf_error(cbf.header_base())?.component_id() == component_id {
+            return Ok(ComponentBlock {
+                block_base_address: block.block_base_address,
+                block_size: block.block_size,
+                sram_base_address: block.sram_base_address,
+            });
+        }
+    }
+    Err(MessageError::CannotFindComponent)
+}
+
+/// Hashes the CBF as it was before the relocation, up to the trailer
+/// (the checksum is replaced at installation)
+fn hash_image(
+    storage: &Storage,
+    cbf: &CbfFile,
+    component: &ComponentBlock,
+) -> Result<[u8; 32], MessageError> {
+    let mut hasher = Sha256::new();
+    let mut chunk: [u8; SOURCE_CHUNK_SIZE] = [0x00; SOURCE_CHUNK_SIZE];
+    // The header is stored as it was received
+    let payload_offset = wrap_cbf_error(cbf.get_readonly_payload())?.get_offset();
+    let mut pos: u32 = 0;
+    while pos < payload_offset {
+        let len = core::cmp::min(SOURCE_CHUNK_SIZE as u32, payload_offset - pos) as usize;
+        storage
+            .read_stream(component.block_base_address, pos, &mut chunk[0..len])
+            .map_err(|_| MessageError::FlashError)?;
+        hasher.update(&chunk[0..len]);
+        pos += len as u32;
+    }
+    // The payload with the relocations undone
+    let mut reader = SourceReader::new(
+        &StoragePlatform,
+        cbf,
+        component.block_base_address,
+        component.sram_base_address,
+    )?;
+    let payload_size = wrap_cbf_error(cbf.payload_size())? as usize;
+    let mut pos: usize = 0;
+    while pos < payload_size {
+        let len = core::cmp::min(SOURCE_CHUNK_SIZE, payload_size - pos);
+        for i in 0..len {
+            chunk[i] = reader.next_byte()?;
+        }
+        hasher.update(&chunk[0..len]);
+        pos += len;
+    }
+    Ok(hasher.finalize().into())
+}
+
+pub fn component_read<T: UpdateTransport>(channel: &mut T) -> Result<(), MessageError> {
+    // Step 1: ask which component
+    let mut request_buff: [u8; ComponentReadRequestMessage::get_size()] =
+        [0x00; ComponentReadRequestMessage::get_size()];
+    channel_ask(
+        channel,
+        ComponentReadCommand::SendReadRequest as u8,
+        &mut request_buff,
+    )?;
+    let request = ComponentReadRequestMessage::from(&request_buff)?;
+    // Step 2: find it
+    let storage = Storage::new();
+    let component = find_component_block(&storage, request.get_component_id())?;
+    let flash_reader = FlashReader::from(
+        &StoragePlatform,
+        component.block_base_address,
+        component.block_size,
+    );
+    let cbf = wrap_cbf_error(CbfFile::from_reader(&flash_reader))?;
+    let total_size = wrap_cbf_error(cbf.header_base())?.total_size();
+    if total_size > component.block_size {
+        return Err(MessageError::CannotReadCBF);
+    }
+    // Step 3: send it back
+    match request.get_mode() {
+        ComponentReadMode::Image => {
+            sys_log!("[UPDATE] Reading component {}", request.get_component_id());
+            let info = ImageInfoMessage::new(
+                total_size,
+                component.block_base_address,
+                component.sram_base_address,
+            );
+            channel_write(channel, &info.get_raw())?;
+            stream_flash(
+                channel,
+                &storage,
+                component.block_base_address,
+                0,
+                total_size,
+            )
+        }
+        ComponentReadMode::Hash => {
+            sys_log!("[UPDATE] Hashing component {}", request.get_component_id());
+            let hash = hash_image(&storage, &cbf, &component)?;
+            channel_write(channel, &ImageHashMessage::new(hash).get_raw())
+        }
+    }
+}
@@ -0,0 +1,31 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Channel used to talk with the update tool, selected by the cargo features.
+//! The rest of the component only sees an UpdateTransport.
+
+#[cfg(all(feature = "transport-uart", feature = "multi-support"))]
+use crate::consts::CHANNEL_ID;
+
+#[cfg(not(feature = "transport-uart"))]
+compile_error!("The update component needs a transport (i.e. feature \"transport-uart\")");
+
+/**
+ * uart-channel
+ */
+#[cfg(all(feature = "transport-uart", not(feature = "multi-support")))]
+pub type Transport = uart_channel_api::UartChannel;
+
+#[cfg(all(feature = "transport-uart", not(feature = "multi-support")))]
+pub fn open_transport() -> Transport {
+    uart_channel_api::UartChannel::new()
+}
+
+#[cfg(all(feature = "transport-uart", feature = "multi-support"))]
+pub type Transport = uart_channel_api::MuxChannel;
+
+#[cfg(all(feature = "transport-uart", feature = "multi-support"))]
+pub fn open_transport() -> Transport {
+    uart_channel_api::MuxChannel::new(CHANNEL_ID)
+}
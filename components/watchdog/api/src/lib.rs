@@ -0,0 +1,48 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+#![no_std]
+
+use userlib::{sys_post, FromPrimitive};
+
+// Generated from watchdog.idl.toml
+include!(concat!(env!("OUT_DIR"), "/client_stub.rs"));
+
+/// Components that can be watched at once, one notification bit each
+pub const MAX_COMPONENTS: usize = 16;
+/// Posted to the supervisor when a component misses its check-in, next to the
+/// fault notification of the kernel (bit 0)
+pub const MISSED_CHECK_IN_NOTIFICATION: u32 = 1 << 1;
+
+// Causes of a reset, the first found in this order if more are flagged
+#[derive(Copy, Clone, Debug, PartialEq, FromPrimitive)]
+pub enum ResetCause {
+    Unknown = 0,
+    Watchdog = 1,
+    WindowWatchdog = 2,
+    LowPower = 3,
+    Software = 4,
+    PowerOn = 5,
+    OptionBytes = 6,
+    Pin = 7,
+}
+
+impl Watchdog {
+    /// Tells the watchdog the caller is alive, with the notification returned
+    /// by register. Cheaper than a message, it never blocks
+    pub fn check_in(&self, notification: u32) {
+        loop {
+            let target = self.0.get();
+            let code = sys_post(target, notification);
+            match userlib::extract_new_generation(code) {
+                // The watchdog has been restarted
+                Some(generation) => self.0.set(userlib::TaskId::for_id_and_gen(
+                    target.component_id(),
+                    generation,
+                )),
+                None => return,
+            }
+        }
+    }
+}
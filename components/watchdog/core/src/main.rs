@@ -0,0 +1,354 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+#![no_std]
+#![no_main]
+
+use rcc_api::{
+    Peripheral, RCC, RESET_IWDG, RESET_LOW_POWER, RESET_OPTION_BYTES, RESET_PIN, RESET_POWER,
+    RESET_SOFTWARE, RESET_WWDG,
+};
+use userlib::*;
+use watchdog_api::*;
+use zerocopy::AsBytes;
+
+// STM32F3
+#[cfg(feature = "board_stm32f303re")]
+use stm32f303re::device;
+
+// STM32L432
+#[cfg(feature = "board_stm32l432kc")]
+use stm32l432kc::device;
+
+// STM32L476
+#[cfg(feature = "board_stm32l476rg")]
+use stm32l476rg::device;
+
+// Generated from watchdog.idl.toml
+include!(concat!(env!("OUT_DIR"), "/server_stub.rs"));
+
+/**
+ * Constants
+ */
+// The check-ins, bit n for the component in slot n
+const CHECK_IN_MASK: u32 = (1 << MAX_COMPONENTS) - 1;
+const TIMER_MASK: u32 = 1 << 16;
+
+/// Kernel ticks between two checks of the components (and refreshes)
+const CHECK_TICKS: u64 = 250;
+/// Kernel ticks given to the supervisor to recover an unresponsive component
+const GRACE_TICKS: u64 = 1000;
+/// The IWDG runs, so the chip is reset once the grace period is over
+const ARMED: bool = cfg!(feature = "iwdg");
+
+// IWDG keys
+const KEY_ENABLE: u32 = 0xCCCC;
+const KEY_UNLOCK: u32 = 0x5555;
+const KEY_REFRESH: u32 = 0xAAAA;
+// LSI / 64, so the reload is about 1.6 s on the F3 (40 kHz) and 2 s on the L4
+// (32 kHz)
+const PRESCALER: u32 = 0b100;
+const RELOAD: u32 = 1000;
+
+// Backup registers, kept across the resets of the chip. They hold the
+// component that made us reset (u16) and the record of the last reset (u8 cause,
+// u16 component), with a mark in the top byte to tell them from garbage.
+const PENDING_REGISTER: usize = 0;
+const LAST_REGISTER: usize = 1;
+const BACKUP_MARK: u32 = 0xA5 << 24;
+
+/**
+ * Hardware
+ */
+fn arm_iwdg(iwdg: &device::iwdg::RegisterBlock) {
+    // Once enabled it cannot be stopped, not even by a restart of this component
+    iwdg.kr.write(|w| unsafe { w.bits(KEY_ENABLE) });
+    iwdg.kr.write(|w| unsafe { w.bits(KEY_UNLOCK) });
+    iwdg.pr.write(|w| unsafe { w.bits(PRESCALER) });
+    iwdg.rlr.write(|w| unsafe { w.bits(RELOAD) });
+    // Wait for the values to reach the LSI domain
+    while iwdg.sr.read().bits() != 0 {}
+    iwdg.kr.write(|w| unsafe { w.bits(KEY_REFRESH) });
+}
+
+fn cause_of(flags: u32) -> ResetCause {
+    // The pin is flagged on almost every reset, as the chip drives it
+    if flags & RESET_IWDG != 0 {
+        ResetCause::Watchdog
+    } else if flags & RESET_WWDG != 0 {
+        ResetCause::WindowWatchdog
+    } else if flags & RESET_LOW_POWER != 0 {
+        ResetCause::LowPower
+    } else if flags & RESET_SOFTWARE != 0 {
+        ResetCause::Software
+    } else if flags & RESET_POWER != 0 {
+        ResetCause::PowerOn
+    } else if flags & RESET_OPTION_BYTES != 0 {
+        ResetCause::OptionBytes
+    } else if flags & RESET_PIN != 0 {
+        ResetCause::Pin
+    } else {
+        ResetCause::Unknown
+    }
+}
+
+/// Lets the backup registers be written. The PWR clock stays on and DBP stays
+/// set, so that a record can later be written without any IPC.
+fn unlock_backup(pwr: &device::pwr::RegisterBlock) {
+    let mut rcc = RCC::new();
+    rcc.enable_clock(Peripheral::PWR).unwrap_lite();
+    // Only gated on the STM32L4
+    #[cfg(any(feature = "board_stm32l432kc", feature = "board_stm32l476rg"))]
+    rcc.enable_clock(Peripheral::RTC).unwrap_lite();
+    #[cfg(feature = "board_stm32f303re")]
+    pwr.cr.modify(|_, w| w.dbp().set_bit());
+    #[cfg(any(feature = "board_stm32l432kc", feature = "board_stm32l476rg"))]
+    pwr.cr1.modify(|_, w| w.dbp().set_bit());
+}
+
+fn read_backup(rtc: &device::rtc::RegisterBlock, register: usize) -> Option<u32> {
+    let value = rtc.bkpr[register].read().bits();
+    match value & 0xFF00_0000 == BACKUP_MARK {
+        true => Some(value & 0x00FF_FFFF),
+        false => None,
+    }
+}
+
+fn write_backup(rtc: &device::rtc::RegisterBlock, register: usize, value: Option<u32>) {
+    let bits = value.map_or(0, |v| BACKUP_MARK | (v & 0x00FF_FFFF));
+    #[cfg(feature = "board_stm32f303re")]
+    rtc.bkpr[register].write(|w| w.bits(bits));
+    #[cfg(any(feature = "board_stm32l432kc", feature = "board_stm32l476rg"))]
+    rtc.bkpr[register].write(|w| unsafe { w.bits(bits) });
+}
+
+/// Cause of the last reset, with the unresponsive component if we caused it.
+/// The flags are seen only at the first start after the reset, the record is
+/// kept in a backup register for the later restarts.
+fn load_last_reset(rtc: &device::rtc::RegisterBlock) -> (ResetCause, u16) {
+    let flags = RCC::new().take_reset_flags().map_or(0, |r| r.flags);
+    if flags == 0 {
+        return match read_backup(rtc, LAST_REGISTER) {
+            Some(record) => (
+                ResetCause::from_u32(record >> 16).unwrap_or(ResetCause::Unknown),
+                record as u16,
+            ),
+            None => (ResetCause::Unknown, 0),
+        };
+    }
+    let cause = cause_of(flags);
+    let component = match (cause, read_backup(rtc, PENDING_REGISTER)) {
+        (ResetCause::Watchdog, Some(pending)) => pending as u16,
+        _ => 0,
+    };
+    write_backup(rtc, PENDING_REGISTER, None);
+    write_backup(
+        rtc,
+        LAST_REGISTER,
+        Some((cause as u32) << 16 | component as u32),
+    );
+    (cause, component)
+}
+
+/**
+ * Server
+ */
+struct Watched {
+    task: TaskId,
+    period: u64,
+    /// Time of the next check-in at the latest
+    deadline: u64,
+    /// Time at which the missed check-in was reported
+    missed_at: Option<u64>,
+}
+
+/// Wakes up the supervisor, that asks the unresponsive components with
+/// `missed_check_in`
+fn report_missed() {
+    // Posting to a component missing from the image faults the sender
+    if !kipc::is_task_present(SUPERVISOR_ID) {
+        return;
+    }
+    let supervisor = sys_refresh_task_id(TaskId::for_id_and_gen(SUPERVISOR_ID, Generation::ZERO));
+    sys_post(supervisor, MISSED_CHECK_IN_NOTIFICATION);
+}
+
+struct Server {
+    iwdg: &'static device::iwdg::RegisterBlock,
+    rtc: &'static device::rtc::RegisterBlock,
+    /// A slot for each notification bit
+    watched: [Option<Watched>; MAX_COMPONENTS],
+    last_reset: (ResetCause, u16),
+    /// Once set the IWDG is left to expire
+    expired: bool,
+}
+
+impl Server {
+    fn slot_of(&self, sender: TaskId) -> Option<usize> {
+        self.watched.iter().position(|w| match w {
+            Some(w) => w.task.component_id() == sender.component_id(),
+            None => false,
+        })
+    }
+
+    fn handle_check_in(&mut self, bits: u32) {
+        let now = sys_get_timer().now;
+        for slot in 0..MAX_COMPONENTS {
+            if bits & (1 << slot) == 0 {
+                continue;
+            }
+            if let Some(watched) = &mut self.watched[slot] {
+                watched.deadline = now + watched.period;
+                if watched.missed_at.take().is_some() {
+                    sys_log!(
+                        "[WATCHDOG] Component {} is back",
+                        watched.task.component_id()
+                    );
+                }
+            }
+        }
+    }
+
+    /// Refreshes the IWDG if all the components are alive or still recovering
+    fn handle_timer(&mut self) {
+        let now = sys_get_timer().now;
+        for slot in 0..MAX_COMPONENTS {
+            let watched = match &mut self.watched[slot] {
+                Some(watched) if now > watched.deadline => watched,
+                _ => continue,
+            };
+            let component = watched.task.component_id();
+            match watched.missed_at {
+                None => {
+                    sys_log!("[WATCHDOG] Component {} missed its check-in", component);
+                    watched.missed_at = Some(now);
+                    // The supervisor decides whether to restart it or wait
+                    report_missed();
+                }
+                Some(missed_at) if ARMED && now - missed_at >= GRACE_TICKS && !self.expired => {
+                    sys_log!("[WATCHDOG] Component {} unresponsive, resetting", component);
+                    // No IPC here, the servers below us may be stuck with it
+                    write_backup(self.rtc, PENDING_REGISTER, Some(component as u32));
+                    self.expired = true;
+                }
+                Some(_) => {}
+            }
+        }
+        if !self.expired {
+            self.iwdg.kr.write(|w| unsafe { w.bits(KEY_REFRESH) });
+        }
+        sys_set_timer(Some(now + CHECK_TICKS), TIMER_MASK);
+    }
+}
+
+impl WatchdogServer for Server {
+    fn register(
+        &mut self,
+        msg: &RegisterRequest,
+        sender: TaskId,
+    ) -> Result<RegisterResponse, WatchdogError> {
+        if msg.period == 0 {
+            return Err(WatchdogError::BadArgument);
+        }
+        let slot = match self.slot_of(sender) {
+            Some(slot) => slot,
+            None => self
+                .watched
+                .iter()
+                .position(|w| w.is_none())
+                .ok_or(WatchdogError::TooManyComponents)?,
+        };
+        let period = msg.period as u64;
+        self.watched[slot] = Some(Watched {
+            task: sender,
+            period: period,
+            deadline: sys_get_timer().now + period,
+            missed_at: None,
+        });
+        Ok(RegisterResponse {
+            notification: 1 << slot,
+        })
+    }
+
+    fn unregister(
+        &mut self,
+        _msg: &UnregisterRequest,
+        sender: TaskId,
+    ) -> Result<(), WatchdogError> {
+        let slot = self.slot_of(sender).ok_or(WatchdogError::NotRegistered)?;
+        self.watched[slot] = None;
+        Ok(())
+    }
+
+    fn missed_check_in(
+        &mut self,
+        msg: &MissedCheckInRequest,
+    ) -> Result<MissedCheckInResponse, WatchdogError> {
+        let cursor = msg.cursor as usize;
+        for (slot, watched) in self.watched.iter().enumerate().skip(cursor) {
+            if let Some(Watched {
+                task,
+                missed_at: Some(_),
+                ..
+            }) = watched
+            {
+                return Ok(MissedCheckInResponse {
+                    component: task.component_id() as u32,
+                    next_cursor: slot as u32 + 1,
+                });
+            }
+        }
+        Err(WatchdogError::EndOfComponents)
+    }
+
+    fn last_reset(&mut self, _msg: &LastResetRequest) -> Result<LastResetResponse, WatchdogError> {
+        Ok(LastResetResponse {
+            cause: self.last_reset.0 as u32,
+            component: self.last_reset.1 as u32,
+        })
+    }
+}
+
+#[export_name = "main"]
+fn main() -> ! {
+    kipc::activate_task();
+    let iwdg = unsafe { &*device::IWDG::ptr() };
+    if ARMED {
+        arm_iwdg(iwdg);
+    }
+    let rtc = unsafe { &*device::RTC::ptr() };
+    unlock_backup(unsafe { &*device::PWR::ptr() });
+    let last_reset = load_last_reset(rtc);
+    sys_log!(
+        "[WATCHDOG] Online! Last reset: {:?} ({})",
+        last_reset.0,
+        last_reset.1
+    );
+    let mut server = Server {
+        iwdg: iwdg,
+        rtc: rtc,
+        watched: Default::default(),
+        last_reset: last_reset,
+        expired: false,
+    };
+    sys_set_timer(Some(sys_get_timer().now + CHECK_TICKS), TIMER_MASK);
+    // Incoming message buffer, as u32 for the alignment of the requests
+    let mut buffer = [0u32; 1];
+    loop {
+        hl::recv(
+            buffer.as_bytes_mut(),
+            CHECK_IN_MASK | TIMER_MASK,
+            &mut server,
+            |server, bits| {
+                if bits & CHECK_IN_MASK != 0 {
+                    server.handle_check_in(bits);
+                }
+                if bits & TIMER_MASK != 0 {
+                    server.handle_timer();
+                }
+            },
+            |server, op, msg| server.dispatch(op, msg),
+        );
+    }
+}
@@ -24,6 +24,10 @@ bitflags::bitflags! {
     pub struct ComponentFlags: u16 {
         const NONE = 0;
         const START_AT_BOOT = 1 << 0;
+        /// The payload in the file is compressed (heatshrink), while the header,
+        /// the sizes and the checksum refer to the uncompressed image.
+        /// Once stored in flash, the payload is always uncompressed.
+        const COMPRESSED = 1 << 1;
     }
 }
 bitflags::bitflags! {
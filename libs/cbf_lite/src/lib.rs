@@ -7,7 +7,7 @@
 #[cfg(feature = "fmt")]
 use core::fmt::{Debug, Error, Formatter};
 
-pub use header::{CbfHeaderBase, CbfHeaderMain, CbfHeaderRelocation, CbfHeaderDependency, CbfVersion, CBF_MAGIC};
+pub use header::{ComponentFlags, CbfHeaderBase, CbfHeaderMain, CbfHeaderRelocation, CbfHeaderDependency, CbfVersion, CBF_MAGIC};
 
 pub use header::{
     CbfHeaderInterrupt, CbfHeaderRegion, FIXED_HEADER_SIZE,
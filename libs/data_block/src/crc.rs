@@ -0,0 +1,34 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+pub const CRC16_INIT: u16 = 0xFFFF;
+
+/// CRC-16/CCITT-FALSE, computed in steps over the parts of a record.
+/// Polynomial: 0x1021, initial value: CRC16_INIT
+pub fn crc16_update(mut crc: u16, data: &[u8]) -> u16 {
+    for byte in data {
+        crc ^= (*byte as u16) << 8;
+        for _ in 0..8u8 {
+            if crc & 0x8000 != 0 {
+                crc = (crc << 1) ^ 0x1021;
+            } else {
+                crc <<= 1;
+            }
+        }
+    }
+    crc
+}
+
+#[cfg(test)]
+mod test {
+    use super::{crc16_update, CRC16_INIT};
+
+    #[test]
+    fn check_value() {
+        assert_eq!(crc16_update(CRC16_INIT, b"123456789"), 0x29B1);
+        // Same result in steps
+        let crc = crc16_update(CRC16_INIT, b"1234");
+        assert_eq!(crc16_update(crc, b"56789"), 0x29B1);
+    }
+}
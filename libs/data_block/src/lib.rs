@@ -0,0 +1,54 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+#![no_std]
+
+#[cfg(any(test, feature = "mock"))]
+extern crate std;
+#[cfg(any(test, feature = "mock"))]
+pub mod mock;
+
+mod crc;
+
+pub use crc::{crc16_update, CRC16_INIT};
+
+/**
+ * Constants
+ */
+/// Write granularity of the flash (FLAG_BYTES of the flash allocator), every
+/// structure is aligned to it, so that no word is ever written twice
+pub const WORD_SIZE: usize = 8;
+pub const COMMIT_SIZE: usize = WORD_SIZE;
+
+/// Written last, the record does not exist until the whole word is zero
+pub const COMMITTED: [u8; COMMIT_SIZE] = [0x00; COMMIT_SIZE];
+
+pub const fn align_up(size: usize) -> usize {
+    (size + WORD_SIZE - 1) & !(WORD_SIZE - 1)
+}
+
+/**
+ * Backend
+ */
+/// Failure of the backend, the users report it as their storage error
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct BackendError;
+
+/// DATA blocks of the flash allocator, as offered by the storage component.
+/// Addresses are the base addresses of the blocks, offsets and lengths of the
+/// writes are always multiples of WORD_SIZE.
+pub trait Backend {
+    /// Allocates a block of at least `size` bytes, returning its base address and
+    /// size. The block is not finalized, so it is erased at start-up if the user
+    /// does not finalize it.
+    fn allocate(&mut self, size: u32) -> Result<(u32, u32), BackendError>;
+    /// Must succeed also when the block is already finalized
+    fn finalize(&mut self, base_address: u32) -> Result<(), BackendError>;
+    fn deallocate(&mut self, base_address: u32) -> Result<(), BackendError>;
+    fn read(&self, base_address: u32, offset: u32, buffer: &mut [u8]) -> Result<(), BackendError>;
+    /// Data must be flushed before returning
+    fn write(&mut self, base_address: u32, offset: u32, data: &[u8]) -> Result<(), BackendError>;
+    /// Base address and size of the n-th DATA block, None after the last one
+    fn data_block(&self, n: u32) -> Result<Option<(u32, u32)>, BackendError>;
+}
@@ -0,0 +1,149 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Flash for host-side tests of the users of the DATA blocks.
+
+use std::vec::Vec;
+
+use crate::{Backend, BackendError, WORD_SIZE};
+
+struct FakeBlock {
+    base_address: u32,
+    content: Vec<u8>,
+    finalized: bool,
+}
+
+/// Fake flash with the rules of the flash allocator: a word is written only
+/// if erased, or to zero it. Power is lost after a number of word writes, the
+/// last one torn in half.
+pub struct FakeFlash {
+    blocks: Vec<FakeBlock>,
+    next_base_address: u32,
+    max_blocks: usize,
+    /// Word writes before the power loss, None to keep the power on
+    pub writes_left: Option<usize>,
+}
+
+impl FakeFlash {
+    pub fn new(max_blocks: usize) -> Self {
+        Self {
+            blocks: Vec::new(),
+            next_base_address: 0x0800_0000,
+            max_blocks,
+            writes_left: None,
+        }
+    }
+
+    fn block(&self, base_address: u32) -> Result<&FakeBlock, BackendError> {
+        self.blocks
+            .iter()
+            .find(|b| b.base_address == base_address)
+            .ok_or(BackendError)
+    }
+
+    /// One step towards the power loss
+    fn consume(&mut self) -> Result<(), BackendError> {
+        match self.writes_left {
+            Some(0) => Err(BackendError),
+            Some(n) => {
+                self.writes_left = Some(n - 1);
+                Ok(())
+            }
+            None => Ok(()),
+        }
+    }
+
+    /// Blocks currently allocated, finalized or not
+    pub fn block_count(&self) -> usize {
+        self.blocks.len()
+    }
+
+    /// Start-up of the storage component: unfinalized blocks are erased
+    pub fn reboot(&mut self) {
+        self.writes_left = None;
+        self.blocks.retain(|b| b.finalized);
+    }
+}
+
+impl Backend for &mut FakeFlash {
+    fn allocate(&mut self, size: u32) -> Result<(u32, u32), BackendError> {
+        if self.blocks.len() == self.max_blocks {
+            return Err(BackendError);
+        }
+        self.consume()?;
+        let base_address = self.next_base_address;
+        self.next_base_address += size;
+        self.blocks.push(FakeBlock {
+            base_address,
+            content: std::vec![0xFF; size as usize],
+            finalized: false,
+        });
+        Ok((base_address, size))
+    }
+
+    fn finalize(&mut self, base_address: u32) -> Result<(), BackendError> {
+        self.block(base_address)?;
+        self.consume()?;
+        let block = self
+            .blocks
+            .iter_mut()
+            .find(|b| b.base_address == base_address);
+        block.unwrap().finalized = true;
+        Ok(())
+    }
+
+    fn deallocate(&mut self, base_address: u32) -> Result<(), BackendError> {
+        self.block(base_address)?;
+        self.consume()?;
+        self.blocks.retain(|b| b.base_address != base_address);
+        Ok(())
+    }
+
+    fn read(&self, base_address: u32, offset: u32, buffer: &mut [u8]) -> Result<(), BackendError> {
+        let block = self.block(base_address)?;
+        let start = offset as usize;
+        buffer.copy_from_slice(
+            block
+                .content
+                .get(start..start + buffer.len())
+                .ok_or(BackendError)?,
+        );
+        Ok(())
+    }
+
+    fn write(&mut self, base_address: u32, offset: u32, data: &[u8]) -> Result<(), BackendError> {
+        assert!(offset as usize & (WORD_SIZE - 1) == 0 && data.len() & (WORD_SIZE - 1) == 0);
+        self.block(base_address)?;
+        for (i, word) in data.chunks(WORD_SIZE).enumerate() {
+            let powered = self.consume().is_ok();
+            let block = self
+                .blocks
+                .iter_mut()
+                .find(|b| b.base_address == base_address)
+                .unwrap();
+            let start = offset as usize + i * WORD_SIZE;
+            let current = block
+                .content
+                .get_mut(start..start + WORD_SIZE)
+                .ok_or(BackendError)?;
+            if current != [0xFF; WORD_SIZE] && word != [0x00; WORD_SIZE] && current != word {
+                panic!("Word at {:#x} written twice", start);
+            }
+            if !powered {
+                // Torn word
+                current[0..WORD_SIZE / 2].copy_from_slice(&word[0..WORD_SIZE / 2]);
+                return Err(BackendError);
+            }
+            current.copy_from_slice(word);
+        }
+        Ok(())
+    }
+
+    fn data_block(&self, n: u32) -> Result<Option<(u32, u32)>, BackendError> {
+        Ok(self
+            .blocks
+            .get(n as usize)
+            .map(|b| (b.base_address, b.content.len() as u32)))
+    }
+}
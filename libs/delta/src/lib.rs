@@ -0,0 +1,236 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+#![no_std]
+
+/**
+ * Operations
+ */
+/// Copies the next bytes of the source
+pub const OP_COPY: u8 = 0x01;
+/// Inserts the bytes that follow
+pub const OP_ADD: u8 = 0x02;
+/// Skips the next bytes of the source
+pub const OP_SKIP: u8 = 0x03;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum PatchError {
+    /// Unknown operation in the patch
+    InvalidOp,
+    /// The patch produced more bytes than expected
+    TooMuchOutput,
+}
+
+#[derive(Clone, Copy)]
+enum State {
+    Op,
+    Length { op: u8, value: u32, count: u32 },
+    Add { remaining: u32 },
+}
+
+/// Streaming patcher: the patch can be split at any point.
+/// The source is read only forward, one byte at a time.
+pub struct Patcher {
+    state: State,
+    remaining: usize,
+}
+
+impl Patcher {
+    /// The patch must produce exactly output_size bytes
+    pub fn new(output_size: usize) -> Self {
+        Self {
+            state: State::Op,
+            remaining: output_size,
+        }
+    }
+
+    pub fn is_complete(&self) -> bool {
+        self.remaining == 0 && matches!(self.state, State::Op)
+    }
+
+    /// Applies the patch, taking the bytes of the source from source and
+    /// passing each byte produced to output
+    pub fn sink<S, F, E>(&mut self, input: &[u8], mut source: S, mut output: F) -> Result<(), E>
+    where
+        S: FnMut() -> Result<u8, E>,
+        F: FnMut(u8) -> Result<(), E>,
+        E: From<PatchError>,
+    {
+        for byte in input {
+            self.state = match self.state {
+                State::Op => match *byte {
+                    OP_COPY | OP_ADD | OP_SKIP => State::Length {
+                        op: *byte,
+                        value: 0,
+                        count: 0,
+                    },
+                    _ => return Err(PatchError::InvalidOp.into()),
+                },
+                State::Length { op, value, count } => {
+                    // Lengths are little endian
+                    let value = value | (*byte as u32) << (8 * count);
+                    if count < 3 {
+                        State::Length {
+                            op,
+                            value,
+                            count: count + 1,
+                        }
+                    } else {
+                        match op {
+                            OP_COPY => {
+                                for _ in 0..value {
+                                    let b = source()?;
+                                    self.emit(b, &mut output)?;
+                                }
+                                State::Op
+                            }
+                            OP_SKIP => {
+                                for _ in 0..value {
+                                    source()?;
+                                }
+                                State::Op
+                            }
+                            _ => match value {
+                                0 => State::Op,
+                                _ => State::Add { remaining: value },
+                            },
+                        }
+                    }
+                }
+                State::Add { remaining } => {
+                    self.emit(*byte, &mut output)?;
+                    match remaining - 1 {
+                        0 => State::Op,
+                        remaining => State::Add { remaining },
+                    }
+                }
+            };
+        }
+        Ok(())
+    }
+
+    fn emit<F, E>(&mut self, byte: u8, output: &mut F) -> Result<(), E>
+    where
+        F: FnMut(u8) -> Result<(), E>,
+        E: From<PatchError>,
+    {
+        if self.remaining == 0 {
+            return Err(PatchError::TooMuchOutput.into());
+        }
+        self.remaining -= 1;
+        output(byte)
+    }
+}
+
+/// Identifies the source of a delta (FNV-1a, 32 bits)
+pub struct SourceHash {
+    hash: u32,
+}
+
+impl SourceHash {
+    pub fn new() -> Self {
+        Self { hash: 0x811C_9DC5 }
+    }
+
+    pub fn update(&mut self, data: &[u8]) {
+        for byte in data {
+            self.hash ^= *byte as u32;
+            self.hash = self.hash.wrapping_mul(0x0100_0193);
+        }
+    }
+
+    pub fn finish(&self) -> u32 {
+        self.hash
+    }
+}
+
+impl Default for SourceHash {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/*
+    Tests
+*/
+#[cfg(test)]
+mod test {
+    extern crate std;
+    use super::*;
+    use std::vec::Vec;
+
+    fn op(patch: &mut Vec<u8>, op: u8, len: u32) {
+        patch.push(op);
+        patch.extend_from_slice(&len.to_le_bytes());
+    }
+
+    fn apply(
+        source: &[u8],
+        patch: &[u8],
+        output_size: usize,
+        chunk: usize,
+    ) -> Result<Vec<u8>, PatchError> {
+        let mut patcher = Patcher::new(output_size);
+        let mut source_pos: usize = 0;
+        let mut output: Vec<u8> = Vec::new();
+        for c in patch.chunks(chunk) {
+            patcher.sink(
+                c,
+                || {
+                    source_pos += 1;
+                    Ok(source[source_pos - 1])
+                },
+                |b| {
+                    output.push(b);
+                    Ok(())
+                },
+            )?;
+        }
+        assert!(patcher.is_complete());
+        Ok(output)
+    }
+
+    #[test]
+    fn copy_add_skip() {
+        let source: Vec<u8> = (0..100u8).collect();
+        let mut patch: Vec<u8> = Vec::new();
+        op(&mut patch, OP_COPY, 10);
+        op(&mut patch, OP_ADD, 3);
+        patch.extend_from_slice(&[0xAA, 0xBB, 0xCC]);
+        op(&mut patch, OP_SKIP, 40);
+        op(&mut patch, OP_COPY, 50);
+        let mut expected: Vec<u8> = (0..10u8).collect();
+        expected.extend_from_slice(&[0xAA, 0xBB, 0xCC]);
+        expected.extend(50..100u8);
+        for chunk in [1, 2, 7, 64] {
+            assert_eq!(
+                apply(&source, &patch, expected.len(), chunk).unwrap(),
+                expected
+            );
+        }
+    }
+
+    #[test]
+    fn invalid_patch() {
+        let source: Vec<u8> = (0..100u8).collect();
+        let mut patch: Vec<u8> = Vec::new();
+        op(&mut patch, OP_COPY, 20);
+        assert_eq!(
+            apply(&source, &patch, 10, 64),
+            Err(PatchError::TooMuchOutput)
+        );
+        assert_eq!(apply(&source, &[0x7F], 10, 64), Err(PatchError::InvalidOp));
+    }
+
+    #[test]
+    fn source_hash() {
+        // Reference values of FNV-1a
+        let mut hash = SourceHash::new();
+        assert_eq!(hash.finish(), 0x811C_9DC5);
+        hash.update(b"a");
+        assert_eq!(hash.finish(), 0xE40C_292C);
+        hash.update(b"");
+        assert_eq!(hash.finish(), 0xE40C_292C);
+    }
+}
@@ -415,7 +415,8 @@ pub mod walker {
 /// Utilities to interact with flash blocks
 pub mod utils {
     use super::HEADER_SIZE;
-    use super::{header::BlockHeader, FlashBlock, FlashMethods};
+    use super::{header::BlockHeader, BlockType, FlashBlock, FlashMethods};
+    use abi::flash::{last_session_record, SESSION_AREA_SIZE};
 
     /// Reads a block header from a given buffer
     pub fn read_block_header<'a, const START_ADDR: u32, const SMALLEST_BLOCK_LEVEL: usize>(
@@ -485,6 +486,59 @@ pub mod utils {
         return None;
     }
 
+    /// Searches for the session of an interrupted update, returning the nominal
+    /// addresses of the session block and of the component block it is installing.
+    /// Only the first session is returned, as the update component runs one at a time.
+    pub fn find_resumable_session<
+        'a,
+        const START_ADDR: u32,
+        const END_ADDR: u32,
+        const START_SCAN_ADDR: u32,
+        const SMALLEST_BLOCK_LEVEL: usize,
+    >(
+        flash: &dyn FlashMethods<'a>,
+    ) -> Option<(u32, u32)> {
+        let size = (END_ADDR - START_ADDR + 1) as usize;
+        let mut process_index: usize = (START_SCAN_ADDR - START_ADDR) as usize;
+        while process_index < size {
+            let block_header =
+                read_block_header::<START_ADDR, SMALLEST_BLOCK_LEVEL>(flash, process_index as u32);
+            if !block_header.is_allocated() {
+                process_index += size >> SMALLEST_BLOCK_LEVEL;
+                continue;
+            }
+            let session_addr = START_ADDR + process_index as u32;
+            process_index += get_block_size::<START_ADDR, END_ADDR>(&block_header);
+            if block_header.is_dismissed() || block_header.block_type() != BlockType::SESSION {
+                continue;
+            }
+            let mut area: [u8; SESSION_AREA_SIZE] = [0xFF; SESSION_AREA_SIZE];
+            if flash
+                .read(session_addr + HEADER_SIZE as u32, &mut area)
+                .is_err()
+            {
+                continue;
+            }
+            let record = match last_session_record(&area) {
+                (Some(record), _) => record,
+                _ => continue,
+            };
+            // The component must still be waiting for the rest of the image
+            let component = get_flash_block::<
+                START_ADDR,
+                END_ADDR,
+                START_SCAN_ADDR,
+                SMALLEST_BLOCK_LEVEL,
+            >(flash, record.component_block_base_address, false);
+            if let Some(component) = component {
+                if component.get_type() == BlockType::COMPONENT && !component.is_finalized() {
+                    return Some((session_addr, component.get_nominal_base_address()));
+                }
+            }
+        }
+        return None;
+    }
+
     /// Marks the block as closed. From this point on, the system will consider
     /// this block complete.
     pub fn finalize_block<'a, const START_ADDR: u32, const SMALLEST_BLOCK_LEVEL: usize>(
@@ -653,6 +707,16 @@ impl<
     const START_SCAN_OFFSET: usize = (START_SCAN_ADDR - START_ADDR) as usize;
 
     pub fn analyze_storage(flash: &mut dyn FlashMethods<'a>, remove_unfinalized_blocks: bool) {
+        // The blocks of an update that can be resumed are kept, any other session is stale
+        let resumable = match remove_unfinalized_blocks {
+            true => utils::find_resumable_session::<
+                START_ADDR,
+                END_ADDR,
+                START_SCAN_ADDR,
+                SMALLEST_BLOCK_LEVEL,
+            >(flash),
+            false => None,
+        };
         let mut process_index: usize = Self::START_SCAN_OFFSET;
         while process_index < Self::ALLOCATOR_SIZE {
             // Read header
@@ -663,17 +727,16 @@ impl<
             // Allocated blocks
             if block_header.is_allocated() {
                 let block_size = utils::get_block_size::<START_ADDR, END_ADDR>(&block_header);
+                let block_addr = START_ADDR + process_index as u32;
+                let is_resumable = matches!(resumable, Some((session_addr, component_addr))
+                    if block_addr == session_addr || block_addr == component_addr);
                 // Check if a freed_block exists
                 if block_header.is_dismissed()
-                    || (remove_unfinalized_blocks & !block_header.is_finalized())
+                    || (remove_unfinalized_blocks && !block_header.is_finalized() && !is_resumable)
                 {
                     // Launch the erase again
                     let mut _block_level_out: u16 = 0;
-                    Self::deallocate_procedure(
-                        flash,
-                        START_ADDR + process_index as u32,
-                        &mut _block_level_out,
-                    );
+                    Self::deallocate_procedure(flash, block_addr, &mut _block_level_out);
                 }
                 // Continue scanning
                 process_index += block_size;
@@ -15,7 +15,9 @@ mod flash_allocator;
 
 #[cfg(test)]
 mod tests {
-    use abi::flash::BlockType;
+    use abi::flash::{
+        last_session_record, BlockType, SessionRecord, SESSION_AREA_SIZE, SESSION_SLOT_SIZE,
+    };
 
     use crate::fake_flash::Flash;
     use crate::flash_allocator::flash::header::{self, BlockHeader};
@@ -1140,6 +1142,96 @@ mod tests {
         );
         check_block(&mut shadow_copy, &block4, 0x06);
     }
+
+    /// Writes a session record in the first free slot of a session block
+    fn write_session_record(flash: &mut [u8], session: &FlashBlock, record: &SessionRecord) {
+        let area_start = (session.get_base_address() - ALLOCATOR_START_ADDR) as usize;
+        let area = &mut flash[area_start..area_start + SESSION_AREA_SIZE];
+        let (_, used) = last_session_record(area);
+        area[used * SESSION_SLOT_SIZE..(used + 1) * SESSION_SLOT_SIZE]
+            .copy_from_slice(&record.to_bytes());
+    }
+
+    #[test]
+    fn test_recovery_keep_resumable_on_start() {
+        const BLOCK_MAX_LEVEL: u16 = TREE_MAX_LEVEL as u16;
+        let mut flash_content: [u8; FLASH_SIZE] = [0xFF; FLASH_SIZE];
+        let mut shadow_copy: &mut [u8];
+        unsafe {
+            let ptr = flash_content.as_mut_ptr();
+            shadow_copy = core::slice::from_raw_parts_mut(ptr, FLASH_SIZE);
+        }
+        let mut flash = Flash::<FLASH_START_ADDR, FLASH_END_ADDR, BLOCK_SIZE, BLOCK_MAX_LEVEL, ALLOCATOR_SIZE, SWAP_PAGE_NUM, FLAG_BYTES>::new(
+            &FLASH_PAGES,
+            &mut flash_content,
+        );
+
+        // Unfinalized components: one with a session, one without any session and
+        // one with a second, stale, session. Then a session left behind by a
+        // component that was installed in the meanwhile.
+        let mut initial_allocator = init_allocator(&mut flash);
+        let mut allocate = |block_type: BlockType| {
+            initial_allocator
+                .allocate(BLOCK_SIZE as u32 - flash::HEADER_SIZE as u32, block_type)
+                .unwrap()
+        };
+        let resumable = allocate(BlockType::COMPONENT);
+        let resumable_session = allocate(BlockType::SESSION);
+        let _no_session = allocate(BlockType::COMPONENT);
+        let stale = allocate(BlockType::COMPONENT);
+        let stale_session = allocate(BlockType::SESSION);
+        let installed = allocate(BlockType::COMPONENT);
+        let installed_session = allocate(BlockType::SESSION);
+        drop(initial_allocator);
+        utils::finalize_block::<FLASH_START_ADDR, TREE_MAX_LEVEL>(&mut flash, installed).unwrap();
+        let mut record = SessionRecord {
+            session_id: 0xCAFE_0001,
+            component_block_base_address: resumable.get_base_address(),
+            committed_offset: 0x100,
+            applied_relocations: 3,
+            validation_checksum: 0x1234_5678,
+            new_checksum: 0x8765_4321,
+            delta_block_base_address: 0,
+            delta_sram_base_address: 0,
+            delta_patch_size: 0,
+        };
+        write_session_record(&mut shadow_copy, &resumable_session, &record);
+        record.committed_offset = 0x200;
+        write_session_record(&mut shadow_copy, &resumable_session, &record);
+        record.component_block_base_address = stale.get_base_address();
+        write_session_record(&mut shadow_copy, &stale_session, &record);
+        record.component_block_base_address = installed.get_base_address();
+        write_session_record(&mut shadow_copy, &installed_session, &record);
+
+        // Simulate the boot
+        FlashAllocatorImpl::<
+            ALLOCATOR_START_ADDR,
+            ALLOCATOR_END_ADDR,
+            ALLOCATOR_START_SCAN_ADDR,
+            BLOCK_SIZE,
+            NUM_BLOCKS,
+            TREE_MAX_LEVEL,
+            NUM_NODES
+        >::analyze_storage(&mut flash, true);
+
+        // Only the first session is left with its component, and the installed one
+        let mut iterator = FlashWalkerImpl::<
+            ALLOCATOR_START_ADDR,
+            ALLOCATOR_END_ADDR,
+            ALLOCATOR_START_SCAN_ADDR,
+            TREE_MAX_LEVEL
+        >::new(&mut flash);
+        assert_eq!(iterator.next(), Some(resumable));
+        assert_eq!(iterator.next(), Some(resumable_session));
+        assert_eq!(iterator.next().map(|b| b.get_nominal_base_address()), Some(installed.get_nominal_base_address()));
+        assert_eq!(iterator.next(), None);
+        drop(iterator);
+        let area_start = (resumable_session.get_base_address() - ALLOCATOR_START_ADDR) as usize;
+        let (last, used) =
+            last_session_record(&shadow_copy[area_start..area_start + SESSION_AREA_SIZE]);
+        assert_eq!(used, 2);
+        assert_eq!(last.unwrap().committed_offset, 0x200);
+    }
 }
 
 fn main() {}
@@ -0,0 +1,267 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+#![no_std]
+
+//! Ownership of the GPIO pins and routing of the EXTI lines. Ports are given as
+//! their index (A = 0), pins as a mask of the port, bit n for pin n.
+
+/**
+ * Constants
+ */
+pub const PINS_PER_PORT: usize = 16;
+/// Ports A to I, the board may not have all of them
+pub const PORTS: usize = 9;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum PinError {
+    /// Empty mask, or beyond the pins of a port
+    InvalidPin,
+    PinInUse,
+    PinNotOwned,
+    /// The line is routed to the same pin of another port
+    LineInUse,
+}
+
+/// Subscriber of an EXTI line, with the port of the line
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Route<S> {
+    pub port: usize,
+    pub subscriber: S,
+}
+
+/**
+ * Pins
+ */
+pub struct Pins<S: Copy> {
+    /// Component ID of the owner of each pin
+    owners: [[Option<u16>; PINS_PER_PORT]; PORTS],
+    /// The line n is shared by the pins n
+    lines: [Option<Route<S>>; PINS_PER_PORT],
+}
+
+impl<S: Copy> Default for Pins<S> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<S: Copy> Pins<S> {
+    pub fn new() -> Self {
+        Self {
+            owners: [[None; PINS_PER_PORT]; PORTS],
+            lines: [None; PINS_PER_PORT],
+        }
+    }
+
+    /// Takes the pins, none of them can be owned by another component
+    pub fn claim(&mut self, owner: u16, port: usize, pins: u32) -> Result<(), PinError> {
+        let pins = check_mask(pins)?;
+        let owners = &mut self.owners[port];
+        for (pin, current) in owners.iter().enumerate() {
+            if pins & (1 << pin) != 0 && matches!(current, Some(o) if *o != owner) {
+                return Err(PinError::PinInUse);
+            }
+        }
+        for (pin, current) in owners.iter_mut().enumerate() {
+            if pins & (1 << pin) != 0 {
+                *current = Some(owner);
+            }
+        }
+        Ok(())
+    }
+
+    /// Pins of the mask, all of them owned by the component
+    pub fn owned(&self, owner: u16, port: usize, pins: u32) -> Result<u16, PinError> {
+        let pins = check_mask(pins)?;
+        for (pin, current) in self.owners[port].iter().enumerate() {
+            if pins & (1 << pin) != 0 && *current != Some(owner) {
+                return Err(PinError::PinNotOwned);
+            }
+        }
+        Ok(pins)
+    }
+
+    /// Gives the pins back, returns the lines unrouted with them
+    pub fn release(&mut self, owner: u16, port: usize, pins: u32) -> Result<u16, PinError> {
+        let pins = self.owned(owner, port, pins)?;
+        let mut unrouted: u16 = 0;
+        for pin in 0..PINS_PER_PORT {
+            if pins & (1 << pin) == 0 {
+                continue;
+            }
+            if matches!(self.lines[pin], Some(route) if route.port == port) {
+                self.lines[pin] = None;
+                unrouted |= 1 << pin;
+            }
+            self.owners[port][pin] = None;
+        }
+        Ok(unrouted)
+    }
+
+    /// Routes the line of the pin to the subscriber, replacing the previous one
+    /// of the same port. Returns the line.
+    pub fn route(
+        &mut self,
+        owner: u16,
+        port: usize,
+        pin: u32,
+        subscriber: S,
+    ) -> Result<usize, PinError> {
+        let line = self.owned_line(owner, port, pin)?;
+        if matches!(self.lines[line], Some(route) if route.port != port) {
+            return Err(PinError::LineInUse);
+        }
+        self.lines[line] = Some(Route { port, subscriber });
+        Ok(line)
+    }
+
+    /// Disconnects the line of the pin, returns it if it was routed
+    pub fn unroute(
+        &mut self,
+        owner: u16,
+        port: usize,
+        pin: u32,
+    ) -> Result<Option<usize>, PinError> {
+        let line = self.owned_line(owner, port, pin)?;
+        match self.lines[line] {
+            Some(route) if route.port == port => {
+                self.lines[line] = None;
+                Ok(Some(line))
+            }
+            _ => Ok(None),
+        }
+    }
+
+    pub fn route_of(&self, line: usize) -> Option<&Route<S>> {
+        self.lines[line].as_ref()
+    }
+
+    pub fn route_of_mut(&mut self, line: usize) -> Option<&mut Route<S>> {
+        self.lines[line].as_mut()
+    }
+
+    fn owned_line(&self, owner: u16, port: usize, pin: u32) -> Result<usize, PinError> {
+        if pin as usize >= PINS_PER_PORT {
+            return Err(PinError::InvalidPin);
+        }
+        self.owned(owner, port, 1 << pin)?;
+        Ok(pin as usize)
+    }
+}
+
+fn check_mask(pins: u32) -> Result<u16, PinError> {
+    if pins == 0 || pins > 0xFFFF {
+        return Err(PinError::InvalidPin);
+    }
+    Ok(pins as u16)
+}
+
+/* Tests */
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const A: usize = 0;
+    const B: usize = 1;
+    const C: usize = 2;
+
+    #[test]
+    fn claim() {
+        let mut pins: Pins<u32> = Pins::new();
+        pins.claim(5, A, 0b0110).unwrap();
+        // Again by the owner, with more pins
+        pins.claim(5, A, 0b1110).unwrap();
+        assert_eq!(pins.owned(5, A, 0b1110), Ok(0b1110));
+        // The same pins of another port are free
+        pins.claim(6, B, 0b0110).unwrap();
+        assert_eq!(pins.claim(5, A, 0), Err(PinError::InvalidPin));
+        assert_eq!(pins.claim(5, A, 0x1_0000), Err(PinError::InvalidPin));
+    }
+
+    #[test]
+    fn double_claim() {
+        let mut pins: Pins<u32> = Pins::new();
+        pins.claim(5, A, 0b0110).unwrap();
+        // One pin in common is enough, and none of the others is taken
+        assert_eq!(pins.claim(6, A, 0b1100), Err(PinError::PinInUse));
+        assert_eq!(pins.owned(6, A, 0b1000), Err(PinError::PinNotOwned));
+        assert_eq!(pins.owned(5, A, 0b0110), Ok(0b0110));
+        // Free once released
+        pins.release(5, A, 0b0100).unwrap();
+        pins.claim(6, A, 0b1100).unwrap();
+        assert_eq!(pins.owned(5, A, 0b0100), Err(PinError::PinNotOwned));
+        assert_eq!(pins.owned(5, A, 0b0010), Ok(0b0010));
+    }
+
+    #[test]
+    fn release_by_non_owner() {
+        let mut pins: Pins<u32> = Pins::new();
+        pins.claim(5, A, 0b0110).unwrap();
+        pins.route(5, A, 2, 0x10).unwrap();
+        assert_eq!(pins.release(6, A, 0b0100), Err(PinError::PinNotOwned));
+        // Partly owned
+        pins.claim(6, A, 0b1000).unwrap();
+        assert_eq!(pins.release(6, A, 0b1100), Err(PinError::PinNotOwned));
+        assert_eq!(pins.unroute(6, A, 2), Err(PinError::PinNotOwned));
+        // Nothing changed
+        assert_eq!(pins.owned(5, A, 0b0110), Ok(0b0110));
+        assert_eq!(pins.owned(6, A, 0b1000), Ok(0b1000));
+        assert_eq!(
+            pins.route_of(2),
+            Some(&Route {
+                port: A,
+                subscriber: 0x10
+            })
+        );
+    }
+
+    #[test]
+    fn release_unroutes() {
+        let mut pins: Pins<u32> = Pins::new();
+        pins.claim(5, A, 0b0110).unwrap();
+        pins.claim(6, B, 0b1000).unwrap();
+        pins.route(5, A, 1, 0x10).unwrap();
+        pins.route(6, B, 3, 0x20).unwrap();
+        assert_eq!(pins.release(5, A, 0b0110), Ok(0b0010));
+        assert_eq!(pins.route_of(1), None);
+        // The line of the other port is kept
+        assert_eq!(pins.release(6, B, 0b1000), Ok(0b1000));
+        assert_eq!(pins.route_of(3), None);
+    }
+
+    #[test]
+    fn line_conflicts() {
+        let mut pins: Pins<u32> = Pins::new();
+        pins.claim(5, A, 1 << 4).unwrap();
+        pins.claim(6, B, 1 << 4).unwrap();
+        pins.claim(7, C, 1 << 4 | 1 << 5).unwrap();
+        assert_eq!(pins.route(5, A, 4, 0x10), Ok(4));
+        // The pins 4 of the other ports share the line
+        assert_eq!(pins.route(6, B, 4, 0x20), Err(PinError::LineInUse));
+        assert_eq!(pins.route(7, C, 4, 0x40), Err(PinError::LineInUse));
+        // Another line is free
+        assert_eq!(pins.route(7, C, 5, 0x40), Ok(5));
+        // Nothing to unroute on another port
+        assert_eq!(pins.unroute(6, B, 4), Ok(None));
+        assert_eq!(pins.route_of(4).map(|r| r.port), Some(A));
+        // Routed again on the same port, with the new subscriber
+        assert_eq!(pins.route(5, A, 4, 0x80), Ok(4));
+        assert_eq!(pins.route_of(4).map(|r| r.subscriber), Some(0x80));
+        // Until it is unrouted
+        assert_eq!(pins.unroute(5, A, 4), Ok(Some(4)));
+        assert_eq!(pins.route(6, B, 4, 0x20), Ok(4));
+        assert_eq!(pins.route(5, A, 4, 0x10), Err(PinError::LineInUse));
+    }
+
+    #[test]
+    fn route_needs_the_pin() {
+        let mut pins: Pins<u32> = Pins::new();
+        pins.claim(5, A, 1 << 4).unwrap();
+        assert_eq!(pins.route(6, A, 4, 0x10), Err(PinError::PinNotOwned));
+        assert_eq!(pins.route(5, A, 5, 0x10), Err(PinError::PinNotOwned));
+        assert_eq!(pins.route(5, A, 16, 0x10), Err(PinError::InvalidPin));
+        assert_eq!(pins.route_of(4), None);
+    }
+}
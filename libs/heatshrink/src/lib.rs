@@ -0,0 +1,266 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+#![no_std]
+
+/**
+ * Constants
+ */
+/// Size of the window (2^8 bytes), the decoder keeps it in RAM
+pub const WINDOW_BITS: u32 = 8;
+/// Longest match (2^4 bytes)
+pub const LOOKAHEAD_BITS: u32 = 4;
+
+const WINDOW_SIZE: usize = 1 << WINDOW_BITS;
+const MAX_MATCH: usize = 1 << LOOKAHEAD_BITS;
+/// A back-reference costs 1 + 8 + 4 bits, so it is convenient from 2 bytes on
+const MIN_MATCH: usize = 2;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum DecoderError {
+    /// The stream produced more bytes than expected
+    TooMuchOutput,
+}
+
+#[derive(Clone, Copy)]
+enum State {
+    Tag,
+    Literal,
+    Index,
+    Count { index: u16 },
+}
+
+/// Streaming decoder: the input can be split at any point.
+pub struct Decoder {
+    window: [u8; WINDOW_SIZE],
+    head: usize,
+    bits: u32,
+    bit_count: u32,
+    state: State,
+    remaining: usize,
+}
+
+impl Decoder {
+    /// The decoder stops after output_size bytes, the bits left are padding
+    pub fn new(output_size: usize) -> Self {
+        Self {
+            window: [0x00; WINDOW_SIZE],
+            head: 0,
+            bits: 0,
+            bit_count: 0,
+            state: State::Tag,
+            remaining: output_size,
+        }
+    }
+
+    pub fn is_complete(&self) -> bool {
+        self.remaining == 0
+    }
+
+    /// Decodes the input, passing each byte produced to output
+    pub fn sink<F, E>(&mut self, input: &[u8], mut output: F) -> Result<(), E>
+    where
+        F: FnMut(u8) -> Result<(), E>,
+        E: From<DecoderError>,
+    {
+        for byte in input {
+            self.bits = (self.bits << 8) | *byte as u32;
+            self.bit_count += 8;
+            loop {
+                match self.state {
+                    State::Tag => {
+                        let tag = match self.take(1) {
+                            Some(value) => value,
+                            None => break,
+                        };
+                        self.state = match tag {
+                            1 => State::Literal,
+                            _ => State::Index,
+                        };
+                    }
+                    State::Literal => {
+                        let literal = match self.take(8) {
+                            Some(value) => value,
+                            None => break,
+                        };
+                        self.emit(literal as u8, &mut output)?;
+                        self.state = State::Tag;
+                    }
+                    State::Index => {
+                        let index = match self.take(WINDOW_BITS) {
+                            Some(value) => value,
+                            None => break,
+                        };
+                        self.state = State::Count { index: index as u16 };
+                    }
+                    State::Count { index } => {
+                        let count = match self.take(LOOKAHEAD_BITS) {
+                            Some(value) => value,
+                            None => break,
+                        };
+                        // Both are stored minus one
+                        let offset = index as usize + 1;
+                        for _ in 0..count + 1 {
+                            let b = self.window[(self.head + WINDOW_SIZE - offset) % WINDOW_SIZE];
+                            self.emit(b, &mut output)?;
+                        }
+                        self.state = State::Tag;
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn take(&mut self, count: u32) -> Option<u32> {
+        if self.bit_count < count {
+            return None;
+        }
+        self.bit_count -= count;
+        let value = (self.bits >> self.bit_count) & ((1 << count) - 1);
+        self.bits &= (1 << self.bit_count) - 1;
+        Some(value)
+    }
+
+    fn emit<F, E>(&mut self, byte: u8, output: &mut F) -> Result<(), E>
+    where
+        F: FnMut(u8) -> Result<(), E>,
+        E: From<DecoderError>,
+    {
+        if self.remaining == 0 {
+            return Err(DecoderError::TooMuchOutput.into());
+        }
+        self.remaining -= 1;
+        self.window[self.head] = byte;
+        self.head = (self.head + 1) % WINDOW_SIZE;
+        output(byte)
+    }
+}
+
+/// Compresses the data, passing each byte of the stream to output.
+/// Greedy search on the whole window, meant for the host.
+pub fn compress<F: FnMut(u8)>(data: &[u8], mut output: F) {
+    let mut writer = BitWriter {
+        current: 0,
+        used: 0,
+    };
+    let mut pos: usize = 0;
+    while pos < data.len() {
+        // Find the longest match in the window (it can overlap the current position)
+        let mut best_len: usize = 0;
+        let mut best_offset: usize = 0;
+        let max_len = core::cmp::min(MAX_MATCH, data.len() - pos);
+        for offset in 1..=core::cmp::min(WINDOW_SIZE, pos) {
+            let start = pos - offset;
+            let mut len: usize = 0;
+            while len < max_len && data[start + len] == data[pos + len] {
+                len += 1;
+            }
+            if len > best_len {
+                best_len = len;
+                best_offset = offset;
+                if len == max_len {
+                    break;
+                }
+            }
+        }
+        if best_len >= MIN_MATCH {
+            writer.write(0, 1, &mut output);
+            writer.write((best_offset - 1) as u32, WINDOW_BITS, &mut output);
+            writer.write((best_len - 1) as u32, LOOKAHEAD_BITS, &mut output);
+            pos += best_len;
+        } else {
+            writer.write(1, 1, &mut output);
+            writer.write(data[pos] as u32, 8, &mut output);
+            pos += 1;
+        }
+    }
+    writer.flush(&mut output);
+}
+
+struct BitWriter {
+    current: u8,
+    used: u32,
+}
+
+impl BitWriter {
+    /// Writes the count lower bits of value, most significant first
+    fn write<F: FnMut(u8)>(&mut self, value: u32, count: u32, output: &mut F) {
+        for i in (0..count).rev() {
+            self.current = (self.current << 1) | ((value >> i) & 1) as u8;
+            self.used += 1;
+            if self.used == 8 {
+                output(self.current);
+                self.current = 0;
+                self.used = 0;
+            }
+        }
+    }
+    /// Pads the last byte with zeros
+    fn flush<F: FnMut(u8)>(&mut self, output: &mut F) {
+        if self.used > 0 {
+            output(self.current << (8 - self.used));
+            self.current = 0;
+            self.used = 0;
+        }
+    }
+}
+
+/*
+    Tests
+*/
+#[cfg(test)]
+mod test {
+    extern crate std;
+    use super::*;
+    use std::vec::Vec;
+
+    fn roundtrip(data: &[u8], chunk: usize) -> (Vec<u8>, usize) {
+        let mut compressed: Vec<u8> = Vec::new();
+        compress(data, |b| compressed.push(b));
+        let mut decoder = Decoder::new(data.len());
+        let mut output: Vec<u8> = Vec::new();
+        for c in compressed.chunks(chunk) {
+            decoder
+                .sink(c, |b| -> Result<(), DecoderError> {
+                    output.push(b);
+                    Ok(())
+                })
+                .unwrap();
+        }
+        assert!(decoder.is_complete());
+        (output, compressed.len())
+    }
+
+    #[test]
+    fn repetitive_data() {
+        let data: Vec<u8> = (0..4096u32).map(|x| (x % 24) as u8).collect();
+        let (output, compressed_size) = roundtrip(&data, 64);
+        assert_eq!(output, data);
+        assert!(compressed_size < data.len() / 4);
+    }
+
+    #[test]
+    fn random_data_split_anywhere() {
+        let mut seed: u32 = 0x1234_5678;
+        let data: Vec<u8> = (0..2000)
+            .map(|_| {
+                seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12345);
+                (seed >> 16) as u8 & 0x0F
+            })
+            .collect();
+        for chunk in [1, 3, 64] {
+            assert_eq!(roundtrip(&data, chunk).0, data);
+        }
+    }
+
+    #[test]
+    fn too_much_output() {
+        let mut compressed: Vec<u8> = Vec::new();
+        compress(&[0xAA; 32], |b| compressed.push(b));
+        let mut decoder = Decoder::new(16);
+        let result = decoder.sink(&compressed, |_| -> Result<(), DecoderError> { Ok(()) });
+        assert_eq!(result, Err(DecoderError::TooMuchOutput));
+    }
+}
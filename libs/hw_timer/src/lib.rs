@@ -0,0 +1,206 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+#![no_std]
+
+//! Settings of the timers of the STM32F3 and STM32L4: prescaler and reload of
+//! a 16 bit counter for a period or a frequency, compare value of a duty cycle
+//! and period of a captured signal.
+
+/**
+ * Constants
+ */
+/// Duty cycle of 100%, in hundredths of a percent
+pub const DUTY_MAX: u16 = 10_000;
+/// Highest reload, so that reload + 1 (always high) fits the compare register
+pub const MAX_RELOAD: u32 = 0xFFFE;
+const MAX_PRESCALER: u64 = 0xFFFF;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TimingError {
+    /// Less than two clock cycles
+    TooShort,
+    /// More than the prescaler can divide
+    TooLong,
+    /// Above DUTY_MAX
+    BadDuty,
+}
+
+/// Values of PSC and ARR, the counter overflows every
+/// (prescaler + 1) * (reload + 1) clock cycles
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Timing {
+    pub prescaler: u32,
+    pub reload: u32,
+}
+
+impl Timing {
+    /// Timing closest to the clock cycles given, with the finest resolution
+    pub fn for_cycles(cycles: u64) -> Result<Self, TimingError> {
+        if cycles < 2 {
+            return Err(TimingError::TooShort);
+        }
+        let prescaler = (cycles - 1) / (MAX_RELOAD as u64 + 1);
+        if prescaler > MAX_PRESCALER {
+            return Err(TimingError::TooLong);
+        }
+        let reload = cycles / (prescaler + 1) - 1;
+        Ok(Self {
+            prescaler: prescaler as u32,
+            reload: reload as u32,
+        })
+    }
+
+    /// Overflow every period, in microseconds
+    pub fn for_period(clock_hz: u32, period_us: u32) -> Result<Self, TimingError> {
+        Self::for_cycles(clock_hz as u64 * period_us as u64 / 1_000_000)
+    }
+
+    /// Overflow at the frequency, in Hz
+    pub fn for_frequency(clock_hz: u32, frequency: u32) -> Result<Self, TimingError> {
+        if frequency == 0 {
+            return Err(TimingError::TooLong);
+        }
+        Self::for_cycles(clock_hz as u64 / frequency as u64)
+    }
+
+    /// Frequency actually obtained, in Hz
+    pub fn frequency(&self, clock_hz: u32) -> u32 {
+        let cycles = (self.prescaler as u64 + 1) * (self.reload as u64 + 1);
+        (clock_hz as u64 / cycles) as u32
+    }
+
+    /// Compare value of PWM mode 1 for the duty cycle: the output is high while
+    /// the counter is below it
+    pub fn compare(&self, duty: u16) -> Result<u32, TimingError> {
+        if duty > DUTY_MAX {
+            return Err(TimingError::BadDuty);
+        }
+        Ok(((self.reload as u64 + 1) * duty as u64 / DUTY_MAX as u64) as u32)
+    }
+}
+
+/// Period of a signal from its rising edges, captured on a free running 32 bit
+/// counter
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Capture {
+    last: Option<u32>,
+    period: u32,
+    edges: u32,
+}
+
+impl Capture {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn on_edge(&mut self, value: u32) {
+        if let Some(last) = self.last {
+            // Right across the end of the counter too
+            self.period = value.wrapping_sub(last);
+        }
+        self.last = Some(value);
+        self.edges = self.edges.saturating_add(1);
+    }
+
+    /// Counter ticks between the last two edges, once there are two
+    pub fn period(&self) -> Option<u32> {
+        if self.edges < 2 {
+            return None;
+        }
+        Some(self.period)
+    }
+
+    /// Edges seen since the start
+    pub fn edges(&self) -> u32 {
+        self.edges
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_period() {
+        // 1 ms at 72 MHz, with 1 us of resolution or better
+        let timing = Timing::for_period(72_000_000, 1000).unwrap();
+        assert_eq!(timing.prescaler, 1);
+        assert_eq!(timing.reload, 35_999);
+        // Short enough for the prescaler to be unused
+        let timing = Timing::for_period(80_000_000, 100).unwrap();
+        assert_eq!(
+            timing,
+            Timing {
+                prescaler: 0,
+                reload: 7999
+            }
+        );
+        // Long periods
+        let timing = Timing::for_period(80_000_000, 10_000_000).unwrap();
+        assert_eq!(timing.prescaler, 12_207);
+        let cycles = (timing.prescaler as u64 + 1) * (timing.reload as u64 + 1);
+        assert!(cycles <= 800_000_000 && 800_000_000 - cycles < 12_208);
+        assert_eq!(
+            Timing::for_period(80_000_000, 60_000_000),
+            Err(TimingError::TooLong)
+        );
+        assert_eq!(
+            Timing::for_period(80_000_000, 0),
+            Err(TimingError::TooShort)
+        );
+    }
+
+    #[test]
+    fn test_frequency() {
+        let timing = Timing::for_frequency(72_000_000, 1000).unwrap();
+        assert_eq!(timing.frequency(72_000_000), 1000);
+        let timing = Timing::for_frequency(80_000_000, 20_000).unwrap();
+        assert_eq!(
+            timing,
+            Timing {
+                prescaler: 0,
+                reload: 3999
+            }
+        );
+        assert_eq!(timing.frequency(80_000_000), 20_000);
+        // Every cycle is not enough
+        assert_eq!(
+            Timing::for_frequency(80_000_000, 80_000_000),
+            Err(TimingError::TooShort)
+        );
+        assert_eq!(
+            Timing::for_frequency(80_000_000, 0),
+            Err(TimingError::TooLong)
+        );
+    }
+
+    #[test]
+    fn test_compare() {
+        let timing = Timing::for_frequency(80_000_000, 20_000).unwrap();
+        assert_eq!(timing.compare(0), Ok(0));
+        assert_eq!(timing.compare(2500), Ok(1000));
+        assert_eq!(timing.compare(DUTY_MAX), Ok(4000));
+        assert_eq!(timing.compare(DUTY_MAX + 1), Err(TimingError::BadDuty));
+        // Always high even with the longest reload
+        let timing = Timing::for_cycles(MAX_RELOAD as u64 + 1).unwrap();
+        assert_eq!(timing.reload, MAX_RELOAD);
+        assert_eq!(timing.compare(DUTY_MAX), Ok(0xFFFF));
+    }
+
+    #[test]
+    fn test_capture() {
+        let mut capture = Capture::new();
+        assert_eq!(capture.period(), None);
+        capture.on_edge(100);
+        assert_eq!(capture.period(), None);
+        capture.on_edge(1100);
+        assert_eq!(capture.period(), Some(1000));
+        // Across the end of the counter
+        capture.on_edge(u32::MAX - 99);
+        capture.on_edge(400);
+        assert_eq!(capture.period(), Some(500));
+        assert_eq!(capture.edges(), 4);
+    }
+}
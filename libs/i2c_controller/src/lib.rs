@@ -0,0 +1,528 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+#![no_std]
+
+//! Master side of the I2C peripheral of the STM32F3 and STM32L4 (the same IP
+//! on both), driven one interrupt at a time. A transaction writes, reads or
+//! writes then reads with a repeated start, the bytes are moved by the
+//! interrupts or by DMA when a channel is available.
+
+#[cfg(test)]
+mod mock;
+
+/**
+ * Constants
+ */
+/// Longest transfer in each direction
+pub const MAX_TRANSFER: usize = 64;
+
+// CR1
+const CR1_PE: u32 = 1 << 0;
+const CR1_TXIE: u32 = 1 << 1;
+const CR1_RXIE: u32 = 1 << 2;
+const CR1_NACKIE: u32 = 1 << 4;
+const CR1_STOPIE: u32 = 1 << 5;
+const CR1_TCIE: u32 = 1 << 6;
+const CR1_ERRIE: u32 = 1 << 7;
+const CR1_TXDMAEN: u32 = 1 << 14;
+const CR1_RXDMAEN: u32 = 1 << 15;
+// CR2
+const CR2_RD_WRN: u32 = 1 << 10;
+const CR2_START: u32 = 1 << 13;
+const CR2_STOP: u32 = 1 << 14;
+const CR2_NBYTES_SHIFT: u32 = 16;
+const CR2_AUTOEND: u32 = 1 << 25;
+// ISR, ICR has the clear bits at the same positions
+const ISR_TXIS: u32 = 1 << 1;
+const ISR_RXNE: u32 = 1 << 2;
+const ISR_NACKF: u32 = 1 << 4;
+const ISR_STOPF: u32 = 1 << 5;
+const ISR_TC: u32 = 1 << 6;
+const ISR_BERR: u32 = 1 << 8;
+const ISR_ARLO: u32 = 1 << 9;
+const ISR_OVR: u32 = 1 << 10;
+const ISR_ERRORS: u32 = ISR_BERR | ISR_ARLO | ISR_OVR;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum I2cError {
+    /// More than MAX_TRANSFER bytes, or nothing to do
+    BadLength,
+    BadAddress,
+    /// The address or a byte was not acknowledged
+    Nack,
+    BusError,
+    ArbitrationLost,
+    Overrun,
+}
+
+/**
+ * Hardware
+ */
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Register {
+    Cr1,
+    Cr2,
+    Isr,
+    Icr,
+    Txdr,
+    Rxdr,
+}
+
+/// Register block of the peripheral, the other registers (TIMINGR, see
+/// `timing`) are set up by the owner before the first transaction
+pub trait Registers {
+    fn read(&mut self, register: Register) -> u32;
+    fn write(&mut self, register: Register, value: u32);
+}
+
+/// Channels moving the bytes between the buffers and TXDR/RXDR. A buffer stays
+/// in place until stop is called, as it is the one of the controller.
+pub trait Dma {
+    /// Starts feeding TXDR, false when there is no channel
+    fn transmit(&mut self, source: *const u8, len: usize) -> bool;
+    /// Starts draining RXDR, false when there is no channel
+    fn receive(&mut self, destination: *mut u8, len: usize) -> bool;
+    fn stop(&mut self);
+}
+
+/// For the boards without DMA, every byte goes through the interrupts
+pub struct NoDma;
+
+impl Dma for NoDma {
+    fn transmit(&mut self, _source: *const u8, _len: usize) -> bool {
+        false
+    }
+    fn receive(&mut self, _destination: *mut u8, _len: usize) -> bool {
+        false
+    }
+    fn stop(&mut self) {}
+}
+
+/**
+ * Timing
+ */
+/// Limits of the bus, in ns: SCL low and high, data setup, rise and fall
+struct BusTimes {
+    low: u32,
+    high: u32,
+    data_setup: u32,
+    rise: u32,
+    fall: u32,
+}
+
+const STANDARD_MODE: BusTimes = BusTimes {
+    low: 4700,
+    high: 4000,
+    data_setup: 250,
+    rise: 1000,
+    fall: 300,
+};
+
+const FAST_MODE: BusTimes = BusTimes {
+    low: 1300,
+    high: 600,
+    data_setup: 100,
+    rise: 300,
+    fall: 300,
+};
+
+/// Periods of the prescaled clock covering at least ns
+fn periods(ns: u32, clock: u32, presc: u32) -> u32 {
+    let num = ns as u64 * clock as u64;
+    let den = 1_000_000_000u64 * (presc as u64 + 1);
+    let periods = num / den;
+    match periods * den < num {
+        true => periods as u32 + 1,
+        false => periods as u32,
+    }
+}
+
+/// TIMINGR for a bus of frequency Hz (up to 400 kHz) from the kernel clock of
+/// the peripheral. The finest prescaler that fits the fields is taken, and the
+/// SCL period is never shorter than the one of the frequency. None when the
+/// clock is too slow or too fast for the bus.
+pub fn timing(clock: u32, frequency: u32) -> Option<u32> {
+    let times = match frequency {
+        0 => return None,
+        1..=100_000 => STANDARD_MODE,
+        100_001..=400_000 => FAST_MODE,
+        _ => return None,
+    };
+    for presc in 0..16u32 {
+        let mut low = periods(times.low, clock, presc);
+        let mut high = periods(times.high, clock, presc);
+        // Stretch both halves up to the period of the frequency
+        let period = periods(1_000_000_000 / frequency, clock, presc);
+        if period > low + high {
+            let extra = period - low - high;
+            low += extra - extra / 2;
+            high += extra / 2;
+        }
+        // SCLDEL and SDADEL are at least one period
+        let scldel = periods(times.rise + times.data_setup, clock, presc).max(1);
+        let sdadel = periods(times.fall, clock, presc);
+        if low > 256 || high > 256 || scldel > 16 || sdadel > 15 {
+            continue;
+        }
+        if low < 2 || high < 2 {
+            return None;
+        }
+        return Some(presc << 28 | (scldel - 1) << 20 | sdadel << 16 | (high - 1) << 8 | (low - 1));
+    }
+    None
+}
+
+/**
+ * Controller
+ */
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum State {
+    Idle,
+    Writing,
+    Reading,
+    /// Waiting for the STOP after an error
+    Stopping,
+}
+
+/// Outcome of an interrupt
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Poll {
+    Pending,
+    Done(Result<(), I2cError>),
+}
+
+pub struct Controller<R: Registers, D: Dma> {
+    registers: R,
+    dma: D,
+    state: State,
+    address: u8,
+    tx: [u8; MAX_TRANSFER],
+    tx_len: usize,
+    tx_pos: usize,
+    rx: [u8; MAX_TRANSFER],
+    rx_len: usize,
+    rx_pos: usize,
+    /// The current phase goes through DMA
+    dma_phase: bool,
+    error: Option<I2cError>,
+}
+
+impl<R: Registers, D: Dma> Controller<R, D> {
+    pub fn new(registers: R, dma: D) -> Self {
+        Self {
+            registers,
+            dma,
+            state: State::Idle,
+            address: 0,
+            tx: [0; MAX_TRANSFER],
+            tx_len: 0,
+            tx_pos: 0,
+            rx: [0; MAX_TRANSFER],
+            rx_len: 0,
+            rx_pos: 0,
+            dma_phase: false,
+            error: None,
+        }
+    }
+
+    /// Where the bytes to write go, before start
+    pub fn tx_buffer(&mut self) -> &mut [u8] {
+        &mut self.tx
+    }
+
+    /// Bytes read by the last transaction
+    pub fn received(&self) -> &[u8] {
+        &self.rx[0..self.rx_pos]
+    }
+
+    pub fn registers(&mut self) -> &mut R {
+        &mut self.registers
+    }
+
+    /// Writes the first write_len bytes of the tx buffer, then reads read_len
+    /// bytes after a repeated start. Either of them can be zero.
+    pub fn start(
+        &mut self,
+        address: u8,
+        write_len: usize,
+        read_len: usize,
+    ) -> Result<(), I2cError> {
+        if address > 0x7F {
+            return Err(I2cError::BadAddress);
+        }
+        if write_len > MAX_TRANSFER || read_len > MAX_TRANSFER || write_len + read_len == 0 {
+            return Err(I2cError::BadLength);
+        }
+        self.address = address;
+        self.tx_len = write_len;
+        self.tx_pos = 0;
+        self.rx_len = read_len;
+        self.rx_pos = 0;
+        self.error = None;
+        self.begin_phase(write_len == 0);
+        Ok(())
+    }
+
+    /// Handles the flags raised, to be called on each interrupt
+    pub fn on_event(&mut self) -> Poll {
+        if self.state == State::Idle {
+            return Poll::Pending;
+        }
+        let isr = self.registers.read(Register::Isr);
+        if isr & ISR_ERRORS != 0 {
+            self.registers.write(Register::Icr, isr & ISR_ERRORS);
+            // The bus is released by the hardware, no STOP follows
+            let error = if isr & ISR_BERR != 0 {
+                I2cError::BusError
+            } else if isr & ISR_ARLO != 0 {
+                I2cError::ArbitrationLost
+            } else {
+                I2cError::Overrun
+            };
+            return self.finish(Err(error));
+        }
+        if isr & ISR_NACKF != 0 {
+            self.registers.write(Register::Icr, ISR_NACKF);
+            self.error = Some(I2cError::Nack);
+            self.registers.write(Register::Cr2, CR2_STOP);
+            self.state = State::Stopping;
+        }
+        if !self.dma_phase {
+            if isr & ISR_TXIS != 0 && self.state == State::Writing && self.tx_pos < self.tx_len {
+                self.registers
+                    .write(Register::Txdr, self.tx[self.tx_pos] as u32);
+                self.tx_pos += 1;
+            }
+            if isr & ISR_RXNE != 0 && self.state == State::Reading {
+                let byte = self.registers.read(Register::Rxdr) as u8;
+                if self.rx_pos < self.rx_len {
+                    self.rx[self.rx_pos] = byte;
+                    self.rx_pos += 1;
+                }
+            }
+        }
+        if isr & ISR_TC != 0 {
+            // Only without AUTOEND, at the end of the write with a read to go
+            if self.state == State::Writing && self.rx_len > 0 {
+                self.dma.stop();
+                self.tx_pos = self.tx_len;
+                self.begin_phase(true);
+            } else if self.state != State::Stopping {
+                self.registers.write(Register::Cr2, CR2_STOP);
+                self.state = State::Stopping;
+            }
+        }
+        if isr & ISR_STOPF != 0 {
+            self.registers.write(Register::Icr, ISR_STOPF);
+            let result = match self.error {
+                Some(error) => Err(error),
+                None => {
+                    if self.dma_phase {
+                        // The last phase went through DMA
+                        match self.state {
+                            State::Reading => self.rx_pos = self.rx_len,
+                            _ => self.tx_pos = self.tx_len,
+                        }
+                    }
+                    Ok(())
+                }
+            };
+            return self.finish(result);
+        }
+        Poll::Pending
+    }
+
+    /// Gives up the transaction, when its interrupts do not come
+    pub fn abort(&mut self) {
+        if self.state != State::Idle {
+            self.registers.write(Register::Cr2, CR2_STOP);
+            self.finish(Ok(()));
+        }
+    }
+
+    /// Sends START for the write or the read, the last phase ends with STOP
+    fn begin_phase(&mut self, read: bool) {
+        let len = if read { self.rx_len } else { self.tx_len };
+        self.dma_phase = if read {
+            self.dma.receive(self.rx.as_mut_ptr(), len)
+        } else {
+            self.dma.transmit(self.tx.as_ptr(), len)
+        };
+        let mut cr1 = CR1_PE | CR1_NACKIE | CR1_STOPIE | CR1_TCIE | CR1_ERRIE;
+        cr1 |= match (read, self.dma_phase) {
+            (true, true) => CR1_RXDMAEN,
+            (true, false) => CR1_RXIE,
+            (false, true) => CR1_TXDMAEN,
+            (false, false) => CR1_TXIE,
+        };
+        self.registers.write(Register::Cr1, cr1);
+        let mut cr2 = (self.address as u32) << 1 | (len as u32) << CR2_NBYTES_SHIFT | CR2_START;
+        if read {
+            cr2 |= CR2_RD_WRN;
+        }
+        if read || self.rx_len == 0 {
+            cr2 |= CR2_AUTOEND;
+        }
+        self.registers.write(Register::Cr2, cr2);
+        self.state = if read { State::Reading } else { State::Writing };
+    }
+
+    fn finish(&mut self, result: Result<(), I2cError>) -> Poll {
+        self.dma.stop();
+        // Interrupts and DMA requests off, the peripheral stays enabled
+        self.registers.write(Register::Cr1, CR1_PE);
+        self.state = State::Idle;
+        self.dma_phase = false;
+        if result.is_err() {
+            self.rx_pos = 0;
+        }
+        Poll::Done(result)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::mock::*;
+    use super::*;
+
+    const SENSOR: u8 = 0x48;
+
+    fn run<D: Dma>(controller: &mut Controller<MockI2c, D>) -> Result<(), I2cError> {
+        for _ in 0..1000 {
+            if let Poll::Done(result) = controller.on_event() {
+                return result;
+            }
+        }
+        panic!("The transaction never ends");
+    }
+
+    fn controller() -> Controller<MockI2c, NoDma> {
+        let mut device = MockI2c::new(SENSOR);
+        device.memory[0x10..0x14].copy_from_slice(&[0xDE, 0xAD, 0xBE, 0xEF]);
+        Controller::new(device, NoDma)
+    }
+
+    #[test]
+    fn write() {
+        let mut controller = controller();
+        controller.tx_buffer()[0..3].copy_from_slice(&[0x20, 0x01, 0x02]);
+        controller.start(SENSOR, 3, 0).unwrap();
+        run(&mut controller).unwrap();
+        assert_eq!(&controller.registers().memory[0x20..0x22], &[0x01, 0x02]);
+        assert_eq!(controller.registers().starts, 1);
+        assert_eq!(controller.received(), &[]);
+    }
+
+    #[test]
+    fn read() {
+        let mut controller = controller();
+        controller.registers().pointer = 0x10;
+        controller.start(SENSOR, 0, 2).unwrap();
+        run(&mut controller).unwrap();
+        assert_eq!(controller.received(), &[0xDE, 0xAD]);
+    }
+
+    #[test]
+    fn write_read() {
+        let mut controller = controller();
+        controller.tx_buffer()[0] = 0x11;
+        controller.start(SENSOR, 1, 3).unwrap();
+        run(&mut controller).unwrap();
+        assert_eq!(controller.received(), &[0xAD, 0xBE, 0xEF]);
+        // Repeated start, a single STOP at the end
+        assert_eq!(controller.registers().starts, 2);
+        assert_eq!(controller.registers().stops, 1);
+    }
+
+    #[test]
+    fn nack() {
+        let mut controller = controller();
+        controller.tx_buffer()[0] = 0x10;
+        controller.start(0x21, 1, 2).unwrap();
+        assert_eq!(run(&mut controller), Err(I2cError::Nack));
+        assert_eq!(controller.received(), &[]);
+        // The bus is free for the next one
+        controller.start(SENSOR, 1, 1).unwrap();
+        run(&mut controller).unwrap();
+        assert_eq!(controller.received(), &[0xDE]);
+    }
+
+    #[test]
+    fn bus_error() {
+        let mut controller = controller();
+        controller.tx_buffer()[0] = 0x10;
+        controller.start(SENSOR, 1, 0).unwrap();
+        controller.registers().isr |= ISR_BERR;
+        assert_eq!(run(&mut controller), Err(I2cError::BusError));
+        assert_eq!(controller.registers().isr & ISR_BERR, 0);
+    }
+
+    #[test]
+    fn bad_arguments() {
+        let mut controller = controller();
+        assert_eq!(controller.start(0x80, 1, 0), Err(I2cError::BadAddress));
+        assert_eq!(controller.start(SENSOR, 0, 0), Err(I2cError::BadLength));
+        assert_eq!(
+            controller.start(SENSOR, MAX_TRANSFER + 1, 0),
+            Err(I2cError::BadLength)
+        );
+    }
+
+    #[test]
+    fn dma() {
+        let (device, dma) = MockI2c::with_dma(SENSOR);
+        let mut controller = Controller::new(device, dma);
+        controller.registers().memory[0x30..0x32].copy_from_slice(&[0x12, 0x34]);
+        controller.tx_buffer()[0] = 0x30;
+        controller.start(SENSOR, 1, 2).unwrap();
+        run(&mut controller).unwrap();
+        assert_eq!(controller.received(), &[0x12, 0x34]);
+        // No byte through the interrupts
+        assert_eq!(controller.registers().byte_interrupts, 0);
+    }
+
+    /// SCL low and high and SCLDEL, in ns, of a TIMINGR
+    fn decode(clock: u32, timing: u32) -> (f64, f64, f64) {
+        let presc = (timing >> 28) as f64 + 1.0;
+        let ns = |field: u32| (field as f64 + 1.0) * presc * 1e9 / clock as f64;
+        (
+            ns(timing & 0xFF),
+            ns((timing >> 8) & 0xFF),
+            ns((timing >> 20) & 0xF),
+        )
+    }
+
+    #[test]
+    fn timing_standard_mode() {
+        assert_eq!(timing(8_000_000, 100_000), Some(0x0093_242A));
+        for clock in [
+            8_000_000, 16_000_000, 48_000_000, 64_000_000, 72_000_000, 80_000_000,
+        ] {
+            let value = timing(clock, 100_000).unwrap();
+            let (low, high, scldel) = decode(clock, value);
+            assert!(low >= 4700.0 && high >= 4000.0, "{:#x} at {}", value, clock);
+            // Not faster than 100 kHz
+            assert!(low + high >= 10_000.0);
+            assert!(scldel >= 1250.0);
+        }
+    }
+
+    #[test]
+    fn timing_fast_mode() {
+        let value = timing(80_000_000, 400_000).unwrap();
+        let (low, high, scldel) = decode(80_000_000, value);
+        assert!(low >= 1300.0 && high >= 600.0 && low + high >= 2500.0);
+        assert!(scldel >= 400.0);
+    }
+
+    #[test]
+    fn timing_out_of_range() {
+        assert_eq!(timing(8_000_000, 0), None);
+        assert_eq!(timing(8_000_000, 1_000_000), None);
+        // Too slow for the high period, then too fast for the prescaler
+        assert_eq!(timing(200_000, 100_000), None);
+        assert_eq!(timing(1_000_000_000, 100_000), None);
+    }
+}
@@ -0,0 +1,217 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+extern crate std;
+
+use std::{cell::RefCell, rc::Rc};
+
+use crate::*;
+
+/// Buffers handed to the DMA channels, shared with the bus that uses them
+#[derive(Default)]
+pub struct Channels {
+    tx: Option<(*const u8, usize)>,
+    rx: Option<(*mut u8, usize)>,
+}
+
+pub struct MockDma {
+    channels: Rc<RefCell<Channels>>,
+}
+
+impl Dma for MockDma {
+    fn transmit(&mut self, source: *const u8, len: usize) -> bool {
+        self.channels.borrow_mut().tx = Some((source, len));
+        true
+    }
+    fn receive(&mut self, destination: *mut u8, len: usize) -> bool {
+        self.channels.borrow_mut().rx = Some((destination, len));
+        true
+    }
+    fn stop(&mut self) {
+        let mut channels = self.channels.borrow_mut();
+        channels.tx = None;
+        channels.rx = None;
+    }
+}
+
+/// Register block with a device on the bus: a memory with an internal pointer,
+/// set by the first byte written after START, as most sensors have
+pub struct MockI2c {
+    pub address: u8,
+    pub memory: [u8; 256],
+    pub pointer: u8,
+    pub isr: u32,
+    pub starts: usize,
+    pub stops: usize,
+    /// Bytes moved through TXDR and RXDR by the controller
+    pub byte_interrupts: usize,
+    cr1: u32,
+    cr2: u32,
+    rxdr: u8,
+    left: usize,
+    reading: bool,
+    autoend: bool,
+    first_write: bool,
+    channels: Option<Rc<RefCell<Channels>>>,
+}
+
+impl MockI2c {
+    pub fn new(address: u8) -> Self {
+        Self {
+            address,
+            memory: [0; 256],
+            pointer: 0,
+            isr: 0,
+            starts: 0,
+            stops: 0,
+            byte_interrupts: 0,
+            cr1: 0,
+            cr2: 0,
+            rxdr: 0,
+            left: 0,
+            reading: false,
+            autoend: false,
+            first_write: false,
+            channels: None,
+        }
+    }
+
+    /// The bus, with the channels that feed it as soon as START is sent
+    pub fn with_dma(address: u8) -> (Self, MockDma) {
+        let channels = Rc::new(RefCell::new(Channels::default()));
+        let mut bus = Self::new(address);
+        bus.channels = Some(channels.clone());
+        (bus, MockDma { channels })
+    }
+
+    fn start(&mut self, cr2: u32) {
+        self.starts += 1;
+        self.isr &= !ISR_TC;
+        self.autoend = cr2 & CR2_AUTOEND != 0;
+        if (cr2 >> 1) & 0x7F != self.address as u32 {
+            self.isr |= ISR_NACKF;
+            if self.autoend {
+                self.stop();
+            }
+            return;
+        }
+        self.left = ((cr2 >> CR2_NBYTES_SHIFT) & 0xFF) as usize;
+        self.reading = cr2 & CR2_RD_WRN != 0;
+        if self.reading {
+            if self.cr1 & CR1_RXDMAEN != 0 {
+                let (destination, len) = self.dma_rx();
+                for i in 0..core::cmp::min(len, self.left) {
+                    unsafe { *destination.add(i) = self.next_byte() };
+                }
+                self.left = 0;
+                self.end_phase();
+            } else {
+                self.rxdr = self.next_byte();
+                self.isr |= ISR_RXNE;
+            }
+        } else {
+            self.first_write = true;
+            if self.cr1 & CR1_TXDMAEN != 0 {
+                let (source, len) = self.dma_tx();
+                for i in 0..core::cmp::min(len, self.left) {
+                    self.write_byte(unsafe { *source.add(i) });
+                }
+                self.left = 0;
+                self.end_phase();
+            } else {
+                self.isr |= ISR_TXIS;
+            }
+        }
+    }
+
+    fn dma_tx(&self) -> (*const u8, usize) {
+        let channels = self.channels.as_ref().expect("No DMA").borrow();
+        channels.tx.expect("TX channel not started")
+    }
+
+    fn dma_rx(&self) -> (*mut u8, usize) {
+        let channels = self.channels.as_ref().expect("No DMA").borrow();
+        channels.rx.expect("RX channel not started")
+    }
+
+    fn next_byte(&mut self) -> u8 {
+        let byte = self.memory[self.pointer as usize];
+        self.pointer = self.pointer.wrapping_add(1);
+        byte
+    }
+
+    fn write_byte(&mut self, byte: u8) {
+        if self.first_write {
+            self.pointer = byte;
+            self.first_write = false;
+        } else {
+            self.memory[self.pointer as usize] = byte;
+            self.pointer = self.pointer.wrapping_add(1);
+        }
+    }
+
+    fn end_phase(&mut self) {
+        if self.autoend {
+            self.stop();
+        } else {
+            self.isr |= ISR_TC;
+        }
+    }
+
+    fn stop(&mut self) {
+        self.stops += 1;
+        self.isr |= ISR_STOPF;
+    }
+}
+
+impl Registers for MockI2c {
+    fn read(&mut self, register: Register) -> u32 {
+        match register {
+            Register::Cr1 => self.cr1,
+            Register::Cr2 => self.cr2,
+            Register::Isr => self.isr,
+            Register::Rxdr => {
+                self.byte_interrupts += 1;
+                let byte = self.rxdr;
+                self.isr &= !ISR_RXNE;
+                self.left -= 1;
+                if self.left > 0 {
+                    self.rxdr = self.next_byte();
+                    self.isr |= ISR_RXNE;
+                } else {
+                    self.end_phase();
+                }
+                byte as u32
+            }
+            Register::Icr | Register::Txdr => 0,
+        }
+    }
+
+    fn write(&mut self, register: Register, value: u32) {
+        match register {
+            Register::Cr1 => self.cr1 = value,
+            Register::Cr2 => {
+                self.cr2 = value;
+                if value & CR2_STOP != 0 {
+                    self.stop();
+                } else if value & CR2_START != 0 {
+                    self.start(value);
+                }
+            }
+            Register::Icr => self.isr &= !value,
+            Register::Txdr => {
+                self.byte_interrupts += 1;
+                self.write_byte(value as u8);
+                self.isr &= !ISR_TXIS;
+                self.left -= 1;
+                if self.left > 0 {
+                    self.isr |= ISR_TXIS;
+                } else {
+                    self.end_phase();
+                }
+            }
+            Register::Isr | Register::Rxdr => {}
+        }
+    }
+}
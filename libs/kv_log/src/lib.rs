@@ -0,0 +1,715 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+#![no_std]
+
+mod record;
+
+pub use data_block::{Backend, BackendError, WORD_SIZE};
+pub use record::{MAX_KEY_LEN, MAX_VALUE_LEN};
+
+use data_block::{COMMITTED, COMMIT_SIZE};
+use record::{RecordHeader, RecordKind, MAX_RECORD_SIZE, RECORD_HEADER_SIZE};
+
+/**
+ * Constants
+ */
+const BLOCK_MAGIC: u32 = 0x4B56_5331; // KVS1
+/// Magic and generation, then the complete flag
+const BLOCK_HEADER_SIZE: usize = 2 * WORD_SIZE;
+const COMPLETE_FLAG_OFFSET: u32 = WORD_SIZE as u32;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum KvError {
+    KeyNotFound,
+    InvalidKey,
+    ValueTooLarge,
+    StoreFull,
+    EndOfKeys,
+    StorageError,
+}
+
+/// Key found by `next_key`
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct KeyEntry {
+    /// Cursor for the following call
+    pub next_cursor: u32,
+    pub key_len: usize,
+    pub value_len: usize,
+}
+
+/// Change of a key, a value of None deletes it
+#[derive(Clone, Copy)]
+struct Update<'a> {
+    namespace: u16,
+    key: &'a [u8],
+    value: Option<&'a [u8]>,
+}
+
+/**
+ * Key-value store
+ *
+ * Records are appended to a single block: the last one for a key wins. When the
+ * block is full, live records are compacted into a new block following the
+ * write-ahead discipline of the swap procedure of the flash allocator: the new
+ * block is filled, marked complete and finalized, only then the old one is
+ * deallocated. A copy interrupted by a power loss is not finalized, so the storage
+ * erases it at start-up and the old block is still the store; after the
+ * finalization the newest block is the store and the old one is removed on mount.
+ */
+pub struct KvStore<B: Backend> {
+    backend: B,
+    /// Size requested for each block
+    block_size: u32,
+    base_address: u32,
+    size: u32,
+    generation: u32,
+    /// End of the committed records
+    tail: u32,
+}
+
+impl<B: Backend> KvStore<B> {
+    /// Opens the store, creating it on the first start-up
+    pub fn mount(backend: B, block_size: u32) -> Result<Self, KvError> {
+        let mut store = Self {
+            backend,
+            block_size,
+            base_address: 0,
+            size: 0,
+            generation: 0,
+            tail: BLOCK_HEADER_SIZE as u32,
+        };
+        // Search for the newest complete block
+        let mut active: Option<(u32, u32, u32)> = None;
+        let mut n: u32 = 0;
+        while let Some((base_address, size)) = store.data_block(n)? {
+            if let Some((generation, true)) = store.read_block_header(base_address)? {
+                if !matches!(active, Some((_, _, g)) if generation <= g) {
+                    active = Some((base_address, size, generation));
+                }
+            }
+            n += 1;
+        }
+        // The old block of a completed compaction, copies not finalized are already
+        // erased by the storage at start-up
+        store.remove_stale_blocks(active.map(|(base_address, _, _)| base_address))?;
+        match active {
+            Some((base_address, size, generation)) => {
+                store.base_address = base_address;
+                store.size = size;
+                store.generation = generation;
+                store.recover_tail()?;
+            }
+            None => store.create()?,
+        }
+        Ok(store)
+    }
+
+    /// Copies the value in the buffer (truncated if it does not fit) and returns
+    /// its full length
+    pub fn get(&self, namespace: u16, key: &[u8], value: &mut [u8]) -> Result<usize, KvError> {
+        check_key(key)?;
+        let (pos, header) = self.find(namespace, key)?.ok_or(KvError::KeyNotFound)?;
+        let len = core::cmp::min(value.len(), header.value_len);
+        self.read(
+            pos + (RECORD_HEADER_SIZE + header.key_len) as u32,
+            &mut value[0..len],
+        )?;
+        Ok(header.value_len)
+    }
+
+    pub fn set(&mut self, namespace: u16, key: &[u8], value: &[u8]) -> Result<(), KvError> {
+        check_key(key)?;
+        if value.len() > MAX_VALUE_LEN {
+            return Err(KvError::ValueTooLarge);
+        }
+        // Avoid wearing the flash when the value is not changing
+        if let Some((pos, header)) = self.find(namespace, key)? {
+            if header.value_len == value.len() {
+                let mut current = [0u8; MAX_VALUE_LEN];
+                let current = &mut current[0..value.len()];
+                self.read(pos + (RECORD_HEADER_SIZE + header.key_len) as u32, current)?;
+                if current == value {
+                    return Ok(());
+                }
+            }
+        }
+        self.append(Update {
+            namespace,
+            key,
+            value: Some(value),
+        })
+    }
+
+    pub fn delete(&mut self, namespace: u16, key: &[u8]) -> Result<(), KvError> {
+        check_key(key)?;
+        self.find(namespace, key)?.ok_or(KvError::KeyNotFound)?;
+        self.append(Update {
+            namespace,
+            key,
+            value: None,
+        })
+    }
+
+    /// Iterates on the keys of the namespace, starting from cursor 0. The cursor
+    /// is valid until the next change to the store.
+    pub fn next_key(
+        &self,
+        namespace: u16,
+        cursor: u32,
+        key: &mut [u8; MAX_KEY_LEN],
+    ) -> Result<KeyEntry, KvError> {
+        let mut pos = core::cmp::max(cursor, BLOCK_HEADER_SIZE as u32);
+        while pos < self.tail {
+            let header = self.record_header(pos)?;
+            if header.namespace == namespace && self.is_live(pos, &header, None)? {
+                self.read(pos + RECORD_HEADER_SIZE as u32, &mut key[0..header.key_len])?;
+                return Ok(KeyEntry {
+                    next_cursor: pos + header.size() as u32,
+                    key_len: header.key_len,
+                    value_len: header.value_len,
+                });
+            }
+            pos += header.size() as u32;
+        }
+        Err(KvError::EndOfKeys)
+    }
+
+    /* Records */
+
+    fn append(&mut self, update: Update) -> Result<(), KvError> {
+        let (kind, value) = match update.value {
+            Some(value) => (RecordKind::Set, value),
+            None => (RecordKind::Delete, &[] as &[u8]),
+        };
+        let header = RecordHeader::new(update.namespace, kind, update.key, value);
+        if self.tail as usize + header.size() > self.size as usize {
+            // The update is applied together with the compaction
+            return self.compact(Some(update));
+        }
+        self.write_record(self.base_address, self.tail, &header, update.key, value)?;
+        self.tail += header.size() as u32;
+        Ok(())
+    }
+
+    fn write_record(
+        &mut self,
+        base_address: u32,
+        pos: u32,
+        header: &RecordHeader,
+        key: &[u8],
+        value: &[u8],
+    ) -> Result<(), KvError> {
+        let mut buffer = [0u8; MAX_RECORD_SIZE];
+        let len = header.write_into(key, value, &mut buffer);
+        self.backend
+            .write(base_address, pos, &buffer[0..len])
+            .map_err(|_| KvError::StorageError)?;
+        // The data is flushed, now the record can be committed
+        self.backend
+            .write(base_address, pos + len as u32, &COMMITTED)
+            .map_err(|_| KvError::StorageError)?;
+        Ok(())
+    }
+
+    /// Header of a record before the tail, already validated
+    fn record_header(&self, pos: u32) -> Result<RecordHeader, KvError> {
+        let mut raw = [0u8; RECORD_HEADER_SIZE];
+        self.read(pos, &mut raw)?;
+        RecordHeader::decode(&raw).ok_or(KvError::StorageError)
+    }
+
+    /// Full check of the record, used on mount to find the tail
+    fn validate_record(&self, pos: u32) -> Result<Option<RecordHeader>, KvError> {
+        if pos as usize + RECORD_HEADER_SIZE + COMMIT_SIZE > self.size as usize {
+            return Ok(None);
+        }
+        let mut raw = [0u8; RECORD_HEADER_SIZE];
+        self.read(pos, &mut raw)?;
+        let header = match RecordHeader::decode(&raw) {
+            Some(header) => header,
+            None => return Ok(None),
+        };
+        if pos as usize + header.size() > self.size as usize {
+            return Ok(None);
+        }
+        let mut commit = [0xFFu8; COMMIT_SIZE];
+        self.read(pos + header.commit_offset() as u32, &mut commit)?;
+        if commit != COMMITTED {
+            return Ok(None);
+        }
+        let mut payload = [0u8; MAX_RECORD_SIZE];
+        let payload = &mut payload[0..header.key_len + header.value_len];
+        self.read(pos + RECORD_HEADER_SIZE as u32, payload)?;
+        let (key, value) = payload.split_at(header.key_len);
+        if header.checksum(key, value) != header.crc {
+            return Ok(None);
+        }
+        Ok(Some(header))
+    }
+
+    fn record_matches(
+        &self,
+        pos: u32,
+        header: &RecordHeader,
+        namespace: u16,
+        key: &[u8],
+    ) -> Result<bool, KvError> {
+        if header.namespace != namespace || header.key_len != key.len() {
+            return Ok(false);
+        }
+        let mut buffer = [0u8; MAX_KEY_LEN];
+        let record_key = &mut buffer[0..key.len()];
+        self.read(pos + RECORD_HEADER_SIZE as u32, record_key)?;
+        Ok(record_key == key)
+    }
+
+    /// Last record of the key, if it is not deleted
+    fn find(&self, namespace: u16, key: &[u8]) -> Result<Option<(u32, RecordHeader)>, KvError> {
+        let mut found: Option<(u32, RecordHeader)> = None;
+        let mut pos = BLOCK_HEADER_SIZE as u32;
+        while pos < self.tail {
+            let header = self.record_header(pos)?;
+            if self.record_matches(pos, &header, namespace, key)? {
+                found = Some((pos, header));
+            }
+            pos += header.size() as u32;
+        }
+        Ok(found.filter(|(_, header)| header.kind == RecordKind::Set))
+    }
+
+    /// A value not replaced or deleted by a following record (or by the update)
+    fn is_live(
+        &self,
+        pos: u32,
+        header: &RecordHeader,
+        update: Option<&Update>,
+    ) -> Result<bool, KvError> {
+        if header.kind != RecordKind::Set {
+            return Ok(false);
+        }
+        let mut buffer = [0u8; MAX_KEY_LEN];
+        let key = &mut buffer[0..header.key_len];
+        self.read(pos + RECORD_HEADER_SIZE as u32, key)?;
+        if let Some(update) = update {
+            if update.namespace == header.namespace && update.key == key {
+                return Ok(false);
+            }
+        }
+        let mut next = pos + header.size() as u32;
+        while next < self.tail {
+            let next_header = self.record_header(next)?;
+            if self.record_matches(next, &next_header, header.namespace, key)? {
+                return Ok(false);
+            }
+            next += next_header.size() as u32;
+        }
+        Ok(true)
+    }
+
+    /// Searches the end of the records after a start-up. A record not committed
+    /// or corrupted is left behind by a power loss: nothing can be appended after
+    /// it, so the store is compacted.
+    fn recover_tail(&mut self) -> Result<(), KvError> {
+        let mut pos = BLOCK_HEADER_SIZE as u32;
+        while let Some(header) = self.validate_record(pos)? {
+            pos += header.size() as u32;
+        }
+        self.tail = pos;
+        if pos as usize + RECORD_HEADER_SIZE > self.size as usize {
+            return Ok(());
+        }
+        let mut raw = [0u8; RECORD_HEADER_SIZE];
+        self.read(pos, &mut raw)?;
+        if raw != [0xFF; RECORD_HEADER_SIZE] && self.compact(None).is_err() {
+            // Still readable, the compaction is retried on the next change
+            self.tail = self.size;
+        }
+        Ok(())
+    }
+
+    /* Blocks */
+
+    fn create(&mut self) -> Result<(), KvError> {
+        let (base_address, size) = self.start_block(0)?;
+        self.commit_block(base_address)?;
+        self.base_address = base_address;
+        self.size = size;
+        self.generation = 0;
+        self.tail = BLOCK_HEADER_SIZE as u32;
+        Ok(())
+    }
+
+    /// Moves the live records, and the update if any, in a new block
+    fn compact(&mut self, update: Option<Update>) -> Result<(), KvError> {
+        // Check the space before allocating
+        let mut needed = BLOCK_HEADER_SIZE;
+        let mut pos = BLOCK_HEADER_SIZE as u32;
+        while pos < self.tail {
+            let header = self.record_header(pos)?;
+            if self.is_live(pos, &header, update.as_ref())? {
+                needed += header.size();
+            }
+            pos += header.size() as u32;
+        }
+        let new_record = update.and_then(|u| {
+            u.value
+                .map(|value| RecordHeader::new(u.namespace, RecordKind::Set, u.key, value))
+        });
+        if let Some(header) = &new_record {
+            needed += header.size();
+        }
+        if needed > self.block_size as usize {
+            return Err(KvError::StoreFull);
+        }
+        // Fill the new block
+        let (base_address, size) = self.start_block(self.generation + 1)?;
+        let result = self.copy_records(base_address, update.as_ref(), new_record.as_ref());
+        let tail = match result.and_then(|tail| self.commit_block(base_address).map(|_| tail)) {
+            Ok(tail) => tail,
+            Err(e) => {
+                // The old block is still the store, drop the copy now instead of
+                // waiting for the storage to erase it at start-up
+                let _ = self.backend.deallocate(base_address);
+                return Err(e);
+            }
+        };
+        // From now on the new block is the store
+        let old_base_address = self.base_address;
+        self.base_address = base_address;
+        self.size = size;
+        self.generation += 1;
+        self.tail = tail;
+        // If this fails, the old block is removed on the next mount
+        let _ = self.backend.deallocate(old_base_address);
+        Ok(())
+    }
+
+    /// Returns the tail of the new block
+    fn copy_records(
+        &mut self,
+        base_address: u32,
+        update: Option<&Update>,
+        new_record: Option<&RecordHeader>,
+    ) -> Result<u32, KvError> {
+        let mut tail = BLOCK_HEADER_SIZE as u32;
+        let mut buffer = [0u8; MAX_RECORD_SIZE];
+        let mut pos = BLOCK_HEADER_SIZE as u32;
+        while pos < self.tail {
+            let header = self.record_header(pos)?;
+            if self.is_live(pos, &header, update)? {
+                // Committed records are moved as they are
+                let record = &mut buffer[0..header.size()];
+                self.read(pos, record)?;
+                self.backend
+                    .write(base_address, tail, record)
+                    .map_err(|_| KvError::StorageError)?;
+                tail += header.size() as u32;
+            }
+            pos += header.size() as u32;
+        }
+        if let (Some(header), Some(update)) = (new_record, update) {
+            self.write_record(
+                base_address,
+                tail,
+                header,
+                update.key,
+                update.value.unwrap(),
+            )?;
+            tail += header.size() as u32;
+        }
+        Ok(tail)
+    }
+
+    /// Allocates a block and writes the header, without the complete flag
+    fn start_block(&mut self, generation: u32) -> Result<(u32, u32), KvError> {
+        let (base_address, size) = self
+            .backend
+            .allocate(self.block_size)
+            .map_err(|_| KvError::StorageError)?;
+        let mut header = [0u8; WORD_SIZE];
+        header[0..4].copy_from_slice(&BLOCK_MAGIC.to_le_bytes());
+        header[4..8].copy_from_slice(&generation.to_le_bytes());
+        if self.backend.write(base_address, 0, &header).is_err() {
+            let _ = self.backend.deallocate(base_address);
+            return Err(KvError::StorageError);
+        }
+        Ok((base_address, size))
+    }
+
+    /// Commit point of a new block: the finalization, as unfinalized blocks are
+    /// erased at start-up whatever their content
+    fn commit_block(&mut self, base_address: u32) -> Result<(), KvError> {
+        self.backend
+            .write(base_address, COMPLETE_FLAG_OFFSET, &COMMITTED)
+            .map_err(|_| KvError::StorageError)?;
+        self.backend
+            .finalize(base_address)
+            .map_err(|_| KvError::StorageError)?;
+        Ok(())
+    }
+
+    /// Generation and complete flag, None if the block is not of the store
+    fn read_block_header(&self, base_address: u32) -> Result<Option<(u32, bool)>, KvError> {
+        let mut header = [0u8; BLOCK_HEADER_SIZE];
+        self.backend
+            .read(base_address, 0, &mut header)
+            .map_err(|_| KvError::StorageError)?;
+        if u32::from_le_bytes([header[0], header[1], header[2], header[3]]) != BLOCK_MAGIC {
+            return Ok(None);
+        }
+        let generation = u32::from_le_bytes([header[4], header[5], header[6], header[7]]);
+        let complete = header[WORD_SIZE..BLOCK_HEADER_SIZE] == COMMITTED;
+        Ok(Some((generation, complete)))
+    }
+
+    fn remove_stale_blocks(&mut self, active: Option<u32>) -> Result<(), KvError> {
+        // The numbering changes after a deallocation, restart each time
+        'search: loop {
+            let mut n: u32 = 0;
+            while let Some((base_address, _)) = self.data_block(n)? {
+                if Some(base_address) != active && self.read_block_header(base_address)?.is_some() {
+                    self.backend
+                        .deallocate(base_address)
+                        .map_err(|_| KvError::StorageError)?;
+                    continue 'search;
+                }
+                n += 1;
+            }
+            return Ok(());
+        }
+    }
+
+    /* Helpers */
+
+    fn read(&self, offset: u32, buffer: &mut [u8]) -> Result<(), KvError> {
+        self.backend
+            .read(self.base_address, offset, buffer)
+            .map_err(|_| KvError::StorageError)
+    }
+
+    fn data_block(&self, n: u32) -> Result<Option<(u32, u32)>, KvError> {
+        self.backend
+            .data_block(n)
+            .map_err(|_| KvError::StorageError)
+    }
+
+    #[cfg(test)]
+    fn backend(&mut self) -> &mut B {
+        &mut self.backend
+    }
+}
+
+fn check_key(key: &[u8]) -> Result<(), KvError> {
+    if key.is_empty() || key.len() > MAX_KEY_LEN {
+        return Err(KvError::InvalidKey);
+    }
+    Ok(())
+}
+
+/*
+    Tests
+*/
+#[cfg(test)]
+mod test {
+    extern crate std;
+    use super::*;
+    use data_block::mock::FakeFlash;
+    use std::vec::Vec;
+
+    const BLOCK_SIZE: u32 = 256;
+
+    fn get(store: &KvStore<&mut FakeFlash>, namespace: u16, key: &[u8]) -> Option<Vec<u8>> {
+        let mut value = [0u8; MAX_VALUE_LEN];
+        match store.get(namespace, key, &mut value) {
+            Ok(len) => Some(value[0..len].to_vec()),
+            Err(KvError::KeyNotFound) => None,
+            Err(e) => panic!("{:?}", e),
+        }
+    }
+
+    #[test]
+    fn set_get_delete() {
+        let mut flash = FakeFlash::new(4);
+        let mut store = KvStore::mount(&mut flash, BLOCK_SIZE).unwrap();
+        store.set(16, b"mode", b"auto").unwrap();
+        store.set(16, b"target", &[21]).unwrap();
+        store.set(16, b"mode", b"heat").unwrap();
+        assert_eq!(get(&store, 16, b"mode"), Some(b"heat".to_vec()));
+        assert_eq!(get(&store, 16, b"target"), Some([21].to_vec()));
+        store.delete(16, b"mode").unwrap();
+        assert_eq!(get(&store, 16, b"mode"), None);
+        assert_eq!(store.delete(16, b"mode"), Err(KvError::KeyNotFound));
+        // Bounds
+        assert_eq!(store.set(16, b"", b"x"), Err(KvError::InvalidKey));
+        assert_eq!(
+            store.set(16, &[b'k'; MAX_KEY_LEN + 1], b"x"),
+            Err(KvError::InvalidKey)
+        );
+        assert_eq!(
+            store.set(16, b"big", &[0; MAX_VALUE_LEN + 1]),
+            Err(KvError::ValueTooLarge)
+        );
+        // Truncated read returns the full length
+        let mut small = [0u8; 2];
+        store.set(16, b"name", b"thermostat").unwrap();
+        assert_eq!(store.get(16, b"name", &mut small), Ok(10));
+        assert_eq!(&small, b"th");
+        // Survives a reboot
+        flash.reboot();
+        let store = KvStore::mount(&mut flash, BLOCK_SIZE).unwrap();
+        assert_eq!(get(&store, 16, b"target"), Some([21].to_vec()));
+        assert_eq!(get(&store, 16, b"name"), Some(b"thermostat".to_vec()));
+        assert_eq!(get(&store, 16, b"mode"), None);
+    }
+
+    #[test]
+    fn namespaces() {
+        let mut flash = FakeFlash::new(4);
+        let mut store = KvStore::mount(&mut flash, BLOCK_SIZE).unwrap();
+        store.set(15, b"key", b"a").unwrap();
+        store.set(16, b"key", b"b").unwrap();
+        store.delete(15, b"key").unwrap();
+        assert_eq!(get(&store, 15, b"key"), None);
+        assert_eq!(get(&store, 16, b"key"), Some(b"b".to_vec()));
+    }
+
+    #[test]
+    fn iteration() {
+        let mut flash = FakeFlash::new(4);
+        let mut store = KvStore::mount(&mut flash, BLOCK_SIZE).unwrap();
+        store.set(16, b"a", b"1").unwrap();
+        store.set(15, b"b", b"2").unwrap();
+        store.set(16, b"c", b"33").unwrap();
+        store.set(16, b"a", b"4").unwrap();
+        store.set(16, b"d", b"5").unwrap();
+        store.delete(16, b"d").unwrap();
+        let mut keys: Vec<(Vec<u8>, usize)> = Vec::new();
+        let mut key = [0u8; MAX_KEY_LEN];
+        let mut cursor = 0;
+        loop {
+            match store.next_key(16, cursor, &mut key) {
+                Ok(entry) => {
+                    keys.push((key[0..entry.key_len].to_vec(), entry.value_len));
+                    cursor = entry.next_cursor;
+                }
+                Err(e) => {
+                    assert_eq!(e, KvError::EndOfKeys);
+                    break;
+                }
+            }
+        }
+        assert_eq!(keys, [(b"c".to_vec(), 2), (b"a".to_vec(), 1)]);
+    }
+
+    #[test]
+    fn compaction() {
+        let mut flash = FakeFlash::new(3);
+        let mut store = KvStore::mount(&mut flash, BLOCK_SIZE).unwrap();
+        // Many times the size of the block
+        for i in 0..200u32 {
+            store.set(16, b"counter", &i.to_le_bytes()).unwrap();
+            store.set(16, b"fixed", b"value").unwrap();
+        }
+        assert_eq!(
+            get(&store, 16, b"counter"),
+            Some(199u32.to_le_bytes().to_vec())
+        );
+        assert_eq!(store.backend().block_count(), 1);
+        // Live data larger than a block
+        let value = [0x55u8; 100];
+        store.set(16, b"x", &value).unwrap();
+        assert_eq!(store.set(16, b"y", &value), Err(KvError::StoreFull));
+        // Replacing a value does not need more space
+        store.set(16, b"x", &[0xAA; 100]).unwrap();
+        store.set(16, b"x", &value).unwrap();
+        assert_eq!(get(&store, 16, b"x"), Some(value.to_vec()));
+        flash.reboot();
+        let store = KvStore::mount(&mut flash, BLOCK_SIZE).unwrap();
+        assert_eq!(get(&store, 16, b"fixed"), Some(b"value".to_vec()));
+        assert_eq!(get(&store, 16, b"x"), Some(value.to_vec()));
+    }
+
+    #[derive(Clone)]
+    enum Op {
+        Set(&'static [u8], Vec<u8>),
+        Delete(&'static [u8]),
+    }
+
+    fn apply(model: &mut Vec<(&'static [u8], Vec<u8>)>, op: &Op) {
+        match op {
+            Op::Set(key, value) => {
+                model.retain(|(k, _)| k != key);
+                model.push((key, value.clone()));
+            }
+            Op::Delete(key) => model.retain(|(k, _)| k != key),
+        }
+    }
+
+    fn matches(store: &KvStore<&mut FakeFlash>, model: &[(&'static [u8], Vec<u8>)]) -> bool {
+        let keys: [&[u8]; 3] = [b"a", b"b", b"c"];
+        keys.iter().all(|key| {
+            let expected = model.iter().find(|(k, _)| k == key).map(|(_, v)| v.clone());
+            get(store, 16, key) == expected
+        })
+    }
+
+    #[test]
+    fn power_loss() {
+        // Enough operations to go through some compactions
+        let mut ops: Vec<Op> = Vec::new();
+        for i in 0..24u8 {
+            ops.push(Op::Set(b"a", std::vec![i; 40]));
+            ops.push(Op::Set(b"b", std::vec![i; (i % 5) as usize]));
+            if i % 3 == 0 {
+                ops.push(Op::Delete(b"b"));
+            }
+            if i % 4 == 0 {
+                ops.push(Op::Set(b"c", std::vec![i; 24]));
+            }
+        }
+        // Cut the power at each step
+        let mut budget: usize = 0;
+        loop {
+            let mut flash = FakeFlash::new(3);
+            let mut model: Vec<(&'static [u8], Vec<u8>)> = Vec::new();
+            let mut interrupted: Option<Op> = None;
+            {
+                let mut store = KvStore::mount(&mut flash, BLOCK_SIZE).unwrap();
+                store.backend().writes_left = Some(budget);
+                for op in &ops {
+                    let result = match op {
+                        Op::Set(key, value) => store.set(16, key, value),
+                        Op::Delete(key) => store.delete(16, key),
+                    };
+                    if result.is_err() {
+                        interrupted = Some(op.clone());
+                        break;
+                    }
+                    apply(&mut model, op);
+                }
+            }
+            let completed = interrupted.is_none();
+            flash.reboot();
+            let mut store = KvStore::mount(&mut flash, BLOCK_SIZE).unwrap();
+            // The interrupted operation happened entirely, or not at all
+            if !matches(&store, &model) {
+                apply(&mut model, &interrupted.expect("Data lost"));
+                assert!(
+                    matches(&store, &model),
+                    "Inconsistent after {} writes",
+                    budget
+                );
+            }
+            // Only the store is left, and it still works
+            assert_eq!(store.backend().block_count(), 1);
+            store.set(16, b"c", b"after").unwrap();
+            assert_eq!(get(&store, 16, b"c"), Some(b"after".to_vec()));
+            if completed {
+                break;
+            }
+            budget += 1;
+        }
+    }
+}
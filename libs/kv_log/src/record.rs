@@ -0,0 +1,147 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use data_block::{align_up, crc16_update, COMMIT_SIZE, CRC16_INIT, WORD_SIZE};
+
+/**
+ * Constants
+ */
+pub const RECORD_HEADER_SIZE: usize = WORD_SIZE;
+
+pub const MAX_KEY_LEN: usize = 32;
+pub const MAX_VALUE_LEN: usize = 256;
+pub const MAX_RECORD_SIZE: usize =
+    RECORD_HEADER_SIZE + align_up(MAX_KEY_LEN + MAX_VALUE_LEN) + COMMIT_SIZE;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[repr(u8)]
+pub enum RecordKind {
+    Set = 0x01,
+    Delete = 0x02,
+}
+
+/**
+ * Record header
+ *
+ * +-----------+---------+------+-----------+--------+
+ * | Namespace | Key len | Kind | Value len | CRC-16 |
+ * +-----------+---------+------+-----------+--------+
+ * | 2 bytes   | 1 byte  | 1 b. | 2 bytes   | 2 bytes|
+ * +-----------+---------+------+-----------+--------+
+ *
+ * followed by the key and the value (padded to a word with 0xFF) and by the
+ * commit word. The CRC covers the first 6 bytes, the key and the value.
+ */
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct RecordHeader {
+    pub namespace: u16,
+    pub kind: RecordKind,
+    pub key_len: usize,
+    pub value_len: usize,
+    pub crc: u16,
+}
+
+impl RecordHeader {
+    pub fn new(namespace: u16, kind: RecordKind, key: &[u8], value: &[u8]) -> Self {
+        let mut header = Self {
+            namespace,
+            kind,
+            key_len: key.len(),
+            value_len: value.len(),
+            crc: 0,
+        };
+        header.crc = header.checksum(key, value);
+        header
+    }
+
+    /// None when the word cannot be a record header (e.g. a torn write)
+    pub fn decode(raw: &[u8; RECORD_HEADER_SIZE]) -> Option<Self> {
+        let kind = match raw[3] {
+            0x01 => RecordKind::Set,
+            0x02 => RecordKind::Delete,
+            _ => return None,
+        };
+        let key_len = raw[2] as usize;
+        let value_len = u16::from_le_bytes([raw[4], raw[5]]) as usize;
+        if key_len == 0 || key_len > MAX_KEY_LEN || value_len > MAX_VALUE_LEN {
+            return None;
+        }
+        if kind == RecordKind::Delete && value_len != 0 {
+            return None;
+        }
+        Some(Self {
+            namespace: u16::from_le_bytes([raw[0], raw[1]]),
+            kind,
+            key_len,
+            value_len,
+            crc: u16::from_le_bytes([raw[6], raw[7]]),
+        })
+    }
+
+    pub fn encode(&self) -> [u8; RECORD_HEADER_SIZE] {
+        let mut raw = [0u8; RECORD_HEADER_SIZE];
+        raw[0..2].copy_from_slice(&self.namespace.to_le_bytes());
+        raw[2] = self.key_len as u8;
+        raw[3] = self.kind as u8;
+        raw[4..6].copy_from_slice(&(self.value_len as u16).to_le_bytes());
+        raw[6..8].copy_from_slice(&self.crc.to_le_bytes());
+        raw
+    }
+
+    pub fn checksum(&self, key: &[u8], value: &[u8]) -> u16 {
+        let raw = self.encode();
+        let crc = crc16_update(CRC16_INIT, &raw[0..6]);
+        let crc = crc16_update(crc, key);
+        crc16_update(crc, value)
+    }
+
+    /// Key and value, padded
+    pub fn payload_size(&self) -> usize {
+        align_up(self.key_len + self.value_len)
+    }
+
+    /// Offset of the commit word from the start of the record
+    pub fn commit_offset(&self) -> usize {
+        RECORD_HEADER_SIZE + self.payload_size()
+    }
+
+    pub fn size(&self) -> usize {
+        self.commit_offset() + COMMIT_SIZE
+    }
+
+    /// Header, key, value and padding, the commit word is left out
+    pub fn write_into(
+        &self,
+        key: &[u8],
+        value: &[u8],
+        buffer: &mut [u8; MAX_RECORD_SIZE],
+    ) -> usize {
+        let end = self.commit_offset();
+        buffer[0..RECORD_HEADER_SIZE].copy_from_slice(&self.encode());
+        let value_start = RECORD_HEADER_SIZE + key.len();
+        buffer[RECORD_HEADER_SIZE..value_start].copy_from_slice(key);
+        buffer[value_start..value_start + value.len()].copy_from_slice(value);
+        buffer[value_start + value.len()..end].fill(0xFF);
+        end
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn encoding() {
+        let header = RecordHeader::new(16, RecordKind::Set, b"mode", b"auto");
+        assert_eq!(RecordHeader::decode(&header.encode()), Some(header));
+        assert_eq!(header.size(), RECORD_HEADER_SIZE + 8 + COMMIT_SIZE);
+        assert_eq!(header.checksum(b"mode", b"auto"), header.crc);
+        assert_ne!(header.checksum(b"mode", b"heat"), header.crc);
+        // Erased or corrupted words
+        assert_eq!(RecordHeader::decode(&[0xFF; RECORD_HEADER_SIZE]), None);
+        let mut raw = RecordHeader::new(16, RecordKind::Delete, b"mode", b"").encode();
+        raw[4] = 1;
+        assert_eq!(RecordHeader::decode(&raw), None);
+    }
+}
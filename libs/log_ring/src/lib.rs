@@ -0,0 +1,678 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+#![no_std]
+
+mod record;
+
+pub use data_block::{Backend, BackendError, WORD_SIZE};
+pub use record::MAX_DATA_LEN;
+
+use data_block::{COMMITTED, COMMIT_SIZE};
+use record::{RecordHeader, RecordKind, MAX_RECORD_SIZE, RECORD_HEADER_SIZE};
+
+/**
+ * Constants
+ */
+const SEGMENT_MAGIC: u32 = 0x4C4F_4731; // LOG1
+/// Magic and number, then the first sequence number and the consumed marker
+const SEGMENT_HEADER_SIZE: usize = 2 * WORD_SIZE;
+/// Most segments a log can be made of
+pub const MAX_SEGMENTS: usize = 16;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum LogError {
+    EndOfLog,
+    DataTooLarge,
+    InvalidCursor,
+    BadConfiguration,
+    StorageError,
+}
+
+/// Record found by `read`
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Entry {
+    /// Sequence number, the cursor of the following record is `seq + 1`
+    pub seq: u32,
+    /// Component that appended the record
+    pub source: u16,
+    /// Lower 48 bits of the timestamp given at append
+    pub timestamp: u64,
+    pub len: usize,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Status {
+    /// Sequence number of the oldest record still stored
+    pub first: u32,
+    /// The records before this one have been consumed
+    pub consumed: u32,
+    /// Sequence number of the next record
+    pub next: u32,
+    pub segments: usize,
+}
+
+/// Block of the log
+#[derive(Clone, Copy)]
+struct Segment {
+    base_address: u32,
+    size: u32,
+    /// Increases with each new segment
+    number: u32,
+    /// Sequence number of the first record
+    first_seq: u32,
+}
+
+const NO_SEGMENT: Segment = Segment {
+    base_address: 0,
+    size: 0,
+    number: 0,
+    first_seq: 0,
+};
+
+/// Content of the segment header
+struct SegmentHeader {
+    number: u32,
+    first_seq: u32,
+    consumed: u32,
+}
+
+/**
+ * Circular log
+ *
+ * Records are appended to the newest segment. When it is full a new one is
+ * allocated and, if the log already has all its segments, the oldest one is
+ * dropped together with its records, consumed or not. The consumed marker is
+ * a record too, and it is copied in the header of each new segment, so that it
+ * is never lost with the oldest one.
+ */
+pub struct LogRing<B: Backend> {
+    backend: B,
+    /// Size requested for each segment
+    segment_size: u32,
+    max_segments: usize,
+    /// From the oldest to the newest
+    segments: [Segment; MAX_SEGMENTS],
+    count: usize,
+    /// End of the committed records of the newest segment
+    tail: u32,
+    next_seq: u32,
+    consumed: u32,
+}
+
+impl<B: Backend> LogRing<B> {
+    /// Opens the log, recovering it after a power loss. Segments are allocated
+    /// when the first records are appended.
+    pub fn mount(backend: B, segment_size: u32, max_segments: usize) -> Result<Self, LogError> {
+        if max_segments == 0
+            || max_segments > MAX_SEGMENTS
+            || (segment_size as usize) < SEGMENT_HEADER_SIZE + MAX_RECORD_SIZE
+        {
+            return Err(LogError::BadConfiguration);
+        }
+        let mut log = Self {
+            backend,
+            segment_size,
+            max_segments,
+            segments: [NO_SEGMENT; MAX_SEGMENTS],
+            count: 0,
+            tail: 0,
+            next_seq: 0,
+            consumed: 0,
+        };
+        log.load_segments()?;
+        // Less segments were configured
+        while log.count > log.max_segments {
+            log.drop_oldest()?;
+        }
+        if log.count > 0 {
+            // The log could have been stopped before the finalization of the
+            // newest segment, do it again
+            let newest = log.segments[log.count - 1];
+            log.backend
+                .finalize(newest.base_address)
+                .map_err(|_| LogError::StorageError)?;
+            log.recover_tail()?;
+        }
+        Ok(log)
+    }
+
+    /// Returns the sequence number of the record
+    pub fn append(&mut self, source: u16, timestamp: u64, data: &[u8]) -> Result<u32, LogError> {
+        if data.len() > MAX_DATA_LEN {
+            return Err(LogError::DataTooLarge);
+        }
+        let header = RecordHeader::new(self.next_seq, source, RecordKind::Data, timestamp, data);
+        self.write_record(&header, data)?;
+        self.next_seq += 1;
+        Ok(header.seq)
+    }
+
+    /// Copies the data of the first record from the cursor on (truncated if it
+    /// does not fit). When the record of the cursor was dropped, the oldest one
+    /// is returned instead.
+    pub fn read(&self, cursor: u32, data: &mut [u8]) -> Result<Entry, LogError> {
+        if cursor >= self.next_seq {
+            return Err(LogError::EndOfLog);
+        }
+        // The segment of the cursor, or the oldest one
+        let start = self.segments[0..self.count]
+            .iter()
+            .rposition(|segment| segment.first_seq <= cursor)
+            .unwrap_or(0);
+        let mut payload = [0u8; MAX_DATA_LEN];
+        for index in start..self.count {
+            let segment = self.segments[index];
+            let end = if index == self.count - 1 {
+                self.tail
+            } else {
+                segment.size
+            };
+            let mut pos = SEGMENT_HEADER_SIZE as u32;
+            while pos < end {
+                let header = match self.read_record(&segment, pos, &mut payload)? {
+                    Some(header) => header,
+                    None => break,
+                };
+                if header.kind == RecordKind::Data && header.seq >= cursor {
+                    let len = core::cmp::min(data.len(), header.len);
+                    data[0..len].copy_from_slice(&payload[0..len]);
+                    return Ok(Entry {
+                        seq: header.seq,
+                        source: header.source,
+                        timestamp: header.timestamp,
+                        len: header.len,
+                    });
+                }
+                pos += header.size() as u32;
+            }
+        }
+        Err(LogError::EndOfLog)
+    }
+
+    /// Marks the records before `up_to` as consumed. The marker never goes back.
+    pub fn consume(&mut self, up_to: u32) -> Result<(), LogError> {
+        if up_to > self.next_seq {
+            return Err(LogError::InvalidCursor);
+        }
+        if up_to <= self.consumed {
+            return Ok(());
+        }
+        let data = up_to.to_le_bytes();
+        let header = RecordHeader::new(self.next_seq, 0, RecordKind::Consumed, 0, &data);
+        // Already the new value, in case a new segment is started
+        let previous = self.consumed;
+        self.consumed = up_to;
+        if let Err(e) = self.write_record(&header, &data) {
+            self.consumed = previous;
+            return Err(e);
+        }
+        Ok(())
+    }
+
+    pub fn status(&self) -> Status {
+        Status {
+            first: if self.count > 0 {
+                self.segments[0].first_seq
+            } else {
+                self.next_seq
+            },
+            consumed: self.consumed,
+            next: self.next_seq,
+            segments: self.count,
+        }
+    }
+
+    /* Records */
+
+    fn write_record(&mut self, header: &RecordHeader, data: &[u8]) -> Result<(), LogError> {
+        if self.count == 0
+            || self.tail as usize + header.size() > self.segments[self.count - 1].size as usize
+        {
+            self.start_segment()?;
+        }
+        let base_address = self.segments[self.count - 1].base_address;
+        let mut buffer = [0u8; MAX_RECORD_SIZE];
+        let len = header.write_into(data, &mut buffer);
+        let result = self
+            .backend
+            .write(base_address, self.tail, &buffer[0..len])
+            .and_then(|_| {
+                // The data is flushed, now the record can be committed
+                self.backend
+                    .write(base_address, self.tail + len as u32, &COMMITTED)
+            });
+        if result.is_err() {
+            // Nothing can be appended after a failed write, use a new segment
+            self.tail = self.segments[self.count - 1].size;
+            return Err(LogError::StorageError);
+        }
+        self.tail += header.size() as u32;
+        Ok(())
+    }
+
+    /// Full check of the record, leaving its data in the buffer. None marks the
+    /// end of the records of the segment.
+    fn read_record(
+        &self,
+        segment: &Segment,
+        pos: u32,
+        data: &mut [u8; MAX_DATA_LEN],
+    ) -> Result<Option<RecordHeader>, LogError> {
+        if pos as usize + RECORD_HEADER_SIZE + COMMIT_SIZE > segment.size as usize {
+            return Ok(None);
+        }
+        let mut raw = [0u8; RECORD_HEADER_SIZE];
+        self.read_flash(segment.base_address, pos, &mut raw)?;
+        let header = match RecordHeader::decode(&raw) {
+            Some(header) => header,
+            None => return Ok(None),
+        };
+        if pos as usize + header.size() > segment.size as usize {
+            return Ok(None);
+        }
+        let mut commit = [0xFFu8; COMMIT_SIZE];
+        self.read_flash(
+            segment.base_address,
+            pos + header.commit_offset() as u32,
+            &mut commit,
+        )?;
+        if commit != COMMITTED {
+            return Ok(None);
+        }
+        let data = &mut data[0..header.len];
+        self.read_flash(segment.base_address, pos + RECORD_HEADER_SIZE as u32, data)?;
+        if header.checksum(data) != header.crc {
+            return Ok(None);
+        }
+        Ok(Some(header))
+    }
+
+    /// Searches the end of the records of the newest segment after a start-up,
+    /// together with the last consumed marker. A record not committed or
+    /// corrupted is left behind by a power loss: nothing can be appended after
+    /// it, so the segment is closed.
+    fn recover_tail(&mut self) -> Result<(), LogError> {
+        let newest = self.segments[self.count - 1];
+        let header = self
+            .read_segment_header(newest.base_address)?
+            .ok_or(LogError::StorageError)?;
+        self.next_seq = newest.first_seq;
+        self.consumed = header.consumed;
+        let mut payload = [0u8; MAX_DATA_LEN];
+        let mut pos = SEGMENT_HEADER_SIZE as u32;
+        while let Some(record) = self.read_record(&newest, pos, &mut payload)? {
+            if record.seq != self.next_seq {
+                break;
+            }
+            match record.kind {
+                RecordKind::Data => self.next_seq += 1,
+                RecordKind::Consumed => {
+                    let up_to =
+                        u32::from_le_bytes([payload[0], payload[1], payload[2], payload[3]]);
+                    self.consumed = core::cmp::max(self.consumed, up_to);
+                }
+            }
+            pos += record.size() as u32;
+        }
+        self.tail = pos;
+        if pos as usize + RECORD_HEADER_SIZE > newest.size as usize {
+            return Ok(());
+        }
+        let mut raw = [0u8; RECORD_HEADER_SIZE];
+        self.read_flash(newest.base_address, pos, &mut raw)?;
+        if raw != [0xFF; RECORD_HEADER_SIZE] {
+            self.tail = newest.size;
+        }
+        Ok(())
+    }
+
+    /* Segments */
+
+    fn load_segments(&mut self) -> Result<(), LogError> {
+        // The numbering changes after a deallocation, restart each time
+        'search: loop {
+            self.count = 0;
+            let mut n: u32 = 0;
+            while let Some((base_address, size)) = self.data_block(n)? {
+                if let Some(header) = self.read_segment_header(base_address)? {
+                    let segment = Segment {
+                        base_address,
+                        size,
+                        number: header.number,
+                        first_seq: header.first_seq,
+                    };
+                    if self.count == MAX_SEGMENTS {
+                        // Left by a configuration with more segments
+                        let oldest = if segment.number < self.segments[0].number {
+                            segment.base_address
+                        } else {
+                            self.segments[0].base_address
+                        };
+                        self.backend
+                            .deallocate(oldest)
+                            .map_err(|_| LogError::StorageError)?;
+                        continue 'search;
+                    }
+                    // Sorted by number
+                    let index = self.segments[0..self.count]
+                        .iter()
+                        .position(|s| s.number > segment.number)
+                        .unwrap_or(self.count);
+                    self.segments.copy_within(index..self.count, index + 1);
+                    self.segments[index] = segment;
+                    self.count += 1;
+                }
+                n += 1;
+            }
+            return Ok(());
+        }
+    }
+
+    /// Allocates a new segment, after dropping the oldest one if needed
+    fn start_segment(&mut self) -> Result<(), LogError> {
+        if self.count == self.max_segments {
+            self.drop_oldest()?;
+        }
+        let (base_address, size) = self
+            .backend
+            .allocate(self.segment_size)
+            .map_err(|_| LogError::StorageError)?;
+        let number = if self.count > 0 {
+            self.segments[self.count - 1].number + 1
+        } else {
+            0
+        };
+        let mut header = [0u8; SEGMENT_HEADER_SIZE];
+        header[0..4].copy_from_slice(&SEGMENT_MAGIC.to_le_bytes());
+        header[4..8].copy_from_slice(&number.to_le_bytes());
+        header[8..12].copy_from_slice(&self.next_seq.to_le_bytes());
+        header[12..16].copy_from_slice(&self.consumed.to_le_bytes());
+        // Until it is finalized, the segment is erased at start-up
+        let result = self
+            .backend
+            .write(base_address, 0, &header)
+            .and_then(|_| self.backend.finalize(base_address));
+        if result.is_err() {
+            let _ = self.backend.deallocate(base_address);
+            return Err(LogError::StorageError);
+        }
+        self.segments[self.count] = Segment {
+            base_address,
+            size,
+            number,
+            first_seq: self.next_seq,
+        };
+        self.count += 1;
+        self.tail = SEGMENT_HEADER_SIZE as u32;
+        Ok(())
+    }
+
+    fn drop_oldest(&mut self) -> Result<(), LogError> {
+        self.backend
+            .deallocate(self.segments[0].base_address)
+            .map_err(|_| LogError::StorageError)?;
+        self.segments.copy_within(1..self.count, 0);
+        self.count -= 1;
+        Ok(())
+    }
+
+    /// None if the block is not a segment of the log
+    fn read_segment_header(&self, base_address: u32) -> Result<Option<SegmentHeader>, LogError> {
+        let mut raw = [0u8; SEGMENT_HEADER_SIZE];
+        self.read_flash(base_address, 0, &mut raw)?;
+        let word = |i: usize| u32::from_le_bytes([raw[i], raw[i + 1], raw[i + 2], raw[i + 3]]);
+        if word(0) != SEGMENT_MAGIC {
+            return Ok(None);
+        }
+        Ok(Some(SegmentHeader {
+            number: word(4),
+            first_seq: word(8),
+            consumed: word(12),
+        }))
+    }
+
+    /* Helpers */
+
+    fn read_flash(
+        &self,
+        base_address: u32,
+        offset: u32,
+        buffer: &mut [u8],
+    ) -> Result<(), LogError> {
+        self.backend
+            .read(base_address, offset, buffer)
+            .map_err(|_| LogError::StorageError)
+    }
+
+    fn data_block(&self, n: u32) -> Result<Option<(u32, u32)>, LogError> {
+        self.backend
+            .data_block(n)
+            .map_err(|_| LogError::StorageError)
+    }
+
+    #[cfg(test)]
+    fn backend(&mut self) -> &mut B {
+        &mut self.backend
+    }
+}
+
+/*
+    Tests
+*/
+#[cfg(test)]
+mod test {
+    extern crate std;
+    use super::*;
+    use data_block::mock::FakeFlash;
+    use std::vec::Vec;
+
+    const SEGMENT_SIZE: u32 = 256;
+
+    /// Records from the cursor on, in order
+    fn read_all(log: &LogRing<&mut FakeFlash>, cursor: u32) -> Vec<(u32, Vec<u8>)> {
+        let mut records: Vec<(u32, Vec<u8>)> = Vec::new();
+        let mut data = [0u8; MAX_DATA_LEN];
+        let mut cursor = cursor;
+        loop {
+            match log.read(cursor, &mut data) {
+                Ok(entry) => {
+                    records.push((entry.seq, data[0..entry.len].to_vec()));
+                    cursor = entry.seq + 1;
+                }
+                Err(LogError::EndOfLog) => return records,
+                Err(e) => panic!("{:?}", e),
+            }
+        }
+    }
+
+    #[test]
+    fn append_read() {
+        let mut flash = FakeFlash::new(4);
+        let mut log = LogRing::mount(&mut flash, SEGMENT_SIZE, 3).unwrap();
+        assert_eq!(log.read(0, &mut [0u8; 8]), Err(LogError::EndOfLog));
+        assert_eq!(log.append(16, 1000, b"21.5"), Ok(0));
+        assert_eq!(log.append(17, 1500, b"door open"), Ok(1));
+        assert_eq!(log.append(16, 2000, b"21.7"), Ok(2));
+        // Cursor-based
+        let mut data = [0u8; MAX_DATA_LEN];
+        let entry = log.read(1, &mut data).unwrap();
+        assert_eq!(
+            entry,
+            Entry {
+                seq: 1,
+                source: 17,
+                timestamp: 1500,
+                len: 9
+            }
+        );
+        assert_eq!(&data[0..entry.len], b"door open");
+        // Truncated read returns the full length
+        let mut small = [0u8; 2];
+        assert_eq!(log.read(2, &mut small).unwrap().len, 4);
+        assert_eq!(&small, b"21");
+        assert_eq!(log.read(3, &mut data), Err(LogError::EndOfLog));
+        assert_eq!(
+            log.append(16, 0, &[0; MAX_DATA_LEN + 1]),
+            Err(LogError::DataTooLarge)
+        );
+        // Survives a reboot
+        flash.reboot();
+        let mut log = LogRing::mount(&mut flash, SEGMENT_SIZE, 3).unwrap();
+        assert_eq!(
+            read_all(&log, 0),
+            [
+                (0, b"21.5".to_vec()),
+                (1, b"door open".to_vec()),
+                (2, b"21.7".to_vec())
+            ]
+        );
+        assert_eq!(log.append(16, 2500, b""), Ok(3));
+    }
+
+    #[test]
+    fn wrap_around() {
+        let mut flash = FakeFlash::new(4);
+        let mut log = LogRing::mount(&mut flash, SEGMENT_SIZE, 3).unwrap();
+        // Many times the size of the log
+        for i in 0..100u32 {
+            log.append(16, i as u64, &i.to_le_bytes()).unwrap();
+        }
+        let status = log.status();
+        assert_eq!(status.next, 100);
+        assert_eq!(status.segments, 3);
+        assert_eq!(log.backend().block_count(), 3);
+        // The oldest records are gone, the others are all there
+        let records = read_all(&log, 0);
+        assert_eq!(records[0].0, status.first);
+        assert!(status.first > 0);
+        for (i, (seq, data)) in records.iter().enumerate() {
+            assert_eq!(*seq, status.first + i as u32);
+            assert_eq!(data, &seq.to_le_bytes());
+        }
+        assert_eq!(records.len() as u32, status.next - status.first);
+        // Same after a reboot, with less segments configured
+        flash.reboot();
+        let log = LogRing::mount(&mut flash, SEGMENT_SIZE, 2).unwrap();
+        assert_eq!(log.status().next, 100);
+        assert_eq!(log.status().segments, 2);
+        assert_eq!(read_all(&log, 0).last().unwrap().0, 99);
+        // Bad configurations
+        let mut flash = FakeFlash::new(4);
+        assert!(LogRing::mount(&mut flash, 64, 2).is_err());
+        let mut flash = FakeFlash::new(4);
+        assert!(LogRing::mount(&mut flash, SEGMENT_SIZE, MAX_SEGMENTS + 1).is_err());
+    }
+
+    #[test]
+    fn consumed_marker() {
+        let mut flash = FakeFlash::new(4);
+        let mut log = LogRing::mount(&mut flash, SEGMENT_SIZE, 3).unwrap();
+        for i in 0..5u8 {
+            log.append(16, 0, &[i]).unwrap();
+        }
+        assert_eq!(log.consume(6), Err(LogError::InvalidCursor));
+        log.consume(3).unwrap();
+        // Never goes back
+        log.consume(1).unwrap();
+        assert_eq!(log.status().consumed, 3);
+        flash.reboot();
+        let mut log = LogRing::mount(&mut flash, SEGMENT_SIZE, 3).unwrap();
+        assert_eq!(log.status().consumed, 3);
+        // Markers do not take sequence numbers
+        assert_eq!(log.append(16, 0, b"x"), Ok(5));
+        log.consume(6).unwrap();
+        // Kept while the segment of the marker is dropped
+        for i in 0..60u8 {
+            log.append(16, 0, &[i; 8]).unwrap();
+        }
+        assert!(log.status().first > 6);
+        flash.reboot();
+        let log = LogRing::mount(&mut flash, SEGMENT_SIZE, 3).unwrap();
+        assert_eq!(log.status().consumed, 6);
+        assert_eq!(log.status().next, 66);
+    }
+
+    #[derive(Clone, Copy)]
+    enum Op {
+        Append(u8),
+        Consume(u32),
+    }
+
+    #[test]
+    fn power_loss() {
+        // Enough operations to go through some new segments
+        let mut ops: Vec<Op> = Vec::new();
+        for i in 0..40u8 {
+            ops.push(Op::Append(i));
+            if i % 6 == 5 {
+                ops.push(Op::Consume(i as u32 - 2));
+            }
+        }
+        // Cut the power at each step
+        let mut budget: usize = 0;
+        loop {
+            let mut flash = FakeFlash::new(4);
+            let mut model: Vec<Vec<u8>> = Vec::new();
+            let mut consumed: u32 = 0;
+            let mut interrupted: Option<Op> = None;
+            {
+                let mut log = LogRing::mount(&mut flash, SEGMENT_SIZE, 3).unwrap();
+                log.backend().writes_left = Some(budget);
+                for op in &ops {
+                    let result = match op {
+                        Op::Append(i) => log.append(16, *i as u64, &[*i; 20]).map(|_| ()),
+                        Op::Consume(up_to) => log.consume(*up_to),
+                    };
+                    if result.is_err() {
+                        interrupted = Some(*op);
+                        break;
+                    }
+                    match op {
+                        Op::Append(i) => model.push(std::vec![*i; 20]),
+                        Op::Consume(up_to) => consumed = *up_to,
+                    }
+                }
+            }
+            let completed = interrupted.is_none();
+            flash.reboot();
+            let mut log = LogRing::mount(&mut flash, SEGMENT_SIZE, 3).unwrap();
+            // The interrupted operation happened entirely, or not at all
+            let status = log.status();
+            match interrupted {
+                Some(Op::Append(i)) if status.next as usize == model.len() + 1 => {
+                    model.push(std::vec![i; 20])
+                }
+                Some(Op::Consume(up_to)) if status.consumed == up_to => consumed = up_to,
+                _ => (),
+            }
+            assert_eq!(
+                status.next as usize,
+                model.len(),
+                "Records lost after {} writes",
+                budget
+            );
+            assert_eq!(
+                status.consumed, consumed,
+                "Marker lost after {} writes",
+                budget
+            );
+            for (seq, data) in read_all(&log, 0) {
+                assert_eq!(
+                    data, model[seq as usize],
+                    "Corrupted after {} writes",
+                    budget
+                );
+            }
+            // It still works
+            let seq = log.append(16, 0, b"after").unwrap();
+            assert_eq!(read_all(&log, seq), [(seq, b"after".to_vec())]);
+            assert!(log.backend().block_count() <= 3);
+            if completed {
+                break;
+            }
+            budget += 1;
+        }
+    }
+}
@@ -0,0 +1,149 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use data_block::{align_up, crc16_update, COMMIT_SIZE, CRC16_INIT, WORD_SIZE};
+
+/**
+ * Constants
+ */
+pub const RECORD_HEADER_SIZE: usize = 2 * WORD_SIZE;
+
+pub const MAX_DATA_LEN: usize = 128;
+pub const MAX_RECORD_SIZE: usize = RECORD_HEADER_SIZE + align_up(MAX_DATA_LEN) + COMMIT_SIZE;
+
+/// Timestamps are stored in 48 bits
+pub const TIMESTAMP_MASK: u64 = 0xFFFF_FFFF_FFFF;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[repr(u8)]
+pub enum RecordKind {
+    /// Appended by a component
+    Data = 0x01,
+    /// The records before the sequence number in the data have been consumed
+    Consumed = 0x02,
+}
+
+/**
+ * Record header
+ *
+ * +----------+--------+-----+------+-----------+--------+
+ * | Sequence | Source | Len | Kind | Timestamp | CRC-16 |
+ * +----------+--------+-----+------+-----------+--------+
+ * | 4 bytes  | 2 bytes| 1 b.| 1 b. | 6 bytes   | 2 bytes|
+ * +----------+--------+-----+------+-----------+--------+
+ *
+ * followed by the data (padded to a word with 0xFF) and by the commit word.
+ * The CRC covers the first 14 bytes and the data.
+ */
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct RecordHeader {
+    pub seq: u32,
+    pub source: u16,
+    pub len: usize,
+    pub kind: RecordKind,
+    pub timestamp: u64,
+    pub crc: u16,
+}
+
+impl RecordHeader {
+    pub fn new(seq: u32, source: u16, kind: RecordKind, timestamp: u64, data: &[u8]) -> Self {
+        let mut header = Self {
+            seq,
+            source,
+            len: data.len(),
+            kind,
+            timestamp: timestamp & TIMESTAMP_MASK,
+            crc: 0,
+        };
+        header.crc = header.checksum(data);
+        header
+    }
+
+    /// None when the words cannot be a record header (e.g. a torn write)
+    pub fn decode(raw: &[u8; RECORD_HEADER_SIZE]) -> Option<Self> {
+        let kind = match raw[7] {
+            0x01 => RecordKind::Data,
+            0x02 => RecordKind::Consumed,
+            _ => return None,
+        };
+        let len = raw[6] as usize;
+        if len > MAX_DATA_LEN {
+            return None;
+        }
+        if kind == RecordKind::Consumed && len != 4 {
+            return None;
+        }
+        let mut timestamp = [0u8; 8];
+        timestamp[0..6].copy_from_slice(&raw[8..14]);
+        Some(Self {
+            seq: u32::from_le_bytes([raw[0], raw[1], raw[2], raw[3]]),
+            source: u16::from_le_bytes([raw[4], raw[5]]),
+            len,
+            kind,
+            timestamp: u64::from_le_bytes(timestamp),
+            crc: u16::from_le_bytes([raw[14], raw[15]]),
+        })
+    }
+
+    pub fn encode(&self) -> [u8; RECORD_HEADER_SIZE] {
+        let mut raw = [0u8; RECORD_HEADER_SIZE];
+        raw[0..4].copy_from_slice(&self.seq.to_le_bytes());
+        raw[4..6].copy_from_slice(&self.source.to_le_bytes());
+        raw[6] = self.len as u8;
+        raw[7] = self.kind as u8;
+        raw[8..14].copy_from_slice(&self.timestamp.to_le_bytes()[0..6]);
+        raw[14..16].copy_from_slice(&self.crc.to_le_bytes());
+        raw
+    }
+
+    pub fn checksum(&self, data: &[u8]) -> u16 {
+        let raw = self.encode();
+        let crc = crc16_update(CRC16_INIT, &raw[0..14]);
+        crc16_update(crc, data)
+    }
+
+    /// Offset of the commit word from the start of the record
+    pub fn commit_offset(&self) -> usize {
+        RECORD_HEADER_SIZE + align_up(self.len)
+    }
+
+    pub fn size(&self) -> usize {
+        self.commit_offset() + COMMIT_SIZE
+    }
+
+    /// Header, data and padding, the commit word is left out
+    pub fn write_into(&self, data: &[u8], buffer: &mut [u8; MAX_RECORD_SIZE]) -> usize {
+        let end = self.commit_offset();
+        buffer[0..RECORD_HEADER_SIZE].copy_from_slice(&self.encode());
+        let data_end = RECORD_HEADER_SIZE + data.len();
+        buffer[RECORD_HEADER_SIZE..data_end].copy_from_slice(data);
+        buffer[data_end..end].fill(0xFF);
+        end
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn encoding() {
+        let header = RecordHeader::new(7, 16, RecordKind::Data, 0x1234_5678_9ABC, b"21.5C");
+        assert_eq!(RecordHeader::decode(&header.encode()), Some(header));
+        assert_eq!(header.size(), RECORD_HEADER_SIZE + 8 + COMMIT_SIZE);
+        assert_eq!(header.checksum(b"21.5C"), header.crc);
+        assert_ne!(header.checksum(b"21.6C"), header.crc);
+        // Only 48 bits of the timestamp are kept
+        let header = RecordHeader::new(8, 16, RecordKind::Data, u64::MAX, b"");
+        assert_eq!(
+            RecordHeader::decode(&header.encode()).unwrap().timestamp,
+            TIMESTAMP_MASK
+        );
+        // Erased or corrupted words
+        assert_eq!(RecordHeader::decode(&[0xFF; RECORD_HEADER_SIZE]), None);
+        let mut raw = RecordHeader::new(9, 0, RecordKind::Consumed, 0, &[0; 4]).encode();
+        raw[6] = 5;
+        assert_eq!(RecordHeader::decode(&raw), None);
+    }
+}
@@ -38,6 +38,8 @@ pub struct Relocator<const LINKED_FLASH_ADDR: u32, const LINKED_SRAM_ADDR: u32,
 
     new_flash_base: u32,
     new_sram_base: u32,
+    /// Restores the linked addresses of an image relocated at the new bases
+    reverse: bool,
 }
 
 impl<'a, const LINKED_FLASH_ADDR: u32, const LINKED_SRAM_ADDR: u32, const BUFF_SIZE: usize, const RELOC_BUFF_SIZE: usize>
@@ -56,9 +58,62 @@ impl<'a, const LINKED_FLASH_ADDR: u32, const LINKED_SRAM_ADDR: u32, const BUFF_S
             current_file_pos: current_file_pos,
             relocation_buffer: RelocationBuff::new(total_relocations_available),
             current_buffer_pos: 0,
+            reverse: false,
         }
     }
 
+    /// Builds a relocator that undoes the relocations of an image
+    /// previously relocated at flash_base and sram_base.
+    pub fn reverse(
+        flash_base: u32,
+        sram_base: u32,
+        current_file_pos: usize,
+        total_relocations_available: usize,
+    ) -> Self {
+        let mut relocator = Self::new(
+            flash_base,
+            sram_base,
+            current_file_pos,
+            total_relocations_available,
+        );
+        relocator.reverse = true;
+        relocator
+    }
+
+    /// Rebuilds the relocator at a position previously returned by checkpoint(),
+    /// where all the relocations before it were already applied and flushed.
+    pub fn resume(
+        new_flash_base: u32,
+        new_sram_base: u32,
+        current_file_pos: usize,
+        total_relocations_available: usize,
+        applied_relocations: usize,
+    ) -> Self {
+        let mut relocator = Self::new(
+            new_flash_base,
+            new_sram_base,
+            current_file_pos,
+            total_relocations_available,
+        );
+        relocator.relocation_buffer.total_used_relocs = applied_relocations;
+        relocator
+    }
+
+    /// Returns the current position and the number of relocations applied, but only
+    /// when nothing is pending in the working buffer. In this case every relocation before
+    /// the position has been applied and none after it, so the state can be rebuilt from
+    /// these two values alone with resume().
+    pub fn checkpoint(&self) -> Option<(usize, usize)> {
+        if self.working_buffer.read_capacity() > 0 {
+            return None;
+        }
+        let pending = self.relocation_buffer.pending_relocs();
+        Some((
+            self.current_file_pos,
+            self.relocation_buffer.total_used_relocs - pending,
+        ))
+    }
+
     fn flush_working_buffer<T>(
         &mut self,
         num_bytes: usize,
@@ -243,7 +298,12 @@ impl<'a, const LINKED_FLASH_ADDR: u32, const LINKED_SRAM_ADDR: u32, const BUFF_S
     }
 
     fn fix_address(&self, addr: u32) -> u32 {
-        if self.is_flash_addr(addr) {
+        // The relocated addresses are still in the same memory areas
+        if self.reverse && self.is_flash_addr(addr) {
+            return addr - self.new_flash_base + LINKED_FLASH_ADDR;
+        } else if self.reverse && self.is_sram_addr(addr) {
+            return addr - self.new_sram_base + LINKED_SRAM_ADDR;
+        } else if self.is_flash_addr(addr) {
             return addr - LINKED_FLASH_ADDR + self.new_flash_base;
         } else if self.is_sram_addr(addr) {
             return addr - LINKED_SRAM_ADDR + self.new_sram_base;
@@ -394,6 +454,16 @@ impl<'a, const N: usize> RelocationBuff<N> {
     fn is_empty(&self) -> bool {
         self.buff.read_capacity() == 0
     }
+    /// Relocations loaded in the buffer, but still to be applied
+    fn pending_relocs(&self) -> usize {
+        let mut pending: usize = 0;
+        for i in 0..self.buff.read_capacity() {
+            if !self.buff.peek(i).unwrap().is_consumed() {
+                pending += 1;
+            }
+        }
+        return pending;
+    }
 
     fn refill<T>(&mut self, relocator_methods: &mut dyn RelocatorMethods<T>, aux: &mut T) -> Result<bool,()> {
         let yet_to_consume: usize = self.total_available_relocs - self.total_used_relocs;
@@ -106,6 +106,85 @@ mod tests {
         // dst_elf_file.write(&output_buff).unwrap();
     }
 
+    type TestRelocator = relocator::Relocator<LINKED_FLASH_BASE,LINKED_SRAM_BASE,BUFF_SIZE,RELOC_BUFF_SIZE>;
+
+    /// Feeds the image from start_pos in chunks of 32 bytes, returning
+    /// what checkpoint() reported after each of them
+    fn run_relocator(
+        mut relocator: TestRelocator,
+        image: &[u8],
+        start_pos: usize,
+        points: &Vec<u32>,
+        output_buff: &mut Vec<u8>,
+    ) -> Vec<Option<(usize, usize)>> {
+        let mut checkpoints = vec![];
+        for chunk in image[start_pos..].chunks(32) {
+            let mut relocator_methods = FileRelocationMethods {
+                points: points,
+                output_buff: output_buff,
+            };
+            relocator.consume_current_buffer(chunk, &mut relocator_methods, &mut ()).unwrap();
+            checkpoints.push(relocator.checkpoint());
+        }
+        let mut relocator_methods = FileRelocationMethods {
+            points: points,
+            output_buff: output_buff,
+        };
+        relocator.finish(&mut relocator_methods, &mut ()).unwrap();
+        checkpoints
+    }
+
+    #[test]
+    fn resume_test_example1() {
+        let file_path = get_test_file_path("example1/relocations.toml");
+        let points = parse_relocations(&file_path).unwrap().points;
+        let image = std::fs::read(get_test_file_path("example1/image.elf")).unwrap();
+
+        // Relocate the whole image at once
+        let mut reference: Vec<u8> = vec![];
+        let relocator = TestRelocator::new(NEW_FLASH_BASE, NEW_SRAM_BASE, 0, points.len());
+        let reported = run_relocator(relocator, &image, 0, &points, &mut reference);
+        assert_eq!(reference.len(), image.len());
+        // No checkpoint while a relocation is waiting for its bytes
+        assert!(reported.iter().any(|c| c.is_none()));
+        let mut checkpoints: Vec<(usize, usize)> = reported.into_iter().flatten().collect();
+        assert!(checkpoints.iter().any(|(_, applied)| *applied > 0 && *applied < points.len()));
+        checkpoints.dedup_by_key(|(_, applied)| *applied);
+
+        // Resuming from any checkpoint, with only the bytes before it, gives the same image
+        for (position, applied) in checkpoints {
+            let mut output = reference[0..position].to_vec();
+            let relocator = TestRelocator::resume(
+                NEW_FLASH_BASE,
+                NEW_SRAM_BASE,
+                position,
+                points.len(),
+                applied,
+            );
+            run_relocator(relocator, &image, position, &points, &mut output);
+            assert!(output == reference, "resume from {} differs", position);
+        }
+    }
+
+    #[test]
+    fn reverse_test_example1() {
+        let file_path = get_test_file_path("example1/relocations.toml");
+        let points = parse_relocations(&file_path).unwrap().points;
+        let image = std::fs::read(get_test_file_path("example1/image.elf")).unwrap();
+
+        let mut relocated: Vec<u8> = vec![];
+        let relocator = TestRelocator::new(NEW_FLASH_BASE, NEW_SRAM_BASE, 0, points.len());
+        run_relocator(relocator, &image, 0, &points, &mut relocated);
+        assert_eq!(relocated.len(), image.len());
+        assert!(relocated != image);
+
+        // Undoing the relocations gives back the linked image
+        let mut restored: Vec<u8> = vec![];
+        let relocator = TestRelocator::reverse(NEW_FLASH_BASE, NEW_SRAM_BASE, 0, points.len());
+        run_relocator(relocator, &relocated, 0, &points, &mut restored);
+        assert!(restored == image);
+    }
+
     #[bench]
     fn bench_example1(b: &mut Bencher){
         // 213,618,933 ns/iter (+/- 2,446,152)
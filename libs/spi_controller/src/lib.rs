@@ -0,0 +1,365 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+#![no_std]
+
+//! Master side of the SPI peripheral of the STM32F3 and STM32L4, 8 bit frames.
+//! A transaction clocks out the bytes to write then 0xFF while the bytes to read
+//! come in, in lockstep on RXNE or by DMA when channels are available. The chip
+//! select is up to the owner, the NSS pin is not used.
+
+#[cfg(test)]
+mod mock;
+
+/**
+ * Constants
+ */
+/// Longest transaction, the bytes written and read together
+pub const MAX_TRANSFER: usize = 128;
+/// Clocked out while the device answers
+pub const FILL_BYTE: u8 = 0xFF;
+
+// CR1
+const CR1_CPHA: u32 = 1 << 0;
+const CR1_CPOL: u32 = 1 << 1;
+const CR1_MSTR: u32 = 1 << 2;
+const CR1_BR_SHIFT: u32 = 3;
+const CR1_SPE: u32 = 1 << 6;
+const CR1_SSI: u32 = 1 << 8;
+const CR1_SSM: u32 = 1 << 9;
+// CR2
+const CR2_RXDMAEN: u32 = 1 << 0;
+const CR2_TXDMAEN: u32 = 1 << 1;
+const CR2_RXNEIE: u32 = 1 << 6;
+const CR2_DS_8BIT: u32 = 0b0111 << 8;
+const CR2_FRXTH: u32 = 1 << 12;
+// SR
+const SR_RXNE: u32 = 1 << 0;
+const SR_OVR: u32 = 1 << 6;
+const SR_BSY: u32 = 1 << 7;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SpiError {
+    /// More than MAX_TRANSFER bytes, or nothing to do
+    BadLength,
+    /// Not one of the modes 0 to 3
+    BadMode,
+    /// A byte came in before the previous one was read
+    Overrun,
+}
+
+/**
+ * Hardware
+ */
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Register {
+    Cr1,
+    Cr2,
+    Sr,
+    /// To be accessed on 8 bits, a 16 bit access moves two frames
+    Dr,
+}
+
+pub trait Registers {
+    fn read(&mut self, register: Register) -> u32;
+    fn write(&mut self, register: Register, value: u32);
+}
+
+/// A pair of channels feeding DR from a buffer and draining it into another
+pub trait Dma {
+    /// Starts both channels, false when there are none
+    fn start(&mut self, source: *const u8, destination: *mut u8, len: usize) -> bool;
+    /// The last byte has been received
+    fn finished(&mut self) -> bool;
+    fn stop(&mut self);
+}
+
+/// For the boards without DMA, every byte goes through the interrupt
+pub struct NoDma;
+
+impl Dma for NoDma {
+    fn start(&mut self, _source: *const u8, _destination: *mut u8, _len: usize) -> bool {
+        false
+    }
+    fn finished(&mut self) -> bool {
+        false
+    }
+    fn stop(&mut self) {}
+}
+
+/**
+ * Baud rate
+ */
+/// BR field of the fastest clock not above max_frequency, from the kernel
+/// clock of the peripheral: clock / 2^(BR + 1). The slowest one, clock / 256,
+/// when none fits.
+pub fn baud_rate(clock: u32, max_frequency: u32) -> u8 {
+    let mut br: u8 = 0;
+    while br < 0b111 && clock >> (br + 1) > max_frequency {
+        br += 1;
+    }
+    br
+}
+
+/**
+ * Controller
+ */
+/// Outcome of an interrupt
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Poll {
+    Pending,
+    Done(Result<(), SpiError>),
+}
+
+pub struct Controller<R: Registers, D: Dma> {
+    registers: R,
+    dma: D,
+    /// Value of the BR field, the clock is fPCLK / 2^(baud_rate + 1)
+    baud_rate: u8,
+    busy: bool,
+    dma_transfer: bool,
+    tx: [u8; MAX_TRANSFER],
+    rx: [u8; MAX_TRANSFER],
+    write_len: usize,
+    len: usize,
+    pos: usize,
+}
+
+impl<R: Registers, D: Dma> Controller<R, D> {
+    pub fn new(registers: R, dma: D, baud_rate: u8) -> Self {
+        Self {
+            registers,
+            dma,
+            baud_rate: baud_rate & 0b111,
+            busy: false,
+            dma_transfer: false,
+            tx: [0; MAX_TRANSFER],
+            rx: [0; MAX_TRANSFER],
+            write_len: 0,
+            len: 0,
+            pos: 0,
+        }
+    }
+
+    /// Where the bytes to write go, before start
+    pub fn tx_buffer(&mut self) -> &mut [u8] {
+        &mut self.tx
+    }
+
+    /// Bytes read by the last transaction, after the ones written
+    pub fn received(&self) -> &[u8] {
+        if self.pos < self.len {
+            return &[];
+        }
+        &self.rx[self.write_len..self.len]
+    }
+
+    pub fn registers(&mut self) -> &mut R {
+        &mut self.registers
+    }
+
+    /// Writes the first write_len bytes of the tx buffer then reads read_len
+    /// bytes, in the SPI mode (CPOL << 1 | CPHA) of the device
+    pub fn start(&mut self, mode: u8, write_len: usize, read_len: usize) -> Result<(), SpiError> {
+        if mode > 3 {
+            return Err(SpiError::BadMode);
+        }
+        let len = write_len + read_len;
+        if len == 0 || len > MAX_TRANSFER {
+            return Err(SpiError::BadLength);
+        }
+        self.tx[write_len..len].fill(FILL_BYTE);
+        self.write_len = write_len;
+        self.len = len;
+        self.pos = 0;
+
+        let mut cr1 = CR1_MSTR | CR1_SSM | CR1_SSI | (self.baud_rate as u32) << CR1_BR_SHIFT;
+        if mode & 0b01 != 0 {
+            cr1 |= CR1_CPHA;
+        }
+        if mode & 0b10 != 0 {
+            cr1 |= CR1_CPOL;
+        }
+        // The clock polarity is set while the peripheral is disabled
+        self.registers.write(Register::Cr1, cr1);
+        self.dma_transfer = self.dma.start(self.tx.as_ptr(), self.rx.as_mut_ptr(), len);
+        self.busy = true;
+        if self.dma_transfer {
+            // RX requests before the peripheral is enabled, TX ones after
+            self.registers
+                .write(Register::Cr2, CR2_DS_8BIT | CR2_FRXTH | CR2_RXDMAEN);
+            self.registers.write(Register::Cr1, cr1 | CR1_SPE);
+            self.registers.write(
+                Register::Cr2,
+                CR2_DS_8BIT | CR2_FRXTH | CR2_RXDMAEN | CR2_TXDMAEN,
+            );
+        } else {
+            self.registers
+                .write(Register::Cr2, CR2_DS_8BIT | CR2_FRXTH | CR2_RXNEIE);
+            self.registers.write(Register::Cr1, cr1 | CR1_SPE);
+            self.registers.write(Register::Dr, self.tx[0] as u32);
+        }
+        Ok(())
+    }
+
+    /// Handles the flags raised, to be called on each interrupt
+    pub fn on_event(&mut self) -> Poll {
+        if !self.busy {
+            return Poll::Pending;
+        }
+        let sr = self.registers.read(Register::Sr);
+        if sr & SR_OVR != 0 {
+            // Cleared by reading DR then SR
+            self.registers.read(Register::Dr);
+            self.registers.read(Register::Sr);
+            return self.finish(Err(SpiError::Overrun));
+        }
+        if self.dma_transfer {
+            if self.dma.finished() {
+                self.pos = self.len;
+                return self.finish(Ok(()));
+            }
+            return Poll::Pending;
+        }
+        if sr & SR_RXNE != 0 {
+            self.rx[self.pos] = self.registers.read(Register::Dr) as u8;
+            self.pos += 1;
+            if self.pos == self.len {
+                return self.finish(Ok(()));
+            }
+            // One byte in flight, so that RXNE paces the transfer
+            self.registers.write(Register::Dr, self.tx[self.pos] as u32);
+        }
+        Poll::Pending
+    }
+
+    /// Gives up the transaction, when its interrupts do not come
+    pub fn abort(&mut self) {
+        if self.busy {
+            self.pos = 0;
+            self.finish(Ok(()));
+        }
+    }
+
+    fn finish(&mut self, result: Result<(), SpiError>) -> Poll {
+        // The last frame is out once BSY drops, the peripheral can be disabled
+        for _ in 0..1000 {
+            if self.registers.read(Register::Sr) & SR_BSY == 0 {
+                break;
+            }
+        }
+        self.dma.stop();
+        self.registers.write(Register::Cr2, CR2_DS_8BIT | CR2_FRXTH);
+        let cr1 = self.registers.read(Register::Cr1);
+        self.registers.write(Register::Cr1, cr1 & !CR1_SPE);
+        self.busy = false;
+        self.dma_transfer = false;
+        if result.is_err() {
+            self.pos = 0;
+        }
+        Poll::Done(result)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    extern crate std;
+
+    use super::mock::*;
+    use super::*;
+
+    fn run<D: Dma>(controller: &mut Controller<MockSpi, D>) -> Result<(), SpiError> {
+        for _ in 0..1000 {
+            if let Poll::Done(result) = controller.on_event() {
+                return result;
+            }
+        }
+        panic!("The transaction never ends");
+    }
+
+    #[test]
+    fn write_read() {
+        let mut controller = Controller::new(MockSpi::new(&[0, 0xEF, 0x40, 0x18]), NoDma, 0b101);
+        controller.tx_buffer()[0] = 0x9F;
+        controller.start(0, 1, 3).unwrap();
+        run(&mut controller).unwrap();
+        assert_eq!(controller.received(), &[0xEF, 0x40, 0x18]);
+        let device = controller.registers();
+        assert_eq!(&device.mosi[..], &[0x9F, FILL_BYTE, FILL_BYTE, FILL_BYTE]);
+        assert_eq!(device.byte_interrupts, 4);
+        // Disabled between the transactions
+        assert!(!device.enabled());
+    }
+
+    #[test]
+    fn write() {
+        let mut controller = Controller::new(MockSpi::new(&[]), NoDma, 0b101);
+        controller.tx_buffer()[0..2].copy_from_slice(&[0x06, 0x01]);
+        controller.start(0, 2, 0).unwrap();
+        run(&mut controller).unwrap();
+        assert_eq!(controller.received(), &[]);
+        assert_eq!(&controller.registers().mosi[..], &[0x06, 0x01]);
+    }
+
+    #[test]
+    fn modes() {
+        let mut controller = Controller::new(MockSpi::new(&[]), NoDma, 0b011);
+        for mode in 0..4 {
+            controller.tx_buffer()[0] = 0;
+            controller.start(mode, 1, 0).unwrap();
+            run(&mut controller).unwrap();
+            assert_eq!(controller.registers().mode, mode);
+            assert_eq!(controller.registers().baud_rate, 0b011);
+        }
+        assert_eq!(controller.start(4, 1, 0), Err(SpiError::BadMode));
+    }
+
+    #[test]
+    fn overrun() {
+        let mut controller = Controller::new(MockSpi::new(&[1, 2, 3]), NoDma, 0b101);
+        controller.tx_buffer()[0] = 0x03;
+        controller.start(0, 1, 2).unwrap();
+        controller.registers().sr |= SR_OVR;
+        assert_eq!(run(&mut controller), Err(SpiError::Overrun));
+        assert_eq!(controller.received(), &[]);
+        assert_eq!(controller.registers().sr & SR_OVR, 0);
+    }
+
+    #[test]
+    fn bad_length() {
+        let mut controller = Controller::new(MockSpi::new(&[]), NoDma, 0b101);
+        assert_eq!(controller.start(0, 0, 0), Err(SpiError::BadLength));
+        assert_eq!(
+            controller.start(0, 100, MAX_TRANSFER - 99),
+            Err(SpiError::BadLength)
+        );
+    }
+
+    #[test]
+    fn dma() {
+        let (device, dma) = MockSpi::with_dma(&[0, 0, 0xCA, 0xFE]);
+        let mut controller = Controller::new(device, dma, 0b101);
+        controller.tx_buffer()[0..2].copy_from_slice(&[0x0B, 0x00]);
+        controller.start(3, 2, 2).unwrap();
+        run(&mut controller).unwrap();
+        assert_eq!(controller.received(), &[0xCA, 0xFE]);
+        let device = controller.registers();
+        assert_eq!(&device.mosi[..], &[0x0B, 0x00, FILL_BYTE, FILL_BYTE]);
+        assert_eq!(device.byte_interrupts, 0);
+    }
+
+    #[test]
+    fn baud_rate_from_clock() {
+        // 1.25 MHz at most
+        assert_eq!(baud_rate(80_000_000, 1_250_000), 0b101);
+        assert_eq!(baud_rate(72_000_000, 1_250_000), 0b101);
+        assert_eq!(baud_rate(4_000_000, 1_250_000), 0b001);
+        assert_eq!(baud_rate(2_000_000, 1_250_000), 0b000);
+        // Exact division
+        assert_eq!(baud_rate(80_000_000, 10_000_000), 0b010);
+        // Saturates at clock / 256
+        assert_eq!(baud_rate(80_000_000, 100_000), 0b111);
+        assert_eq!(baud_rate(80_000_000, 0), 0b111);
+    }
+}
@@ -0,0 +1,160 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+extern crate std;
+
+use std::{cell::RefCell, rc::Rc, vec::Vec};
+
+use crate::*;
+
+/// Buffers handed to the DMA channels, shared with the bus that uses them
+#[derive(Default)]
+pub struct Channels {
+    buffers: Option<(*const u8, *mut u8, usize)>,
+    done: bool,
+}
+
+pub struct MockDma {
+    channels: Rc<RefCell<Channels>>,
+}
+
+impl Dma for MockDma {
+    fn start(&mut self, source: *const u8, destination: *mut u8, len: usize) -> bool {
+        let mut channels = self.channels.borrow_mut();
+        channels.buffers = Some((source, destination, len));
+        channels.done = false;
+        true
+    }
+    fn finished(&mut self) -> bool {
+        self.channels.borrow().done
+    }
+    fn stop(&mut self) {
+        self.channels.borrow_mut().buffers = None;
+    }
+}
+
+/// Register block with a device on the bus answering a scripted sequence of
+/// bytes, one per byte clocked out, then zeros
+pub struct MockSpi {
+    /// Everything clocked out
+    pub mosi: Vec<u8>,
+    pub sr: u32,
+    /// Mode and BR field when the peripheral was last enabled
+    pub mode: u8,
+    pub baud_rate: u8,
+    /// Bytes read from DR by the controller
+    pub byte_interrupts: usize,
+    miso: Vec<u8>,
+    cr1: u32,
+    cr2: u32,
+    dr: u8,
+    clearing_overrun: bool,
+    channels: Option<Rc<RefCell<Channels>>>,
+}
+
+impl MockSpi {
+    pub fn new(miso: &[u8]) -> Self {
+        Self {
+            mosi: Vec::new(),
+            sr: 0,
+            mode: 0,
+            baud_rate: 0,
+            byte_interrupts: 0,
+            miso: miso.to_vec(),
+            cr1: 0,
+            cr2: 0,
+            dr: 0,
+            clearing_overrun: false,
+            channels: None,
+        }
+    }
+
+    /// The bus, with the channels that move everything once TX requests are on
+    pub fn with_dma(miso: &[u8]) -> (Self, MockDma) {
+        let channels = Rc::new(RefCell::new(Channels::default()));
+        let mut bus = Self::new(miso);
+        bus.channels = Some(channels.clone());
+        (bus, MockDma { channels })
+    }
+
+    pub fn enabled(&self) -> bool {
+        self.cr1 & CR1_SPE != 0
+    }
+
+    fn exchange(&mut self, byte: u8) -> u8 {
+        let answer = self.miso.get(self.mosi.len()).copied().unwrap_or(0);
+        self.mosi.push(byte);
+        answer
+    }
+
+    fn run_dma(&mut self) {
+        if !self.enabled() || self.cr2 & CR2_TXDMAEN == 0 || self.cr2 & CR2_RXDMAEN == 0 {
+            return;
+        }
+        let channels = match &self.channels {
+            Some(channels) => channels.clone(),
+            None => return,
+        };
+        let mut channels = channels.borrow_mut();
+        if let Some((source, destination, len)) = channels.buffers {
+            for i in 0..len {
+                let answer = self.exchange(unsafe { *source.add(i) });
+                unsafe { *destination.add(i) = answer };
+            }
+            channels.done = true;
+        }
+    }
+}
+
+impl Registers for MockSpi {
+    fn read(&mut self, register: Register) -> u32 {
+        match register {
+            Register::Cr1 => self.cr1,
+            Register::Cr2 => self.cr2,
+            Register::Sr => {
+                let sr = self.sr;
+                if self.clearing_overrun {
+                    self.sr &= !SR_OVR;
+                    self.clearing_overrun = false;
+                }
+                sr
+            }
+            Register::Dr => {
+                if self.sr & SR_OVR != 0 {
+                    self.clearing_overrun = true;
+                } else {
+                    self.byte_interrupts += 1;
+                }
+                self.sr &= !SR_RXNE;
+                self.dr as u32
+            }
+        }
+    }
+
+    fn write(&mut self, register: Register, value: u32) {
+        match register {
+            Register::Cr1 => {
+                if value & CR1_SPE != 0 && !self.enabled() {
+                    self.mode = (value & (CR1_CPOL | CR1_CPHA)) as u8;
+                    self.baud_rate = ((value >> CR1_BR_SHIFT) & 0b111) as u8;
+                }
+                self.cr1 = value;
+                self.run_dma();
+            }
+            Register::Cr2 => {
+                self.cr2 = value;
+                self.run_dma();
+            }
+            Register::Sr => {}
+            Register::Dr => {
+                assert!(self.enabled(), "DR written with the peripheral disabled");
+                self.dr = self.exchange(value as u8);
+                if self.sr & SR_RXNE != 0 {
+                    self.sr |= SR_OVR;
+                }
+                self.sr |= SR_RXNE;
+            }
+        }
+    }
+}
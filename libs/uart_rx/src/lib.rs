@@ -0,0 +1,621 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+#![no_std]
+
+//! Receive side of the uart-channel component. The DMA writes the bytes of the
+//! USART in a ring, read behind it; the bytes are then kept in queues until a
+//! reader takes them, split in packets by channel when the line is muxed.
+
+use core::cmp::min;
+
+/**
+ * Constants
+ */
+/// Start of a packet on a muxed line, repeated PREAMBLE_LEN times
+pub const PREAMBLE_BYTE: u8 = 0b1010_1010;
+pub const PREAMBLE_LEN: usize = 4;
+/// Channel ID and data length, both big endian
+pub const HEADER_LEN: usize = 4;
+
+/// Bytes lost on reception since boot
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct RxStats {
+    /// Bytes not read in time from the USART (ORE), e.g. with the DMA paused and no RTS
+    pub usart_overruns: u32,
+    /// Times the DMA wrote over bytes not read yet. The whole ring is dropped.
+    pub ring_overruns: u32,
+    /// Bytes with no room in their queue, or for a channel nobody reads
+    pub dropped_bytes: u32,
+    /// Packets that failed the CRC check
+    pub crc_errors: u32,
+}
+
+/**
+ * DMA ring
+ */
+/// Read side of a buffer of N bytes (N even) written by the DMA in circular
+/// mode. The DMA raises the half transfer flag when it reaches N / 2, the
+/// transfer complete flag when it wraps to 0.
+pub struct DmaRing<const N: usize> {
+    /// First byte not read yet
+    tail: usize,
+}
+
+/// Bytes made readable by `DmaRing::advance`
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Advance {
+    /// Position of the first byte, the bytes wrap at the end of the ring
+    pub start: usize,
+    pub len: usize,
+    /// The DMA lapped the tail: nothing is readable, the ring restarts from the DMA
+    pub overrun: bool,
+    /// Flags of the DMA to clear, raised while writing these bytes
+    pub clear_half: bool,
+    pub clear_full: bool,
+}
+
+impl Advance {
+    /// The bytes in the ring, in at most two slices
+    pub fn slices<'a>(&self, ring: &'a [u8]) -> (&'a [u8], &'a [u8]) {
+        let end = self.start + self.len;
+        if end <= ring.len() {
+            (&ring[self.start..end], &[])
+        } else {
+            (&ring[self.start..], &ring[..end - ring.len()])
+        }
+    }
+}
+
+impl<const N: usize> DmaRing<N> {
+    pub const fn new() -> Self {
+        Self { tail: 0 }
+    }
+
+    pub fn tail(&self) -> usize {
+        self.tail
+    }
+
+    /// Takes the bytes up to `head`, the position of the DMA (N - CNDTR). The
+    /// flags `half` and `full` must be read before the counter, so that each
+    /// flag raised is for a boundary between the tail and the head: otherwise
+    /// the DMA went around the ring since the last call.
+    pub fn advance(&mut self, head: usize, half: bool, full: bool) -> Advance {
+        let head = head % N;
+        let len = (head + N - self.tail) % N;
+        // The DMA went through the positions tail + 1 ..= head
+        let crosses = |boundary: usize| {
+            let distance = (boundary + N - self.tail) % N;
+            distance != 0 && distance <= len
+        };
+        let crosses_half = crosses(N / 2);
+        let crosses_full = crosses(0);
+        let overrun = (half && !crosses_half) || (full && !crosses_full);
+        let start = self.tail;
+        self.tail = head;
+        Advance {
+            start: match overrun {
+                true => head,
+                false => start,
+            },
+            len: match overrun {
+                true => 0,
+                false => len,
+            },
+            overrun,
+            clear_half: half || crosses_half,
+            clear_full: full || crosses_full,
+        }
+    }
+}
+
+impl<const N: usize> Default for DmaRing<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/**
+ * Queues
+ */
+/// Bytes received and not read yet. They are pushed pending, then committed
+/// (readable) or discarded all together, so that the data of a packet is seen
+/// only after its CRC.
+pub struct ByteQueue<const N: usize> {
+    buffer: [u8; N],
+    /// First readable byte
+    head: usize,
+    /// Readable bytes from the head
+    committed: usize,
+    /// Bytes pushed after the committed ones
+    pending: usize,
+}
+
+impl<const N: usize> ByteQueue<N> {
+    const EMPTY: Self = Self::new();
+
+    pub const fn new() -> Self {
+        Self {
+            buffer: [0x00; N],
+            head: 0,
+            committed: 0,
+            pending: 0,
+        }
+    }
+
+    /// Readable bytes
+    pub fn len(&self) -> usize {
+        self.committed
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.committed == 0
+    }
+
+    pub fn free(&self) -> usize {
+        N - self.committed - self.pending
+    }
+
+    /// Pushes as many bytes as there is room for, returns how many
+    pub fn push(&mut self, data: &[u8]) -> usize {
+        let count = min(data.len(), self.free());
+        for &byte in &data[..count] {
+            self.buffer[(self.head + self.committed + self.pending) % N] = byte;
+            self.pending += 1;
+        }
+        count
+    }
+
+    /// Makes the pending bytes readable
+    pub fn commit(&mut self) {
+        self.committed += self.pending;
+        self.pending = 0;
+    }
+
+    /// Drops the pending bytes, returns how many
+    pub fn discard(&mut self) -> usize {
+        core::mem::replace(&mut self.pending, 0)
+    }
+
+    /// Readable bytes up to the end of the buffer, the others follow after `consume`
+    pub fn front(&self) -> &[u8] {
+        &self.buffer[self.head..min(self.head + self.committed, N)]
+    }
+
+    pub fn consume(&mut self, count: usize) {
+        let count = min(count, self.committed);
+        self.head = (self.head + count) % N;
+        self.committed -= count;
+    }
+
+    /// Drops all the bytes, returns how many
+    pub fn clear(&mut self) -> usize {
+        let count = self.committed + self.pending;
+        self.head = 0;
+        self.committed = 0;
+        self.pending = 0;
+        count
+    }
+}
+
+impl<const N: usize> Default for ByteQueue<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Where the deframer puts the data of the packets
+pub trait Sink {
+    /// Keeps bytes of the packet being received, returns how many it kept
+    fn push(&mut self, channel: u16, data: &[u8]) -> usize;
+    /// The packet passed the CRC check
+    fn commit(&mut self, channel: u16);
+    /// The packet is dropped, returns how many of its bytes were kept
+    fn discard(&mut self, channel: u16) -> usize;
+}
+
+/// A queue for each of up to C channels, assigned as their readers show up.
+/// The packets of the other channels are dropped.
+pub struct ChannelQueues<const C: usize, const N: usize> {
+    channels: [Option<u16>; C],
+    queues: [ByteQueue<N>; C],
+}
+
+impl<const C: usize, const N: usize> ChannelQueues<C, N> {
+    pub const fn new() -> Self {
+        Self {
+            channels: [None; C],
+            queues: [ByteQueue::EMPTY; C],
+        }
+    }
+
+    pub fn queue(&mut self, channel: u16) -> Option<&mut ByteQueue<N>> {
+        let slot = self.channels.iter().position(|c| *c == Some(channel))?;
+        Some(&mut self.queues[slot])
+    }
+
+    /// Assigns a queue to the channel if it has none: a free one, or else the
+    /// one of a channel that is not `busy`, whose bytes are dropped. Returns
+    /// how many, None when all the channels are busy.
+    pub fn open<F: Fn(u16) -> bool>(&mut self, channel: u16, busy: F) -> Option<usize> {
+        if self.channels.contains(&Some(channel)) {
+            return Some(0);
+        }
+        let slot = self
+            .channels
+            .iter()
+            .position(Option::is_none)
+            .or_else(|| self.channels.iter().position(|c| !busy(c.unwrap())))?;
+        self.channels[slot] = Some(channel);
+        Some(self.queues[slot].clear())
+    }
+
+    /// Room left in the fullest queue
+    pub fn min_free(&self) -> usize {
+        self.channels
+            .iter()
+            .zip(self.queues.iter())
+            .filter(|(channel, _)| channel.is_some())
+            .map(|(_, queue)| queue.free())
+            .min()
+            .unwrap_or(N)
+    }
+}
+
+impl<const C: usize, const N: usize> Default for ChannelQueues<C, N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const C: usize, const N: usize> Sink for ChannelQueues<C, N> {
+    fn push(&mut self, channel: u16, data: &[u8]) -> usize {
+        self.queue(channel).map_or(0, |queue| queue.push(data))
+    }
+
+    fn commit(&mut self, channel: u16) {
+        if let Some(queue) = self.queue(channel) {
+            queue.commit();
+        }
+    }
+
+    fn discard(&mut self, channel: u16) -> usize {
+        self.queue(channel).map_or(0, |queue| queue.discard())
+    }
+}
+
+/**
+ * Deframer
+ */
+/// Splits the bytes of a muxed line in packets
+/// ```text
+/// +----------+------------+---------------+----------+--------+
+/// | Preamble | Channel ID | Packet Length |   Data   | CRC-8  |
+/// +----------+------------+---------------+----------+--------+
+/// | 4 bytes  | 2 bytes    | 2 bytes       | pl_bytes | 1 byte |
+/// +----------+------------+---------------+----------+--------+
+/// ```
+/// The CRC covers the header and the data. The bytes come in chunks of any
+/// size, the deframer keeps its place between them.
+pub struct Deframer {
+    /// Preamble bytes seen in a row
+    preamble: usize,
+    header: [u8; HEADER_LEN],
+    header_pos: usize,
+    /// Channel of the packet being received, once the header is complete
+    channel: Option<u16>,
+    /// Data bytes still to come, the CRC excluded
+    remaining: usize,
+    crc: u8,
+    /// Some bytes found no room, the packet is dropped at its end
+    truncated: bool,
+}
+
+impl Deframer {
+    pub const fn new() -> Self {
+        Self {
+            preamble: 0,
+            header: [0x00; HEADER_LEN],
+            header_pos: 0,
+            channel: None,
+            remaining: 0,
+            crc: 0,
+            truncated: false,
+        }
+    }
+
+    /// Looks for the preamble of the next packet
+    pub fn reset(&mut self) {
+        *self = Self::new();
+    }
+
+    pub fn receive<S: Sink>(&mut self, mut data: &[u8], sink: &mut S, stats: &mut RxStats) {
+        while let Some(&byte) = data.first() {
+            let channel = match self.channel {
+                Some(channel) => channel,
+                None => {
+                    self.receive_header(byte);
+                    data = &data[1..];
+                    continue;
+                }
+            };
+            if self.remaining > 0 {
+                // Data of the packet, kept until the CRC
+                let count = min(self.remaining, data.len());
+                let chunk = &data[..count];
+                for &byte in chunk {
+                    crc8_update(&mut self.crc, byte);
+                }
+                let kept = match self.truncated {
+                    true => 0,
+                    false => sink.push(channel, chunk),
+                };
+                if kept < count {
+                    self.truncated = true;
+                    stats.dropped_bytes = stats.dropped_bytes.saturating_add((count - kept) as u32);
+                }
+                self.remaining -= count;
+                data = &data[count..];
+            } else {
+                // CRC, the end of the packet
+                if byte != self.crc {
+                    sink.discard(channel);
+                    stats.crc_errors = stats.crc_errors.saturating_add(1);
+                } else if self.truncated {
+                    let dropped = sink.discard(channel);
+                    stats.dropped_bytes = stats.dropped_bytes.saturating_add(dropped as u32);
+                } else {
+                    sink.commit(channel);
+                }
+                data = &data[1..];
+                self.reset();
+            }
+        }
+    }
+
+    fn receive_header(&mut self, byte: u8) {
+        if self.preamble < PREAMBLE_LEN {
+            // Any other byte restarts the search
+            self.preamble = match byte == PREAMBLE_BYTE {
+                true => self.preamble + 1,
+                false => 0,
+            };
+            return;
+        }
+        self.header[self.header_pos] = byte;
+        self.header_pos += 1;
+        if self.header_pos == HEADER_LEN {
+            self.channel = Some(u16::from_be_bytes([self.header[0], self.header[1]]));
+            self.remaining = u16::from_be_bytes([self.header[2], self.header[3]]) as usize;
+            self.crc = 0;
+            for byte in self.header {
+                crc8_update(&mut self.crc, byte);
+            }
+        }
+    }
+}
+
+impl Default for Deframer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Optimized Dallas (now Maxim) iButton 8-bit CRC calculation.
+/// Polynomial: x^8 + x^5 + x^4 + 1 (0x8C)
+/// Initial value: 0x0
+pub fn crc8_update(crc: &mut u8, byte: u8) {
+    let mut tmp = (*crc) ^ byte;
+    for _ in 0..8u8 {
+        if tmp & 0x01 == 1 {
+            tmp = (tmp >> 1) ^ 0x8C;
+        } else {
+            tmp >>= 1;
+        }
+    }
+    *crc = tmp;
+}
+
+#[cfg(test)]
+mod test {
+    extern crate std;
+    use super::*;
+    use std::vec::Vec;
+
+    fn frame(channel: u16, data: &[u8]) -> Vec<u8> {
+        let mut frame = Vec::from([PREAMBLE_BYTE; PREAMBLE_LEN]);
+        frame.extend_from_slice(&channel.to_be_bytes());
+        frame.extend_from_slice(&(data.len() as u16).to_be_bytes());
+        frame.extend_from_slice(data);
+        let mut crc = 0;
+        for &byte in &frame[PREAMBLE_LEN..] {
+            crc8_update(&mut crc, byte);
+        }
+        frame.push(crc);
+        frame
+    }
+
+    fn drain<const N: usize>(queue: &mut ByteQueue<N>) -> Vec<u8> {
+        let mut data = Vec::new();
+        while !queue.is_empty() {
+            let front = queue.front();
+            data.extend_from_slice(front);
+            let count = front.len();
+            queue.consume(count);
+        }
+        data
+    }
+
+    /// Two channels with a reader, noise and a packet for nobody in between
+    fn line() -> Vec<u8> {
+        let mut line = Vec::from([0x00, PREAMBLE_BYTE, 0x17]);
+        line.extend(frame(1, b"hello"));
+        line.extend([PREAMBLE_BYTE, PREAMBLE_BYTE, 0x55]);
+        line.extend(frame(9, b"nobody"));
+        line.extend(frame(2, &[PREAMBLE_BYTE; 7]));
+        line.extend(frame(1, b""));
+        line.extend(frame(1, b" world"));
+        line
+    }
+
+    fn check_line(queues: &mut ChannelQueues<2, 32>, stats: &RxStats) {
+        assert_eq!(drain(queues.queue(1).unwrap()), b"hello world");
+        assert_eq!(drain(queues.queue(2).unwrap()), [PREAMBLE_BYTE; 7]);
+        assert_eq!(stats.dropped_bytes, 6);
+        assert_eq!(stats.crc_errors, 0);
+    }
+
+    fn receive_in_chunks(line: &[u8], mut next_chunk: impl FnMut(usize) -> usize) {
+        let mut queues = ChannelQueues::<2, 32>::new();
+        queues.open(1, |_| true).unwrap();
+        queues.open(2, |_| true).unwrap();
+        let mut deframer = Deframer::new();
+        let mut stats = RxStats::default();
+        let mut data = line;
+        while !data.is_empty() {
+            let count = min(next_chunk(data.len()), data.len());
+            deframer.receive(&data[..count], &mut queues, &mut stats);
+            data = &data[count..];
+        }
+        check_line(&mut queues, &stats);
+    }
+
+    #[test]
+    fn packets_split_anywhere() {
+        let line = line();
+        // In one go, byte by byte, cut in two at each position
+        receive_in_chunks(&line, |len| len);
+        receive_in_chunks(&line, |_| 1);
+        for cut in 1..line.len() {
+            let mut first = true;
+            receive_in_chunks(&line, |len| match core::mem::replace(&mut first, false) {
+                true => cut,
+                false => len,
+            });
+        }
+        // Random chunks
+        let mut seed: u32 = 12345;
+        for _ in 0..200 {
+            receive_in_chunks(&line, |_| {
+                seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12345);
+                1 + (seed >> 16) as usize % 13
+            });
+        }
+    }
+
+    #[test]
+    fn bad_crc_drops_packet() {
+        let mut queues = ChannelQueues::<2, 32>::new();
+        queues.open(1, |_| true).unwrap();
+        let mut deframer = Deframer::new();
+        let mut stats = RxStats::default();
+        let mut bad = frame(1, b"garbled");
+        bad[PREAMBLE_LEN + HEADER_LEN + 2] ^= 0x20;
+        deframer.receive(&bad[..10], &mut queues, &mut stats);
+        deframer.receive(&bad[10..], &mut queues, &mut stats);
+        assert!(queues.queue(1).unwrap().is_empty());
+        assert_eq!(stats.crc_errors, 1);
+        // The next packet is received
+        deframer.receive(&frame(1, b"fine"), &mut queues, &mut stats);
+        assert_eq!(drain(queues.queue(1).unwrap()), b"fine");
+    }
+
+    #[test]
+    fn full_queue_drops_packet() {
+        let mut queues = ChannelQueues::<1, 8>::new();
+        queues.open(1, |_| true).unwrap();
+        let mut deframer = Deframer::new();
+        let mut stats = RxStats::default();
+        deframer.receive(&frame(1, b"abcde"), &mut queues, &mut stats);
+        // Only 3 bytes of room left
+        deframer.receive(&frame(1, b"fghij"), &mut queues, &mut stats);
+        assert_eq!(stats.dropped_bytes, 5);
+        assert_eq!(drain(queues.queue(1).unwrap()), b"abcde");
+        deframer.receive(&frame(1, b"klm"), &mut queues, &mut stats);
+        assert_eq!(drain(queues.queue(1).unwrap()), b"klm");
+        assert_eq!(stats.crc_errors, 0);
+    }
+
+    #[test]
+    fn queues_reassigned_when_idle() {
+        let mut queues = ChannelQueues::<2, 8>::new();
+        assert_eq!(queues.open(1, |_| true), Some(0));
+        assert_eq!(queues.open(2, |_| true), Some(0));
+        assert_eq!(queues.open(1, |_| true), Some(0));
+        queues.queue(2).unwrap().push(b"abc");
+        queues.queue(2).unwrap().commit();
+        assert_eq!(queues.min_free(), 5);
+        // Both channels have a reader
+        assert_eq!(queues.open(3, |_| true), None);
+        // The reader of channel 2 left
+        assert_eq!(queues.open(3, |channel| channel == 1), Some(3));
+        assert!(queues.queue(2).is_none());
+        assert!(queues.queue(3).unwrap().is_empty());
+    }
+
+    #[test]
+    fn queue_wraps() {
+        let mut queue = ByteQueue::<8>::new();
+        assert_eq!(queue.push(b"abcdef"), 6);
+        queue.commit();
+        queue.consume(4);
+        assert_eq!(queue.push(b"ghijkl"), 6);
+        assert_eq!(queue.push(b"m"), 0);
+        // Pending bytes are not readable
+        assert_eq!(queue.len(), 2);
+        queue.commit();
+        assert_eq!(queue.front(), b"efgh");
+        assert_eq!(drain(&mut queue), b"efghijkl");
+        assert_eq!(queue.free(), 8);
+    }
+
+    #[test]
+    fn ring_reads_behind_dma() {
+        let mut ring = DmaRing::<16>::new();
+        let buffer: Vec<u8> = (0..16).collect();
+        let advance = ring.advance(5, false, false);
+        assert_eq!(advance.slices(&buffer), (&buffer[0..5], &[][..]));
+        assert!(!advance.clear_half && !advance.clear_full);
+        // Nothing new
+        assert_eq!(ring.advance(5, false, false).len, 0);
+        // Half transfer
+        let advance = ring.advance(8, true, false);
+        assert_eq!(advance.slices(&buffer), (&buffer[5..8], &[][..]));
+        assert!(advance.clear_half && !advance.clear_full);
+        // Wrapped
+        let advance = ring.advance(3, false, true);
+        assert_eq!(advance.slices(&buffer), (&buffer[8..], &buffer[..3]));
+        assert!(!advance.overrun && advance.clear_full);
+        // Transfer complete, the head read as 0
+        let advance = ring.advance(16, true, true);
+        assert_eq!((advance.start, advance.len), (3, 13));
+        assert!(!advance.overrun && advance.clear_half && advance.clear_full);
+        assert_eq!(ring.tail(), 0);
+    }
+
+    #[test]
+    fn ring_detects_lap() {
+        let mut ring = DmaRing::<16>::new();
+        ring.advance(4, false, false);
+        // Around the ring and 2 bytes more
+        let advance = ring.advance(6, true, true);
+        assert!(advance.overrun);
+        assert_eq!(advance.len, 0);
+        assert!(advance.clear_half && advance.clear_full);
+        // Restarts from the DMA
+        assert_eq!(ring.advance(9, true, false).start, 6);
+        // Exactly one lap
+        assert!(ring.advance(9, true, true).overrun);
+    }
+
+    #[test]
+    fn ring_flag_after_read_is_cleared() {
+        let mut ring = DmaRing::<16>::new();
+        // The DMA reached the half between the read of the flags and of the counter
+        let advance = ring.advance(10, false, false);
+        assert!(!advance.overrun && advance.clear_half);
+        // Not a lap later
+        assert!(!ring.advance(12, false, false).overrun);
+    }
+}
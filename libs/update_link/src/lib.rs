@@ -0,0 +1,457 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+#![no_std]
+
+mod crc;
+
+pub use crc::{crc16, crc32};
+
+/*
+ * Constants
+ */
+pub const MAX_PAYLOAD_SIZE: usize = 64;
+pub const DATA_FRAME_SIZE: usize = 1 + 1 + 2 + 1 + MAX_PAYLOAD_SIZE + 4;
+pub const CONTROL_FRAME_SIZE: usize = 1 + 2 + 1 + 2;
+
+const DATA_FRAME: u8 = 0xD1;
+const ACK_FRAME: u8 = 0xA1;
+const NAK_FRAME: u8 = 0xA2;
+
+/// Streams of the update protocol
+pub const STREAM_VARIABLE_HEADER: u8 = 0x01;
+pub const STREAM_PAYLOAD: u8 = 0x02;
+pub const STREAM_BENCHMARK: u8 = 0x03;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[repr(u8)]
+pub enum LinkError {
+    InvalidType = 1,
+    InvalidCRC = 2,
+    InvalidLength = 3,
+    UnknownReason = 4,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[repr(u8)]
+pub enum NakReason {
+    /// The frame was corrupted
+    InvalidCRC = 1,
+    /// A following frame arrived first
+    Missing = 2,
+    /// Nothing arrived in time
+    Timeout = 3,
+}
+
+impl TryFrom<u8> for NakReason {
+    type Error = LinkError;
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        match value {
+            1 => Ok(NakReason::InvalidCRC),
+            2 => Ok(NakReason::Missing),
+            3 => Ok(NakReason::Timeout),
+            _ => Err(LinkError::UnknownReason),
+        }
+    }
+}
+
+/*
+ * Frames
+ */
+/// True if the byte can be the start of a control frame
+pub fn is_control_frame(first_byte: u8) -> bool {
+    first_byte == ACK_FRAME || first_byte == NAK_FRAME
+}
+
+#[derive(Clone, Copy)]
+pub struct DataFrame {
+    /// Transfer the frame belongs to, to discard late frames of the previous one
+    pub stream: u8,
+    pub sequence: u16,
+    pub length: u8,
+    pub payload: [u8; MAX_PAYLOAD_SIZE],
+}
+
+impl DataFrame {
+    pub fn new(stream: u8, sequence: u16, data: &[u8]) -> Self {
+        assert!(data.len() <= MAX_PAYLOAD_SIZE);
+        let mut payload: [u8; MAX_PAYLOAD_SIZE] = [0x00; MAX_PAYLOAD_SIZE];
+        payload[0..data.len()].copy_from_slice(data);
+        Self {
+            stream,
+            sequence,
+            length: data.len() as u8,
+            payload,
+        }
+    }
+
+    pub fn data(&self) -> &[u8] {
+        &self.payload[0..self.length as usize]
+    }
+
+    pub fn encode(&self) -> [u8; DATA_FRAME_SIZE] {
+        let mut buffer: [u8; DATA_FRAME_SIZE] = [0x00; DATA_FRAME_SIZE];
+        buffer[0] = DATA_FRAME;
+        buffer[1] = self.stream;
+        buffer[2..4].copy_from_slice(&self.sequence.to_le_bytes());
+        buffer[4] = self.length;
+        buffer[5..5 + MAX_PAYLOAD_SIZE].copy_from_slice(&self.payload);
+        let crc = crc32(&buffer[0..DATA_FRAME_SIZE - 4]);
+        buffer[DATA_FRAME_SIZE - 4..].copy_from_slice(&crc.to_le_bytes());
+        buffer
+    }
+
+    pub fn decode(buffer: &[u8]) -> Result<Self, LinkError> {
+        if buffer.len() != DATA_FRAME_SIZE {
+            return Err(LinkError::InvalidLength);
+        }
+        if buffer[0] != DATA_FRAME {
+            return Err(LinkError::InvalidType);
+        }
+        let crc = u32::from_le_bytes(buffer[DATA_FRAME_SIZE - 4..].try_into().unwrap());
+        if crc != crc32(&buffer[0..DATA_FRAME_SIZE - 4]) {
+            return Err(LinkError::InvalidCRC);
+        }
+        if buffer[4] as usize > MAX_PAYLOAD_SIZE {
+            return Err(LinkError::InvalidLength);
+        }
+        Ok(Self {
+            stream: buffer[1],
+            sequence: u16::from_le_bytes([buffer[2], buffer[3]]),
+            length: buffer[4],
+            payload: buffer[5..5 + MAX_PAYLOAD_SIZE].try_into().unwrap(),
+        })
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ControlFrame {
+    /// Every frame up to the sequence was received,
+    /// with the number of frames the receiver can accept
+    Ack { sequence: u16, window: u8 },
+    /// The frame with the sequence must be sent again
+    Nak { sequence: u16, reason: NakReason },
+}
+
+impl ControlFrame {
+    pub fn encode(&self) -> [u8; CONTROL_FRAME_SIZE] {
+        let mut buffer: [u8; CONTROL_FRAME_SIZE] = [0x00; CONTROL_FRAME_SIZE];
+        let (frame_type, sequence, arg) = match self {
+            ControlFrame::Ack { sequence, window } => (ACK_FRAME, *sequence, *window),
+            ControlFrame::Nak { sequence, reason } => (NAK_FRAME, *sequence, *reason as u8),
+        };
+        buffer[0] = frame_type;
+        buffer[1..3].copy_from_slice(&sequence.to_le_bytes());
+        buffer[3] = arg;
+        let crc = crc16(&buffer[0..CONTROL_FRAME_SIZE - 2]);
+        buffer[CONTROL_FRAME_SIZE - 2..].copy_from_slice(&crc.to_le_bytes());
+        buffer
+    }
+
+    pub fn decode(buffer: &[u8]) -> Result<Self, LinkError> {
+        if buffer.len() != CONTROL_FRAME_SIZE {
+            return Err(LinkError::InvalidLength);
+        }
+        let crc = u16::from_le_bytes([buffer[4], buffer[5]]);
+        if crc != crc16(&buffer[0..CONTROL_FRAME_SIZE - 2]) {
+            return Err(LinkError::InvalidCRC);
+        }
+        let sequence = u16::from_le_bytes([buffer[1], buffer[2]]);
+        match buffer[0] {
+            ACK_FRAME => Ok(ControlFrame::Ack {
+                sequence,
+                window: buffer[3],
+            }),
+            NAK_FRAME => Ok(ControlFrame::Nak {
+                sequence,
+                reason: NakReason::try_from(buffer[3])?,
+            }),
+            _ => Err(LinkError::InvalidType),
+        }
+    }
+}
+
+/*
+ * Receiver side
+ */
+
+/// Rebuilds a stream of total_size bytes, buffering up to WINDOW
+/// frames arrived out of order.
+pub struct LinkReceiver<const WINDOW: usize> {
+    stream: u8,
+    total_frames: u16,
+    /// Sequence of the next frame to deliver
+    expected: u16,
+    slots: [Option<DataFrame>; WINDOW],
+}
+
+impl<const WINDOW: usize> LinkReceiver<WINDOW> {
+    pub fn new(stream: u8, total_size: usize) -> Self {
+        Self {
+            stream,
+            total_frames: frames_for(total_size),
+            expected: 0,
+            slots: [None; WINDOW],
+        }
+    }
+
+    pub fn is_complete(&self) -> bool {
+        self.expected == self.total_frames
+    }
+
+    /// What to answer when nothing arrived in time
+    pub fn timeout(&self) -> ControlFrame {
+        ControlFrame::Nak {
+            sequence: self.expected,
+            reason: NakReason::Timeout,
+        }
+    }
+
+    fn ack(&self) -> ControlFrame {
+        ControlFrame::Ack {
+            // Wraps to 0xFFFF before the first frame, i.e. nothing received yet
+            sequence: self.expected.wrapping_sub(1),
+            window: WINDOW as u8,
+        }
+    }
+
+    /// Processes a raw frame, calling deliver with the data in order.
+    /// Returns the control frame to send back.
+    pub fn receive<F, E>(&mut self, raw: &[u8], mut deliver: F) -> Result<ControlFrame, E>
+    where
+        F: FnMut(&[u8]) -> Result<(), E>,
+    {
+        // 1. Check the frame is intact, otherwise the sequence cannot be trusted
+        let frame = match DataFrame::decode(raw) {
+            Ok(frame) => frame,
+            Err(_) => {
+                return Ok(ControlFrame::Nak {
+                    sequence: self.expected,
+                    reason: NakReason::InvalidCRC,
+                })
+            }
+        };
+        // 2. Already delivered (i.e. our ACK was lost) or out of the stream
+        let distance = frame.sequence.wrapping_sub(self.expected) as usize;
+        if frame.stream != self.stream || frame.sequence >= self.total_frames || distance >= WINDOW
+        {
+            return Ok(self.ack());
+        }
+        // 3. Keep it
+        self.slots[frame.sequence as usize % WINDOW] = Some(frame);
+        // 4. Deliver all the frames now in order
+        while let Some(frame) = self.slots[self.expected as usize % WINDOW].take() {
+            deliver(frame.data())?;
+            self.expected += 1;
+            if self.is_complete() {
+                break;
+            }
+        }
+        // 5. If there is a hole, ask for it immediately
+        if distance > 0 && !self.is_complete() {
+            return Ok(ControlFrame::Nak {
+                sequence: self.expected,
+                reason: NakReason::Missing,
+            });
+        }
+        Ok(self.ack())
+    }
+}
+
+/*
+ * Sender side
+ */
+
+/// Splits data in frames, keeping at most window of them in flight
+pub struct LinkSender<'a> {
+    stream: u8,
+    data: &'a [u8],
+    total_frames: u16,
+    /// First frame not acknowledged yet
+    base: u16,
+    /// Next frame never sent
+    next: u16,
+    window: usize,
+    max_window: usize,
+}
+
+impl<'a> LinkSender<'a> {
+    /// The actual window starts at 1, then follows the one advertised
+    /// by the receiver, without exceeding max_window
+    pub fn new(stream: u8, data: &'a [u8], max_window: usize) -> Self {
+        Self {
+            stream,
+            data,
+            total_frames: frames_for(data.len()),
+            base: 0,
+            next: 0,
+            window: 1,
+            max_window: core::cmp::max(max_window, 1),
+        }
+    }
+
+    pub fn is_complete(&self) -> bool {
+        self.base == self.total_frames
+    }
+
+    pub fn total_frames(&self) -> u16 {
+        self.total_frames
+    }
+
+    /// Frames confirmed by the receiver
+    pub fn acknowledged(&self) -> u16 {
+        self.base
+    }
+
+    pub fn frame(&self, sequence: u16) -> DataFrame {
+        let start = sequence as usize * MAX_PAYLOAD_SIZE;
+        let end = core::cmp::min(start + MAX_PAYLOAD_SIZE, self.data.len());
+        DataFrame::new(self.stream, sequence, &self.data[start..end])
+    }
+
+    /// Next new frame to send, if the window allows it
+    pub fn next_frame(&mut self) -> Option<DataFrame> {
+        if self.next >= self.total_frames || (self.next - self.base) as usize >= self.window {
+            return None;
+        }
+        let frame = self.frame(self.next);
+        self.next += 1;
+        Some(frame)
+    }
+
+    /// Updates the window, returning the frame to send again (if any)
+    pub fn on_control(&mut self, control: ControlFrame) -> Option<DataFrame> {
+        match control {
+            ControlFrame::Ack { sequence, window } => {
+                // 0xFFFF means nothing was received yet
+                let acked = sequence.wrapping_add(1);
+                if acked > self.base && acked <= self.next {
+                    self.base = acked;
+                }
+                self.window = core::cmp::min(core::cmp::max(window as usize, 1), self.max_window);
+                None
+            }
+            ControlFrame::Nak { sequence, .. } => {
+                // Everything before was received
+                if sequence > self.base && sequence <= self.next {
+                    self.base = sequence;
+                }
+                if sequence < self.next {
+                    return Some(self.frame(sequence));
+                }
+                None
+            }
+        }
+    }
+
+    /// Nothing was heard from the receiver: send again the oldest frame in flight
+    pub fn on_timeout(&mut self) -> Option<DataFrame> {
+        if self.base < self.next {
+            return Some(self.frame(self.base));
+        }
+        None
+    }
+}
+
+fn frames_for(size: usize) -> u16 {
+    let mut frames = size / MAX_PAYLOAD_SIZE;
+    if frames * MAX_PAYLOAD_SIZE < size {
+        frames += 1;
+    }
+    frames as u16
+}
+
+/*
+    Tests
+*/
+#[cfg(test)]
+mod test {
+    extern crate std;
+    use super::*;
+    use std::vec::Vec;
+
+    fn transfer(data: &[u8], drop_frames: &[u16]) -> Vec<u8> {
+        let mut sender = LinkSender::new(1, data, 4);
+        let mut receiver = LinkReceiver::<4>::new(1, data.len());
+        let mut output: Vec<u8> = Vec::new();
+        let mut dropped: Vec<u16> = Vec::new();
+        let mut in_flight: Vec<[u8; DATA_FRAME_SIZE]> = Vec::new();
+        while !sender.is_complete() {
+            while let Some(frame) = sender.next_frame() {
+                in_flight.push(frame.encode());
+            }
+            if in_flight.is_empty() {
+                if let Some(frame) = sender.on_timeout() {
+                    in_flight.push(frame.encode());
+                }
+                continue;
+            }
+            let raw = in_flight.remove(0);
+            let sequence = u16::from_le_bytes([raw[2], raw[3]]);
+            // Each frame in the list is lost the first time only
+            if drop_frames.contains(&sequence) && !dropped.contains(&sequence) {
+                dropped.push(sequence);
+                continue;
+            }
+            let control = receiver
+                .receive(&raw, |d| -> Result<(), ()> {
+                    output.extend_from_slice(d);
+                    Ok(())
+                })
+                .unwrap();
+            let decoded = ControlFrame::decode(&control.encode()).unwrap();
+            if let Some(frame) = sender.on_control(decoded) {
+                in_flight.push(frame.encode());
+            }
+        }
+        assert!(receiver.is_complete());
+        output
+    }
+
+    #[test]
+    fn in_order_transfer() {
+        let data: Vec<u8> = (0..1000u32).map(|x| x as u8).collect();
+        assert_eq!(transfer(&data, &[]), data);
+    }
+
+    #[test]
+    fn selective_retransmission() {
+        let data: Vec<u8> = (0..1000u32).map(|x| (x * 7) as u8).collect();
+        assert_eq!(transfer(&data, &[1, 5, 6, 15]), data);
+    }
+
+    #[test]
+    fn corrupted_frame() {
+        let mut receiver = LinkReceiver::<2>::new(1, 100);
+        let mut raw = DataFrame::new(1, 0, &[0x01; 64]).encode();
+        raw[10] ^= 0xFF;
+        let control = receiver.receive(&raw, |_| -> Result<(), ()> { Ok(()) });
+        assert_eq!(
+            control,
+            Ok(ControlFrame::Nak {
+                sequence: 0,
+                reason: NakReason::InvalidCRC
+            })
+        );
+    }
+
+    #[test]
+    fn late_frame_of_previous_stream() {
+        let mut receiver = LinkReceiver::<2>::new(2, 100);
+        let raw = DataFrame::new(1, 0, &[0x01; 64]).encode();
+        let mut delivered = false;
+        let control = receiver.receive(&raw, |_| -> Result<(), ()> {
+            delivered = true;
+            Ok(())
+        });
+        assert!(!delivered);
+        assert_eq!(
+            control,
+            Ok(ControlFrame::Ack {
+                sequence: 0xFFFF,
+                window: 2
+            })
+        );
+    }
+}
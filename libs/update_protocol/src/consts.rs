@@ -0,0 +1,19 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+pub const READ_TIMEOUT_TICKS: u32 = 50_000;
+pub const LINKED_FLASH_BASE: u32 = 0x0800_0000;
+pub const LINKED_SRAM_BASE: u32 = 0x2000_0000;
+pub const BUFF_SIZE: usize = 128;
+pub const RELOC_BUFF_SIZE: usize = 16;
+/// Decompressed (or patched) bytes processed at once
+pub const DECODED_CHUNK_SIZE: usize = 64;
+/// Bytes of the delta source read from flash at once
+pub const SOURCE_CHUNK_SIZE: usize = 64;
+/// Fragments that can be received out of order before they are written
+pub const LINK_WINDOW: usize = 4;
+/// Consecutive timeouts before giving up a transfer
+pub const LINK_MAX_TIMEOUTS: u32 = 3;
+/// Write granularity of the flash, checkpoints are recorded only at its boundaries
+pub const FLASH_WRITE_GRANULARITY: u32 = 8;
@@ -2,10 +2,8 @@
 // License, v. 2.0. If a copy of the MPL was not distributed with this
 // file, You can obtain one at https://mozilla.org/MPL/2.0/.
 
-/**
- * Simple implementation of CRC-8-Dallas/Maxim.
- * Maybe in the future we can exploit hardware CRC unit to offload CPU.
- */
+//! Simple implementation of CRC-8-Dallas/Maxim.
+//! Maybe in the future we can exploit hardware CRC unit to offload CPU.
 
 /// Optimized Dallas (now Maxim) iButton 8-bit CRC calculation.
 /// Polynomial: x^8 + x^5 + x^4 + 1 (0x8C)
@@ -20,4 +18,13 @@ pub fn crc8_update(crc: &mut u8, byte: u8) {
         }
     }
     *crc = tmp;
-}
\ No newline at end of file
+}
+
+/// CRC of a whole buffer
+pub fn crc8(data: &[u8]) -> u8 {
+    let mut crc: u8 = 0x00;
+    for b in data {
+        crc8_update(&mut crc, *b);
+    }
+    crc
+}
@@ -0,0 +1,212 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use abi::flash::BlockType;
+use cbf_lite::CbfFile;
+use delta::SourceHash;
+use relocator::Relocator;
+use unwrap_lite::UnwrapLite;
+
+use crate::consts::*;
+use crate::messages::*;
+use crate::platform::UpdatePlatform;
+use crate::utils::{wrap_cbf_error, FlashReader};
+
+/// Installed component a delta is applied to
+#[derive(Clone, Copy)]
+pub struct DeltaSource {
+    pub block_base_address: u32,
+    pub block_size: u32,
+    pub sram_base_address: u32,
+    pub patch_size: u32,
+}
+
+impl DeltaSource {
+    pub fn flash_reader<'p, P: UpdatePlatform>(&self, platform: &'p P) -> FlashReader<'p, P> {
+        FlashReader::from(platform, self.block_base_address, self.block_size)
+    }
+}
+
+/// Searches the installed component the delta was made for. Its payload, once
+/// the relocations are undone, must match the hash of the original image.
+pub fn find_delta_source<P: UpdatePlatform>(
+    platform: &P,
+    msg: &DeltaSourceMessage,
+) -> Result<DeltaSource, MessageError> {
+    let blocks = platform.blocks().map_err(|_| MessageError::FlashError)?;
+    for block_num in 0..blocks {
+        let block = platform
+            .nth_block(block_num)
+            .map_err(|_| MessageError::FlashError)?;
+        // The source is an installed component
+        if block.block_type != BlockType::COMPONENT || !block.finalized {
+            continue;
+        }
+        let source = DeltaSource {
+            block_base_address: block.block_base_address,
+            block_size: block.block_size,
+            sram_base_address: block.sram_base_address,
+            patch_size: msg.get_patch_size(),
+        };
+        let flash_reader = source.flash_reader(platform);
+        let cbf = match CbfFile::from_reader(&flash_reader) {
+            Ok(cbf) => cbf,
+            Err(_) => continue,
+        };
+        let cbf_base = wrap_cbf_error(cbf.header_base())?;
+        if cbf_base.component_id() != msg.get_component_id() {
+            continue;
+        }
+        // Another version of the same component (e.g. an interrupted update) may be installed
+        if cbf_base.component_version() != msg.get_component_version() {
+            continue;
+        }
+        // Hash the original payload
+        let mut reader = SourceReader::new(
+            platform,
+            &cbf,
+            source.block_base_address,
+            source.sram_base_address,
+        )?;
+        let mut hash = SourceHash::new();
+        for _ in 0..wrap_cbf_error(cbf.payload_size())? {
+            hash.update(&[reader.next_byte()?]);
+        }
+        if hash.finish() != msg.get_source_hash() {
+            return Err(MessageError::DeltaSourceMismatch);
+        }
+        return Ok(source);
+    }
+    Err(MessageError::DeltaSourceMismatch)
+}
+
+/// Un-relocated bytes, waiting to be read
+pub struct SourceBuff {
+    data: [u8; BUFF_SIZE + SOURCE_CHUNK_SIZE],
+    pos: usize,
+    len: usize,
+}
+
+struct SourceRelocator<'a, 'cbf> {
+    cbf: &'a CbfFile<'cbf>,
+    num_relocations: usize,
+}
+
+impl<'a, 'cbf> relocator::RelocatorMethods<SourceBuff> for SourceRelocator<'a, 'cbf> {
+    fn read_relocations(
+        &self,
+        start_index: usize,
+        dst: &mut [u32],
+        _buff: &mut SourceBuff,
+    ) -> Result<usize, ()> {
+        assert!(dst.len() <= self.num_relocations - start_index);
+        for (r, d) in dst.iter_mut().enumerate() {
+            let reloc = self
+                .cbf
+                .relocation_nth((start_index + r) as u32)
+                .map_err(|_| ())?;
+            *d = reloc.value();
+        }
+        Ok(dst.len())
+    }
+    fn flush(&mut self, _position: usize, src: &[u8], buff: &mut SourceBuff) -> Result<(), ()> {
+        // Only called when the buffer is empty, so there is always room
+        buff.data[buff.len..buff.len + src.len()].copy_from_slice(src);
+        buff.len += src.len();
+        Ok(())
+    }
+}
+
+/// Reads the payload of the installed component as it was before the relocation.
+/// The relocations are undone while reading, so the payload can be read only forward.
+pub struct SourceReader<'a, 'cbf, P: UpdatePlatform> {
+    platform: &'a P,
+    cbf: &'a CbfFile<'cbf>,
+    block_base_address: u32,
+    num_relocations: usize,
+    /// Next position in the CBF to read from flash
+    read_pos: u32,
+    end_pos: u32,
+    relocator: Option<Relocator<LINKED_FLASH_BASE, LINKED_SRAM_BASE, BUFF_SIZE, RELOC_BUFF_SIZE>>,
+    buff: SourceBuff,
+}
+
+impl<'a, 'cbf, P: UpdatePlatform> SourceReader<'a, 'cbf, P> {
+    pub fn new(
+        platform: &'a P,
+        cbf: &'a CbfFile<'cbf>,
+        block_base_address: u32,
+        sram_base_address: u32,
+    ) -> Result<Self, MessageError> {
+        let payload_start_offset = wrap_cbf_error(cbf.get_readonly_payload())?.get_offset();
+        let payload_size = wrap_cbf_error(cbf.payload_size())?;
+        let num_relocations = wrap_cbf_error(cbf.header_base())?.num_relocations() as usize;
+        // Same bases used when the component was installed
+        let relocator = Relocator::reverse(
+            block_base_address + 8 + payload_start_offset,
+            sram_base_address,
+            payload_start_offset as usize,
+            num_relocations,
+        );
+        Ok(Self {
+            platform,
+            cbf,
+            block_base_address,
+            num_relocations,
+            read_pos: payload_start_offset,
+            end_pos: payload_start_offset + payload_size,
+            relocator: Some(relocator),
+            buff: SourceBuff {
+                data: [0x00; BUFF_SIZE + SOURCE_CHUNK_SIZE],
+                pos: 0,
+                len: 0,
+            },
+        })
+    }
+
+    /// Returns the next byte of the original payload
+    pub fn next_byte(&mut self) -> Result<u8, MessageError> {
+        while self.buff.pos == self.buff.len {
+            self.refill()?;
+        }
+        let b = self.buff.data[self.buff.pos];
+        self.buff.pos += 1;
+        Ok(b)
+    }
+
+    fn refill(&mut self) -> Result<(), MessageError> {
+        self.buff.pos = 0;
+        self.buff.len = 0;
+        let mut reloc_methods = SourceRelocator {
+            cbf: self.cbf,
+            num_relocations: self.num_relocations,
+        };
+        if self.read_pos < self.end_pos {
+            // Undo the relocations on the next chunk
+            let mut chunk: [u8; SOURCE_CHUNK_SIZE] = [0x00; SOURCE_CHUNK_SIZE];
+            let size = core::cmp::min(SOURCE_CHUNK_SIZE as u32, self.end_pos - self.read_pos);
+            self.platform
+                .read_stream(
+                    self.block_base_address,
+                    self.read_pos,
+                    &mut chunk[0..size as usize],
+                )
+                .map_err(|_| MessageError::FlashError)?;
+            self.read_pos += size;
+            self.relocator
+                .as_mut()
+                .unwrap_lite()
+                .consume_current_buffer(&chunk[0..size as usize], &mut reloc_methods, &mut self.buff)
+                .map_err(|_| MessageError::FlashError)
+        } else if let Some(relocator) = self.relocator.take() {
+            // Whatever is left in the relocator
+            relocator
+                .finish(&mut reloc_methods, &mut self.buff)
+                .map_err(|_| MessageError::FlashError)
+        } else {
+            // The delta reads past the end of the source
+            Err(MessageError::FailedCBFValidation)
+        }
+    }
+}
@@ -0,0 +1,22 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+#![no_std]
+
+#[cfg(test)]
+extern crate std;
+#[cfg(test)]
+mod mock;
+
+pub mod consts;
+pub mod crc;
+pub mod delta_source;
+pub mod messages;
+mod platform;
+mod session;
+mod update;
+pub mod utils;
+
+pub use platform::{Allocation, Block, PlatformError, UpdatePlatform};
+pub use update::{component_add_update, component_delta_update, component_resume, link_benchmark};
@@ -0,0 +1,862 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use cbf_lite::{BufferReaderImpl, CbfFile};
+use delta::PatchError;
+use heatshrink::DecoderError;
+use update_transport::TransportError;
+
+use crate::crc::crc8;
+
+/**
+ * Generic enums
+ */
+#[derive(Clone, Copy, Debug)]
+#[repr(u8)]
+pub enum MessageError {
+    InvalidSize = 0xE1,
+    InvalidCRC = 0xE2,
+    InvalidOperation = 0xE3,
+    CannotReadCBF = 0xE4,
+    NotEnoughSpace = 0xE5,
+    FlashError = 0xE6,
+    TimeoutError = 0xE7,
+    FailedCBFValidation = 0xE8,
+    DependencyError = 0xE9,
+    MissingDependency = 0xEA,
+    IllegalDowngrade = 0xEB,
+    CannotFindComponent = 0xEC,
+    //CannotFindVersion = 0xED,
+    CannotStartComponent = 0xEE,
+    NoResumableSession = 0xEF,
+    DeltaSourceMismatch = 0xF0,
+    LogUnavailable = 0xF1,
+    InvalidCursor = 0xF2,
+    ChannelError = 0xFF,
+}
+impl From<TransportError> for MessageError {
+    fn from(x: TransportError) -> Self {
+        match x {
+            TransportError::ReadTimeOut => MessageError::TimeoutError,
+            TransportError::ChannelError => MessageError::ChannelError,
+        }
+    }
+}
+/// A compressed payload that does not expand to the expected image
+impl From<DecoderError> for MessageError {
+    fn from(_: DecoderError) -> Self {
+        MessageError::FailedCBFValidation
+    }
+}
+/// A delta that does not produce the expected image
+impl From<PatchError> for MessageError {
+    fn from(_: PatchError) -> Self {
+        MessageError::FailedCBFValidation
+    }
+}
+
+#[derive(Clone, Copy)]
+#[repr(u8)]
+pub enum OperationType {
+    ComponentDelta = 0xC8,
+    LinkBenchmark = 0xC9,
+    ComponentUpdate = 0xCA,
+    SystemInfo = 0xCB,
+    ComponentResume = 0xCC,
+    CoreDumpRead = 0xCD,
+    ComponentErase = 0xCE,
+    CoreDumpErase = 0xCF,
+    ComponentRead = 0xD0,
+    LogRead = 0xD1,
+}
+
+/**
+ * Generic Messages
+ */
+/// Hello Message
+pub struct HelloMessage {
+    operation: OperationType,
+}
+impl TryFrom<u8> for OperationType {
+    type Error = MessageError;
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        match value {
+            0xC8 => Ok(OperationType::ComponentDelta),
+            0xC9 => Ok(OperationType::LinkBenchmark),
+            0xCA => Ok(OperationType::ComponentUpdate),
+            0xCB => Ok(OperationType::SystemInfo),
+            0xCC => Ok(OperationType::ComponentResume),
+            0xCD => Ok(OperationType::CoreDumpRead),
+            0xCE => Ok(OperationType::ComponentErase),
+            0xCF => Ok(OperationType::CoreDumpErase),
+            0xD0 => Ok(OperationType::ComponentRead),
+            0xD1 => Ok(OperationType::LogRead),
+            _ => Err(MessageError::InvalidOperation),
+        }
+    }
+}
+impl<'a> HelloMessage {
+    pub fn from(buffer: &'a [u8]) -> Result<Self, MessageError> {
+        // Validate buffer
+        let op = Self::validate(buffer)?;
+        // Return instance
+        Ok(Self {
+            operation: op,
+        })
+    }
+    pub const fn get_size() -> usize {
+        2
+    }
+    fn validate(buffer: &'a [u8]) -> Result<OperationType, MessageError> {
+        // Check message size
+        if buffer.len() != Self::get_size() {
+            return Err(MessageError::InvalidSize);
+        }
+        // Check OP
+        let op = OperationType::try_from(buffer[0])?;
+        // Check CRC
+        let crc = crc8(&buffer[0..buffer.len() - 1]);
+        if crc != buffer[buffer.len() - 1] {
+            return Err(MessageError::InvalidCRC);
+        }
+        // Return
+        Ok(op)
+    }
+    pub fn get_operation(&self) -> OperationType {
+        self.operation
+    }
+}
+
+/// Hello Response Message
+pub struct HelloResponseMessage {
+    operation: OperationType,
+}
+
+impl HelloResponseMessage {
+    pub fn new(operation: OperationType) -> Self {
+        Self {
+            operation,
+        }
+    }
+    const fn get_size() -> usize {
+        7
+    }
+    pub fn get_raw(&self) -> [u8; Self::get_size()] {
+        let mut buffer: [u8; Self::get_size()] = [0x00; Self::get_size()];
+        buffer[0] = b'O';
+        buffer[1] = b'L';
+        buffer[2] = b'L';
+        buffer[3] = b'E';
+        buffer[4] = b'H';
+        buffer[5] = self.operation as u8;
+        buffer[6] = crc8(&buffer[0..6]);
+        buffer
+    }
+}
+
+/// Raw Incoming Packet
+pub struct RawPacket {
+    //buffer: &'a [u8],
+}
+
+impl RawPacket {
+    pub fn validate(buffer: &[u8]) -> Result<(), MessageError> {
+        // Check CRC
+        let crc = crc8(&buffer[0..buffer.len() - 1]);
+        if crc != buffer[buffer.len() - 1] {
+            return Err(MessageError::InvalidCRC);
+        }
+        // Return
+        Ok(())
+    }
+}
+
+/**
+ * Component Update
+ * - Enums
+ */
+#[repr(u8)]
+pub enum ComponentUpdateCommand {
+    SendComponentFixedHeader = 0x01,
+    SendComponentVariableHeader = 0x02,
+    SendComponentPayload = 0x03,
+    SendComponentTrailer = 0x04,
+    SendSessionId = 0x05,
+    ResumeFromOffset = 0x06,
+    SendCompressedSize = 0x07,
+    SendDeltaSource = 0x08,
+}
+
+#[repr(u8)]
+pub enum ComponentUpdateResponse {
+    Success = 0xFF,
+}
+
+/**
+ * Component Update
+ * - Messages
+ */
+pub struct FixedHeaderMessage<'a> {
+    buffer: &'a [u8],
+}
+
+impl<'a> FixedHeaderMessage<'a> {
+    pub fn from(buffer: &'a [u8]) -> Result<Self, MessageError> {
+        // Validate buffer
+        Self::validate(buffer)?;
+        // Return instance
+        Ok(Self { buffer })
+    }
+    pub const fn get_size() -> usize {
+        cbf_lite::FIXED_HEADER_SIZE + 1
+    }
+    pub fn get_raw(&self) -> &'a [u8] {
+        &self.buffer[0..self.buffer.len() - 1]
+    }
+    fn validate(buffer: &'a [u8]) -> Result<(), MessageError> {
+        // Check message size
+        if buffer.len() != Self::get_size() {
+            return Err(MessageError::InvalidSize);
+        }
+        // Check CRC
+        let crc = crc8(&buffer[0..buffer.len() - 1]);
+        if crc != buffer[buffer.len() - 1] {
+            return Err(MessageError::InvalidCRC);
+        }
+        // Check header
+        let buff_reader = BufferReaderImpl::from(&buffer[0..buffer.len() - 1]);
+        let cbf = CbfFile::from_reader(&buff_reader);
+        if cbf.is_err() {
+            return Err(MessageError::CannotReadCBF);
+        }
+        // Return
+        Ok(())
+    }
+}
+
+/// Identifies the interrupted update to resume
+pub struct SessionIdMessage {
+    session_id: u32,
+}
+
+impl SessionIdMessage {
+    pub fn from(buffer: &[u8]) -> Result<Self, MessageError> {
+        // Check message size
+        if buffer.len() != Self::get_size() {
+            return Err(MessageError::InvalidSize);
+        }
+        // Check CRC
+        RawPacket::validate(buffer)?;
+        // Return instance
+        Ok(Self {
+            session_id: u32::from_le_bytes(buffer[0..4].try_into().unwrap()),
+        })
+    }
+    pub const fn get_size() -> usize {
+        5
+    }
+    pub fn get_session_id(&self) -> u32 {
+        self.session_id
+    }
+}
+
+/// Length of the compressed payload stream
+pub struct CompressedSizeMessage {
+    compressed_size: u32,
+}
+
+impl CompressedSizeMessage {
+    pub fn from(buffer: &[u8]) -> Result<Self, MessageError> {
+        // Check message size
+        if buffer.len() != Self::get_size() {
+            return Err(MessageError::InvalidSize);
+        }
+        // Check CRC
+        RawPacket::validate(buffer)?;
+        // Return instance
+        Ok(Self {
+            compressed_size: u32::from_le_bytes(buffer[0..4].try_into().unwrap()),
+        })
+    }
+    pub const fn get_size() -> usize {
+        5
+    }
+    pub fn get_compressed_size(&self) -> u32 {
+        self.compressed_size
+    }
+}
+
+/// Installed component the delta was made for, and length of the patch
+pub struct DeltaSourceMessage {
+    component_id: u16,
+    component_version: u32,
+    source_hash: u32,
+    patch_size: u32,
+}
+
+impl DeltaSourceMessage {
+    pub fn from(buffer: &[u8]) -> Result<Self, MessageError> {
+        // Check message size
+        if buffer.len() != Self::get_size() {
+            return Err(MessageError::InvalidSize);
+        }
+        // Check CRC
+        RawPacket::validate(buffer)?;
+        // Return instance
+        Ok(Self {
+            component_id: u16::from_le_bytes(buffer[0..2].try_into().unwrap()),
+            component_version: u32::from_le_bytes(buffer[2..6].try_into().unwrap()),
+            source_hash: u32::from_le_bytes(buffer[6..10].try_into().unwrap()),
+            patch_size: u32::from_le_bytes(buffer[10..14].try_into().unwrap()),
+        })
+    }
+    pub const fn get_size() -> usize {
+        15
+    }
+    pub fn get_component_id(&self) -> u16 {
+        self.component_id
+    }
+    pub fn get_component_version(&self) -> u32 {
+        self.component_version
+    }
+    pub fn get_source_hash(&self) -> u32 {
+        self.source_hash
+    }
+    pub fn get_patch_size(&self) -> u32 {
+        self.patch_size
+    }
+}
+
+/// Progress of the interrupted update: the offset in the image from where
+/// to continue, and the checksum of the original data received up to there
+pub struct ResumeStatusMessage {
+    committed_offset: u32,
+    validation_checksum: u32,
+}
+
+impl ResumeStatusMessage {
+    pub fn new(committed_offset: u32, validation_checksum: u32) -> Self {
+        Self {
+            committed_offset,
+            validation_checksum,
+        }
+    }
+    pub const fn get_size() -> usize {
+        10
+    }
+    pub fn get_raw(&self) -> [u8; Self::get_size()] {
+        let mut buffer: [u8; Self::get_size()] = [0x00; Self::get_size()];
+        buffer[0] = ComponentUpdateCommand::ResumeFromOffset as u8;
+        buffer[1..5].copy_from_slice(&self.committed_offset.to_le_bytes());
+        buffer[5..9].copy_from_slice(&self.validation_checksum.to_le_bytes());
+        let crc = crc8(&buffer[0..buffer.len() - 1]);
+        buffer[buffer.len() - 1] = crc;
+        buffer
+    }
+}
+
+/**
+ * Link Benchmark
+ */
+#[repr(u8)]
+pub enum LinkBenchmarkCommand {
+    SendBenchmarkSize = 0x01,
+}
+
+#[repr(u8)]
+pub enum LinkBenchmarkResponse {
+    Success = 0xFF,
+}
+
+/// Number of bytes the tool is going to stream
+pub struct BenchmarkSizeMessage {
+    size: u32,
+}
+
+impl BenchmarkSizeMessage {
+    pub fn from(buffer: &[u8]) -> Result<Self, MessageError> {
+        // Check message size
+        if buffer.len() != Self::get_size() {
+            return Err(MessageError::InvalidSize);
+        }
+        // Check CRC
+        RawPacket::validate(buffer)?;
+        // Return instance
+        Ok(Self {
+            size: u32::from_le_bytes(buffer[0..4].try_into().unwrap()),
+        })
+    }
+    pub const fn get_size() -> usize {
+        5
+    }
+    pub fn get_benchmark_size(&self) -> u32 {
+        self.size
+    }
+}
+
+/* 
+pub struct ComponentIDPacket<'a> {
+    buffer: &'a [u8],
+}
+
+impl<'a> ComponentIDPacket<'a> {
+    pub fn from(buffer: &'a [u8]) -> Result<Self, MessageError> {
+        // Validate buffer
+        Self::validate(buffer)?;
+        // Return instance
+        Ok(Self { buffer: buffer })
+    }
+    pub const fn get_size() -> usize {
+        7
+    }
+    pub fn get_component_id(&self) -> u16 {
+        // Little endian encoding
+        self.buffer[0] as u16 | ((self.buffer[1] as u16) << 8)
+    }
+    pub fn get_component_version(&self) -> u32 {
+        u32_from_le_bytes(&self.buffer[2..2+4])
+    }
+    fn validate(buffer: &'a [u8]) -> Result<(), MessageError> {
+        // Check len
+        if buffer.len() != Self::get_size() {
+            return Err(MessageError::InvalidSize);
+        }
+        // Check CRC
+        let crc = crc8(&buffer[0..buffer.len() - 1]);
+        if crc != buffer[buffer.len() - 1] {
+            return Err(MessageError::InvalidCRC);
+        }
+        // Return
+        Ok(())
+    }
+} */
+
+/* 
+/**
+ * Component Erase
+ */
+#[repr(u8)]
+pub enum ComponentEraseCommand {
+    SendComponentID = 0x01,
+}
+
+#[repr(u8)]
+pub enum ComponentEraseResponse {
+    CannotFindComponent = 0xE1,
+    CannotFindVersion = 0xE2,
+    GenericFailure = 0xEF,
+    Success = 0xFF,
+}
+*/
+
+/**
+ * System Info
+ */
+pub const NO_MORE_COMPONENTS: u16 = 0x0000;
+
+bitflags::bitflags! {
+    #[repr(transparent)]
+    pub struct ComponentStatus: u16 {
+        const NONE = 0;
+        /// The cbf of the component is intact
+        const CBF_VALID = 1 << 0;
+    }
+}
+
+/// Fields are public, as the message is a plain list of values
+pub struct ComponentInfoMessage {
+    pub component_id: u16,
+    pub component_version: u32,
+    pub allocated_flash: u32,
+    pub allocated_ram: u32,
+    pub component_status: ComponentStatus,
+    pub block_base_address: u32,
+    pub sram_base_address: u32,
+    /// Followed by as many ComponentDependencyMessage
+    pub num_dependencies: u16,
+}
+
+impl ComponentInfoMessage {
+    pub const fn get_size() -> usize {
+        27
+    }
+    pub fn write_to_buffer(&self, buffer: &mut [u8; Self::get_size()]) {
+        // Write fields
+        let mut pos: usize = 0;
+        for b in self.component_id.to_le_bytes() {
+            buffer[pos] = b;
+            pos += 1;
+        }
+        for b in self.component_version.to_le_bytes() {
+            buffer[pos] = b;
+            pos += 1;
+        }
+        for b in self.allocated_flash.to_le_bytes() {
+            buffer[pos] = b;
+            pos += 1;
+        }
+        for b in self.allocated_ram.to_le_bytes() {
+            buffer[pos] = b;
+            pos += 1;
+        }
+        for b in self.component_status.bits.to_le_bytes() {
+            buffer[pos] = b;
+            pos += 1;
+        }
+        for b in self.block_base_address.to_le_bytes() {
+            buffer[pos] = b;
+            pos += 1;
+        }
+        for b in self.sram_base_address.to_le_bytes() {
+            buffer[pos] = b;
+            pos += 1;
+        }
+        for b in self.num_dependencies.to_le_bytes() {
+            buffer[pos] = b;
+            pos += 1;
+        }
+        // Compute CRC-8
+        let crc = crc8(&buffer[0..buffer.len() - 1]);
+        buffer[buffer.len()-1] = crc;
+    }
+}
+
+/// A dependency of an installed component, as in its CBF (a version of 0 means no bound)
+pub struct ComponentDependencyMessage {
+    component_id: u16,
+    min_version: u32,
+    max_version: u32,
+}
+
+impl ComponentDependencyMessage {
+    pub fn new(component_id: u16, min_version: u32, max_version: u32) -> Self {
+        Self {
+            component_id,
+            min_version,
+            max_version,
+        }
+    }
+    pub const fn get_size() -> usize {
+        11
+    }
+    pub fn get_raw(&self) -> [u8; Self::get_size()] {
+        let mut buffer: [u8; Self::get_size()] = [0x00; Self::get_size()];
+        buffer[0..2].copy_from_slice(&self.component_id.to_le_bytes());
+        buffer[2..6].copy_from_slice(&self.min_version.to_le_bytes());
+        buffer[6..10].copy_from_slice(&self.max_version.to_le_bytes());
+        let crc = crc8(&buffer[0..buffer.len() - 1]);
+        buffer[buffer.len() - 1] = crc;
+        buffer
+    }
+}
+
+/// Cause of the last reset, sent after the components
+pub struct ResetCauseMessage {
+    cause: u8,
+    component: u16,
+}
+
+impl ResetCauseMessage {
+    pub fn new(cause: u8, component: u16) -> Self {
+        Self {
+            cause,
+            component,
+        }
+    }
+    pub const fn get_size() -> usize {
+        4
+    }
+    pub fn get_raw(&self) -> [u8; Self::get_size()] {
+        let mut buffer: [u8; Self::get_size()] = [0x00; Self::get_size()];
+        buffer[0] = self.cause;
+        buffer[1..3].copy_from_slice(&self.component.to_le_bytes());
+        let crc = crc8(&buffer[0..buffer.len() - 1]);
+        buffer[buffer.len() - 1] = crc;
+        buffer
+    }
+}
+
+/**
+ * Core Dump
+ */
+#[repr(u8)]
+pub enum CoreDumpResponse {
+    Success = 0xFF,
+}
+
+/// Size of the dump that is going to be sent, 0 if no dump is available
+pub struct CoreDumpSizeMessage {
+    size: u32,
+}
+
+impl CoreDumpSizeMessage {
+    pub fn new(size: u32) -> Self {
+        Self { size }
+    }
+    pub const fn get_size() -> usize {
+        5
+    }
+    pub fn get_raw(&self) -> [u8; Self::get_size()] {
+        let mut buffer: [u8; Self::get_size()] = [0x00; Self::get_size()];
+        buffer[0..4].copy_from_slice(&self.size.to_le_bytes());
+        let crc = crc8(&buffer[0..buffer.len() - 1]);
+        buffer[buffer.len() - 1] = crc;
+        buffer
+    }
+}
+
+/**
+ * Component Read
+ */
+#[repr(u8)]
+pub enum ComponentReadCommand {
+    SendReadRequest = 0x01,
+    ImageInfo = 0x02,
+    ImageHash = 0x03,
+}
+
+/// What is sent back of the component
+#[derive(Clone, Copy)]
+#[repr(u8)]
+pub enum ComponentReadMode {
+    /// The CBF as it is stored in flash
+    Image = 0x00,
+    /// The SHA-256 of the CBF before the relocation, up to the trailer
+    Hash = 0x01,
+}
+
+impl TryFrom<u8> for ComponentReadMode {
+    type Error = MessageError;
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        match value {
+            0x00 => Ok(ComponentReadMode::Image),
+            0x01 => Ok(ComponentReadMode::Hash),
+            _ => Err(MessageError::InvalidOperation),
+        }
+    }
+}
+
+/// Component to read, and how
+pub struct ComponentReadRequestMessage {
+    component_id: u16,
+    mode: ComponentReadMode,
+}
+
+impl ComponentReadRequestMessage {
+    pub fn from(buffer: &[u8]) -> Result<Self, MessageError> {
+        // Check message size
+        if buffer.len() != Self::get_size() {
+            return Err(MessageError::InvalidSize);
+        }
+        // Check CRC
+        RawPacket::validate(buffer)?;
+        // Return instance
+        Ok(Self {
+            component_id: u16::from_le_bytes(buffer[0..2].try_into().unwrap()),
+            mode: ComponentReadMode::try_from(buffer[2])?,
+        })
+    }
+    pub const fn get_size() -> usize {
+        4
+    }
+    pub fn get_component_id(&self) -> u16 {
+        self.component_id
+    }
+    pub fn get_mode(&self) -> ComponentReadMode {
+        self.mode
+    }
+}
+
+/// Size of the CBF that is going to be sent, and the bases it was relocated at
+pub struct ImageInfoMessage {
+    size: u32,
+    block_base_address: u32,
+    sram_base_address: u32,
+}
+
+impl ImageInfoMessage {
+    pub fn new(size: u32, block_base_address: u32, sram_base_address: u32) -> Self {
+        Self {
+            size,
+            block_base_address,
+            sram_base_address,
+        }
+    }
+    pub const fn get_size() -> usize {
+        14
+    }
+    pub fn get_raw(&self) -> [u8; Self::get_size()] {
+        let mut buffer: [u8; Self::get_size()] = [0x00; Self::get_size()];
+        buffer[0] = ComponentReadCommand::ImageInfo as u8;
+        buffer[1..5].copy_from_slice(&self.size.to_le_bytes());
+        buffer[5..9].copy_from_slice(&self.block_base_address.to_le_bytes());
+        buffer[9..13].copy_from_slice(&self.sram_base_address.to_le_bytes());
+        let crc = crc8(&buffer[0..buffer.len() - 1]);
+        buffer[buffer.len() - 1] = crc;
+        buffer
+    }
+}
+
+/// Hash of the component, in place of the image
+pub struct ImageHashMessage {
+    hash: [u8; 32],
+}
+
+impl ImageHashMessage {
+    pub fn new(hash: [u8; 32]) -> Self {
+        Self { hash }
+    }
+    pub const fn get_size() -> usize {
+        34
+    }
+    pub fn get_raw(&self) -> [u8; Self::get_size()] {
+        let mut buffer: [u8; Self::get_size()] = [0x00; Self::get_size()];
+        buffer[0] = ComponentReadCommand::ImageHash as u8;
+        buffer[1..33].copy_from_slice(&self.hash);
+        let crc = crc8(&buffer[0..buffer.len() - 1]);
+        buffer[buffer.len() - 1] = crc;
+        buffer
+    }
+}
+
+/**
+ * Log Read
+ */
+#[repr(u8)]
+pub enum LogReadCommand {
+    Status = 0x01,
+    SendRequest = 0x02,
+    Record = 0x03,
+    EndOfLog = 0x04,
+}
+
+#[repr(u8)]
+pub enum LogReadResponse {
+    Success = 0xFF,
+}
+
+/// Sequence numbers of the log, sent at the beginning
+pub struct LogStatusMessage {
+    first: u32,
+    consumed: u32,
+    next: u32,
+}
+
+impl LogStatusMessage {
+    pub fn new(first: u32, consumed: u32, next: u32) -> Self {
+        Self {
+            first,
+            consumed,
+            next,
+        }
+    }
+    pub const fn get_size() -> usize {
+        14
+    }
+    pub fn get_raw(&self) -> [u8; Self::get_size()] {
+        let mut buffer: [u8; Self::get_size()] = [0x00; Self::get_size()];
+        buffer[0] = LogReadCommand::Status as u8;
+        buffer[1..5].copy_from_slice(&self.first.to_le_bytes());
+        buffer[5..9].copy_from_slice(&self.consumed.to_le_bytes());
+        buffer[9..13].copy_from_slice(&self.next.to_le_bytes());
+        let crc = crc8(&buffer[0..buffer.len() - 1]);
+        buffer[buffer.len() - 1] = crc;
+        buffer
+    }
+}
+
+/// What the tool wants next
+#[derive(Clone, Copy)]
+#[repr(u8)]
+pub enum LogRequestAction {
+    /// The first record from the cursor on
+    Read = 0x00,
+    /// The records before the value have been saved
+    Ack = 0x01,
+    /// End of the operation
+    Done = 0x02,
+}
+
+impl TryFrom<u8> for LogRequestAction {
+    type Error = MessageError;
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        match value {
+            0x00 => Ok(LogRequestAction::Read),
+            0x01 => Ok(LogRequestAction::Ack),
+            0x02 => Ok(LogRequestAction::Done),
+            _ => Err(MessageError::InvalidOperation),
+        }
+    }
+}
+
+pub struct LogRequestMessage {
+    action: LogRequestAction,
+    value: u32,
+}
+
+impl LogRequestMessage {
+    pub fn from(buffer: &[u8]) -> Result<Self, MessageError> {
+        // Check message size
+        if buffer.len() != Self::get_size() {
+            return Err(MessageError::InvalidSize);
+        }
+        // Check CRC
+        RawPacket::validate(buffer)?;
+        // Return instance
+        Ok(Self {
+            action: LogRequestAction::try_from(buffer[0])?,
+            value: u32::from_le_bytes(buffer[1..5].try_into().unwrap()),
+        })
+    }
+    pub const fn get_size() -> usize {
+        6
+    }
+    pub fn get_action(&self) -> LogRequestAction {
+        self.action
+    }
+    /// Cursor of Read, or the sequence number of Ack
+    pub fn get_value(&self) -> u32 {
+        self.value
+    }
+}
+
+/// A record of the log, the size depends on its data
+pub struct LogRecordMessage<'a> {
+    seq: u32,
+    source: u16,
+    timestamp: u64,
+    data: &'a [u8],
+}
+
+impl<'a> LogRecordMessage<'a> {
+    pub fn new(seq: u32, source: u16, timestamp: u64, data: &'a [u8]) -> Self {
+        Self {
+            seq,
+            source,
+            timestamp,
+            data,
+        }
+    }
+    /// Command, sequence number, source, timestamp and data length
+    pub const fn get_header_size() -> usize {
+        16
+    }
+    pub const fn get_max_size() -> usize {
+        Self::get_header_size() + log_ring::MAX_DATA_LEN + 1
+    }
+    /// Writes the message in the buffer (of at least get_max_size() bytes) and
+    /// returns its length
+    pub fn write_raw(&self, buffer: &mut [u8]) -> usize {
+        let end = Self::get_header_size() + self.data.len();
+        buffer[0] = LogReadCommand::Record as u8;
+        buffer[1..5].copy_from_slice(&self.seq.to_le_bytes());
+        buffer[5..7].copy_from_slice(&self.source.to_le_bytes());
+        buffer[7..15].copy_from_slice(&self.timestamp.to_le_bytes());
+        buffer[15] = self.data.len() as u8;
+        buffer[Self::get_header_size()..end].copy_from_slice(self.data);
+        let crc = crc8(&buffer[0..end]);
+        buffer[end] = crc;
+        end + 1
+    }
+}
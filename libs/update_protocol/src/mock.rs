@@ -0,0 +1,190 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Platform for host-side tests: the blocks live in memory, erased as a flash.
+
+use core::cell::RefCell;
+use std::vec;
+use std::vec::Vec;
+
+use abi::flash::BlockType;
+
+use crate::platform::{Allocation, Block, PlatformError, UpdatePlatform};
+
+const FLASH_BASE: u32 = 0x0802_0000;
+const SRAM_BASE: u32 = 0x2000_4000;
+/// Blocks are allocated one after the other, aligned to this size
+const BLOCK_ALIGN: u32 = 0x1000;
+
+struct MockBlock {
+    block: Block,
+    content: Vec<u8>,
+}
+
+pub struct MockPlatform {
+    blocks: RefCell<Vec<MockBlock>>,
+    flash_size: u32,
+    next_flash: RefCell<u32>,
+    next_sram: RefCell<u32>,
+    deallocated: RefCell<Vec<u32>>,
+    loaded: RefCell<Vec<u32>>,
+}
+
+impl MockPlatform {
+    /// Empty flash of the supplied size
+    pub fn new(flash_size: u32) -> Self {
+        Self {
+            blocks: RefCell::new(Vec::new()),
+            flash_size,
+            next_flash: RefCell::new(FLASH_BASE),
+            next_sram: RefCell::new(SRAM_BASE),
+            deallocated: RefCell::new(Vec::new()),
+            loaded: RefCell::new(Vec::new()),
+        }
+    }
+
+    /// Blocks given back to the storage
+    pub fn deallocated(&self) -> Vec<u32> {
+        self.deallocated.borrow().clone()
+    }
+
+    /// Components the kernel was asked to start
+    pub fn loaded(&self) -> Vec<u32> {
+        self.loaded.borrow().clone()
+    }
+
+    fn allocate(
+        &self,
+        size: u32,
+        sram_base_address: u32,
+        block_type: BlockType,
+    ) -> Result<u32, PlatformError> {
+        let base = *self.next_flash.borrow();
+        if base + size > FLASH_BASE + self.flash_size {
+            return Err(PlatformError::OutOfSpace);
+        }
+        *self.next_flash.borrow_mut() = (base + size + BLOCK_ALIGN - 1) & !(BLOCK_ALIGN - 1);
+        self.blocks.borrow_mut().push(MockBlock {
+            block: Block {
+                block_base_address: base,
+                block_size: size,
+                sram_base_address,
+                block_type,
+                finalized: false,
+            },
+            content: vec![0xFF; size as usize],
+        });
+        Ok(base)
+    }
+
+    fn with_block<R>(
+        &self,
+        block_base_address: u32,
+        f: impl FnOnce(&mut MockBlock) -> R,
+    ) -> Result<R, PlatformError> {
+        let mut blocks = self.blocks.borrow_mut();
+        match blocks
+            .iter_mut()
+            .find(|b| b.block.block_base_address == block_base_address)
+        {
+            Some(block) => Ok(f(block)),
+            None => Err(PlatformError::Storage),
+        }
+    }
+}
+
+impl UpdatePlatform for MockPlatform {
+    fn allocate_component(
+        &self,
+        flash_size: u32,
+        ram_size: u32,
+    ) -> Result<Allocation, PlatformError> {
+        let sram = *self.next_sram.borrow();
+        let base = self.allocate(flash_size, sram, BlockType::COMPONENT)?;
+        *self.next_sram.borrow_mut() = sram + ram_size;
+        Ok(Allocation {
+            flash_base_address: base,
+            flash_size,
+            ram_base_address: sram,
+            ram_size,
+        })
+    }
+
+    fn allocate_session(&self) -> Result<u32, PlatformError> {
+        self.allocate(abi::flash::SESSION_AREA_SIZE as u32, 0, BlockType::SESSION)
+    }
+
+    fn deallocate_block(&self, block_base_address: u32) -> Result<(), PlatformError> {
+        let mut blocks = self.blocks.borrow_mut();
+        let pos = blocks
+            .iter()
+            .position(|b| b.block.block_base_address == block_base_address)
+            .ok_or(PlatformError::Storage)?;
+        blocks.remove(pos);
+        self.deallocated.borrow_mut().push(block_base_address);
+        Ok(())
+    }
+
+    fn write_stream(
+        &self,
+        block_base_address: u32,
+        offset: u32,
+        data: &[u8],
+        _flush_after: bool,
+    ) -> Result<(), PlatformError> {
+        self.with_block(block_base_address, |block| {
+            let start = offset as usize;
+            let end = start + data.len();
+            if end > block.content.len() {
+                return Err(PlatformError::Storage);
+            }
+            // As on a flash, a write can only clear bits
+            for (dst, src) in block.content[start..end].iter_mut().zip(data) {
+                *dst &= *src;
+            }
+            Ok(())
+        })?
+    }
+
+    fn read_stream(
+        &self,
+        block_base_address: u32,
+        offset: u32,
+        buffer: &mut [u8],
+    ) -> Result<(), PlatformError> {
+        self.with_block(block_base_address, |block| {
+            let start = offset as usize;
+            let end = start + buffer.len();
+            if end > block.content.len() {
+                return Err(PlatformError::Storage);
+            }
+            buffer.copy_from_slice(&block.content[start..end]);
+            Ok(())
+        })?
+    }
+
+    fn blocks(&self) -> Result<u32, PlatformError> {
+        Ok(self.blocks.borrow().len() as u32)
+    }
+
+    fn nth_block(&self, block_number: u32) -> Result<Block, PlatformError> {
+        self.blocks
+            .borrow()
+            .get(block_number as usize)
+            .map(|b| b.block)
+            .ok_or(PlatformError::Storage)
+    }
+
+    fn load_component(&self, block_base_address: u32) -> bool {
+        // As the kernel, the block is finalized when the component is loaded
+        if self
+            .with_block(block_base_address, |block| block.block.finalized = true)
+            .is_err()
+        {
+            return false;
+        }
+        self.loaded.borrow_mut().push(block_base_address);
+        true
+    }
+}
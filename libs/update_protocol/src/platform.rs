@@ -0,0 +1,70 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! What the update needs from the system: the storage of the blocks and the
+//! kernel starting the components. On the device they are IPCs, in the tests
+//! an emulated flash.
+
+use abi::flash::BlockType;
+
+/// A block of the flash, as the storage reports it
+#[derive(Clone, Copy, Debug)]
+pub struct Block {
+    pub block_base_address: u32,
+    pub block_size: u32,
+    /// Base of the SRAM assigned to the component, 0 for other blocks
+    pub sram_base_address: u32,
+    pub block_type: BlockType,
+    /// Unfinalized blocks are still being written
+    pub finalized: bool,
+}
+
+/// Space reserved for a new component
+#[derive(Clone, Copy, Debug)]
+pub struct Allocation {
+    pub flash_base_address: u32,
+    pub flash_size: u32,
+    pub ram_base_address: u32,
+    pub ram_size: u32,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum PlatformError {
+    /// Not enough flash or SRAM for the component
+    OutOfSpace,
+    /// Any other failure of the storage
+    Storage,
+}
+
+pub trait UpdatePlatform {
+    fn allocate_component(
+        &self,
+        flash_size: u32,
+        ram_size: u32,
+    ) -> Result<Allocation, PlatformError>;
+    /// Block recording the progress of an update, returns its base address
+    fn allocate_session(&self) -> Result<u32, PlatformError>;
+    fn deallocate_block(&self, block_base_address: u32) -> Result<(), PlatformError>;
+    /// Writes are buffered up to the write granularity of the flash, unless flushed
+    fn write_stream(
+        &self,
+        block_base_address: u32,
+        offset: u32,
+        data: &[u8],
+        flush_after: bool,
+    ) -> Result<(), PlatformError>;
+    fn read_stream(
+        &self,
+        block_base_address: u32,
+        offset: u32,
+        buffer: &mut [u8],
+    ) -> Result<(), PlatformError>;
+    /// Number of blocks in the flash
+    fn blocks(&self) -> Result<u32, PlatformError>;
+    fn nth_block(&self, block_number: u32) -> Result<Block, PlatformError>;
+    /// Starts the component installed in the block, false if the kernel refuses it
+    fn load_component(&self, block_base_address: u32) -> bool;
+    /// Progress of the update, for the log of the system
+    fn log(&self, _message: &str) {}
+}
@@ -0,0 +1,304 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use crate::consts::*;
+use crate::delta_source::DeltaSource;
+use crate::messages::MessageError;
+use crate::platform::{Allocation, Block, UpdatePlatform};
+use crate::utils::{wrap_cbf_error, FlashReader};
+use abi::flash::{
+    last_session_record, BlockType, SessionRecord, SESSION_AREA_SIZE, SESSION_SLOTS,
+    SESSION_SLOT_SIZE,
+};
+use cbf_lite::CbfFile;
+
+/// State of an update interrupted while receiving the payload.
+/// The block is kept allocated (and unfinalized), so that the update can continue
+/// from the last checkpoint instead of starting over. The checkpoints are recorded
+/// in a session block, so the storage keeps both also across a reset.
+#[derive(Clone, Copy)]
+pub struct ResumeSession {
+    /// Checksum of the fixed header, the update tool computes the same value
+    pub session_id: u32,
+    /// Block of the records, given back when the session ends
+    pub session_block_base_address: u32,
+    pub flash_base_address: u32,
+    pub flash_size: u32,
+    pub ram_base_address: u32,
+    pub checksum_offset: u32,
+    /// Position in the image of the last checkpoint. Everything before it is in flash,
+    /// already relocated, and the relocator state can be rebuilt from here.
+    pub committed_offset: u32,
+    pub applied_relocations: u32,
+    /// Checksums of the original and of the relocated data up to committed_offset
+    pub validation_checksum: u32,
+    pub new_checksum: u32,
+    /// The payload is sent as a delta of this component
+    pub delta: Option<DeltaSource>,
+    /// Slots of the session block already written
+    used_slots: usize,
+    /// Checkpoint of the last record
+    recorded_offset: u32,
+}
+
+impl ResumeSession {
+    pub fn new(
+        session_id: u32,
+        session_block_base_address: u32,
+        allocation: &Allocation,
+        checksum_offset: u32,
+        committed_offset: u32,
+        validation_checksum: u32,
+        delta: Option<DeltaSource>,
+    ) -> Self {
+        Self {
+            session_id,
+            session_block_base_address,
+            flash_base_address: allocation.flash_base_address,
+            flash_size: allocation.flash_size,
+            ram_base_address: allocation.ram_base_address,
+            checksum_offset,
+            committed_offset,
+            applied_relocations: 0,
+            validation_checksum,
+            new_checksum: validation_checksum,
+            delta,
+            used_slots: 0,
+            recorded_offset: 0,
+        }
+    }
+
+    fn record(&self, committed_offset: u32) -> SessionRecord {
+        let (delta_block_base_address, delta_sram_base_address, delta_patch_size) =
+            match self.delta {
+                Some(delta) => (
+                    delta.block_base_address,
+                    delta.sram_base_address,
+                    delta.patch_size,
+                ),
+                None => (0, 0, 0),
+            };
+        SessionRecord {
+            session_id: self.session_id,
+            component_block_base_address: self.flash_base_address,
+            committed_offset,
+            applied_relocations: self.applied_relocations,
+            validation_checksum: self.validation_checksum,
+            new_checksum: self.new_checksum,
+            delta_block_base_address,
+            delta_sram_base_address,
+            delta_patch_size,
+        }
+    }
+
+    fn write_record<P: UpdatePlatform>(
+        &mut self,
+        platform: &P,
+        record: &SessionRecord,
+    ) -> Result<(), MessageError> {
+        let offset = (self.used_slots * SESSION_SLOT_SIZE) as u32;
+        platform
+            .write_stream(
+                self.session_block_base_address,
+                offset,
+                &record.to_bytes(),
+                true,
+            )
+            .map_err(|_| MessageError::FlashError)?;
+        self.used_slots += 1;
+        Ok(())
+    }
+
+    /// Records the beginning of the session, right after the header
+    pub fn start<P: UpdatePlatform>(&mut self, platform: &P) -> Result<(), MessageError> {
+        self.write_record(platform, &self.record(self.committed_offset))?;
+        self.recorded_offset = self.committed_offset;
+        Ok(())
+    }
+
+    /// True when a checkpoint can be recorded. The last slot is kept for closing the session.
+    pub fn can_record(&self) -> bool {
+        self.used_slots < SESSION_SLOTS - 1
+            && self.committed_offset != self.recorded_offset
+            // Any other position would flush the write buffer of the kernel half full
+            && self.committed_offset & (FLASH_WRITE_GRANULARITY - 1) == 0
+    }
+
+    /// Records the current checkpoint in flash
+    pub fn save<P: UpdatePlatform>(&mut self, platform: &P) -> Result<(), MessageError> {
+        if !self.can_record() {
+            return Ok(());
+        }
+        self.write_record(platform, &self.record(self.committed_offset))?;
+        self.recorded_offset = self.committed_offset;
+        Ok(())
+    }
+
+    /// Records a checkpoint only every interval bytes, so that
+    /// the slots last until the end of the payload
+    pub fn save_periodically<P: UpdatePlatform>(
+        &mut self,
+        platform: &P,
+        interval: u32,
+    ) -> Result<(), MessageError> {
+        if self.committed_offset < self.recorded_offset + interval {
+            return Ok(());
+        }
+        self.save(platform)
+    }
+
+    /// Bytes between two checkpoints, spreading the slots left on the rest of the payload
+    pub fn record_interval(&self, payload_end_offset: u32) -> u32 {
+        let slots_left = (SESSION_SLOTS - 1).saturating_sub(self.used_slots) as u32;
+        (payload_end_offset - self.committed_offset) / (slots_left + 1)
+    }
+
+    /// The update completed or failed, the session cannot be resumed anymore
+    pub fn close<P: UpdatePlatform>(&self, platform: &P) -> Result<(), MessageError> {
+        platform
+            .deallocate_block(self.session_block_base_address)
+            .map_err(|_| MessageError::FlashError)
+    }
+
+    /// Searches the flash for an interrupted update, any or the one with the supplied id
+    pub fn find<P: UpdatePlatform>(
+        platform: &P,
+        session_id: Option<u32>,
+    ) -> Result<Option<Self>, MessageError> {
+        let blocks = platform.blocks().map_err(|_| MessageError::FlashError)?;
+        for block_num in 0..blocks {
+            let block = platform
+                .nth_block(block_num)
+                .map_err(|_| MessageError::FlashError)?;
+            let (record, used_slots) = match read_record(platform, &block)? {
+                Some(found) => found,
+                None => continue,
+            };
+            if matches!(session_id, Some(id) if id != record.session_id) {
+                continue;
+            }
+            let component = match resumable_component(platform, &record)? {
+                Some(component) => component,
+                None => continue,
+            };
+            return Self::from_record(platform, &block, &component, &record, used_slots).map(Some);
+        }
+        Ok(None)
+    }
+
+    /// Gives back the blocks of every interrupted update, as a new update replaces them
+    pub fn discard_all<P: UpdatePlatform>(platform: &P) -> Result<(), MessageError> {
+        let mut block_num: u32 = 0;
+        while block_num < platform.blocks().map_err(|_| MessageError::FlashError)? {
+            let block = platform
+                .nth_block(block_num)
+                .map_err(|_| MessageError::FlashError)?;
+            if block.block_type != BlockType::SESSION {
+                block_num += 1;
+                continue;
+            }
+            if let Some((record, _)) = read_record(platform, &block)? {
+                if let Some(component) = resumable_component(platform, &record)? {
+                    platform
+                        .deallocate_block(component.block_base_address)
+                        .map_err(|_| MessageError::FlashError)?;
+                }
+            }
+            platform
+                .deallocate_block(block.block_base_address)
+                .map_err(|_| MessageError::FlashError)?;
+            // The blocks are numbered again
+            block_num = 0;
+        }
+        Ok(())
+    }
+
+    fn from_record<P: UpdatePlatform>(
+        platform: &P,
+        session_block: &Block,
+        block: &Block,
+        record: &SessionRecord,
+        used_slots: usize,
+    ) -> Result<Self, MessageError> {
+        // The header of the image is already in flash
+        let flash_reader = FlashReader::from(platform, block.block_base_address, block.block_size);
+        let flash_cbf = wrap_cbf_error(CbfFile::from_reader(&flash_reader))?;
+        let checksum_offset = wrap_cbf_error(flash_cbf.checksum_offset())?;
+        let delta = match record.delta_block_base_address {
+            0 => None,
+            base => Some(DeltaSource {
+                block_base_address: base,
+                block_size: find_block(platform, base)?
+                    // The source of the delta was removed in the meanwhile
+                    .ok_or(MessageError::DeltaSourceMismatch)?
+                    .block_size,
+                sram_base_address: record.delta_sram_base_address,
+                patch_size: record.delta_patch_size,
+            }),
+        };
+        Ok(Self {
+            session_id: record.session_id,
+            session_block_base_address: session_block.block_base_address,
+            flash_base_address: block.block_base_address,
+            flash_size: block.block_size,
+            ram_base_address: block.sram_base_address,
+            checksum_offset,
+            committed_offset: record.committed_offset,
+            applied_relocations: record.applied_relocations,
+            validation_checksum: record.validation_checksum,
+            new_checksum: record.new_checksum,
+            delta,
+            used_slots,
+            recorded_offset: record.committed_offset,
+        })
+    }
+}
+
+/// Last record of a session block, with the number of slots used
+fn read_record<P: UpdatePlatform>(
+    platform: &P,
+    block: &Block,
+) -> Result<Option<(SessionRecord, usize)>, MessageError> {
+    if block.block_type != BlockType::SESSION || block.block_size < SESSION_AREA_SIZE as u32 {
+        return Ok(None);
+    }
+    let mut area: [u8; SESSION_AREA_SIZE] = [0xFF; SESSION_AREA_SIZE];
+    platform
+        .read_stream(block.block_base_address, 0, &mut area)
+        .map_err(|_| MessageError::FlashError)?;
+    match last_session_record(&area) {
+        (Some(record), used_slots) => Ok(Some((record, used_slots))),
+        _ => Ok(None),
+    }
+}
+
+/// The block of the session, when it is still waiting for the rest of the image
+fn resumable_component<P: UpdatePlatform>(
+    platform: &P,
+    record: &SessionRecord,
+) -> Result<Option<Block>, MessageError> {
+    match find_block(platform, record.component_block_base_address)? {
+        Some(block) if block.block_type == BlockType::COMPONENT && !block.finalized => {
+            Ok(Some(block))
+        }
+        _ => Ok(None),
+    }
+}
+
+/// The block starting at the supplied address
+fn find_block<P: UpdatePlatform>(
+    platform: &P,
+    block_base_address: u32,
+) -> Result<Option<Block>, MessageError> {
+    let blocks = platform.blocks().map_err(|_| MessageError::FlashError)?;
+    for block_num in 0..blocks {
+        let block = platform
+            .nth_block(block_num)
+            .map_err(|_| MessageError::FlashError)?;
+        if block.block_base_address == block_base_address {
+            return Ok(Some(block));
+        }
+    }
+    Ok(None)
+}
@@ -0,0 +1,1129 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use crate::consts::*;
+use crate::delta_source::{find_delta_source, DeltaSource, SourceReader};
+use crate::messages::*;
+use crate::platform::{Allocation, Block, PlatformError, UpdatePlatform};
+use crate::session::ResumeSession;
+use crate::utils::u32_from_le_bytes;
+use crate::utils::wrap_cbf_error;
+use crate::utils::FlashReader;
+use abi::flash::BlockType;
+use cbf_lite::CbfHeaderBase;
+use cbf_lite::{BufferReaderImpl, CbfFile, ComponentFlags};
+use delta::Patcher;
+use heatshrink::Decoder;
+use relocator::Relocator;
+use unwrap_lite::UnwrapLite;
+use update_link::{STREAM_BENCHMARK, STREAM_PAYLOAD, STREAM_VARIABLE_HEADER};
+use update_transport::{receive_stream, TransportError, UpdateTransport};
+
+/*
+   Some objects that will become useful later
+*/
+/// This object deallocates the provisioned area if an error occurs.
+/// It's used for simplicity, still it's necessary to craft a secondary mutable reference
+/// to the storage allocator: here it does not constitute a problem, as those methods actually
+/// does not mutate any state internally to the allocator (there is no such state currently).
+struct UpdateMethods<'a, T: UpdateTransport, P: UpdatePlatform> {
+    memory_pointer: u32,
+    platform: &'a P,
+    channel: &'a mut T,
+}
+
+impl<'a, T: UpdateTransport, P: UpdatePlatform> UpdateMethods<'a, T, P> {
+    pub fn methods_for_requirements(
+        needed_flash: u32,
+        needed_sram: u32,
+        platform: &'a P,
+        channel: &'a mut T,
+    ) -> Result<(Self, Allocation), PlatformError> {
+        let allocation = platform.allocate_component(needed_flash, needed_sram)?;
+        Ok((
+            Self {
+                memory_pointer: allocation.flash_base_address,
+                platform,
+                channel,
+            },
+            allocation,
+        ))
+    }
+    /// Continues to work on an already allocated block
+    pub fn for_block(flash_base_address: u32, platform: &'a P, channel: &'a mut T) -> Self {
+        Self {
+            memory_pointer: flash_base_address,
+            platform,
+            channel,
+        }
+    }
+    pub fn storage_write_stream(
+        &mut self,
+        offset: u32,
+        data: &[u8],
+        flush_after: bool,
+    ) -> Result<(), MessageError> {
+        self.platform
+            .write_stream(self.memory_pointer, offset, data, flush_after)
+            .map_err(|_| MessageError::FlashError)
+    }
+
+    pub fn storage_read_stream(
+        &mut self,
+        block_base_address: u32,
+        offset: u32,
+        data: &mut [u8],
+    ) -> Result<(), MessageError> {
+        self.platform
+            .read_stream(block_base_address, offset, data)
+            .map_err(|_| MessageError::FlashError)
+    }
+
+    pub fn storage_blocks(&mut self) -> Result<u32, MessageError> {
+        self.platform
+            .blocks()
+            .map_err(|_| MessageError::FlashError)
+    }
+
+    pub fn storage_get_nth_block(&mut self, nth: u32) -> Result<Block, MessageError> {
+        self.platform
+            .nth_block(nth)
+            .map_err(|_| MessageError::FlashError)
+    }
+    pub fn channel_write_single(&mut self, value: u8) -> Result<(), MessageError> {
+        crate::utils::channel_write_single(self.channel, value)
+    }
+    pub fn channel_ask(&mut self, cmd: u8, buffer: &mut [u8]) -> Result<(), MessageError> {
+        crate::utils::channel_ask(self.channel, cmd, buffer)
+    }
+
+    pub fn deallocate(&mut self) {
+        self.platform
+            .deallocate_block(self.memory_pointer)
+            .unwrap_lite();
+    }
+}
+
+/// The link layer talks directly with the channel
+impl<'a, T: UpdateTransport, P: UpdatePlatform> UpdateTransport for UpdateMethods<'a, T, P> {
+    fn write_block(&mut self, data: &[u8]) -> Result<(), TransportError> {
+        self.channel.write_block(data)
+    }
+    fn read_block(&mut self, data: &mut [u8]) -> Result<(), TransportError> {
+        self.channel.read_block(data)
+    }
+    fn read_block_timed(
+        &mut self,
+        data: &mut [u8],
+        timeout_ticks: u32,
+    ) -> Result<(), TransportError> {
+        self.channel.read_block_timed(data, timeout_ticks)
+    }
+    fn transmit_timed(
+        &mut self,
+        data_out: &[u8],
+        data_in: &mut [u8],
+        timeout_ticks: u32,
+    ) -> Result<(), TransportError> {
+        self.channel
+            .transmit_timed(data_out, data_in, timeout_ticks)
+    }
+}
+
+struct ChecksumBuff {
+    buff: [u8; 4],
+    pos: usize,
+}
+
+impl ChecksumBuff {
+    pub fn new() -> Self {
+        Self {
+            buff: [0x00; 4],
+            pos: 0,
+        }
+    }
+    pub fn compute(&mut self, checksum: &mut u32, src: &[u8]) {
+        for b in src {
+            // Append to the buffer
+            self.buff[self.pos] = *b;
+            self.pos += 1;
+            // Check if we got a full word
+            if self.pos == self.buff.len() {
+                update_checksum(checksum, &self.buff);
+                self.pos = 0;
+            }
+        }
+    }
+    /// True when no partial word is waiting to be added to the checksum
+    pub fn is_aligned(&self) -> bool {
+        self.pos == 0
+    }
+}
+
+struct UpdateRelocator<'a, 'cbf, 'um, T: UpdateTransport, P: UpdatePlatform> {
+    cbf: &'a CbfFile<'cbf>,
+    methods: &'a mut UpdateMethods<'um, T, P>,
+    num_relocations: usize, // Cached here to simplify code
+    checksum: &'a mut u32,
+}
+
+impl<'a, 'cbf, 'um, T: UpdateTransport, P: UpdatePlatform>
+    relocator::RelocatorMethods<ChecksumBuff> for UpdateRelocator<'a, 'cbf, 'um, T, P>
+{
+    fn read_relocations(
+        &self,
+        start_index: usize,
+        dst: &mut [u32],
+        _checksum_buff: &mut ChecksumBuff,
+    ) -> Result<usize, ()> {
+        // Compute the available relocations
+        assert!(start_index < self.num_relocations);
+        assert!(dst.len() <= self.num_relocations - start_index);
+        // Copy such relocations in the buffer
+        for (r, d) in dst.iter_mut().enumerate() {
+            let reloc = self
+                .cbf
+                .relocation_nth((start_index + r) as u32)
+                .map_err(|_| ())?;
+            *d = reloc.value();
+        }
+        Ok(dst.len())
+    }
+    fn flush(
+        &mut self,
+        position: usize,
+        src: &[u8],
+        checksum_buff: &mut ChecksumBuff,
+    ) -> Result<(), ()> {
+        // After fixes, compute also the new checksum
+        checksum_buff.compute(self.checksum, src);
+        // Flush buffer
+        
+        self
+            .methods
+            .storage_write_stream(position as u32, src, false)
+            .map_err(|_| ())
+    }
+}
+
+/// Processing of the payload, the same whether it is received as it is,
+/// compressed or as a delta
+struct PayloadState<'a, 'cbf> {
+    cbf: &'a CbfFile<'cbf>,
+    num_relocations: usize,
+    relocator: Relocator<LINKED_FLASH_BASE, LINKED_SRAM_BASE, BUFF_SIZE, RELOC_BUFF_SIZE>,
+    /// Checksums of the original and of the relocated payload
+    validation_checksum: u32,
+    new_checksum: u32,
+    checksum_buff: ChecksumBuff,
+    checkpoints: bool,
+    session: &'a mut ResumeSession,
+    /// Bytes between two checkpoints recorded in flash
+    record_interval: u32,
+    /// Decoded bytes waiting to be processed
+    chunk: [u8; DECODED_CHUNK_SIZE],
+    chunk_len: usize,
+}
+
+impl<'a, 'cbf> PayloadState<'a, 'cbf> {
+    /// Relocates the next piece of the (uncompressed) payload and writes it in flash
+    fn process<T: UpdateTransport, P: UpdatePlatform>(
+        &mut self,
+        methods: &mut UpdateMethods<T, P>,
+        data: &[u8],
+    ) -> Result<(), MessageError> {
+        update_checksum(&mut self.validation_checksum, data);
+        // Create relocator methods
+        let mut reloc_methods = UpdateRelocator {
+            cbf: self.cbf,
+            methods,
+            num_relocations: self.num_relocations,
+            checksum: &mut self.new_checksum,
+        };
+        // Process buffer
+        self.relocator
+            .consume_current_buffer(data, &mut reloc_methods, &mut self.checksum_buff)
+            .map_err(|_| MessageError::FlashError)?;
+        // Move the checkpoint forward when all the data received is in flash
+        if !self.checkpoints {
+            return Ok(());
+        }
+        if let Some((position, applied_relocations)) = self.relocator.checkpoint() {
+            if self.checksum_buff.is_aligned() {
+                self.session.committed_offset = position as u32;
+                self.session.applied_relocations = applied_relocations as u32;
+                self.session.validation_checksum = self.validation_checksum;
+                self.session.new_checksum = self.new_checksum;
+                self.session
+                    .save_periodically(methods.platform, self.record_interval)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Adds a decoded byte, processing the bytes in chunks
+    fn push<T: UpdateTransport, P: UpdatePlatform>(
+        &mut self,
+        methods: &mut UpdateMethods<T, P>,
+        b: u8,
+    ) -> Result<(), MessageError> {
+        self.chunk[self.chunk_len] = b;
+        self.chunk_len += 1;
+        if self.chunk_len < DECODED_CHUNK_SIZE {
+            return Ok(());
+        }
+        self.flush_chunk(methods)
+    }
+
+    /// Processes the bytes left in the chunk
+    fn flush_chunk<T: UpdateTransport, P: UpdatePlatform>(
+        &mut self,
+        methods: &mut UpdateMethods<T, P>,
+    ) -> Result<(), MessageError> {
+        if self.chunk_len == 0 {
+            return Ok(());
+        }
+        let chunk = self.chunk;
+        let chunk_len = self.chunk_len;
+        self.chunk_len = 0;
+        self.process(methods, &chunk[0..chunk_len])
+    }
+}
+
+/// Receives bytes_to_read bytes through the link layer: the sender keeps
+/// a window of fragments in flight, each one acknowledged by a control frame.
+fn read_exact_bytes<T, P, F, D>(
+    methods: &mut UpdateMethods<T, P>,
+    stream: u8,
+    bytes_to_read: usize,
+    aux_data: &mut D,
+    mut buffer_process: F,
+) -> Result<(), MessageError>
+where
+    T: UpdateTransport,
+    P: UpdatePlatform,
+    F: FnMut(&mut UpdateMethods<T, P>, &[u8], &mut D) -> Result<(), MessageError>,
+{
+    receive_stream::<_, _, _, LINK_WINDOW>(
+        methods,
+        stream,
+        bytes_to_read,
+        READ_TIMEOUT_TICKS,
+        LINK_MAX_TIMEOUTS,
+        |methods, data| buffer_process(methods, data, aux_data),
+    )
+}
+
+/// Scans the system to verify if all the dependencies of this component
+/// are satisfied
+fn validate_component_version_and_dependencies<T: UpdateTransport, P: UpdatePlatform>(
+    cbf: &CbfFile,
+    methods: &mut UpdateMethods<T, P>,
+    block_base_address: u32,
+) -> Result<(), MessageError> {
+    // Iterating for components is expensive, so must be ideally done once.
+    // The number of dependencies is unknown at compile time, so we may exploit ordering
+    // constraint.
+    let cbf_base = wrap_cbf_error(cbf.header_base())?;
+    let num_dependencies = cbf_base.num_dependencies();
+    let mut solved_dependencies: u16 = 0;
+    // Prepare for iterating over the blocks
+    // Get block stats
+    let blocks = methods.storage_blocks()?;
+    // Iterate all blocks
+    for block_num in 0..blocks {
+        // Get block
+        let block = methods.storage_get_nth_block(block_num)?;
+        // Skip the current block!
+        if block.block_base_address == block_base_address {
+            continue;
+        }
+        // Only installed components, not the ones still being written
+        if block.block_type == BlockType::COMPONENT && block.finalized {
+            // Read cbf header
+            let mut buff: [u8; cbf_lite::CBF_HEADER_MIN_SIZE] =
+                [0x00; cbf_lite::CBF_HEADER_MIN_SIZE];
+            methods.storage_read_stream(block.block_base_address, 0, &mut buff)?;
+            // Parse it
+            let reader = cbf_lite::BufferReaderImpl::from(&buff);
+            let comp_cbf = wrap_cbf_error(cbf_lite::CbfFile::from_reader(&reader))?;
+            let comp_data = wrap_cbf_error(comp_cbf.header_base())?;
+            // Check whether this component is an old version of ours
+            if comp_data.component_id() == cbf_base.component_id() {
+                // Check constraint on greater version
+                if comp_data.component_version() >= cbf_base.component_version() {
+                    return Err(MessageError::IllegalDowngrade);
+                }
+            }
+            // Unfortunately, as components are not ordered in flash, we have to iterate over all
+            // dependencies for each block.
+            if solved_dependencies < num_dependencies {
+                // Only makes sense if we miss something
+                for dep_num in 0..num_dependencies {
+                    let dep = wrap_cbf_error(cbf.dependency_nth(dep_num))?;
+                    // Check version (lower and upper bound)
+                    if (dep.min_version() > 0 && comp_data.component_version() < dep.min_version())
+                        || (dep.max_version() > 0
+                            && comp_data.component_version() > dep.max_version())
+                    {
+                        return Err(MessageError::DependencyError);
+                    }
+                    solved_dependencies += 1;
+                }
+            }
+        }
+    }
+    // At the end, the number of solved dependencies gives the result
+    if solved_dependencies == num_dependencies {
+        Ok(())
+    } else {
+        // Missing dependency
+        Err(MessageError::MissingDependency)
+    }
+}
+
+fn add_update_core<T: UpdateTransport, P: UpdatePlatform>(
+    methods: &mut UpdateMethods<T, P>,
+    allocation: &Allocation,
+    checksum_offset: u32,
+    header_base: &CbfHeaderBase,
+    fhm: &FixedHeaderMessage,
+    delta: Option<DeltaSource>,
+    session: &mut Option<ResumeSession>,
+) -> Result<(), MessageError> {
+    // NOTE: here we have to work with two checksums:
+    //       -> validation_checksum: checksum computed on the original data that is sent from the update
+    //                               server. Needed to ensure that the original image was received correctly
+    //       -> new_checksum: checksum we compute on the modified image (i.e. due to relocations),
+    //                        that will replace the original one to ensure at start-up this component
+    //                        can pass validation.
+
+    let mut curr_pos: u32 = 0; // Tracks the current position in the image
+
+    let mut validation_checksum: u32 = 0;
+
+    // Start computing the new checksum from the untouched bytes of the fixed header.
+    update_checksum(
+        &mut validation_checksum,
+        fhm.get_raw(), //&cbf_header_buff[0..cbf_header_buff.len() - 1],
+    );
+    // The checksum of the fixed header identifies this update, in case it must be resumed
+    let session_id = validation_checksum;
+
+    // ------------------------------------------------------------------------------
+    //    Step 2: Flush such fixed header to flash.
+    //            This is needed as we cannot store in SRAM the variable
+    //            header, containing the dependencies needed for the validation.
+    // -------------------------------------------------------------------------------
+    // NOTE: We cannot force flush now as otherwise we risk problems
+    // due to the write granularity. Still we don't access fields of the main header, so
+    // it's safe.
+    methods.storage_write_stream(curr_pos, fhm.get_raw(), false)?;
+    curr_pos += fhm.get_raw().len() as u32;
+
+    // -------------------------------------
+    //    Step 3: Receive variable header
+    // -------------------------------------
+    let to_read: usize = cbf_lite::REGION_SIZE * header_base.num_regions() as usize
+        + cbf_lite::INTERRUPT_SIZE * header_base.num_interrupts() as usize
+        + cbf_lite::RELOC_SIZE * header_base.num_relocations() as usize
+        + cbf_lite::DEPENDENCY_SIZE * header_base.num_dependencies() as usize
+        + header_base.padding_bytes() as usize;
+
+    methods.channel_write_single(ComponentUpdateCommand::SendComponentVariableHeader as u8)?;
+
+    methods.platform.log("Waiting for variable header");
+
+    read_exact_bytes(
+        methods,
+        STREAM_VARIABLE_HEADER,
+        to_read,
+        &mut (&mut curr_pos, &mut validation_checksum),
+        |methods, data, aux_data| {
+            let (curr_pos, validation_checksum) = aux_data;
+            update_checksum(validation_checksum, data);
+            // Flush data to storage as it is
+            methods.storage_write_stream(**curr_pos, data, false)?;
+            **curr_pos += data.len() as u32;
+            Ok(())
+        },
+    )?;
+    // Force a flush, as now we must be able to read the whole CBF header
+    methods.storage_write_stream(curr_pos, &[], true)?;
+
+    // --------------------------------------------------------------------------------
+    //    Step 4: Now the entire header has been received and it's stored on flash.
+    //            Perform all the validations (i.e. dependencies check).
+    // --------------------------------------------------------------------------------
+    let flash_reader = FlashReader::from(
+        methods.platform,
+        allocation.flash_base_address,
+        allocation.flash_size,
+    );
+
+    methods.platform.log("Reading CBF from flash");
+    let flash_cbf = wrap_cbf_error(CbfFile::from_reader(&flash_reader))?;
+
+    // Before reading the payload, validate the dependencies of this component
+    methods.platform.log("Checking dependencies");
+    validate_component_version_and_dependencies(
+        &flash_cbf,
+        methods,
+        allocation.flash_base_address,
+    )?;
+
+    // From this point on, an interruption does not require to start over.
+    // The validation and new checksum are going to be different,
+    // because of the relocations that are going to be applied.
+    let session_block_base_address = methods
+        .platform
+        .allocate_session()
+        .map_err(allocation_error)?;
+    let session = session.insert(ResumeSession::new(
+        session_id,
+        session_block_base_address,
+        allocation,
+        checksum_offset,
+        curr_pos,
+        validation_checksum,
+        delta,
+    ));
+    session.start(methods.platform)?;
+    receive_payload_and_trailer(methods, session)
+}
+
+/// Steps 5-7 of the update, starting from the last checkpoint of the session
+fn receive_payload_and_trailer<T: UpdateTransport, P: UpdatePlatform>(
+    methods: &mut UpdateMethods<T, P>,
+    session: &mut ResumeSession,
+) -> Result<(), MessageError> {
+    let platform = methods.platform;
+    let flash_reader = FlashReader::from(platform, session.flash_base_address, session.flash_size);
+    let flash_cbf = wrap_cbf_error(CbfFile::from_reader(&flash_reader))?;
+
+    // ------------------------------------------------------------------------
+    //    Step 5: Receive the CBF payload, and apply the needed relocations.
+    // ------------------------------------------------------------------------
+    // Now at the same way, read the payload (or what is left of it)
+    let payload_start_offset = wrap_cbf_error(flash_cbf.get_readonly_payload())?.get_offset();
+    let payload_end_offset = payload_start_offset + wrap_cbf_error(flash_cbf.payload_size())?;
+    if session.committed_offset < payload_start_offset
+        || session.committed_offset > payload_end_offset
+    {
+        return Err(MessageError::CannotReadCBF);
+    }
+    let to_read = (payload_end_offset - session.committed_offset) as usize;
+    // A delta is computed on the uncompressed payload
+    let delta = session.delta;
+    let compressed = delta.is_none()
+        && wrap_cbf_error(flash_cbf.header_main())?
+            .component_flags()
+            .contains(ComponentFlags::COMPRESSED);
+
+    // Prepare the relocator. The state is rebuilt from the checkpoint, which
+    // for a new update is simply the start of the payload.
+    let num_relocations = wrap_cbf_error(flash_cbf.header_base())?.num_relocations();
+    let new_flash_base_address: u32 = session.flash_base_address + 8 + payload_start_offset;
+
+    let relocator =
+        Relocator::<LINKED_FLASH_BASE, LINKED_SRAM_BASE, BUFF_SIZE, RELOC_BUFF_SIZE>::resume(
+            new_flash_base_address,
+            session.ram_base_address,
+            session.committed_offset as usize,
+            num_relocations as usize,
+            session.applied_relocations as usize,
+        );
+    let record_interval = session.record_interval(payload_end_offset);
+    let mut state = PayloadState {
+        cbf: &flash_cbf,
+        num_relocations: num_relocations as usize,
+        relocator,
+        validation_checksum: session.validation_checksum,
+        new_checksum: session.new_checksum,
+        checksum_buff: ChecksumBuff::new(),
+        // The decoder state cannot be rebuilt, so a compressed payload
+        // (or a delta) always starts over
+        checkpoints: !compressed && delta.is_none(),
+        session: &mut *session,
+        record_interval,
+        chunk: [0x00; DECODED_CHUNK_SIZE],
+        chunk_len: 0,
+    };
+
+    if let Some(delta) = delta {
+        // Ask for it
+        methods.channel_write_single(ComponentUpdateCommand::SendComponentPayload as u8)?;
+        platform.log("Waiting for delta");
+        // Patch the installed payload while receiving, then process the result as usual
+        let source_flash_reader = delta.flash_reader(platform);
+        let source_cbf = wrap_cbf_error(CbfFile::from_reader(&source_flash_reader))?;
+        let mut source = SourceReader::new(
+            platform,
+            &source_cbf,
+            delta.block_base_address,
+            delta.sram_base_address,
+        )?;
+        let mut patcher = Patcher::new(to_read);
+        read_exact_bytes(
+            methods,
+            STREAM_PAYLOAD,
+            delta.patch_size as usize,
+            &mut (&mut state, &mut patcher, &mut source),
+            |methods, data, aux_data| {
+                let (state, patcher, source) = aux_data;
+                patcher.sink(data, || source.next_byte(), |b| state.push(methods, b))
+            },
+        )?;
+        state.flush_chunk(methods)?;
+        if !patcher.is_complete() {
+            return Err(MessageError::FailedCBFValidation);
+        }
+    } else if compressed {
+        // The stream is shorter than the payload, ask for its size
+        let mut size_buff: [u8; CompressedSizeMessage::get_size()] =
+            [0; CompressedSizeMessage::get_size()];
+        methods.channel_ask(
+            ComponentUpdateCommand::SendCompressedSize as u8,
+            &mut size_buff,
+        )?;
+        let compressed_size = CompressedSizeMessage::from(&size_buff)?.get_compressed_size();
+        // Ask for it
+        methods.channel_write_single(ComponentUpdateCommand::SendComponentPayload as u8)?;
+        platform.log("Waiting for compressed payload");
+        // Decompress while receiving, then process the payload as usual
+        let mut decoder = Decoder::new(to_read);
+        read_exact_bytes(
+            methods,
+            STREAM_PAYLOAD,
+            compressed_size as usize,
+            &mut (&mut state, &mut decoder),
+            |methods, data, aux_data| {
+                let (state, decoder) = aux_data;
+                decoder.sink(data, |b| state.push(methods, b))
+            },
+        )?;
+        state.flush_chunk(methods)?;
+        if !decoder.is_complete() {
+            return Err(MessageError::FailedCBFValidation);
+        }
+    } else {
+        // Ask for it
+        methods.channel_write_single(ComponentUpdateCommand::SendComponentPayload as u8)?;
+        platform.log("Waiting for payload");
+        // Read every byte
+        read_exact_bytes(
+            methods,
+            STREAM_PAYLOAD,
+            to_read,
+            &mut state,
+            |methods, data, state| state.process(methods, data),
+        )?;
+    }
+
+    // Finish the relocator operations
+    let mut reloc_methods = UpdateRelocator {
+        cbf: &flash_cbf,
+        methods,
+        num_relocations: num_relocations as usize,
+        checksum: &mut state.new_checksum,
+    };
+    state
+        .relocator
+        .finish(&mut reloc_methods, &mut state.checksum_buff)
+        .map_err(|_| MessageError::FlashError)?;
+    let validation_checksum = state.validation_checksum;
+    let new_checksum = state.new_checksum;
+
+    // -----------------------------------------------------------------
+    //    Step 6: Receive the CBF trailer, and validate total checksum
+    // -----------------------------------------------------------------
+    static_assertions::const_assert_eq!(cbf_lite::CBF_TRAILER_SIZE, 4);
+
+    let mut cbf_trailer_buff: [u8; 4] = [0x00; 4];
+    platform.log("Waiting for trailer");
+
+    methods.channel_ask(
+        ComponentUpdateCommand::SendComponentTrailer as u8,
+        &mut cbf_trailer_buff,
+    )?;
+
+    // Read the trailer (raw)
+    let original_checksum = u32::from_le_bytes(cbf_trailer_buff);
+
+    // Validate checksum and write the new checksum in flash
+    let new_checksum_bytes = new_checksum.to_le_bytes();
+    if validation_checksum != original_checksum
+        || methods
+            .storage_write_stream(
+                session.checksum_offset,
+                &new_checksum_bytes,
+                true, // !!--- important to flush, as later the validation will need the whole cbf stored
+            )
+            .is_err()
+    {
+        return Err(MessageError::FailedCBFValidation);
+    }
+
+    // -----------------------------------------------------------------
+    //    Step 7: Flush write buffer and validate using library
+    // -----------------------------------------------------------------
+
+    // Validate the CBF (last one, to ensure the library reads it correctly)
+    if !wrap_cbf_error(flash_cbf.validate())? {
+        return Err(MessageError::FailedCBFValidation);
+    }
+    // Nothing left to resume
+    session.close(platform)
+}
+
+/// Keeps the block for a later resume when the payload was interrupted by
+/// the link, otherwise deallocates it
+fn abort_update<T: UpdateTransport, P: UpdatePlatform>(
+    methods: &mut UpdateMethods<T, P>,
+    session: Option<ResumeSession>,
+    error: MessageError,
+) -> MessageError {
+    match (session, error) {
+        (
+            Some(mut session),
+            MessageError::TimeoutError | MessageError::ChannelError | MessageError::InvalidCRC,
+        ) => {
+            methods.platform.log("Update interrupted, it can be resumed");
+            // On failure the update resumes from an older checkpoint
+            let _ = session.save(methods.platform);
+        }
+        (session, _) => {
+            if let Some(session) = session {
+                let _ = session.close(methods.platform);
+            }
+            methods.deallocate()
+        }
+    }
+    error
+}
+
+fn start_component<T: UpdateTransport, P: UpdatePlatform>(
+    methods: &mut UpdateMethods<T, P>,
+    flash_base_address: u32,
+) -> Result<(), MessageError> {
+    // Start component, do stuff ...
+    methods.platform.log("Try to start component");
+    if !methods.platform.load_component(flash_base_address) {
+        return Err(MessageError::CannotStartComponent);
+    }
+    methods.platform.log("Component started!");
+    // Respond (at this point, do not delete the component if we just fail to send the end byte)
+    methods.channel_write_single(ComponentUpdateResponse::Success as u8)
+}
+
+pub fn component_add_update<T: UpdateTransport, P: UpdatePlatform>(
+    channel: &mut T,
+    platform: &P,
+) -> Result<(), MessageError> {
+    component_update(channel, platform, None)
+}
+
+/// Same as a normal update, but the payload is a delta of the installed version.
+/// When that version is not the source of the delta the tool falls back to a normal update.
+pub fn component_delta_update<T: UpdateTransport, P: UpdatePlatform>(
+    channel: &mut T,
+    platform: &P,
+) -> Result<(), MessageError> {
+    // -----------------------------
+    //    Step 0: Delta source
+    // -----------------------------
+    let mut source_buff: [u8; DeltaSourceMessage::get_size()] = [0; DeltaSourceMessage::get_size()];
+    crate::utils::channel_ask(
+        channel,
+        ComponentUpdateCommand::SendDeltaSource as u8,
+        &mut source_buff,
+    )?;
+    let msg = DeltaSourceMessage::from(&source_buff)?;
+    platform.log("Checking delta source");
+    let delta = find_delta_source(platform, &msg)?;
+    component_update(channel, platform, Some(delta))
+}
+
+fn component_update<T: UpdateTransport, P: UpdatePlatform>(
+    channel: &mut T,
+    platform: &P,
+    delta: Option<DeltaSource>,
+) -> Result<(), MessageError> {
+    // A new update replaces the interrupted ones, if any
+    ResumeSession::discard_all(platform)?;
+    // -----------------------------
+    //    Step 1: Fixed Header
+    // -----------------------------
+    let mut cbf_header_buff: [u8; FixedHeaderMessage::get_size()] =
+        [0; FixedHeaderMessage::get_size()];
+    crate::utils::channel_ask(
+        channel,
+        ComponentUpdateCommand::SendComponentFixedHeader as u8,
+        &mut cbf_header_buff,
+    )?;
+    // Validate header
+    let fhm = FixedHeaderMessage::from(&cbf_header_buff)?;
+    // Read cbf, caching only the needed values: the object is not used anymore
+    let (header_base, needed_flash, needed_ram, checksum_offset) = {
+        let cbf_reader = BufferReaderImpl::from(fhm.get_raw());
+        let cbf = wrap_cbf_error(cbf_lite::CbfFile::from_reader(&cbf_reader))?;
+        let header_base = wrap_cbf_error(cbf.header_base())?;
+        (
+            header_base,
+            header_base.total_size(),
+            wrap_cbf_error(cbf.header_main())?.component_min_ram(),
+            wrap_cbf_error(cbf.checksum_offset())?, // safe call
+        )
+    };
+
+    // Request the allocation
+    let (mut methods, allocation) =
+        UpdateMethods::methods_for_requirements(needed_flash, needed_ram, platform, channel)
+            .map_err(allocation_error)?;
+    // Process everything
+    let mut session: Option<ResumeSession> = None;
+    add_update_core(
+        &mut methods,
+        &allocation,
+        checksum_offset,
+        &header_base,
+        &fhm,
+        delta,
+        &mut session,
+    )
+    .map_err(|e| abort_update(&mut methods, session, e))?;
+    start_component(&mut methods, allocation.flash_base_address)
+}
+
+pub fn component_resume<T: UpdateTransport, P: UpdatePlatform>(
+    channel: &mut T,
+    platform: &P,
+) -> Result<(), MessageError> {
+    // -----------------------------
+    //    Step 1: Session ID
+    // -----------------------------
+    let mut session_buff: [u8; SessionIdMessage::get_size()] = [0; SessionIdMessage::get_size()];
+    crate::utils::channel_ask(
+        channel,
+        ComponentUpdateCommand::SendSessionId as u8,
+        &mut session_buff,
+    )?;
+    let msg = SessionIdMessage::from(&session_buff)?;
+    let mut session = match ResumeSession::find(platform, Some(msg.get_session_id()))? {
+        Some(session) => session,
+        None => return Err(MessageError::NoResumableSession),
+    };
+    // -----------------------------
+    //    Step 2: Report the progress
+    // -----------------------------
+    // The tool checks the validation checksum against its copy of the image,
+    // then continues from the offset.
+    let status = ResumeStatusMessage::new(session.committed_offset, session.validation_checksum);
+    crate::utils::channel_write(channel, &status.get_raw())?;
+    platform.log("Resuming update");
+    // -----------------------------
+    //    Step 3: Continue the update
+    // -----------------------------
+    let mut methods = UpdateMethods::for_block(session.flash_base_address, platform, channel);
+    receive_payload_and_trailer(&mut methods, &mut session)
+        .map_err(|e| abort_update(&mut methods, Some(session), e))?;
+    start_component(&mut methods, session.flash_base_address)
+}
+
+/// Receives and discards a stream, for measuring the throughput of the link
+pub fn link_benchmark<T: UpdateTransport, P: UpdatePlatform>(
+    channel: &mut T,
+    platform: &P,
+) -> Result<(), MessageError> {
+    let mut size_buff: [u8; BenchmarkSizeMessage::get_size()] =
+        [0; BenchmarkSizeMessage::get_size()];
+    crate::utils::channel_ask(
+        channel,
+        LinkBenchmarkCommand::SendBenchmarkSize as u8,
+        &mut size_buff,
+    )?;
+    let msg = BenchmarkSizeMessage::from(&size_buff)?;
+    let mut methods = UpdateMethods::for_block(0, platform, channel);
+    read_exact_bytes(
+        &mut methods,
+        STREAM_BENCHMARK,
+        msg.get_benchmark_size() as usize,
+        &mut (),
+        |_, _, _| Ok(()),
+    )?;
+    methods.channel_write_single(LinkBenchmarkResponse::Success as u8)
+}
+
+/// Fail if no space available
+fn allocation_error(error: PlatformError) -> MessageError {
+    match error {
+        PlatformError::OutOfSpace => MessageError::NotEnoughSpace,
+        PlatformError::Storage => MessageError::FlashError,
+    }
+}
+
+fn update_checksum(checksum: &mut u32, bytes: &[u8]) {
+    for i in (0..bytes.len()).step_by(4) {
+        // Read 4 bytes
+        let word: u32 = u32_from_le_bytes(&bytes[i..i + 4]);
+        *checksum ^= word;
+    }
+}
+
+/*
+    Tests
+*/
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::crc::crc8_update;
+    use crate::mock::MockPlatform;
+    use crate::utils::receive_hello;
+    use std::collections::VecDeque;
+    use std::vec::Vec;
+    use update_transport::mock::{LinkHost, MockHost, MockTransport};
+
+    const COMPONENT: &[u8] = include_bytes!(
+        "../../../toolchain/modules/elf2cbf/examples/component3/output/component.cbf"
+    );
+
+    /// Sends a component as the update tool does
+    struct UpdateHost {
+        fixed_header: Vec<u8>,
+        variable_header: &'static [u8],
+        payload: &'static [u8],
+        trailer: [u8; 4],
+        lost_frames: Vec<u16>,
+        link: Option<LinkHost<'static>>,
+    }
+
+    impl UpdateHost {
+        fn new(cbf: &'static [u8]) -> Self {
+            let reader = BufferReaderImpl::from(cbf);
+            let payload_start = CbfFile::from_reader(&reader)
+                .unwrap()
+                .get_readonly_payload()
+                .unwrap()
+                .get_offset() as usize;
+            let trailer_start = cbf.len() - cbf_lite::CBF_TRAILER_SIZE;
+            let mut fixed_header = cbf[..cbf_lite::FIXED_HEADER_SIZE].to_vec();
+            let mut crc: u8 = 0x00;
+            for b in fixed_header.iter() {
+                crc8_update(&mut crc, *b);
+            }
+            fixed_header.push(crc);
+            Self {
+                fixed_header,
+                variable_header: &cbf[cbf_lite::FIXED_HEADER_SIZE..payload_start],
+                payload: &cbf[payload_start..trailer_start],
+                trailer: cbf[trailer_start..].try_into().unwrap(),
+                lost_frames: Vec::new(),
+                link: None,
+            }
+        }
+
+        fn is_complete(&self) -> bool {
+            matches!(&self.link, Some(link) if link.is_complete())
+        }
+    }
+
+    impl MockHost for UpdateHost {
+        fn on_write(&mut self, data: &[u8], incoming: &mut VecDeque<u8>) {
+            // Control frames of the link are longer than a command
+            if data.len() > 1 {
+                if let Some(link) = self.link.as_mut() {
+                    link.on_write(data, incoming);
+                }
+                return;
+            }
+            match data[0] {
+                x if x == ComponentUpdateCommand::SendComponentFixedHeader as u8 => {
+                    incoming.extend(&self.fixed_header)
+                }
+                x if x == ComponentUpdateCommand::SendComponentVariableHeader as u8 => {
+                    self.link = Some(LinkHost::new(
+                        STREAM_VARIABLE_HEADER,
+                        self.variable_header,
+                        LINK_WINDOW,
+                        &[],
+                    ))
+                }
+                x if x == ComponentUpdateCommand::SendComponentPayload as u8 => {
+                    self.link = Some(LinkHost::new(
+                        STREAM_PAYLOAD,
+                        self.payload,
+                        LINK_WINDOW,
+                        &self.lost_frames,
+                    ))
+                }
+                x if x == ComponentUpdateCommand::SendComponentTrailer as u8 => {
+                    incoming.extend(&self.trailer)
+                }
+                _ => (),
+            }
+        }
+
+        fn on_idle(&mut self, incoming: &mut VecDeque<u8>) {
+            if let Some(link) = self.link.as_mut() {
+                link.on_idle(incoming);
+            }
+        }
+    }
+
+    /// Runs the update as the main loop of the component does
+    fn update(
+        transport: &mut MockTransport<UpdateHost>,
+        platform: &MockPlatform,
+    ) -> Result<(), MessageError> {
+        let operation = OperationType::ComponentUpdate as u8;
+        let mut crc: u8 = 0x00;
+        crc8_update(&mut crc, operation);
+        transport.push(&[operation, crc]);
+        let msg = receive_hello(transport).unwrap();
+        assert_eq!(
+            msg.get_operation() as u8,
+            OperationType::ComponentUpdate as u8
+        );
+        component_add_update(transport, platform)
+    }
+
+    fn assert_installed(platform: &MockPlatform, base: u32) {
+        let flash_reader = FlashReader::from(platform, base, COMPONENT.len() as u32);
+        let cbf = CbfFile::from_reader(&flash_reader).unwrap();
+        assert_eq!(cbf.validate(), Ok(true));
+        assert_eq!(platform.loaded(), [base]);
+        // Nothing left to resume
+        assert!(ResumeSession::find(platform, None).unwrap().is_none());
+    }
+
+    #[test]
+    fn full_update() {
+        let platform = MockPlatform::new(0x10000);
+        let mut transport = MockTransport::new(UpdateHost::new(COMPONENT));
+        assert!(update(&mut transport, &platform).is_ok());
+        assert!(transport.host().is_complete());
+        assert_eq!(
+            transport.written().last(),
+            Some(&(ComponentUpdateResponse::Success as u8))
+        );
+        let base = platform.nth_block(0).unwrap().block_base_address;
+        assert_installed(&platform, base);
+        // Only the session is given back
+        assert_eq!(platform.blocks(), Ok(1));
+        assert_eq!(platform.deallocated().len(), 1);
+    }
+
+    #[test]
+    fn lost_payload_frames() {
+        let platform = MockPlatform::new(0x10000);
+        let mut host = UpdateHost::new(COMPONENT);
+        host.lost_frames = [1, 2, 5].to_vec();
+        let mut transport = MockTransport::new(host);
+        assert!(update(&mut transport, &platform).is_ok());
+        let base = platform.nth_block(0).unwrap().block_base_address;
+        assert_installed(&platform, base);
+    }
+
+    #[test]
+    fn wrong_trailer() {
+        let platform = MockPlatform::new(0x10000);
+        let mut host = UpdateHost::new(COMPONENT);
+        host.trailer[0] ^= 0x01;
+        let mut transport = MockTransport::new(host);
+        assert!(matches!(
+            update(&mut transport, &platform),
+            Err(MessageError::FailedCBFValidation)
+        ));
+        // The blocks are given back, and the component never started
+        assert_eq!(platform.blocks(), Ok(0));
+        assert_eq!(platform.deallocated().len(), 2);
+        assert!(platform.loaded().is_empty());
+    }
+
+    /// Leaves an update interrupted after the header, as a reset would
+    fn interrupt_update(platform: &MockPlatform, session_id: u32) -> ResumeSession {
+        let allocation = platform
+            .allocate_component(COMPONENT.len() as u32, 0x100)
+            .unwrap();
+        platform
+            .write_stream(allocation.flash_base_address, 0, COMPONENT, true)
+            .unwrap();
+        let session_block = platform.allocate_session().unwrap();
+        let mut session =
+            ResumeSession::new(session_id, session_block, &allocation, 0, 0x100, 0, None);
+        session.start(platform).unwrap();
+        session
+    }
+
+    #[test]
+    fn find_session() {
+        let platform = MockPlatform::new(0x10000);
+        let first = interrupt_update(&platform, 1);
+        let second = interrupt_update(&platform, 2);
+        let installed = interrupt_update(&platform, 3);
+        assert!(platform.load_component(installed.flash_base_address));
+        let found = ResumeSession::find(&platform, None).unwrap().unwrap();
+        assert_eq!(found.flash_base_address, first.flash_base_address);
+        assert_eq!(found.session_block_base_address, first.session_block_base_address);
+        assert_eq!(found.committed_offset, 0x100);
+        let found = ResumeSession::find(&platform, Some(2)).unwrap().unwrap();
+        assert_eq!(found.flash_base_address, second.flash_base_address);
+        // The session of an installed component cannot be resumed
+        assert!(ResumeSession::find(&platform, Some(3)).unwrap().is_none());
+    }
+
+    #[test]
+    fn stale_sessions_replaced() {
+        let platform = MockPlatform::new(0x10000);
+        interrupt_update(&platform, 1);
+        interrupt_update(&platform, 2);
+        let mut transport = MockTransport::new(UpdateHost::new(COMPONENT));
+        assert!(update(&mut transport, &platform).is_ok());
+        // The blocks of both interrupted updates are given back, then the new session
+        assert_eq!(platform.blocks(), Ok(1));
+        assert_eq!(platform.deallocated().len(), 5);
+        let base = platform.nth_block(0).unwrap().block_base_address;
+        assert_installed(&platform, base);
+    }
+
+    #[test]
+    fn unfinalized_component_ignored() {
+        let platform = MockPlatform::new(0x10000);
+        // A partial copy of the same version is not installed, so it's not a downgrade
+        let allocation = platform
+            .allocate_component(COMPONENT.len() as u32, 0x100)
+            .unwrap();
+        platform
+            .write_stream(allocation.flash_base_address, 0, COMPONENT, true)
+            .unwrap();
+        let mut transport = MockTransport::new(UpdateHost::new(COMPONENT));
+        assert!(update(&mut transport, &platform).is_ok());
+        let base = platform.nth_block(1).unwrap().block_base_address;
+        assert_installed(&platform, base);
+    }
+
+    #[test]
+    fn corrupted_fixed_header() {
+        let platform = MockPlatform::new(0x10000);
+        let mut host = UpdateHost::new(COMPONENT);
+        *host.fixed_header.last_mut().unwrap() ^= 0xFF;
+        let mut transport = MockTransport::new(host);
+        assert!(matches!(
+            update(&mut transport, &platform),
+            Err(MessageError::InvalidCRC)
+        ));
+        // Nothing was allocated
+        assert_eq!(platform.blocks(), Ok(0));
+    }
+
+    #[test]
+    fn not_enough_space() {
+        let platform = MockPlatform::new(COMPONENT.len() as u32);
+        let mut transport = MockTransport::new(UpdateHost::new(COMPONENT));
+        assert!(matches!(
+            update(&mut transport, &platform),
+            Err(MessageError::NotEnoughSpace)
+        ));
+    }
+}
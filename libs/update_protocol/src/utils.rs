@@ -0,0 +1,104 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use crate::consts::*;
+use crate::messages::{HelloMessage, HelloResponseMessage, MessageError};
+use crate::platform::UpdatePlatform;
+use cbf_lite::BufferReader;
+use update_transport::UpdateTransport;
+
+/**
+ * Flash Reader
+ */
+pub struct FlashReader<'p, P: UpdatePlatform> {
+    platform: &'p P,
+    base_addr: u32,
+    size: u32,
+}
+
+impl<'p, P: UpdatePlatform> FlashReader<'p, P> {
+    pub fn from(platform: &'p P, base_addr: u32, size: u32) -> Self {
+        Self {
+            platform,
+            base_addr,
+            size,
+        }
+    }
+}
+
+impl<'a, P: UpdatePlatform> BufferReader<'a> for FlashReader<'_, P> {
+    fn read(&self, offset: u32, dest: &mut [u8]) -> Result<(), cbf_lite::CbfError> {
+        // Check offset
+        if offset >= self.size {
+            return Err(cbf_lite::CbfError::ReadError);
+        }
+        // Ask to read to storage
+        if self.platform.read_stream(self.base_addr, offset, dest).is_err() {
+            return Err(cbf_lite::CbfError::ReadError);
+        }
+        Ok(())
+    }
+}
+
+/**
+ * Channel functions
+ */
+pub fn channel_write_single<T: UpdateTransport>(
+    channel: &mut T,
+    value: u8,
+) -> Result<(), MessageError> {
+    let buffer: [u8; 1] = [value; 1];
+    Ok(channel.write_block(&buffer)?)
+}
+pub fn channel_write<T: UpdateTransport>(channel: &mut T, buff: &[u8]) -> Result<(), MessageError> {
+    Ok(channel.write_block(buff)?)
+}
+pub fn channel_ask<T: UpdateTransport>(
+    channel: &mut T,
+    cmd: u8,
+    buffer: &mut [u8],
+) -> Result<(), MessageError> {
+    let buffer_out: [u8; 1] = [cmd; 1];
+    Ok(channel.transmit_timed(&buffer_out, buffer, READ_TIMEOUT_TICKS)?)
+}
+
+/**
+ * Hello
+ */
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum HelloError {
+    /// Nothing arrived on the channel
+    Read,
+    /// Not a hello, it's ignored
+    Invalid,
+    /// The response cannot be sent
+    Write,
+}
+
+/// Waits for the hello of the update tool and acknowledges it
+pub fn receive_hello<T: UpdateTransport>(channel: &mut T) -> Result<HelloMessage, HelloError> {
+    let mut hello_buffer: [u8; HelloMessage::get_size()] = [0x00; HelloMessage::get_size()];
+    channel
+        .read_block(&mut hello_buffer)
+        .map_err(|_| HelloError::Read)?;
+    let msg = HelloMessage::from(&hello_buffer).map_err(|_| HelloError::Invalid)?;
+    let response = HelloResponseMessage::new(msg.get_operation());
+    channel
+        .write_block(&response.get_raw())
+        .map_err(|_| HelloError::Write)?;
+    Ok(msg)
+}
+
+pub fn u32_from_le_bytes(buff: &[u8]) -> u32 {
+    buff[0] as u32
+        | ((buff[1] as u32) << 8)
+        | ((buff[2] as u32) << 16)
+        | ((buff[3] as u32) << 24)
+}
+
+pub fn wrap_cbf_error<T>(r: Result<T, cbf_lite::CbfError>) -> Result<T, MessageError> {
+    r.map_err(|_| {
+        MessageError::CannotReadCBF
+    })
+}
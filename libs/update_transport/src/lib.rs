@@ -0,0 +1,151 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+#![no_std]
+
+#[cfg(any(test, feature = "mock"))]
+extern crate std;
+#[cfg(any(test, feature = "mock"))]
+pub mod mock;
+
+use update_link::{ControlFrame, LinkReceiver, DATA_FRAME_SIZE};
+
+/**
+ * Error Type
+ */
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[repr(u8)]
+pub enum TransportError {
+    /// The channel cannot be used (busy, unavailable, bad arguments)
+    ChannelError = 1,
+    /// Nothing arrived in time
+    ReadTimeOut = 2,
+}
+
+/**
+ * Transport
+ */
+/// Channel used by the update component to talk with the update tool.
+/// Reads always fill the whole buffer, writes always send the whole buffer.
+pub trait UpdateTransport {
+    fn write_block(&mut self, data: &[u8]) -> Result<(), TransportError>;
+    /// Waits until the buffer is filled
+    fn read_block(&mut self, data: &mut [u8]) -> Result<(), TransportError>;
+    fn read_block_timed(
+        &mut self,
+        data: &mut [u8],
+        timeout_ticks: u32,
+    ) -> Result<(), TransportError>;
+    /// Sends data_out, while already waiting for data_in. Quick responses are not
+    /// missed even if the caller is delayed after the transmission.
+    fn transmit_timed(
+        &mut self,
+        data_out: &[u8],
+        data_in: &mut [u8],
+        timeout_ticks: u32,
+    ) -> Result<(), TransportError>;
+}
+
+/// Receives size bytes of a stream through the link layer, calling deliver with
+/// the data in order: the sender keeps a window of fragments in flight, each one
+/// acknowledged by a control frame. Fails after max_timeouts consecutive timeouts.
+pub fn receive_stream<T, F, E, const WINDOW: usize>(
+    transport: &mut T,
+    stream: u8,
+    size: usize,
+    timeout_ticks: u32,
+    max_timeouts: u32,
+    mut deliver: F,
+) -> Result<(), E>
+where
+    T: UpdateTransport,
+    F: FnMut(&mut T, &[u8]) -> Result<(), E>,
+    E: From<TransportError>,
+{
+    let mut receiver = LinkReceiver::<WINDOW>::new(stream, size);
+    let mut frame_buffer: [u8; DATA_FRAME_SIZE] = [0x00; DATA_FRAME_SIZE];
+    let mut control: Option<ControlFrame> = None;
+    let mut timeouts: u32 = 0;
+
+    while !receiver.is_complete() {
+        // 1. Wait for the next frame, answering to the previous one
+        let result = match control {
+            Some(control) => {
+                transport.transmit_timed(&control.encode(), &mut frame_buffer, timeout_ticks)
+            }
+            None => transport.read_block_timed(&mut frame_buffer, timeout_ticks),
+        };
+        match result {
+            Err(TransportError::ReadTimeOut) => {
+                // Nothing arrived, ask again for the missing fragment
+                timeouts += 1;
+                if timeouts > max_timeouts {
+                    return Err(TransportError::ReadTimeOut.into());
+                }
+                control = Some(receiver.timeout());
+                continue;
+            }
+            other => other?,
+        }
+        timeouts = 0;
+        // 2. Validate the frame, then deliver the data that is now in order
+        control = Some(receiver.receive(&frame_buffer, |data| deliver(transport, data))?);
+    }
+    // 3. Acknowledge the last fragment
+    if let Some(control) = control {
+        transport.write_block(&control.encode())?;
+    }
+    Ok(())
+}
+
+/*
+    Tests
+*/
+#[cfg(test)]
+mod test {
+    use super::*;
+    use mock::{LinkHost, MockTransport};
+    use std::vec::Vec;
+
+    const WINDOW: usize = 4;
+
+    fn receive(
+        transport: &mut MockTransport<LinkHost>,
+        size: usize,
+    ) -> Result<Vec<u8>, TransportError> {
+        let mut output: Vec<u8> = Vec::new();
+        receive_stream::<_, _, TransportError, WINDOW>(transport, 1, size, 100, 3, |_, data| {
+            output.extend_from_slice(data);
+            Ok(())
+        })?;
+        Ok(output)
+    }
+
+    #[test]
+    fn clean_transfer() {
+        let data: Vec<u8> = (0..1000u32).map(|x| x as u8).collect();
+        let mut transport = MockTransport::new(LinkHost::new(1, &data, WINDOW, &[]));
+        assert_eq!(receive(&mut transport, data.len()), Ok(data.clone()));
+        assert!(transport.host().is_complete());
+        assert_eq!(transport.timeouts(), 0);
+    }
+
+    #[test]
+    fn lost_frames() {
+        let data: Vec<u8> = (0..1000u32).map(|x| (x * 7) as u8).collect();
+        let mut transport = MockTransport::new(LinkHost::new(1, &data, WINDOW, &[0, 3, 4, 15]));
+        assert_eq!(receive(&mut transport, data.len()), Ok(data.clone()));
+        assert!(transport.host().is_complete());
+    }
+
+    #[test]
+    fn silent_sender() {
+        let mut transport = MockTransport::new(LinkHost::new(1, &[], WINDOW, &[]));
+        assert_eq!(
+            receive(&mut transport, 100),
+            Err(TransportError::ReadTimeOut)
+        );
+        assert_eq!(transport.timeouts(), 4);
+    }
+}
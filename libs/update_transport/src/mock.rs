@@ -0,0 +1,180 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Transport for host-side tests. The update logic runs as the device, while
+//! the test emulates the update tool at the other end of the link.
+
+use std::collections::VecDeque;
+use std::vec::Vec;
+
+use update_link::{ControlFrame, DataFrame, LinkSender};
+
+use crate::{TransportError, UpdateTransport};
+
+/// Other end of the link
+pub trait MockHost {
+    /// Called with every block written by the device, the answer goes in incoming
+    fn on_write(&mut self, data: &[u8], incoming: &mut VecDeque<u8>);
+    /// Called when the device waits for more than what is in incoming
+    fn on_idle(&mut self, _incoming: &mut VecDeque<u8>) {}
+}
+
+/// A plain closure answers only to what the device writes
+impl<F> MockHost for F
+where
+    F: FnMut(&[u8], &mut VecDeque<u8>),
+{
+    fn on_write(&mut self, data: &[u8], incoming: &mut VecDeque<u8>) {
+        self(data, incoming)
+    }
+}
+
+pub struct MockTransport<H: MockHost> {
+    host: H,
+    incoming: VecDeque<u8>,
+    written: Vec<u8>,
+    timeouts: usize,
+}
+
+impl<H: MockHost> MockTransport<H> {
+    pub fn new(host: H) -> Self {
+        Self {
+            host,
+            incoming: VecDeque::new(),
+            written: Vec::new(),
+            timeouts: 0,
+        }
+    }
+
+    pub fn host(&self) -> &H {
+        &self.host
+    }
+
+    /// Queues data for the device, as if the host sent it first
+    pub fn push(&mut self, data: &[u8]) {
+        self.incoming.extend(data);
+    }
+
+    /// Everything the device wrote so far
+    pub fn written(&self) -> &[u8] {
+        &self.written
+    }
+
+    /// Timed reads that found nothing
+    pub fn timeouts(&self) -> usize {
+        self.timeouts
+    }
+
+    /// Fills data with the incoming bytes. As on a real channel,
+    /// a partial block is lost when the read fails.
+    fn fill(&mut self, data: &mut [u8]) -> bool {
+        if self.incoming.len() < data.len() {
+            self.host.on_idle(&mut self.incoming);
+        }
+        if self.incoming.len() < data.len() {
+            self.incoming.clear();
+            return false;
+        }
+        for b in data.iter_mut() {
+            *b = self.incoming.pop_front().unwrap();
+        }
+        true
+    }
+}
+
+impl<H: MockHost> UpdateTransport for MockTransport<H> {
+    fn write_block(&mut self, data: &[u8]) -> Result<(), TransportError> {
+        self.written.extend_from_slice(data);
+        self.host.on_write(data, &mut self.incoming);
+        Ok(())
+    }
+
+    fn read_block(&mut self, data: &mut [u8]) -> Result<(), TransportError> {
+        // The host has nothing more to say: a device would wait forever
+        if !self.fill(data) {
+            return Err(TransportError::ChannelError);
+        }
+        Ok(())
+    }
+
+    fn read_block_timed(
+        &mut self,
+        data: &mut [u8],
+        _timeout_ticks: u32,
+    ) -> Result<(), TransportError> {
+        if !self.fill(data) {
+            self.timeouts += 1;
+            return Err(TransportError::ReadTimeOut);
+        }
+        Ok(())
+    }
+
+    fn transmit_timed(
+        &mut self,
+        data_out: &[u8],
+        data_in: &mut [u8],
+        timeout_ticks: u32,
+    ) -> Result<(), TransportError> {
+        self.write_block(data_out)?;
+        self.read_block_timed(data_in, timeout_ticks)
+    }
+}
+
+/// Sends a stream through the link layer, as the update tool does.
+/// Each frame in lost_frames is lost the first time it is sent.
+pub struct LinkHost<'a> {
+    sender: LinkSender<'a>,
+    started: bool,
+    lost_frames: Vec<u16>,
+}
+
+impl<'a> LinkHost<'a> {
+    pub fn new(stream: u8, data: &'a [u8], window: usize, lost_frames: &[u16]) -> Self {
+        Self {
+            sender: LinkSender::new(stream, data, window),
+            started: false,
+            lost_frames: lost_frames.to_vec(),
+        }
+    }
+
+    /// True when the device acknowledged the whole stream
+    pub fn is_complete(&self) -> bool {
+        self.sender.is_complete()
+    }
+
+    fn send(&mut self, frame: DataFrame, incoming: &mut VecDeque<u8>) {
+        if let Some(pos) = self.lost_frames.iter().position(|s| *s == frame.sequence) {
+            self.lost_frames.remove(pos);
+            return;
+        }
+        incoming.extend(frame.encode());
+    }
+
+    fn send_window(&mut self, incoming: &mut VecDeque<u8>) {
+        while let Some(frame) = self.sender.next_frame() {
+            self.send(frame, incoming);
+        }
+    }
+}
+
+impl<'a> MockHost for LinkHost<'a> {
+    fn on_write(&mut self, data: &[u8], incoming: &mut VecDeque<u8>) {
+        let control = match ControlFrame::decode(data) {
+            Ok(control) => control,
+            Err(_) => return,
+        };
+        if let Some(frame) = self.sender.on_control(control) {
+            self.send(frame, incoming);
+        }
+        self.send_window(incoming);
+    }
+
+    fn on_idle(&mut self, incoming: &mut VecDeque<u8>) {
+        // The sender starts on its own, then follows the control frames
+        if !self.started {
+            self.started = true;
+            self.send_window(incoming);
+        }
+    }
+}
@@ -0,0 +1,146 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use zerocopy::{AsBytes, FromBytes};
+
+use crate::FaultInfo;
+use crate::FaultSource;
+
+/**
+ * Core dump structures, shared between the kernel (that writes the dump),
+ * the update component (that retrieves it) and the system builder (that
+ * reserves the block).
+ *
+ * Layout of a COREDUMP flash block, after the allocator header:
+ *  - CoreDumpConfig, written when the image is generated
+ *  - CoreDumpHeader, written by the kernel when the dump is taken
+ *  - header.num_tasks * CoreDumpTaskEntry
+ *  - header.stack_size bytes, copy of the faulting stack from header.stack_base
+ *
+ * Everything after the config is left erased (0xFF) until a dump is taken.
+ */
+pub const COREDUMP_MAGIC: u32 = 0x504D_4443; // "CDMP"
+pub const COREDUMP_VERSION: u16 = 1;
+pub const COREDUMP_PANIC_FILE_LEN: usize = 32;
+pub const COREDUMP_NO_COMPONENT: u16 = 0xFFFF;
+pub const COREDUMP_NO_ADDRESS: u32 = 0xFFFF_FFFF;
+
+bitflags::bitflags! {
+    #[repr(transparent)]
+    pub struct CoreDumpFlags: u32 {
+        /// Take a dump also when a component faults, not only on kernel panic
+        const ON_FAULT = 1 << 0;
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+#[repr(u16)]
+pub enum CoreDumpReason {
+    KernelPanic = 1,
+    ComponentFault = 2,
+}
+
+/// Configuration of the dump, placed at the beginning of the block
+#[derive(Copy, Clone, Debug, FromBytes, AsBytes)]
+#[repr(C)]
+pub struct CoreDumpConfig {
+    /// Maximum number of bytes of the faulting stack to save
+    pub stack_window: u32,
+    /// Raw CoreDumpFlags
+    pub flags: u32,
+}
+
+impl CoreDumpConfig {
+    pub fn flags(&self) -> CoreDumpFlags {
+        CoreDumpFlags::from_bits_truncate(self.flags)
+    }
+}
+
+/// Fixed part of the dump
+#[derive(Copy, Clone, Debug, FromBytes, AsBytes)]
+#[repr(C)]
+pub struct CoreDumpHeader {
+    pub magic: u32,
+    pub version: u16,
+    /// Raw CoreDumpReason
+    pub reason: u16,
+    /// Size of the whole dump (header, tasks and stack)
+    pub total_size: u32,
+    /// Faulting component, or the one running during a kernel panic
+    pub component_id: u16,
+    pub num_tasks: u16,
+    /// Fault, as encoded by encode_fault
+    pub fault_kind: u32,
+    pub fault_arg0: u32,
+    pub fault_arg1: u32,
+    /// Fault status registers
+    pub cfsr: u32,
+    pub mmfar: u32,
+    pub bfar: u32,
+    /// SavedState of the task: r4-r11, psp, exc_return, s16-s31
+    pub saved_state: [u32; 26],
+    /// Exception frame found at psp: r0-r3, r12, lr, pc, xpsr
+    pub exception_frame: [u32; 8],
+    /// Kernel context (only for kernel panics)
+    pub kernel_sp: u32,
+    pub kernel_lr: u32,
+    /// Window of the stack saved after the task entries
+    pub stack_base: u32,
+    pub stack_size: u32,
+    /// Location of the kernel panic
+    pub panic_line: u32,
+    pub panic_file: [u8; COREDUMP_PANIC_FILE_LEN],
+}
+
+/// Summary of a task, one for each loaded component
+#[derive(Copy, Clone, Debug, FromBytes, AsBytes)]
+#[repr(C)]
+pub struct CoreDumpTaskEntry {
+    pub component_id: u16,
+    /// 0: healthy, 1: faulted
+    pub state: u8,
+    pub generation: u8,
+    pub component_version: u32,
+    /// Base address of the component flash block
+    pub block_base: u32,
+    /// Relocated base of the read-only payload, needed to resolve symbols
+    pub text_base: u32,
+    /// Relocated SRAM base
+    pub sram_base: u32,
+    pub psp: u32,
+    pub pc: u32,
+}
+
+pub const COREDUMP_CONFIG_SIZE: usize = core::mem::size_of::<CoreDumpConfig>();
+pub const COREDUMP_HEADER_SIZE: usize = core::mem::size_of::<CoreDumpHeader>();
+pub const COREDUMP_TASK_ENTRY_SIZE: usize = core::mem::size_of::<CoreDumpTaskEntry>();
+
+/// Converts a fault in the (kind, arg0, arg1) triple saved in the dump.
+pub fn encode_fault(fault: &FaultInfo) -> (u32, u32, u32) {
+    let source_code = |source: &FaultSource| match source {
+        FaultSource::User => 0u32,
+        FaultSource::Kernel => 1u32,
+    };
+    match fault {
+        FaultInfo::MemoryAccess { address, source } => (
+            1,
+            address.unwrap_or(COREDUMP_NO_ADDRESS),
+            source_code(source),
+        ),
+        FaultInfo::StackOverflow { address } => (2, *address, 0),
+        FaultInfo::BusError { address, source } => (
+            3,
+            address.unwrap_or(COREDUMP_NO_ADDRESS),
+            source_code(source),
+        ),
+        FaultInfo::DivideByZero => (4, 0, 0),
+        FaultInfo::IllegalText => (5, 0, 0),
+        FaultInfo::IllegalInstruction => (6, 0, 0),
+        FaultInfo::InvalidOperation(code) => (7, *code, 0),
+        FaultInfo::SyscallUsage(usage) => (8, *usage as u32, 0),
+        FaultInfo::Panic => (9, 0, 0),
+        FaultInfo::Injected(task) => (10, task.0 as u32, 0),
+        FaultInfo::FromServer(task, reason) => (11, task.0 as u32, *reason as u32),
+    }
+}
@@ -15,7 +15,9 @@ use zerocopy::{FromBytes, AsBytes, ByteSlice};
 pub enum BlockType {
     NONE,
     COMPONENT,
-    // DATA,
+    DATA,
+    COREDUMP,
+    SESSION,
     UNKNOWN(u16),
 }
 
@@ -24,6 +26,9 @@ impl From<u16> for BlockType {
         match x {
             0xFFFF => BlockType::NONE,
             0xFFFE => BlockType::COMPONENT,
+            0xFFFD => BlockType::DATA,
+            0xFFFC => BlockType::COREDUMP,
+            0xFFFB => BlockType::SESSION,
             x => BlockType::UNKNOWN(x)
         }
     }
@@ -33,6 +38,9 @@ impl From<BlockType> for u16 {
         match x {
             BlockType::NONE => 0xFFFF,
             BlockType::COMPONENT => 0xFFFE,
+            BlockType::DATA => 0xFFFD,
+            BlockType::COREDUMP => 0xFFFC,
+            BlockType::SESSION => 0xFFFB,
             BlockType::UNKNOWN(x) => x
         }
     }
@@ -89,4 +97,107 @@ unsafe impl AsBytes for BlockType {
         Self: Sized {
     }
     // TODO: other to add here?
-}
\ No newline at end of file
+}
+/**
+ * While a component is being installed, the update component records the progress
+ * in a separate SESSION block, so that the update can be resumed also after a reset.
+ * The block is a sequence of slots written once each, the last valid one is the
+ * state of the session. The block is given back when the session ends.
+ */
+
+/// Size of a session record, a multiple of the write granularity
+pub const SESSION_SLOT_SIZE: usize = 40;
+pub const SESSION_SLOTS: usize = 8;
+pub const SESSION_AREA_SIZE: usize = SESSION_SLOT_SIZE * SESSION_SLOTS;
+
+const SESSION_MAGIC: u32 = 0x5E55_10A7;
+const SESSION_WORDS: usize = 9;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SessionRecord {
+    /// Checksum of the fixed header of the image
+    pub session_id: u32,
+    /// Block receiving the image, as the storage reports it
+    pub component_block_base_address: u32,
+    /// Position in the image of the checkpoint
+    pub committed_offset: u32,
+    pub applied_relocations: u32,
+    pub validation_checksum: u32,
+    pub new_checksum: u32,
+    /// Installed component the payload is a delta of, 0 if none
+    pub delta_block_base_address: u32,
+    pub delta_sram_base_address: u32,
+    pub delta_patch_size: u32,
+}
+
+impl SessionRecord {
+    fn words(&self) -> [u32; SESSION_WORDS] {
+        [
+            self.session_id,
+            self.component_block_base_address,
+            self.committed_offset,
+            self.applied_relocations,
+            self.validation_checksum,
+            self.new_checksum,
+            self.delta_block_base_address,
+            self.delta_sram_base_address,
+            self.delta_patch_size,
+        ]
+    }
+
+    /// The check word, wrong for an erased or partially written slot
+    fn check(words: &[u32; SESSION_WORDS]) -> u32 {
+        words.iter().fold(SESSION_MAGIC, |check, w| check ^ w)
+    }
+
+    pub fn to_bytes(&self) -> [u8; SESSION_SLOT_SIZE] {
+        let mut bytes: [u8; SESSION_SLOT_SIZE] = [0xFF; SESSION_SLOT_SIZE];
+        let words = self.words();
+        for (i, w) in words.iter().enumerate() {
+            bytes[i * 4..i * 4 + 4].copy_from_slice(&w.to_le_bytes());
+        }
+        bytes[SESSION_WORDS * 4..SESSION_WORDS * 4 + 4]
+            .copy_from_slice(&Self::check(&words).to_le_bytes());
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        if bytes.len() < SESSION_SLOT_SIZE {
+            return None;
+        }
+        let word = |i: usize| {
+            u32::from_le_bytes([bytes[i * 4], bytes[i * 4 + 1], bytes[i * 4 + 2], bytes[i * 4 + 3]])
+        };
+        let record = Self {
+            session_id: word(0),
+            component_block_base_address: word(1),
+            committed_offset: word(2),
+            applied_relocations: word(3),
+            validation_checksum: word(4),
+            new_checksum: word(5),
+            delta_block_base_address: word(6),
+            delta_sram_base_address: word(7),
+            delta_patch_size: word(8),
+        };
+        match Self::check(&record.words()) == word(SESSION_WORDS) {
+            true => Some(record),
+            false => None,
+        }
+    }
+}
+
+/// Scans a session area, returning the last valid record and the number of slots used
+pub fn last_session_record(area: &[u8]) -> (Option<SessionRecord>, usize) {
+    let mut last: Option<SessionRecord> = None;
+    let mut used: usize = 0;
+    for slot in area.chunks_exact(SESSION_SLOT_SIZE).take(SESSION_SLOTS) {
+        if slot.iter().all(|b| *b == 0xFF) {
+            break;
+        }
+        used += 1;
+        if let Some(record) = SessionRecord::from_bytes(slot) {
+            last = Some(record);
+        }
+    }
+    (last, used)
+}
@@ -7,6 +7,7 @@
 
 #![no_std]
 
+pub mod coredump;
 pub mod flash;
 
 use cbf_lite::{BufferReaderImpl, CbfHeaderBase, CbfHeaderInterrupt, CbfHeaderMain};
@@ -24,7 +25,11 @@ pub const HUBRIS_MAX_IRQS: usize = 16;
 
 pub const SUPERVISOR_ID: u16 = 0;
 pub const STORAGE_ID: u16 = 4;
+pub const UPDATE_ID: u16 = 5;
 pub const UPDATE_TEMP_ID: u16 = 1023;
+/// Channel of uart-channel for the deferred logs. The other channels take the ID of
+/// the component using them, so this is the first value that is not a component ID.
+pub const LOG_CHANNEL_ID: u16 = 1024;
 pub const REVERT_UPDATE_TIMEOUT: u64 = 30_000;
 pub const STATE_TRANSFER_REQUESTED_MASK: u32 = 1 << 31;
 
@@ -196,6 +201,26 @@ impl TaskDescriptor {
         let sram_size = unsafe { u32_from_le_bytes_raw(self.block_start_address + 4) };
         sram_base + sram_size - 8 // Keep some margin from the top, to avoid alignment problems
     }
+    /// Returns the (base, size) of the SRAM block assigned to the component
+    pub fn sram_region(&self) -> (u32, u32) {
+        let sram_base = unsafe { u32_from_le_bytes_raw(self.block_start_address) };
+        let sram_size = unsafe { u32_from_le_bytes_raw(self.block_start_address + 4) };
+        (sram_base, sram_size)
+    }
+    /// Returns the address where the read-only payload of the component has been placed,
+    /// i.e. the flash base address used when relocating the component
+    pub fn text_base(&self) -> u32 {
+        let raw_block_bytes = unsafe {
+            core::slice::from_raw_parts(
+                (self.block_start_address + 8) as *const u8,
+                self.block_size as usize,
+            )
+        };
+        let block_reader = BufferReaderImpl::from(raw_block_bytes);
+        let cbf = cbf_lite::CbfFile::from_reader(&block_reader).unwrap_lite();
+        let offset = cbf.get_readonly_payload().unwrap_lite().get_offset();
+        self.block_start_address + 8 + offset
+    }
     pub fn priority(&self) -> u16 {
         self.get_cbf_main().component_priority()
     }
@@ -111,6 +111,11 @@ static CURRENT_TASK_PTR: AtomicPtr<task::Task> =
 #[no_mangle]
 static CLOCK_FREQ_KHZ: AtomicU32 = AtomicU32::new(0);
 
+/// The fault handler clears the CFSR before delivering the fault, so we keep
+/// the last value around for core dumps.
+#[cfg(feature = "coredump")]
+static LAST_CFSR: AtomicU32 = AtomicU32::new(0);
+
 /// ARMvx-M volatile registers that must be saved across context switches.
 #[repr(C)]
 #[derive(Debug, Default)]
@@ -147,6 +152,21 @@ pub struct SavedState {
     // NOTE: the above fields must be kept contiguous!
 }
 
+impl SavedState {
+    /// Returns the registers in the same order as they are stored,
+    /// used when taking a core dump.
+    #[cfg(feature = "coredump")]
+    pub fn dump_words(&self) -> [u32; 26] {
+        [
+            self.r4, self.r5, self.r6, self.r7, self.r8, self.r9, self.r10,
+            self.r11, self.psp, self.exc_return, self.s16, self.s17, self.s18,
+            self.s19, self.s20, self.s21, self.s22, self.s23, self.s24,
+            self.s25, self.s26, self.s27, self.s28, self.s29, self.s30,
+            self.s31,
+        ]
+    }
+}
+
 /// Map the volatile registers to (architecture-independent) syscall argument
 /// and return slots.
 impl task::ArchState for SavedState {
@@ -1151,6 +1171,9 @@ unsafe extern "C" fn handle_fault(
         ),
     };
 
+    #[cfg(feature = "coredump")]
+    LAST_CFSR.store(cfsr.bits(), Ordering::Relaxed);
+
     // Because we are responsible for clearing all conditions, we write back
     // the value of CFSR that we read
     //
@@ -1214,6 +1237,40 @@ unsafe extern "C" fn handle_fault(
     });
 }
 
+/// Returns the task that was running when the kernel was entered, if any.
+///
+/// # Safety
+///
+/// The returned reference aliases the task table: use it only when the
+/// kernel is not going to resume (i.e. during a panic).
+#[cfg(feature = "coredump")]
+pub unsafe fn current_task() -> Option<&'static Task> {
+    let task = CURRENT_TASK_PTR.load(Ordering::Relaxed);
+    unsafe { task.as_ref() }
+}
+
+/// Returns (CFSR, MMFAR, BFAR), to be saved in a core dump
+#[cfg(feature = "coredump")]
+pub fn fault_status() -> (u32, u32, u32) {
+    let scb = unsafe { &*cortex_m::peripheral::SCB::PTR };
+    let cfsr = match scb.cfsr.read() {
+        0 => LAST_CFSR.load(Ordering::Relaxed),
+        cfsr => cfsr,
+    };
+    (cfsr, scb.mmfar.read(), scb.bfar.read())
+}
+
+/// Returns the kernel (main) stack pointer and link register of the caller
+#[cfg(feature = "coredump")]
+#[inline(always)]
+pub fn kernel_context() -> (u32, u32) {
+    let lr: u32;
+    unsafe {
+        asm!("mov {}, lr", out(reg) lr);
+    }
+    (cortex_m::register::msp::read(), lr)
+}
+
 impl AtomicExt for AtomicBool {
     type Primitive = bool;
 
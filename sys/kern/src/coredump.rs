@@ -0,0 +1,317 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Core dump support.
+//!
+//! On kernel panic, or on component fault when enabled in the block
+//! configuration, the kernel saves a compact dump in the COREDUMP flash block
+//! reserved by the system builder. Only the first dump is kept: a new one is
+//! recorded only after the previous has been erased through the update
+//! component.
+
+use core::panic::PanicInfo;
+
+use abi::coredump::*;
+use abi::flash::BlockType;
+use abi::{FaultInfo, TaskState, HUBRIS_MAX_SUPPORTED_TASKS};
+use flash_allocator::flash::header::BlockHeader;
+use flash_allocator::flash::{FlashBlock, FlashMethods, HEADER_SIZE};
+use zerocopy::{AsBytes, FromBytes};
+
+use crate::log::sys_log;
+use crate::structures::TaskIndexes;
+use crate::task::{ArchState, NextTask, Task};
+
+/**
+ * Block handling
+ */
+fn find_coredump_block() -> Option<FlashBlock> {
+    crate::arch::get_flash_walker().find(|b| b.get_type() == BlockType::COREDUMP)
+}
+
+fn read_config(block: &FlashBlock) -> Option<CoreDumpConfig> {
+    let mut buffer: [u8; COREDUMP_CONFIG_SIZE] = [0x00; COREDUMP_CONFIG_SIZE];
+    crate::arch::get_flash_interface()
+        .read(block.get_base_address(), &mut buffer)
+        .ok()?;
+    CoreDumpConfig::read_from(&buffer[..])
+}
+
+/// Address of the first byte of the dump area
+fn dump_area_base(block: &FlashBlock) -> u32 {
+    block.get_base_address() + COREDUMP_CONFIG_SIZE as u32
+}
+
+fn dump_area_size(block: &FlashBlock) -> u32 {
+    block.get_size() - COREDUMP_CONFIG_SIZE as u32
+}
+
+fn is_dump_present(block: &FlashBlock) -> bool {
+    let mut magic: [u8; 4] = [0xFF; 4];
+    if crate::arch::get_flash_interface()
+        .read(dump_area_base(block), &mut magic)
+        .is_err()
+    {
+        // Consider it present, to avoid writing on it
+        return true;
+    }
+    u32::from_le_bytes(magic) != 0xFFFF_FFFF
+}
+
+/// Streams data in the dump area, without ever crossing its end
+struct DumpWriter {
+    address: u32,
+    limit: u32,
+}
+
+impl DumpWriter {
+    fn write(&mut self, data: &[u8]) -> Result<(), ()> {
+        if self.address + data.len() as u32 > self.limit {
+            return Err(());
+        }
+        crate::arch::get_flash_interface().write(self.address, data)?;
+        self.address += data.len() as u32;
+        Ok(())
+    }
+    fn finish(self) -> Result<(), ()> {
+        crate::arch::get_flash_interface().flush_write_buffer()
+    }
+}
+
+/**
+ * Dump generation
+ */
+fn task_entry(task: &Task) -> CoreDumpTaskEntry {
+    let descriptor = task.descriptor();
+    let (sram_base, _) = descriptor.sram_region();
+    let psp = task.save().stack_pointer();
+    CoreDumpTaskEntry {
+        component_id: task.id(),
+        state: match task.state() {
+            TaskState::Healthy(_) => 0,
+            TaskState::Faulted { .. } => 1,
+        },
+        generation: task.generation().into(),
+        component_version: descriptor.component_version(),
+        block_base: descriptor.get_descriptor_block(),
+        text_base: descriptor.text_base(),
+        sram_base: sram_base,
+        psp: psp,
+        pc: read_exception_frame(task).map(|f| f[6]).unwrap_or(0),
+    }
+}
+
+/// Reads the exception frame stacked at psp, if psp is inside the task memory
+fn read_exception_frame(task: &Task) -> Option<[u32; 8]> {
+    let (sram_base, sram_size) = task.descriptor().sram_region();
+    let psp = task.save().stack_pointer();
+    if psp < sram_base || psp + 32 > sram_base + sram_size || psp % 4 != 0 {
+        return None;
+    }
+    let mut frame: [u32; 8] = [0; 8];
+    for i in 0..frame.len() {
+        // Safety: the address was checked to be inside the task SRAM,
+        // and the kernel has full access to it.
+        frame[i] = unsafe { core::ptr::read_volatile((psp + 4 * i as u32) as *const u32) };
+    }
+    Some(frame)
+}
+
+/// Returns the portion of the task stack to save, as (base, size)
+fn stack_window(task: &Task, max_size: u32) -> (u32, u32) {
+    let (sram_base, sram_size) = task.descriptor().sram_region();
+    let psp = task.save().stack_pointer();
+    if psp < sram_base || psp >= sram_base + sram_size {
+        // Stack pointer is not valid (i.e. stack overflow)
+        return (psp, 0);
+    }
+    let available = sram_base + sram_size - psp;
+    (psp, core::cmp::min(available, max_size))
+}
+
+fn write_dump(
+    block: &FlashBlock,
+    config: &CoreDumpConfig,
+    mut header: CoreDumpHeader,
+    task_list: &[Task; HUBRIS_MAX_SUPPORTED_TASKS],
+    task_map: &TaskIndexes,
+    faulting: Option<&Task>,
+) -> Result<(), ()> {
+    let indexes = task_map.valid_indexes();
+    // Fit the stack window in the remaining space
+    let fixed_size =
+        COREDUMP_HEADER_SIZE as u32 + (indexes.len() * COREDUMP_TASK_ENTRY_SIZE) as u32;
+    let space_left = dump_area_size(block).checked_sub(fixed_size).ok_or(())?;
+    let (stack_base, stack_size) = match faulting {
+        Some(task) => stack_window(task, core::cmp::min(config.stack_window, space_left)),
+        None => (0, 0),
+    };
+    header.num_tasks = indexes.len() as u16;
+    header.stack_base = stack_base;
+    header.stack_size = stack_size;
+    header.total_size = fixed_size + stack_size;
+    if let Some(task) = faulting {
+        header.component_id = task.id();
+        header.saved_state = task.save().dump_words();
+        header.exception_frame = read_exception_frame(task).unwrap_or([0; 8]);
+    }
+    let (cfsr, mmfar, bfar) = crate::arch::fault_status();
+    header.cfsr = cfsr;
+    header.mmfar = mmfar;
+    header.bfar = bfar;
+    // Now write everything
+    let mut writer = DumpWriter {
+        address: dump_area_base(block),
+        limit: dump_area_base(block) + dump_area_size(block),
+    };
+    writer.write(header.as_bytes())?;
+    for index in indexes {
+        writer.write(task_entry(&task_list[*index]).as_bytes())?;
+    }
+    if stack_size > 0 {
+        // Safety: the window was checked to be inside the task SRAM
+        let stack = unsafe {
+            core::slice::from_raw_parts(stack_base as *const u8, stack_size as usize)
+        };
+        writer.write(stack)?;
+    }
+    writer.finish()
+}
+
+fn empty_header(reason: CoreDumpReason) -> CoreDumpHeader {
+    CoreDumpHeader {
+        magic: COREDUMP_MAGIC,
+        version: COREDUMP_VERSION,
+        reason: reason as u16,
+        total_size: 0,
+        component_id: COREDUMP_NO_COMPONENT,
+        num_tasks: 0,
+        fault_kind: 0,
+        fault_arg0: 0,
+        fault_arg1: 0,
+        cfsr: 0,
+        mmfar: 0,
+        bfar: 0,
+        saved_state: [0; 26],
+        exception_frame: [0; 8],
+        kernel_sp: 0,
+        kernel_lr: 0,
+        stack_base: 0,
+        stack_size: 0,
+        panic_line: 0,
+        panic_file: [0; COREDUMP_PANIC_FILE_LEN],
+    }
+}
+
+/// Records the fault of a component, if enabled in the dump configuration.
+/// Called by force_fault, before the supervisor gets notified.
+pub fn record_fault(
+    task_list: &[Task; HUBRIS_MAX_SUPPORTED_TASKS],
+    task_map: &TaskIndexes,
+    task_index: usize,
+    fault: &FaultInfo,
+) {
+    let block = match find_coredump_block() {
+        Some(b) => b,
+        None => return,
+    };
+    let config = match read_config(&block) {
+        Some(c) => c,
+        None => return,
+    };
+    if !config.flags().contains(CoreDumpFlags::ON_FAULT) || is_dump_present(&block) {
+        return;
+    }
+    let mut header = empty_header(CoreDumpReason::ComponentFault);
+    (header.fault_kind, header.fault_arg0, header.fault_arg1) = encode_fault(fault);
+    let result = write_dump(
+        &block,
+        &config,
+        header,
+        task_list,
+        task_map,
+        Some(&task_list[task_index]),
+    );
+    if result.is_err() {
+        sys_log!("Cannot write core dump");
+    } else {
+        sys_log!("Core dump saved");
+    }
+}
+
+/// Erases the dump area, keeping the block header and the configuration
+pub fn erase(
+    task_list: &mut [Task; HUBRIS_MAX_SUPPORTED_TASKS],
+    task_map: &mut TaskIndexes,
+) -> Result<NextTask, ()> {
+    let block = find_coredump_block().ok_or(())?;
+    let config = read_config(&block).ok_or(())?;
+    let flash = crate::arch::get_flash_interface();
+    // Save the header of the block
+    let mut header_buffer: [u8; HEADER_SIZE] = [0x00; HEADER_SIZE];
+    flash.read(block.get_nominal_base_address(), &mut header_buffer)?;
+    // Erase all the pages of the block
+    let mut next_task = NextTask::Same;
+    let mut address = block.get_nominal_base_address();
+    let end_address = address + block.get_nominal_size();
+    while address < end_address {
+        let page = flash.page_from_address(address).ok_or(())?;
+        next_task = next_task.combine(flash.erase_timed(task_list, task_map, page.page_number())?);
+        address = page.end_address() + 1;
+    }
+    // Restore header and config
+    let header = BlockHeader::new(&header_buffer, 0);
+    let header_buffer = BlockHeader::write_buffer(
+        true,
+        false,
+        header.is_finalized(),
+        header.block_level(),
+        header.block_type(),
+    );
+    flash.write(block.get_nominal_base_address(), &header_buffer)?;
+    flash.write(block.get_base_address(), config.as_bytes())?;
+    flash.flush_write_buffer()?;
+    Ok(next_task)
+}
+
+/**
+ * Panic handler
+ */
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    sys_log!("{}", info);
+    // Try to take the dump, ignoring any error: we are going to reset anyway
+    if let Some(block) = find_coredump_block() {
+        if let Some(config) = read_config(&block) {
+            if !is_dump_present(&block) {
+                let mut header = empty_header(CoreDumpReason::KernelPanic);
+                (header.kernel_sp, header.kernel_lr) = crate::arch::kernel_context();
+                if let Some(location) = info.location() {
+                    header.panic_line = location.line();
+                    // Keep the last part of the path, the most meaningful
+                    let file = location.file().as_bytes();
+                    let start = file.len().saturating_sub(COREDUMP_PANIC_FILE_LEN);
+                    header.panic_file[..file.len() - start].copy_from_slice(&file[start..]);
+                }
+                // Safety: we are not going back to the kernel, so nobody else
+                // is going to use the task table concurrently.
+                if let Some((task_list, task_map)) =
+                    unsafe { crate::startup::task_table_for_dump() }
+                {
+                    let current = unsafe { crate::arch::current_task() };
+                    write_dump(&block, &config, header, task_list, task_map, current).ok();
+                } else {
+                    header.total_size = COREDUMP_HEADER_SIZE as u32;
+                    let mut writer = DumpWriter {
+                        address: dump_area_base(&block),
+                        limit: dump_area_base(&block) + dump_area_size(&block),
+                    };
+                    writer.write(header.as_bytes()).ok();
+                    writer.finish().ok();
+                }
+            }
+        }
+    }
+    cortex_m::peripheral::SCB::sys_reset();
+}
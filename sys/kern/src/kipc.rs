@@ -53,6 +53,13 @@ pub fn handle_kernel_message(
             caller_index,
             args.message?,
         ),
+        4 => is_task_present(
+            task_list,
+            task_map,
+            caller_id,
+            caller_index,
+            args.message?,
+        ),
         10 => set_update_capability(
             task_list,
             task_map,
@@ -93,6 +100,8 @@ pub fn handle_kernel_message(
             caller_index,
             args.message?,
         ),
+        #[cfg(feature = "coredump")]
+        37 => coredump_erase(task_list, task_map, caller_id, caller_index),
         _ => {
             // Task has sent an unknown message to the kernel. That's bad.
             Err(UserError::Unrecoverable(FaultInfo::SyscallUsage(
@@ -261,6 +270,24 @@ fn fault_task(
     Ok(NextTask::Same)
 }
 
+/// Tells whether a component with the given ID is in the system, so that a
+/// task can avoid sending to an optional component that would fault it.
+fn is_task_present(
+    task_list: &mut [Task; HUBRIS_MAX_SUPPORTED_TASKS],
+    task_map: &mut TaskIndexes,
+    _caller_id: u16,
+    caller_index: usize,
+    message: USlice<u8>,
+) -> Result<NextTask, UserError> {
+    // Read arguments
+    let id: u32 = deserialize_message(&task_list[caller_index], message)?;
+    let present = task_map.get_task_index(id as u16).is_some();
+    task_list[caller_index]
+        .save_mut()
+        .set_send_response_and_length(present as u32, 0);
+    Ok(NextTask::Same)
+}
+
 fn set_update_capability(
     task_list: &mut [Task; HUBRIS_MAX_SUPPORTED_TASKS],
     _task_map: &mut TaskIndexes,
@@ -504,3 +531,32 @@ fn flash_erase(
     // may have halt the CPU for more than expected.
     Ok(next_task)
 }
+
+#[cfg(feature = "coredump")]
+fn coredump_erase(
+    task_list: &mut [Task; HUBRIS_MAX_SUPPORTED_TASKS],
+    task_map: &mut TaskIndexes,
+    caller_id: u16,
+    caller_index: usize,
+) -> Result<NextTask, UserError> {
+    // Only the update component is allowed to discard the core dump
+    if caller_id != abi::UPDATE_ID {
+        return Err(UserError::Unrecoverable(FaultInfo::SyscallUsage(
+            UsageError::IllegalTask,
+        )));
+    }
+    let response_code: u32;
+    let mut next_task = NextTask::Same;
+    match crate::coredump::erase(task_list, task_map) {
+        Ok(switch) => {
+            response_code = 0;
+            next_task = switch;
+        }
+        Err(_) => response_code = 1,
+    }
+    // Write the operation response
+    task_list[caller_index]
+        .save_mut()
+        .set_send_response_and_length(response_code, 0);
+    Ok(next_task)
+}
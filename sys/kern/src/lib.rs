@@ -40,6 +40,8 @@
 pub mod arch;
 
 pub mod atomic;
+#[cfg(feature = "coredump")]
+pub mod coredump;
 pub mod err;
 pub mod kipc;
 pub mod profiling;
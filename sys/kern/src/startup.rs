@@ -37,6 +37,11 @@ static mut TASK_TABLE: MaybeUninit<[Task; HUBRIS_MAX_SUPPORTED_TASKS]> =
 /// Map ID -> task index
 static mut TASK_MAP: MaybeUninit<TaskIndexes> = MaybeUninit::uninit();
 
+/// Set once the task table has been populated, so that the panic handler
+/// knows whether it can be inspected.
+#[cfg(feature = "coredump")]
+static TASK_TABLE_READY: AtomicBool = AtomicBool::new(false);
+
 /// Map ID -> interrupt descriptor (mask & owner)
 pub static mut IRQ_TO_TASK: MaybeUninit<
     KHash<
@@ -102,6 +107,9 @@ pub unsafe fn start_kernel(tick_divisor: u32) -> ! {
         irq_map,
     );
 
+    #[cfg(feature = "coredump")]
+    TASK_TABLE_READY.store(true, Ordering::Release);
+
     // Debug!
     sys_log!("--------- Kernel Start ----------");
     log_structures(task_table, task_map, irq_map);
@@ -169,3 +177,20 @@ pub(crate) fn with_irq_table<R>(
     let r = body(irq_map_ptr);
     r
 }
+
+/// Returns the task table, bypassing the `with_task_table` lock.
+///
+/// # Safety
+///
+/// Only meant for the panic handler, when the kernel is not going to resume:
+/// the table may be in the middle of an update.
+#[cfg(feature = "coredump")]
+pub(crate) unsafe fn task_table_for_dump() -> Option<(
+    &'static [Task; HUBRIS_MAX_SUPPORTED_TASKS],
+    &'static TaskIndexes,
+)> {
+    if !TASK_TABLE_READY.load(Ordering::Acquire) {
+        return None;
+    }
+    unsafe { Some((TASK_TABLE.assume_init_ref(), TASK_MAP.assume_init_ref())) }
+}
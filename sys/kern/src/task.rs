@@ -1063,6 +1063,9 @@ pub fn force_fault(
         fault
     );
     log_task(task);
+    #[cfg(feature = "coredump")]
+    crate::coredump::record_fault(task_list, task_map, task_index, &fault);
+    let task = &mut task_list[task_index];
     task.state = match task.state {
         TaskState::Healthy(sched) => TaskState::Faulted {
             original_state: sched,
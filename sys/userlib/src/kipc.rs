@@ -40,6 +40,13 @@ pub fn fault_task(task_id: u16) {
     }
 }
 
+pub fn is_task_present(task_id: u16) -> bool {
+    // Coerce `task` to a known size (Rust doesn't assume that usize == u32)
+    let task_id = task_id as u32;
+    let (rc, _len) = sys_send(TaskId::KERNEL, 4, task_id.as_bytes(), &mut [], &[]);
+    return rc != 0;
+}
+
 pub fn set_update_support(is_supported: bool) {
     let (rc, _len) = sys_send(TaskId::KERNEL, 10, is_supported.as_bytes(), &mut [], &[]);
     if rc != 0 {
@@ -101,4 +108,12 @@ pub fn flash_erase(page_num: u16) -> Result<(), ()> {
     } else {
         Err(())
     }
-}
\ No newline at end of file
+}
+pub fn erase_coredump() -> Result<(), ()> {
+    let (rc, _len) = sys_send(TaskId::KERNEL, 37, &[], &mut [], &[]);
+    if rc == 0 {
+        Ok(())
+    } else {
+        Err(())
+    }
+}
@@ -46,6 +46,8 @@ use core::arch::asm;
 pub mod hl;
 pub mod kipc;
 pub mod util;
+#[cfg(any(feature = "log-deferred-itm", feature = "log-deferred-channel"))]
+pub mod log;
 
 #[derive(Debug)]
 #[repr(transparent)]
@@ -0,0 +1,205 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Deferred logging backend.
+//!
+//! Format strings are never formatted on the device: `sys_log!` places them in
+//! the `.concept_log` section, that is not loaded in flash, and uses their offset
+//! in the section as index. Only the index and the raw arguments are sent, then the
+//! host rebuilds the message using the string table extracted by the component builder.
+//!
+//! Each message is sent as a frame:
+//!   +--------+-----------+--------------+------------+-----------+-----------------+
+//!   | Marker | Frame len | Component ID | String idx | Num. args | Arguments       |
+//!   +--------+-----------+--------------+------------+-----------+-----------------+
+//!   | 1 byte | 1 byte    | 2 bytes      | 2 bytes    | 1 byte    | tag + value ... |
+//!   +--------+-----------+--------------+------------+-----------+-----------------+
+//! Multi-byte fields are little endian. If not all the arguments fit in the frame,
+//! the MSB of Num. args is set and the remaining ones are dropped.
+
+pub const FRAME_MARKER: u8 = 0xC5;
+pub const FRAME_HEADER_SIZE: usize = 7;
+pub const MAX_FRAME_SIZE: usize = 64;
+pub const TRUNCATED_FLAG: u8 = 0x80;
+
+/// Argument tags
+pub const TAG_U32: u8 = 0;
+pub const TAG_I32: u8 = 1;
+pub const TAG_U64: u8 = 2;
+pub const TAG_I64: u8 = 3;
+pub const TAG_BOOL: u8 = 4;
+pub const TAG_CHAR: u8 = 5;
+pub const TAG_STR: u8 = 6;
+pub const TAG_F32: u8 = 7;
+
+/// ID of the component, set by the component builder at compile time
+pub const COMPONENT_ID: u16 = parse_component_id(option_env!("CONCEPT_OS_COMPONENT_ID"));
+
+const fn parse_component_id(id: Option<&str>) -> u16 {
+    let bytes = match id {
+        Some(id) => id.as_bytes(),
+        None => return 0,
+    };
+    let mut value: u16 = 0;
+    let mut i = 0;
+    while i < bytes.len() {
+        value = value * 10 + (bytes[i] - b'0') as u16;
+        i += 1;
+    }
+    return value;
+}
+
+/**
+ * Format strings helpers, used by sys_log! to place
+ * the NUL terminated string in the log section.
+ */
+pub const fn format_len(format: &str) -> usize {
+    format.len() + 1
+}
+
+pub const fn format_bytes<const N: usize>(format: &str) -> [u8; N] {
+    let bytes = format.as_bytes();
+    let mut result: [u8; N] = [0x00; N];
+    let mut i = 0;
+    while i < bytes.len() {
+        result[i] = bytes[i];
+        i += 1;
+    }
+    return result;
+}
+
+/**
+ * Frame encoder
+ */
+pub struct Encoder {
+    buffer: [u8; MAX_FRAME_SIZE],
+    pos: usize,
+}
+
+impl Encoder {
+    pub fn new(string_index: u16) -> Self {
+        let mut buffer: [u8; MAX_FRAME_SIZE] = [0x00; MAX_FRAME_SIZE];
+        buffer[0] = FRAME_MARKER;
+        buffer[2..4].copy_from_slice(&COMPONENT_ID.to_le_bytes());
+        buffer[4..6].copy_from_slice(&string_index.to_le_bytes());
+        Self {
+            buffer: buffer,
+            pos: FRAME_HEADER_SIZE,
+        }
+    }
+
+    /// Adds an argument, only if it fits as a whole
+    pub fn push(&mut self, tag: u8, value: &[u8]) {
+        if self.buffer[6] & TRUNCATED_FLAG != 0 || self.pos + 1 + value.len() > MAX_FRAME_SIZE {
+            self.buffer[6] |= TRUNCATED_FLAG;
+            return;
+        }
+        self.buffer[self.pos] = tag;
+        self.buffer[self.pos + 1..self.pos + 1 + value.len()].copy_from_slice(value);
+        self.pos += 1 + value.len();
+        self.buffer[6] += 1;
+    }
+
+    /// Strings are length prefixed, and cut to the space left in the frame
+    pub fn push_str(&mut self, value: &str) {
+        if self.buffer[6] & TRUNCATED_FLAG != 0 || self.pos + 2 > MAX_FRAME_SIZE {
+            self.buffer[6] |= TRUNCATED_FLAG;
+            return;
+        }
+        let len = core::cmp::min(value.len(), MAX_FRAME_SIZE - self.pos - 2);
+        self.buffer[self.pos] = TAG_STR;
+        self.buffer[self.pos + 1] = len as u8;
+        self.buffer[self.pos + 2..self.pos + 2 + len].copy_from_slice(&value.as_bytes()[0..len]);
+        self.pos += 2 + len;
+        self.buffer[6] += 1;
+        if len < value.len() {
+            self.buffer[6] |= TRUNCATED_FLAG;
+        }
+    }
+
+    pub fn finish(&mut self) -> &[u8] {
+        self.buffer[1] = self.pos as u8;
+        &self.buffer[0..self.pos]
+    }
+}
+
+/// Values that can be sent as arguments of sys_log!
+pub trait LogArgument {
+    fn encode(&self, encoder: &mut Encoder);
+}
+
+macro_rules! impl_log_argument {
+    ($tag:expr, $dest:ty, $($t:ty),*) => {
+        $(
+            impl LogArgument for $t {
+                fn encode(&self, encoder: &mut Encoder) {
+                    encoder.push($tag, &(*self as $dest).to_le_bytes());
+                }
+            }
+        )*
+    };
+}
+impl_log_argument!(TAG_U32, u32, u8, u16, u32, usize);
+impl_log_argument!(TAG_I32, i32, i8, i16, i32, isize);
+impl_log_argument!(TAG_U64, u64, u64);
+impl_log_argument!(TAG_I64, i64, i64);
+impl_log_argument!(TAG_F32, f32, f32);
+
+impl LogArgument for bool {
+    fn encode(&self, encoder: &mut Encoder) {
+        encoder.push(TAG_BOOL, &[*self as u8]);
+    }
+}
+impl LogArgument for char {
+    fn encode(&self, encoder: &mut Encoder) {
+        encoder.push(TAG_CHAR, &(*self as u32).to_le_bytes());
+    }
+}
+impl LogArgument for str {
+    fn encode(&self, encoder: &mut Encoder) {
+        encoder.push_str(self);
+    }
+}
+impl<T: LogArgument + ?Sized> LogArgument for &T {
+    fn encode(&self, encoder: &mut Encoder) {
+        (**self).encode(encoder);
+    }
+}
+
+/**
+ * Transports
+ */
+cfg_if::cfg_if! {
+    if #[cfg(feature = "log-deferred-itm")] {
+        /// Frames are sent on their own stimulus port, to not mix them with text logs
+        const ITM_STIM_PORT: u32 = 0xE000_0000 + 4 * 1;
+
+        pub fn write_frame(frame: &[u8]) {
+            let stim = ITM_STIM_PORT as *mut u32;
+            for b in frame {
+                unsafe {
+                    // Wait for the FIFO to be ready
+                    while core::ptr::read_volatile(stim) & 0x1 == 0 {}
+                    core::ptr::write_volatile(stim as *mut u8, *b);
+                }
+            }
+        }
+    } else {
+        /// Where frames are sent, i.e. a uart-channel channel
+        static mut LOG_SINK: Option<fn(&[u8])> = None;
+
+        /// Sets the function used to send frames. Until set, frames are dropped.
+        pub fn set_sink(sink: fn(&[u8])) {
+            unsafe {
+                LOG_SINK = Some(sink);
+            }
+        }
+
+        pub fn write_frame(frame: &[u8]) {
+            if let Some(sink) = unsafe { LOG_SINK } {
+                sink(frame);
+            }
+        }
+    }
+}
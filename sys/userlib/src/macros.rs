@@ -33,6 +33,22 @@ cfg_if::cfg_if! {
                 { let _ = cortex_m_semihosting::hprintln!($s, $($tt)*); }
             };
         }
+    } else if #[cfg(any(feature = "log-deferred-itm", feature = "log-deferred-channel"))] {
+        #[macro_export]
+        macro_rules! sys_log {
+            ($s:expr $(, $x:expr)* $(,)?) => {
+                {
+                    // Not loaded on the device, the offset is the index of the string
+                    #[link_section = ".concept_log"]
+                    static FORMAT: [u8; $crate::log::format_len($s)] = $crate::log::format_bytes($s);
+                    let mut encoder = $crate::log::Encoder::new(&FORMAT as *const _ as usize as u16);
+                    $(
+                        $crate::log::LogArgument::encode(&$x, &mut encoder);
+                    )*
+                    $crate::log::write_frame(encoder.finish());
+                }
+            };
+        }
     } else if #[cfg(feature = "log-null")] {
         #[macro_export]
         macro_rules! sys_log {
@@ -55,13 +71,13 @@ cfg_if::cfg_if! {
             ($s:expr) => {
                 compile_error!(concat!(
                         "to use sys_log! must enable either ",
-                        "'log-semihosting' or 'log-itm' feature"
+                        "'log-semihosting', 'log-itm' or a 'log-deferred' feature"
                 ))
             };
             ($s:expr, $($tt:tt)*) => {
                 compile_error!(concat!(
                         "to use sys_log! must enable either ",
-                        "'log-semihosting' or 'log-itm' feature"
+                        "'log-semihosting', 'log-itm' or a 'log-deferred' feature"
                 ))
             };
         }
@@ -4,7 +4,7 @@
 // Originally forked from: https://github.com/oxidecomputer/hubris
 
 use core::cell::UnsafeCell;
-use core::sync::atomic::{AtomicBool, Ordering};
+use core::sync::atomic::{AtomicBool, AtomicU32, Ordering};
 
 
 /// A RefCell-style container that can be used in a static for cases where only
@@ -74,3 +74,17 @@ impl<'a, T> core::ops::DerefMut for StaticRef<'a, T> {
         self.contents
     }
 }
+
+/// Replaces the bits of `mask` in a register shared with other components, as
+/// the CSELR of a DMA. The exclusive load and store (LDREX/STREX) are retried
+/// when a context switch came in between, so the bits written meanwhile by
+/// another component are not lost as with a read-modify-write.
+///
+/// # Safety
+/// `register` must be a 32 bit register in a region of the component.
+pub unsafe fn modify_shared(register: *mut u32, mask: u32, value: u32) {
+    let register = &*(register as *const AtomicU32);
+    let _ = register.fetch_update(Ordering::SeqCst, Ordering::SeqCst, |bits| {
+        Some(bits & !mask | value & mask)
+    });
+}
@@ -2,9 +2,29 @@
 // License, v. 2.0. If a copy of the MPL was not distributed with this
 // file, You can obtain one at https://mozilla.org/MPL/2.0/.
 
-use std::{fs::File, io::Write, path::PathBuf};
+use std::{cmp::Ordering, fs::File, io::Write, path::PathBuf};
 
-use crate::elf_editor::{AllocStatEntry, AllocStats};
+#[derive(Debug, Clone)]
+pub struct AllocStatEntry {
+    pub name: String,
+    pub component_id: u16,
+    pub flash_address: u32,
+    pub flash_size: u32,
+    pub flash_needed_size: u32,
+    pub ram_address: u32,
+    pub ram_size: u32,
+    pub ram_needed_size: u32,
+}
+
+pub struct AllocStats {
+    pub flash_start: u32,
+    pub flash_size: u32,
+    pub ram_start: u32,
+    pub ram_size: u32,
+    pub kernel_reserved_ram: u32,
+    pub kernel_reserved_flash: u32,
+    pub entries: Vec<AllocStatEntry>,
+}
 
 const TOTAL_WIDTH: usize = 1000;
 
@@ -26,7 +46,7 @@ fn visualize_flash(stats: &AllocStats, root: &PathBuf) {
     let file = File::create(flash_report_path).unwrap();
     // First sort stats by address
     let mut ordered_entries = stats.entries.to_vec();
-    ordered_entries.sort_by(|a, b| a.flash_address.cmp(&b.flash_address));
+    ordered_entries.sort_by_key(|e| e.flash_address);
     // Now iterate for each address to find blocks
     let mut curr_address: u32 = stats.flash_start;
     let mut curr_ptr: usize = 0;
@@ -40,27 +60,31 @@ fn visualize_flash(stats: &AllocStats, root: &PathBuf) {
             // Get the current allocation
             let curr_entry = &ordered_entries[curr_ptr];
             // We have three cases
-            if curr_entry.flash_address > curr_address {
-                // This is free flash
-                blocks.push(Block {
-                    //start_addr: curr_address,
-                    size: curr_entry.flash_address - curr_address,
-                    entry: None,
-                });
-                curr_address = curr_entry.flash_address;
-            } else if curr_entry.flash_address == curr_address {
-                // We got to our block, add it then skip it
-                blocks.push(Block {
-                    //start_addr: curr_address,
-                    size: curr_entry.flash_size,
-                    entry: Some(curr_entry.clone()),
-                });
-                used_flash += curr_entry.flash_size;
-                curr_ptr += 1;
-                curr_address = curr_entry.flash_address + curr_entry.flash_size;
-            } else {
-                // We might have problems with entries ordering
-                panic!("Something went wrong");
+            match curr_entry.flash_address.cmp(&curr_address) {
+                Ordering::Greater => {
+                    // This is free flash
+                    blocks.push(Block {
+                        //start_addr: curr_address,
+                        size: curr_entry.flash_address - curr_address,
+                        entry: None,
+                    });
+                    curr_address = curr_entry.flash_address;
+                }
+                Ordering::Equal => {
+                    // We got to our block, add it then skip it
+                    blocks.push(Block {
+                        //start_addr: curr_address,
+                        size: curr_entry.flash_size,
+                        entry: Some(curr_entry.clone()),
+                    });
+                    used_flash += curr_entry.flash_size;
+                    curr_ptr += 1;
+                    curr_address = curr_entry.flash_address + curr_entry.flash_size;
+                }
+                Ordering::Less => {
+                    // We might have problems with entries ordering
+                    panic!("Something went wrong");
+                }
             }
         } else {
             // Otherwise we finished all the allocations: all free space
@@ -87,7 +111,9 @@ fn visualize_ram(stats: &AllocStats, root: &PathBuf) {
     let file = File::create(ram_report_path).unwrap();
     // First sort stats by address
     let mut ordered_entries = stats.entries.to_vec();
-    ordered_entries.sort_by(|a, b| a.ram_address.cmp(&b.ram_address));
+    // Skip blocks that only use flash (i.e. core dump)
+    ordered_entries.retain(|e| e.ram_size > 0);
+    ordered_entries.sort_by_key(|e| e.ram_address);
     // Now iterate for each address to find blocks
     let mut curr_address: u32 = stats.ram_start;
     let mut curr_ptr: usize = 0;
@@ -101,27 +127,31 @@ fn visualize_ram(stats: &AllocStats, root: &PathBuf) {
             // Get the current allocation
             let curr_entry = &ordered_entries[curr_ptr];
             // We have three cases
-            if curr_entry.ram_address > curr_address {
-                // This is free ram
-                blocks.push(Block {
-                    //start_addr: curr_address,
-                    size: curr_entry.ram_address - curr_address,
-                    entry: None,
-                });
-                curr_address = curr_entry.ram_address;
-            } else if curr_entry.ram_address == curr_address {
-                // We got to our block, add it then skip it
-                blocks.push(Block {
-                    //start_addr: curr_address,
-                    size: curr_entry.ram_size,
-                    entry: Some(curr_entry.clone()),
-                });
-                used_ram += curr_entry.ram_size;
-                curr_ptr += 1;
-                curr_address = curr_entry.ram_address + curr_entry.ram_size;
-            } else {
-                // We might have problems with entries ordering
-                panic!("Something went wrong");
+            match curr_entry.ram_address.cmp(&curr_address) {
+                Ordering::Greater => {
+                    // This is free ram
+                    blocks.push(Block {
+                        //start_addr: curr_address,
+                        size: curr_entry.ram_address - curr_address,
+                        entry: None,
+                    });
+                    curr_address = curr_entry.ram_address;
+                }
+                Ordering::Equal => {
+                    // We got to our block, add it then skip it
+                    blocks.push(Block {
+                        //start_addr: curr_address,
+                        size: curr_entry.ram_size,
+                        entry: Some(curr_entry.clone()),
+                    });
+                    used_ram += curr_entry.ram_size;
+                    curr_ptr += 1;
+                    curr_address = curr_entry.ram_address + curr_entry.ram_size;
+                }
+                Ordering::Less => {
+                    // We might have problems with entries ordering
+                    panic!("Something went wrong");
+                }
             }
         } else {
             // Otherwise we finished all the allocations: all free space
@@ -188,11 +218,11 @@ impl StatWriter {
         info_gen: fn(&AllocStatEntry) -> String,
     ) -> Self {
         Self {
-            file: file,
-            width: width,
-            total_size: total_size,
-            used_size: used_size,
-            info_gen: info_gen,
+            file,
+            width,
+            total_size,
+            used_size,
+            info_gen,
             blocks: Vec::new(),
         }
     }
@@ -238,6 +268,6 @@ impl StatWriter {
             }
         }
         // Write output
-        self.file.write(out.as_bytes()).unwrap();
+        self.file.write_all(out.as_bytes()).unwrap();
     }
 }
@@ -4,7 +4,9 @@
 
 use std::error::Error;
 mod structures;
-pub use structures::AppConfig;
+pub use structures::{
+    AppConfig, ClockConfig, ClockSource, ComponentConfig, CoreDumpConfig, KernelClock, PllConfig,
+};
 
 pub fn read_configuration(path: &str) -> Result<AppConfig, Box<dyn Error>> {
     // Read file
@@ -16,7 +18,7 @@ pub fn read_configuration(path: &str) -> Result<AppConfig, Box<dyn Error>> {
 mod tests {
     use std::path::PathBuf;
 
-    use crate::read_configuration;
+    use crate::{read_configuration, ClockSource, KernelClock};
 
     fn get_test_file_path(name: &str) -> String {
         let mut d = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
@@ -32,4 +34,38 @@ mod tests {
         assert_eq!(config.name, "stm32f303re_demo");
         println!("{:?}", config);
     }
+
+    #[test]
+    fn test_clocks() {
+        let test_file_path = get_test_file_path("example2.toml");
+        let config = read_configuration(&test_file_path).unwrap();
+        let clocks = config.clocks.unwrap();
+        assert_eq!(clocks.source, ClockSource::Pll);
+        assert_eq!(clocks.msi, Some(4_000_000));
+        assert_eq!(clocks.hse, None);
+        // Not given, so not divided
+        assert_eq!(clocks.ahb_prescaler, 1);
+        assert_eq!(clocks.apb1_prescaler, 2);
+        let pll = clocks.pll.unwrap();
+        assert_eq!(pll.source, ClockSource::Msi);
+        assert_eq!(pll.multiplier, 40);
+        assert_eq!(clocks.kernel.get("usart2"), Some(&KernelClock::Hsi));
+    }
+
+    #[test]
+    fn test_instances() {
+        let test_file_path = get_test_file_path("example2.toml");
+        let config = read_configuration(&test_file_path).unwrap();
+        let uart = &config.components["uart-channel"];
+        assert_eq!(uart.component, None);
+        assert_eq!(uart.id, None);
+        assert_eq!(uart.config, None);
+        // Second instance of the same component
+        let modem = &config.components["modem-uart"];
+        assert_eq!(modem.component.as_deref(), Some("uart-channel"));
+        assert_eq!(modem.id, Some(16));
+        let settings = modem.config.as_ref().unwrap();
+        assert_eq!(settings["usart"].as_str(), Some("usart1"));
+        assert_eq!(settings["baudrate"].as_integer(), Some(9600));
+    }
 }
\ No newline at end of file
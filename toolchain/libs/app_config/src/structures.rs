@@ -11,7 +11,82 @@ use serde::Deserialize;
  */
 #[derive(Deserialize, PartialEq, Debug)]
 pub struct ComponentConfig {
-    pub features: Vec<String>
+    pub features: Vec<String>,
+    /// Directory in components/, the name of the table if not given. Set it to
+    /// build a component once more, as another instance.
+    pub component: Option<String>,
+    /// Replaces the id of Component.toml, every instance needs its own
+    pub id: Option<u16>,
+    /// Replaces the settings with the same key in the [config] of Component.toml
+    pub config: Option<toml::value::Table>,
+}
+
+#[derive(Deserialize, PartialEq, Debug)]
+pub struct CoreDumpConfig {
+    /// Size of the flash block reserved for the dump
+    pub size: u32,
+    /// Maximum number of bytes of the faulting stack to save
+    pub stack_window: u32,
+    /// Take a dump also when a component faults
+    pub on_fault: bool,
+}
+
+/// Oscillators, the PLL is fed by one of the others
+#[derive(Deserialize, PartialEq, Debug, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+pub enum ClockSource {
+    Hsi,
+    Hse,
+    /// Only on the STM32L4
+    Msi,
+    Pll,
+}
+
+/// Clock of a peripheral that has a selector (USARTs and I2Cs)
+#[derive(Deserialize, PartialEq, Debug, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+pub enum KernelClock {
+    Pclk,
+    Sysclk,
+    Hsi,
+}
+
+#[derive(Deserialize, PartialEq, Debug)]
+pub struct PllConfig {
+    pub source: ClockSource,
+    /// PREDIV on the STM32F3, PLLM on the STM32L4
+    pub divider: u32,
+    /// PLLMUL on the STM32F3, PLLN on the STM32L4
+    pub multiplier: u32,
+    /// PLLR, only on the STM32L4 (2 if not given)
+    pub output_divider: Option<u32>,
+}
+
+fn default_prescaler() -> u32 {
+    1
+}
+
+#[derive(Deserialize, PartialEq, Debug)]
+pub struct ClockConfig {
+    /// Source of SYSCLK
+    pub source: ClockSource,
+    /// Frequency of the external oscillator, if there is one
+    pub hse: Option<u32>,
+    /// The external clock is given on OSC_IN, without a crystal
+    #[serde(default)]
+    pub hse_bypass: bool,
+    /// Frequency of the MSI range, 4 MHz if not given
+    pub msi: Option<u32>,
+    pub pll: Option<PllConfig>,
+    #[serde(default = "default_prescaler")]
+    pub ahb_prescaler: u32,
+    #[serde(default = "default_prescaler")]
+    pub apb1_prescaler: u32,
+    #[serde(default = "default_prescaler")]
+    pub apb2_prescaler: u32,
+    /// Peripheral (as "usart2") to its clock, the others keep the one at reset
+    #[serde(default)]
+    pub kernel: BTreeMap<String, KernelClock>,
 }
 
 #[derive(Deserialize, PartialEq, Debug)]
@@ -21,5 +96,8 @@ pub struct AppConfig {
     pub kernel_ram: u32,
     pub clock_speed: u32,
     pub strip_panics: bool,
-    pub components: BTreeMap<String, ComponentConfig>
+    pub components: BTreeMap<String, ComponentConfig>,
+    pub coredump: Option<CoreDumpConfig>,
+    /// Applied by the rcc component at start, the kernel setup is kept if missing
+    pub clocks: Option<ClockConfig>,
 }
\ No newline at end of file
@@ -0,0 +1,173 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use app_config::KernelClock;
+
+/**
+ * Structures
+ */
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Family {
+    /// RM0316
+    F3,
+    /// RM0394 and RM0351
+    L4,
+}
+
+/// Clock selector of a peripheral, in CFGR3 (STM32F3) or CCIPR (STM32L4)
+pub struct KernelSelector {
+    /// Key in App.toml, the rcc_api::Peripheral in upper case
+    pub name: &'static str,
+    pub shift: u32,
+    pub width: u32,
+    /// PCLK2 instead of PCLK1
+    pub apb2: bool,
+    /// Value of the field for each clock, the first is the one at reset
+    pub clocks: &'static [(KernelClock, u32)],
+}
+
+/// Limits of the datasheet, all the ranges are inclusive
+pub struct Limits {
+    pub family: Family,
+    pub sysclk_max: u32,
+    pub pclk1_max: u32,
+    pub pclk2_max: u32,
+    pub hsi: u32,
+    pub hse: (u32, u32),
+    pub pll_divider: (u32, u32),
+    pub pll_multiplier: (u32, u32),
+    /// After the divider
+    pub pll_input: (u32, u32),
+    /// Before PLLR, only on the STM32L4
+    pub pll_vco: Option<(u32, u32)>,
+    pub pll_output: (u32, u32),
+    /// Highest frequency for each number of wait states, of SYSCLK on the
+    /// STM32F3 and of HCLK on the STM32L4
+    pub flash_latency: &'static [u32],
+    pub kernel: &'static [KernelSelector],
+}
+
+/**
+ * Boards
+ */
+/// Frequencies of the MSI ranges, in the order of MSIRANGE
+pub const MSI_RANGES: [u32; 12] = [
+    100_000, 200_000, 400_000, 800_000, 1_000_000, 2_000_000, 4_000_000, 8_000_000, 16_000_000,
+    24_000_000, 32_000_000, 48_000_000,
+];
+
+// 9.4.13 Clock configuration register 3 (RCC_CFGR3), LSE is not offered
+const F3_USART: &[(KernelClock, u32)] = &[
+    (KernelClock::Pclk, 0b00),
+    (KernelClock::Sysclk, 0b01),
+    (KernelClock::Hsi, 0b11),
+];
+const F3_I2C: &[(KernelClock, u32)] = &[(KernelClock::Hsi, 0), (KernelClock::Sysclk, 1)];
+
+// 6.4.28 Peripherals independent clock configuration register (RCC_CCIPR)
+const L4_SELECTOR: &[(KernelClock, u32)] = &[
+    (KernelClock::Pclk, 0b00),
+    (KernelClock::Sysclk, 0b01),
+    (KernelClock::Hsi, 0b10),
+];
+
+const fn selector(
+    name: &'static str,
+    shift: u32,
+    width: u32,
+    apb2: bool,
+    clocks: &'static [(KernelClock, u32)],
+) -> KernelSelector {
+    KernelSelector {
+        name,
+        shift,
+        width,
+        apb2,
+        clocks,
+    }
+}
+
+static STM32F303RE: Limits = Limits {
+    family: Family::F3,
+    sysclk_max: 72_000_000,
+    pclk1_max: 36_000_000,
+    pclk2_max: 72_000_000,
+    hsi: 8_000_000,
+    hse: (4_000_000, 32_000_000),
+    pll_divider: (1, 16),
+    pll_multiplier: (2, 16),
+    pll_input: (1_000_000, 24_000_000),
+    pll_vco: None,
+    pll_output: (16_000_000, 72_000_000),
+    flash_latency: &[24_000_000, 48_000_000, 72_000_000],
+    kernel: &[
+        selector("usart1", 0, 2, true, F3_USART),
+        selector("usart2", 16, 2, false, F3_USART),
+        selector("usart3", 18, 2, false, F3_USART),
+        selector("uart4", 20, 2, false, F3_USART),
+        selector("uart5", 22, 2, false, F3_USART),
+        selector("i2c1", 4, 1, false, F3_I2C),
+        selector("i2c2", 5, 1, false, F3_I2C),
+        selector("i2c3", 6, 1, false, F3_I2C),
+    ],
+};
+
+const L4_FLASH_LATENCY: &[u32] = &[16_000_000, 32_000_000, 48_000_000, 64_000_000, 80_000_000];
+
+static STM32L432KC: Limits = Limits {
+    family: Family::L4,
+    sysclk_max: 80_000_000,
+    pclk1_max: 80_000_000,
+    pclk2_max: 80_000_000,
+    hsi: 16_000_000,
+    hse: (4_000_000, 48_000_000),
+    pll_divider: (1, 8),
+    pll_multiplier: (8, 86),
+    pll_input: (4_000_000, 16_000_000),
+    pll_vco: Some((64_000_000, 344_000_000)),
+    pll_output: (8_000_000, 80_000_000),
+    flash_latency: L4_FLASH_LATENCY,
+    kernel: &[
+        selector("usart1", 0, 2, true, L4_SELECTOR),
+        selector("usart2", 2, 2, false, L4_SELECTOR),
+        selector("lpuart1", 10, 2, false, L4_SELECTOR),
+        selector("i2c1", 12, 2, false, L4_SELECTOR),
+        selector("i2c3", 16, 2, false, L4_SELECTOR),
+    ],
+};
+
+static STM32L476RG: Limits = Limits {
+    family: Family::L4,
+    sysclk_max: 80_000_000,
+    pclk1_max: 80_000_000,
+    pclk2_max: 80_000_000,
+    hsi: 16_000_000,
+    hse: (4_000_000, 48_000_000),
+    pll_divider: (1, 8),
+    pll_multiplier: (8, 86),
+    pll_input: (4_000_000, 16_000_000),
+    pll_vco: Some((64_000_000, 344_000_000)),
+    pll_output: (8_000_000, 80_000_000),
+    flash_latency: L4_FLASH_LATENCY,
+    kernel: &[
+        selector("usart1", 0, 2, true, L4_SELECTOR),
+        selector("usart2", 2, 2, false, L4_SELECTOR),
+        selector("usart3", 4, 2, false, L4_SELECTOR),
+        selector("uart4", 6, 2, false, L4_SELECTOR),
+        selector("uart5", 8, 2, false, L4_SELECTOR),
+        selector("lpuart1", 10, 2, false, L4_SELECTOR),
+        selector("i2c1", 12, 2, false, L4_SELECTOR),
+        selector("i2c2", 14, 2, false, L4_SELECTOR),
+        selector("i2c3", 16, 2, false, L4_SELECTOR),
+    ],
+};
+
+pub fn limits(board: &str) -> Option<&'static Limits> {
+    match board {
+        "stm32f303re" => Some(&STM32F303RE),
+        "stm32l432kc" => Some(&STM32L432KC),
+        "stm32l476rg" => Some(&STM32L476RG),
+        _ => None,
+    }
+}
@@ -0,0 +1,625 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+mod boards;
+mod source;
+
+pub use boards::{limits, Family, Limits};
+pub use source::generate;
+
+use app_config::{ClockConfig, ClockSource, KernelClock, PllConfig};
+use std::collections::BTreeMap;
+use std::env;
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::path::PathBuf;
+
+use boards::MSI_RANGES;
+
+/// Path of the tree generated by the system_builder, read by the rcc build script
+pub const SOURCE_ENV: &str = "CONCEPT_OS_CLOCKS";
+
+/*
+    Errors
+*/
+#[derive(Debug, PartialEq)]
+pub enum ClockError {
+    UnknownBoard(String),
+    /// Not available on the board
+    Unsupported(&'static str),
+    MissingHse,
+    MissingPll,
+    BadPllSource(ClockSource),
+    OutOfRange {
+        what: &'static str,
+        value: u32,
+        min: u32,
+        max: u32,
+    },
+    BadPrescaler {
+        bus: &'static str,
+        value: u32,
+    },
+    BadOutputDivider(u32),
+    BadMsiRange(u32),
+    UnknownPeripheral(String),
+    BadKernelClock {
+        peripheral: String,
+        clock: KernelClock,
+    },
+    ClockSpeedMismatch {
+        hclk: u32,
+        clock_speed: u32,
+    },
+}
+
+impl fmt::Display for ClockError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ClockError::UnknownBoard(board) => write!(f, "no clock limits for board '{}'", board),
+            ClockError::Unsupported(what) => write!(f, "'{}' is not available on this board", what),
+            ClockError::MissingHse => write!(f, "the HSE is used but its frequency is not given"),
+            ClockError::MissingPll => write!(f, "SYSCLK is the PLL but [clocks.pll] is missing"),
+            ClockError::BadPllSource(source) => write!(f, "the PLL cannot be fed by {:?}", source),
+            ClockError::OutOfRange {
+                what,
+                value,
+                min,
+                max,
+            } => write!(
+                f,
+                "{} is {}, it must be between {} and {}",
+                what, value, min, max
+            ),
+            ClockError::BadPrescaler { bus, value } => {
+                write!(f, "{} is not a valid prescaler of {}", value, bus)
+            }
+            ClockError::BadOutputDivider(value) => {
+                write!(f, "{} is not a valid output divider of the PLL", value)
+            }
+            ClockError::BadMsiRange(value) => write!(f, "{} Hz is not a range of the MSI", value),
+            ClockError::UnknownPeripheral(name) => {
+                write!(f, "'{}' has no clock selector on this board", name)
+            }
+            ClockError::BadKernelClock { peripheral, clock } => {
+                write!(f, "'{}' cannot be clocked by {:?}", peripheral, clock)
+            }
+            ClockError::ClockSpeedMismatch { hclk, clock_speed } => write!(
+                f,
+                "HCLK is {} but clock_speed (used for the SysTick) is {}",
+                hclk, clock_speed
+            ),
+        }
+    }
+}
+
+impl Error for ClockError {}
+
+/*
+    Structures
+*/
+// Fields of CR and CFGR, the same on both the families
+const CR_HSEON: u32 = 1 << 16;
+const CR_HSEBYP: u32 = 1 << 18;
+const CR_MSIRGSEL: u32 = 1 << 3;
+const CR_MSIRANGE_SHIFT: u32 = 4;
+const CFGR_HPRE_SHIFT: u32 = 4;
+const CFGR_PPRE1_SHIFT: u32 = 8;
+const CFGR_PPRE2_SHIFT: u32 = 11;
+// PLL of the STM32F3, in CFGR
+const F3_PLLSRC_SHIFT: u32 = 15;
+const F3_PLLMUL_SHIFT: u32 = 18;
+// PLLCFGR of the STM32L4
+const L4_PLLM_SHIFT: u32 = 4;
+const L4_PLLN_SHIFT: u32 = 8;
+const L4_PLLREN: u32 = 1 << 24;
+const L4_PLLR_SHIFT: u32 = 25;
+
+/// Values written by the rcc component, the masks select the bits it owns
+#[derive(Debug, Default, PartialEq)]
+pub struct Registers {
+    /// Oscillators in CR (and the MSI range on the STM32L4)
+    pub cr: u32,
+    pub cr_mask: u32,
+    /// CFGR2 on the STM32F3, PLLCFGR on the STM32L4
+    pub pll: u32,
+    /// Prescalers in CFGR (and the PLL on the STM32F3)
+    pub cfgr: u32,
+    pub cfgr_mask: u32,
+    /// SW field of CFGR
+    pub sw: u32,
+    pub pll_on: bool,
+    /// CFGR3 on the STM32F3, CCIPR on the STM32L4
+    pub kernel: u32,
+    pub kernel_mask: u32,
+    /// Wait states of the flash
+    pub latency: u32,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct ClockTree {
+    pub family: Family,
+    pub sysclk: u32,
+    pub hclk: u32,
+    pub pclk1: u32,
+    pub pclk2: u32,
+    /// Twice PCLK when the bus is divided
+    pub apb1_timers: u32,
+    pub apb2_timers: u32,
+    /// Every peripheral with a clock selector on the board (as the variant of
+    /// rcc_api::Peripheral), with its frequency
+    pub kernel: Vec<(String, u32)>,
+    pub registers: Registers,
+}
+
+/*
+    Methods
+*/
+fn check_range(what: &'static str, value: u32, range: (u32, u32)) -> Result<(), ClockError> {
+    if value < range.0 || value > range.1 {
+        return Err(ClockError::OutOfRange {
+            what,
+            value,
+            min: range.0,
+            max: range.1,
+        });
+    }
+    Ok(())
+}
+
+/// HPRE field of the AHB prescaler
+fn ahb_bits(prescaler: u32) -> Option<u32> {
+    match prescaler {
+        1 => Some(0b0000),
+        2 => Some(0b1000),
+        4 => Some(0b1001),
+        8 => Some(0b1010),
+        16 => Some(0b1011),
+        64 => Some(0b1100),
+        128 => Some(0b1101),
+        256 => Some(0b1110),
+        512 => Some(0b1111),
+        _ => None,
+    }
+}
+
+/// PPREx field of an APB prescaler
+fn apb_bits(prescaler: u32) -> Option<u32> {
+    match prescaler {
+        1 => Some(0b000),
+        2 => Some(0b100),
+        4 => Some(0b101),
+        8 => Some(0b110),
+        16 => Some(0b111),
+        _ => None,
+    }
+}
+
+/// Output of the PLL, its fields are added to the registers
+fn resolve_pll(
+    limits: &Limits,
+    pll: &PllConfig,
+    input: u32,
+    registers: &mut Registers,
+) -> Result<u32, ClockError> {
+    check_range("pll.divider", pll.divider, limits.pll_divider)?;
+    check_range("pll.multiplier", pll.multiplier, limits.pll_multiplier)?;
+    let input = input / pll.divider;
+    check_range("the input of the PLL", input, limits.pll_input)?;
+    let output = match limits.family {
+        Family::F3 => {
+            if pll.output_divider.is_some() {
+                return Err(ClockError::Unsupported("pll.output_divider"));
+            }
+            // HSI/PREDIV or HSE/PREDIV
+            let source = match pll.source {
+                ClockSource::Hsi => 0b01,
+                ClockSource::Hse => 0b10,
+                other => return Err(ClockError::BadPllSource(other)),
+            };
+            registers.pll = pll.divider - 1;
+            registers.cfgr |= source << F3_PLLSRC_SHIFT | (pll.multiplier - 2) << F3_PLLMUL_SHIFT;
+            registers.cfgr_mask |= 0b11 << F3_PLLSRC_SHIFT | 0b1111 << F3_PLLMUL_SHIFT;
+            input * pll.multiplier
+        }
+        Family::L4 => {
+            let source = match pll.source {
+                ClockSource::Msi => 0b01,
+                ClockSource::Hsi => 0b10,
+                ClockSource::Hse => 0b11,
+                other => return Err(ClockError::BadPllSource(other)),
+            };
+            let r = pll.output_divider.unwrap_or(2);
+            if ![2, 4, 6, 8].contains(&r) {
+                return Err(ClockError::BadOutputDivider(r));
+            }
+            let vco = input * pll.multiplier;
+            if let Some(range) = limits.pll_vco {
+                check_range("the VCO of the PLL", vco, range)?;
+            }
+            registers.pll = source
+                | (pll.divider - 1) << L4_PLLM_SHIFT
+                | pll.multiplier << L4_PLLN_SHIFT
+                | L4_PLLREN
+                | (r / 2 - 1) << L4_PLLR_SHIFT;
+            vco / r
+        }
+    };
+    check_range("the output of the PLL", output, limits.pll_output)?;
+    Ok(output)
+}
+
+/// Computes the frequencies and the register values of the tree, checking it
+/// against the limits of the board
+pub fn resolve(board: &str, config: &ClockConfig) -> Result<ClockTree, ClockError> {
+    let limits = limits(board).ok_or(ClockError::UnknownBoard(board.to_string()))?;
+    let mut registers = Registers::default();
+    // Oscillators, the HSI is always on
+    if let Some(hse) = config.hse {
+        check_range("hse", hse, limits.hse)?;
+        registers.cr |= CR_HSEON;
+        if config.hse_bypass {
+            registers.cr |= CR_HSEBYP;
+        }
+        registers.cr_mask |= CR_HSEON | CR_HSEBYP;
+    }
+    let msi = match limits.family {
+        Family::F3 if config.msi.is_some() => return Err(ClockError::Unsupported("msi")),
+        Family::F3 => None,
+        Family::L4 => {
+            let msi = config.msi.unwrap_or(4_000_000);
+            let range = MSI_RANGES
+                .iter()
+                .position(|r| *r == msi)
+                .ok_or(ClockError::BadMsiRange(msi))?;
+            registers.cr |= (range as u32) << CR_MSIRANGE_SHIFT | CR_MSIRGSEL;
+            registers.cr_mask |= 0b1111 << CR_MSIRANGE_SHIFT | CR_MSIRGSEL;
+            Some(msi)
+        }
+    };
+    let oscillator = |source: ClockSource| -> Result<u32, ClockError> {
+        match source {
+            ClockSource::Hsi => Ok(limits.hsi),
+            ClockSource::Hse => config.hse.ok_or(ClockError::MissingHse),
+            ClockSource::Msi => msi.ok_or(ClockError::Unsupported("msi")),
+            ClockSource::Pll => Err(ClockError::BadPllSource(source)),
+        }
+    };
+    // SYSCLK
+    let sysclk = match config.source {
+        ClockSource::Pll => {
+            let pll = config.pll.as_ref().ok_or(ClockError::MissingPll)?;
+            registers.pll_on = true;
+            resolve_pll(limits, pll, oscillator(pll.source)?, &mut registers)?
+        }
+        source => oscillator(source)?,
+    };
+    check_range("sysclk", sysclk, (1, limits.sysclk_max))?;
+    registers.sw = match (limits.family, config.source) {
+        (Family::F3, ClockSource::Hsi) => 0b00,
+        (Family::F3, ClockSource::Hse) => 0b01,
+        (Family::F3, _) => 0b10,
+        (Family::L4, ClockSource::Msi) => 0b00,
+        (Family::L4, ClockSource::Hsi) => 0b01,
+        (Family::L4, ClockSource::Hse) => 0b10,
+        (Family::L4, ClockSource::Pll) => 0b11,
+    };
+    // Buses
+    let prescaler = |bus: &'static str, value: u32, bits: fn(u32) -> Option<u32>| {
+        bits(value).ok_or(ClockError::BadPrescaler { bus, value })
+    };
+    let hpre = prescaler("ahb", config.ahb_prescaler, ahb_bits)?;
+    let ppre1 = prescaler("apb1", config.apb1_prescaler, apb_bits)?;
+    let ppre2 = prescaler("apb2", config.apb2_prescaler, apb_bits)?;
+    let hclk = sysclk / config.ahb_prescaler;
+    let pclk1 = hclk / config.apb1_prescaler;
+    let pclk2 = hclk / config.apb2_prescaler;
+    check_range("pclk1", pclk1, (1, limits.pclk1_max))?;
+    check_range("pclk2", pclk2, (1, limits.pclk2_max))?;
+    registers.cfgr |=
+        hpre << CFGR_HPRE_SHIFT | ppre1 << CFGR_PPRE1_SHIFT | ppre2 << CFGR_PPRE2_SHIFT;
+    registers.cfgr_mask |=
+        0b1111 << CFGR_HPRE_SHIFT | 0b111 << CFGR_PPRE1_SHIFT | 0b111 << CFGR_PPRE2_SHIFT;
+    // Flash, SYSCLK is within the last step
+    let reference = match limits.family {
+        Family::F3 => sysclk,
+        Family::L4 => hclk,
+    };
+    registers.latency = limits
+        .flash_latency
+        .iter()
+        .position(|max| reference <= *max)
+        .unwrap() as u32;
+    // Peripherals
+    for (name, clock) in &config.kernel {
+        let selector = limits
+            .kernel
+            .iter()
+            .find(|s| s.name == name)
+            .ok_or(ClockError::UnknownPeripheral(name.clone()))?;
+        let (_, value) =
+            selector
+                .clocks
+                .iter()
+                .find(|(c, _)| c == clock)
+                .ok_or(ClockError::BadKernelClock {
+                    peripheral: name.clone(),
+                    clock: *clock,
+                })?;
+        registers.kernel |= value << selector.shift;
+        registers.kernel_mask |= ((1 << selector.width) - 1) << selector.shift;
+    }
+    let kernel = limits
+        .kernel
+        .iter()
+        .map(|selector| {
+            let clock = match config.kernel.get(selector.name) {
+                Some(clock) => *clock,
+                None => selector.clocks[0].0,
+            };
+            let frequency = match clock {
+                KernelClock::Pclk if selector.apb2 => pclk2,
+                KernelClock::Pclk => pclk1,
+                KernelClock::Sysclk => sysclk,
+                KernelClock::Hsi => limits.hsi,
+            };
+            (selector.name.to_uppercase(), frequency)
+        })
+        .collect();
+    Ok(ClockTree {
+        family: limits.family,
+        sysclk,
+        hclk,
+        pclk1,
+        pclk2,
+        apb1_timers: if config.apb1_prescaler == 1 {
+            pclk1
+        } else {
+            2 * pclk1
+        },
+        apb2_timers: if config.apb2_prescaler == 1 {
+            pclk2
+        } else {
+            2 * pclk2
+        },
+        kernel,
+        registers,
+    })
+}
+
+/// The kernel counts the ticks in HCLK cycles
+pub fn check_clock_speed(tree: &ClockTree, clock_speed: u32) -> Result<(), ClockError> {
+    if tree.hclk != clock_speed {
+        return Err(ClockError::ClockSpeedMismatch {
+            hclk: tree.hclk,
+            clock_speed,
+        });
+    }
+    Ok(())
+}
+
+/// The tree set up by the kernel of the demo apps
+pub fn default_config(board: &str) -> Result<ClockConfig, ClockError> {
+    let limits = limits(board).ok_or(ClockError::UnknownBoard(board.to_string()))?;
+    Ok(match limits.family {
+        // HSI / 1 * 9, APB1 at its maximum
+        Family::F3 => ClockConfig {
+            source: ClockSource::Pll,
+            hse: None,
+            hse_bypass: false,
+            msi: None,
+            pll: Some(PllConfig {
+                source: ClockSource::Hsi,
+                divider: 1,
+                multiplier: 9,
+                output_divider: None,
+            }),
+            ahb_prescaler: 1,
+            apb1_prescaler: 2,
+            apb2_prescaler: 1,
+            kernel: BTreeMap::new(),
+        },
+        // MSI / 1 * 40 / 2
+        Family::L4 => ClockConfig {
+            source: ClockSource::Pll,
+            hse: None,
+            hse_bypass: false,
+            msi: Some(4_000_000),
+            pll: Some(PllConfig {
+                source: ClockSource::Msi,
+                divider: 1,
+                multiplier: 40,
+                output_divider: Some(2),
+            }),
+            ahb_prescaler: 1,
+            apb1_prescaler: 1,
+            apb2_prescaler: 1,
+            kernel: BTreeMap::new(),
+        },
+    })
+}
+
+/// To be called from the build script of the rcc component. The tree is the one
+/// written by the system_builder or, when the component is built on its own,
+/// the one of the kernel (that is then not applied again).
+pub fn build_source(output_name: &str) -> Result<(), Box<dyn Error>> {
+    println!("cargo:rerun-if-env-changed={}", SOURCE_ENV);
+    let content = match env::var(SOURCE_ENV) {
+        Ok(path) => {
+            println!("cargo:rerun-if-changed={}", path);
+            fs::read_to_string(path)?
+        }
+        Err(_) => {
+            // Enabled by the component_builder as board_<name>
+            let board = env::vars()
+                .find_map(|(key, _)| {
+                    key.strip_prefix("CARGO_FEATURE_BOARD_")
+                        .map(|b| b.to_lowercase())
+                })
+                .ok_or("no board feature enabled")?;
+            let tree = resolve(&board, &default_config(&board)?)?;
+            generate(&tree, false)
+        }
+    };
+    let mut output = PathBuf::from(env::var("OUT_DIR")?);
+    output.push(output_name);
+    fs::write(output, content)?;
+    Ok(())
+}
+
+/*
+    Tests
+*/
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn config(board: &str) -> ClockConfig {
+        default_config(board).unwrap()
+    }
+
+    #[test]
+    fn test_defaults() {
+        let tree = resolve("stm32f303re", &config("stm32f303re")).unwrap();
+        assert_eq!(tree.sysclk, 72_000_000);
+        assert_eq!(tree.pclk1, 36_000_000);
+        assert_eq!(tree.apb1_timers, 72_000_000);
+        assert_eq!(tree.pclk2, 72_000_000);
+        // USART2 on PCLK1, I2C1 on HSI at reset
+        assert!(tree.kernel.contains(&("USART2".to_string(), 36_000_000)));
+        assert!(tree.kernel.contains(&("I2C1".to_string(), 8_000_000)));
+        assert_eq!(tree.registers.pll, 0);
+        assert_eq!(tree.registers.cfgr, 0b01 << 15 | 7 << 18 | 0b100 << 8);
+        assert_eq!(tree.registers.sw, 0b10);
+        assert_eq!(tree.registers.latency, 2);
+        assert_eq!(tree.registers.kernel_mask, 0);
+        for board in ["stm32l432kc", "stm32l476rg"] {
+            let tree = resolve(board, &config(board)).unwrap();
+            assert_eq!(tree.hclk, 80_000_000);
+            assert_eq!(tree.apb1_timers, 80_000_000);
+            assert_eq!(tree.registers.pll, 0b01 | 40 << 8 | 1 << 24);
+            assert_eq!(tree.registers.cr, 6 << 4 | 1 << 3);
+            assert_eq!(tree.registers.latency, 4);
+            assert!(check_clock_speed(&tree, 80_000_000).is_ok());
+        }
+    }
+
+    #[test]
+    fn test_custom() {
+        // HSE at 8 MHz bypassed, 48 MHz with the buses halved
+        let mut config = config("stm32l476rg");
+        config.hse = Some(8_000_000);
+        config.hse_bypass = true;
+        config.pll = Some(PllConfig {
+            source: ClockSource::Hse,
+            divider: 1,
+            multiplier: 24,
+            output_divider: Some(4),
+        });
+        config.apb1_prescaler = 2;
+        config.kernel.insert("usart2".to_string(), KernelClock::Hsi);
+        config
+            .kernel
+            .insert("i2c1".to_string(), KernelClock::Sysclk);
+        let tree = resolve("stm32l476rg", &config).unwrap();
+        assert_eq!(tree.sysclk, 48_000_000);
+        assert_eq!(tree.pclk1, 24_000_000);
+        assert_eq!(tree.apb1_timers, 48_000_000);
+        assert_eq!(tree.registers.latency, 2);
+        assert_eq!(tree.registers.cr & (1 << 16 | 1 << 18), 1 << 16 | 1 << 18);
+        assert_eq!(tree.registers.pll, 0b11 | 24 << 8 | 1 << 24 | 1 << 25);
+        assert_eq!(tree.registers.kernel, 0b10 << 2 | 0b01 << 12);
+        assert_eq!(tree.registers.kernel_mask, 0b11 << 2 | 0b11 << 12);
+        assert!(tree.kernel.contains(&("USART2".to_string(), 16_000_000)));
+        assert!(tree.kernel.contains(&("USART1".to_string(), 48_000_000)));
+        assert_eq!(
+            check_clock_speed(&tree, 80_000_000),
+            Err(ClockError::ClockSpeedMismatch {
+                hclk: 48_000_000,
+                clock_speed: 80_000_000
+            })
+        );
+    }
+
+    #[test]
+    fn test_limits() {
+        // PCLK1 of the F3 is 36 MHz at most
+        let mut f3 = config("stm32f303re");
+        f3.apb1_prescaler = 1;
+        assert!(matches!(
+            resolve("stm32f303re", &f3),
+            Err(ClockError::OutOfRange { what: "pclk1", .. })
+        ));
+        f3.apb1_prescaler = 3;
+        assert_eq!(
+            resolve("stm32f303re", &f3),
+            Err(ClockError::BadPrescaler {
+                bus: "apb1",
+                value: 3
+            })
+        );
+        // 8 MHz * 16
+        f3.apb1_prescaler = 2;
+        f3.pll.as_mut().unwrap().multiplier = 16;
+        assert!(matches!(
+            resolve("stm32f303re", &f3),
+            Err(ClockError::OutOfRange {
+                what: "the output of the PLL",
+                ..
+            })
+        ));
+        f3.msi = Some(4_000_000);
+        assert_eq!(
+            resolve("stm32f303re", &f3),
+            Err(ClockError::Unsupported("msi"))
+        );
+        // The PLL input of the L4 is 4 MHz at least
+        let mut l4 = config("stm32l432kc");
+        l4.pll.as_mut().unwrap().divider = 2;
+        assert!(matches!(
+            resolve("stm32l432kc", &l4),
+            Err(ClockError::OutOfRange {
+                what: "the input of the PLL",
+                ..
+            })
+        ));
+        l4.pll.as_mut().unwrap().divider = 1;
+        l4.msi = Some(3_000_000);
+        assert_eq!(
+            resolve("stm32l432kc", &l4),
+            Err(ClockError::BadMsiRange(3_000_000))
+        );
+        l4.msi = None;
+        l4.source = ClockSource::Hse;
+        assert_eq!(resolve("stm32l432kc", &l4), Err(ClockError::MissingHse));
+    }
+
+    #[test]
+    fn test_kernel_clocks() {
+        // No USART3 on the L432KC
+        let mut l4 = config("stm32l432kc");
+        l4.kernel.insert("usart3".to_string(), KernelClock::Pclk);
+        assert_eq!(
+            resolve("stm32l432kc", &l4),
+            Err(ClockError::UnknownPeripheral("usart3".to_string()))
+        );
+        // The I2C of the F3 are on HSI or SYSCLK only
+        let mut f3 = config("stm32f303re");
+        f3.kernel.insert("i2c1".to_string(), KernelClock::Pclk);
+        assert_eq!(
+            resolve("stm32f303re", &f3),
+            Err(ClockError::BadKernelClock {
+                peripheral: "i2c1".to_string(),
+                clock: KernelClock::Pclk
+            })
+        );
+        f3.kernel.insert("i2c1".to_string(), KernelClock::Sysclk);
+        f3.kernel.insert("usart2".to_string(), KernelClock::Hsi);
+        let tree = resolve("stm32f303re", &f3).unwrap();
+        assert_eq!(tree.registers.kernel, 1 << 4 | 0b11 << 16);
+        assert!(tree.kernel.contains(&("I2C1".to_string(), 72_000_000)));
+        assert!(tree.kernel.contains(&("USART2".to_string(), 8_000_000)));
+    }
+}
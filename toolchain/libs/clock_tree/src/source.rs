@@ -0,0 +1,79 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use std::fmt::Write;
+
+use crate::ClockTree;
+
+/// Source included by the rcc component, `Setup` and `Peripheral` must be in
+/// scope where it is included. The registers are written only when `apply` is
+/// set, the frequencies are always given.
+pub fn generate(tree: &ClockTree, apply: bool) -> String {
+    let mut out = String::new();
+    let r = &tree.registers;
+    writeln!(out, "// Generated by the clock_tree library, do not edit").unwrap();
+    writeln!(out).unwrap();
+    writeln!(
+        out,
+        "/// Tree of App.toml, None to keep the one of the kernel"
+    )
+    .unwrap();
+    if apply {
+        writeln!(out, "pub const SETUP: Option<Setup> = Some(Setup {{").unwrap();
+        writeln!(out, "    cr: {:#010x},", r.cr).unwrap();
+        writeln!(out, "    cr_mask: {:#010x},", r.cr_mask).unwrap();
+        writeln!(out, "    pll: {:#010x},", r.pll).unwrap();
+        writeln!(out, "    cfgr: {:#010x},", r.cfgr).unwrap();
+        writeln!(out, "    cfgr_mask: {:#010x},", r.cfgr_mask).unwrap();
+        writeln!(out, "    sw: {:#x},", r.sw).unwrap();
+        writeln!(out, "    pll_on: {},", r.pll_on).unwrap();
+        writeln!(out, "    kernel: {:#010x},", r.kernel).unwrap();
+        writeln!(out, "    kernel_mask: {:#010x},", r.kernel_mask).unwrap();
+        writeln!(out, "    latency: {},", r.latency).unwrap();
+        writeln!(out, "}});").unwrap();
+    } else {
+        writeln!(out, "pub const SETUP: Option<Setup> = None;").unwrap();
+    }
+    writeln!(out).unwrap();
+    writeln!(out, "pub const SYSCLK_HZ: u32 = {};", tree.sysclk).unwrap();
+    writeln!(out, "pub const HCLK_HZ: u32 = {};", tree.hclk).unwrap();
+    writeln!(out, "pub const PCLK1_HZ: u32 = {};", tree.pclk1).unwrap();
+    writeln!(out, "pub const PCLK2_HZ: u32 = {};", tree.pclk2).unwrap();
+    writeln!(out, "pub const APB1_TIMERS_HZ: u32 = {};", tree.apb1_timers).unwrap();
+    writeln!(out, "pub const APB2_TIMERS_HZ: u32 = {};", tree.apb2_timers).unwrap();
+    writeln!(out).unwrap();
+    writeln!(out, "/// Peripherals with a clock selector").unwrap();
+    writeln!(
+        out,
+        "pub const KERNEL_CLOCKS: [(Peripheral, u32); {}] = [",
+        tree.kernel.len()
+    )
+    .unwrap();
+    for (peripheral, frequency) in &tree.kernel {
+        writeln!(out, "    (Peripheral::{}, {}),", peripheral, frequency).unwrap();
+    }
+    writeln!(out, "];").unwrap();
+    out
+}
+
+/*
+    Tests
+*/
+#[cfg(test)]
+mod test {
+    use crate::{default_config, generate, resolve};
+
+    #[test]
+    fn test_generate() {
+        let tree = resolve("stm32l432kc", &default_config("stm32l432kc").unwrap()).unwrap();
+        let source = generate(&tree, true);
+        assert!(source.contains("pll: 0x01002801,"));
+        assert!(source.contains("pll_on: true,"));
+        assert!(source.contains("pub const PCLK1_HZ: u32 = 80000000;"));
+        assert!(source.contains("KERNEL_CLOCKS: [(Peripheral, u32); 5]"));
+        assert!(source.contains("(Peripheral::I2C3, 80000000),"));
+        let source = generate(&tree, false);
+        assert!(source.contains("pub const SETUP: Option<Setup> = None;"));
+    }
+}
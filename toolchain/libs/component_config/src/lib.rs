@@ -4,11 +4,17 @@
 
 pub mod structures;
 
-use structures::ComponentExtendedConfig;
+use structures::{ComponentExtendedConfig, ConfigTable};
 
 use crate::structures::ComponentConfig;
+use std::env;
 use std::fs;
 use std::error::Error;
+use std::path::PathBuf;
+use toml::Value;
+
+/// Path of the settings of the instance being built, set by the component_builder
+pub const CONFIG_ENV: &str = "CONCEPT_OS_COMPONENT_CONFIG";
 
 /*
     Methods
@@ -38,6 +44,59 @@ pub fn write_component_config(file_name: &str, config: &ComponentConfig) -> Resu
     return Ok(());
 }
 
+/// The settings of the instance replace the defaults with the same key
+pub fn merge_config(defaults: &mut ConfigTable, instance: &ConfigTable) {
+    for (key, value) in instance {
+        defaults.insert(key.clone(), value.clone());
+    }
+}
+
+/// Replaces every `{key}` in the name of a peripheral (or of an interrupt) with
+/// the value of the setting, as "{usart}.irq" to "usart1.irq"
+pub fn expand_placeholders(name: &str, config: &ConfigTable) -> Result<String, Box<dyn Error>> {
+    let mut expanded = String::new();
+    let mut rest = name;
+    while let Some(start) = rest.find('{') {
+        let end = rest[start..].find('}').ok_or(format!("Unclosed placeholder in '{}'", name))?;
+        let key = &rest[start + 1..start + end];
+        let value = match config.get(key) {
+            Some(Value::String(value)) => value.clone(),
+            Some(Value::Integer(value)) => value.to_string(),
+            Some(_) => return Err(format!("Setting '{}' cannot be used in '{}'", key, name).into()),
+            None => return Err(format!("Missing setting '{}' for '{}'", key, name).into()),
+        };
+        expanded.push_str(&rest[..start]);
+        expanded.push_str(&value);
+        rest = &rest[start + end + 1..];
+    }
+    expanded.push_str(rest);
+    Ok(expanded)
+}
+
+pub fn write_instance_config(file_name: &str, config: &ConfigTable) -> Result<(), Box<dyn Error>> {
+    // As a value, the settings that are tables are written after the others
+    let file_content = toml::to_string_pretty(&Value::Table(config.clone()))?;
+    fs::write(file_name, file_content)?;
+    Ok(())
+}
+
+/// To be called from the build script of a component. The settings are the ones
+/// written by the component_builder or, when the component is built on its own,
+/// the defaults of its Component.toml.
+pub fn read_instance_config() -> Result<ConfigTable, Box<dyn Error>> {
+    println!("cargo:rerun-if-env-changed={}", CONFIG_ENV);
+    if let Ok(path) = env::var(CONFIG_ENV) {
+        println!("cargo:rerun-if-changed={}", path);
+        let file_content = fs::read_to_string(path)?;
+        return Ok(toml::from_str(&file_content)?);
+    }
+    let mut path = PathBuf::from(env::var("CARGO_MANIFEST_DIR")?);
+    path.push("Component.toml");
+    println!("cargo:rerun-if-changed={}", path.display());
+    let config = read_component_extended_config(path.to_str().unwrap())?;
+    Ok(config.config.unwrap_or_default())
+}
+
 /*
     Tests
 */
@@ -245,4 +304,34 @@ mod test {
         let recovered = read_component_extended_config(&test_file_path).unwrap();
         println!("{:?}", recovered);
     }
+
+    #[test]
+    fn extended_config() {
+        let test_file_path = get_test_file_path("extended2.toml");
+        let recovered = read_component_extended_config(&test_file_path).unwrap();
+        let mut config = recovered.config.unwrap();
+        // Override the defaults as an instance in App.toml
+        let instance: ConfigTable = toml::from_str("usart = \"usart1\"\ndma_channel = 5").unwrap();
+        merge_config(&mut config, &instance);
+        assert_eq!(config.get("baudrate"), Some(&Value::Integer(115200)));
+        let peripherals = recovered.component.peripherals.unwrap();
+        assert_eq!(expand_placeholders(&peripherals[0], &config).unwrap(), "usart1");
+        assert_eq!(expand_placeholders(&peripherals[1], &config).unwrap(), "dma1");
+        let interrupts: Vec<String> = recovered.component.interrupts.unwrap()
+            .keys()
+            .map(|name| expand_placeholders(name, &config).unwrap())
+            .collect();
+        assert_eq!(interrupts, vec!["dma1.irq5", "usart1.irq"]);
+        let dma_channels = recovered.component.dma_channels.unwrap();
+        assert_eq!(expand_placeholders(&dma_channels[0], &config).unwrap(), "dma1.5");
+        // Written for the build script, with the settings that are tables
+        let file = NamedTempFile::new().unwrap();
+        write_instance_config(file.path().to_str().unwrap(), &config).unwrap();
+        let written: ConfigTable = toml::from_str(&fs::read_to_string(file.path()).unwrap()).unwrap();
+        assert_eq!(written, config);
+        // Not usable as a name
+        assert!(expand_placeholders("{baudrate", &config).is_err());
+        assert!(expand_placeholders("{parity}", &config).is_err());
+        assert!(expand_placeholders("{tx}", &config).is_err());
+    }
 }
\ No newline at end of file
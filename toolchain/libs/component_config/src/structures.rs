@@ -66,10 +66,15 @@ pub struct Dependency {
 }
 
 
+/// Settings of an instance of the component, read by its build script
+pub type ConfigTable = toml::value::Table;
+
 #[derive(Deserialize, PartialEq, Debug)]
 pub struct ComponentExtendedConfig {
     pub component: ComponentExtended,
-    pub dependencies: Option<Vec<Dependency>>
+    pub dependencies: Option<Vec<Dependency>>,
+    /// Defaults of the settings, App.toml can override them for each instance
+    pub config: Option<ConfigTable>
 }
 
 #[derive(Deserialize, PartialEq, Debug)]
@@ -79,6 +84,10 @@ pub struct ComponentExtended {
     pub priority: u16,
     pub flags: Vec<ComponentFlag>,
     pub min_ram: u32,
+    /// Names in Board.toml, `{key}` is replaced with the value of the setting
     pub peripherals: Option<Vec<String>>,
     pub interrupts: Option<BTreeMap<String, u32>>,
+    /// DMA channels programmed by the component, as "dma2.6", `{key}` is
+    /// replaced as in the peripherals. No two components can claim the same.
+    pub dma_channels: Option<Vec<String>>,
 }
\ No newline at end of file
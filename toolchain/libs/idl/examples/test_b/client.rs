@@ -0,0 +1,82 @@
+// Generated from the schema of the TestB interface, do not edit.
+
+/**
+ * Constants
+ */
+const TEST_B_TASK_ID: userlib::TaskId = userlib::TaskId(16);
+
+/**
+ * Error Type
+ */
+#[derive(Copy, Clone, Debug)]
+#[repr(u32)]
+pub enum BError {
+    BadArgument = 1,
+    ComponentUnavailable = 2,
+}
+impl From<u32> for BError {
+    fn from(x: u32) -> Self {
+        match x {
+            1 => BError::BadArgument,
+            _ => BError::ComponentUnavailable,
+        }
+    }
+}
+impl From<BError> for u32 {
+    fn from(x: BError) -> Self {
+        x as u32
+    }
+}
+
+/**
+ * Operations
+ */
+#[derive(Copy, Clone, Debug, userlib::FromPrimitive)]
+pub enum Operation {
+    Mock1 = 1,
+}
+
+#[derive(zerocopy::FromBytes, zerocopy::AsBytes)]
+#[repr(C)]
+pub struct Mock1Request {
+    pub a: u32,
+    pub b: u32,
+}
+impl userlib::hl::Call for Mock1Request {
+    const OP: u16 = Operation::Mock1 as u16;
+    type Response = ();
+    type Err = BError;
+}
+
+/**
+ * Component Interface
+ */
+pub struct TestB(core::cell::Cell<userlib::TaskId>);
+
+impl TestB {
+    pub fn new() -> Self {
+        Self {
+            0: core::cell::Cell::new(TEST_B_TASK_ID),
+        }
+    }
+
+    /// The component is in the system, so it can be called
+    pub fn is_present(&self) -> bool {
+        userlib::kipc::is_task_present(self.0.get().component_id())
+    }
+
+    pub fn mock1(
+        &mut self,
+        a: u32,
+        b: u32,
+    ) -> Result<(), BError> {
+        userlib::hl::send_with_retry(
+            &self.0,
+            &Mock1Request {
+                a: a,
+                b: b,
+            },
+            &[],
+        )
+    }
+}
@@ -0,0 +1,28 @@
+// Generated from the schema of the TestB interface, do not edit.
+
+/// Server side of the TestB interface. Leases are passed with their length,
+/// deferred operations get the caller and reply on their own.
+pub trait TestBServer {
+    fn mock1(
+        &mut self,
+        msg: &Mock1Request,
+    ) -> Result<(), BError>;
+
+    /// Handles a message received with `hl::recv`
+    fn dispatch(
+        &mut self,
+        op: Operation,
+        msg: userlib::hl::Message<'_>,
+    ) -> Result<(), BError> {
+        match op {
+            Operation::Mock1 => {
+                let (msg, caller) = msg
+                    .fixed::<Mock1Request, ()>()
+                    .ok_or(BError::BadArgument)?;
+                self.mock1(msg)?;
+                caller.reply(());
+                Ok(())
+            }
+        }
+    }
+}
@@ -0,0 +1,236 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use crate::structures::{Field, Interface, LeaseAccess, Operation, TaskIdValue};
+use crate::{push_cfg, push_doc, snake_case};
+
+/// Client stub of the interface: error type, operations, requests and the client
+/// structure. Only full paths are used, so that it can be included next to
+/// hand-written code.
+pub fn generate_client(interface: &Interface) -> String {
+    let mut output = String::new();
+    output.push_str(&format!(
+        "// Generated from the schema of the {} interface, do not edit.\n\n",
+        interface.interface.name
+    ));
+    push_constants(&mut output, interface);
+    push_error(&mut output, interface);
+    push_operations(&mut output, interface);
+    push_client(&mut output, interface);
+    output
+}
+
+fn task_id_constant(interface: &Interface) -> String {
+    format!(
+        "{}_TASK_ID",
+        snake_case(&interface.interface.name).to_uppercase()
+    )
+}
+
+fn push_constants(output: &mut String, interface: &Interface) {
+    let value = match &interface.interface.task_id {
+        TaskIdValue::Number(id) => id.to_string(),
+        TaskIdValue::Constant(constant) => constant.clone(),
+    };
+    output.push_str("/**\n * Constants\n */\n");
+    output.push_str(&format!(
+        "const {}: userlib::TaskId = userlib::TaskId({});\n\n",
+        task_id_constant(interface),
+        value
+    ));
+}
+
+fn push_error(output: &mut String, interface: &Interface) {
+    let error = &interface.error;
+    let mut derives = vec!["Copy", "Clone", "Debug"];
+    derives.extend(error.derives.iter().map(|d| d.as_str()));
+    output.push_str("/**\n * Error Type\n */\n");
+    output.push_str(&format!("#[derive({})]\n", derives.join(", ")));
+    output.push_str("#[repr(u32)]\n");
+    output.push_str(&format!("pub enum {} {{\n", error.name));
+    for (i, variant) in error.variants.iter().enumerate() {
+        output.push_str(&format!("    {} = {},\n", variant, i + 1));
+    }
+    output.push_str("}\n");
+    output.push_str(&format!("impl From<u32> for {} {{\n", error.name));
+    output.push_str("    fn from(x: u32) -> Self {\n");
+    output.push_str("        match x {\n");
+    let (last, others) = error.variants.split_last().unwrap();
+    for (i, variant) in others.iter().enumerate() {
+        output.push_str(&format!(
+            "            {} => {}::{},\n",
+            i + 1,
+            error.name,
+            variant
+        ));
+    }
+    output.push_str(&format!("            _ => {}::{},\n", error.name, last));
+    output.push_str("        }\n    }\n}\n");
+    output.push_str(&format!("impl From<{}> for u32 {{\n", error.name));
+    output.push_str(&format!("    fn from(x: {}) -> Self {{\n", error.name));
+    output.push_str("        x as u32\n    }\n}\n\n");
+}
+
+fn push_struct(output: &mut String, name: &str, derives: &str, fields: &[Field]) {
+    output.push_str(&format!("#[derive({})]\n", derives));
+    output.push_str("#[repr(C)]\n");
+    if fields.is_empty() {
+        output.push_str(&format!("pub struct {} {{}}\n", name));
+        return;
+    }
+    output.push_str(&format!("pub struct {} {{\n", name));
+    for field in fields {
+        push_doc(output, "    ", &field.doc);
+        push_cfg(output, "    ", &field.cfg);
+        output.push_str(&format!("    pub {}: {},\n", field.name, field.ty));
+    }
+    output.push_str("}\n");
+}
+
+fn push_operations(output: &mut String, interface: &Interface) {
+    output.push_str("/**\n * Operations\n */\n");
+    output.push_str("#[derive(Copy, Clone, Debug, userlib::FromPrimitive)]\n");
+    output.push_str("pub enum Operation {\n");
+    for operation in &interface.operations {
+        output.push_str(&format!("    {} = {},\n", operation.name, operation.id));
+    }
+    output.push_str("}\n");
+    for operation in &interface.operations {
+        output.push('\n');
+        push_doc(output, "", &operation.doc);
+        push_struct(
+            output,
+            &format!("{}Request", operation.name),
+            "zerocopy::FromBytes, zerocopy::AsBytes",
+            &operation.request,
+        );
+        if let Some(response) = &operation.response {
+            push_struct(
+                output,
+                &format!("{}Response", operation.name),
+                "Debug, zerocopy::FromBytes, zerocopy::AsBytes",
+                response,
+            );
+        }
+        output.push_str(&format!(
+            "impl userlib::hl::Call for {}Request {{\n",
+            operation.name
+        ));
+        output.push_str(&format!(
+            "    const OP: u16 = Operation::{} as u16;\n",
+            operation.name
+        ));
+        output.push_str(&format!(
+            "    type Response = {};\n",
+            response_type(operation)
+        ));
+        output.push_str(&format!("    type Err = {};\n", interface.error.name));
+        output.push_str("}\n");
+    }
+    output.push('\n');
+}
+
+pub(crate) fn response_type(operation: &Operation) -> String {
+    match operation.response {
+        Some(_) => format!("{}Response", operation.name),
+        None => String::from("()"),
+    }
+}
+
+fn push_client(output: &mut String, interface: &Interface) {
+    let name = &interface.interface.name;
+    output.push_str("/**\n * Component Interface\n */\n");
+    push_doc(output, "", &interface.interface.doc);
+    output.push_str(&format!(
+        "pub struct {}(core::cell::Cell<userlib::TaskId>);\n\n",
+        name
+    ));
+    output.push_str(&format!("impl {} {{\n", name));
+    output.push_str("    pub fn new() -> Self {\n");
+    output.push_str("        Self {\n");
+    output.push_str(&format!(
+        "            0: core::cell::Cell::new({}),\n",
+        task_id_constant(interface)
+    ));
+    output.push_str("        }\n    }\n\n");
+    // Sending to a component missing from the image faults the sender
+    output.push_str("    /// The component is in the system, so it can be called\n");
+    output.push_str("    pub fn is_present(&self) -> bool {\n");
+    output.push_str(
+        "        userlib::kipc::is_task_present(self.0.get().component_id())\n",
+    );
+    output.push_str("    }\n");
+    for operation in interface.operations.iter().filter(|o| o.client) {
+        push_method(output, interface, operation);
+    }
+    output.push_str("}\n");
+}
+
+fn push_method(output: &mut String, interface: &Interface, operation: &Operation) {
+    // Arguments in the order of the schema
+    let default_params: Vec<String> = operation
+        .request
+        .iter()
+        .map(|f| f.name.clone())
+        .chain(operation.leases.iter().map(|l| l.name.clone()))
+        .collect();
+    let params = operation.params.as_ref().unwrap_or(&default_params);
+    output.push('\n');
+    push_doc(output, "    ", &operation.doc);
+    output.push_str(&format!("    pub fn {}(\n", snake_case(&operation.name)));
+    output.push_str(&format!(
+        "        &{}self,\n",
+        if operation.mutable { "mut " } else { "" }
+    ));
+    for param in params {
+        if let Some(field) = operation.request.iter().find(|f| &f.name == param) {
+            push_cfg(output, "        ", &field.cfg);
+            output.push_str(&format!(
+                "        {}: {},\n",
+                field.name,
+                field.param.as_ref().unwrap_or(&field.ty)
+            ));
+        } else if let Some(lease) = operation.leases.iter().find(|l| &l.name == param) {
+            let ty = match lease.access {
+                LeaseAccess::Read => "&[u8]",
+                LeaseAccess::Write => "&mut [u8]",
+            };
+            output.push_str(&format!("        {}: {},\n", lease.name, ty));
+        }
+    }
+    output.push_str(&format!(
+        "    ) -> Result<{}, {}> {{\n",
+        response_type(operation),
+        interface.error.name
+    ));
+    output.push_str("        userlib::hl::send_with_retry(\n");
+    output.push_str("            &self.0,\n");
+    if operation.request.is_empty() {
+        output.push_str(&format!("            &{}Request {{}},\n", operation.name));
+    } else {
+        output.push_str(&format!("            &{}Request {{\n", operation.name));
+        for field in &operation.request {
+            push_cfg(output, "                ", &field.cfg);
+            let cast = match &field.param {
+                Some(_) => format!(" as {}", field.ty),
+                None => String::new(),
+            };
+            output.push_str(&format!(
+                "                {}: {}{},\n",
+                field.name, field.name, cast
+            ));
+        }
+        output.push_str("            },\n");
+    }
+    let leases: Vec<String> = operation
+        .leases
+        .iter()
+        .map(|l| match l.access {
+            LeaseAccess::Read => format!("userlib::Lease::read_only({})", l.name),
+            LeaseAccess::Write => format!("userlib::Lease::write_only({})", l.name),
+        })
+        .collect();
+    output.push_str(&format!("            &[{}],\n", leases.join(", ")));
+    output.push_str("        )\n    }\n");
+}
@@ -0,0 +1,356 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+mod client;
+mod server;
+pub mod structures;
+
+pub use client::generate_client;
+pub use server::generate_server;
+
+use std::collections::HashSet;
+use std::env;
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::path::PathBuf;
+
+use structures::Interface;
+
+/*
+    Errors
+*/
+#[derive(Debug, PartialEq)]
+pub enum IdlError {
+    NoErrorVariants,
+    MissingBadArgument(String),
+    DuplicateOperationName(String),
+    DuplicateOperationId(u16),
+    DuplicateParam { operation: String, param: String },
+    UnknownParam { operation: String, param: String },
+    MissingParam { operation: String, param: String },
+}
+
+impl fmt::Display for IdlError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            IdlError::NoErrorVariants => write!(f, "the error type has no variants"),
+            IdlError::MissingBadArgument(name) => {
+                write!(f, "the error type has no variant named '{}'", name)
+            }
+            IdlError::DuplicateOperationName(name) => {
+                write!(f, "operation '{}' is defined twice", name)
+            }
+            IdlError::DuplicateOperationId(id) => write!(f, "operation id {} is used twice", id),
+            IdlError::DuplicateParam { operation, param } => {
+                write!(
+                    f,
+                    "'{}' is defined twice in operation '{}'",
+                    param, operation
+                )
+            }
+            IdlError::UnknownParam { operation, param } => write!(
+                f,
+                "'{}' is not a request field or a lease of operation '{}'",
+                param, operation
+            ),
+            IdlError::MissingParam { operation, param } => write!(
+                f,
+                "'{}' is missing from the params of operation '{}'",
+                param, operation
+            ),
+        }
+    }
+}
+
+impl Error for IdlError {}
+
+/*
+    Methods
+*/
+
+pub fn parse_interface(content: &str) -> Result<Interface, Box<dyn Error>> {
+    let interface: Interface = toml::from_str(content)?;
+    validate(&interface)?;
+    Ok(interface)
+}
+
+pub fn read_interface(file_name: &str) -> Result<Interface, Box<dyn Error>> {
+    // Read file
+    let file_content = fs::read_to_string(file_name)?;
+    // Parse interface from file
+    parse_interface(&file_content)
+}
+
+/// To be called from the build script of the API crate, the stub is then
+/// included with `include!(concat!(env!("OUT_DIR"), "/<output_name>"))`
+pub fn build_client_stub(schema: &str, output_name: &str) -> Result<(), Box<dyn Error>> {
+    let interface = read_interface(schema)?;
+    write_output(schema, output_name, &generate_client(&interface))
+}
+
+/// To be called from the build script of the server, the dispatch trait refers to
+/// the types of the API crate, that must be in scope where it is included
+pub fn build_server_support(schema: &str, output_name: &str) -> Result<(), Box<dyn Error>> {
+    let interface = read_interface(schema)?;
+    write_output(schema, output_name, &generate_server(&interface))
+}
+
+fn write_output(schema: &str, output_name: &str, content: &str) -> Result<(), Box<dyn Error>> {
+    let mut output = PathBuf::from(env::var("OUT_DIR")?);
+    output.push(output_name);
+    fs::write(output, content)?;
+    println!("cargo:rerun-if-changed={}", schema);
+    Ok(())
+}
+
+fn validate(interface: &Interface) -> Result<(), IdlError> {
+    let error = &interface.error;
+    if error.variants.is_empty() {
+        return Err(IdlError::NoErrorVariants);
+    }
+    if !error.variants.contains(&error.bad_argument) {
+        return Err(IdlError::MissingBadArgument(error.bad_argument.clone()));
+    }
+    let mut names: HashSet<&str> = HashSet::new();
+    let mut ids: HashSet<u16> = HashSet::new();
+    for operation in &interface.operations {
+        if !names.insert(&operation.name) {
+            return Err(IdlError::DuplicateOperationName(operation.name.clone()));
+        }
+        if !ids.insert(operation.id) {
+            return Err(IdlError::DuplicateOperationId(operation.id));
+        }
+        // Arguments of the client method
+        let mut args: Vec<&str> = Vec::new();
+        for name in operation
+            .request
+            .iter()
+            .map(|f| f.name.as_str())
+            .chain(operation.leases.iter().map(|l| l.name.as_str()))
+        {
+            if args.contains(&name) {
+                return Err(IdlError::DuplicateParam {
+                    operation: operation.name.clone(),
+                    param: String::from(name),
+                });
+            }
+            args.push(name);
+        }
+        if let Some(params) = &operation.params {
+            for param in params {
+                if !args.contains(&param.as_str()) {
+                    return Err(IdlError::UnknownParam {
+                        operation: operation.name.clone(),
+                        param: param.clone(),
+                    });
+                }
+            }
+            for arg in args {
+                if params.iter().filter(|p| p.as_str() == arg).count() != 1 {
+                    return Err(IdlError::MissingParam {
+                        operation: operation.name.clone(),
+                        param: String::from(arg),
+                    });
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+/*
+    Helpers
+*/
+
+/// AllocateComponent -> allocate_component, RCC -> rcc
+pub(crate) fn snake_case(name: &str) -> String {
+    let chars: Vec<char> = name.chars().collect();
+    let mut result = String::new();
+    for (i, c) in chars.iter().enumerate() {
+        if c.is_uppercase() && i > 0 {
+            let previous = chars[i - 1];
+            let next_lower = matches!(chars.get(i + 1), Some(n) if n.is_lowercase());
+            if previous.is_lowercase()
+                || previous.is_ascii_digit()
+                || (previous.is_uppercase() && next_lower)
+            {
+                result.push('_');
+            }
+        }
+        result.extend(c.to_lowercase());
+    }
+    result
+}
+
+/// Doc comment, one line per line of the text
+pub(crate) fn push_doc(output: &mut String, indent: &str, doc: &Option<String>) {
+    if let Some(doc) = doc {
+        for line in doc.trim_end().lines() {
+            output.push_str(&format!("{}/// {}\n", indent, line).replace("/// \n", "///\n"));
+        }
+    }
+}
+
+/// Condition of a field, if any
+pub(crate) fn push_cfg(output: &mut String, indent: &str, cfg: &Option<String>) {
+    if let Some(cfg) = cfg {
+        output.push_str(&format!("{}#[cfg({})]\n", indent, cfg));
+    }
+}
+
+/*
+    Tests
+*/
+#[cfg(test)]
+mod test {
+    use std::path::PathBuf;
+
+    use super::*;
+    use crate::structures::{LeaseAccess, TaskIdValue};
+
+    fn get_test_file_path(name: &str) -> String {
+        let mut d = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        d.push("examples");
+        d.push(name);
+        String::from(d.to_str().unwrap())
+    }
+
+    const MINIMAL: &str = r#"
+        [interface]
+        name = "TestB"
+        task_id = 16
+
+        [error]
+        name = "BError"
+        variants = ["BadArgument", "ComponentUnavailable"]
+    "#;
+
+    #[test]
+    fn snake_case_names() {
+        assert_eq!(snake_case("AllocateComponent"), "allocate_component");
+        assert_eq!(snake_case("GetNthBlock"), "get_nth_block");
+        assert_eq!(snake_case("RCC"), "rcc");
+        assert_eq!(snake_case("RCCError"), "rcc_error");
+        assert_eq!(snake_case("TestB"), "test_b");
+        assert_eq!(snake_case("Mock1"), "mock1");
+    }
+
+    #[test]
+    fn parse_minimal() {
+        let interface = parse_interface(MINIMAL).unwrap();
+        assert_eq!(interface.interface.task_id, TaskIdValue::Number(16));
+        assert_eq!(interface.error.bad_argument, "BadArgument");
+        assert!(interface.operations.is_empty());
+    }
+
+    #[test]
+    fn parse_operation() {
+        let content = format!(
+            "{}{}",
+            MINIMAL,
+            r#"
+            [[operation]]
+            name = "Transmit"
+            id = 3
+            params = ["data_out", "channel_id", "data_in"]
+            deferred = true
+            [[operation.request]]
+            name = "channel_id"
+            type = "u32"
+            param = "u16"
+            cfg = 'feature = "multi-support"'
+            [[operation.lease]]
+            name = "data_out"
+            access = "read"
+            [[operation.lease]]
+            name = "data_in"
+            access = "write"
+        "#
+        );
+        let interface = parse_interface(&content).unwrap();
+        let operation = &interface.operations[0];
+        assert_eq!(operation.id, 3);
+        assert!(operation.client && operation.mutable && operation.deferred);
+        assert_eq!(operation.request[0].param.as_deref(), Some("u16"));
+        assert_eq!(operation.leases[1].access, LeaseAccess::Write);
+        assert!(operation.response.is_none());
+    }
+
+    #[test]
+    fn validation_errors() {
+        let check = |extra: &str| -> IdlError {
+            let content = format!("{}{}", MINIMAL, extra);
+            let interface: Interface = toml::from_str(&content).unwrap();
+            validate(&interface).unwrap_err()
+        };
+        assert_eq!(
+            check("[[operation]]\nname = \"A\"\nid = 1\n[[operation]]\nname = \"A\"\nid = 2\n"),
+            IdlError::DuplicateOperationName(String::from("A"))
+        );
+        assert_eq!(
+            check("[[operation]]\nname = \"A\"\nid = 1\n[[operation]]\nname = \"B\"\nid = 1\n"),
+            IdlError::DuplicateOperationId(1)
+        );
+        assert_eq!(
+            check("[[operation]]\nname = \"A\"\nid = 1\nparams = [\"x\"]\n"),
+            IdlError::UnknownParam {
+                operation: String::from("A"),
+                param: String::from("x")
+            }
+        );
+        assert_eq!(
+            check(concat!(
+                "[[operation]]\nname = \"A\"\nid = 1\nparams = []\n",
+                "[[operation.lease]]\nname = \"data\"\naccess = \"read\"\n"
+            )),
+            IdlError::MissingParam {
+                operation: String::from("A"),
+                param: String::from("data")
+            }
+        );
+        let interface: Interface =
+            toml::from_str(&MINIMAL.replace("\"BadArgument\", ", "")).unwrap();
+        assert_eq!(
+            validate(&interface).unwrap_err(),
+            IdlError::MissingBadArgument(String::from("BadArgument"))
+        );
+    }
+
+    #[test]
+    fn client_generation() {
+        let interface = read_interface(&get_test_file_path("test_b/test_b.idl.toml")).unwrap();
+        let expected = fs::read_to_string(get_test_file_path("test_b/client.rs")).unwrap();
+        assert_eq!(generate_client(&interface), expected);
+    }
+
+    #[test]
+    fn server_generation() {
+        let interface = read_interface(&get_test_file_path("test_b/test_b.idl.toml")).unwrap();
+        let expected = fs::read_to_string(get_test_file_path("test_b/server.rs")).unwrap();
+        assert_eq!(generate_server(&interface), expected);
+    }
+
+    #[test]
+    fn server_sender() {
+        let content = format!(
+            "{}{}",
+            MINIMAL,
+            r#"
+            [[operation]]
+            name = "Get"
+            id = 1
+            sender = true
+            [[operation.lease]]
+            name = "key"
+            access = "read"
+        "#
+        );
+        let server = generate_server(&parse_interface(&content).unwrap());
+        assert!(server.contains(
+            "        sender: userlib::TaskId,\n        key: &userlib::hl::Borrow<'_>,\n"
+        ));
+        assert!(server.contains("self.get(msg, caller.task_id(), &key, key_info.len)?;"));
+    }
+}
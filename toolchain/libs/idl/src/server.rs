@@ -0,0 +1,155 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use crate::client::response_type;
+use crate::structures::{Interface, LeaseAccess, Operation};
+use crate::{push_doc, snake_case};
+
+/// Server dispatch trait of the interface: one method per operation, called by
+/// `dispatch` once the request is decoded and its leases checked.
+pub fn generate_server(interface: &Interface) -> String {
+    let name = &interface.interface.name;
+    let mut output = String::new();
+    output.push_str(&format!(
+        "// Generated from the schema of the {} interface, do not edit.\n\n",
+        name
+    ));
+    output.push_str(&format!(
+        "/// Server side of the {} interface. Leases are passed with their length,\n",
+        name
+    ));
+    output.push_str("/// deferred operations get the caller and reply on their own.\n");
+    output.push_str(&format!("pub trait {}Server {{\n", name));
+    for operation in &interface.operations {
+        push_handler(&mut output, interface, operation);
+    }
+    push_dispatch(&mut output, interface);
+    output.push_str("}\n");
+    output
+}
+
+fn push_handler(output: &mut String, interface: &Interface, operation: &Operation) {
+    push_doc(output, "    ", &operation.doc);
+    let mut args = vec![
+        String::from("&mut self"),
+        format!("msg: &{}Request", operation.name),
+    ];
+    let result = if operation.deferred {
+        args.push(format!(
+            "caller: userlib::hl::Caller<{}>",
+            response_type(operation)
+        ));
+        String::from("()")
+    } else {
+        if operation.sender {
+            args.push(String::from("sender: userlib::TaskId"));
+        }
+        for lease in &operation.leases {
+            args.push(format!("{}: &userlib::hl::Borrow<'_>", lease.name));
+            args.push(format!("{}_len: usize", lease.name));
+        }
+        response_type(operation)
+    };
+    output.push_str(&format!(
+        "    fn {}(\n        {},\n    ) -> Result<{}, {}>;\n",
+        snake_case(&operation.name),
+        args.join(",\n        "),
+        result,
+        interface.error.name
+    ));
+}
+
+fn push_dispatch(output: &mut String, interface: &Interface) {
+    let error = &interface.error.name;
+    let bad_argument = format!("{}::{}", error, interface.error.bad_argument);
+    output.push_str("\n    /// Handles a message received with `hl::recv`\n");
+    output.push_str("    fn dispatch(\n");
+    output.push_str("        &mut self,\n");
+    output.push_str("        op: Operation,\n");
+    output.push_str("        msg: userlib::hl::Message<'_>,\n");
+    output.push_str(&format!("    ) -> Result<(), {}> {{\n", error));
+    output.push_str("        match op {\n");
+    for operation in &interface.operations {
+        output.push_str(&format!(
+            "            Operation::{} => {{\n",
+            operation.name
+        ));
+        // Parse message
+        let (parse, count) = if operation.leases.is_empty() {
+            ("fixed", String::new())
+        } else {
+            ("fixed_with_leases", operation.leases.len().to_string())
+        };
+        output.push_str("                let (msg, caller) = msg\n");
+        output.push_str(&format!(
+            "                    .{}::<{}Request, {}>({})\n",
+            parse,
+            operation.name,
+            response_type(operation),
+            count
+        ));
+        output.push_str(&format!("                    .ok_or({})?;\n", bad_argument));
+        // Check lease permissions, the borrows are kept only if passed to the handler
+        for (i, lease) in operation.leases.iter().enumerate() {
+            let attribute = match lease.access {
+                LeaseAccess::Read => "READ",
+                LeaseAccess::Write => "WRITE",
+            };
+            let borrow = if operation.deferred {
+                format!("caller.borrow({})", i)
+            } else {
+                output.push_str(&format!(
+                    "                let {} = caller.borrow({});\n",
+                    lease.name, i
+                ));
+                lease.name.clone()
+            };
+            output.push_str(&format!(
+                "                let {}_info = {}.info().ok_or({})?;\n",
+                lease.name, borrow, bad_argument
+            ));
+            output.push_str(&format!(
+                "                if !{}_info.attributes.contains(userlib::LeaseAttributes::{}) {{\n",
+                lease.name, attribute
+            ));
+            output.push_str(&format!(
+                "                    return Err({});\n",
+                bad_argument
+            ));
+            output.push_str("                }\n");
+        }
+        // Call the handler
+        let method = snake_case(&operation.name);
+        if operation.deferred {
+            output.push_str(&format!("                self.{}(msg, caller)\n", method));
+        } else {
+            let mut args = vec![String::from("msg")];
+            if operation.sender {
+                args.push(String::from("caller.task_id()"));
+            }
+            for lease in &operation.leases {
+                args.push(format!("&{}", lease.name));
+                args.push(format!("{}_info.len", lease.name));
+            }
+            if operation.response.is_some() {
+                output.push_str(&format!(
+                    "                let response = self.{}({})?;\n",
+                    method,
+                    args.join(", ")
+                ));
+                output.push_str("                caller.reply(response);\n");
+            } else {
+                output.push_str(&format!(
+                    "                self.{}({})?;\n",
+                    method,
+                    args.join(", ")
+                ));
+                output.push_str("                caller.reply(());\n");
+            }
+            output.push_str("                Ok(())\n");
+        }
+        output.push_str("            }\n");
+    }
+    output.push_str("        }\n    }\n");
+}
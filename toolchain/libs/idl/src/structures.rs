@@ -0,0 +1,109 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use serde::Deserialize;
+
+/*
+    Enums
+*/
+#[derive(Deserialize, PartialEq, Debug)]
+#[serde(untagged)]
+pub enum TaskIdValue {
+    /// Id written in the schema, as in the Component.toml
+    Number(u16),
+    /// Path of a constant holding the id (e.g. `userlib::STORAGE_ID`)
+    Constant(String),
+}
+
+#[derive(Deserialize, PartialEq, Debug, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+pub enum LeaseAccess {
+    Read,
+    Write,
+}
+
+/*
+    Schema
+*/
+#[derive(Deserialize, PartialEq, Debug)]
+pub struct Interface {
+    pub interface: InterfaceInfo,
+    pub error: ErrorInfo,
+    #[serde(rename = "operation", default)]
+    pub operations: Vec<Operation>,
+}
+
+#[derive(Deserialize, PartialEq, Debug)]
+pub struct InterfaceInfo {
+    /// Name of the client structure
+    pub name: String,
+    pub task_id: TaskIdValue,
+    pub doc: Option<String>,
+}
+
+#[derive(Deserialize, PartialEq, Debug)]
+pub struct ErrorInfo {
+    pub name: String,
+    /// Derives on top of Copy, Clone and Debug
+    #[serde(default)]
+    pub derives: Vec<String>,
+    /// Numbered from 1, the last one is used for the unknown codes
+    pub variants: Vec<String>,
+    /// Returned by the server for malformed requests
+    #[serde(default = "default_bad_argument")]
+    pub bad_argument: String,
+}
+
+fn default_bad_argument() -> String {
+    String::from("BadArgument")
+}
+
+#[derive(Deserialize, PartialEq, Debug)]
+pub struct Operation {
+    pub name: String,
+    pub id: u16,
+    pub doc: Option<String>,
+    #[serde(default)]
+    pub request: Vec<Field>,
+    /// Without response fields the operation replies with ()
+    pub response: Option<Vec<Field>>,
+    #[serde(rename = "lease", default)]
+    pub leases: Vec<Lease>,
+    /// Order of the arguments of the client method, fields then leases if omitted
+    pub params: Option<Vec<String>>,
+    /// Generate the client method (false when it is hand-written)
+    #[serde(default = "default_true")]
+    pub client: bool,
+    /// The client method takes &mut self
+    #[serde(default = "default_true")]
+    pub mutable: bool,
+    /// The server keeps the caller and replies later
+    #[serde(default)]
+    pub deferred: bool,
+    /// The handler also gets the id of the calling task (deferred ones get the caller)
+    #[serde(default)]
+    pub sender: bool,
+}
+
+fn default_true() -> bool {
+    true
+}
+
+#[derive(Deserialize, PartialEq, Debug)]
+pub struct Field {
+    pub name: String,
+    #[serde(rename = "type")]
+    pub ty: String,
+    /// Type of the client argument, cast with `as` into the field
+    pub param: Option<String>,
+    /// Condition for the presence of the field (e.g. `feature = "x"`)
+    pub cfg: Option<String>,
+    pub doc: Option<String>,
+}
+
+#[derive(Deserialize, PartialEq, Debug)]
+pub struct Lease {
+    pub name: String,
+    pub access: LeaseAccess,
+}
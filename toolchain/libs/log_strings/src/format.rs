@@ -0,0 +1,126 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+/// Argument received from the device
+#[derive(Debug, Clone, PartialEq)]
+pub enum LogValue {
+    Unsigned(u64),
+    Signed(i64),
+    Float(f32),
+    Bool(bool),
+    Char(char),
+    Str(String),
+}
+
+/// Subset of the core::fmt specification supported on the host:
+/// {[:[#][0][width][?|x|X|b|o]]}
+struct FormatSpec {
+    alternate: bool,
+    zero_pad: bool,
+    width: usize,
+    kind: char,
+}
+
+impl FormatSpec {
+    fn parse(spec: &str) -> Self {
+        let mut result = FormatSpec {
+            alternate: false,
+            zero_pad: false,
+            width: 0,
+            kind: ' ',
+        };
+        let mut chars = spec.chars().peekable();
+        if chars.peek() == Some(&'#') {
+            result.alternate = true;
+            chars.next();
+        }
+        if chars.peek() == Some(&'0') {
+            result.zero_pad = true;
+            chars.next();
+        }
+        while let Some(c) = chars.peek() {
+            if let Some(digit) = c.to_digit(10) {
+                result.width = result.width * 10 + digit as usize;
+                chars.next();
+            } else {
+                break;
+            }
+        }
+        if let Some(c) = chars.next() {
+            result.kind = c;
+        }
+        result
+    }
+
+    fn apply(&self, value: &LogValue) -> String {
+        let (prefix, digits) = match (value, self.kind) {
+            (LogValue::Unsigned(v), 'x') => ("0x", format!("{:x}", v)),
+            (LogValue::Unsigned(v), 'X') => ("0x", format!("{:X}", v)),
+            (LogValue::Unsigned(v), 'b') => ("0b", format!("{:b}", v)),
+            (LogValue::Unsigned(v), 'o') => ("0o", format!("{:o}", v)),
+            (LogValue::Signed(v), 'x') => ("0x", format!("{:x}", v)),
+            (LogValue::Signed(v), 'X') => ("0x", format!("{:X}", v)),
+            (LogValue::Signed(v), 'b') => ("0b", format!("{:b}", v)),
+            (LogValue::Signed(v), 'o') => ("0o", format!("{:o}", v)),
+            (LogValue::Str(v), '?') => ("", format!("{:?}", v)),
+            (LogValue::Char(v), '?') => ("", format!("{:?}", v)),
+            (LogValue::Unsigned(v), _) => ("", v.to_string()),
+            (LogValue::Signed(v), _) => ("", v.to_string()),
+            (LogValue::Float(v), _) => ("", v.to_string()),
+            (LogValue::Bool(v), _) => ("", v.to_string()),
+            (LogValue::Char(v), _) => ("", v.to_string()),
+            (LogValue::Str(v), _) => ("", v.clone()),
+        };
+        let prefix = if self.alternate { prefix } else { "" };
+        // As in core::fmt, the width includes the prefix
+        let len = prefix.len() + digits.chars().count();
+        if len >= self.width {
+            return format!("{}{}", prefix, digits);
+        }
+        let padding = self.width - len;
+        if self.zero_pad {
+            return format!("{}{}{}", prefix, "0".repeat(padding), digits);
+        }
+        format!("{}{}{}", " ".repeat(padding), prefix, digits)
+    }
+}
+
+/// Rebuilds the message, as sys_log! would have done on the device
+pub fn format_message(format: &str, args: &[LogValue]) -> String {
+    let mut result = String::new();
+    let mut args = args.iter();
+    let mut chars = format.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '{' if chars.peek() == Some(&'{') => {
+                chars.next();
+                result.push('{');
+            }
+            '}' if chars.peek() == Some(&'}') => {
+                chars.next();
+                result.push('}');
+            }
+            '{' => {
+                // Read the placeholder
+                let mut placeholder = String::new();
+                for p in chars.by_ref() {
+                    if p == '}' {
+                        break;
+                    }
+                    placeholder.push(p);
+                }
+                let spec = match placeholder.split_once(':') {
+                    Some((_, spec)) => FormatSpec::parse(spec),
+                    None => FormatSpec::parse(""),
+                };
+                match args.next() {
+                    Some(value) => result.push_str(&spec.apply(value)),
+                    None => result.push_str("<missing>"),
+                }
+            }
+            _ => result.push(c),
+        }
+    }
+    result
+}
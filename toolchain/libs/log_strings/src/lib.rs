@@ -0,0 +1,296 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+mod format;
+
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::error::Error;
+use std::fs;
+
+pub use format::{format_message, LogValue};
+
+/// Section of the component ELF containing the format strings
+pub const LOG_SECTION: &str = ".concept_log";
+/// Name of the table generated next to the component ELF
+pub const TABLE_FILE: &str = "log_strings.toml";
+
+/*
+    Frame layout, must match userlib::log
+*/
+const FRAME_MARKER: u8 = 0xC5;
+const FRAME_HEADER_SIZE: usize = 7;
+const MAX_FRAME_SIZE: usize = 64;
+const TRUNCATED_FLAG: u8 = 0x80;
+
+const TAG_U32: u8 = 0;
+const TAG_I32: u8 = 1;
+const TAG_U64: u8 = 2;
+const TAG_I64: u8 = 3;
+const TAG_BOOL: u8 = 4;
+const TAG_CHAR: u8 = 5;
+const TAG_STR: u8 = 6;
+const TAG_F32: u8 = 7;
+
+/*
+    String table
+*/
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct LogString {
+    pub index: u16,
+    pub format: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct LogStringTable {
+    pub component_id: u16,
+    pub strings: Vec<LogString>,
+}
+
+impl LogStringTable {
+    /// Splits the content of the log section: NUL terminated strings,
+    /// the index of each one is its offset in the section
+    pub fn from_section(component_id: u16, section: &[u8]) -> Self {
+        let mut strings: Vec<LogString> = Vec::new();
+        let mut start: usize = 0;
+        for (pos, b) in section.iter().enumerate() {
+            if *b == 0x00 {
+                strings.push(LogString {
+                    index: start as u16,
+                    format: String::from_utf8_lossy(&section[start..pos]).to_string(),
+                });
+                start = pos + 1;
+            }
+        }
+        Self {
+            component_id,
+            strings,
+        }
+    }
+
+    /// Extracts the table from the component ELF. No section means no deferred logs.
+    pub fn from_elf(component_id: u16, elf_bytes: &[u8]) -> Result<Self, Box<dyn Error>> {
+        let elf = goblin::elf::Elf::parse(elf_bytes)?;
+        for section in &elf.section_headers {
+            if elf.shdr_strtab.get_at(section.sh_name) == Some(LOG_SECTION) {
+                let start = section.sh_offset as usize;
+                let end = start + section.sh_size as usize;
+                return Ok(Self::from_section(component_id, &elf_bytes[start..end]));
+            }
+        }
+        Ok(Self {
+            component_id,
+            strings: Vec::new(),
+        })
+    }
+
+    pub fn get(&self, index: u16) -> Option<&String> {
+        self.strings
+            .iter()
+            .find(|s| s.index == index)
+            .map(|s| &s.format)
+    }
+}
+
+pub fn read_table(file_name: &str) -> Result<LogStringTable, Box<dyn Error>> {
+    // Read file
+    let file_content = fs::read_to_string(file_name)?;
+    // Parse table from file
+    let table = toml::from_str(&file_content)?;
+    Ok(table)
+}
+
+pub fn write_table(file_name: &str, table: &LogStringTable) -> Result<(), Box<dyn Error>> {
+    // Generate table
+    let file_content = toml::to_string_pretty(table)?;
+    // Write to the file
+    fs::write(file_name, file_content)?;
+    Ok(())
+}
+
+/*
+    Frames
+*/
+#[derive(Debug, Clone, PartialEq)]
+pub struct LogFrame {
+    pub component_id: u16,
+    pub string_index: u16,
+    pub truncated: bool,
+    pub args: Vec<LogValue>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FrameError {
+    TooShort,
+    UnknownTag(u8),
+}
+
+pub fn decode_frame(frame: &[u8]) -> Result<LogFrame, FrameError> {
+    if frame.len() < FRAME_HEADER_SIZE || frame.len() != frame[1] as usize {
+        return Err(FrameError::TooShort);
+    }
+    let num_args = frame[6] & !TRUNCATED_FLAG;
+    let mut args: Vec<LogValue> = Vec::new();
+    let mut data = &frame[FRAME_HEADER_SIZE..];
+    for _ in 0..num_args {
+        if data.is_empty() {
+            return Err(FrameError::TooShort);
+        }
+        let tag = data[0];
+        let size = match tag {
+            TAG_U32 | TAG_I32 | TAG_CHAR | TAG_F32 => 4,
+            TAG_U64 | TAG_I64 => 8,
+            TAG_BOOL => 1,
+            TAG_STR => 1 + *data.get(1).ok_or(FrameError::TooShort)? as usize,
+            _ => return Err(FrameError::UnknownTag(tag)),
+        };
+        let value = data.get(1..1 + size).ok_or(FrameError::TooShort)?;
+        args.push(match tag {
+            TAG_U32 => LogValue::Unsigned(u32::from_le_bytes(value.try_into().unwrap()) as u64),
+            TAG_I32 => LogValue::Signed(i32::from_le_bytes(value.try_into().unwrap()) as i64),
+            TAG_U64 => LogValue::Unsigned(u64::from_le_bytes(value.try_into().unwrap())),
+            TAG_I64 => LogValue::Signed(i64::from_le_bytes(value.try_into().unwrap())),
+            TAG_BOOL => LogValue::Bool(value[0] != 0),
+            TAG_CHAR => LogValue::Char(
+                char::from_u32(u32::from_le_bytes(value.try_into().unwrap())).unwrap_or('?'),
+            ),
+            TAG_F32 => LogValue::Float(f32::from_le_bytes(value.try_into().unwrap())),
+            _ => LogValue::Str(String::from_utf8_lossy(&value[1..]).to_string()),
+        });
+        data = &data[1 + size..];
+    }
+    Ok(LogFrame {
+        component_id: u16::from_le_bytes([frame[2], frame[3]]),
+        string_index: u16::from_le_bytes([frame[4], frame[5]]),
+        truncated: frame[6] & TRUNCATED_FLAG != 0,
+        args,
+    })
+}
+
+/// Rebuilds frames from a byte stream, i.e. an ITM port
+pub struct FrameReader {
+    buffer: Vec<u8>,
+}
+
+impl Default for FrameReader {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl FrameReader {
+    pub fn new() -> Self {
+        Self { buffer: Vec::new() }
+    }
+
+    /// Returns the frame completed by this byte, if any
+    pub fn push(&mut self, byte: u8) -> Option<Vec<u8>> {
+        // Wait for the start of a frame
+        if self.buffer.is_empty() && byte != FRAME_MARKER {
+            return None;
+        }
+        self.buffer.push(byte);
+        if self.buffer.len() == 2 {
+            let len = byte as usize;
+            if !(FRAME_HEADER_SIZE..=MAX_FRAME_SIZE).contains(&len) {
+                // Not a frame, resync
+                self.buffer.clear();
+            }
+            return None;
+        }
+        if self.buffer.len() > 2 && self.buffer.len() == self.buffer[1] as usize {
+            return Some(std::mem::take(&mut self.buffer));
+        }
+        None
+    }
+}
+
+/// Decodes frames of every component of the system
+pub struct LogDecoder {
+    tables: HashMap<u16, LogStringTable>,
+}
+
+impl LogDecoder {
+    pub fn new(tables: Vec<LogStringTable>) -> Self {
+        let mut map: HashMap<u16, LogStringTable> = HashMap::new();
+        for table in tables {
+            map.insert(table.component_id, table);
+        }
+        Self { tables: map }
+    }
+
+    pub fn decode(&self, frame: &[u8]) -> String {
+        let frame = match decode_frame(frame) {
+            Ok(frame) => frame,
+            Err(e) => return format!("<malformed log frame: {:?}>", e),
+        };
+        let format = self
+            .tables
+            .get(&frame.component_id)
+            .and_then(|t| t.get(frame.string_index));
+        let mut message = match format {
+            Some(format) => format_message(format, &frame.args),
+            None => format!(
+                "<unknown string {} of component {}: {:?}>",
+                frame.string_index, frame.component_id, frame.args
+            ),
+        };
+        if frame.truncated {
+            message.push_str(" <truncated>");
+        }
+        format!("[{}] {}", frame.component_id, message)
+    }
+}
+
+/*
+    Tests
+*/
+#[cfg(test)]
+mod test {
+    use crate::{decode_frame, format_message, FrameReader, LogStringTable, LogValue};
+
+    #[test]
+    fn table_from_section() {
+        let table = LogStringTable::from_section(3, b"Hello\0Value: {}\0");
+        assert_eq!(table.get(0).unwrap(), "Hello");
+        assert_eq!(table.get(6).unwrap(), "Value: {}");
+        assert!(table.get(1).is_none());
+    }
+
+    #[test]
+    fn frame_round_trip() {
+        // Component 3, string 6, u32 42 and "ab"
+        let frame: [u8; 16] = [
+            0xC5, 16, 3, 0, 6, 0, 2, 0, 42, 0, 0, 0, 6, 2, b'a', b'b',
+        ];
+        let mut reader = FrameReader::new();
+        let mut result = None;
+        // Garbage before the frame is skipped
+        for b in [0x00, 0x12].iter().chain(frame.iter()) {
+            result = reader.push(*b);
+        }
+        let decoded = decode_frame(&result.unwrap()).unwrap();
+        assert_eq!(decoded.component_id, 3);
+        assert_eq!(decoded.string_index, 6);
+        assert!(!decoded.truncated);
+        assert_eq!(
+            decoded.args,
+            vec![LogValue::Unsigned(42), LogValue::Str(String::from("ab"))]
+        );
+    }
+
+    #[test]
+    fn message_format() {
+        let args = vec![
+            LogValue::Unsigned(255),
+            LogValue::Signed(-2),
+            LogValue::Str(String::from("x")),
+        ];
+        assert_eq!(
+            format_message("{:#010x} {} {:?} {{}}", &args),
+            "0x000000ff -2 \"x\" {}"
+        );
+        assert_eq!(format_message("{} {}", &args[0..1]), "255 <missing>");
+    }
+}
@@ -0,0 +1,399 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Embedded MQTT 3.1.1 broker, just enough to test the tools end to end:
+//! retained messages, wildcards and last wills. Messages are delivered with QoS 0,
+//! the QoS of incoming publishes is only acknowledged.
+
+use std::{
+    collections::HashMap,
+    io::{self, BufReader, Read, Write},
+    net::{Shutdown, TcpListener, TcpStream},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    thread::{self, JoinHandle},
+    time::Duration,
+};
+
+const CONNECT: u8 = 1;
+const PUBLISH: u8 = 3;
+const PUBREL: u8 = 6;
+const SUBSCRIBE: u8 = 8;
+const PINGREQ: u8 = 12;
+const DISCONNECT: u8 = 14;
+
+struct Message {
+    topic: String,
+    payload: Vec<u8>,
+    retain: bool,
+}
+
+struct Session {
+    stream: Arc<Mutex<TcpStream>>,
+    filters: Vec<String>,
+}
+
+#[derive(Default)]
+struct State {
+    sessions: HashMap<usize, Session>,
+    retained: HashMap<String, Vec<u8>>,
+    next_id: usize,
+}
+
+pub struct Broker {
+    port: u16,
+    state: Arc<Mutex<State>>,
+    running: Arc<AtomicBool>,
+    listener: Option<JoinHandle<()>>,
+}
+
+fn matches(filter: &str, topic: &str) -> bool {
+    let mut levels = topic.split('/');
+    for filter_level in filter.split('/') {
+        if filter_level == "#" {
+            return true;
+        }
+        match levels.next() {
+            Some(level) if filter_level == "+" || filter_level == level => {}
+            _ => return false,
+        }
+    }
+    levels.next().is_none()
+}
+
+fn encode_packet(header: u8, body: &[u8]) -> Vec<u8> {
+    let mut packet = vec![header];
+    let mut len = body.len();
+    loop {
+        let mut byte = (len % 128) as u8;
+        len /= 128;
+        if len > 0 {
+            byte |= 0x80;
+        }
+        packet.push(byte);
+        if len == 0 {
+            break;
+        }
+    }
+    packet.extend_from_slice(body);
+    packet
+}
+
+fn encode_publish(topic: &str, payload: &[u8], retain: bool) -> Vec<u8> {
+    let mut body = (topic.len() as u16).to_be_bytes().to_vec();
+    body.extend_from_slice(topic.as_bytes());
+    body.extend_from_slice(payload);
+    encode_packet((PUBLISH << 4) | retain as u8, &body)
+}
+
+fn read_packet(reader: &mut impl Read) -> io::Result<(u8, Vec<u8>)> {
+    let mut byte = [0u8; 1];
+    reader.read_exact(&mut byte)?;
+    let header = byte[0];
+    let mut len = 0usize;
+    let mut shift = 0;
+    loop {
+        reader.read_exact(&mut byte)?;
+        len |= ((byte[0] & 0x7F) as usize) << shift;
+        shift += 7;
+        if byte[0] & 0x80 == 0 {
+            break;
+        }
+    }
+    let mut body = vec![0u8; len];
+    reader.read_exact(&mut body)?;
+    Ok((header, body))
+}
+
+/// Reads the length-prefixed fields of a packet
+struct Fields<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Fields<'a> {
+    fn u8(&mut self) -> u8 {
+        let value = self.data[0];
+        self.data = &self.data[1..];
+        value
+    }
+
+    fn u16(&mut self) -> u16 {
+        let value = u16::from_be_bytes([self.data[0], self.data[1]]);
+        self.data = &self.data[2..];
+        value
+    }
+
+    fn bytes(&mut self) -> Vec<u8> {
+        let len = self.u16() as usize;
+        let value = self.data[..len].to_vec();
+        self.data = &self.data[len..];
+        value
+    }
+
+    fn string(&mut self) -> String {
+        String::from_utf8(self.bytes()).unwrap()
+    }
+}
+
+impl State {
+    fn publish(&mut self, message: Message) {
+        if message.retain {
+            self.retained
+                .insert(message.topic.clone(), message.payload.clone());
+        }
+        let packet = encode_publish(&message.topic, &message.payload, false);
+        for session in self.sessions.values() {
+            if session
+                .filters
+                .iter()
+                .any(|filter| matches(filter, &message.topic))
+            {
+                let _ = session.stream.lock().unwrap().write_all(&packet);
+            }
+        }
+    }
+}
+
+impl Broker {
+    /// Listens on localhost, on a free port if port is 0
+    pub fn start(port: u16) -> Self {
+        let listener = TcpListener::bind(("127.0.0.1", port)).unwrap();
+        listener.set_nonblocking(true).unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let state = Arc::new(Mutex::new(State::default()));
+        let running = Arc::new(AtomicBool::new(true));
+
+        let listener_state = state.clone();
+        let listener_running = running.clone();
+        let listener = thread::spawn(move || {
+            while listener_running.load(Ordering::SeqCst) {
+                match listener.accept() {
+                    Ok((stream, _)) => {
+                        stream.set_nonblocking(false).unwrap();
+                        let state = listener_state.clone();
+                        thread::spawn(move || serve(stream, state));
+                    }
+                    Err(_) => thread::sleep(Duration::from_millis(10)),
+                }
+            }
+        });
+        Self {
+            port,
+            state,
+            running,
+            listener: Some(listener),
+        }
+    }
+
+    pub fn port(&self) -> u16 {
+        self.port
+    }
+
+    /// Drops every connection at once, as a crashed broker would
+    pub fn stop(mut self) {
+        self.running.store(false, Ordering::SeqCst);
+        if let Some(listener) = self.listener.take() {
+            listener.join().unwrap();
+        }
+        let mut state = self.state.lock().unwrap();
+        for session in state.sessions.values() {
+            let _ = session.stream.lock().unwrap().shutdown(Shutdown::Both);
+        }
+        state.sessions.clear();
+    }
+}
+
+fn serve(stream: TcpStream, state: Arc<Mutex<State>>) {
+    let mut reader = BufReader::new(stream.try_clone().unwrap());
+    let writer = Arc::new(Mutex::new(stream));
+    let send = |packet: Vec<u8>| {
+        let _ = writer.lock().unwrap().write_all(&packet);
+    };
+
+    // The first packet must be a CONNECT
+    let (header, body) = match read_packet(&mut reader) {
+        Ok(packet) => packet,
+        Err(_) => return,
+    };
+    if header >> 4 != CONNECT {
+        return;
+    }
+    let mut fields = Fields { data: &body };
+    fields.string(); // Protocol name
+    fields.u8(); // Protocol level
+    let flags = fields.u8();
+    fields.u16(); // Keep alive
+    fields.string(); // Client id
+    let will = match flags & 0x04 != 0 {
+        true => Some(Message {
+            topic: fields.string(),
+            payload: fields.bytes(),
+            retain: flags & 0x20 != 0,
+        }),
+        false => None,
+    };
+    let id = {
+        let mut state = state.lock().unwrap();
+        let id = state.next_id;
+        state.next_id += 1;
+        state.sessions.insert(
+            id,
+            Session {
+                stream: writer.clone(),
+                filters: Vec::new(),
+            },
+        );
+        id
+    };
+    send(encode_packet(0x20, &[0, 0]));
+
+    let mut clean = false;
+    while let Ok((header, body)) = read_packet(&mut reader) {
+        let mut fields = Fields { data: &body };
+        match header >> 4 {
+            PUBLISH => {
+                let qos = (header >> 1) & 0x03;
+                let topic = fields.string();
+                let packet_id = match qos {
+                    0 => None,
+                    _ => Some(fields.u16().to_be_bytes()),
+                };
+                let message = Message {
+                    topic,
+                    payload: fields.data.to_vec(),
+                    retain: header & 0x01 != 0,
+                };
+                state.lock().unwrap().publish(message);
+                // PUBACK or PUBREC, once delivered
+                if let Some(packet_id) = packet_id {
+                    let ack = if qos == 1 { 0x40 } else { 0x50 };
+                    send(encode_packet(ack, &packet_id));
+                }
+            }
+            PUBREL => send(encode_packet(0x70, &body[..2])),
+            SUBSCRIBE => {
+                let packet_id = fields.u16();
+                let mut filters = Vec::new();
+                while !fields.data.is_empty() {
+                    filters.push(fields.string());
+                    fields.u8(); // Requested QoS
+                }
+                let mut suback = packet_id.to_be_bytes().to_vec();
+                suback.extend(filters.iter().map(|_| 0u8));
+                send(encode_packet(0x90, &suback));
+                let mut state = state.lock().unwrap();
+                let retained: Vec<Vec<u8>> = state
+                    .retained
+                    .iter()
+                    .filter(|(topic, _)| filters.iter().any(|filter| matches(filter, topic)))
+                    .map(|(topic, payload)| encode_publish(topic, payload, true))
+                    .collect();
+                if let Some(session) = state.sessions.get_mut(&id) {
+                    session.filters.extend(filters);
+                }
+                drop(state);
+                for packet in retained {
+                    send(packet);
+                }
+            }
+            PINGREQ => send(encode_packet(0xD0, &[])),
+            DISCONNECT => {
+                clean = true;
+                break;
+            }
+            _ => {}
+        }
+    }
+
+    let mut state = state.lock().unwrap();
+    // Stopped by the broker itself, nobody left to notify
+    if state.sessions.remove(&id).is_none() {
+        return;
+    }
+    if let (false, Some(will)) = (clean, will) {
+        state.publish(will);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{
+        io::Write,
+        net::{Shutdown, TcpStream},
+    };
+
+    use super::{encode_packet, encode_publish, matches, read_packet, Broker};
+
+    fn connect(port: u16, will: Option<(&str, &[u8])>) -> TcpStream {
+        let mut body = vec![0, 4, b'M', b'Q', b'T', b'T', 4];
+        body.push(if will.is_some() { 0x24 } else { 0x00 });
+        body.extend_from_slice(&[0, 30, 0, 1, b'c']);
+        if let Some((topic, payload)) = will {
+            body.extend_from_slice(&(topic.len() as u16).to_be_bytes());
+            body.extend_from_slice(topic.as_bytes());
+            body.extend_from_slice(&(payload.len() as u16).to_be_bytes());
+            body.extend_from_slice(payload);
+        }
+        let mut stream = TcpStream::connect(("127.0.0.1", port)).unwrap();
+        stream.write_all(&encode_packet(0x10, &body)).unwrap();
+        assert_eq!(read_packet(&mut stream).unwrap(), (0x20, vec![0, 0]));
+        stream
+    }
+
+    fn subscribe(stream: &mut TcpStream, filter: &str) {
+        let mut body = vec![0, 1];
+        body.extend_from_slice(&(filter.len() as u16).to_be_bytes());
+        body.extend_from_slice(filter.as_bytes());
+        body.push(2);
+        stream.write_all(&encode_packet(0x82, &body)).unwrap();
+        assert_eq!(read_packet(stream).unwrap(), (0x90, vec![0, 1, 0]));
+    }
+
+    #[test]
+    fn test_publish() {
+        let broker = Broker::start(0);
+        let mut subscriber = connect(broker.port(), None);
+        let mut publisher = connect(broker.port(), Some(("cOS/available", b"0")));
+        // Retained before the subscription (acknowledged, to be sure it is stored)
+        let mut body = vec![0, 13];
+        body.extend_from_slice(b"cOS/available");
+        body.extend_from_slice(&[0x00, 0x01, b'1']);
+        publisher.write_all(&encode_packet(0x33, &body)).unwrap();
+        assert_eq!(read_packet(&mut publisher).unwrap(), (0x40, vec![0x00, 0x01]));
+        subscribe(&mut subscriber, "cOS/#");
+        assert_eq!(
+            read_packet(&mut subscriber).unwrap(),
+            read_packet(&mut &encode_publish("cOS/available", b"1", true)[..]).unwrap()
+        );
+        // QoS 1, acknowledged
+        let mut body = vec![0, 8];
+        body.extend_from_slice(b"cOS/5/in");
+        body.extend_from_slice(&[0x12, 0x34, 0xAB]);
+        publisher.write_all(&encode_packet(0x32, &body)).unwrap();
+        assert_eq!(read_packet(&mut publisher).unwrap(), (0x40, vec![0x12, 0x34]));
+        assert_eq!(
+            read_packet(&mut subscriber).unwrap(),
+            read_packet(&mut &encode_publish("cOS/5/in", &[0xAB], false)[..]).unwrap()
+        );
+        // Lost connection
+        publisher.shutdown(Shutdown::Both).unwrap();
+        assert_eq!(
+            read_packet(&mut subscriber).unwrap(),
+            read_packet(&mut &encode_publish("cOS/available", b"0", false)[..]).unwrap()
+        );
+        broker.stop();
+    }
+
+    #[test]
+    fn test_matches() {
+        assert!(matches("cOS/+/in", "cOS/5/in"));
+        assert!(!matches("cOS/+/in", "cOS/5/out"));
+        assert!(!matches("cOS/+/in", "cOS/5/in/x"));
+        assert!(matches("cOS/#", "cOS/available"));
+        assert!(matches("cOS/available", "cOS/available"));
+        assert!(!matches("cOS/available", "cOS"));
+    }
+}
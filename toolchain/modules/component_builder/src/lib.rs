@@ -5,22 +5,50 @@
 use board_config::BoardConfig;
 use cargo_metadata::MetadataCommand;
 use component_config::{
-    read_component_extended_config,
-    structures::{Component, ComponentConfig, Interrupt, Region, RegionAttribute},
-    write_component_config,
+    expand_placeholders, merge_config, read_component_extended_config,
+    structures::{
+        Component, ComponentConfig, ComponentExtendedConfig, ConfigTable, Interrupt, Region,
+        RegionAttribute,
+    },
+    write_component_config, write_instance_config,
 };
+use log_strings::LogStringTable;
 use regex::Regex;
 use std::{
     path::{Path, PathBuf},
     process::Command,
 };
 
+/// Settings of one instance of a component, given by the App.toml
+#[derive(Default)]
+pub struct Instance {
+    /// Name of the instance, every instance after the first one needs it
+    pub name: Option<String>,
+    /// Replaces the id of the Component.toml
+    pub id: Option<u16>,
+    /// Replaces the defaults in the [config] of the Component.toml
+    pub config: Option<ConfigTable>,
+}
+
+/// Build directory of the component, one for each board and instance
+pub fn build_path(component_path: &Path, target_board: &str, instance: &Instance) -> PathBuf {
+    let mut component_build_path = PathBuf::from(component_path);
+    component_build_path.push("build");
+    match &instance.name {
+        Some(name) => component_build_path.push(format!("{}-{}", target_board, name)),
+        None => component_build_path.push(target_board),
+    }
+    component_build_path
+}
+
 fn build_component(
     component_name: &String,
     component_path: &PathBuf,
     component_build_path: &PathBuf,
+    instance_config_path: &PathBuf,
     target: &String,
     features: &Vec<String>,
+    component_id: u16,
     verbose: bool,
 ) -> Result<PathBuf, ()> {
     println!("Building into '{}'", component_build_path.display());
@@ -53,6 +81,10 @@ fn build_component(
     );
     cmd.env("CARGO_TARGET_DIR", &component_build_path); // Location of where to place all generated artifacts, relative to the current working directory.
     cmd.env("CARGO_BUILD_TARGET", target);
+    // Used by the deferred logs to tag the frames
+    cmd.env("CONCEPT_OS_COMPONENT_ID", component_id.to_string());
+    // Read by the build script of the component
+    cmd.env(component_config::CONFIG_ENV, instance_config_path);
     // Launch build
     let status = cmd.status();
     if !status.is_ok() {
@@ -101,6 +133,24 @@ fn compute_relocations(
     Ok(reloc_path)
 }
 
+/// Extracts the format strings of the deferred logs, to decode them on the host
+fn extract_log_strings(
+    component_build_path: &PathBuf,
+    artifact_path: &PathBuf,
+    component_id: u16,
+    verbose: bool,
+) -> Result<PathBuf, ()> {
+    let elf_bytes = std::fs::read(artifact_path).map_err(|_| ())?;
+    let table = LogStringTable::from_elf(component_id, &elf_bytes).map_err(|_| ())?;
+    if verbose {
+        println!("Found {} log strings", table.strings.len());
+    }
+    let mut table_path = component_build_path.clone();
+    table_path.push(log_strings::TABLE_FILE);
+    log_strings::write_table(table_path.to_str().unwrap(), &table).map_err(|_| ())?;
+    Ok(table_path)
+}
+
 fn assemble_cbf(
     root_path: &Path,
     config_path: &PathBuf,
@@ -134,12 +184,11 @@ fn assemble_cbf(
     Ok(())
 }
 
-fn process_component_config(
-    component_path: String,
-    component_build_path: &PathBuf,
-    board_config: &BoardConfig,
-) -> PathBuf {
-    // Read the extended config
+/// Reads the Component.toml, with the settings of the instance over the defaults
+fn read_instance(
+    component_path: &Path,
+    instance: &Instance,
+) -> (ComponentExtendedConfig, ConfigTable) {
     let mut config_path = PathBuf::from(component_path);
     config_path.push("Component.toml");
     if !config_path.exists() {
@@ -147,15 +196,48 @@ fn process_component_config(
     }
     let config = read_component_extended_config(config_path.to_str().unwrap())
         .expect("Cannot read the component descriptor 'Component.toml'");
+    let mut instance_config = config.config.clone().unwrap_or_default();
+    if let Some(overrides) = &instance.config {
+        merge_config(&mut instance_config, overrides);
+    }
+    (config, instance_config)
+}
+
+fn expand(name: &String, instance_config: &ConfigTable) -> String {
+    expand_placeholders(name, instance_config)
+        .unwrap_or_else(|e| panic!("Cannot expand '{}': {}", name, e))
+}
+
+/// DMA channels claimed by an instance of the component, as "dma2.6"
+pub fn dma_channels(component_path: &Path, instance: &Instance) -> Vec<String> {
+    let (config, instance_config) = read_instance(component_path, instance);
+    config
+        .component
+        .dma_channels
+        .unwrap_or_default()
+        .iter()
+        .map(|name| expand(name, &instance_config))
+        .collect()
+}
+
+fn process_component_config(
+    component_path: String,
+    component_build_path: &PathBuf,
+    board_config: &BoardConfig,
+    instance: &Instance,
+) -> (PathBuf, PathBuf, u16) {
+    // Read the extended config, with the settings of this instance
+    let (config, instance_config) = read_instance(Path::new(&component_path), instance);
+    let expand = |name: &String| expand(name, &instance_config);
 
     // Generate regions
     let mut component_regions = Vec::<Region>::new();
     if let Some(peripherals) = &config.component.peripherals {
-        for peripheral in peripherals {
+        for peripheral in peripherals.iter().map(expand) {
             // Search the peripheral in the board config to estract the regions
             let p = board_config
                 .peripheral
-                .get(peripheral)
+                .get(&peripheral)
                 .expect(&format!("Cannot find peripheral {}", peripheral));
             component_regions.push(Region {
                 base_address: p.base_address,
@@ -180,7 +262,8 @@ fn process_component_config(
     if let Some(interrupts) = &config.component.interrupts {
         for (peripheral_interrupt, mask) in interrupts {
             // Get the peripheral
-            let res = p_name_regex.captures(peripheral_interrupt).unwrap();
+            let peripheral_interrupt = expand(peripheral_interrupt);
+            let res = p_name_regex.captures(&peripheral_interrupt).unwrap();
             let peripheral_name = res.get(1).unwrap().as_str().to_string();
             let irq_name = res.get(2).unwrap().as_str().to_string();
             // Search the peripheral in the board config to estract the regions
@@ -208,7 +291,7 @@ fn process_component_config(
     // Construct a new component config
     let new_config = ComponentConfig {
         component: Component {
-            id: config.component.id,
+            id: instance.id.unwrap_or(config.component.id),
             version: config.component.version,
             priority: config.component.priority,
             flags: config.component.flags,
@@ -228,7 +311,15 @@ fn process_component_config(
     let mut config_simple_path = component_build_path.clone();
     config_simple_path.push("Component.toml");
     write_component_config(config_simple_path.clone().to_str().unwrap(), &new_config).unwrap();
-    return config_simple_path;
+    // Generate the settings file
+    let mut instance_config_path = component_build_path.clone();
+    instance_config_path.push("config.toml");
+    write_instance_config(instance_config_path.to_str().unwrap(), &instance_config).unwrap();
+    return (
+        config_simple_path,
+        instance_config_path,
+        new_config.component.id,
+    );
 }
 
 pub fn build_process(
@@ -236,14 +327,13 @@ pub fn build_process(
     cbf_output_path: String,
     target_board: String,
     features: &Vec<String>,
+    instance: &Instance,
     verbose: bool,
     clean_up: bool,
 ) -> Result<(), ()> {
     // Init paths
     let component_path_buf = PathBuf::from(component_path.clone());
-    let mut component_build_path = component_path_buf.clone();
-    component_build_path.push("build");
-    component_build_path.push(&target_board);
+    let component_build_path = build_path(&component_path_buf, &target_board, instance);
     // Generate build dir if not exists
     if !component_build_path.exists() {
         if std::fs::create_dir_all(&component_build_path).is_err() {
@@ -313,7 +403,12 @@ MEMORY
     }
 
     // Process the component config to obtain the simplified version
-    let config_path = process_component_config(component_path.clone(), &component_build_path, &board_config);
+    let (config_path, instance_config_path, component_id) = process_component_config(
+        component_path.clone(),
+        &component_build_path,
+        &board_config,
+        instance,
+    );
 
     // Add the board to the features
     let mut feature_list = features.clone();
@@ -323,10 +418,14 @@ MEMORY
         &component_name,
         &component_path_buf,
         &component_build_path,
+        &instance_config_path,
         &board_config.board.target,
         &feature_list,
+        component_id,
         verbose,
     )?;
+    // Extract the string table of the logs
+    extract_log_strings(&component_build_path, &artifact_path, component_id, verbose)?;
     // Compute relocations
     let relocations_path =
         compute_relocations(&component_build_path, root_path, &artifact_path, verbose)?;
@@ -356,7 +455,7 @@ MEMORY
 mod test {
     use std::path::PathBuf;
 
-    use crate::build_process;
+    use crate::{build_process, Instance};
 
     fn get_test_file_path(name: &str) -> String {
         let mut d = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
@@ -375,6 +474,7 @@ mod test {
             get_test_file_path("component1/component1.cbf"),
             "stm32l432kc".to_string(),
             &Vec::<String>::new(),
+            &Instance::default(),
             false,
             true,
         )
@@ -43,6 +43,7 @@ fn process_args() -> i32 {
         args.cbf_output_path,
         args.target_board,
         &args.features,
+        &component_builder::Instance::default(),
         args.verbose,
         args.clean_up,
     )
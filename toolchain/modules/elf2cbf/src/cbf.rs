@@ -46,7 +46,11 @@ bitflags::bitflags! {
     pub struct ComponentFlags: u16 {
         const NONE = 0;
         const START_AT_BOOT = 1 << 0;
-        const RESERVED = !1;
+        /// The payload in the file is compressed (heatshrink), while the header,
+        /// the sizes and the checksum refer to the uncompressed image.
+        /// Once stored in flash, the payload is always uncompressed.
+        const COMPRESSED = 1 << 1;
+        const RESERVED = !0b11;
     }
 }
 bitflags::bitflags! {
@@ -839,6 +843,11 @@ impl CbfFile {
         self.header.main.entry_point_offset += 1; // OR 1: ARM Thumb Mode
     }
 
+    /// Emit the payload compressed
+    pub fn set_compressed(&mut self) {
+        self.header.main.component_flags |= ComponentFlags::COMPRESSED;
+    }
+
     pub fn add_data(&mut self, data_section: Option<&ElfSection>, bss_size: u32) {
         let mut data_size = 0u32;
         // Append section data
@@ -949,7 +958,18 @@ impl CbfFile {
         wordbuf[2] = ((checksum >> 16) & 0xFF) as u8;
         wordbuf[3] = ((checksum >> 24) & 0xFF) as u8;
         bytes_cur.write_all(&wordbuf)?;
-        return Ok(bytes_cur.into_inner());
+        let bytes = bytes_cur.into_inner();
+        if !self.header.main.component_flags.contains(ComponentFlags::COMPRESSED) {
+            return Ok(bytes);
+        }
+        // Replace the payload with the compressed one: header and checksum
+        // stay the ones of the uncompressed image
+        let payload_start = self.header.size() as usize;
+        let payload_end = self.header.base.trailer_offset as usize;
+        let mut compressed = bytes[0..payload_start].to_vec();
+        heatshrink::compress(&bytes[payload_start..payload_end], |b| compressed.push(b));
+        compressed.extend_from_slice(&bytes[payload_end..]);
+        return Ok(compressed);
     }
 }
 
@@ -15,7 +15,8 @@ use clap::Parser;
 pub fn generate_cbf(
     component_config_file: &str, 
     component_elf_file: &str,
-    component_relocations_file: &str
+    component_relocations_file: &str,
+    compress: bool
 ) -> Result<Vec<u8>, Box<dyn Error>> {
     // Parse component config
     let component_config: ComponentConfig = component_config::read_component_config(component_config_file)?;
@@ -41,6 +42,9 @@ pub fn generate_cbf(
         (&elf_result.data_section).as_ref(), 
         elf_result.bss_size
     );
+    if compress {
+        component_cbf.set_compressed();
+    }
     // Generate
     let cbf_bytes = component_cbf.generate()?;
     return Ok(cbf_bytes);
@@ -61,7 +65,11 @@ struct Arguments {
     component_relocations_file: String,
     #[clap(short, long)]
     #[clap(short = 'o')]
-    cbf_output_path: String
+    cbf_output_path: String,
+    /// Compress the payload, the update component decompresses it while receiving
+    #[clap(short, long)]
+    #[clap(short = 'z')]
+    compress: bool
 }
 
 fn process_args() -> i32 {
@@ -69,7 +77,8 @@ fn process_args() -> i32 {
     let cbf = generate_cbf(
         &args.component_config_file,
         &args.component_elf_file,
-        &args.component_relocations_file
+        &args.component_relocations_file,
+        args.compress
     );
     if cbf.is_err() {
         println!("Error: \n{}", cbf.unwrap_err());
@@ -240,7 +249,8 @@ mod test {
         let cbf = generate_cbf(
             &component_config_file,
             &component_elf_file,
-            &component_relocations_file
+            &component_relocations_file,
+            false
         ).unwrap();
         std::fs::write(cbf_out_file, &cbf).unwrap();
         // Parse back the cbf, and compare
@@ -277,7 +287,8 @@ mod test {
         let cbf = generate_cbf(
             &component_config_file,
             &component_elf_file,
-            &component_relocations_file
+            &component_relocations_file,
+            false
         ).unwrap();
         std::fs::write(cbf_out_file, &cbf).unwrap();
         // Parse back the cbf, and compare
@@ -314,7 +325,8 @@ mod test {
         let cbf = generate_cbf(
             &component_config_file,
             &component_elf_file,
-            &component_relocations_file
+            &component_relocations_file,
+            false
         ).unwrap();
         std::fs::write(cbf_out_file, &cbf).unwrap();
         // Parse back the cbf, and compare
@@ -351,7 +363,8 @@ mod test {
         let cbf = generate_cbf(
             &component_config_file,
             &component_elf_file,
-            &component_relocations_file
+            &component_relocations_file,
+            false
         ).unwrap();
         std::fs::write(cbf_out_file, &cbf).unwrap();
         // Parse back the cbf, and compare
@@ -379,4 +392,40 @@ mod test {
         let bss_size = bss_size(&component_elf_file).unwrap();
         assert_eq!(bss_size, parsed_cbf.bss_size());
     }
-}
\ No newline at end of file
+    #[test]
+    fn gen_compressed() {
+        let component_config_file = get_test_file_path("component1/Component.toml");
+        let component_elf_file = get_test_file_path("component1/output/image.elf");
+        let component_relocations_file = get_test_file_path("component1/output/relocations.toml");
+        let cbf = generate_cbf(
+            &component_config_file,
+            &component_elf_file,
+            &component_relocations_file,
+            false
+        ).unwrap();
+        let compressed = generate_cbf(
+            &component_config_file,
+            &component_elf_file,
+            &component_relocations_file,
+            true
+        ).unwrap();
+        assert!(compressed.len() < cbf.len());
+        // Expand the payload back
+        let header = cbf_rs::parse_cbf(&compressed).unwrap();
+        assert!(header.header_main().component_flags().contains(CBF_CF::COMPRESSED));
+        let payload_start = header.read_only_section().offset() as usize;
+        let payload_size = header.payload_size() as usize;
+        let mut expanded = compressed[0..payload_start].to_vec();
+        let mut decoder = heatshrink::Decoder::new(payload_size);
+        decoder.sink(&compressed[payload_start..compressed.len() - 4], |b| -> Result<(), heatshrink::DecoderError> {
+            expanded.push(b);
+            Ok(())
+        }).unwrap();
+        assert!(decoder.is_complete());
+        expanded.extend_from_slice(&compressed[compressed.len() - 4..]);
+        // Same content of the uncompressed CBF, checksum computed with the flag
+        let parsed_cbf = cbf_rs::parse_cbf(&expanded).unwrap();
+        assert!(parsed_cbf.validate());
+        assert_eq!(expanded[payload_start..], cbf[payload_start..]);
+    }
+}
@@ -0,0 +1,168 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+/**
+ * Packets of the uart-channel, when the multi-support feature is enabled:
+ *   +----------+------------+--------+------+-------+
+ *   | Preamble | Channel ID | Length | Data | CRC-8 |
+ *   +----------+------------+--------+------+-------+
+ *   | 4 bytes  | 2 bytes    | 2 bytes| len  | 1 byte|
+ *   +----------+------------+--------+------+-------+
+ * Channel ID and Length are big endian, the CRC covers everything but the preamble.
+ */
+const PREAMBLE_BYTE: u8 = 0xAA;
+const PREAMBLE_LEN: usize = 4;
+const HEADER_LEN: usize = 4;
+
+/// Optimized Dallas (now Maxim) iButton 8-bit CRC calculation.
+/// Polynomial: x^8 + x^5 + x^4 + 1 (0x8C)
+/// Initial value: 0x0
+pub fn crc8_update(crc: &mut u8, byte: u8) {
+    let mut tmp = (*crc) ^ byte;
+    for _ in 0..8u8 {
+        if tmp & 0x01 == 1 {
+            tmp = (tmp >> 1) ^ 0x8C;
+        } else {
+            tmp >>= 1;
+        }
+    }
+    *crc = tmp;
+}
+
+/// Wraps the data for the channel in a packet
+pub fn encode(channel_id: u16, data: &[u8]) -> Vec<u8> {
+    let mut packet = Vec::with_capacity(PREAMBLE_LEN + HEADER_LEN + data.len() + 1);
+    packet.extend_from_slice(&[PREAMBLE_BYTE; PREAMBLE_LEN]);
+    packet.extend_from_slice(&channel_id.to_be_bytes());
+    packet.extend_from_slice(&(data.len() as u16).to_be_bytes());
+    packet.extend_from_slice(data);
+    let mut crc: u8 = 0;
+    for b in &packet[PREAMBLE_LEN..] {
+        crc8_update(&mut crc, *b);
+    }
+    packet.push(crc);
+    return packet;
+}
+
+/// Same state machine of rx_update_caller in the uart-channel
+pub struct Decoder {
+    preamble: usize,
+    header: Vec<u8>,
+    data: Vec<u8>,
+    len: usize,
+    crc: u8,
+    discarded: usize,
+}
+
+impl Decoder {
+    pub fn new() -> Self {
+        Self {
+            preamble: 0,
+            header: Vec::new(),
+            data: Vec::new(),
+            len: 0,
+            crc: 0,
+            discarded: 0,
+        }
+    }
+
+    fn reset(&mut self) {
+        self.preamble = 0;
+        self.header.clear();
+        self.data.clear();
+        self.len = 0;
+        self.crc = 0;
+    }
+
+    /// Packets dropped for a wrong CRC
+    pub fn discarded(&self) -> usize {
+        self.discarded
+    }
+
+    /// Returns the channel and the data of the packet completed by this byte, if valid
+    pub fn push(&mut self, byte: u8) -> Option<(u16, Vec<u8>)> {
+        // Synchronize on the preamble
+        if self.preamble < PREAMBLE_LEN {
+            if byte == PREAMBLE_BYTE {
+                self.preamble += 1;
+            } else {
+                self.preamble = 0;
+            }
+            return None;
+        }
+        // Header
+        if self.header.len() < HEADER_LEN {
+            crc8_update(&mut self.crc, byte);
+            self.header.push(byte);
+            if self.header.len() == HEADER_LEN {
+                self.len = u16::from_be_bytes([self.header[2], self.header[3]]) as usize;
+            }
+            return None;
+        }
+        // Data
+        if self.data.len() < self.len {
+            crc8_update(&mut self.crc, byte);
+            self.data.push(byte);
+            return None;
+        }
+        // CRC
+        let channel_id = u16::from_be_bytes([self.header[0], self.header[1]]);
+        let result = match byte == self.crc {
+            true => Some((channel_id, std::mem::take(&mut self.data))),
+            false => {
+                self.discarded += 1;
+                None
+            }
+        };
+        self.reset();
+        return result;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{encode, Decoder};
+
+    fn decode_all(decoder: &mut Decoder, bytes: &[u8]) -> Vec<(u16, Vec<u8>)> {
+        return bytes.iter().filter_map(|b| decoder.push(*b)).collect();
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let mut decoder = Decoder::new();
+        let mut stream = vec![0x01, 0xAA, 0x02];
+        stream.extend(encode(5, &[0x12, 0x34, 0xAA]));
+        stream.extend(encode(6, &[]));
+        stream.extend(encode(0x0102, &[0xAA; 300]));
+        assert_eq!(
+            decode_all(&mut decoder, &stream),
+            vec![
+                (5, vec![0x12, 0x34, 0xAA]),
+                (6, vec![]),
+                (0x0102, vec![0xAA; 300])
+            ]
+        );
+        assert_eq!(decoder.discarded(), 0);
+    }
+
+    #[test]
+    fn test_known_packet() {
+        // CRC-8/MAXIM of 00 06 00 02 12 34
+        assert_eq!(
+            encode(6, &[0x12, 0x34]),
+            vec![0xAA, 0xAA, 0xAA, 0xAA, 0x00, 0x06, 0x00, 0x02, 0x12, 0x34, 0x71]
+        );
+    }
+
+    #[test]
+    fn test_corrupted_packet() {
+        let mut decoder = Decoder::new();
+        let mut corrupted = encode(5, &[0x12, 0x34]);
+        corrupted[9] ^= 0xFF;
+        let mut stream = corrupted;
+        stream.extend(encode(5, &[0x56]));
+        assert_eq!(decode_all(&mut decoder, &stream), vec![(5, vec![0x56])]);
+        assert_eq!(decoder.discarded(), 1);
+    }
+}
@@ -0,0 +1,194 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use std::{
+    fmt::Arguments,
+    fs::{self, File, OpenOptions},
+    io::Write,
+    str::FromStr,
+    sync::{Mutex, OnceLock},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use crate::settings::LoggerSettings;
+
+/**
+ * Minimal logger, with the levels (and the settings) of loguru used by the former Python adapter.
+ * Logs go to stderr and, if configured, to a file rotated when it exceeds max_size.
+ */
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Level {
+    Debug,
+    Info,
+    Success,
+    Warning,
+    Error,
+}
+
+impl FromStr for Level {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_uppercase().as_str() {
+            "DEBUG" => Ok(Level::Debug),
+            "INFO" => Ok(Level::Info),
+            "SUCCESS" => Ok(Level::Success),
+            "WARNING" => Ok(Level::Warning),
+            "ERROR" => Ok(Level::Error),
+            _ => Err(format!("unknown log level '{}'", s)),
+        }
+    }
+}
+
+impl Level {
+    fn name(&self) -> &'static str {
+        match self {
+            Level::Debug => "DEBUG",
+            Level::Info => "INFO",
+            Level::Success => "SUCCESS",
+            Level::Warning => "WARNING",
+            Level::Error => "ERROR",
+        }
+    }
+}
+
+struct LogFile {
+    path: String,
+    max_size: Option<u64>,
+    file: File,
+    size: u64,
+}
+
+struct Logger {
+    level: Level,
+    file: Option<Mutex<LogFile>>,
+}
+
+/// None when the logger is disabled
+static LOGGER: OnceLock<Option<Logger>> = OnceLock::new();
+
+/// Parses sizes like "500 Mb" or "10 KB"
+fn parse_size(size: &str) -> Result<u64, String> {
+    let size = size.trim();
+    let split = size
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(size.len());
+    let value: u64 = size[..split]
+        .parse()
+        .map_err(|_| format!("invalid size '{}'", size))?;
+    let multiplier = match size[split..].trim().to_uppercase().as_str() {
+        "" | "B" => 1,
+        "KB" => 1 << 10,
+        "MB" => 1 << 20,
+        "GB" => 1 << 30,
+        _ => return Err(format!("invalid size '{}'", size)),
+    };
+    return Ok(value * multiplier);
+}
+
+fn open_log(path: &str) -> std::io::Result<(File, u64)> {
+    let file = OpenOptions::new().create(true).append(true).open(path)?;
+    let size = file.metadata()?.len();
+    return Ok((file, size));
+}
+
+/// Configures the logger, only the first call is effective
+pub fn init(settings: &LoggerSettings) -> Result<(), String> {
+    let logger = match settings.enable {
+        false => None,
+        true => {
+            let level = Level::from_str(&settings.level)?;
+            let file = match &settings.file {
+                None => None,
+                Some(path) => {
+                    let max_size = settings.max_size.as_deref().map(parse_size).transpose()?;
+                    let (file, size) = open_log(path)
+                        .map_err(|e| format!("cannot open the log file '{}': {}", path, e))?;
+                    Some(Mutex::new(LogFile {
+                        path: path.clone(),
+                        max_size,
+                        file,
+                        size,
+                    }))
+                }
+            };
+            Some(Logger { level, file })
+        }
+    };
+    let _ = LOGGER.set(logger);
+    return Ok(());
+}
+
+pub fn log(level: Level, args: Arguments) {
+    // Without init (i.e. in tests) everything goes to stderr
+    let logger = match LOGGER.get() {
+        Some(Some(logger)) => Some(logger),
+        Some(None) => return,
+        None => None,
+    };
+    if logger.map_or(false, |logger| level < logger.level) {
+        return;
+    }
+    let timestamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default();
+    let line = format!(
+        "{}.{:03} | {:<7} | {}\n",
+        timestamp.as_secs(),
+        timestamp.subsec_millis(),
+        level.name(),
+        args
+    );
+    eprint!("{}", line);
+    if let Some(Some(log_file)) = logger.map(|logger| &logger.file) {
+        let mut log_file = log_file.lock().unwrap();
+        if log_file
+            .max_size
+            .map_or(false, |max| log_file.size + line.len() as u64 > max)
+        {
+            // Rotate, keeping only the previous file
+            let rotated = format!("{}.1", log_file.path);
+            let _ = fs::rename(&log_file.path, rotated);
+            if let Ok((file, size)) = open_log(&log_file.path) {
+                log_file.file = file;
+                log_file.size = size;
+            }
+        }
+        if log_file.file.write_all(line.as_bytes()).is_ok() {
+            log_file.size += line.len() as u64;
+        }
+    }
+}
+
+macro_rules! debug {
+    ($($arg:tt)*) => { $crate::logger::log($crate::logger::Level::Debug, format_args!($($arg)*)) };
+}
+
+macro_rules! info {
+    ($($arg:tt)*) => { $crate::logger::log($crate::logger::Level::Info, format_args!($($arg)*)) };
+}
+
+macro_rules! warning {
+    ($($arg:tt)*) => { $crate::logger::log($crate::logger::Level::Warning, format_args!($($arg)*)) };
+}
+
+macro_rules! error {
+    ($($arg:tt)*) => { $crate::logger::log($crate::logger::Level::Error, format_args!($($arg)*)) };
+}
+
+pub(crate) use {debug, error, info, warning};
+
+#[cfg(test)]
+mod test {
+    use super::parse_size;
+
+    #[test]
+    fn test_parse_size() {
+        assert_eq!(parse_size("500 Mb"), Ok(500 << 20));
+        assert_eq!(parse_size("10KB"), Ok(10 << 10));
+        assert_eq!(parse_size("42"), Ok(42));
+        assert!(parse_size("a lot").is_err());
+    }
+}
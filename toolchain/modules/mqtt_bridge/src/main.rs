@@ -0,0 +1,252 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+mod codec;
+mod logger;
+mod mqtt;
+mod serial;
+mod settings;
+
+use std::{
+    process,
+    thread::{self, JoinHandle},
+    time::Duration,
+};
+
+use clap::Parser;
+use logger::{error, info, warning};
+use mqtt::mqtt_loop;
+use serial::serial_loop;
+use settings::Settings;
+
+/// Channel ID and data of a packet of the uart-channel
+pub type Packet = (u16, Vec<u8>);
+
+/// Wait before opening again a lost serial port or broker connection
+pub const RECONNECT_DELAY: Duration = Duration::from_secs(1);
+/// Packets waiting in each direction
+const QUEUE_LEN: usize = 1000;
+
+/**
+ * Command line arguments
+ */
+
+#[derive(Parser)]
+#[clap(name = "Concept-OS Serial to MQTT Bridge")]
+#[clap(version)]
+#[clap(about = "Publishes each channel of the uart-channel on <root>/<id>/in|out.", long_about = None)]
+struct Cli {
+    /// Specify a custom config path
+    #[clap(short, long, value_parser)]
+    #[clap(short = 'c')]
+    #[clap(default_value = "settings.yaml")]
+    config: String,
+}
+
+/// Starts the serial and MQTT sides, they run until one of them stops
+pub fn bridge_start(settings: Settings) -> (JoinHandle<()>, JoinHandle<()>) {
+    let (serial_producer, serial_consumer) = crossbeam_channel::bounded::<Packet>(QUEUE_LEN);
+    let (mqtt_producer, mqtt_consumer) = crossbeam_channel::bounded::<Packet>(QUEUE_LEN);
+    let serial_settings = settings.serial;
+    let mqtt_settings = settings.mqtt;
+    let serial_handle =
+        thread::spawn(move || serial_loop(serial_settings, mqtt_producer, serial_consumer));
+    let mqtt_handle =
+        thread::spawn(move || mqtt_loop(mqtt_settings, serial_producer, mqtt_consumer));
+    return (serial_handle, mqtt_handle);
+}
+
+fn main() {
+    let args = Cli::parse();
+
+    let settings = match Settings::load(&args.config) {
+        Ok(settings) => settings,
+        Err(e) => {
+            eprintln!("Cannot load settings file '{}': {}", args.config, e);
+            process::exit(-1);
+        }
+    };
+    if let Err(e) = logger::init(&settings.logger) {
+        logger::init(&Default::default()).unwrap();
+        warning!(
+            "Invalid logger configuration ({}), fallback logger is used",
+            e
+        );
+    }
+
+    let (serial_handle, mqtt_handle) = bridge_start(settings);
+    info!("Loading completed");
+    serial_handle.join().unwrap();
+    mqtt_handle.join().unwrap();
+    error!("Bridge stopped");
+}
+
+#[cfg(test)]
+mod test {
+    use std::{
+        env, fs,
+        io::{Read, Write},
+        os::unix::fs::symlink,
+        path::Path,
+        process, thread,
+        time::{Duration, Instant},
+    };
+
+    use crossbeam_channel::Receiver;
+    use rumqttc::{Client, Event, MqttOptions, Packet as MqttPacket, QoS};
+    use serialport::{SerialPort, TTYPort};
+
+    use crate::{
+        bridge_start,
+        broker::Broker,
+        codec::{encode, Decoder},
+        settings::Settings,
+    };
+
+    const TIMEOUT: Duration = Duration::from_secs(10);
+
+    /// Emulated device, on a pseudo-terminal reachable through a symlink like /dev/serial/by-id
+    struct Device {
+        master: TTYPort,
+        _slave: TTYPort,
+        decoder: Decoder,
+    }
+
+    impl Device {
+        fn plug(link: &Path) -> Self {
+            let (mut master, slave) = TTYPort::pair().unwrap();
+            let path = slave.name().unwrap();
+            drop(slave);
+            // Keeps the slave open in raw mode, but not exclusive, for the bridge to open it
+            let mut slave = serialport::new(&path, 115_200).open_native().unwrap();
+            slave.set_exclusive(false).unwrap();
+            master.set_timeout(Duration::from_millis(10)).unwrap();
+            let _ = fs::remove_file(link);
+            symlink(&path, link).unwrap();
+            return Self {
+                master,
+                _slave: slave,
+                decoder: Decoder::new(),
+            };
+        }
+
+        fn send(&mut self, channel_id: u16, data: &[u8]) {
+            self.master.write_all(&encode(channel_id, data)).unwrap();
+        }
+
+        fn receive(&mut self, timeout: Duration) -> Option<(u16, Vec<u8>)> {
+            let deadline = Instant::now() + timeout;
+            let mut buffer = [0u8; 64];
+            while Instant::now() < deadline {
+                let len = self.master.read(&mut buffer).unwrap_or(0);
+                for b in &buffer[..len] {
+                    if let Some(packet) = self.decoder.push(*b) {
+                        return Some(packet);
+                    }
+                }
+            }
+            return None;
+        }
+    }
+
+    /// MQTT client on the other side of the bridge
+    struct Observer {
+        client: Client,
+        messages: Receiver<(String, Vec<u8>)>,
+    }
+
+    impl Observer {
+        fn connect(port: u16) -> Self {
+            let options = MqttOptions::new("observer", "127.0.0.1", port);
+            let (mut client, mut connection) = Client::new(options, 10);
+            client.subscribe("cOS/#", QoS::AtMostOnce).unwrap();
+            let (producer, consumer) = crossbeam_channel::unbounded();
+            thread::spawn(move || {
+                for notification in connection.iter() {
+                    match notification {
+                        Ok(Event::Incoming(MqttPacket::Publish(publish))) => {
+                            let _ = producer.send((publish.topic, publish.payload.to_vec()));
+                        }
+                        Ok(_) => {}
+                        Err(_) => return,
+                    }
+                }
+            });
+            return Self {
+                client,
+                messages: consumer,
+            };
+        }
+
+        fn wait(&self, topic: &str, payload: &[u8], timeout: Duration) -> bool {
+            let deadline = Instant::now() + timeout;
+            while let Ok(message) = self.messages.recv_deadline(deadline) {
+                if message.0 == topic && message.1 == payload {
+                    return true;
+                }
+            }
+            return false;
+        }
+    }
+
+    /// Retries until the data goes through the bridge in both directions
+    fn check_link(device: &mut Device, observer: &mut Observer) {
+        let deadline = Instant::now() + TIMEOUT;
+        loop {
+            assert!(Instant::now() < deadline, "Device to MQTT failed");
+            device.send(5, b"from device");
+            if observer.wait("cOS/5/out", b"from device", Duration::from_millis(200)) {
+                break;
+            }
+        }
+        loop {
+            assert!(Instant::now() < deadline, "MQTT to device failed");
+            observer
+                .client
+                .publish("cOS/6/in", QoS::ExactlyOnce, false, b"to device".to_vec())
+                .unwrap();
+            if device.receive(Duration::from_millis(200)) == Some((6, b"to device".to_vec())) {
+                break;
+            }
+        }
+    }
+
+    #[test]
+    fn test_end_to_end() {
+        let dir = env::temp_dir().join(format!("mqtt-bridge-{}", process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let link = dir.join("ttyDevice");
+
+        let broker = Broker::start(0);
+        let port = broker.port();
+        let mut device = Device::plug(&link);
+        let settings = Settings::parse(&format!(
+            "logger:\n  level: INFO\n\
+             serial:\n  port_name: {}\n  baudrate: 115200\n\
+             mqtt:\n  server_ip: 127.0.0.1\n  server_port: {}\n  root_topic: cOS\n",
+            link.display(),
+            port
+        ))
+        .unwrap();
+        bridge_start(settings);
+
+        let mut observer = Observer::connect(port);
+        assert!(observer.wait("cOS/available", b"1", TIMEOUT));
+        check_link(&mut device, &mut observer);
+
+        // The device is unplugged and plugged again
+        drop(device);
+        let mut device = Device::plug(&link);
+        check_link(&mut device, &mut observer);
+
+        // The broker restarts
+        broker.stop();
+        let broker = Broker::start(port);
+        let mut observer = Observer::connect(port);
+        assert!(observer.wait("cOS/available", b"1", TIMEOUT));
+        check_link(&mut device, &mut observer);
+        broker.stop();
+        let _ = fs::remove_dir_all(dir);
+    }
+}
@@ -0,0 +1,147 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use std::{thread, time::Duration};
+
+use crossbeam_channel::{Receiver, Sender, TrySendError};
+use rumqttc::{Client, Event, LastWill, MqttOptions, Packet as MqttPacket, QoS};
+
+use crate::{
+    logger::{debug, error, info, warning},
+    settings::MqttSettings,
+    Packet, RECONNECT_DELAY,
+};
+
+const CLIENT_ID: &str = "concept-os-adapter";
+const KEEP_ALIVE: Duration = Duration::from_secs(30);
+/// Retained on <root>/available
+const ONLINE_PAYLOAD: &str = "1";
+const OFFLINE_PAYLOAD: &str = "0";
+
+/**
+ * Topics, for each channel of the uart-channel:
+ *   <root>/<id>/in   data to send to the device
+ *   <root>/<id>/out  data received from the device
+ */
+
+fn out_topic(root: &str, channel_id: u16) -> String {
+    return format!("{}/{}/out", root, channel_id);
+}
+
+/// Channel of a <root>/<id>/in topic
+fn in_channel(root: &str, topic: &str) -> Option<u16> {
+    let id = topic
+        .strip_prefix(root)?
+        .strip_prefix('/')?
+        .strip_suffix("/in")?;
+    return id.parse().ok();
+}
+
+/// Keeps the connection to the broker, reconnecting when lost, until the serial side is gone
+pub fn mqtt_loop(
+    settings: MqttSettings,
+    serial_producer: Sender<Packet>,
+    mqtt_consumer: Receiver<Packet>,
+) {
+    let root = settings.root_topic.clone();
+    let available_topic = format!("{}/available", root);
+
+    let mut options = MqttOptions::new(CLIENT_ID, settings.server_ip.clone(), settings.server_port);
+    options.set_keep_alive(KEEP_ALIVE);
+    options.set_credentials(settings.username.clone(), settings.password.clone());
+    options.set_last_will(LastWill::new(
+        available_topic.clone(),
+        OFFLINE_PAYLOAD,
+        QoS::ExactlyOnce,
+        true,
+    ));
+    let (mut client, mut connection) = Client::new(options, 10);
+
+    // Publish what comes from the device, the client queues it while offline
+    let mut publisher = client.clone();
+    let publisher_root = root.clone();
+    thread::spawn(move || {
+        for (channel_id, data) in mqtt_consumer.iter() {
+            let topic = out_topic(&publisher_root, channel_id);
+            if publisher
+                .publish(topic, QoS::ExactlyOnce, false, data)
+                .is_err()
+            {
+                warning!("Cannot publish packet to MQTT");
+            }
+        }
+        // The serial side is gone, stop the connection too
+        let _ = publisher.disconnect();
+    });
+
+    let mut online = false;
+    for notification in connection.iter() {
+        match notification {
+            Ok(Event::Incoming(MqttPacket::ConnAck(_))) => {
+                online = true;
+                info!(
+                    "MQTT online on {}:{}",
+                    settings.server_ip, settings.server_port
+                );
+                // The session is clean, so subscriptions are restored at each connection
+                if client
+                    .try_subscribe(format!("{}/+/in", root), QoS::ExactlyOnce)
+                    .is_err()
+                {
+                    error!("Cannot subscribe to MQTT");
+                }
+                if client
+                    .try_publish(
+                        available_topic.clone(),
+                        QoS::ExactlyOnce,
+                        true,
+                        ONLINE_PAYLOAD,
+                    )
+                    .is_err()
+                {
+                    warning!("Cannot publish the availability to MQTT");
+                }
+            }
+            Ok(Event::Incoming(MqttPacket::Publish(publish))) => {
+                let channel_id = match in_channel(&root, &publish.topic) {
+                    Some(channel_id) => channel_id,
+                    None => {
+                        warning!("Invalid topic selected: {}", publish.topic);
+                        continue;
+                    }
+                };
+                debug!("{} bytes for channel {}", publish.payload.len(), channel_id);
+                match serial_producer.try_send((channel_id, publish.payload.to_vec())) {
+                    Ok(()) => {}
+                    Err(TrySendError::Full(_)) => warning!("Serial queue full, packet dropped"),
+                    Err(TrySendError::Disconnected(_)) => return,
+                }
+            }
+            Ok(_) => {}
+            Err(e) => {
+                // The next iteration reconnects
+                if online {
+                    error!("MQTT connection lost: {}", e);
+                    online = false;
+                }
+                thread::sleep(RECONNECT_DELAY);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{in_channel, out_topic};
+
+    #[test]
+    fn test_topics() {
+        assert_eq!(out_topic("cOS", 5), "cOS/5/out");
+        assert_eq!(in_channel("cOS", "cOS/5/in"), Some(5));
+        assert_eq!(in_channel("home/cOS", "home/cOS/65535/in"), Some(65535));
+        assert_eq!(in_channel("cOS", "cOS/5/out"), None);
+        assert_eq!(in_channel("cOS", "cOS/x/in"), None);
+        assert_eq!(in_channel("cOS", "cOSx/5/in"), None);
+    }
+}
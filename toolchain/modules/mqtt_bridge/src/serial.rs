@@ -0,0 +1,99 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use std::{
+    io::{self, Read, Write},
+    thread,
+    time::Duration,
+};
+
+use crossbeam_channel::{Receiver, Sender, TryRecvError, TrySendError};
+use serialport::TTYPort;
+
+use crate::{
+    codec::{encode, Decoder},
+    logger::{error, info, warning},
+    settings::SerialSettings,
+    Packet, RECONNECT_DELAY,
+};
+
+/// How long a read waits for data, before checking the packets from MQTT
+const POLL_TIMEOUT: Duration = Duration::from_millis(5);
+
+/// Keeps the serial port open, reopening it when lost, until the MQTT side is gone
+pub fn serial_loop(
+    settings: SerialSettings,
+    mqtt_producer: Sender<Packet>,
+    serial_consumer: Receiver<Packet>,
+) {
+    let mut reported = false;
+    loop {
+        let port = serialport::new(&settings.port_name, settings.baudrate)
+            .timeout(POLL_TIMEOUT)
+            .open_native();
+        let mut port = match port {
+            Ok(port) => port,
+            Err(e) => {
+                // Report only the first failure, the port may be missing for a while
+                if !reported {
+                    warning!("Cannot open serial port '{}': {}", settings.port_name, e);
+                    reported = true;
+                }
+                thread::sleep(RECONNECT_DELAY);
+                continue;
+            }
+        };
+        reported = false;
+        // Packets queued while the port was closed are stale
+        while serial_consumer.try_recv().is_ok() {}
+        info!("Serial port '{}' online", settings.port_name);
+        match serve(&mut port, &mqtt_producer, &serial_consumer) {
+            Ok(()) => return,
+            Err(e) => error!("Serial port '{}' lost: {}", settings.port_name, e),
+        }
+        drop(port);
+        thread::sleep(RECONNECT_DELAY);
+    }
+}
+
+/// Moves packets until the port fails (Err) or the MQTT side is gone (Ok)
+fn serve(
+    port: &mut TTYPort,
+    mqtt_producer: &Sender<Packet>,
+    serial_consumer: &Receiver<Packet>,
+) -> io::Result<()> {
+    let mut decoder = Decoder::new();
+    let mut buffer = [0u8; 256];
+    loop {
+        // Encoder
+        loop {
+            match serial_consumer.try_recv() {
+                Ok((channel_id, data)) => port.write_all(&encode(channel_id, &data))?,
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) => return Ok(()),
+            }
+        }
+        port.flush()?;
+        // Decoder
+        let len = match port.read(&mut buffer) {
+            Ok(len) => len,
+            Err(e) if e.kind() == io::ErrorKind::TimedOut => 0,
+            Err(e) => return Err(e),
+        };
+        for b in &buffer[..len] {
+            let discarded = decoder.discarded();
+            let packet = decoder.push(*b);
+            if decoder.discarded() != discarded {
+                warning!("Discarding package for wrong CRC");
+            }
+            if let Some(packet) = packet {
+                match mqtt_producer.try_send(packet) {
+                    Ok(()) => {}
+                    Err(TrySendError::Full(_)) => warning!("MQTT queue full, packet dropped"),
+                    Err(TrySendError::Disconnected(_)) => return Ok(()),
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,154 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use std::{fmt::Display, fs, io};
+
+use serde::Deserialize;
+
+/**
+ * Settings of the bridge (settings.yaml), the same of the former Python adapter:
+ *
+ * logger:
+ *   enable: true
+ *   file: "logger.log"
+ *   max_size: "500 Mb"
+ *   level: "DEBUG"
+ * serial:
+ *   port_name: /dev/ttyACM0
+ *   baudrate: 115200
+ * mqtt:
+ *   server_ip: "192.168.0.3"
+ *   server_port: 1883
+ *   username: "mqtt"
+ *   password: "mqtt"
+ *   root_topic: "cOS"
+ */
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Settings {
+    #[serde(default)]
+    pub logger: LoggerSettings,
+    pub serial: SerialSettings,
+    pub mqtt: MqttSettings,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct LoggerSettings {
+    #[serde(default = "default_logger_enable")]
+    pub enable: bool,
+    pub file: Option<String>,
+    pub max_size: Option<String>,
+    #[serde(default = "default_logger_level")]
+    pub level: String,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct SerialSettings {
+    pub port_name: String,
+    #[serde(default = "default_baudrate")]
+    pub baudrate: u32,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct MqttSettings {
+    pub server_ip: String,
+    #[serde(default = "default_server_port")]
+    pub server_port: u16,
+    #[serde(default = "default_credential")]
+    pub username: String,
+    #[serde(default = "default_credential")]
+    pub password: String,
+    pub root_topic: String,
+}
+
+impl Default for LoggerSettings {
+    fn default() -> Self {
+        Self {
+            enable: default_logger_enable(),
+            file: None,
+            max_size: None,
+            level: default_logger_level(),
+        }
+    }
+}
+
+fn default_logger_enable() -> bool {
+    true
+}
+
+fn default_logger_level() -> String {
+    String::from("DEBUG")
+}
+
+fn default_baudrate() -> u32 {
+    9600
+}
+
+fn default_server_port() -> u16 {
+    1883
+}
+
+fn default_credential() -> String {
+    String::from("mqtt")
+}
+
+#[derive(Debug)]
+pub enum SettingsError {
+    Io(io::Error),
+    Invalid(serde_yaml::Error),
+}
+
+impl Display for SettingsError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SettingsError::Io(e) => write!(f, "cannot read the settings: {}", e),
+            SettingsError::Invalid(e) => write!(f, "invalid settings: {}", e),
+        }
+    }
+}
+
+impl Settings {
+    pub fn parse(yaml: &str) -> Result<Self, SettingsError> {
+        return serde_yaml::from_str(yaml).map_err(SettingsError::Invalid);
+    }
+
+    pub fn load(path: &str) -> Result<Self, SettingsError> {
+        let yaml = fs::read_to_string(path).map_err(SettingsError::Io)?;
+        return Self::parse(&yaml);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Settings;
+
+    #[test]
+    fn test_default_file() {
+        // The settings.yaml next to the bridge
+        let settings = Settings::parse(include_str!("../settings.yaml")).unwrap();
+        assert!(settings.logger.enable);
+        assert_eq!(settings.logger.level, "DEBUG");
+        assert_eq!(settings.logger.file, None);
+        assert_eq!(settings.serial.port_name, "/dev/ttyACM0");
+        assert_eq!(settings.serial.baudrate, 115200);
+        assert_eq!(settings.mqtt.server_ip, "192.168.0.3");
+        assert_eq!(settings.mqtt.server_port, 1883);
+        assert_eq!(settings.mqtt.root_topic, "cOS");
+    }
+
+    #[test]
+    fn test_defaults() {
+        let settings = Settings::parse(
+            "serial:\n  port_name: /dev/ttyUSB0\nmqtt:\n  server_ip: localhost\n  root_topic: cOS\n",
+        )
+        .unwrap();
+        assert!(settings.logger.enable);
+        assert_eq!(settings.serial.baudrate, 9600);
+        assert_eq!(settings.mqtt.server_port, 1883);
+        assert_eq!(settings.mqtt.username, "mqtt");
+        assert_eq!(settings.mqtt.password, "mqtt");
+        // Both are required
+        assert!(Settings::parse("serial:\n  port_name: /dev/ttyUSB0\n").is_err());
+    }
+}
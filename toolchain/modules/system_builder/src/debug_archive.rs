@@ -0,0 +1,249 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use goblin::elf::section_header::SHF_ALLOC;
+use serde::Serialize;
+use std::path::PathBuf;
+use std::process::Command;
+
+use crate::elf_editor::{LINKED_FLASH_BASE, LINKED_SRAM_BASE};
+
+/**
+ * Debug archive, a folder placed next to the image containing:
+ *  - kernel.elf
+ *  - component_<id>.elf, with sections moved to where the component was placed
+ *  - component_<id>.log_strings.toml, the string table of the deferred logs
+ *  - App.toml and Board.toml used for the build
+ *  - blocks.json, the map of the allocated flash blocks
+ */
+pub const BLOCK_MAP_FILE: &str = "blocks.json";
+
+#[derive(Serialize, Debug, Clone)]
+pub struct DebugBlockEntry {
+    pub block_type: String,
+    pub component_id: Option<u16>,
+    pub component_version: Option<u32>,
+    pub flash_address: u32,
+    pub flash_size: u32,
+    /// Relocated base of the read-only payload
+    pub text_base: Option<u32>,
+    pub sram_address: Option<u32>,
+    pub sram_size: Option<u32>,
+    /// ELF of the component, as built
+    #[serde(skip)]
+    pub source_elf: Option<PathBuf>,
+    /// Name of the offset ELF in the archive
+    pub elf: Option<String>,
+    /// Name of the string table of the logs in the archive
+    pub log_strings: Option<String>,
+}
+
+#[derive(Serialize, Debug)]
+struct BlockMap<'a> {
+    app: &'a String,
+    board: &'a String,
+    kernel: &'a str,
+    blocks: &'a Vec<DebugBlockEntry>,
+}
+
+pub fn generate(
+    archive_path: &PathBuf,
+    app_name: &String,
+    board_name: &String,
+    kernel_elf: &PathBuf,
+    app_config_path: &PathBuf,
+    board_config_path: &PathBuf,
+    entries: &Vec<DebugBlockEntry>,
+    verbose: bool,
+) {
+    // Start from a clean folder
+    if archive_path.exists() {
+        std::fs::remove_dir_all(archive_path).expect("Cannot clean debug archive");
+    }
+    std::fs::create_dir_all(archive_path).expect("Cannot create debug archive");
+    // Copy the fixed files
+    copy_to_archive(archive_path, kernel_elf, "kernel.elf");
+    copy_to_archive(archive_path, app_config_path, "App.toml");
+    copy_to_archive(archive_path, board_config_path, "Board.toml");
+    // Generate the component ELFs
+    let mut blocks = entries.to_vec();
+    for entry in &mut blocks {
+        if let (Some(source), Some(text_base), Some(sram_base)) =
+            (&entry.source_elf, entry.text_base, entry.sram_address)
+        {
+            let elf_name = format!("component_{}.elf", entry.component_id.unwrap());
+            let mut dest = archive_path.clone();
+            dest.push(&elf_name);
+            offset_component_elf(source, &dest, text_base, sram_base, verbose);
+            entry.elf = Some(elf_name);
+            // The component builder places the string table next to the ELF
+            let table = source.with_file_name(log_strings::TABLE_FILE);
+            if table.exists() {
+                let table_name =
+                    format!("component_{}.log_strings.toml", entry.component_id.unwrap());
+                copy_to_archive(archive_path, &table, &table_name);
+                entry.log_strings = Some(table_name);
+            }
+        }
+    }
+    // Write the block map
+    let map = BlockMap {
+        app: app_name,
+        board: board_name,
+        kernel: "kernel.elf",
+        blocks: &blocks,
+    };
+    let mut map_path = archive_path.clone();
+    map_path.push(BLOCK_MAP_FILE);
+    std::fs::write(
+        &map_path,
+        serde_json::to_string_pretty(&map).expect("Cannot serialize block map"),
+    )
+    .expect("Cannot write block map");
+    println!("Debug archive placed in: {}", archive_path.display());
+}
+
+fn copy_to_archive(archive_path: &PathBuf, src: &PathBuf, name: &str) {
+    let mut dest = archive_path.clone();
+    dest.push(name);
+    if std::fs::copy(src, &dest).is_err() {
+        panic!("Cannot copy '{}' in the debug archive", src.display());
+    }
+}
+
+/// Allocated section of a component ELF, with the offset added by the relocator
+struct SectionMove {
+    name: String,
+    address: u32,
+    #[allow(dead_code)]
+    size: u32,
+    delta: u32,
+}
+
+fn section_moves(elf_bytes: &[u8], text_base: u32, sram_base: u32) -> Vec<SectionMove> {
+    let elf = goblin::elf::Elf::parse(elf_bytes).expect("Cannot parse component ELF");
+    let mut moves: Vec<SectionMove> = Vec::new();
+    for section in &elf.section_headers {
+        if section.sh_flags & SHF_ALLOC as u64 == 0 || section.sh_addr == 0 {
+            continue;
+        }
+        let address = section.sh_addr as u32;
+        let delta = if address >= LINKED_SRAM_BASE {
+            sram_base - LINKED_SRAM_BASE
+        } else if address >= LINKED_FLASH_BASE {
+            text_base - LINKED_FLASH_BASE
+        } else {
+            continue;
+        };
+        let name = elf
+            .shdr_strtab
+            .get_at(section.sh_name)
+            .expect("Invalid section name");
+        moves.push(SectionMove {
+            name: String::from(name),
+            address,
+            size: section.sh_size as u32,
+            delta,
+        });
+    }
+    moves
+}
+
+/// Moves every section of the component ELF where the relocator placed it
+fn offset_component_elf(
+    src: &PathBuf,
+    dest: &PathBuf,
+    text_base: u32,
+    sram_base: u32,
+    verbose: bool,
+) {
+    let elf_bytes = std::fs::read(src).expect(&format!("Cannot read ELF at '{}'", src.display()));
+    let mut cmd = Command::new("arm-none-eabi-objcopy");
+    for section in section_moves(&elf_bytes, text_base, sram_base) {
+        if verbose {
+            println!(
+                "\t{}: {:#010x} -> {:#010x}",
+                section.name,
+                section.address,
+                section.address + section.delta
+            );
+        }
+        cmd.arg("--change-section-address")
+            .arg(format!("{}+{:#x}", section.name, section.delta));
+    }
+    cmd.arg(src).arg(dest);
+    if !verbose {
+        // Avoid warnings on sections that do not need to be moved
+        cmd.stderr(std::process::Stdio::null());
+    }
+    let status = cmd.status().expect("failed to objcopy");
+    if !status.success() {
+        panic!("objcopy failed, see output for details");
+    }
+}
+
+/*
+    Tests
+*/
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::elf_editor::{extract_cbf_relocations, relocate_cbf};
+    use cbf_rs::CbfFile;
+
+    fn example_path(file: &str) -> PathBuf {
+        let mut path = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        path.push("../elf2cbf/examples/component3/output");
+        path.push(file);
+        path
+    }
+
+    fn read_word(bytes: &[u8], pos: usize) -> u32 {
+        u32::from_le_bytes(bytes[pos..pos + 4].try_into().unwrap())
+    }
+
+    #[test]
+    fn offsets_match_relocator() {
+        let elf_bytes = std::fs::read(example_path("image.elf")).unwrap();
+        let linked = std::fs::read(example_path("component.cbf")).unwrap();
+        let cbf = cbf_rs::parse_cbf(&linked).unwrap();
+        let relocs = extract_cbf_relocations(&cbf);
+        // Where the system builder could place it
+        let text_base = 0x0804_0000 + 8 + cbf.read_only_section().offset() + 32;
+        let sram_base = 0x2000_6000;
+        let mut relocated = linked.clone();
+        relocate_cbf(
+            &String::new(),
+            &mut relocated,
+            text_base,
+            sram_base,
+            &relocs,
+            false,
+        );
+        let moves = section_moves(&elf_bytes, text_base, sram_base);
+        // Every address stored in the image is moved as its section in the archive
+        let mut checked = 0;
+        for reloc in relocs {
+            // Absolute addresses only, the MOVW/MOVT pairs are split in two
+            if reloc >> 30 != 0 {
+                continue;
+            }
+            let pos = reloc as usize & 0xFF_FFFF;
+            let address = read_word(&linked, pos);
+            let section = moves
+                .iter()
+                .find(|s| address >= s.address && address <= s.address + s.size)
+                .unwrap_or_else(|| panic!("No section for {:#010x}", address));
+            assert_eq!(
+                read_word(&relocated, pos),
+                address + section.delta,
+                "Address {:#010x} in {}",
+                address,
+                section.name
+            );
+            checked += 1;
+        }
+        assert!(checked > 0);
+    }
+}
@@ -5,6 +5,8 @@
 use flash_allocator::flash::{BlockType, FlashAllocatorImpl, FlashMethods};
 use goblin::{container::Container, elf64::program_header::PT_LOAD};
 use cbf_rs::CbfFile;
+use crate::debug_archive::DebugBlockEntry;
+use alloc_report::{AllocStatEntry, AllocStats};
 use ram_allocator::{RAMAllocator, RAMAllocatorImpl};
 use relocator::RelocatorMethods;
 use std::fs::File;
@@ -17,28 +19,6 @@ use std::{collections::BTreeMap, path::PathBuf};
 /// after initially 0xab clearing it
 const DEFAULT_KERNEL_STACK_SIZE: u32 = 1350; //1250;
 
-#[derive(Debug, Clone)]
-pub struct AllocStatEntry {
-    pub name: String,
-    pub component_id: u16,
-    pub flash_address: u32,
-    pub flash_size: u32,
-    pub flash_needed_size: u32,
-    pub ram_address: u32,
-    pub ram_size: u32,
-    pub ram_needed_size: u32,
-}
-
-pub struct AllocStats {
-    pub flash_start: u32,
-    pub flash_size: u32,
-    pub ram_start: u32,
-    pub ram_size: u32,
-    pub kernel_reserved_ram: u32,
-    pub kernel_reserved_flash: u32,
-    pub entries: Vec<AllocStatEntry>,
-}
-
 pub struct ElfEditor<'a> {
     dest_path: &'a PathBuf,
     output_sections: BTreeMap<u32, Vec<u8>>,
@@ -47,6 +27,7 @@ pub struct ElfEditor<'a> {
     kentry: u32,
     flash_buffer: BufferFlash,
     allocation_stats: AllocStats,
+    debug_entries: Vec<DebugBlockEntry>,
 }
 
 impl<'a> ElfEditor<'a> {
@@ -105,6 +86,7 @@ impl<'a> ElfEditor<'a> {
                 kernel_reserved_flash: kernel_flash,
                 entries: Vec::new(),
             },
+            debug_entries: Vec::new(),
         }
     }
     fn is_in_flash(&self, addr: u32) -> bool {
@@ -177,7 +159,7 @@ impl<'a> ElfEditor<'a> {
         }
     }
 
-    pub fn add_component(&mut self, cbf_path: &PathBuf, verbose: bool) {
+    pub fn add_component(&mut self, cbf_path: &PathBuf, elf_path: &PathBuf, verbose: bool) {
         // Read bytes
         let mut cbf_bytes =
             std::fs::read(cbf_path).expect(&format!("Cannot read CBF at: {}", cbf_path.display()));
@@ -234,6 +216,20 @@ impl<'a> ElfEditor<'a> {
             + cbf.read_only_section().offset()
             + flash_allocator::flash::HEADER_SIZE as u32;
         let new_sram_base_address = alloc_result.sram_address;
+        // Keep track of the placement, for the debug archive
+        self.debug_entries.push(DebugBlockEntry {
+            block_type: String::from("component"),
+            component_id: Some(cbf.header_base().component_id()),
+            component_version: Some(cbf.header_base().component_version()),
+            flash_address: alloc_result.flash_address,
+            flash_size: alloc_result.flash_size,
+            text_base: Some(new_flash_base_address),
+            sram_address: Some(alloc_result.sram_address),
+            sram_size: Some(alloc_result.sram_size),
+            source_elf: Some(elf_path.clone()),
+            elf: None,
+            log_strings: None,
+        });
         let checksum_offset = cbf.checksum_offset() as usize;
         let mut out_cbf = String::from(self.dest_path.to_str().unwrap());
         out_cbf += &format!("_component_{}.cbf", cbf.header_base().component_id());
@@ -253,6 +249,55 @@ impl<'a> ElfEditor<'a> {
             .insert(block_base_addr, component_bytes);
     }
 
+    pub fn add_coredump_block(&mut self, size: u32, stack_window: u32, on_fault: bool) {
+        // Reserve the block
+        let alloc_result = perform_block_allocation(
+            self.board_name.clone(),
+            &mut self.flash_buffer,
+            size,
+            BlockType::COREDUMP,
+        );
+        println!(
+            "Allocated core dump block at flash: {:#010x} [size: {}]",
+            alloc_result.flash_address, alloc_result.flash_size,
+        );
+        // Add stat
+        self.allocation_stats.entries.push(AllocStatEntry {
+            name: String::from("Core dump"),
+            component_id: 0,
+            flash_address: alloc_result.flash_address,
+            flash_size: alloc_result.flash_size,
+            flash_needed_size: size,
+            ram_address: 0,
+            ram_size: 0,
+            ram_needed_size: 0,
+        });
+        self.debug_entries.push(DebugBlockEntry {
+            block_type: String::from("coredump"),
+            component_id: None,
+            component_version: None,
+            flash_address: alloc_result.flash_address,
+            flash_size: alloc_result.flash_size,
+            text_base: None,
+            sram_address: None,
+            sram_size: None,
+            source_elf: None,
+            elf: None,
+            log_strings: None,
+        });
+        // Block header, followed by the dump configuration (see abi::coredump)
+        let mut block_bytes: Vec<u8> = Vec::new();
+        block_bytes.extend_from_slice(&alloc_result.data);
+        block_bytes.extend_from_slice(&stack_window.to_le_bytes());
+        block_bytes.extend_from_slice(&(on_fault as u32).to_le_bytes());
+        self.output_sections
+            .insert(alloc_result.flash_address, block_bytes);
+    }
+
+    pub fn debug_entries(&self) -> &Vec<DebugBlockEntry> {
+        &self.debug_entries
+    }
+
     fn write_srec(&mut self) -> String {
         // Generate SREC
         let mut srec_out = vec![srec::Record::S0("conceptos".to_string())];
@@ -340,7 +385,7 @@ fn objcopy_translate_to_binary(in_format: &str, src: &PathBuf, dest: &PathBuf) {
     }
 }
 
-fn extract_cbf_relocations(cbf: &dyn CbfFile) -> Vec<u32> {
+pub(crate) fn extract_cbf_relocations(cbf: &dyn CbfFile) -> Vec<u32> {
     let mut result: Vec<u32> = Vec::new();
     for reloc in cbf.relocation_iter() {
         let r = reloc.value();
@@ -377,7 +422,7 @@ impl<'a> RelocatorMethods<()> for FileRelocationMethods<'a> {
     }
 }
 
-fn relocate_cbf(
+pub(crate) fn relocate_cbf(
     out_cbf_path: &String,
     cbf_bytes: &mut [u8],
     flash_base_address: u32,
@@ -716,6 +761,71 @@ fn perform_allocation(
     panic!("Unsupported board: {}", board_name);
 }
 
+struct AllocatedBlock {
+    pub flash_address: u32,
+    pub flash_size: u32,
+    pub data: Vec<u8>,
+}
+
+/// Allocates a finalized block that does not need any SRAM
+fn perform_block_allocation(
+    board_name: String,
+    flash_buffer: &mut BufferFlash,
+    needed_flash: u32,
+    block_type: BlockType,
+) -> AllocatedBlock {
+    macro_rules! allocate_block {
+        ($board:ident) => {{
+            const FLASH_START_ADDR: u32 = $board::FLASH_ALLOCATOR_START_ADDR;
+            const FLASH_END_ADDR: u32 = $board::FLASH_ALLOCATOR_END_ADDR;
+            const FLASH_ALLOCATOR_SCAN: u32 = $board::FLASH_ALLOCATOR_START_SCAN_ADDR;
+            const FLASH_BLOCK_SIZE: usize = $board::FLASH_BLOCK_SIZE;
+            const FLASH_NUM_BLOCKS: usize = $board::FLASH_NUM_BLOCKS;
+            const FLASH_TREE_MAX_LEVEL: usize = $board::FLASH_TREE_MAX_LEVEL;
+            const FLASH_NUM_NODES: usize = $board::FLASH_NUM_NODES;
+            // Create fake flash memory
+            flash_buffer.change_base_address(FLASH_START_ADDR);
+            // Create the standard allocator
+            let mut flash_alloc = FlashAllocatorImpl::<
+                FLASH_START_ADDR,
+                FLASH_END_ADDR,
+                FLASH_ALLOCATOR_SCAN,
+                FLASH_BLOCK_SIZE,
+                FLASH_NUM_BLOCKS,
+                FLASH_TREE_MAX_LEVEL,
+                FLASH_NUM_NODES,
+            >::from_flash(flash_buffer, false, false);
+            // Perform the allocation
+            let flash_block = flash_alloc
+                .allocate(needed_flash, block_type)
+                .expect("Failed to allocate block");
+            drop(flash_alloc);
+            // Now finalize the block header
+            flash_allocator::flash::utils::finalize_block::<FLASH_START_ADDR, FLASH_TREE_MAX_LEVEL>(
+                flash_buffer,
+                flash_block,
+            )
+            .unwrap();
+            const BLOCK_HEADER_SIZE: usize = flash_allocator::flash::HEADER_SIZE;
+            let mut header_bytes: [u8; BLOCK_HEADER_SIZE] = [0x00; BLOCK_HEADER_SIZE];
+            flash_buffer
+                .read(flash_block.get_nominal_base_address(), &mut header_bytes)
+                .unwrap();
+            AllocatedBlock {
+                flash_address: flash_block.get_nominal_base_address(),
+                flash_size: flash_block.get_nominal_size(),
+                data: Vec::from(header_bytes),
+            }
+        }};
+    }
+    match board_name.as_str() {
+        "stm32f303re" => allocate_block!(stm32f303re),
+        "stm32l432kc" => allocate_block!(stm32l432kc),
+        "stm32l476rg" => allocate_block!(stm32l476rg),
+        _ => panic!("Unsupported board: {}", board_name),
+    }
+}
+
 struct BufferFlash {
     base_addr: u32,
     buffer: Vec<u8>,
@@ -3,13 +3,13 @@
 // file, You can obtain one at https://mozilla.org/MPL/2.0/.
 
 use crate::elf_editor::ElfEditor;
-use app_config::AppConfig;
+use app_config::{AppConfig, ComponentConfig};
 use board_config::BoardConfig;
 use clap::Parser;
-use std::{path::PathBuf, process::Command, str::FromStr};
+use std::{collections::BTreeMap, path::PathBuf, process::Command, str::FromStr};
 
+mod debug_archive;
 mod elf_editor;
-mod visualize_stats;
 
 #[derive(Parser)]
 #[clap(version, about)]
@@ -61,6 +61,12 @@ fn build_system(app_config_path: String, output_path: String, verbose: bool) {
     let board_config_root = get_board_root_path(&app_config.board, &root_path);
     // Parse the board data
     let board_config = read_board_config(&board_config_root, &app_config.board);
+    let mut board_config_path = board_config_root.clone();
+    board_config_path.push("Board.toml");
+    // Check the clock tree before building anything, the rcc component applies it
+    generate_clock_tree(&app_config, &app_root);
+    // Same for the DMA channels, each one is programmed by a single component
+    check_dma_channels(&root_path, &app_config);
     // Now that we know the target, build the kernel
     let kern_elf = build_kernel(
         &root_path,
@@ -76,18 +82,39 @@ fn build_system(app_config_path: String, output_path: String, verbose: bool) {
     elf_edit.add_kernel(&kern_elf);
     // For each component, build and add
     for (component_name, component_config) in &app_config.components {
+        let (component_dir, instance) = component_instance(component_name, component_config);
         let cbf_path = build_component(
             &root_path,
             &app_config,
             &component_config.features,
             &app_root,
             &board_config,
+            &component_dir,
             &component_name,
+            &instance,
         );
-        elf_edit.add_component(&cbf_path, verbose);
+        let elf_path = component_elf_path(&root_path, &app_config.board, &component_dir, &instance);
+        elf_edit.add_component(&cbf_path, &elf_path, verbose);
+    }
+    // Reserve space for the core dump
+    if let Some(coredump) = &app_config.coredump {
+        elf_edit.add_coredump_block(coredump.size, coredump.stack_window, coredump.on_fault);
     }
+    // Generate the debug archive
+    let mut archive_path = String::from(system_out.to_str().unwrap());
+    archive_path += "_debug";
+    debug_archive::generate(
+        &PathBuf::from(archive_path),
+        &app_config.name,
+        &app_config.board,
+        &kern_elf,
+        &config_path,
+        &board_config_path,
+        elf_edit.debug_entries(),
+        verbose,
+    );
     // Generate ELF
-    visualize_stats::visualize(elf_edit.finish(), &app_root);
+    alloc_report::visualize(elf_edit.finish(), &app_root);
     println!("Final image placed in: {}", system_out.display());
 }
 
@@ -124,6 +151,33 @@ fn read_board_config(board_config_root: &PathBuf, board_name: &String) -> BoardC
     ))
 }
 
+fn generate_clock_tree(app_config: &AppConfig, app_root: &PathBuf) {
+    let clocks = match &app_config.clocks {
+        Some(clocks) => clocks,
+        None => return,
+    };
+    let tree = clock_tree::resolve(&app_config.board, clocks)
+        .and_then(|tree| clock_tree::check_clock_speed(&tree, app_config.clock_speed).map(|_| tree))
+        .unwrap_or_else(|e| panic!("Invalid clock configuration: {}", e));
+    println!(
+        "Clocks: SYSCLK {} Hz, HCLK {} Hz, PCLK1 {} Hz, PCLK2 {} Hz",
+        tree.sysclk, tree.hclk, tree.pclk1, tree.pclk2
+    );
+    let mut clocks_out = PathBuf::from(&app_root);
+    clocks_out.push("build");
+    if !clocks_out.exists() {
+        if std::fs::create_dir(&clocks_out).is_err() {
+            panic!("Cannot create output dir for the clock tree");
+        }
+    }
+    clocks_out.push("clock_tree.rs");
+    if std::fs::write(&clocks_out, clock_tree::generate(&tree, true)).is_err() {
+        panic!("Cannot write the clock tree");
+    }
+    // Read by the build script of the rcc component
+    std::env::set_var(clock_tree::SOURCE_ENV, &clocks_out);
+}
+
 fn build_kernel(
     _root_path: &PathBuf,
     app_config: &AppConfig,
@@ -175,6 +229,9 @@ MEMORY
         cmd.arg("-v");
     }
     cmd.current_dir(&app_root);
+    if app_config.coredump.is_some() {
+        cmd.arg("--features").arg("coredump");
+    }
 
     if app_config.strip_panics {
         cmd.arg("-Z")
@@ -230,19 +287,84 @@ MEMORY
     kernel_out_image
 }
 
+/// The table in App.toml names the component, unless it is another instance of
+/// the one given by `component`. Returns the directory of the component too.
+fn component_instance(
+    component_name: &String,
+    component_config: &ComponentConfig,
+) -> (String, component_builder::Instance) {
+    let (component_dir, name) = match &component_config.component {
+        Some(component) if component != component_name => {
+            if component_config.id.is_none() {
+                panic!(
+                    "Instance {} of component {} needs its own id",
+                    component_name, component
+                );
+            }
+            (component.clone(), Some(component_name.clone()))
+        }
+        _ => (component_name.clone(), None),
+    };
+    let instance = component_builder::Instance {
+        name: name,
+        id: component_config.id,
+        config: component_config.config.clone(),
+    };
+    (component_dir, instance)
+}
+
+/// Panics when two components, or two instances of one, claim the same DMA
+/// channel in their Component.toml
+fn check_dma_channels(root_path: &PathBuf, app_config: &AppConfig) {
+    let mut claims = Vec::<(String, Vec<String>)>::new();
+    for (component_name, component_config) in &app_config.components {
+        let (component_dir, instance) = component_instance(component_name, component_config);
+        let mut component_root = PathBuf::from(root_path);
+        component_root.push("components");
+        component_root.push(component_dir);
+        component_root.push("core");
+        if !component_root.exists() {
+            panic!("Cannot find component {}", component_name);
+        }
+        let channels = component_builder::dma_channels(&component_root, &instance);
+        claims.push((component_name.clone(), channels));
+    }
+    if let Some((channel, first, second)) = find_dma_conflict(&claims) {
+        panic!(
+            "DMA channel {} is claimed by both {} and {}",
+            channel, first, second
+        );
+    }
+}
+
+/// First channel in the claims of two components, with their names
+fn find_dma_conflict(claims: &[(String, Vec<String>)]) -> Option<(String, String, String)> {
+    let mut owners = BTreeMap::<&String, &String>::new();
+    for (component_name, channels) in claims {
+        for channel in channels {
+            if let Some(owner) = owners.insert(channel, component_name) {
+                return Some((channel.clone(), owner.clone(), component_name.clone()));
+            }
+        }
+    }
+    None
+}
+
 fn build_component(
     root_path: &PathBuf,
     app_config: &AppConfig,
     features: &Vec<String>,
     _app_root: &PathBuf,
     _board_config: &BoardConfig,
+    component_dir: &String,
     component_name: &String,
+    instance: &component_builder::Instance,
 ) -> PathBuf {
     std::env::set_var("ROOT_DIR", &root_path.to_str().unwrap());
     // Search this component
     let mut component_root = PathBuf::from(root_path);
     component_root.push("components");
-    component_root.push(component_name);
+    component_root.push(component_dir);
     component_root.push("core");
     if !component_root.exists() {
         panic!("Cannot find component {}", component_name);
@@ -256,6 +378,7 @@ fn build_component(
         String::from(out_file.to_str().unwrap()),
         app_config.board.clone(),
         features,
+        instance,
         false,
         false,
     )
@@ -266,6 +389,22 @@ fn build_component(
     out_file
 }
 
+/// ELF of the component, as left by the component builder
+fn component_elf_path(
+    root_path: &PathBuf,
+    board_name: &String,
+    component_dir: &String,
+    instance: &component_builder::Instance,
+) -> PathBuf {
+    let mut component_root = PathBuf::from(root_path);
+    component_root.push("components");
+    component_root.push(component_dir);
+    component_root.push("core");
+    let mut elf_path = component_builder::build_path(&component_root, board_name, instance);
+    elf_path.push("image.elf");
+    elf_path
+}
+
 fn main() {
     let res = process_args();
     std::process::exit(res);
@@ -276,7 +415,7 @@ fn main() {
 */
 #[cfg(test)]
 mod test {
-    use crate::build_system;
+    use crate::{build_system, find_dma_conflict};
 
     #[test]
     fn build_kernel() {
@@ -286,4 +425,31 @@ mod test {
             false,
         );
     }
+
+    #[test]
+    fn dma_conflict() {
+        let claim = |name: &str, channels: &[&str]| {
+            (
+                String::from(name),
+                channels.iter().map(|c| String::from(*c)).collect(),
+            )
+        };
+        let mut claims = vec![
+            claim("i2c", &["dma2.6", "dma2.7"]),
+            claim("spi", &["dma2.3", "dma2.4"]),
+            claim("uart-channel", &["dma1.6"]),
+            claim("idle", &[]),
+        ];
+        assert_eq!(find_dma_conflict(&claims), None);
+        // A second instance of uart-channel on the channel of the LPUART1
+        claims.push(claim("modem-uart", &["dma2.7"]));
+        assert_eq!(
+            find_dma_conflict(&claims),
+            Some((
+                String::from("dma2.7"),
+                String::from("i2c"),
+                String::from("modem-uart")
+            ))
+        );
+    }
 }
@@ -0,0 +1,195 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+mod plan;
+
+use std::{fs, path::PathBuf};
+
+use cbf_rs::CbfFile;
+use crossbeam_channel::{Receiver, Sender};
+use pbr::ProgressBar;
+use serde::Deserialize;
+
+use self::plan::{changes, order, Change, Target};
+use crate::flash_component::{update_component, ComponentImage};
+use crate::info::{query_components, Dependency};
+
+/**
+ * Plan of the components the device must have (TOML), in any order:
+ *
+ * components = [
+ *     "component1/output/component.cbf",
+ *     "component2/output/component.cbf",
+ * ]
+ */
+
+#[derive(Deserialize)]
+struct PlanFile {
+    /// CBFs, relative to the plan
+    components: Vec<PathBuf>,
+}
+
+/// Installs the components of the plan, in an order that keeps the dependencies
+/// valid at every step. Returns false if the plan cannot be applied.
+pub fn apply(
+    channel_in_consumer: Receiver<u8>,
+    channel_out_producer: Sender<Vec<u8>>,
+    plan_file: String,
+    dry_run: bool,
+    window: usize,
+    verbose: bool,
+) -> bool {
+    let images = load_plan(&PathBuf::from(&plan_file));
+    let targets: Vec<Target> = images.iter().map(|image| target(&image.cbf())).collect();
+
+    // Compare with the device
+    let installed = match query_components(&channel_in_consumer, &channel_out_producer, verbose) {
+        Ok(installed) => installed,
+        Err(e) => {
+            eprintln!("{}", e);
+            return false;
+        }
+    };
+    println!("----------- Plan -----------");
+    println!("\n\tComponent\tInstalled\tTarget");
+    for (target, change) in targets.iter().zip(changes(&installed, &targets)) {
+        let (installed, action) = match change {
+            Change::Install => (String::from("-"), "install"),
+            Change::Update { from } => (from.to_string(), "update"),
+            Change::Unchanged => (target.component_version.to_string(), "unchanged"),
+        };
+        println!(
+            "\t{}\t\t{}\t\t{}\t\t{}",
+            target.component_id, installed, target.component_version, action
+        );
+    }
+    for component in installed
+        .iter()
+        .filter(|c| !targets.iter().any(|t| t.component_id == c.component_id))
+    {
+        println!(
+            "\t{}\t\t{}\t\t-\t\tnot in the plan",
+            component.component_id, component.component_version
+        );
+    }
+    let steps = match order(&installed, &targets) {
+        Ok(steps) => steps,
+        Err(e) => {
+            eprintln!("\n{}", e);
+            return false;
+        }
+    };
+    if steps.is_empty() {
+        println!("\nThe device already matches the plan");
+        return true;
+    }
+    println!("\n\tOrder:");
+    for (i, step) in steps.iter().enumerate() {
+        println!(
+            "\t{}. component {} v{}",
+            i + 1,
+            targets[*step].component_id,
+            targets[*step].component_version
+        );
+    }
+    if dry_run {
+        return true;
+    }
+
+    // Execute
+    for (i, step) in steps.iter().enumerate() {
+        let image = &images[*step];
+        println!(
+            "\n[{}/{}] Component {} v{}",
+            i + 1,
+            steps.len(),
+            image.component_id(),
+            image.component_version()
+        );
+        let mut progress = ProgressBar::new(image.progress_total());
+        progress.show_speed = false;
+        progress.show_counter = false;
+        progress.show_time_left = false;
+        progress.set_width(Some(80));
+        let result = update_component(
+            &channel_in_consumer,
+            &channel_out_producer,
+            image,
+            None,
+            false,
+            window,
+            &mut progress,
+            verbose,
+        );
+        if let Err(e) = result {
+            eprintln!("\n{}", e);
+            eprintln!(
+                "Stopped at step {} of {}, the previous steps are installed: run the plan again to continue",
+                i + 1,
+                steps.len()
+            );
+            return false;
+        }
+    }
+
+    // Check the result
+    let installed = match query_components(&channel_in_consumer, &channel_out_producer, verbose) {
+        Ok(installed) => installed,
+        Err(e) => {
+            eprintln!("\n{}", e);
+            return false;
+        }
+    };
+    let missing: Vec<&Target> = targets
+        .iter()
+        .filter(|t| {
+            !installed.iter().any(|c| {
+                c.component_id == t.component_id && c.component_version == t.component_version
+            })
+        })
+        .collect();
+    if !missing.is_empty() {
+        for target in missing {
+            eprintln!(
+                "\nComponent {} v{} is not on the device",
+                target.component_id, target.component_version
+            );
+        }
+        return false;
+    }
+    println!("\nSuccess! The device matches the plan");
+    return true;
+}
+
+/// Reads the CBFs of the plan, resolving them from its directory
+fn load_plan(plan_path: &PathBuf) -> Vec<ComponentImage> {
+    let content = fs::read_to_string(plan_path)
+        .unwrap_or_else(|e| panic!("Cannot read the plan '{}': {}", plan_path.display(), e));
+    let plan: PlanFile = toml::from_str(&content)
+        .unwrap_or_else(|e| panic!("Invalid plan '{}': {}", plan_path.display(), e));
+    if plan.components.is_empty() {
+        panic!("The plan has no components");
+    }
+    let dir = plan_path.parent().map(PathBuf::from).unwrap_or_default();
+    return plan
+        .components
+        .iter()
+        .map(|component| ComponentImage::load(&dir.join(component)))
+        .collect();
+}
+
+fn target(cbf: &dyn CbfFile) -> Target {
+    Target {
+        component_id: cbf.header_base().component_id(),
+        component_version: cbf.header_base().component_version(),
+        dependencies: cbf
+            .dependency_iter()
+            .map(|d| Dependency {
+                component_id: d.component_id(),
+                min_version: d.min_version(),
+                max_version: d.max_version(),
+            })
+            .collect(),
+    }
+}
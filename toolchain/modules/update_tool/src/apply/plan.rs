@@ -0,0 +1,390 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use std::{
+    collections::{BTreeMap, HashSet},
+    fmt,
+};
+
+use crate::info::{ComponentInfo, Dependency};
+
+/**
+ * Ordering of the installs, so that every intermediate state of the device is valid:
+ * the device checks the dependencies of the component it receives, and a replaced
+ * component must still satisfy the components that depend on it. The device
+ * reports the dependencies of the installed components.
+ */
+
+/// A component of the plan
+#[derive(Debug, Clone)]
+pub struct Target {
+    pub component_id: u16,
+    pub component_version: u32,
+    pub dependencies: Vec<Dependency>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Change {
+    /// Not on the device
+    Install,
+    Update {
+        from: u32,
+    },
+    Unchanged,
+}
+
+/// A component that cannot be installed at some point of the plan
+#[derive(Debug, Clone)]
+pub struct Blocker {
+    pub component_id: u16,
+    pub component_version: u32,
+    pub reason: String,
+}
+
+#[derive(Debug)]
+pub enum PlanError {
+    /// The device refuses to go back to an older version
+    Downgrade {
+        component_id: u16,
+        installed: u32,
+        target: u32,
+    },
+    /// A component is in the plan more than once
+    Duplicate(u16),
+    /// Whatever the order, a step would break the dependencies. The installs
+    /// of the longest valid sequence found are followed by what blocks the rest.
+    NoOrder {
+        done: Vec<(u16, u32)>,
+        blockers: Vec<Blocker>,
+    },
+}
+
+impl fmt::Display for PlanError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PlanError::Downgrade {
+                component_id,
+                installed,
+                target,
+            } => write!(
+                f,
+                "Component {} has version {} installed, the device refuses to go back to version {}",
+                component_id, installed, target
+            ),
+            PlanError::Duplicate(component_id) => {
+                write!(f, "Component {} is in the plan more than once", component_id)
+            }
+            PlanError::NoOrder { done, blockers } => {
+                writeln!(f, "No order of the installs keeps the dependencies valid.")?;
+                if !done.is_empty() {
+                    let done: Vec<String> = done
+                        .iter()
+                        .map(|(id, version)| format!("{} v{}", id, version))
+                        .collect();
+                    writeln!(f, "After installing {}:", done.join(", "))?;
+                }
+                for blocker in blockers {
+                    writeln!(
+                        f,
+                        "\tcomponent {} v{} {}",
+                        blocker.component_id, blocker.component_version, blocker.reason
+                    )?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+/// Version and dependencies of each component on the device
+type State = BTreeMap<u16, (u32, Vec<Dependency>)>;
+
+/// What the plan changes on the device, for each target
+pub fn changes(installed: &[ComponentInfo], targets: &[Target]) -> Vec<Change> {
+    targets
+        .iter()
+        .map(|target| {
+            match installed
+                .iter()
+                .find(|c| c.component_id == target.component_id)
+            {
+                None => Change::Install,
+                Some(c) if c.component_version == target.component_version => Change::Unchanged,
+                Some(c) => Change::Update {
+                    from: c.component_version,
+                },
+            }
+        })
+        .collect()
+}
+
+/// Returns the targets to install (indexes), in an order that keeps every step valid
+pub fn order(installed: &[ComponentInfo], targets: &[Target]) -> Result<Vec<usize>, PlanError> {
+    let mut steps: Vec<usize> = Vec::new();
+    for (i, target) in targets.iter().enumerate() {
+        if targets[..i]
+            .iter()
+            .any(|other| other.component_id == target.component_id)
+        {
+            return Err(PlanError::Duplicate(target.component_id));
+        }
+        match installed
+            .iter()
+            .find(|c| c.component_id == target.component_id)
+        {
+            Some(c) if c.component_version > target.component_version => {
+                return Err(PlanError::Downgrade {
+                    component_id: target.component_id,
+                    installed: c.component_version,
+                    target: target.component_version,
+                })
+            }
+            Some(c) if c.component_version == target.component_version => {}
+            _ => steps.push(i),
+        }
+    }
+    let state: State = installed
+        .iter()
+        .map(|c| {
+            (
+                c.component_id,
+                (c.component_version, c.dependencies.clone()),
+            )
+        })
+        .collect();
+    let mut search = Search {
+        targets: targets,
+        steps: &steps,
+        applied: vec![false; targets.len()],
+        order: Vec::new(),
+        visited: HashSet::new(),
+        deepest: None,
+    };
+    if search.run(&state) {
+        return Ok(search.order);
+    }
+    let (done, blockers) = search.deepest.unwrap_or_default();
+    Err(PlanError::NoOrder {
+        done: done
+            .iter()
+            .map(|i| (targets[*i].component_id, targets[*i].component_version))
+            .collect(),
+        blockers: blockers,
+    })
+}
+
+/// Depth-first search of the orders, in the order of the plan first
+struct Search<'a> {
+    targets: &'a [Target],
+    steps: &'a [usize],
+    applied: Vec<bool>,
+    order: Vec<usize>,
+    /// Sets of installs already explored
+    visited: HashSet<Vec<bool>>,
+    /// Longest sequence that cannot go on, with the reasons
+    deepest: Option<(Vec<usize>, Vec<Blocker>)>,
+}
+
+impl Search<'_> {
+    fn run(&mut self, state: &State) -> bool {
+        if self.order.len() == self.steps.len() {
+            return true;
+        }
+        if !self.visited.insert(self.applied.clone()) {
+            return false;
+        }
+        let targets = self.targets;
+        let mut blockers: Vec<Blocker> = Vec::new();
+        for &step in self.steps {
+            if self.applied[step] {
+                continue;
+            }
+            let target = &targets[step];
+            if let Err(reason) = check(state, target) {
+                blockers.push(Blocker {
+                    component_id: target.component_id,
+                    component_version: target.component_version,
+                    reason: reason,
+                });
+                continue;
+            }
+            let mut next = state.clone();
+            next.insert(
+                target.component_id,
+                (target.component_version, target.dependencies.clone()),
+            );
+            self.applied[step] = true;
+            self.order.push(step);
+            if self.run(&next) {
+                return true;
+            }
+            self.applied[step] = false;
+            self.order.pop();
+        }
+        // A dead end: nothing can be installed from here
+        let remaining = self.steps.len() - self.order.len();
+        let deeper = self
+            .deepest
+            .as_ref()
+            .map_or(true, |(done, _)| self.order.len() > done.len());
+        if blockers.len() == remaining && deeper {
+            self.deepest = Some((self.order.clone(), blockers));
+        }
+        false
+    }
+}
+
+/// Why the target cannot be installed on the device as it is
+fn check(state: &State, target: &Target) -> Result<(), String> {
+    // The dependencies of the target, checked by the device
+    for dependency in &target.dependencies {
+        match state.get(&dependency.component_id) {
+            None => return Err(format!("needs {}, not installed", dependency)),
+            Some((version, _)) if !dependency.accepts(*version) => {
+                return Err(format!("needs {}, installed v{}", dependency, version))
+            }
+            Some(_) => {}
+        }
+    }
+    // The components depending on the target
+    for (component_id, (version, dependencies)) in state {
+        if *component_id == target.component_id {
+            continue;
+        }
+        for dependency in dependencies {
+            if dependency.component_id == target.component_id
+                && !dependency.accepts(target.component_version)
+            {
+                return Err(format!(
+                    "would break component {} v{}, which needs {}",
+                    component_id, version, dependency
+                ));
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn installed(components: &[(u16, u32)]) -> Vec<ComponentInfo> {
+        components
+            .iter()
+            .map(|(id, version)| ComponentInfo {
+                component_id: *id,
+                component_version: *version,
+                block_base_address: 0,
+                sram_base_address: 0,
+                dependencies: Vec::new(),
+            })
+            .collect()
+    }
+
+    fn target(
+        component_id: u16,
+        component_version: u32,
+        dependencies: &[(u16, u32, u32)],
+    ) -> Target {
+        Target {
+            component_id: component_id,
+            component_version: component_version,
+            dependencies: dependencies
+                .iter()
+                .map(|(id, min, max)| Dependency {
+                    component_id: *id,
+                    min_version: *min,
+                    max_version: *max,
+                })
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn test_dependencies_first() {
+        // 1 v2 needs 2 >= 2, which needs 3
+        let targets = vec![
+            target(1, 2, &[(2, 2, 0)]),
+            target(2, 2, &[(3, 0, 0)]),
+            target(3, 1, &[]),
+        ];
+        let installed = installed(&[(1, 1), (2, 1)]);
+        assert_eq!(order(&installed, &targets).unwrap(), vec![2, 1, 0]);
+        assert_eq!(
+            changes(&installed, &targets),
+            vec![
+                Change::Update { from: 1 },
+                Change::Update { from: 1 },
+                Change::Install
+            ]
+        );
+    }
+
+    #[test]
+    fn test_unchanged() {
+        let targets = vec![target(1, 2, &[(2, 1, 1)]), target(2, 1, &[])];
+        assert_eq!(
+            order(&installed(&[(1, 1), (2, 1)]), &targets).unwrap(),
+            vec![0]
+        );
+        assert!(order(&installed(&[(1, 2), (2, 1)]), &targets)
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn test_installed_dependents() {
+        // 1 v1 accepts only 2 <= 1, and is not in the plan: 2 v2 cannot be installed
+        let mut installed = installed(&[(1, 1), (2, 1)]);
+        installed[0].dependencies = target(1, 1, &[(2, 0, 1)]).dependencies;
+        let targets = vec![target(2, 2, &[]), target(3, 1, &[])];
+        match order(&installed, &targets) {
+            Err(PlanError::NoOrder { done, blockers }) => {
+                assert_eq!(done, vec![(3, 1)]);
+                assert_eq!(blockers.len(), 1);
+                assert_eq!(blockers[0].component_id, 2);
+                assert!(blockers[0].reason.contains("would break component 1 v1"));
+            }
+            _ => panic!("The plan should have no order"),
+        }
+        // 1 v2 accepts both versions of 2: it goes first
+        let targets = vec![target(2, 2, &[]), target(1, 2, &[(2, 0, 2)])];
+        assert_eq!(order(&installed, &targets).unwrap(), vec![1, 0]);
+    }
+
+    #[test]
+    fn test_no_order() {
+        // Each one needs the new version of the other
+        let targets = vec![target(1, 2, &[(2, 2, 0)]), target(2, 2, &[(1, 2, 0)])];
+        let error = order(&installed(&[(1, 1), (2, 1)]), &targets).unwrap_err();
+        let explanation = error.to_string();
+        assert!(explanation.contains("component 1 v2 needs component 2 >= 2, installed v1"));
+        assert!(explanation.contains("component 2 v2 needs component 1 >= 2, installed v1"));
+        // A dependency nobody provides
+        let targets = vec![target(1, 1, &[(7, 0, 0)])];
+        let error = order(&installed(&[]), &targets).unwrap_err();
+        assert!(error
+            .to_string()
+            .contains("needs component 7, not installed"));
+    }
+
+    #[test]
+    fn test_invalid_plans() {
+        let targets = vec![target(1, 1, &[])];
+        assert!(matches!(
+            order(&installed(&[(1, 2)]), &targets),
+            Err(PlanError::Downgrade {
+                component_id: 1,
+                installed: 2,
+                target: 1
+            })
+        ));
+        let targets = vec![target(1, 1, &[]), target(1, 2, &[])];
+        assert!(matches!(
+            order(&installed(&[]), &targets),
+            Err(PlanError::Duplicate(1))
+        ));
+    }
+}
@@ -0,0 +1,63 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use crossbeam_channel::Receiver;
+use crossbeam_channel::Sender;
+use update_link::{LinkReceiver, DATA_FRAME_SIZE, STREAM_BENCHMARK};
+
+use super::messages::*;
+use crate::common_messages::OperationType;
+use crate::crc::crc8_update;
+use crate::utils::*;
+
+/// Fragments the emulated device accepts out of order
+const LOOPBACK_WINDOW: usize = 16;
+
+/// Answers to benchmarks as the update component would, on the other end of
+/// a loopback (i.e. a pty pair), to measure the link without a device
+pub fn emulate_device(channel_in_consumer: Receiver<u8>, channel_out_producer: Sender<Vec<u8>>) {
+    loop {
+        // Hello
+        let mut hello: [u8; 2] = [0x00; 2];
+        channel_read(&channel_in_consumer, &mut hello).unwrap();
+        let mut crc: u8 = 0x00;
+        crc8_update(&mut crc, hello[0]);
+        if crc != hello[1] || hello[0] != OperationType::LinkBenchmark as u8 {
+            continue;
+        }
+        let mut response: Vec<u8> = vec!['O' as u8, 'L' as u8, 'L' as u8, 'E' as u8, 'H' as u8];
+        response.push(hello[0]);
+        let mut crc: u8 = 0x00;
+        for b in &response {
+            crc8_update(&mut crc, *b);
+        }
+        response.push(crc);
+        channel_write(&channel_out_producer, &response).unwrap();
+        // Size
+        channel_write(
+            &channel_out_producer,
+            &[LinkBenchmarkCommand::SendBenchmarkSize as u8],
+        )
+        .unwrap();
+        let mut size_buff: [u8; BenchmarkSizeMessage::get_size()] =
+            [0x00; BenchmarkSizeMessage::get_size()];
+        channel_read(&channel_in_consumer, &mut size_buff).unwrap();
+        let size = u32_from_le_bytes(&size_buff[0..4]) as usize;
+        // Data, thrown away
+        let mut receiver = LinkReceiver::<LOOPBACK_WINDOW>::new(STREAM_BENCHMARK, size);
+        let mut frame: [u8; DATA_FRAME_SIZE] = [0x00; DATA_FRAME_SIZE];
+        while !receiver.is_complete() {
+            channel_read(&channel_in_consumer, &mut frame).unwrap();
+            let control = receiver
+                .receive(&frame, |_| -> Result<(), ()> { Ok(()) })
+                .unwrap();
+            channel_write(&channel_out_producer, &control.encode()).unwrap();
+        }
+        channel_write(
+            &channel_out_producer,
+            &[LinkBenchmarkResponse::Success as u8],
+        )
+        .unwrap();
+    }
+}
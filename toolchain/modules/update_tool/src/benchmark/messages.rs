@@ -0,0 +1,43 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use crate::{common_messages::SerializableMessage, crc::crc8_update};
+
+#[repr(u8)]
+pub enum LinkBenchmarkCommand {
+    SendBenchmarkSize = 0x01,
+}
+
+#[repr(u8)]
+pub enum LinkBenchmarkResponse {
+    Success = 0xFF,
+}
+
+/// Number of bytes that are going to be streamed
+pub struct BenchmarkSizeMessage {
+    size: u32,
+}
+
+impl BenchmarkSizeMessage {
+    pub fn new(size: u32) -> Self {
+        Self { size: size }
+    }
+    pub const fn get_size() -> usize {
+        5
+    }
+}
+
+impl<'a> SerializableMessage<'a> for BenchmarkSizeMessage {
+    fn get_raw(&self) -> Vec<u8> {
+        let mut buffer = Vec::<u8>::new();
+        buffer.extend_from_slice(&self.size.to_le_bytes());
+        // Compute and append crc
+        let mut crc: u8 = 0x00;
+        for i in 0..buffer.len() {
+            crc8_update(&mut crc, buffer[i]);
+        }
+        buffer.push(crc);
+        buffer
+    }
+}
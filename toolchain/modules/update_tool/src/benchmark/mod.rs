@@ -0,0 +1,91 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+mod loopback;
+mod messages;
+
+use std::time::Instant;
+
+use crossbeam_channel::Receiver;
+use crossbeam_channel::Sender;
+use update_link::STREAM_BENCHMARK;
+
+use self::messages::*;
+use crate::common_messages::*;
+use crate::link::{link_send, read_command};
+use crate::utils::*;
+
+pub use self::loopback::emulate_device;
+
+/// Streams size bytes to the update component, then reports the effective throughput
+pub fn benchmark(
+    channel_in_consumer: Receiver<u8>,
+    channel_out_producer: Sender<Vec<u8>>,
+    size: usize,
+    window: usize,
+    verbose: bool,
+) {
+    let data: Vec<u8> = (0..size).map(|i| (i * 31 + 7) as u8).collect();
+    // Send hello message
+    let hello_msg = HelloMessage::new(OperationType::LinkBenchmark);
+    channel_flush_read(&channel_in_consumer);
+    channel_write(&channel_out_producer, &hello_msg.get_raw()).unwrap();
+    let mut buff: [u8; HelloResponseMessage::get_size()] = [0x00; HelloResponseMessage::get_size()];
+    channel_read(&channel_in_consumer, &mut buff).unwrap();
+    HelloResponseMessage::from(&buff).expect("Wrong response from device at HELLO");
+    if verbose {
+        println!("Got HELLO!");
+    }
+    // Wait for the size request
+    let command = read_command(&channel_in_consumer).unwrap();
+    if command != LinkBenchmarkCommand::SendBenchmarkSize as u8 {
+        eprintln!(
+            "Unexpected response from device (Size): {:?}",
+            MessageError::from(command)
+        );
+        return;
+    }
+    channel_write(
+        &channel_out_producer,
+        &BenchmarkSizeMessage::new(size as u32).get_raw(),
+    )
+    .unwrap();
+    // Stream the data
+    let start = Instant::now();
+    let result = link_send(
+        &channel_in_consumer,
+        &channel_out_producer,
+        STREAM_BENCHMARK,
+        &data,
+        window,
+        |_| {},
+    );
+    let stats = match result {
+        Ok(stats) => stats,
+        Err(e) => {
+            eprintln!("Transfer failed: {:?}", e);
+            return;
+        }
+    };
+    let elapsed = start.elapsed();
+    let command = read_command(&channel_in_consumer).unwrap();
+    if command != LinkBenchmarkResponse::Success as u8 {
+        eprintln!(
+            "Unexpected response from device at the end: {:?}",
+            MessageError::from(command)
+        );
+        return;
+    }
+    // Report
+    let seconds = elapsed.as_secs_f64();
+    println!(
+        "Transferred {} bytes in {:.3} s (window {})",
+        size, seconds, window
+    );
+    println!(
+        "Frames: {}, retransmissions: {}",
+        stats.frames, stats.retransmissions
+    );
+    println!("Effective throughput: {:.0} bytes/s", size as f64 / seconds);
+}
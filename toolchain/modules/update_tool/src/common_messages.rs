@@ -6,8 +6,6 @@ use std::fmt;
 
 use crate::crc::crc8_update;
 
-const PACKET_BUFFER_SIZE: usize = 64;
-
 #[derive(Clone, Copy, Debug)]
 pub enum MessageError {
     InvalidSize,
@@ -21,8 +19,12 @@ pub enum MessageError {
     DependencyError,
     MissingDependency,
     IllegalDowngrade,
-    //CannotFindComponent = 0xEC,
+    CannotFindComponent,
     //CannotFindVersion = 0xED,
+    NoResumableSession,
+    DeltaSourceMismatch,
+    LogUnavailable,
+    InvalidCursor,
 }
 
 impl From<u8> for MessageError {
@@ -39,6 +41,11 @@ impl From<u8> for MessageError {
             0xE9 => Self::DependencyError,
             0xEA => Self::MissingDependency,
             0xEB => Self::IllegalDowngrade,
+            0xEC => Self::CannotFindComponent,
+            0xEF => Self::NoResumableSession,
+            0xF0 => Self::DeltaSourceMismatch,
+            0xF1 => Self::LogUnavailable,
+            0xF2 => Self::InvalidCursor,
             _ => panic!("Unknown response"),
         }
     }
@@ -46,7 +53,7 @@ impl From<u8> for MessageError {
 
 impl fmt::Display for MessageError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "{:?}", self.to_string())
+        write!(f, "{:?}", self)
     }
 }
 
@@ -55,26 +62,34 @@ pub trait SerializableMessage<'a> {
     fn get_raw(&self) -> Vec<u8>;
 }
 
-pub trait FragmentedMessage<'a> {
-    fn get_next_fragment(&mut self) -> Option<Vec<u8>>;
-    fn get_next_fragment_number(&self) -> Option<usize>;
-    fn get_total_fragments(&self) -> usize;
-}
-
 #[derive(Clone, Copy, PartialEq)]
 #[repr(u8)]
 pub enum OperationType {
+    ComponentDelta = 0xC8,
+    LinkBenchmark = 0xC9,
     ComponentUpdate = 0xCA,
     SystemInfo = 0xCB,
+    ComponentResume = 0xCC,
+    CoreDumpRead = 0xCD,
     ComponentErase = 0xCE,
+    CoreDumpErase = 0xCF,
+    ComponentRead = 0xD0,
+    LogRead = 0xD1,
 }
 impl TryFrom<u8> for OperationType {
     type Error = MessageError;
     fn try_from(value: u8) -> Result<Self, MessageError> {
         match value {
+            0xC8 => Ok(OperationType::ComponentDelta),
+            0xC9 => Ok(OperationType::LinkBenchmark),
             0xCA => Ok(OperationType::ComponentUpdate),
             0xCB => Ok(OperationType::SystemInfo),
+            0xCC => Ok(OperationType::ComponentResume),
+            0xCD => Ok(OperationType::CoreDumpRead),
             0xCE => Ok(OperationType::ComponentErase),
+            0xCF => Ok(OperationType::CoreDumpErase),
+            0xD0 => Ok(OperationType::ComponentRead),
+            0xD1 => Ok(OperationType::LogRead),
             _ => Err(MessageError::InvalidOperation),
         }
     }
@@ -146,55 +161,3 @@ impl<'a> HelloResponseMessage<> {
         Ok(op)
     }
 }
-
-pub struct RawPacket<'a> {
-    buffer: &'a [u8],
-    current_pos: usize,
-}
-
-impl<'a> RawPacket<'a> {
-    pub fn new(buffer: &'a [u8]) -> Self {
-        Self {
-            buffer: buffer,
-            current_pos: 0,
-        }
-    }
-}
-
-impl<'a> FragmentedMessage<'a> for RawPacket<'a> {
-    fn get_next_fragment(&mut self) -> Option<Vec<u8>> {
-        // Check if we already consumed the whole buffer
-        if self.current_pos >= self.buffer.len() {
-            return None;
-        }
-        // Otherwise extract the new fragment
-        let fragment_size =
-            core::cmp::min(PACKET_BUFFER_SIZE, self.buffer.len() - self.current_pos);
-        let fragment = &self.buffer[self.current_pos..self.current_pos + fragment_size];
-        // Append to buffer
-        let mut buffer = Vec::<u8>::new();
-        buffer.extend_from_slice(fragment);
-        // Calculate crc
-        let mut crc: u8 = 0x00;
-        for i in 0..fragment_size {
-            crc8_update(&mut crc, buffer[i]);
-        }
-        buffer.push(crc);
-        // Go next in the message
-        self.current_pos += fragment_size;
-        // Return data
-        Some(buffer)
-    }
-
-    fn get_next_fragment_number(&self) -> Option<usize> {
-        // Check if we already consumed the whole buffer
-        if self.current_pos >= self.buffer.len() {
-            return None;
-        }
-        Some(self.current_pos / PACKET_BUFFER_SIZE)
-    }
-
-    fn get_total_fragments(&self) -> usize {
-        self.buffer.len() / PACKET_BUFFER_SIZE
-    }
-}
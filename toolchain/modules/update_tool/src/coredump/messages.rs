@@ -0,0 +1,284 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use crate::{
+    common_messages::MessageError,
+    crc::crc8_update,
+    utils::{u16_from_le_bytes, u32_from_le_bytes},
+};
+
+/**
+ * Core dump layout, must match abi::coredump
+ */
+pub const COREDUMP_MAGIC: u32 = 0x504D_4443;
+pub const COREDUMP_VERSION: u16 = 1;
+pub const COREDUMP_HEADER_SIZE: usize = 228;
+pub const COREDUMP_TASK_ENTRY_SIZE: usize = 28;
+pub const COREDUMP_NO_COMPONENT: u16 = 0xFFFF;
+pub const COREDUMP_NO_ADDRESS: u32 = 0xFFFF_FFFF;
+
+/// Size of the dump, sent by the device before the dump itself
+pub struct CoreDumpSizeMessage<'a> {
+    buffer: &'a [u8],
+}
+
+impl<'a> CoreDumpSizeMessage<'a> {
+    pub fn from(buffer: &'a [u8]) -> Result<Self, MessageError> {
+        // Validate buffer
+        Self::validate(buffer)?;
+        // Return instance
+        Ok(Self { buffer: buffer })
+    }
+    pub const fn get_size() -> usize {
+        5
+    }
+    pub fn get_dump_size(&self) -> u32 {
+        u32_from_le_bytes(&self.buffer[0..4])
+    }
+    fn validate(buffer: &'a [u8]) -> Result<(), MessageError> {
+        // Check message size
+        if buffer.len() != Self::get_size() {
+            return Err(MessageError::InvalidSize);
+        }
+        // Check CRC
+        let mut crc = 0x00;
+        for i in 0..(buffer.len() - 1) {
+            crc8_update(&mut crc, buffer[i]);
+        }
+        if crc != buffer[buffer.len() - 1] {
+            return Err(MessageError::InvalidCRC);
+        }
+        // Return
+        Ok(())
+    }
+}
+
+/**
+ * Parsed dump
+ */
+pub struct CoreDumpTask {
+    pub component_id: u16,
+    pub faulted: bool,
+    pub generation: u8,
+    pub component_version: u32,
+    pub block_base: u32,
+    pub text_base: u32,
+    pub sram_base: u32,
+    pub psp: u32,
+    pub pc: u32,
+}
+
+pub struct CoreDump {
+    pub reason: u16,
+    pub component_id: u16,
+    pub fault_kind: u32,
+    pub fault_arg0: u32,
+    pub fault_arg1: u32,
+    pub cfsr: u32,
+    pub mmfar: u32,
+    pub bfar: u32,
+    pub saved_state: Vec<u32>,
+    pub exception_frame: Vec<u32>,
+    pub kernel_sp: u32,
+    pub kernel_lr: u32,
+    pub stack_base: u32,
+    pub panic_line: u32,
+    pub panic_file: String,
+    pub tasks: Vec<CoreDumpTask>,
+    pub stack: Vec<u8>,
+}
+
+fn words(buffer: &[u8], count: usize) -> Vec<u32> {
+    (0..count)
+        .map(|i| u32_from_le_bytes(&buffer[4 * i..4 * i + 4]))
+        .collect()
+}
+
+impl CoreDump {
+    pub fn from(buffer: &[u8]) -> Result<Self, String> {
+        if buffer.len() < COREDUMP_HEADER_SIZE {
+            return Err(String::from("Dump too short"));
+        }
+        // Header
+        if u32_from_le_bytes(&buffer[0..4]) != COREDUMP_MAGIC {
+            return Err(String::from("Invalid magic"));
+        }
+        let version = u16_from_le_bytes(&buffer[4..6]);
+        if version != COREDUMP_VERSION {
+            return Err(format!("Unsupported dump version: {}", version));
+        }
+        let num_tasks = u16_from_le_bytes(&buffer[14..16]) as usize;
+        let stack_size = u32_from_le_bytes(&buffer[188..192]) as usize;
+        let panic_file_raw = &buffer[196..228];
+        let panic_file_len = panic_file_raw
+            .iter()
+            .position(|b| *b == 0)
+            .unwrap_or(panic_file_raw.len());
+        let expected_size =
+            COREDUMP_HEADER_SIZE + num_tasks * COREDUMP_TASK_ENTRY_SIZE + stack_size;
+        if buffer.len() < expected_size {
+            return Err(String::from("Dump truncated"));
+        }
+        // Tasks
+        let mut tasks: Vec<CoreDumpTask> = Vec::new();
+        for i in 0..num_tasks {
+            let entry = &buffer[COREDUMP_HEADER_SIZE + i * COREDUMP_TASK_ENTRY_SIZE..];
+            tasks.push(CoreDumpTask {
+                component_id: u16_from_le_bytes(&entry[0..2]),
+                faulted: entry[2] != 0,
+                generation: entry[3],
+                component_version: u32_from_le_bytes(&entry[4..8]),
+                block_base: u32_from_le_bytes(&entry[8..12]),
+                text_base: u32_from_le_bytes(&entry[12..16]),
+                sram_base: u32_from_le_bytes(&entry[16..20]),
+                psp: u32_from_le_bytes(&entry[20..24]),
+                pc: u32_from_le_bytes(&entry[24..28]),
+            });
+        }
+        // Stack
+        let stack_start = COREDUMP_HEADER_SIZE + num_tasks * COREDUMP_TASK_ENTRY_SIZE;
+        Ok(Self {
+            reason: u16_from_le_bytes(&buffer[6..8]),
+            component_id: u16_from_le_bytes(&buffer[12..14]),
+            fault_kind: u32_from_le_bytes(&buffer[16..20]),
+            fault_arg0: u32_from_le_bytes(&buffer[20..24]),
+            fault_arg1: u32_from_le_bytes(&buffer[24..28]),
+            cfsr: u32_from_le_bytes(&buffer[28..32]),
+            mmfar: u32_from_le_bytes(&buffer[32..36]),
+            bfar: u32_from_le_bytes(&buffer[36..40]),
+            saved_state: words(&buffer[40..144], 26),
+            exception_frame: words(&buffer[144..176], 8),
+            kernel_sp: u32_from_le_bytes(&buffer[176..180]),
+            kernel_lr: u32_from_le_bytes(&buffer[180..184]),
+            stack_base: u32_from_le_bytes(&buffer[184..188]),
+            panic_line: u32_from_le_bytes(&buffer[192..196]),
+            panic_file: String::from_utf8_lossy(&panic_file_raw[..panic_file_len]).to_string(),
+            tasks: tasks,
+            stack: buffer[stack_start..stack_start + stack_size].to_vec(),
+        })
+    }
+
+    pub fn reason_name(&self) -> &'static str {
+        match self.reason {
+            1 => "Kernel panic",
+            2 => "Component fault",
+            _ => "Unknown",
+        }
+    }
+
+    /// Decodes the fault, as encoded by abi::coredump::encode_fault
+    pub fn fault_description(&self) -> String {
+        let address = |a: u32| match a {
+            COREDUMP_NO_ADDRESS => String::from("unknown"),
+            a => format!("{:#010x}", a),
+        };
+        let source = |s: u32| match s {
+            0 => "user",
+            _ => "kernel",
+        };
+        match self.fault_kind {
+            0 => String::from("None"),
+            1 => format!(
+                "Memory access at {} from {}",
+                address(self.fault_arg0),
+                source(self.fault_arg1)
+            ),
+            2 => format!("Stack overflow at {:#010x}", self.fault_arg0),
+            3 => format!(
+                "Bus error at {} from {}",
+                address(self.fault_arg0),
+                source(self.fault_arg1)
+            ),
+            4 => String::from("Divide by zero"),
+            5 => String::from("Illegal text"),
+            6 => String::from("Illegal instruction"),
+            7 => format!("Invalid operation ({:#x})", self.fault_arg0),
+            8 => format!("Syscall usage error ({})", self.fault_arg0),
+            9 => String::from("Panic"),
+            10 => format!("Injected by component {}", self.fault_arg0),
+            11 => format!(
+                "Reported by server {} (reason {})",
+                self.fault_arg0, self.fault_arg1
+            ),
+            other => format!("Unknown fault kind {}", other),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{fs, path::PathBuf};
+
+    use super::*;
+
+    /// Dump of a memory access fault of component 15, with component 16 healthy
+    fn fixture() -> Vec<u8> {
+        let mut path = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        path.push("fixtures/coredump/component_fault.bin");
+        return fs::read(path).unwrap();
+    }
+
+    #[test]
+    fn test_layout() {
+        assert_eq!(COREDUMP_MAGIC, abi::coredump::COREDUMP_MAGIC);
+        assert_eq!(COREDUMP_VERSION, abi::coredump::COREDUMP_VERSION);
+        assert_eq!(COREDUMP_HEADER_SIZE, abi::coredump::COREDUMP_HEADER_SIZE);
+        assert_eq!(
+            COREDUMP_TASK_ENTRY_SIZE,
+            abi::coredump::COREDUMP_TASK_ENTRY_SIZE
+        );
+    }
+
+    #[test]
+    fn test_parse_fixture() {
+        let dump = CoreDump::from(&fixture()).unwrap();
+        assert_eq!(dump.reason_name(), "Component fault");
+        assert_eq!(dump.component_id, 15);
+        assert_eq!(
+            dump.fault_description(),
+            "Memory access at 0x20008000 from user"
+        );
+        assert_eq!((dump.cfsr, dump.mmfar, dump.bfar), (0x82, 0x2000_8000, 0));
+        // Registers
+        assert_eq!(
+            dump.exception_frame,
+            vec![0x1, 0x2, 0x3, 0x4, 0xC, 0x0800_9123, 0x0800_9156, 0x0100_0000]
+        );
+        assert_eq!(dump.saved_state.len(), 26);
+        assert_eq!(dump.saved_state[0], 0x4444_0004);
+        assert_eq!(dump.saved_state[7], 0xBBBB_000B);
+        assert_eq!(dump.saved_state[8], 0x2000_1F60);
+        assert_eq!(dump.saved_state[9], 0xFFFF_FFFD);
+        assert_eq!(dump.panic_file, "");
+        // Tasks
+        assert_eq!(dump.tasks.len(), 2);
+        let faulted = &dump.tasks[0];
+        assert_eq!(faulted.component_id, 15);
+        assert!(faulted.faulted);
+        assert_eq!(faulted.generation, 1);
+        assert_eq!(faulted.text_base, 0x0800_9040);
+        assert_eq!(faulted.sram_base, 0x2000_1000);
+        assert_eq!(faulted.pc, 0x0800_9156);
+        let healthy = &dump.tasks[1];
+        assert_eq!(healthy.component_id, 16);
+        assert!(!healthy.faulted);
+        assert_eq!(healthy.component_version, 2);
+        assert_eq!(healthy.psp, 0x2000_2F80);
+        // Stack, starting with the exception frame
+        assert_eq!(dump.stack_base, 0x2000_1F60);
+        assert_eq!(dump.stack.len(), 32);
+        assert_eq!(u32_from_le_bytes(&dump.stack[24..28]), 0x0800_9156);
+    }
+
+    #[test]
+    fn test_parse_invalid() {
+        let dump = fixture();
+        assert!(CoreDump::from(&dump[0..COREDUMP_HEADER_SIZE - 1]).is_err());
+        // The stack is missing
+        assert!(CoreDump::from(&dump[0..dump.len() - 4]).is_err());
+        let mut other = dump.clone();
+        other[0] ^= 0xFF;
+        assert!(CoreDump::from(&other).is_err());
+    }
+}
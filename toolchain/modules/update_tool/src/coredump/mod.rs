@@ -0,0 +1,258 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+mod messages;
+mod symbols;
+
+use crossbeam_channel::Receiver;
+use crossbeam_channel::Sender;
+use std::path::PathBuf;
+
+use self::messages::*;
+use self::symbols::SymbolTable;
+use crate::common_messages::*;
+use crate::crc::crc8_update;
+use crate::utils::*;
+
+const PACKET_BUFFER_SIZE: usize = 64;
+
+pub fn coredump(
+    channel_in_consumer: Receiver<u8>,
+    channel_out_producer: Sender<Vec<u8>>,
+    app_config: Option<String>,
+    output: Option<String>,
+    erase: bool,
+    verbose: bool,
+) {
+    // Retrieve the dump
+    let dump_bytes = read_coredump(&channel_in_consumer, &channel_out_producer, verbose);
+    if dump_bytes.is_empty() {
+        println!("No core dump available");
+        return;
+    }
+    // Save it, if requested
+    if let Some(output) = output {
+        std::fs::write(&output, &dump_bytes).expect(&format!("Cannot write dump to '{}'", output));
+        println!("Raw core dump saved in: {}", output);
+    }
+    // Decode it
+    let dump = match CoreDump::from(&dump_bytes) {
+        Ok(dump) => dump,
+        Err(e) => panic!("Cannot parse core dump: {}", e),
+    };
+    let symbols = match app_config {
+        Some(path) => load_symbols(&PathBuf::from(path), &dump, verbose),
+        None => SymbolTable::new(),
+    };
+    print_coredump(&dump, &symbols);
+    // Finally erase it, to allow a new dump to be taken
+    if erase {
+        erase_coredump(&channel_in_consumer, &channel_out_producer, verbose);
+        println!("Core dump erased");
+    }
+}
+
+fn begin_operation(
+    channel_in_consumer: &Receiver<u8>,
+    channel_out_producer: &Sender<Vec<u8>>,
+    operation: OperationType,
+    verbose: bool,
+) {
+    // Send hello message
+    let hello_msg = HelloMessage::new(operation);
+    channel_write(&channel_out_producer, &hello_msg.get_raw()).unwrap();
+    // Read hello response
+    let mut buff: [u8; HelloResponseMessage::get_size()] = [0x00; HelloResponseMessage::get_size()];
+    channel_read(&channel_in_consumer, &mut buff).unwrap();
+    // Validate hello response
+    HelloResponseMessage::from(&buff).expect("Wrong response from device at HELLO");
+    if verbose {
+        println!("Got HELLO!");
+    }
+}
+
+fn read_coredump(
+    channel_in_consumer: &Receiver<u8>,
+    channel_out_producer: &Sender<Vec<u8>>,
+    verbose: bool,
+) -> Vec<u8> {
+    begin_operation(
+        channel_in_consumer,
+        channel_out_producer,
+        OperationType::CoreDumpRead,
+        verbose,
+    );
+    // Step 1: read the size
+    let mut buff: [u8; CoreDumpSizeMessage::get_size()] = [0x00; CoreDumpSizeMessage::get_size()];
+    channel_read(&channel_in_consumer, &mut buff).unwrap();
+    let dump_size = CoreDumpSizeMessage::from(&buff)
+        .expect("Wrong size message from device")
+        .get_dump_size() as usize;
+    if verbose {
+        println!("Core dump size: {}", dump_size);
+    }
+    // Step 2: read the packets
+    let mut dump: Vec<u8> = Vec::with_capacity(dump_size);
+    let mut packet: [u8; PACKET_BUFFER_SIZE] = [0x00; PACKET_BUFFER_SIZE];
+    while dump.len() < dump_size {
+        let len = core::cmp::min(PACKET_BUFFER_SIZE - 1, dump_size - dump.len());
+        channel_read(&channel_in_consumer, &mut packet[0..len + 1]).unwrap();
+        let mut crc: u8 = 0x00;
+        for i in 0..len {
+            crc8_update(&mut crc, packet[i]);
+        }
+        if crc != packet[len] {
+            panic!("Invalid CRC while reading the core dump");
+        }
+        dump.extend_from_slice(&packet[0..len]);
+    }
+    dump
+}
+
+fn erase_coredump(
+    channel_in_consumer: &Receiver<u8>,
+    channel_out_producer: &Sender<Vec<u8>>,
+    verbose: bool,
+) {
+    begin_operation(
+        channel_in_consumer,
+        channel_out_producer,
+        OperationType::CoreDumpErase,
+        verbose,
+    );
+    let mut buff: [u8; 1] = [0x00; 1];
+    channel_read(&channel_in_consumer, &mut buff).unwrap();
+    if buff[0] != 0xFF {
+        panic!("Cannot erase core dump: {}", MessageError::from(buff[0]));
+    }
+}
+
+/// Loads the kernel and component symbols from the last build of the app
+fn load_symbols(app_config_path: &PathBuf, dump: &CoreDump, verbose: bool) -> SymbolTable {
+    if !app_config_path.exists() {
+        panic!("Cannot find app config at '{}'", app_config_path.display());
+    }
+    let app_config = app_config::read_configuration(&app_config_path.to_str().unwrap())
+        .expect("Cannot read app config");
+    let app_root = app_config_path.parent().unwrap().to_path_buf();
+    let root = app_root.parent().unwrap().parent().unwrap().to_path_buf();
+    let mut symbols = SymbolTable::new();
+    // Kernel
+    let mut kernel_elf = app_root.clone();
+    kernel_elf.push("build");
+    kernel_elf.push("kernel.elf");
+    if kernel_elf.exists() {
+        symbols.add_kernel(&kernel_elf);
+    } else {
+        println!("Cannot find kernel ELF at '{}'", kernel_elf.display());
+    }
+    // Components
+    for component_name in app_config.components.keys() {
+        let mut build_path = root.clone();
+        build_path.push("components");
+        build_path.push(component_name);
+        build_path.push("core");
+        build_path.push("build");
+        build_path.push(&app_config.board);
+        let mut config_path = build_path.clone();
+        config_path.push("Component.toml");
+        let mut elf_path = build_path.clone();
+        elf_path.push("image.elf");
+        if !config_path.exists() || !elf_path.exists() {
+            println!("Cannot find the build of component '{}'", component_name);
+            continue;
+        }
+        let config = component_config::read_component_config(config_path.to_str().unwrap())
+            .expect(&format!("Cannot read config of component '{}'", component_name));
+        // Use the position it had when the dump was taken
+        if let Some(task) = dump
+            .tasks
+            .iter()
+            .find(|t| t.component_id == config.component.id)
+        {
+            if verbose {
+                println!(
+                    "Loading symbols of '{}' [text: {:#010x}, sram: {:#010x}]",
+                    component_name, task.text_base, task.sram_base
+                );
+            }
+            symbols.add_component(&elf_path, task.text_base, task.sram_base);
+        }
+    }
+    symbols
+}
+
+fn format_address(symbols: &SymbolTable, address: u32) -> String {
+    match symbols.lookup(address) {
+        Some(name) => format!("{:#010x} <{}>", address, name),
+        None => format!("{:#010x}", address),
+    }
+}
+
+fn print_coredump(dump: &CoreDump, symbols: &SymbolTable) {
+    println!("------------- Core Dump -------------");
+    println!("Reason: {}", dump.reason_name());
+    if dump.component_id != COREDUMP_NO_COMPONENT {
+        println!("Component: {}", dump.component_id);
+    }
+    println!("Fault: {}", dump.fault_description());
+    println!(
+        "CFSR: {:#010x}\tMMFAR: {:#010x}\tBFAR: {:#010x}",
+        dump.cfsr, dump.mmfar, dump.bfar
+    );
+    if dump.reason == 1 {
+        println!("Panic at: {}:{}", dump.panic_file, dump.panic_line);
+        println!("Kernel SP: {:#010x}", dump.kernel_sp);
+        println!("Kernel LR: {}", format_address(symbols, dump.kernel_lr));
+    }
+    if dump.component_id != COREDUMP_NO_COMPONENT {
+        // Registers of the component
+        const FRAME_NAMES: [&str; 8] = ["r0", "r1", "r2", "r3", "r12", "lr", "pc", "xpsr"];
+        println!("\nRegisters:");
+        for (name, value) in FRAME_NAMES.iter().zip(dump.exception_frame.iter()) {
+            match *name {
+                "lr" | "pc" => println!("\t{}:\t{}", name, format_address(symbols, *value)),
+                _ => println!("\t{}:\t{:#010x}", name, value),
+            }
+        }
+        for i in 0..8 {
+            println!("\tr{}:\t{:#010x}", i + 4, dump.saved_state[i]);
+        }
+        println!("\tpsp:\t{:#010x}", dump.saved_state[8]);
+        println!("\texc_return:\t{:#010x}", dump.saved_state[9]);
+    }
+    // Tasks
+    println!("\nComponents:");
+    for task in &dump.tasks {
+        println!(
+            "\t{} [v {}, gen {}] {}\tblock: {:#010x}\tpsp: {:#010x}\tpc: {}",
+            task.component_id,
+            task.component_version,
+            task.generation,
+            match task.faulted {
+                true => "FAULTED",
+                false => "healthy",
+            },
+            task.block_base,
+            task.psp,
+            format_address(symbols, task.pc)
+        );
+    }
+    // Stack
+    if !dump.stack.is_empty() {
+        println!("\nStack ({} bytes from {:#010x}):", dump.stack.len(), dump.stack_base);
+        for (i, word) in dump.stack.chunks(4).enumerate() {
+            if word.len() < 4 {
+                break;
+            }
+            let value = u32_from_le_bytes(word);
+            let address = dump.stack_base + 4 * i as u32;
+            match symbols.lookup(value) {
+                Some(name) => println!("\t{:#010x}: {:#010x} <{}>", address, value, name),
+                None => println!("\t{:#010x}: {:#010x}", address, value),
+            }
+        }
+    }
+    println!("\n----------- ------------- -----------");
+}
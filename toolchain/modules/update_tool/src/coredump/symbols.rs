@@ -0,0 +1,93 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use std::path::PathBuf;
+
+use goblin::elf::sym::{STT_FUNC, STT_OBJECT};
+
+/// Addresses components are linked at, before relocation
+const LINKED_FLASH_BASE: u32 = 0x0800_0000;
+const LINKED_SRAM_BASE: u32 = 0x2000_0000;
+
+struct Symbol {
+    address: u32,
+    size: u32,
+    name: String,
+}
+
+/// Symbols of the kernel and of the components, at their runtime addresses
+pub struct SymbolTable {
+    symbols: Vec<Symbol>,
+}
+
+impl SymbolTable {
+    pub fn new() -> Self {
+        Self {
+            symbols: Vec::new(),
+        }
+    }
+
+    /// Loads the symbols of the kernel, that is never relocated
+    pub fn add_kernel(&mut self, elf_path: &PathBuf) {
+        self.add_elf(elf_path, |address| address);
+    }
+
+    /// Loads the symbols of a component, moving them where the component was relocated
+    pub fn add_component(&mut self, elf_path: &PathBuf, text_base: u32, sram_base: u32) {
+        self.add_elf(elf_path, |address| {
+            if address >= LINKED_SRAM_BASE {
+                address - LINKED_SRAM_BASE + sram_base
+            } else {
+                address - LINKED_FLASH_BASE + text_base
+            }
+        });
+    }
+
+    fn add_elf<F: Fn(u32) -> u32>(&mut self, elf_path: &PathBuf, relocate: F) {
+        let elf_bytes = std::fs::read(elf_path)
+            .expect(&format!("Cannot read ELF at '{}'", elf_path.display()));
+        let elf = goblin::elf::Elf::parse(&elf_bytes)
+            .expect(&format!("Cannot parse ELF at '{}'", elf_path.display()));
+        for sym in elf.syms.iter() {
+            let sym_type = sym.st_type();
+            if (sym_type != STT_FUNC && sym_type != STT_OBJECT) || sym.st_value == 0 {
+                continue;
+            }
+            let address = sym.st_value as u32;
+            if address < LINKED_FLASH_BASE {
+                continue; // Not in memory
+            }
+            let name = match elf.strtab.get_at(sym.st_name) {
+                Some(name) => name,
+                None => continue,
+            };
+            self.symbols.push(Symbol {
+                // Remove the thumb bit
+                address: relocate(address & !1),
+                size: sym.st_size as u32,
+                name: String::from(name),
+            });
+        }
+        self.symbols.sort_by_key(|s| s.address);
+    }
+
+    /// Returns "symbol+offset" for the address, if it belongs to a known symbol
+    pub fn lookup(&self, address: u32) -> Option<String> {
+        let address = address & !1;
+        let index = match self.symbols.binary_search_by_key(&address, |s| s.address) {
+            Ok(i) => i,
+            Err(0) => return None,
+            Err(i) => i - 1,
+        };
+        let symbol = &self.symbols[index];
+        let offset = address - symbol.address;
+        if offset >= symbol.size.max(1) {
+            return None;
+        }
+        match offset {
+            0 => Some(symbol.name.clone()),
+            _ => Some(format!("{}+{:#x}", symbol.name, offset)),
+        }
+    }
+}
@@ -0,0 +1,111 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use crate::crc::crc8_update;
+
+/**
+ * Packets of the uart-channel, when the multi-support feature is enabled:
+ *   +----------+------------+--------+------+-------+
+ *   | Preamble | Channel ID | Length | Data | CRC-8 |
+ *   +----------+------------+--------+------+-------+
+ *   | 4 bytes  | 2 bytes    | 2 bytes| len  | 1 byte|
+ *   +----------+------------+--------+------+-------+
+ * Channel ID and Length are big endian, the CRC covers everything but the preamble.
+ */
+const PREAMBLE_BYTE: u8 = 0xAA;
+const PREAMBLE_LEN: usize = 4;
+const HEADER_LEN: usize = 4;
+
+pub struct PacketReader {
+    preamble: usize,
+    header: Vec<u8>,
+    data: Vec<u8>,
+    len: usize,
+    crc: u8,
+}
+
+impl PacketReader {
+    pub fn new() -> Self {
+        Self {
+            preamble: 0,
+            header: Vec::new(),
+            data: Vec::new(),
+            len: 0,
+            crc: 0,
+        }
+    }
+
+    fn reset(&mut self) {
+        self.preamble = 0;
+        self.header.clear();
+        self.data.clear();
+        self.len = 0;
+        self.crc = 0;
+    }
+
+    /// Returns the channel and the data of the packet completed by this byte, if valid
+    pub fn push(&mut self, byte: u8) -> Option<(u16, Vec<u8>)> {
+        // Synchronize on the preamble
+        if self.preamble < PREAMBLE_LEN {
+            if byte == PREAMBLE_BYTE {
+                self.preamble += 1;
+            } else {
+                self.preamble = 0;
+            }
+            return None;
+        }
+        // Header
+        if self.header.len() < HEADER_LEN {
+            crc8_update(&mut self.crc, byte);
+            self.header.push(byte);
+            if self.header.len() == HEADER_LEN {
+                self.len = u16::from_be_bytes([self.header[2], self.header[3]]) as usize;
+            }
+            return None;
+        }
+        // Data
+        if self.data.len() < self.len {
+            crc8_update(&mut self.crc, byte);
+            self.data.push(byte);
+            return None;
+        }
+        // CRC
+        let channel_id = u16::from_be_bytes([self.header[0], self.header[1]]);
+        let result = match byte == self.crc {
+            true => Some((channel_id, std::mem::take(&mut self.data))),
+            false => None,
+        };
+        self.reset();
+        return result;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::PacketReader;
+    use crate::crc::crc8_update;
+
+    #[test]
+    fn test_packet_reader() {
+        let mut packet: Vec<u8> = vec![0x00, 0x06, 0x00, 0x02, 0x12, 0x34];
+        let mut crc: u8 = 0;
+        for b in &packet {
+            crc8_update(&mut crc, *b);
+        }
+        packet.push(crc);
+        let mut reader = PacketReader::new();
+        let mut result = None;
+        // Noise before the preamble is skipped
+        for b in [0x01, 0xAA, 0xAA, 0xAA, 0xAA].iter().chain(packet.iter()) {
+            result = reader.push(*b);
+        }
+        assert_eq!(result, Some((6, vec![0x12, 0x34])));
+        // Corrupted packets are dropped
+        packet[5] ^= 0xFF;
+        for b in [0xAA, 0xAA, 0xAA, 0xAA].iter().chain(packet.iter()) {
+            result = reader.push(*b);
+        }
+        assert_eq!(result, None);
+    }
+}
@@ -2,17 +2,35 @@
 // License, v. 2.0. If a copy of the MPL was not distributed with this
 // file, You can obtain one at https://mozilla.org/MPL/2.0/.
 
+mod channel;
+
 use signal_hook::consts::SIGTERM;
 
+use self::channel::PacketReader;
+use abi::LOG_CHANNEL_ID;
+use crate::gdb::{log_string_tables, symbol_commands};
 use crate::utils::*;
+use crossbeam_channel::{Receiver, Sender};
+use log_strings::{FrameReader, LogDecoder};
 use std::fs::File;
 use std::io::{BufReader, Read};
 use std::path::PathBuf;
 use std::process::{Child, Command};
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+
+/// ITM port used by userlib for text logs
+const ITM_TEXT_PORT: u8 = 0;
+/// ITM port used by userlib for deferred logs (log-deferred-itm)
+const ITM_DEFERRED_PORT: u8 = 1;
 
-pub fn debug(app_config: String, verbose: bool) {
+pub fn debug(
+    app_config: String,
+    live: Option<(&Receiver<u8>, &Sender<Vec<u8>>)>,
+    log_port: Option<String>,
+    verbose: bool,
+) {
     // Validate paths
     let app_config_path = PathBuf::from(app_config);
     if !app_config_path.exists() {
@@ -27,12 +45,24 @@ pub fn debug(app_config: String, verbose: bool) {
     // Extract needed information
     let target_chip = openocd_board_to_chip(&app_config.board);
     let target_freq = app_config.clock_speed as u32;
+    // Find where the symbols are
+    let symbol_commands = symbol_commands(&app_config_path, None, live, verbose);
+    // Deferred logs decoding
+    let tables = log_string_tables(&app_config_path, None);
+    if verbose {
+        println!("Loaded log strings of {} components", tables.len());
+    }
+    let log_decoder = Arc::new(LogDecoder::new(tables));
+    let mut frame_reader = FrameReader::new();
+    if let Some(log_port) = log_port {
+        channel_log_start(log_port, log_decoder.clone(), should_terminate.clone());
+    }
     // Create the channel
     let channel = create_channel(verbose);
     // Start openocd
     let mut openocd = openocd_start(&target_chip, verbose);
     // Start gdb
-    let mut gdb = gdb_start(&channel, target_freq, verbose);
+    let mut gdb = gdb_start(&channel, target_freq, &symbol_commands, verbose);
     // Parsing loop
     let mut reader = BufReader::new(File::open(channel).expect("Cannot open itm channel pipe"));
     let mut tmp_buff: Vec<u8> = Vec::new();
@@ -46,11 +76,18 @@ pub fn debug(app_config: String, verbose: bool) {
         let pkt = packet_result.unwrap();
         match pkt.kind() {
             itm::packet::Kind::Instrumentation(data) => match data.port() {
-                0 => {
+                ITM_TEXT_PORT => {
                     let message =
                         std::str::from_utf8(data.payload()).expect("Cannot parse ITM packet");
                     print!("{}", message);
                 }
+                ITM_DEFERRED_PORT => {
+                    for b in data.payload() {
+                        if let Some(frame) = frame_reader.push(*b) {
+                            println!("{}", log_decoder.decode(&frame));
+                        }
+                    }
+                }
                 _ => {
                     let message =
                         std::str::from_utf8(data.payload()).expect("Cannot parse ITM packet");
@@ -65,6 +102,42 @@ pub fn debug(app_config: String, verbose: bool) {
     openocd.kill().unwrap();
 }
 
+/// Decodes the deferred logs sent on the uart-channel log channel.
+/// The port carries the multi-support framing, packets of other channels are ignored.
+fn channel_log_start(
+    log_port: String,
+    log_decoder: Arc<LogDecoder>,
+    should_terminate: Arc<AtomicBool>,
+) -> JoinHandle<()> {
+    let mut port = serialport::new(&log_port, crate::transport::SERIAL_BAUDRATE)
+        .timeout(std::time::Duration::from_millis(100))
+        .open()
+        .expect("Cannot open log serial port");
+    thread::spawn(move || {
+        let mut packet_reader = PacketReader::new();
+        let mut frame_reader = FrameReader::new();
+        let mut buffer: [u8; 64] = [0x00; 64];
+        while !should_terminate.load(Ordering::Relaxed) {
+            let len = match port.read(&mut buffer) {
+                Ok(len) => len,
+                Err(_) => continue, // Timeout
+            };
+            for b in &buffer[0..len] {
+                if let Some((channel_id, data)) = packet_reader.push(*b) {
+                    if channel_id != LOG_CHANNEL_ID {
+                        continue;
+                    }
+                    for d in data {
+                        if let Some(frame) = frame_reader.push(d) {
+                            println!("{}", log_decoder.decode(&frame));
+                        }
+                    }
+                }
+            }
+        }
+    })
+}
+
 fn create_channel(verbose: bool) -> PathBuf {
     let channel_file = PathBuf::from("/tmp/concept-os-itm-fifo");
     if !channel_file.exists() {
@@ -95,8 +168,16 @@ fn openocd_start(target_chip: &String, verbose: bool) -> Child {
     status.unwrap()
 }
 
-fn gdb_start(channel: &PathBuf, target_freq: u32, verbose: bool) -> Child {
+fn gdb_start(
+    channel: &PathBuf,
+    target_freq: u32,
+    symbol_commands: &Vec<String>,
+    verbose: bool,
+) -> Child {
     let mut cmd = Command::new("gdb-multiarch");
+    for command in symbol_commands {
+        cmd.arg("-ex").arg(command);
+    }
     cmd.arg("-ex")
         .arg("target extended-remote localhost:3333")
         .arg("-ex")
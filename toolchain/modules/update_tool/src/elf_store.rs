@@ -0,0 +1,197 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use crate::flash_component::expand_cbf;
+use cbf_rs::CbfFile;
+use goblin::elf::section_header::SHF_ALLOC;
+use std::path::{Path, PathBuf};
+
+/// Addresses components are linked at, before relocation
+const LINKED_FLASH_BASE: u32 = 0x0800_0000;
+const LINKED_SRAM_BASE: u32 = 0x2000_0000;
+
+const STORE_ENV: &str = "CONCEPT_OS_ELF_STORE";
+const CBF_FILE: &str = "component.cbf";
+const ELF_FILE: &str = "image.elf";
+
+/**
+ * Local store of the components flashed on devices, keyed by ID and version:
+ *      <store>/<id>/<version>/component.cbf
+ *      <store>/<id>/<version>/image.elf
+ * By default it's placed in ~/.concept-os/elf-store, unless CONCEPT_OS_ELF_STORE is set.
+ */
+pub struct ElfStore {
+    root: PathBuf,
+}
+
+impl ElfStore {
+    pub fn open() -> Self {
+        let root = match std::env::var(STORE_ENV) {
+            Ok(path) => PathBuf::from(path),
+            Err(_) => {
+                let mut path = PathBuf::from(std::env::var("HOME").expect("Cannot find home"));
+                path.push(".concept-os");
+                path.push("elf-store");
+                path
+            }
+        };
+        Self { root: root }
+    }
+
+    fn entry_path(&self, component_id: u16, component_version: u32) -> PathBuf {
+        let mut path = self.root.clone();
+        path.push(component_id.to_string());
+        path.push(component_version.to_string());
+        path
+    }
+
+    /// Saves a component, together with the ELF it was generated from
+    pub fn store(&self, cbf_path: &PathBuf, elf_path: &PathBuf) {
+        let cbf_bytes = std::fs::read(cbf_path)
+            .expect(&format!("Cannot read CBF at '{}'", cbf_path.display()));
+        let cbf = cbf_rs::parse_cbf(&cbf_bytes).expect("Cannot parse CBF");
+        let entry = self.entry_path(
+            cbf.header_base().component_id(),
+            cbf.header_base().component_version(),
+        );
+        std::fs::create_dir_all(&entry).expect("Cannot create ELF store entry");
+        let mut dest = entry.clone();
+        dest.push(CBF_FILE);
+        std::fs::write(&dest, &cbf_bytes).expect("Cannot save CBF in ELF store");
+        let mut dest = entry.clone();
+        dest.push(ELF_FILE);
+        std::fs::copy(elf_path, &dest).expect("Cannot save ELF in ELF store");
+    }
+
+    /// Generates the GDB command that loads the symbols of the component,
+    /// placed at the given flash block and SRAM base
+    pub fn gdb_symbol_command(
+        &self,
+        component_id: u16,
+        component_version: u32,
+        block_base_address: u32,
+        sram_base_address: u32,
+    ) -> Option<String> {
+        let entry = self.entry_path(component_id, component_version);
+        let mut cbf_path = entry.clone();
+        cbf_path.push(CBF_FILE);
+        let mut elf_path = entry.clone();
+        elf_path.push(ELF_FILE);
+        if !cbf_path.exists() || !elf_path.exists() {
+            return None;
+        }
+        let cbf_bytes = std::fs::read(&cbf_path).ok()?;
+        let elf_bytes = std::fs::read(&elf_path).ok()?;
+        symbol_command(
+            &elf_path,
+            cbf_bytes,
+            &elf_bytes,
+            block_base_address,
+            sram_base_address,
+        )
+    }
+}
+
+/// `add-symbol-file` command of the ELF, with each section moved where the
+/// component has been placed
+fn symbol_command(
+    elf_path: &Path,
+    cbf_bytes: Vec<u8>,
+    elf_bytes: &[u8],
+    block_base_address: u32,
+    sram_base_address: u32,
+) -> Option<String> {
+    // Saved as it was flashed, while the device stores the payload uncompressed
+    let (cbf_bytes, _) = expand_cbf(cbf_bytes);
+    let cbf = cbf_rs::parse_cbf(&cbf_bytes).ok()?;
+    // The read-only payload is placed after the SRAM base and size
+    let text_base = block_base_address + 8 + cbf.read_only_section().offset();
+    // Relocate each section
+    let elf = goblin::elf::Elf::parse(elf_bytes).ok()?;
+    let mut text_address: Option<u32> = None;
+    let mut other_sections = String::new();
+    for section in &elf.section_headers {
+        if section.sh_flags & SHF_ALLOC as u64 == 0 || section.sh_addr == 0 {
+            continue;
+        }
+        let address = section.sh_addr as u32;
+        let relocated = if address >= LINKED_SRAM_BASE {
+            address - LINKED_SRAM_BASE + sram_base_address
+        } else if address >= LINKED_FLASH_BASE {
+            address - LINKED_FLASH_BASE + text_base
+        } else {
+            continue;
+        };
+        let name = elf.shdr_strtab.get_at(section.sh_name)?;
+        if name == ".text" {
+            text_address = Some(relocated);
+        } else {
+            other_sections += &format!(" -s {} {:#010x}", name, relocated);
+        }
+    }
+    Some(format!(
+        "add-symbol-file {} {:#010x}{}",
+        elf_path.display(),
+        text_address?,
+        other_sections
+    ))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const BLOCK_BASE: u32 = 0x0802_0000;
+    const SRAM_BASE: u32 = 0x2000_4000;
+
+    fn example_path(file: &str) -> PathBuf {
+        let mut path = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        path.push("../elf2cbf/examples/component3/output");
+        path.push(file);
+        path
+    }
+
+    /// The CBF as elf2cbf emits it with --compress
+    fn compress(cbf_bytes: &[u8]) -> Vec<u8> {
+        let cbf = cbf_rs::parse_cbf(cbf_bytes).unwrap();
+        let payload_start = cbf.read_only_section().offset() as usize;
+        let payload_end = cbf.header_base().offset_trailer() as usize;
+        // Flags after the priority in the main header
+        let flags_offset = cbf.header_base().get_raw().len() + 2;
+        let flags = u16::from_le_bytes([cbf_bytes[flags_offset], cbf_bytes[flags_offset + 1]])
+            | cbf_rs::ComponentFlags::COMPRESSED.bits();
+        let mut compressed = cbf_bytes[0..payload_start].to_vec();
+        compressed[flags_offset..flags_offset + 2].copy_from_slice(&flags.to_le_bytes());
+        heatshrink::compress(&cbf_bytes[payload_start..payload_end], |b| compressed.push(b));
+        compressed.extend_from_slice(&cbf_bytes[payload_end..]);
+        compressed
+    }
+
+    #[test]
+    fn section_offsets() {
+        // Linked with .text and .rodata at 0x08000000 (0x120 bytes of .text), .data
+        // and .bss at 0x20000000 (0x28 bytes of .data). The payload starts at 0xB8
+        // in the CBF, after the SRAM base and size in the block.
+        let elf_path = example_path("image.elf");
+        let elf_bytes = std::fs::read(&elf_path).unwrap();
+        let cbf_bytes = std::fs::read(example_path("component.cbf")).unwrap();
+        let expected = format!(
+            "add-symbol-file {} 0x080200c0 -s .rodata 0x080201e0 -s .data 0x20004000 -s .bss 0x20004028",
+            elf_path.display()
+        );
+        let command = symbol_command(
+            &elf_path,
+            cbf_bytes.clone(),
+            &elf_bytes,
+            BLOCK_BASE,
+            SRAM_BASE,
+        );
+        assert_eq!(command.as_deref(), Some(expected.as_str()));
+        // Same offsets from the compressed CBF
+        let compressed = compress(&cbf_bytes);
+        assert!(compressed.len() < cbf_bytes.len());
+        let command = symbol_command(&elf_path, compressed, &elf_bytes, BLOCK_BASE, SRAM_BASE);
+        assert_eq!(command.as_deref(), Some(expected.as_str()));
+    }
+}
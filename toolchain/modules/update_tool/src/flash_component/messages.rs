@@ -2,7 +2,10 @@
 // License, v. 2.0. If a copy of the MPL was not distributed with this
 // file, You can obtain one at https://mozilla.org/MPL/2.0/.
 
-use crate::{common_messages::SerializableMessage, crc::crc8_update};
+use crate::{
+    common_messages::{MessageError, SerializableMessage},
+    crc::crc8_update,
+};
 
 #[repr(u8)]
 pub enum ComponentUpdateCommand {
@@ -10,7 +13,10 @@ pub enum ComponentUpdateCommand {
     SendComponentVariableHeader = 0x02,
     SendComponentPayload = 0x03,
     SendComponentTrailer = 0x04,
-    SendNextFragment = 0xA0
+    SendSessionId = 0x05,
+    ResumeFromOffset = 0x06,
+    SendCompressedSize = 0x07,
+    SendDeltaSource = 0x08,
 }
 
 #[repr(u8)]
@@ -49,3 +55,132 @@ impl<'a> SerializableMessage<'a> for FixedHeaderMessage<'a> {
     }
 }
 
+/// Identifies the interrupted update to resume
+pub struct SessionIdMessage {
+    session_id: u32,
+}
+
+impl SessionIdMessage {
+    pub fn new(session_id: u32) -> Self {
+        Self {
+            session_id: session_id,
+        }
+    }
+}
+
+impl<'a> SerializableMessage<'a> for SessionIdMessage {
+    fn get_raw(&self) -> Vec<u8> {
+        let mut buffer = Vec::<u8>::new();
+        buffer.extend_from_slice(&self.session_id.to_le_bytes());
+        // Compute and append crc
+        let mut crc: u8 = 0x00;
+        for i in 0..buffer.len() {
+            crc8_update(&mut crc, buffer[i]);
+        }
+        buffer.push(crc);
+        buffer
+    }
+}
+
+/// Length of the compressed payload stream
+pub struct CompressedSizeMessage {
+    compressed_size: u32,
+}
+
+impl CompressedSizeMessage {
+    pub fn new(compressed_size: u32) -> Self {
+        Self {
+            compressed_size: compressed_size,
+        }
+    }
+}
+
+impl<'a> SerializableMessage<'a> for CompressedSizeMessage {
+    fn get_raw(&self) -> Vec<u8> {
+        let mut buffer = Vec::<u8>::new();
+        buffer.extend_from_slice(&self.compressed_size.to_le_bytes());
+        // Compute and append crc
+        let mut crc: u8 = 0x00;
+        for i in 0..buffer.len() {
+            crc8_update(&mut crc, buffer[i]);
+        }
+        buffer.push(crc);
+        buffer
+    }
+}
+
+/// Installed component the delta was made for, and length of the patch
+pub struct DeltaSourceMessage {
+    component_id: u16,
+    component_version: u32,
+    source_hash: u32,
+    patch_size: u32,
+}
+
+impl DeltaSourceMessage {
+    pub fn new(component_id: u16, component_version: u32, source_hash: u32, patch_size: u32) -> Self {
+        Self {
+            component_id: component_id,
+            component_version: component_version,
+            source_hash: source_hash,
+            patch_size: patch_size,
+        }
+    }
+}
+
+impl<'a> SerializableMessage<'a> for DeltaSourceMessage {
+    fn get_raw(&self) -> Vec<u8> {
+        let mut buffer = Vec::<u8>::new();
+        buffer.extend_from_slice(&self.component_id.to_le_bytes());
+        buffer.extend_from_slice(&self.component_version.to_le_bytes());
+        buffer.extend_from_slice(&self.source_hash.to_le_bytes());
+        buffer.extend_from_slice(&self.patch_size.to_le_bytes());
+        // Compute and append crc
+        let mut crc: u8 = 0x00;
+        for i in 0..buffer.len() {
+            crc8_update(&mut crc, buffer[i]);
+        }
+        buffer.push(crc);
+        buffer
+    }
+}
+
+/// Progress of the interrupted update, as reported by the device
+pub struct ResumeStatusMessage {
+    committed_offset: u32,
+    validation_checksum: u32,
+}
+
+impl ResumeStatusMessage {
+    pub fn from(buffer: &[u8]) -> Result<Self, MessageError> {
+        // Check message size
+        if buffer.len() != Self::get_size() {
+            return Err(MessageError::InvalidSize);
+        }
+        // Check command
+        if buffer[0] != ComponentUpdateCommand::ResumeFromOffset as u8 {
+            return Err(MessageError::InvalidOperation);
+        }
+        // Check CRC
+        let mut crc = 0x00;
+        for i in 0..(buffer.len() - 1) {
+            crc8_update(&mut crc, buffer[i]);
+        }
+        if crc != buffer[buffer.len() - 1] {
+            return Err(MessageError::InvalidCRC);
+        }
+        Ok(Self {
+            committed_offset: u32::from_le_bytes(buffer[1..5].try_into().unwrap()),
+            validation_checksum: u32::from_le_bytes(buffer[5..9].try_into().unwrap()),
+        })
+    }
+    pub const fn get_size() -> usize {
+        10
+    }
+    pub fn get_committed_offset(&self) -> u32 {
+        self.committed_offset
+    }
+    pub fn get_validation_checksum(&self) -> u32 {
+        self.validation_checksum
+    }
+}
@@ -8,46 +8,198 @@ use crossbeam_channel::Receiver;
 use crossbeam_channel::Sender;
 use cbf_rs::CbfFile;
 use pbr::ProgressBar;
-use std::{io::Stdout, path::PathBuf};
+use std::{fmt, io::Stdout, path::PathBuf, time::Instant};
 
 use self::messages::*;
 use crate::common_messages::*;
+use crate::elf_store::ElfStore;
+use crate::link::{link_send, read_command, LinkSendError};
+use crate::make_delta::DeltaFile;
 use crate::utils::*;
+use update_link::{STREAM_PAYLOAD, STREAM_VARIABLE_HEADER};
+
+/// Size of the CBF trailer (the checksum)
+const TRAILER_SIZE: usize = 4;
+/// The only step the device keeps checkpoints of
+const PAYLOAD_STEP: &str = "fourth step (Payload)";
+
+/// A CBF ready to be sent
+pub struct ComponentImage {
+    /// The CBF with the payload uncompressed, as the device is going to store it
+    cbf_bytes: Vec<u8>,
+    /// The payload as stored in the CBF, when compressed
+    compressed_payload: Option<Vec<u8>>,
+}
+
+/// How the component is going to be sent
+pub struct Transfer<'a> {
+    /// Fragments in flight on the link
+    window: usize,
+    image: &'a ComponentImage,
+    /// Sent in place of the payload, if the device has its source
+    delta: Option<DeltaFile>,
+    started: Instant,
+}
+
+/// Where the update reports its progress, in bytes of the image stored by the device
+pub trait UpdateProgress {
+    fn message(&mut self, message: &str);
+    fn inc(&mut self) -> u64;
+    fn add(&mut self, delta: u64) -> u64;
+    fn set(&mut self, done: u64) -> u64;
+    fn finish(&mut self);
+}
+
+impl UpdateProgress for ProgressBar<Stdout> {
+    fn message(&mut self, message: &str) {
+        ProgressBar::message(self, message)
+    }
+    fn inc(&mut self) -> u64 {
+        ProgressBar::inc(self)
+    }
+    fn add(&mut self, delta: u64) -> u64 {
+        ProgressBar::add(self, delta)
+    }
+    fn set(&mut self, done: u64) -> u64 {
+        ProgressBar::set(self, done)
+    }
+    fn finish(&mut self) {
+        ProgressBar::finish(self)
+    }
+}
+
+#[derive(Debug)]
+pub enum FlashError {
+    /// Wrong answer to the HELLO
+    Hello,
+    /// The device refused a step of the update
+    Device(&'static str, MessageError),
+    /// The data of a step did not get through
+    Link(&'static str, LinkSendError),
+    /// The device was receiving a different image
+    CannotResume,
+    /// The device asked for the size of a compressed payload, the CBF is not
+    NotCompressed,
+    /// The connection to the device is gone
+    Closed,
+}
+
+impl From<ChannelClosed> for FlashError {
+    fn from(_: ChannelClosed) -> Self {
+        FlashError::Closed
+    }
+}
+
+impl FlashError {
+    /// The update can be continued with --resume
+    pub fn is_resumable(&self) -> bool {
+        matches!(
+            self,
+            FlashError::Link(
+                PAYLOAD_STEP,
+                LinkSendError::Timeout
+                    | LinkSendError::Device(MessageError::TimeoutError | MessageError::InvalidCRC)
+            )
+        )
+    }
+}
+
+impl fmt::Display for FlashError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            FlashError::Hello => write!(f, "Wrong response from device at HELLO"),
+            FlashError::Device(step, e) => {
+                write!(f, "Unexpected response from device at {}: {:?}", step, e)
+            }
+            FlashError::Link(_, LinkSendError::Closed) | FlashError::Closed => {
+                write!(f, "{}", ChannelClosed)
+            }
+            FlashError::Link(step, e) => {
+                write!(f, "Unexpected response from device at {}: {:?}", step, e)
+            }
+            FlashError::CannotResume => {
+                write!(f, "The device was receiving a different image, cannot resume")
+            }
+            FlashError::NotCompressed => write!(f, "The device expects a compressed payload"),
+        }
+    }
+}
+
+impl ComponentImage {
+    /// Reads the CBF, checking its integrity
+    pub fn load(cbf_path: &PathBuf) -> Self {
+        if !cbf_path.exists() {
+            panic!("Cannot find the CBF at '{}'", cbf_path.display());
+        }
+        // Read the whole cbf in memory (surely small for a PC)
+        let cbf_bytes = std::fs::read(cbf_path)
+            .expect(&format!("Cannot open the CBF at '{}'", cbf_path.display()));
+        // A compressed CBF is checked on the image the device is going to store
+        let (cbf_bytes, compressed_payload) = expand_cbf(cbf_bytes);
+        // Parse the cbf
+        let cbf_result = cbf_rs::parse_cbf(&cbf_bytes);
+        if cbf_result.is_err() {
+            match cbf_result.unwrap_err() {
+                cbf_rs::Error::BufferTooShort | cbf_rs::Error::InvalidMagic => {
+                    panic!("CBF file not valid!")
+                }
+                cbf_rs::Error::UnsupportedVersion => {
+                    panic!("CBF version still not supported by the tool")
+                }
+            }
+        }
+        // Validate cbf
+        if !cbf_result.unwrap().validate() {
+            panic!("CBF file integrity test failed!");
+        }
+        Self {
+            cbf_bytes: cbf_bytes,
+            compressed_payload: compressed_payload,
+        }
+    }
+
+    pub fn cbf(&self) -> impl CbfFile + '_ {
+        cbf_rs::parse_cbf(&self.cbf_bytes).unwrap()
+    }
+
+    pub fn component_id(&self) -> u16 {
+        self.cbf().header_base().component_id()
+    }
+
+    pub fn component_version(&self) -> u32 {
+        self.cbf().header_base().component_version()
+    }
+
+    /// Steps of the update, as counted by the progress
+    pub fn progress_total(&self) -> u64 {
+        (self.cbf().header_base().total_size() + 4) as u64
+    }
+}
 
 pub fn flash_component(
     channel_in_consumer: Receiver<u8>,
     channel_out_producer: Sender<Vec<u8>>,
     cbf_file: String,
+    elf_file: Option<String>,
+    delta_file: Option<String>,
+    resume: bool,
+    window: usize,
     verbose: bool,
 ) {
     if verbose {
         println!("---> Flashing Component");
     }
-    // First, check the cbf path
+    // First, check the cbf
     let cbf_path = PathBuf::from(cbf_file.clone());
-    if !cbf_path.exists() {
-        panic!("Cannot find the CBF at '{}'", cbf_file);
-    }
-    // Read the whole cbf in memory (surely small for a PC)
-    let cbf_bytes =
-        std::fs::read(cbf_path).expect(&format!("Cannot open the CBF at '{}'", cbf_file));
-    // Parse the cbf
-    let cbf_result = cbf_rs::parse_cbf(&cbf_bytes);
-    if cbf_result.is_err() {
-        match cbf_result.unwrap_err() {
-            cbf_rs::Error::BufferTooShort | cbf_rs::Error::InvalidMagic => {
-                panic!("CBF file not valid!")
-            }
-            cbf_rs::Error::UnsupportedVersion => {
-                panic!("CBF version still not supported by the tool")
-            }
+    let image = ComponentImage::load(&cbf_path);
+    let cbf = image.cbf();
+    // The delta must generate exactly this CBF
+    let delta = delta_file.map(|delta_file| DeltaFile::load(&delta_file));
+    if let Some(delta) = &delta {
+        if !delta.targets(&cbf) {
+            panic!("The delta was not generated for this CBF");
         }
     }
-    let cbf = cbf_result.unwrap();
-    // Validate cbf
-    if !cbf.validate() {
-        panic!("CBF file integrity test failed!");
-    }
     // If verbose, print some info
     if verbose {
         println!("\n\tComponent ID: {}", cbf.header_base().component_id());
@@ -61,208 +213,430 @@ pub fn flash_component(
             cbf.header_main().component_min_ram()
         );
     }
+    // Keep the ELF, to be able to load the symbols once the component is placed.
+    // Same ID and version means same content, so it's fine to save it in advance.
+    match find_component_elf(&cbf_path, elf_file) {
+        Some(elf_path) => ElfStore::open().store(&cbf_path, &elf_path),
+        None => println!("Cannot find the ELF of the component, symbols will not be available"),
+    }
     // Send hello
     println!("");
-    let mut progress = ProgressBar::new((cbf.header_base().total_size() + 4) as u64);
+    let mut progress = ProgressBar::new(image.progress_total());
     progress.show_speed = false;
     progress.show_counter = false;
     progress.show_time_left = false;
     progress.set_width(Some(80));
-    begin_communication(
+    let had_delta = delta.is_some();
+    let result = update_component(
         &channel_in_consumer,
         &channel_out_producer,
-        &cbf,
+        &image,
+        delta,
+        resume,
+        window,
         &mut progress,
         verbose,
     );
+    let transfer = match result {
+        Ok(transfer) => transfer,
+        Err(e) => {
+            eprintln!("\n{}", e);
+            if e.is_resumable() {
+                eprintln!("The update can be continued with --resume");
+            }
+            return;
+        }
+    };
+
+    println!("\nSuccess!");
+    println!(
+        "Transfer time: {:.1} s",
+        transfer.started.elapsed().as_secs_f64()
+    );
+    let payload_size = extract_payload(&cbf).len();
+    if let Some(delta) = &transfer.delta {
+        println!(
+            "Payload sent as a delta of {} bytes ({:.1}%)",
+            delta.patch.len(),
+            100.0 * delta.patch.len() as f64 / payload_size as f64
+        );
+    } else {
+        if had_delta {
+            println!("The device could not apply the delta, the whole component was sent");
+        }
+        if let Some(compressed_payload) = &image.compressed_payload {
+            println!(
+                "Payload compressed from {} to {} bytes ({:.1}%)",
+                payload_size,
+                compressed_payload.len(),
+                100.0 * compressed_payload.len() as f64 / payload_size as f64
+            );
+        }
+    }
 }
 
-fn begin_communication(
+/// Sends the component to the device, as a delta if given (falling back to the
+/// whole component if the device does not have its source)
+pub fn update_component<'a>(
     channel_in_consumer: &Receiver<u8>,
     channel_out_producer: &Sender<Vec<u8>>,
-    cbf: &dyn CbfFile,
-    progress: &mut ProgressBar<Stdout>,
+    image: &'a ComponentImage,
+    delta: Option<DeltaFile>,
+    resume: bool,
+    window: usize,
+    progress: &mut dyn UpdateProgress,
     verbose: bool,
-) {
+) -> Result<Transfer<'a>, FlashError> {
+    let cbf = image.cbf();
+    let mut transfer = Transfer {
+        window: window,
+        image: image,
+        delta: delta,
+        started: Instant::now(),
+    };
+    if resume {
+        resume_communication(
+            channel_in_consumer,
+            channel_out_producer,
+            &cbf,
+            &transfer,
+            progress,
+            verbose,
+        )?;
+        return Ok(transfer);
+    }
+    let result = begin_communication(
+        channel_in_consumer,
+        channel_out_producer,
+        &cbf,
+        &transfer,
+        progress,
+        verbose,
+    );
+    if let Err(FlashError::Device(_, MessageError::DeltaSourceMismatch)) = result {
+        // The installed version is not the source of the delta
+        transfer.delta = None;
+        progress.set(0);
+        begin_communication(
+            channel_in_consumer,
+            channel_out_producer,
+            &cbf,
+            &transfer,
+            progress,
+            verbose,
+        )?;
+        return Ok(transfer);
+    }
+    result?;
+    return Ok(transfer);
+}
+
+/// Uses the given ELF, otherwise searches the one left by the component builder
+/// (<component>/<name>.cbf -> <component>/build/<board>/image.elf)
+fn find_component_elf(cbf_path: &PathBuf, elf_file: Option<String>) -> Option<PathBuf> {
+    if let Some(elf_file) = elf_file {
+        let elf_path = PathBuf::from(elf_file);
+        if !elf_path.exists() {
+            panic!("Cannot find the ELF at '{}'", elf_path.display());
+        }
+        return Some(elf_path);
+    }
+    let mut build_path = cbf_path.parent()?.to_path_buf();
+    build_path.push("build");
+    let mut candidates: Vec<PathBuf> = Vec::new();
+    for entry in std::fs::read_dir(&build_path).ok()? {
+        let mut elf_path = entry.ok()?.path();
+        elf_path.push("image.elf");
+        if elf_path.exists() {
+            candidates.push(elf_path);
+        }
+    }
+    // Avoid guessing the board
+    match candidates.len() {
+        1 => candidates.pop(),
+        _ => None,
+    }
+}
+
+fn send_hello(
+    channel_in_consumer: &Receiver<u8>,
+    channel_out_producer: &Sender<Vec<u8>>,
+    operation: OperationType,
+    progress: &mut dyn UpdateProgress,
+    verbose: bool,
+) -> Result<(), FlashError> {
     // Send hello message
     progress.message("Connection Setup   ");
-    let hello_msg = HelloMessage::new(OperationType::ComponentUpdate);
+    let hello_msg = HelloMessage::new(operation);
     channel_flush_read(channel_in_consumer);
-    channel_write(channel_out_producer, &hello_msg.get_raw());
+    channel_write(channel_out_producer, &hello_msg.get_raw())?;
     // Read hello response
     let mut buff: [u8; HelloResponseMessage::get_size()] = [0x00; HelloResponseMessage::get_size()];
-    channel_read(channel_in_consumer, &mut buff);
+    channel_read(channel_in_consumer, &mut buff)?;
     // Validate hello response
     let hello_response = HelloResponseMessage::from(&buff);
     if hello_response.is_err() {
-        eprintln!("Wrong response from device at HELLO");
-        return;
+        return Err(FlashError::Hello);
     }
     if verbose {
         println!("Got HELLO!");
     }
     progress.inc();
+    return Ok(());
+}
+
+fn begin_communication(
+    channel_in_consumer: &Receiver<u8>,
+    channel_out_producer: &Sender<Vec<u8>>,
+    cbf: &dyn CbfFile,
+    transfer: &Transfer,
+    progress: &mut dyn UpdateProgress,
+    verbose: bool,
+) -> Result<(), FlashError> {
+    let operation = match transfer.delta {
+        Some(_) => OperationType::ComponentDelta,
+        None => OperationType::ComponentUpdate,
+    };
+    send_hello(
+        channel_in_consumer,
+        channel_out_producer,
+        operation,
+        progress,
+        verbose,
+    )?;
     // Wait for header request
     let mut buff: [u8; 1] = [0x00; 1];
     //flush_read(serial);
-    channel_read(channel_in_consumer, &mut buff);
+    channel_read(channel_in_consumer, &mut buff)?;
+    // A delta starts from its source
+    if let Some(delta) = &transfer.delta {
+        if buff[0] == ComponentUpdateCommand::SendDeltaSource as u8 {
+            if verbose {
+                println!("--> Send Delta Source");
+            }
+            let source_msg = DeltaSourceMessage::new(
+                delta.source.component_id,
+                delta.source.component_version,
+                delta.source.payload_hash,
+                delta.patch.len() as u32,
+            );
+            channel_write(channel_out_producer, &source_msg.get_raw())?;
+            // The device checks the installed version first
+            channel_read(channel_in_consumer, &mut buff)?;
+        }
+    }
     if buff[0] != ComponentUpdateCommand::SendComponentFixedHeader as u8 {
-        eprintln!(
-            "Unexpected response from device at first step (Fixed Header): {:?}",
-            MessageError::from(buff[0])
-        );
-        return;
+        return Err(FlashError::Device(
+            "first step (Fixed Header)",
+            MessageError::from(buff[0]),
+        ));
     }
-    send_fixed_header(channel_in_consumer, channel_out_producer, cbf, progress, verbose);
+    send_fixed_header(channel_in_consumer, channel_out_producer, cbf, transfer, progress, verbose)
 }
 
 fn send_fixed_header(
     channel_in_consumer: &Receiver<u8>,
     channel_out_producer: &Sender<Vec<u8>>,
     cbf: &dyn CbfFile,
-    progress: &mut ProgressBar<Stdout>,
+    transfer: &Transfer,
+    progress: &mut dyn UpdateProgress,
     verbose: bool,
-) {
+) -> Result<(), FlashError> {
     if verbose {
         println!("--> Send Fixed Header");
     }
     progress.inc();
     // Send fixed header
-    let base_header_raw = cbf.header_base().get_raw();
-    let main_header_raw = cbf.header_main().get_raw();
-    // Combine in a single packet
-    let mut out_buff = Vec::<u8>::new();
-    out_buff.extend_from_slice(base_header_raw);
-    out_buff.extend_from_slice(main_header_raw);
+    let out_buff = extract_fixed_header(cbf);
     // Construct packet and send
     progress.message("Header   ");
     let fixed_header_msg = FixedHeaderMessage::new(&out_buff);
-    channel_write(channel_out_producer, &fixed_header_msg.get_raw());
+    channel_write(channel_out_producer, &fixed_header_msg.get_raw())?;
     // Update progress
     progress.add((out_buff.len() - 1) as u64);
     // Wait for variable header request
     let mut buff: [u8; 1] = [0x00; 1];
-    channel_read(channel_in_consumer, &mut buff);
+    channel_read(channel_in_consumer, &mut buff)?;
     if buff[0] != ComponentUpdateCommand::SendComponentVariableHeader as u8 {
-        eprintln!(
-            "Unexpected response from device at second step (Variable Header): {:?}",
-            MessageError::from(buff[0])
-        );
-        return;
+        return Err(FlashError::Device(
+            "second step (Variable Header)",
+            MessageError::from(buff[0]),
+        ));
     }
-    send_variable_header(channel_in_consumer, channel_out_producer, cbf, progress, verbose);
+    send_variable_header(channel_in_consumer, channel_out_producer, cbf, transfer, progress, verbose)
 }
 
 fn send_variable_header(
     channel_in_consumer: &Receiver<u8>,
     channel_out_producer: &Sender<Vec<u8>>,
     cbf: &dyn CbfFile,
-    progress: &mut ProgressBar<Stdout>,
+    transfer: &Transfer,
+    progress: &mut dyn UpdateProgress,
     verbose: bool,
-) {
+) -> Result<(), FlashError> {
     if verbose {
         println!("--> Send Variable Header");
     }
     progress.inc();
     // Generate bytes
     let vhb = extract_variable_header(cbf);
-    // Start sending
-    let mut pkt = RawPacket::new(&vhb);
-    loop {
-        let mut buff: [u8; 1] = [0x00; 1];
-        // Wait for next request
-        channel_read(channel_in_consumer, &mut buff);
-
-        if buff[0] == ComponentUpdateCommand::SendComponentPayload as u8 {
-            // Check we actually finished sending the variable header
-            if pkt.get_next_fragment().is_some() {
-                eprintln!("Still some header to be send!");
-                return;
-            }
-            break; // Everything okay
-        } else if buff[0] != ComponentUpdateCommand::SendNextFragment as u8 {
-            eprintln!(
-                "Unexpected response from device at third step (Variable Header): {:?}",
-                MessageError::from(buff[0])
-            );
-            return;
-        }
-        // Send fragment
-        progress.message(&format!(
-            "Header Fragment {}/{}   ",
-            pkt.get_next_fragment_number().unwrap(),
-            pkt.get_total_fragments()
+    // Stream them
+    progress.message("Header   ");
+    let start = progress.add(0);
+    link_send(
+        channel_in_consumer,
+        channel_out_producer,
+        STREAM_VARIABLE_HEADER,
+        &vhb,
+        transfer.window,
+        |acknowledged| {
+            progress.set(start + acknowledged as u64);
+        },
+    )
+    .map_err(|e| FlashError::Link("third step (Variable Header)", e))?;
+    // Wait for the payload request
+    wait_payload_request(
+        channel_in_consumer,
+        channel_out_producer,
+        transfer,
+        "third step (Variable Header)",
+    )?;
+    send_payload(channel_in_consumer, channel_out_producer, cbf, 0, transfer, progress, verbose)
+}
+
+/// Continues an interrupted update from the last checkpoint of the device
+fn resume_communication(
+    channel_in_consumer: &Receiver<u8>,
+    channel_out_producer: &Sender<Vec<u8>>,
+    cbf: &dyn CbfFile,
+    transfer: &Transfer,
+    progress: &mut dyn UpdateProgress,
+    verbose: bool,
+) -> Result<(), FlashError> {
+    send_hello(
+        channel_in_consumer,
+        channel_out_producer,
+        OperationType::ComponentResume,
+        progress,
+        verbose,
+    )?;
+    // Wait for session request
+    let mut buff: [u8; 1] = [0x00; 1];
+    channel_read(channel_in_consumer, &mut buff)?;
+    if buff[0] != ComponentUpdateCommand::SendSessionId as u8 {
+        return Err(FlashError::Device(
+            "first step (Session ID)",
+            MessageError::from(buff[0]),
         ));
-        //println!("\tSending Fragment {}/{}", pkt.get_next_fragment_number().unwrap(), pkt.get_total_fragments());
-        let fragment_data = pkt.get_next_fragment().unwrap();
-        channel_write(channel_out_producer, &fragment_data);
-        // Update progress
-        progress.add((fragment_data.len() - 1) as u64);
     }
-    send_payload(channel_in_consumer, channel_out_producer, cbf, progress, verbose);
+    // The session is identified by the checksum of the fixed header
+    let fixed_header = extract_fixed_header(cbf);
+    let session_msg = SessionIdMessage::new(xor_checksum(&fixed_header));
+    channel_write(channel_out_producer, &session_msg.get_raw())?;
+    // Read where the device stopped
+    let mut buff: [u8; ResumeStatusMessage::get_size()] = [0x00; ResumeStatusMessage::get_size()];
+    channel_read(channel_in_consumer, &mut buff[0..1])?;
+    if buff[0] != ComponentUpdateCommand::ResumeFromOffset as u8 {
+        return Err(FlashError::Device("resume", MessageError::from(buff[0])));
+    }
+    channel_read(channel_in_consumer, &mut buff[1..])?;
+    let status = ResumeStatusMessage::from(&buff)
+        .map_err(|e| FlashError::Device("resume (Status)", e))?;
+    // Check the device received exactly our image up to there
+    let mut image = fixed_header;
+    image.extend_from_slice(&extract_variable_header(cbf));
+    let header_size = image.len();
+    image.extend_from_slice(&extract_payload(cbf));
+    let offset = status.get_committed_offset() as usize;
+    if offset < header_size
+        || offset > image.len()
+        || xor_checksum(&image[0..offset]) != status.get_validation_checksum()
+    {
+        return Err(FlashError::CannotResume);
+    }
+    if verbose {
+        println!(
+            "--> Resuming from offset {} ({} bytes left)",
+            offset,
+            image.len() - offset
+        );
+    }
+    progress.set(offset as u64);
+    // Wait for the payload request
+    wait_payload_request(
+        channel_in_consumer,
+        channel_out_producer,
+        transfer,
+        "resume (Payload)",
+    )?;
+    send_payload(
+        channel_in_consumer,
+        channel_out_producer,
+        cbf,
+        offset - header_size,
+        transfer,
+        progress,
+        verbose,
+    )
 }
 
 fn send_payload(
     channel_in_consumer: &Receiver<u8>,
     channel_out_producer: &Sender<Vec<u8>>,
     cbf: &dyn CbfFile,
-    progress: &mut ProgressBar<Stdout>,
+    payload_start: usize,
+    transfer: &Transfer,
+    progress: &mut dyn UpdateProgress,
     verbose: bool,
-) {
+) -> Result<(), FlashError> {
     if verbose {
         println!("--> Send Payload");
     }
     progress.inc();
     // -------> Sending Payload
-    // Get bytes
-    let mut payload_bytes = Vec::<u8>::new();
-    payload_bytes.extend_from_slice(cbf.read_only_section().content());
-    if cbf.data_section().is_some() {
-        payload_bytes.extend_from_slice(cbf.data_section().unwrap().content());
-    }
-    // Generate packet
-    let mut pkt = RawPacket::new(&payload_bytes);
-    loop {
-        // Wait for next request
-        let mut buff: [u8; 1] = [0x00; 1];
-        channel_read(channel_in_consumer, &mut buff);
-
-        if buff[0] == ComponentUpdateCommand::SendComponentTrailer as u8 {
-            // Check we actually finished sending the variable header
-            if pkt.get_next_fragment().is_some() {
-                eprintln!("Still some payload to be send!");
-                return;
-            }
-            break;
-        } else if buff[0] != ComponentUpdateCommand::SendNextFragment as u8 {
-            eprintln!(
-                "Unexpected response from device at fourth step (Payload) {:?}",
-                MessageError::from(buff[0])
-            );
-            return;
-        }
-        // Send fragment
-        //println!("\tSending Fragment {}/{}", pkt.get_next_fragment_number().unwrap(), pkt.get_total_fragments());
-        progress.message(&format!(
-            "Payload Fragment {}/{}   ",
-            pkt.get_next_fragment_number().unwrap(),
-            pkt.get_total_fragments()
+    // Get bytes (only the ones the device still misses)
+    let payload_bytes = extract_payload(cbf);
+    let remaining = payload_bytes.len() - payload_start;
+    // A compressed payload (or a delta) is always sent from its start, the device cannot resume it
+    let stream = match (&transfer.delta, &transfer.image.compressed_payload) {
+        (Some(delta), _) => &delta.patch[..],
+        (None, Some(compressed_payload)) => &compressed_payload[..],
+        (None, None) => &payload_bytes[payload_start..],
+    };
+    // Stream them
+    progress.message("Payload   ");
+    let start = progress.add(0);
+    link_send(
+        channel_in_consumer,
+        channel_out_producer,
+        STREAM_PAYLOAD,
+        stream,
+        transfer.window,
+        |acknowledged| {
+            // Progress on the image the device stores
+            let done = acknowledged as u64 * remaining as u64 / stream.len() as u64;
+            progress.set(start + done);
+        },
+    )
+    .map_err(|e| FlashError::Link(PAYLOAD_STEP, e))?;
+    // Wait for the trailer request
+    let command = read_command(channel_in_consumer)?;
+    if command != ComponentUpdateCommand::SendComponentTrailer as u8 {
+        return Err(FlashError::Link(
+            PAYLOAD_STEP,
+            LinkSendError::Device(MessageError::from(command)),
         ));
-        let fragment_data = pkt.get_next_fragment().unwrap();
-        channel_write(channel_out_producer, &fragment_data);
-        // Update progress
-        progress.add((fragment_data.len() - 1) as u64);
     }
-    send_trailer(channel_in_consumer, channel_out_producer, cbf, progress, verbose);
+    send_trailer(channel_in_consumer, channel_out_producer, cbf, progress, verbose)
 }
 
 fn send_trailer(
     channel_in_consumer: &Receiver<u8>,
     channel_out_producer: &Sender<Vec<u8>>,
     cbf: &dyn CbfFile,
-    progress: &mut ProgressBar<Stdout>,
+    progress: &mut dyn UpdateProgress,
     verbose: bool,
-) {
+) -> Result<(), FlashError> {
     if verbose {
         println!("--> Send Trailer");
     }
@@ -273,24 +647,110 @@ fn send_trailer(
     checksum_bytes.extend_from_slice(&cbf.trailer().checksum().to_le_bytes());
     // Send data
     progress.message("Checksum   ");
-    channel_write(channel_out_producer, &checksum_bytes);
+    channel_write(channel_out_producer, &checksum_bytes)?;
 
     // Wait for confirmation
     let mut buff: [u8; 1] = [0x00; 1];
-    channel_read(channel_in_consumer, &mut buff);
+    channel_read(channel_in_consumer, &mut buff)?;
     if buff[0] != ComponentUpdateResponse::Success as u8 {
-        eprintln!(
-            "Unexpected response from device at final step: {:?}",
-            MessageError::from(buff[0])
-        );
-        return;
+        return Err(FlashError::Device("final step", MessageError::from(buff[0])));
     }
     progress.finish();
+    return Ok(());
+}
 
-    println!("\nSuccess!");
+/// Waits for the payload request. For a compressed payload the device
+/// first asks for the length of the stream.
+fn wait_payload_request(
+    channel_in_consumer: &Receiver<u8>,
+    channel_out_producer: &Sender<Vec<u8>>,
+    transfer: &Transfer,
+    step: &'static str,
+) -> Result<(), FlashError> {
+    let mut command = read_command(channel_in_consumer)?;
+    if command == ComponentUpdateCommand::SendCompressedSize as u8 {
+        let compressed_payload = match &transfer.image.compressed_payload {
+            Some(compressed_payload) => compressed_payload,
+            None => return Err(FlashError::NotCompressed),
+        };
+        let size_msg = CompressedSizeMessage::new(compressed_payload.len() as u32);
+        channel_write(channel_out_producer, &size_msg.get_raw())?;
+        command = read_command(channel_in_consumer)?;
+    }
+    if command != ComponentUpdateCommand::SendComponentPayload as u8 {
+        return Err(FlashError::Device(step, MessageError::from(command)));
+    }
+    Ok(())
+}
+
+/// Returns the CBF with the payload uncompressed, as the device is going to store it,
+/// together with the compressed payload (if the CBF is compressed)
+pub fn expand_cbf(cbf_bytes: Vec<u8>) -> (Vec<u8>, Option<Vec<u8>>) {
+    let compressed = match cbf_rs::parse_cbf(&cbf_bytes) {
+        Ok(cbf)
+            if cbf
+                .header_main()
+                .component_flags()
+                .contains(cbf_rs::ComponentFlags::COMPRESSED) =>
+        {
+            Some((
+                cbf.read_only_section().offset() as usize,
+                cbf.payload_size() as usize,
+            ))
+        }
+        _ => None,
+    };
+    let (payload_start, payload_size) = match compressed {
+        Some(payload) => payload,
+        // Let the caller report the errors
+        None => return (cbf_bytes, None),
+    };
+    let trailer_start = cbf_bytes.len() - TRAILER_SIZE;
+    let compressed_payload = cbf_bytes[payload_start..trailer_start].to_vec();
+    let mut expanded = cbf_bytes[0..payload_start].to_vec();
+    let mut decoder = heatshrink::Decoder::new(payload_size);
+    decoder
+        .sink(&compressed_payload, |b| -> Result<(), heatshrink::DecoderError> {
+            expanded.push(b);
+            Ok(())
+        })
+        .expect("The compressed payload is larger than the image");
+    if !decoder.is_complete() {
+        panic!("The compressed payload is shorter than the image");
+    }
+    expanded.extend_from_slice(&cbf_bytes[trailer_start..]);
+    (expanded, Some(compressed_payload))
+}
+
+pub fn extract_fixed_header(cbf: &dyn CbfFile) -> Vec<u8> {
+    // Base and main header, combined in a single packet
+    let mut buffer = Vec::<u8>::new();
+    buffer.extend_from_slice(cbf.header_base().get_raw());
+    buffer.extend_from_slice(cbf.header_main().get_raw());
+    buffer
+}
+
+pub fn extract_payload(cbf: &dyn CbfFile) -> Vec<u8> {
+    let mut buffer = Vec::<u8>::new();
+    buffer.extend_from_slice(cbf.read_only_section().content());
+    if cbf.data_section().is_some() {
+        buffer.extend_from_slice(cbf.data_section().unwrap().content());
+    }
+    buffer
+}
+
+/// Same checksum computed by the device on the data received (XOR of LE words)
+pub fn xor_checksum(data: &[u8]) -> u32 {
+    let mut checksum: u32 = 0;
+    for word in data.chunks(4) {
+        let mut bytes: [u8; 4] = [0x00; 4];
+        bytes[0..word.len()].copy_from_slice(word);
+        checksum ^= u32::from_le_bytes(bytes);
+    }
+    checksum
 }
 
-fn extract_variable_header(cbf: &dyn CbfFile) -> Vec<u8> {
+pub fn extract_variable_header(cbf: &dyn CbfFile) -> Vec<u8> {
     let mut buffer = Vec::<u8>::new();
     // Start with regions
     for r in cbf.region_iter() {
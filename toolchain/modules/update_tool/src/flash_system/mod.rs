@@ -2,6 +2,7 @@
 // License, v. 2.0. If a copy of the MPL was not distributed with this
 // file, You can obtain one at https://mozilla.org/MPL/2.0/.
 
+use crate::elf_store::ElfStore;
 use crate::utils::*;
 use std::{path::PathBuf, process::Command};
 
@@ -34,9 +35,48 @@ pub fn flash_system(app_config: String, ihex_file: String, verbose: bool) {
     }
     openocd_program(&target_chip, &ihex_path.to_str().unwrap(), verbose);
     // -> Step 3: sw reset
+    // -> Step 4: keep the ELFs of the flashed components
+    store_component_elfs(&app_config_path, &app_config, verbose);
     println!("Success!");
 }
 
+/// Saves the components of the app in the ELF store, using the last build
+fn store_component_elfs(
+    app_config_path: &PathBuf,
+    app_config: &app_config::AppConfig,
+    verbose: bool,
+) {
+    let root = app_config_path
+        .parent()
+        .unwrap()
+        .parent()
+        .unwrap()
+        .parent()
+        .unwrap()
+        .to_path_buf();
+    let store = ElfStore::open();
+    for component_name in app_config.components.keys() {
+        let mut component_root = root.clone();
+        component_root.push("components");
+        component_root.push(component_name);
+        component_root.push("core");
+        let mut cbf_path = component_root.clone();
+        cbf_path.push(format!("{}.cbf", component_name));
+        let mut elf_path = component_root.clone();
+        elf_path.push("build");
+        elf_path.push(&app_config.board);
+        elf_path.push("image.elf");
+        if !cbf_path.exists() || !elf_path.exists() {
+            println!("Cannot find the build of component '{}'", component_name);
+            continue;
+        }
+        if verbose {
+            println!("Saving '{}' in the ELF store", component_name);
+        }
+        store.store(&cbf_path, &elf_path);
+    }
+}
+
 fn openocd_flash_erase(target_chip: &String, verbose: bool) {
     let mut cmd = Command::new("openocd");
     cmd.arg("-f")
@@ -0,0 +1,195 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use std::{fmt, fs, io, path::PathBuf, time::Duration};
+
+use serde::Deserialize;
+
+/**
+ * Manifest of a fleet update (TOML):
+ *
+ * concurrency = 4                # devices updated at the same time
+ * retries = 2                    # further attempts for a device that failed
+ * timeout = 10                   # seconds of silence before giving up an attempt
+ * window = 4                     # fragments in flight on the update link
+ * components = [                 # target versions, installed in this order
+ *     "component1/output/component.cbf",
+ * ]
+ * devices = ["cOS/kitchen", "cOS/garage"]   # root topics of the bridges
+ *
+ * [mqtt]
+ * host = "192.168.0.3"
+ * port = 1883
+ * status_topic = "cOS/fleet/status"         # where progress and results are published
+ */
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Manifest {
+    pub mqtt: MqttManifest,
+    #[serde(default = "default_concurrency")]
+    pub concurrency: usize,
+    #[serde(default = "default_retries")]
+    pub retries: u32,
+    #[serde(default = "default_timeout")]
+    pub timeout: u64,
+    /// Fragments in flight on the update link
+    pub window: Option<usize>,
+    /// CBFs, relative to the manifest
+    pub components: Vec<PathBuf>,
+    /// Root topics of the devices
+    pub devices: Vec<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct MqttManifest {
+    pub host: String,
+    #[serde(default = "default_port")]
+    pub port: u16,
+    pub status_topic: Option<String>,
+}
+
+fn default_concurrency() -> usize {
+    4
+}
+
+fn default_retries() -> u32 {
+    2
+}
+
+fn default_timeout() -> u64 {
+    10
+}
+
+fn default_port() -> u16 {
+    1883
+}
+
+#[derive(Debug)]
+pub enum ManifestError {
+    Io(io::Error),
+    Invalid(toml::de::Error),
+    Inconsistent(String),
+}
+
+impl fmt::Display for ManifestError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ManifestError::Io(e) => write!(f, "cannot read the manifest: {}", e),
+            ManifestError::Invalid(e) => write!(f, "invalid manifest: {}", e),
+            ManifestError::Inconsistent(e) => write!(f, "invalid manifest: {}", e),
+        }
+    }
+}
+
+impl Manifest {
+    pub fn parse(manifest: &str) -> Result<Self, ManifestError> {
+        let manifest: Manifest = toml::from_str(manifest).map_err(ManifestError::Invalid)?;
+        if manifest.concurrency == 0 {
+            return Err(ManifestError::Inconsistent(String::from(
+                "concurrency must be at least 1",
+            )));
+        }
+        if manifest.timeout == 0 {
+            return Err(ManifestError::Inconsistent(String::from(
+                "timeout must be at least 1 second",
+            )));
+        }
+        if manifest.components.is_empty() {
+            return Err(ManifestError::Inconsistent(String::from("no components")));
+        }
+        if manifest.devices.is_empty() {
+            return Err(ManifestError::Inconsistent(String::from("no devices")));
+        }
+        for (i, device) in manifest.devices.iter().enumerate() {
+            if device.is_empty() || device.contains(['+', '#']) {
+                return Err(ManifestError::Inconsistent(format!(
+                    "invalid root topic '{}'",
+                    device
+                )));
+            }
+            if manifest.devices[..i].contains(device) {
+                return Err(ManifestError::Inconsistent(format!(
+                    "device '{}' listed twice",
+                    device
+                )));
+            }
+        }
+        return Ok(manifest);
+    }
+
+    /// Reads the manifest, resolving the CBFs from its directory
+    pub fn load(path: &PathBuf) -> Result<Self, ManifestError> {
+        let content = fs::read_to_string(path).map_err(ManifestError::Io)?;
+        let mut manifest = Self::parse(&content)?;
+        if let Some(dir) = path.parent() {
+            for component in manifest.components.iter_mut() {
+                *component = dir.join(&component);
+            }
+        }
+        return Ok(manifest);
+    }
+
+    pub fn timeout(&self) -> Duration {
+        return Duration::from_secs(self.timeout);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::path::PathBuf;
+
+    use super::Manifest;
+
+    #[test]
+    fn test_parse() {
+        let manifest = Manifest::parse(
+            "concurrency = 2\n\
+             components = [\"a.cbf\", \"b.cbf\"]\n\
+             devices = [\"cOS/1\", \"cOS/2\"]\n\
+             [mqtt]\n\
+             host = \"localhost\"\n\
+             status_topic = \"cOS/fleet\"\n",
+        )
+        .unwrap();
+        assert_eq!(manifest.concurrency, 2);
+        assert_eq!(manifest.retries, 2);
+        assert_eq!(manifest.timeout, 10);
+        assert_eq!(manifest.window, None);
+        assert_eq!(manifest.mqtt.port, 1883);
+        assert_eq!(manifest.mqtt.status_topic.as_deref(), Some("cOS/fleet"));
+        assert_eq!(
+            manifest.components,
+            vec![PathBuf::from("a.cbf"), PathBuf::from("b.cbf")]
+        );
+        assert_eq!(manifest.devices, vec!["cOS/1", "cOS/2"]);
+    }
+
+    #[test]
+    fn test_invalid() {
+        let mqtt = "[mqtt]\nhost = \"localhost\"\n";
+        // The MQTT section is required
+        assert!(Manifest::parse("components = [\"a.cbf\"]\ndevices = [\"cOS\"]\n").is_err());
+        assert!(
+            Manifest::parse(&format!("components = []\ndevices = [\"cOS\"]\n{}", mqtt)).is_err()
+        );
+        assert!(
+            Manifest::parse(&format!("components = [\"a.cbf\"]\ndevices = []\n{}", mqtt)).is_err()
+        );
+        assert!(Manifest::parse(&format!(
+            "components = [\"a.cbf\"]\ndevices = [\"cOS\", \"cOS\"]\n{}",
+            mqtt
+        ))
+        .is_err());
+        assert!(Manifest::parse(&format!(
+            "components = [\"a.cbf\"]\ndevices = [\"cOS/+\"]\n{}",
+            mqtt
+        ))
+        .is_err());
+        assert!(Manifest::parse(&format!(
+            "concurrency = 0\ncomponents = [\"a.cbf\"]\ndevices = [\"cOS\"]\n{}",
+            mqtt
+        ))
+        .is_err());
+    }
+}
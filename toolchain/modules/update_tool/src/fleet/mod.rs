@@ -0,0 +1,481 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+mod manifest;
+mod report;
+#[cfg(test)]
+mod simulator;
+mod status;
+
+use std::{
+    fs,
+    path::PathBuf,
+    sync::atomic::{AtomicBool, Ordering},
+    thread,
+    time::{Duration, Instant},
+};
+
+use crossbeam_channel::{select, Receiver, Sender};
+
+use self::manifest::Manifest;
+use self::report::*;
+use self::status::*;
+use crate::flash_component::{update_component, ComponentImage};
+use crate::info::{query_components, ComponentInfo};
+use crate::transport::Transport;
+use crate::utils::channel_flush_read;
+
+/// What a device needs for a component of the manifest
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Action {
+    Install,
+    Nothing(ComponentResult),
+}
+
+/// Updates every device of the manifest, returning false if any of them failed
+pub fn fleet(
+    manifest_file: String,
+    report_file: Option<String>,
+    window: usize,
+    verbose: bool,
+) -> bool {
+    let manifest_path = PathBuf::from(&manifest_file);
+    let manifest = Manifest::load(&manifest_path).unwrap_or_else(|e| panic!("{}", e));
+    let images: Vec<ComponentImage> = manifest
+        .components
+        .iter()
+        .map(ComponentImage::load)
+        .collect();
+    for (i, image) in images.iter().enumerate() {
+        if images[..i]
+            .iter()
+            .any(|other| other.component_id() == image.component_id())
+        {
+            panic!(
+                "Component {} is in the manifest more than once",
+                image.component_id()
+            );
+        }
+    }
+    let status = StatusPublisher::connect(
+        &manifest.mqtt.host,
+        manifest.mqtt.port,
+        manifest.mqtt.status_topic.clone(),
+    );
+    let report = update_fleet(&manifest, &manifest_file, &images, &status, window, verbose);
+    status.close();
+    if let Some(report_file) = report_file {
+        let json = serde_json::to_string_pretty(&report).unwrap();
+        fs::write(&report_file, json)
+            .unwrap_or_else(|e| panic!("Cannot write the report '{}': {}", report_file, e));
+    }
+    return report.summary.failed == 0;
+}
+
+fn update_fleet(
+    manifest: &Manifest,
+    manifest_file: &str,
+    images: &[ComponentImage],
+    status: &StatusPublisher,
+    window: usize,
+    verbose: bool,
+) -> FleetReport {
+    let started = Instant::now();
+    status.publish(StatusEvent::Started {
+        devices: manifest.devices.len(),
+        components: images
+            .iter()
+            .map(|image| TargetComponent {
+                component_id: image.component_id(),
+                component_version: image.component_version(),
+            })
+            .collect(),
+    });
+    let window = manifest.window.unwrap_or(window);
+    // Each worker takes the next device
+    let (device_producer, device_consumer) = crossbeam_channel::unbounded::<(usize, &String)>();
+    for device in manifest.devices.iter().enumerate() {
+        device_producer.send(device).unwrap();
+    }
+    drop(device_producer);
+    let (report_producer, report_consumer) =
+        crossbeam_channel::unbounded::<(usize, DeviceReport)>();
+    thread::scope(|s| {
+        for _ in 0..manifest.concurrency.min(manifest.devices.len()) {
+            let device_consumer = device_consumer.clone();
+            let report_producer = report_producer.clone();
+            s.spawn(move || {
+                for (i, device) in device_consumer.iter() {
+                    let report = update_device(manifest, device, images, status, window, verbose);
+                    status.publish(StatusEvent::DeviceFinished(report.clone()));
+                    report_producer.send((i, report)).unwrap();
+                }
+            });
+        }
+    });
+    drop(report_producer);
+    // Same order of the manifest
+    let mut devices: Vec<(usize, DeviceReport)> = report_consumer.iter().collect();
+    devices.sort_by_key(|(i, _)| *i);
+    let devices: Vec<DeviceReport> = devices.into_iter().map(|(_, report)| report).collect();
+    let summary = Summary::new(&devices);
+    status.publish(StatusEvent::Finished(summary.clone()));
+    return FleetReport {
+        manifest: String::from(manifest_file),
+        summary: summary,
+        duration_s: started.elapsed().as_secs_f64(),
+        devices: devices,
+    };
+}
+
+/// Updates a device, trying again from the query when an attempt fails
+fn update_device(
+    manifest: &Manifest,
+    device: &str,
+    images: &[ComponentImage],
+    status: &StatusPublisher,
+    window: usize,
+    verbose: bool,
+) -> DeviceReport {
+    let started = Instant::now();
+    let mut report = DeviceReport::new(device);
+    // One connection for all the attempts, through the bridge of the device
+    let (channel_in_producer, channel_in_consumer) = crossbeam_channel::bounded::<u8>(100);
+    let (channel_out_producer, channel_out_consumer) = crossbeam_channel::bounded::<Vec<u8>>(100);
+    let transport = Transport::Mqtt {
+        host: manifest.mqtt.host.clone(),
+        port: manifest.mqtt.port,
+        root: String::from(device),
+    };
+    transport.start(channel_in_producer, channel_out_consumer);
+    for attempt in 1..=manifest.retries + 1 {
+        report.attempts = attempt;
+        let result = run_attempt(
+            &channel_in_consumer,
+            &channel_out_producer,
+            manifest.timeout(),
+            |channel_in_consumer, channel_out_producer| {
+                update_attempt(
+                    channel_in_consumer,
+                    channel_out_producer,
+                    device,
+                    attempt,
+                    images,
+                    status,
+                    window,
+                    verbose,
+                    &mut report,
+                )
+            },
+        );
+        match result {
+            Ok(()) => {
+                report.error = None;
+                report.result = match report
+                    .components
+                    .iter()
+                    .any(|c| c.result == ComponentResult::Updated)
+                {
+                    true => DeviceResult::Updated,
+                    false => DeviceResult::UpToDate,
+                };
+                break;
+            }
+            Err(e) => {
+                report.error = Some(e.clone());
+                if attempt <= manifest.retries {
+                    status.publish(StatusEvent::Device {
+                        device: String::from(device),
+                        state: DeviceState::Retrying,
+                        attempt: attempt,
+                        error: Some(e),
+                    });
+                }
+            }
+        }
+    }
+    report.duration_s = started.elapsed().as_secs_f64();
+    return report;
+}
+
+/// One pass on the device: query what is installed, then send what is missing
+fn update_attempt(
+    channel_in_consumer: &Receiver<u8>,
+    channel_out_producer: &Sender<Vec<u8>>,
+    device: &str,
+    attempt: u32,
+    images: &[ComponentImage],
+    status: &StatusPublisher,
+    window: usize,
+    verbose: bool,
+    report: &mut DeviceReport,
+) -> Result<(), String> {
+    status.publish(StatusEvent::Device {
+        device: String::from(device),
+        state: DeviceState::Querying,
+        attempt: attempt,
+        error: None,
+    });
+    let installed = query_components(channel_in_consumer, channel_out_producer, verbose)
+        .map_err(|e| e.to_string())?;
+    if report.installed.is_none() {
+        report.installed = Some(
+            installed
+                .iter()
+                .map(|c| InstalledComponent {
+                    component_id: c.component_id,
+                    component_version: c.component_version,
+                })
+                .collect(),
+        );
+    }
+    let mut updating = false;
+    for image in images {
+        let from = installed
+            .iter()
+            .find(|c| c.component_id == image.component_id())
+            .map(|c| c.component_version);
+        let mut component = ComponentReport {
+            component_id: image.component_id(),
+            from: from,
+            to: image.component_version(),
+            result: ComponentResult::UpToDate,
+            error: None,
+        };
+        match plan(&installed, image.component_id(), image.component_version()) {
+            Action::Nothing(result) => {
+                component.result = result;
+                report.record(component);
+                continue;
+            }
+            Action::Install => {}
+        }
+        if !updating {
+            updating = true;
+            status.publish(StatusEvent::Device {
+                device: String::from(device),
+                state: DeviceState::Updating,
+                attempt: attempt,
+                error: None,
+            });
+        }
+        let mut progress =
+            StatusProgress::new(status, device, image.component_id(), image.progress_total());
+        let result = update_component(
+            channel_in_consumer,
+            channel_out_producer,
+            image,
+            None,
+            false,
+            window,
+            &mut progress,
+            verbose,
+        );
+        match result {
+            Ok(_) => {
+                component.result = ComponentResult::Updated;
+                report.record(component.clone());
+                status.publish(StatusEvent::Component {
+                    device: String::from(device),
+                    component: component,
+                });
+            }
+            Err(e) => {
+                component.result = ComponentResult::Failed;
+                component.error = Some(e.to_string());
+                report.record(component.clone());
+                status.publish(StatusEvent::Component {
+                    device: String::from(device),
+                    component: component,
+                });
+                return Err(format!("Component {}: {}", image.component_id(), e));
+            }
+        }
+    }
+    return Ok(());
+}
+
+/// Only newer versions are installed, the device refuses downgrades anyway
+fn plan(installed: &[ComponentInfo], component_id: u16, component_version: u32) -> Action {
+    match installed.iter().find(|c| c.component_id == component_id) {
+        None => Action::Install,
+        Some(c) if c.component_version < component_version => Action::Install,
+        Some(c) if c.component_version == component_version => {
+            Action::Nothing(ComponentResult::UpToDate)
+        }
+        Some(_) => Action::Nothing(ComponentResult::NewerInstalled),
+    }
+}
+
+/// Runs an attempt, giving it up when the device is silent for the timeout: the
+/// operations fail when the bytes of the device stop coming, and the reason is
+/// found in how the relay ended.
+fn run_attempt<F>(
+    channel_in_consumer: &Receiver<u8>,
+    channel_out_producer: &Sender<Vec<u8>>,
+    timeout: Duration,
+    attempt: F,
+) -> Result<(), String>
+where
+    F: FnOnce(&Receiver<u8>, &Sender<Vec<u8>>) -> Result<(), String>,
+{
+    // Late answers of the previous attempt
+    channel_flush_read(channel_in_consumer);
+    let (relay_producer, relay_consumer) = crossbeam_channel::bounded::<u8>(100);
+    let (done_producer, done_consumer) = crossbeam_channel::bounded::<()>(1);
+    let silent = AtomicBool::new(false);
+    let disconnected = AtomicBool::new(false);
+    thread::scope(|s| {
+        let silent = &silent;
+        let disconnected = &disconnected;
+        // Moves the bytes of the device to the attempt, until it ends or the device is silent.
+        // Dropping the relay makes the attempt fail.
+        s.spawn(move || loop {
+            select! {
+                recv(channel_in_consumer) -> b => match b {
+                    Ok(b) => {
+                        if relay_producer.send(b).is_err() {
+                            return;
+                        }
+                    }
+                    Err(_) => {
+                        disconnected.store(true, Ordering::Relaxed);
+                        return;
+                    }
+                },
+                recv(done_consumer) -> _ => return,
+                default(timeout) => {
+                    silent.store(true, Ordering::Relaxed);
+                    return;
+                }
+            }
+        });
+        let result = attempt(&relay_consumer, channel_out_producer);
+        let _ = done_producer.send(());
+        match result {
+            Ok(()) => Ok(()),
+            Err(_) if silent.load(Ordering::Relaxed) => Err(format!(
+                "No answer from the device for {} s",
+                timeout.as_secs()
+            )),
+            Err(_) if disconnected.load(Ordering::Relaxed) => {
+                Err(String::from("Connection to the broker lost"))
+            }
+            Err(e) => Err(e),
+        }
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use std::{env, fs, path::PathBuf, process};
+
+    use mqtt_broker::Broker;
+
+    use super::simulator::simulate_device;
+    use super::*;
+
+    fn example_cbf(name: &str) -> PathBuf {
+        let mut path = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        path.push("../elf2cbf/examples");
+        path.push(name);
+        path.push("output/component.cbf");
+        return path;
+    }
+
+    #[test]
+    fn test_plan() {
+        let installed = vec![ComponentInfo {
+            component_id: 2,
+            component_version: 2,
+            block_base_address: 0,
+            sram_base_address: 0,
+            dependencies: Vec::new(),
+        }];
+        assert_eq!(plan(&installed, 1, 1), Action::Install);
+        assert_eq!(plan(&installed, 2, 3), Action::Install);
+        assert_eq!(
+            plan(&installed, 2, 2),
+            Action::Nothing(ComponentResult::UpToDate)
+        );
+        assert_eq!(
+            plan(&installed, 2, 1),
+            Action::Nothing(ComponentResult::NewerInstalled)
+        );
+    }
+
+    #[test]
+    fn test_fleet() {
+        let broker = Broker::start(0);
+        let port = broker.port();
+        let components = vec![example_cbf("component1"), example_cbf("component2")];
+        // Needs both, is up to date, fails the first update, is offline
+        let needs_all = simulate_device(port, "fleet/needs-all", vec![], &components, 0);
+        let up_to_date = simulate_device(
+            port,
+            "fleet/up-to-date",
+            vec![(1, 1), (2, 2)],
+            &components,
+            0,
+        );
+        let flaky = simulate_device(port, "fleet/flaky", vec![(1, 1)], &components, 1);
+
+        let dir = env::temp_dir().join(format!("update-tool-fleet-{}", process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let manifest_path = dir.join("fleet.toml");
+        fs::write(
+            &manifest_path,
+            format!(
+                "concurrency = 2\nretries = 1\ntimeout = 2\n\
+                 components = [{:?}, {:?}]\n\
+                 devices = [\"fleet/needs-all\", \"fleet/up-to-date\", \"fleet/flaky\", \"fleet/offline\"]\n\
+                 [mqtt]\nhost = \"127.0.0.1\"\nport = {}\nstatus_topic = \"fleet/status\"\n",
+                components[0], components[1], port
+            ),
+        )
+        .unwrap();
+        let manifest = Manifest::load(&manifest_path).unwrap();
+        let images: Vec<ComponentImage> = manifest
+            .components
+            .iter()
+            .map(ComponentImage::load)
+            .collect();
+        let status =
+            StatusPublisher::connect("127.0.0.1", port, manifest.mqtt.status_topic.clone());
+        let report = update_fleet(&manifest, "fleet.toml", &images, &status, 4, false);
+        status.close();
+
+        assert_eq!(report.summary.devices, 4);
+        assert_eq!(report.summary.updated, 2);
+        assert_eq!(report.summary.up_to_date, 1);
+        assert_eq!(report.summary.failed, 1);
+        let devices = &report.devices;
+        assert_eq!(devices[0].device, "fleet/needs-all");
+        assert_eq!(devices[0].result, DeviceResult::Updated);
+        assert_eq!(devices[0].attempts, 1);
+        assert!(devices[0]
+            .components
+            .iter()
+            .all(|c| c.result == ComponentResult::Updated && c.from.is_none()));
+        assert_eq!(*needs_all.lock().unwrap(), vec![(1, 1), (2, 2)]);
+
+        assert_eq!(devices[1].result, DeviceResult::UpToDate);
+        assert_eq!(*up_to_date.lock().unwrap(), vec![(1, 1), (2, 2)]);
+
+        // The first attempt is refused by the device, the second one updates
+        assert_eq!(devices[2].result, DeviceResult::Updated);
+        assert_eq!(devices[2].attempts, 2);
+        assert_eq!(devices[2].components[0].result, ComponentResult::UpToDate);
+        assert_eq!(devices[2].components[1].result, ComponentResult::Updated);
+        assert_eq!(*flaky.lock().unwrap(), vec![(1, 1), (2, 2)]);
+
+        assert_eq!(devices[3].result, DeviceResult::Failed);
+        assert_eq!(devices[3].attempts, 2);
+        assert!(devices[3].installed.is_none());
+        assert!(devices[3].error.as_ref().unwrap().contains("No answer"));
+
+        broker.stop();
+        let _ = fs::remove_dir_all(dir);
+    }
+}
@@ -0,0 +1,123 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use serde::Serialize;
+
+/**
+ * Results of a fleet update, written as JSON at the end and published on the
+ * status topic while the update goes on.
+ */
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DeviceResult {
+    /// At least one component was installed
+    Updated,
+    /// Nothing to do
+    UpToDate,
+    Failed,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ComponentResult {
+    Updated,
+    UpToDate,
+    /// The device has a newer version, it is left alone
+    NewerInstalled,
+    Failed,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct InstalledComponent {
+    pub component_id: u16,
+    pub component_version: u32,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ComponentReport {
+    pub component_id: u16,
+    /// Version installed before the update, if any
+    pub from: Option<u32>,
+    pub to: u32,
+    pub result: ComponentResult,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct DeviceReport {
+    pub device: String,
+    pub result: DeviceResult,
+    pub attempts: u32,
+    /// Components found at the first query
+    pub installed: Option<Vec<InstalledComponent>>,
+    pub components: Vec<ComponentReport>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    pub duration_s: f64,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct Summary {
+    pub devices: usize,
+    pub updated: usize,
+    pub up_to_date: usize,
+    pub failed: usize,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct FleetReport {
+    pub manifest: String,
+    pub summary: Summary,
+    pub duration_s: f64,
+    pub devices: Vec<DeviceReport>,
+}
+
+impl DeviceReport {
+    pub fn new(device: &str) -> Self {
+        return Self {
+            device: String::from(device),
+            result: DeviceResult::Failed,
+            attempts: 0,
+            installed: None,
+            components: Vec::new(),
+            error: None,
+            duration_s: 0.0,
+        };
+    }
+
+    /// Records the outcome of a component. An update done in a previous attempt
+    /// is kept, the following ones find the component up to date.
+    pub fn record(&mut self, component: ComponentReport) {
+        match self
+            .components
+            .iter_mut()
+            .find(|c| c.component_id == component.component_id)
+        {
+            Some(c)
+                if c.result == ComponentResult::Updated
+                    && component.result == ComponentResult::UpToDate => {}
+            Some(c) => *c = component,
+            None => self.components.push(component),
+        }
+    }
+}
+
+impl Summary {
+    pub fn new(devices: &[DeviceReport]) -> Self {
+        let mut summary = Summary {
+            devices: devices.len(),
+            ..Default::default()
+        };
+        for device in devices {
+            match device.result {
+                DeviceResult::Updated => summary.updated += 1,
+                DeviceResult::UpToDate => summary.up_to_date += 1,
+                DeviceResult::Failed => summary.failed += 1,
+            }
+        }
+        return summary;
+    }
+}
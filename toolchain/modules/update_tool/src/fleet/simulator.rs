@@ -0,0 +1,263 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use std::{
+    path::PathBuf,
+    sync::{Arc, Mutex},
+    thread,
+    time::Duration,
+};
+
+use cbf_rs::CbfFile;
+use crossbeam_channel::{Receiver, Sender};
+use rumqttc::{Client, Event, MqttOptions, Packet, QoS};
+use update_link::{LinkReceiver, DATA_FRAME_SIZE, STREAM_PAYLOAD, STREAM_VARIABLE_HEADER};
+
+use crate::common_messages::OperationType;
+use crate::crc::crc8_update;
+use crate::flash_component::{
+    extract_fixed_header, extract_payload, extract_variable_header, ComponentImage,
+};
+use crate::utils::*;
+
+/// Fragments the simulated device accepts out of order
+const SIMULATOR_WINDOW: usize = 16;
+/// Answer of the device to a failed flash write
+const FLASH_ERROR: u8 = 0xE6;
+const NO_CBF: u8 = 0xE4;
+
+/**
+ * A device behind the serial-to-MQTT bridge, for the tests: it answers on
+ * <root>/5/out to what arrives on <root>/5/in, as the update component would.
+ * Only uncompressed CBFs are supported.
+ */
+
+/// Components installed on a simulated device (ID, version)
+pub type Installed = Arc<Mutex<Vec<(u16, u32)>>>;
+
+/// Starts a device, returning once it listens. The first fail_updates updates are refused.
+pub fn simulate_device(
+    port: u16,
+    root: &str,
+    installed: Vec<(u16, u32)>,
+    images: &[PathBuf],
+    fail_updates: usize,
+) -> Installed {
+    let (channel_in_producer, channel_in_consumer) = crossbeam_channel::unbounded::<u8>();
+    let (channel_out_producer, channel_out_consumer) = crossbeam_channel::unbounded::<Vec<u8>>();
+    let (ready_producer, ready_consumer) = crossbeam_channel::bounded::<()>(1);
+
+    let options = MqttOptions::new(format!("simulator-{}", root), "127.0.0.1", port);
+    let (mut client, mut connection) = Client::new(options, 10);
+    client
+        .subscribe(format!("{}/5/in", root), QoS::ExactlyOnce)
+        .unwrap();
+    thread::spawn(move || {
+        for notification in connection.iter() {
+            match notification {
+                Ok(Event::Incoming(Packet::SubAck(_))) => {
+                    let _ = ready_producer.try_send(());
+                }
+                Ok(Event::Incoming(Packet::Publish(publish))) => {
+                    for b in publish.payload {
+                        if channel_in_producer.send(b).is_err() {
+                            return;
+                        }
+                    }
+                }
+                Ok(_) => {}
+                Err(_) => thread::sleep(Duration::from_millis(100)),
+            }
+        }
+    });
+    let out_topic = format!("{}/5/out", root);
+    thread::spawn(move || {
+        for data in channel_out_consumer.iter() {
+            client
+                .publish(out_topic.clone(), QoS::ExactlyOnce, false, data)
+                .unwrap();
+        }
+    });
+
+    let installed = Arc::new(Mutex::new(installed));
+    let device_installed = installed.clone();
+    let images: Vec<ComponentImage> = images.iter().map(ComponentImage::load).collect();
+    // Until the test ends and drops the connection
+    thread::spawn(move || {
+        run_device(
+            channel_in_consumer,
+            channel_out_producer,
+            device_installed,
+            images,
+            fail_updates,
+        )
+    });
+    ready_consumer
+        .recv_timeout(Duration::from_secs(10))
+        .expect("The simulated device cannot subscribe");
+    return installed;
+}
+
+fn run_device(
+    channel_in_consumer: Receiver<u8>,
+    channel_out_producer: Sender<Vec<u8>>,
+    installed: Installed,
+    images: Vec<ComponentImage>,
+    mut fail_updates: usize,
+) -> Result<(), ChannelClosed> {
+    loop {
+        // Hello
+        let mut hello: [u8; 2] = [0x00; 2];
+        channel_read(&channel_in_consumer, &mut hello)?;
+        let mut crc: u8 = 0x00;
+        crc8_update(&mut crc, hello[0]);
+        if crc != hello[1] {
+            continue;
+        }
+        let operation = match OperationType::try_from(hello[0]) {
+            Ok(operation) => operation,
+            Err(_) => continue,
+        };
+        let mut response: Vec<u8> = b"OLLEH".to_vec();
+        response.push(hello[0]);
+        let mut crc: u8 = 0x00;
+        for b in &response {
+            crc8_update(&mut crc, *b);
+        }
+        response.push(crc);
+        channel_write(&channel_out_producer, &response)?;
+        match operation {
+            OperationType::SystemInfo => {
+                for (id, version) in installed.lock().unwrap().iter() {
+                    channel_write(&channel_out_producer, &component_info(*id, *version))?;
+                }
+                channel_write(&channel_out_producer, &[0x00, 0x00])?;
+                channel_write(&channel_out_producer, &reset_cause())?;
+            }
+            OperationType::ComponentUpdate => {
+                let refuse = fail_updates > 0;
+                fail_updates = fail_updates.saturating_sub(1);
+                if let Some((id, version)) =
+                    receive_component(&channel_in_consumer, &channel_out_producer, &images, refuse)?
+                {
+                    let mut installed = installed.lock().unwrap();
+                    installed.retain(|(installed_id, _)| *installed_id != id);
+                    installed.push((id, version));
+                }
+            }
+            _ => channel_write(&channel_out_producer, &[NO_CBF])?,
+        }
+    }
+}
+
+/// Status of an installed component, as sent by the update component
+fn component_info(id: u16, version: u32) -> Vec<u8> {
+    let mut message = Vec::<u8>::new();
+    message.extend_from_slice(&id.to_le_bytes());
+    message.extend_from_slice(&version.to_le_bytes());
+    // Flash and RAM
+    message.extend_from_slice(&0x1000u32.to_le_bytes());
+    message.extend_from_slice(&0x400u32.to_le_bytes());
+    // CBF_VALID
+    message.extend_from_slice(&1u16.to_le_bytes());
+    // Block and SRAM base
+    message.extend_from_slice(&(0x0800_0000u32 + id as u32 * 0x1000).to_le_bytes());
+    message.extend_from_slice(&(0x2000_0000u32 + id as u32 * 0x400).to_le_bytes());
+    // No dependencies
+    message.extend_from_slice(&0u16.to_le_bytes());
+    let mut crc: u8 = 0x00;
+    for b in &message {
+        crc8_update(&mut crc, *b);
+    }
+    message.push(crc);
+    return message;
+}
+
+/// Last reset of the device, a plain power on
+fn reset_cause() -> Vec<u8> {
+    let mut message = vec![0x05, 0x00, 0x00];
+    let mut crc: u8 = 0x00;
+    for b in &message {
+        crc8_update(&mut crc, *b);
+    }
+    message.push(crc);
+    return message;
+}
+
+/// Receives one of the known images, returning its ID and version once installed
+fn receive_component(
+    channel_in_consumer: &Receiver<u8>,
+    channel_out_producer: &Sender<Vec<u8>>,
+    images: &[ComponentImage],
+    refuse: bool,
+) -> Result<Option<(u16, u32)>, ChannelClosed> {
+    // Fixed header
+    channel_write(channel_out_producer, &[0x01])?;
+    let mut fixed_header: [u8; cbf_rs::FIXED_HEADER_SIZE + 1] =
+        [0x00; cbf_rs::FIXED_HEADER_SIZE + 1];
+    channel_read(channel_in_consumer, &mut fixed_header)?;
+    let image = images.iter().find(|image| {
+        extract_fixed_header(&image.cbf()) == fixed_header[..cbf_rs::FIXED_HEADER_SIZE]
+    });
+    let image = match image {
+        Some(image) if !refuse => image,
+        Some(_) => {
+            channel_write(channel_out_producer, &[FLASH_ERROR])?;
+            return Ok(None);
+        }
+        None => {
+            channel_write(channel_out_producer, &[NO_CBF])?;
+            return Ok(None);
+        }
+    };
+    let cbf = image.cbf();
+    // Variable header and payload, checked against the image
+    channel_write(channel_out_producer, &[0x02])?;
+    let variable_header = extract_variable_header(&cbf);
+    let received = receive_stream(
+        channel_in_consumer,
+        channel_out_producer,
+        STREAM_VARIABLE_HEADER,
+        variable_header.len(),
+    )?;
+    assert_eq!(received, variable_header);
+    channel_write(channel_out_producer, &[0x03])?;
+    let payload = extract_payload(&cbf);
+    let received = receive_stream(
+        channel_in_consumer,
+        channel_out_producer,
+        STREAM_PAYLOAD,
+        payload.len(),
+    )?;
+    assert_eq!(received, payload);
+    // Trailer
+    channel_write(channel_out_producer, &[0x04])?;
+    let mut trailer: [u8; 4] = [0x00; 4];
+    channel_read(channel_in_consumer, &mut trailer)?;
+    assert_eq!(u32::from_le_bytes(trailer), cbf.trailer().checksum());
+    channel_write(channel_out_producer, &[0xFF])?;
+    return Ok(Some((image.component_id(), image.component_version())));
+}
+
+fn receive_stream(
+    channel_in_consumer: &Receiver<u8>,
+    channel_out_producer: &Sender<Vec<u8>>,
+    stream: u8,
+    size: usize,
+) -> Result<Vec<u8>, ChannelClosed> {
+    let mut data = Vec::<u8>::new();
+    let mut receiver = LinkReceiver::<SIMULATOR_WINDOW>::new(stream, size);
+    let mut frame: [u8; DATA_FRAME_SIZE] = [0x00; DATA_FRAME_SIZE];
+    while !receiver.is_complete() {
+        channel_read(channel_in_consumer, &mut frame)?;
+        let control = receiver
+            .receive(&frame, |chunk: &[u8]| -> Result<(), ()> {
+                data.extend_from_slice(chunk);
+                Ok(())
+            })
+            .unwrap();
+        channel_write(channel_out_producer, &control.encode())?;
+    }
+    return Ok(data);
+}
@@ -0,0 +1,262 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use std::{
+    process,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    thread::{self, JoinHandle},
+    time::Duration,
+};
+
+use rumqttc::{Client, Event, MqttOptions, Outgoing, QoS};
+use serde::Serialize;
+
+use super::report::{ComponentReport, DeviceReport, Summary};
+use crate::flash_component::UpdateProgress;
+
+/// Progress is published at every step of this percentage
+const PROGRESS_STEP: u64 = 10;
+
+/**
+ * Events published on the status topic, one JSON object per message:
+ *   {"event": "started", "devices": 2, "components": [...]}
+ *   {"event": "device", "device": "cOS/1", "state": "querying", "attempt": 1}
+ *   {"event": "progress", "device": "cOS/1", "component_id": 2, "percent": 40}
+ *   {"event": "component", "device": "cOS/1", "component": {"component_id": 2, "from": 1, "to": 2, "result": "updated"}}
+ *   {"event": "device_finished", "device": "cOS/1", "result": "updated", ...}
+ *   {"event": "finished", "devices": 2, "updated": 1, "up_to_date": 1, "failed": 0}
+ */
+
+#[derive(Debug, Clone, Copy, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DeviceState {
+    Querying,
+    Updating,
+    /// The attempt failed, another one follows
+    Retrying,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct TargetComponent {
+    pub component_id: u16,
+    pub component_version: u32,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum StatusEvent {
+    Started {
+        devices: usize,
+        components: Vec<TargetComponent>,
+    },
+    Device {
+        device: String,
+        state: DeviceState,
+        attempt: u32,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        error: Option<String>,
+    },
+    Progress {
+        device: String,
+        component_id: u16,
+        percent: u64,
+    },
+    Component {
+        device: String,
+        component: ComponentReport,
+    },
+    DeviceFinished(DeviceReport),
+    Finished(Summary),
+}
+
+/// Publishes the events on the status topic (if any) and prints them
+pub struct StatusPublisher {
+    client: Option<Client>,
+    topic: String,
+    closing: Arc<AtomicBool>,
+    handle: Option<JoinHandle<()>>,
+}
+
+impl StatusPublisher {
+    pub fn connect(host: &str, port: u16, topic: Option<String>) -> Self {
+        let topic = match topic {
+            Some(topic) => topic,
+            None => {
+                return Self {
+                    client: None,
+                    topic: String::new(),
+                    closing: Arc::new(AtomicBool::new(false)),
+                    handle: None,
+                }
+            }
+        };
+        let mut options =
+            MqttOptions::new(format!("update-tool-fleet-{}", process::id()), host, port);
+        options.set_keep_alive(Duration::from_secs(30));
+        let (client, mut connection) = Client::new(options, 100);
+        let closing = Arc::new(AtomicBool::new(false));
+        let connection_closing = closing.clone();
+        // The status is best effort, the update goes on without the broker
+        let handle = thread::spawn(move || {
+            for notification in connection.iter() {
+                match notification {
+                    Ok(Event::Outgoing(Outgoing::Disconnect)) => return,
+                    Ok(_) => {}
+                    Err(_) if connection_closing.load(Ordering::Relaxed) => return,
+                    Err(_) => thread::sleep(Duration::from_secs(1)),
+                }
+            }
+        });
+        return Self {
+            client: Some(client),
+            topic: topic,
+            closing: closing,
+            handle: Some(handle),
+        };
+    }
+
+    pub fn publish(&self, event: StatusEvent) {
+        print_event(&event);
+        if let Some(client) = &self.client {
+            let payload = serde_json::to_vec(&event).unwrap();
+            // The client is shared by the workers
+            let _ =
+                client
+                    .clone()
+                    .try_publish(self.topic.clone(), QoS::AtLeastOnce, false, payload);
+        }
+    }
+
+    /// Sends what is still queued, then disconnects
+    pub fn close(mut self) {
+        self.closing.store(true, Ordering::Relaxed);
+        if let Some(client) = &mut self.client {
+            let _ = client.try_disconnect();
+        }
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.join();
+        }
+    }
+}
+
+/// Human readable version of the events, the progress is left out
+fn print_event(event: &StatusEvent) {
+    match event {
+        StatusEvent::Started {
+            devices,
+            components,
+        } => println!(
+            "Updating {} devices to {} components",
+            devices,
+            components.len()
+        ),
+        StatusEvent::Device {
+            device,
+            state: DeviceState::Retrying,
+            attempt,
+            error,
+        } => println!(
+            "[{}] Attempt {} failed: {}",
+            device,
+            attempt,
+            error.as_deref().unwrap_or("unknown error")
+        ),
+        StatusEvent::Component { device, component } => println!(
+            "[{}] Component {}: {} -> {} {:?}{}",
+            device,
+            component.component_id,
+            component
+                .from
+                .map_or(String::from("none"), |from| from.to_string()),
+            component.to,
+            component.result,
+            component
+                .error
+                .as_ref()
+                .map_or(String::new(), |e| format!(" ({})", e))
+        ),
+        StatusEvent::DeviceFinished(report) => println!(
+            "[{}] {:?} after {} attempts{}",
+            report.device,
+            report.result,
+            report.attempts,
+            report
+                .error
+                .as_ref()
+                .map_or(String::new(), |e| format!(": {}", e))
+        ),
+        StatusEvent::Finished(summary) => println!(
+            "Fleet update finished: {} updated, {} up to date, {} failed",
+            summary.updated, summary.up_to_date, summary.failed
+        ),
+        StatusEvent::Device { .. } | StatusEvent::Progress { .. } => {}
+    }
+}
+
+/// Progress of a component update, as status events
+pub struct StatusProgress<'a> {
+    status: &'a StatusPublisher,
+    device: &'a str,
+    component_id: u16,
+    total: u64,
+    done: u64,
+    /// Last percentage published
+    published: Option<u64>,
+}
+
+impl<'a> StatusProgress<'a> {
+    pub fn new(
+        status: &'a StatusPublisher,
+        device: &'a str,
+        component_id: u16,
+        total: u64,
+    ) -> Self {
+        return Self {
+            status: status,
+            device: device,
+            component_id: component_id,
+            total: total.max(1),
+            done: 0,
+            published: None,
+        };
+    }
+
+    fn update(&mut self) {
+        let percent = (self.done * 100 / self.total).min(100);
+        let step = percent - percent % PROGRESS_STEP;
+        if self.published.map_or(true, |published| step > published) {
+            self.published = Some(step);
+            self.status.publish(StatusEvent::Progress {
+                device: String::from(self.device),
+                component_id: self.component_id,
+                percent: step,
+            });
+        }
+    }
+}
+
+impl UpdateProgress for StatusProgress<'_> {
+    fn message(&mut self, _message: &str) {}
+
+    fn inc(&mut self) -> u64 {
+        return self.add(1);
+    }
+
+    fn add(&mut self, delta: u64) -> u64 {
+        return self.set(self.done + delta);
+    }
+
+    fn set(&mut self, done: u64) -> u64 {
+        self.done = done;
+        self.update();
+        return self.done;
+    }
+
+    fn finish(&mut self) {
+        self.set(self.total);
+    }
+}
@@ -0,0 +1,105 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use log_strings::LogStringTable;
+use serde::Deserialize;
+use std::path::PathBuf;
+
+/// Name of the block map inside the archive generated by the system builder
+const BLOCK_MAP_FILE: &str = "blocks.json";
+
+#[derive(Deserialize, Debug)]
+#[allow(dead_code)]
+pub struct DebugBlockEntry {
+    pub block_type: String,
+    pub component_id: Option<u16>,
+    pub component_version: Option<u32>,
+    pub flash_address: u32,
+    pub flash_size: u32,
+    pub text_base: Option<u32>,
+    pub sram_address: Option<u32>,
+    pub sram_size: Option<u32>,
+    pub elf: Option<String>,
+    pub log_strings: Option<String>,
+}
+
+#[derive(Deserialize, Debug)]
+#[allow(dead_code)]
+struct BlockMap {
+    app: String,
+    board: String,
+    kernel: String,
+    blocks: Vec<DebugBlockEntry>,
+}
+
+/// Debug archive generated by the system builder
+pub struct DebugArchive {
+    root: PathBuf,
+    map: BlockMap,
+}
+
+impl DebugArchive {
+    pub fn open(root: &PathBuf) -> Self {
+        let mut map_path = root.clone();
+        map_path.push(BLOCK_MAP_FILE);
+        let content = std::fs::read_to_string(&map_path)
+            .expect(&format!("Cannot read block map at '{}'", map_path.display()));
+        let map: BlockMap = serde_json::from_str(&content)
+            .expect(&format!("Cannot parse block map at '{}'", map_path.display()));
+        Self {
+            root: root.clone(),
+            map: map,
+        }
+    }
+
+    pub fn kernel_elf(&self) -> PathBuf {
+        let mut path = self.root.clone();
+        path.push(&self.map.kernel);
+        path
+    }
+
+    pub fn blocks(&self) -> &Vec<DebugBlockEntry> {
+        &self.map.blocks
+    }
+
+    /// Returns the ELFs of the components, already moved to their final address
+    pub fn component_elfs(&self) -> Vec<(u16, PathBuf)> {
+        let mut result: Vec<(u16, PathBuf)> = Vec::new();
+        for block in &self.map.blocks {
+            if let (Some(id), Some(elf)) = (block.component_id, &block.elf) {
+                let mut path = self.root.clone();
+                path.push(elf);
+                result.push((id, path));
+            }
+        }
+        result
+    }
+
+    /// GDB commands that load the symbols of the whole system
+    pub fn gdb_symbol_commands(&self) -> Vec<String> {
+        let mut commands: Vec<String> = Vec::new();
+        commands.push(format!("file {}", self.kernel_elf().display()));
+        for (_, elf) in self.component_elfs() {
+            // Sections already have the right addresses
+            commands.push(format!("add-symbol-file {}", elf.display()));
+        }
+        commands
+    }
+
+    /// Format strings of the deferred logs of every component
+    pub fn log_string_tables(&self) -> Vec<LogStringTable> {
+        let mut result: Vec<LogStringTable> = Vec::new();
+        for block in &self.map.blocks {
+            if let Some(table) = &block.log_strings {
+                let mut path = self.root.clone();
+                path.push(table);
+                match log_strings::read_table(path.to_str().unwrap()) {
+                    Ok(table) => result.push(table),
+                    Err(e) => println!("Cannot read log strings at '{}': {}", path.display(), e),
+                }
+            }
+        }
+        result
+    }
+}
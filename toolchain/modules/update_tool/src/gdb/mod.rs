@@ -2,11 +2,24 @@
 // License, v. 2.0. If a copy of the MPL was not distributed with this
 // file, You can obtain one at https://mozilla.org/MPL/2.0/.
 
+mod archive;
+
+use crate::elf_store::ElfStore;
+use crate::info::query_components;
 use crate::utils::*;
+use crossbeam_channel::{Receiver, Sender};
+use log_strings::LogStringTable;
 use std::path::PathBuf;
 use std::process::{Child, Command};
 
-pub fn gdb(app_config: String, verbose: bool) {
+use self::archive::DebugArchive;
+
+pub fn gdb(
+    app_config: String,
+    debug_archive: Option<String>,
+    live: Option<(&Receiver<u8>, &Sender<Vec<u8>>)>,
+    verbose: bool,
+) {
     // Validate paths
     let app_config_path = PathBuf::from(app_config);
     if !app_config_path.exists() {
@@ -17,16 +30,113 @@ pub fn gdb(app_config: String, verbose: bool) {
         .expect("Cannot read app config");
     // Extract needed information
     let target_chip = openocd_board_to_chip(&app_config.board);
+    // Find where the symbols are
+    let symbol_commands = symbol_commands(&app_config_path, debug_archive, live, verbose);
     // Start openocd
     let mut openocd = openocd_start(&target_chip, verbose);
     // Start gdb
-    let mut gdb = gdb_start();
+    let mut gdb = gdb_start(&symbol_commands);
     // Wait gdb
     gdb.wait().unwrap();
     // Terminate childs
     openocd.kill().unwrap();
 }
 
+/// Generates the GDB commands to load the symbols of the whole system.
+/// When the device is reachable, the components are loaded from the local ELF store
+/// where they are now, otherwise from the debug archive generated at build time.
+pub fn symbol_commands(
+    app_config_path: &PathBuf,
+    debug_archive: Option<String>,
+    live: Option<(&Receiver<u8>, &Sender<Vec<u8>>)>,
+    verbose: bool,
+) -> Vec<String> {
+    let archive_path = match debug_archive {
+        Some(path) => PathBuf::from(path),
+        None => default_archive_path(app_config_path),
+    };
+    let archive = match archive_path.exists() {
+        true => Some(DebugArchive::open(&archive_path)),
+        false => {
+            println!("Cannot find debug archive at '{}'", archive_path.display());
+            None
+        }
+    };
+    if let (Some(archive), true) = (&archive, verbose) {
+        for block in archive.blocks() {
+            println!(
+                "Block {} at {:#010x} [size: {}]",
+                block.block_type, block.flash_address, block.flash_size
+            );
+        }
+    }
+    let (channel_in_consumer, channel_out_producer) = match live {
+        Some(channels) => channels,
+        None => {
+            return match archive {
+                Some(archive) => archive.gdb_symbol_commands(),
+                None => Vec::new(),
+            }
+        }
+    };
+    // Kernel is never moved
+    let mut commands: Vec<String> = Vec::new();
+    let kernel_elf = match &archive {
+        Some(archive) => archive.kernel_elf(),
+        None => {
+            let mut path = app_config_path.parent().unwrap().to_path_buf();
+            path.push("build");
+            path.push("kernel.elf");
+            path
+        }
+    };
+    if kernel_elf.exists() {
+        commands.push(format!("file {}", kernel_elf.display()));
+    }
+    // Components are where the device says
+    let store = ElfStore::open();
+    let components = query_components(channel_in_consumer, channel_out_producer, verbose)
+        .unwrap_or_else(|e| panic!("{}", e));
+    for component in components {
+        match store.gdb_symbol_command(
+            component.component_id,
+            component.component_version,
+            component.block_base_address,
+            component.sram_base_address,
+        ) {
+            Some(command) => commands.push(command),
+            None => println!(
+                "Component {} [v {}] not found in the ELF store, no symbols loaded",
+                component.component_id, component.component_version
+            ),
+        }
+    }
+    commands
+}
+
+/// Loads the format strings of the deferred logs from the debug archive
+pub fn log_string_tables(app_config_path: &PathBuf, debug_archive: Option<String>) -> Vec<LogStringTable> {
+    let archive_path = match debug_archive {
+        Some(path) => PathBuf::from(path),
+        None => default_archive_path(app_config_path),
+    };
+    if !archive_path.exists() {
+        println!(
+            "Cannot find debug archive at '{}', deferred logs cannot be decoded",
+            archive_path.display()
+        );
+        return Vec::new();
+    }
+    return DebugArchive::open(&archive_path).log_string_tables();
+}
+
+/// The system builder places the archive next to the image (App -> App_debug)
+fn default_archive_path(app_config_path: &PathBuf) -> PathBuf {
+    let mut path = app_config_path.parent().unwrap().to_path_buf();
+    path.push("App_debug");
+    path
+}
+
 fn openocd_start(target_chip: &String, verbose: bool) -> Child {
     let mut cmd = Command::new("openocd");
     cmd.arg("-f")
@@ -46,8 +156,11 @@ fn openocd_start(target_chip: &String, verbose: bool) -> Child {
     status.unwrap()
 }
 
-fn gdb_start() -> Child {
+fn gdb_start(symbol_commands: &Vec<String>) -> Child {
     let mut cmd = Command::new("gdb-multiarch");
+    for command in symbol_commands {
+        cmd.arg("-ex").arg(command);
+    }
     cmd.arg("-ex")
         .arg("target extended-remote localhost:3333");
     let status = cmd.spawn();
@@ -2,7 +2,7 @@
 // License, v. 2.0. If a copy of the MPL was not distributed with this
 // file, You can obtain one at https://mozilla.org/MPL/2.0/.
 
-use std::fmt::Debug;
+use std::fmt::{Debug, Display};
 
 use crate::{
     crc::crc8_update,
@@ -41,7 +41,7 @@ impl<'a> ComponentInfoMessage<'a> {
         2
     }
     pub const fn max_size() -> usize {
-        17
+        27
     }
     pub fn get_component_id(&self) -> u16 {
         u16_from_le_bytes(&self.buffer[0..0 + 2])
@@ -58,6 +58,16 @@ impl<'a> ComponentInfoMessage<'a> {
     pub fn get_component_status(&self) -> ComponentStatus {
         ComponentStatus::from_bits_truncate(u16_from_le_bytes(&self.buffer[14..14 + 2]))
     }
+    pub fn get_block_base_address(&self) -> u32 {
+        u32_from_le_bytes(&self.buffer[16..16 + 4])
+    }
+    pub fn get_sram_base_address(&self) -> u32 {
+        u32_from_le_bytes(&self.buffer[20..20 + 4])
+    }
+    /// Number of ComponentDependencyMessage following
+    pub fn get_num_dependencies(&self) -> u16 {
+        u16_from_le_bytes(&self.buffer[24..24 + 2])
+    }
     fn validate(buffer: &'a [u8]) -> Result<(), ComponentInfoResult> {
         // Check message size
         if buffer.len() == Self::min_size() {
@@ -91,6 +101,169 @@ impl Debug for ComponentInfoMessage<'_> {
         f.write_fmt(format_args!("\tVersion: {}\n", &self.get_component_version()))?;
         f.write_fmt(format_args!("\tFlash: {}\n", &self.get_allocated_flash()))?;
         f.write_fmt(format_args!("\tRAM: {}\n", &self.get_allocated_ram()))?;
+        f.write_fmt(format_args!("\tFlash Block: {:#010x}\n", &self.get_block_base_address()))?;
+        f.write_fmt(format_args!("\tSRAM Base: {:#010x}\n", &self.get_sram_base_address()))?;
         f.write_fmt(format_args!("\tStatus: {:?}", &self.get_component_status()))
     }
-}
\ No newline at end of file
+}
+/// A dependency of the component before, as in its CBF (a version of 0 means no bound)
+pub struct ComponentDependencyMessage<'a> {
+    buffer: &'a [u8],
+}
+
+impl<'a> ComponentDependencyMessage<'a> {
+    pub fn from(buffer: &'a [u8]) -> Result<Self, ComponentInfoResult> {
+        // Check message size
+        if buffer.len() != Self::get_size() {
+            return Err(ComponentInfoResult::InvalidMessage);
+        }
+        // Check CRC
+        let mut crc = 0x00;
+        for i in 0..(buffer.len() - 1) {
+            crc8_update(&mut crc, buffer[i]);
+        }
+        if crc != buffer[buffer.len() - 1] {
+            return Err(ComponentInfoResult::InvalidCRC);
+        }
+        // Return instance
+        Ok(Self { buffer: buffer })
+    }
+    pub const fn get_size() -> usize {
+        11
+    }
+    pub fn get_component_id(&self) -> u16 {
+        u16_from_le_bytes(&self.buffer[0..0 + 2])
+    }
+    pub fn get_min_version(&self) -> u32 {
+        u32_from_le_bytes(&self.buffer[2..2 + 4])
+    }
+    pub fn get_max_version(&self) -> u32 {
+        u32_from_le_bytes(&self.buffer[6..6 + 4])
+    }
+}
+
+/// Cause of the last reset, sent after the components
+pub struct ResetCauseMessage<'a> {
+    buffer: &'a [u8],
+}
+
+impl<'a> ResetCauseMessage<'a> {
+    pub fn from(buffer: &'a [u8]) -> Result<Self, ComponentInfoResult> {
+        // Check message size
+        if buffer.len() != Self::get_size() {
+            return Err(ComponentInfoResult::InvalidMessage);
+        }
+        // Check CRC
+        let mut crc = 0x00;
+        for i in 0..(buffer.len() - 1) {
+            crc8_update(&mut crc, buffer[i]);
+        }
+        if crc != buffer[buffer.len() - 1] {
+            return Err(ComponentInfoResult::InvalidCRC);
+        }
+        // Return instance
+        Ok(Self { buffer: buffer })
+    }
+    pub const fn get_size() -> usize {
+        4
+    }
+    /// As the ResetCause of the watchdog component
+    pub fn get_cause(&self) -> u8 {
+        self.buffer[0]
+    }
+    /// The one that missed its check-in, 0 if unknown
+    pub fn get_component(&self) -> u16 {
+        u16_from_le_bytes(&self.buffer[1..1 + 2])
+    }
+}
+
+impl Display for ResetCauseMessage<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let cause = match self.get_cause() {
+            1 => "watchdog",
+            2 => "window watchdog",
+            3 => "low power",
+            4 => "software",
+            5 => "power on",
+            6 => "option bytes",
+            7 => "reset pin",
+            _ => "unknown",
+        };
+        f.write_fmt(format_args!("last reset: {}", cause))?;
+        if self.get_cause() == 1 && self.get_component() != 0 {
+            f.write_fmt(format_args!(
+                " (component {} unresponsive)",
+                self.get_component()
+            ))?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn record(cause: u8, component: u16) -> Vec<u8> {
+        let mut buffer = vec![cause];
+        buffer.extend_from_slice(&component.to_le_bytes());
+        let mut crc = 0x00;
+        for b in &buffer {
+            crc8_update(&mut crc, *b);
+        }
+        buffer.push(crc);
+        buffer
+    }
+
+    #[test]
+    fn test_reset_cause() {
+        let buffer = record(1, 5);
+        let msg = ResetCauseMessage::from(&buffer).unwrap();
+        assert_eq!(msg.get_component(), 5);
+        assert_eq!(
+            msg.to_string(),
+            "last reset: watchdog (component 5 unresponsive)"
+        );
+        let buffer = record(1, 0);
+        let msg = ResetCauseMessage::from(&buffer).unwrap();
+        assert_eq!(msg.to_string(), "last reset: watchdog");
+        let buffer = record(5, 0);
+        let msg = ResetCauseMessage::from(&buffer).unwrap();
+        assert_eq!(msg.to_string(), "last reset: power on");
+    }
+
+    #[test]
+    fn test_reset_cause_invalid() {
+        let mut buffer = record(1, 5);
+        buffer[1] = 6;
+        assert!(matches!(
+            ResetCauseMessage::from(&buffer),
+            Err(ComponentInfoResult::InvalidCRC)
+        ));
+        assert!(matches!(
+            ResetCauseMessage::from(&buffer[0..3]),
+            Err(ComponentInfoResult::InvalidMessage)
+        ));
+    }
+
+    #[test]
+    fn test_dependency() {
+        let mut buffer = 3u16.to_le_bytes().to_vec();
+        buffer.extend_from_slice(&2u32.to_le_bytes());
+        buffer.extend_from_slice(&0u32.to_le_bytes());
+        let mut crc = 0x00;
+        for b in &buffer {
+            crc8_update(&mut crc, *b);
+        }
+        buffer.push(crc);
+        let msg = ComponentDependencyMessage::from(&buffer).unwrap();
+        assert_eq!(msg.get_component_id(), 3);
+        assert_eq!(msg.get_min_version(), 2);
+        assert_eq!(msg.get_max_version(), 0);
+        buffer[2] = 1;
+        assert!(matches!(
+            ComponentDependencyMessage::from(&buffer),
+            Err(ComponentInfoResult::InvalidCRC)
+        ));
+    }
+}
@@ -4,37 +4,134 @@
 
 mod messages;
 
+use std::{fmt, time::Duration};
+
 use crossbeam_channel::Receiver;
 use crossbeam_channel::Sender;
 
 use crate::common_messages::*;
 use crate::utils::*;
 
+use self::messages::ComponentDependencyMessage;
 use self::messages::ComponentInfoMessage;
 use self::messages::ComponentInfoResult;
+use self::messages::ResetCauseMessage;
+
+/// Wait for the cause of the last reset after the components. The device sends
+/// it right after them, unless its firmware predates it.
+const RESET_CAUSE_TIMEOUT: Duration = Duration::from_millis(500);
+
+/// Placement of a component on the device
+#[derive(Debug, Clone)]
+#[allow(dead_code)]
+pub struct ComponentInfo {
+    pub component_id: u16,
+    pub component_version: u32,
+    pub block_base_address: u32,
+    pub sram_base_address: u32,
+    /// As in the CBF of the installed version
+    pub dependencies: Vec<Dependency>,
+}
+
+/// A version of 0 means no bound
+#[derive(Debug, Clone, PartialEq)]
+pub struct Dependency {
+    pub component_id: u16,
+    pub min_version: u32,
+    pub max_version: u32,
+}
+
+impl Dependency {
+    pub fn accepts(&self, version: u32) -> bool {
+        (self.min_version == 0 || version >= self.min_version)
+            && (self.max_version == 0 || version <= self.max_version)
+    }
+}
+
+impl fmt::Display for Dependency {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match (self.min_version, self.max_version) {
+            (0, 0) => write!(f, "component {}", self.component_id),
+            (min, 0) => write!(f, "component {} >= {}", self.component_id, min),
+            (0, max) => write!(f, "component {} <= {}", self.component_id, max),
+            (min, max) => write!(f, "component {} in {}..={}", self.component_id, min, max),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum InfoError {
+    /// Wrong answer to the HELLO
+    Hello,
+    /// A component could not be read
+    InvalidMessage,
+    Channel(ChannelClosed),
+}
+
+impl From<ChannelClosed> for InfoError {
+    fn from(e: ChannelClosed) -> Self {
+        InfoError::Channel(e)
+    }
+}
+
+impl fmt::Display for InfoError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            InfoError::Hello => write!(f, "Wrong response from device at HELLO"),
+            InfoError::InvalidMessage => write!(f, "Cannot read component status message"),
+            InfoError::Channel(e) => write!(f, "{}", e),
+        }
+    }
+}
 
 pub fn info(
     channel_in_consumer: Receiver<u8>,
     channel_out_producer: Sender<Vec<u8>>,
     verbose: bool,
 ) {
+    begin_system_info(&channel_in_consumer, &channel_out_producer, verbose)
+        .and_then(|_| read_system_info(&channel_in_consumer, &channel_out_producer, true))
+        .unwrap_or_else(|e| panic!("{}", e));
+}
+
+/// Retrieves the components currently on the device, without printing them
+pub fn query_components(
+    channel_in_consumer: &Receiver<u8>,
+    channel_out_producer: &Sender<Vec<u8>>,
+    verbose: bool,
+) -> Result<Vec<ComponentInfo>, InfoError> {
+    begin_system_info(channel_in_consumer, channel_out_producer, verbose)?;
+    read_system_info(channel_in_consumer, channel_out_producer, false)
+}
+
+fn begin_system_info(
+    channel_in_consumer: &Receiver<u8>,
+    channel_out_producer: &Sender<Vec<u8>>,
+    verbose: bool,
+) -> Result<(), InfoError> {
     // Send hello message
     let hello_msg = HelloMessage::new(OperationType::SystemInfo);
-    channel_write(&channel_out_producer, &hello_msg.get_raw());
+    channel_write(&channel_out_producer, &hello_msg.get_raw())?;
     // Read hello response
     let mut buff: [u8; HelloResponseMessage::get_size()] = [0x00; HelloResponseMessage::get_size()];
-    channel_read(&channel_in_consumer, &mut buff);
+    channel_read(&channel_in_consumer, &mut buff)?;
     // Validate hello response
-    HelloResponseMessage::from(&buff).expect("Wrong response from device at HELLO");
+    HelloResponseMessage::from(&buff).map_err(|_| InfoError::Hello)?;
     if verbose {
         println!("Got HELLO!");
     }
-    read_system_info(&channel_in_consumer, &channel_out_producer);
+    Ok(())
 }
 
-fn read_system_info(channel_in_consumer: &Receiver<u8>, _channel_out_producer: &Sender<Vec<u8>>) {
-    println!("----------- System Status -----------");
-    let mut at_least_one: bool = false;
+fn read_system_info(
+    channel_in_consumer: &Receiver<u8>,
+    _channel_out_producer: &Sender<Vec<u8>>,
+    print: bool,
+) -> Result<Vec<ComponentInfo>, InfoError> {
+    if print {
+        println!("----------- System Status -----------");
+    }
+    let mut components: Vec<ComponentInfo> = Vec::new();
     loop {
         // Start two read the first bytes
         let mut buff: [u8; ComponentInfoMessage::max_size()] =
@@ -42,34 +139,72 @@ fn read_system_info(channel_in_consumer: &Receiver<u8>, _channel_out_producer: &
         channel_read(
             &channel_in_consumer,
             &mut buff[..ComponentInfoMessage::min_size()],
-        );
+        )?;
         let parse_res = ComponentInfoMessage::from(&buff[..ComponentInfoMessage::min_size()]);
         if parse_res.is_err() {
             match parse_res.unwrap_err() {
                 ComponentInfoResult::NoMoreComponents => break, // Finished
                 ComponentInfoResult::InvalidMessage | ComponentInfoResult::InvalidCRC => {
-                    panic!("Got invalid message")
+                    return Err(InfoError::InvalidMessage)
                 }
                 ComponentInfoResult::NeedMoreBytes => {
                     // Read missing bytes
                     channel_read(
                         &channel_in_consumer,
                         &mut buff[ComponentInfoMessage::min_size()..],
-                    );
+                    )?;
                     // Try to parse now
-                    let msg_result = ComponentInfoMessage::from(&buff);
-                    if msg_result.is_err() {
-                        panic!("Cannot read component status message");
+                    let msg =
+                        ComponentInfoMessage::from(&buff).map_err(|_| InfoError::InvalidMessage)?;
+                    if print {
+                        println!("{:?}", msg);
                     }
-                    let msg = msg_result.unwrap();
-                    println!("{:?}", msg);
-                    at_least_one = true;
+                    // Then its dependencies
+                    let mut dependencies: Vec<Dependency> = Vec::new();
+                    for _ in 0..msg.get_num_dependencies() {
+                        let mut buff: [u8; ComponentDependencyMessage::get_size()] =
+                            [0x00; ComponentDependencyMessage::get_size()];
+                        channel_read(&channel_in_consumer, &mut buff)?;
+                        let dependency = ComponentDependencyMessage::from(&buff)
+                            .map_err(|_| InfoError::InvalidMessage)?;
+                        dependencies.push(Dependency {
+                            component_id: dependency.get_component_id(),
+                            min_version: dependency.get_min_version(),
+                            max_version: dependency.get_max_version(),
+                        });
+                    }
+                    if print {
+                        for dependency in &dependencies {
+                            println!("\tNeeds: {}", dependency);
+                        }
+                    }
+                    components.push(ComponentInfo {
+                        component_id: msg.get_component_id(),
+                        component_version: msg.get_component_version(),
+                        block_base_address: msg.get_block_base_address(),
+                        sram_base_address: msg.get_sram_base_address(),
+                        dependencies: dependencies,
+                    });
                 }
             }
         }
     }
-    if !at_least_one {
-        println!("\n\tNo components found!");
+    // Then the cause of the last reset, that older firmware does not send
+    let mut buff: [u8; ResetCauseMessage::get_size()] = [0x00; ResetCauseMessage::get_size()];
+    let has_reset_cause =
+        channel_read_timeout(&channel_in_consumer, &mut buff, RESET_CAUSE_TIMEOUT);
+    if print {
+        if components.is_empty() {
+            println!("\n\tNo components found!");
+        }
+        if has_reset_cause {
+            let reset_cause =
+                ResetCauseMessage::from(&buff).expect("Cannot read reset cause message");
+            println!("\n{}", reset_cause);
+        } else {
+            println!("\nlast reset: not reported by the device");
+        }
+        println!("\n----------- ------------- -----------");
     }
-    println!("\n----------- ------------- -----------");
+    Ok(components)
 }
@@ -0,0 +1,214 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use flash_allocator::flash::{page::FlashPage, FlashMethods};
+use std::path::PathBuf;
+
+/// Intel HEX record types
+const IHEX_DATA: u8 = 0x00;
+const IHEX_EOF: u8 = 0x01;
+const IHEX_EXTENDED_SEGMENT: u8 = 0x02;
+const IHEX_EXTENDED_LINEAR: u8 = 0x04;
+
+/**
+ * Read-only copy of the whole flash of the device.
+ * Bytes not covered by the dump are considered erased (0xFF).
+ */
+pub struct FlashImage {
+    base_addr: u32,
+    page_size: u32,
+    buffer: Vec<u8>,
+}
+
+impl FlashImage {
+    /// Loads a dump, deciding the format from the extension:
+    /// .ihex/.hex files are parsed as Intel HEX, everything else as raw binary
+    /// starting at the base of the flash.
+    pub fn load(path: &PathBuf, base_addr: u32, size: u32, page_size: u32) -> Self {
+        let bytes =
+            std::fs::read(path).expect(&format!("Cannot read image at '{}'", path.display()));
+        let mut image = Self {
+            base_addr: base_addr,
+            page_size: page_size,
+            buffer: vec![0xFF; size as usize],
+        };
+        let extension = path
+            .extension()
+            .map(|e| e.to_string_lossy().to_lowercase())
+            .unwrap_or_default();
+        match extension.as_str() {
+            "ihex" | "hex" => image.load_ihex(&bytes),
+            _ => image.load_bin(&bytes),
+        }
+        return image;
+    }
+
+    fn load_bin(&mut self, bytes: &[u8]) {
+        if bytes.len() > self.buffer.len() {
+            panic!(
+                "Image is bigger than the flash: {} bytes, but only {} available",
+                bytes.len(),
+                self.buffer.len()
+            );
+        }
+        self.buffer[0..bytes.len()].copy_from_slice(bytes);
+    }
+
+    fn load_ihex(&mut self, bytes: &[u8]) {
+        let text = String::from_utf8_lossy(bytes);
+        let mut upper_address: u32 = 0;
+        for (line_num, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() {
+                continue;
+            }
+            let record = parse_ihex_record(line)
+                .expect(&format!("Malformed iHEX record at line {}", line_num + 1));
+            // Record: [len, addr_hi, addr_lo, type, data..., checksum]
+            let data_len = record[0] as usize;
+            let offset = u16::from_be_bytes([record[1], record[2]]) as u32;
+            let data = &record[4..4 + data_len];
+            match record[3] {
+                IHEX_DATA => {
+                    let address = upper_address + offset;
+                    if address < self.base_addr
+                        || address + data_len as u32 > self.base_addr + self.buffer.len() as u32
+                    {
+                        panic!(
+                            "iHEX record at line {} is outside the flash: {:#010x}",
+                            line_num + 1,
+                            address
+                        );
+                    }
+                    let start = (address - self.base_addr) as usize;
+                    self.buffer[start..start + data_len].copy_from_slice(data);
+                }
+                IHEX_EOF => break,
+                IHEX_EXTENDED_SEGMENT => {
+                    upper_address = (u16::from_be_bytes([data[0], data[1]]) as u32) << 4;
+                }
+                IHEX_EXTENDED_LINEAR => {
+                    upper_address = (u16::from_be_bytes([data[0], data[1]]) as u32) << 16;
+                }
+                _ => {} // Start addresses are not relevant here
+            }
+        }
+    }
+
+    pub fn base_address(&self) -> u32 {
+        self.base_addr
+    }
+
+    pub fn size(&self) -> u32 {
+        self.buffer.len() as u32
+    }
+
+    pub fn read_u32(&self, address: u32) -> u32 {
+        let mut buff: [u8; 4] = [0x00; 4];
+        self.read(address, &mut buff).unwrap();
+        return u32::from_le_bytes(buff);
+    }
+
+    pub fn read_u16(&self, address: u32) -> u16 {
+        let mut buff: [u8; 2] = [0x00; 2];
+        self.read(address, &mut buff).unwrap();
+        return u16::from_le_bytes(buff);
+    }
+
+    /// Returns the data in [address, address + len)
+    pub fn slice(&self, address: u32, len: usize) -> Option<&[u8]> {
+        if address < self.base_addr {
+            return None;
+        }
+        let start = (address - self.base_addr) as usize;
+        return self.buffer.get(start..start + len);
+    }
+}
+
+/// Decodes a single record, validating its checksum
+fn parse_ihex_record(line: &str) -> Option<Vec<u8>> {
+    let hex = line.strip_prefix(':')?;
+    if hex.len() % 2 != 0 || hex.len() < 10 {
+        return None;
+    }
+    let mut record: Vec<u8> = Vec::new();
+    for i in (0..hex.len()).step_by(2) {
+        record.push(u8::from_str_radix(&hex[i..i + 2], 16).ok()?);
+    }
+    // Length must match the record
+    if record.len() != record[0] as usize + 5 {
+        return None;
+    }
+    // The sum of all the bytes, checksum included, must be zero
+    let sum = record.iter().fold(0u8, |acc, b| acc.wrapping_add(*b));
+    if sum != 0 {
+        return None;
+    }
+    return Some(record);
+}
+
+impl<'a> FlashMethods<'a> for FlashImage {
+    fn read(&self, address: u32, buffer: &mut [u8]) -> Result<(), ()> {
+        match self.slice(address, buffer.len()) {
+            Some(data) => {
+                buffer.copy_from_slice(data);
+                Ok(())
+            }
+            None => Err(()),
+        }
+    }
+
+    fn page_from_address(&self, address: u32) -> Option<FlashPage> {
+        if address < self.base_addr || address >= self.base_addr + self.size() {
+            return None;
+        }
+        let page_num = (address - self.base_addr) / self.page_size;
+        self.page_from_number(page_num as u16)
+    }
+
+    fn page_from_number(&self, page_num: u16) -> Option<FlashPage> {
+        if page_num as u32 >= self.size() / self.page_size {
+            return None;
+        }
+        let base_addr = self.base_addr + page_num as u32 * self.page_size;
+        Some(FlashPage::new(page_num, base_addr, self.page_size as u16))
+    }
+
+    fn prev_page(&self, page_num: u16) -> Option<FlashPage> {
+        if page_num == 0 {
+            return None;
+        }
+        self.page_from_number(page_num - 1)
+    }
+
+    fn write(&mut self, _address: u32, _data: &[u8]) -> Result<(), ()> {
+        panic!("Image is read-only");
+    }
+
+    fn flush_write_buffer(&mut self) -> Result<(), ()> {
+        Ok(())
+    }
+
+    fn erase(&mut self, _page_num: u16) -> Result<(), ()> {
+        panic!("Image is read-only");
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::parse_ihex_record;
+
+    #[test]
+    fn test_ihex_record() {
+        // Extended linear address 0x0800
+        assert_eq!(
+            parse_ihex_record(":020000040800F2"),
+            Some(vec![0x02, 0x00, 0x00, 0x04, 0x08, 0x00, 0xF2])
+        );
+        // Wrong checksum
+        assert_eq!(parse_ihex_record(":020000040800F3"), None);
+        // Wrong length
+        assert_eq!(parse_ihex_record(":030000040800F2"), None);
+    }
+}
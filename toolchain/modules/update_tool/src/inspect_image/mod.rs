@@ -0,0 +1,386 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+mod image;
+
+use alloc_report::{AllocStatEntry, AllocStats};
+use cbf_rs::CbfFile;
+use flash_allocator::flash::{utils, walker::FlashWalkerImpl, BlockType, FlashBlock, FlashMethods};
+use std::path::PathBuf;
+
+use self::image::FlashImage;
+
+/// Minimum write granularity used by the swap procedure (see flash_allocator::FLAG_BYTES)
+const SWAP_FLAG_BYTES: u32 = 8;
+/// Bytes placed before the CBF in a component block: SRAM base + SRAM size
+const COMPONENT_PREFIX_SIZE: u32 = 8;
+
+/// Parameters of the board the image was dumped from
+struct BoardLayout {
+    flash_start: u32,
+    flash_size: u32,
+    page_size: u32,
+    allocator_start: u32,
+    allocator_size: u32,
+    allocator_scan: u32,
+    sram_start: u32,
+    sram_size: u32,
+    sram_reserved: u32,
+}
+
+/// A live block found in the image
+struct ImageBlock {
+    block: FlashBlock,
+    level: u16,
+}
+
+pub fn inspect_image(
+    image_path: String,
+    board: String,
+    swap_page: Option<u16>,
+    report_path: Option<String>,
+    verbose: bool,
+) {
+    let layout = board_layout(&board);
+    let image_path = PathBuf::from(image_path);
+    let mut image = FlashImage::load(
+        &image_path,
+        layout.flash_start,
+        layout.flash_size,
+        layout.page_size,
+    );
+    if verbose {
+        println!(
+            "Loaded image of {} bytes starting at {:#010x}",
+            image.size(),
+            image.base_address()
+        );
+    }
+    println!("\n------- Flash Image -------");
+    println!("Board: {}", board);
+    println!(
+        "Allocator: {:#010x} - {:#010x} [first block at {:#010x}]",
+        layout.allocator_start,
+        layout.allocator_start + layout.allocator_size - 1,
+        layout.allocator_scan
+    );
+    // Walk the image
+    let (blocks, dismissed) = walk_image(&board, &mut image);
+    // Collect the statistics while printing the blocks
+    let mut stats = AllocStats {
+        flash_start: layout.allocator_start,
+        flash_size: layout.allocator_size,
+        ram_start: layout.sram_start,
+        ram_size: layout.sram_size,
+        kernel_reserved_ram: layout.sram_reserved,
+        kernel_reserved_flash: layout.allocator_scan - layout.allocator_start,
+        entries: Vec::new(),
+    };
+    let kernel_used = kernel_flash_usage(&image, &layout);
+    println!("Kernel: {} / {} bytes", kernel_used, stats.kernel_reserved_flash);
+    stats.entries.push(AllocStatEntry {
+        name: String::from("Kernel"),
+        component_id: 0,
+        flash_address: stats.flash_start,
+        flash_size: stats.kernel_reserved_flash,
+        flash_needed_size: kernel_used,
+        ram_address: stats.ram_start,
+        ram_size: stats.kernel_reserved_ram,
+        ram_needed_size: stats.kernel_reserved_ram,
+    });
+    println!("\n------- Blocks -------");
+    for b in &blocks {
+        if let Some(entry) = inspect_block(&image, &layout, b) {
+            stats.entries.push(entry);
+        }
+    }
+    if dismissed > 0 {
+        println!("{} dismissed block(s) waiting to be erased", dismissed);
+    }
+    print_sram_allocations(&stats);
+    // Check for interrupted swap operations
+    if let Some(page_num) = swap_page {
+        print_swap_state(&image, page_num);
+    }
+    // Finally, generate the same reports of the system builder
+    let report_root = match report_path {
+        Some(path) => PathBuf::from(path),
+        None => image_path
+            .parent()
+            .map(|p| p.to_path_buf())
+            .unwrap_or(PathBuf::from(".")),
+    };
+    std::fs::create_dir_all(&report_root).expect("Cannot create report directory");
+    alloc_report::visualize(stats, &report_root);
+    println!("\nReports generated in: {}", report_root.display());
+}
+
+fn board_layout(board: &String) -> BoardLayout {
+    macro_rules! layout {
+        ($board:ident) => {
+            BoardLayout {
+                flash_start: $board::FLASH_START_ADDR,
+                flash_size: $board::FLASH_END_ADDR - $board::FLASH_START_ADDR + 1,
+                page_size: $board::FLASH_PAGE_SIZE,
+                allocator_start: $board::FLASH_ALLOCATOR_START_ADDR,
+                allocator_size: $board::FLASH_ALLOCATOR_SIZE as u32,
+                allocator_scan: $board::FLASH_ALLOCATOR_START_SCAN_ADDR,
+                sram_start: $board::SRAM_START_ADDR,
+                sram_size: $board::SRAM_END_ADDR - $board::SRAM_START_ADDR + 1,
+                sram_reserved: $board::SRAM_RESERVED,
+            }
+        };
+    }
+    match board.as_str() {
+        "stm32f303re" => layout!(stm32f303re),
+        "stm32l432kc" => layout!(stm32l432kc),
+        "stm32l476rg" => layout!(stm32l476rg),
+        _ => panic!("Unsupported board: {}", board),
+    }
+}
+
+/// Returns the live blocks and the number of dismissed blocks not yet erased
+fn walk_image(board: &String, image: &mut FlashImage) -> (Vec<ImageBlock>, usize) {
+    macro_rules! walk {
+        ($board:ident) => {{
+            const FLASH_START_ADDR: u32 = $board::FLASH_ALLOCATOR_START_ADDR;
+            const FLASH_END_ADDR: u32 = $board::FLASH_ALLOCATOR_END_ADDR;
+            const FLASH_ALLOCATOR_SCAN: u32 = $board::FLASH_ALLOCATOR_START_SCAN_ADDR;
+            const FLASH_TREE_MAX_LEVEL: usize = $board::FLASH_TREE_MAX_LEVEL;
+            // The walker needs write access, even if it only reads
+            let walker = FlashWalkerImpl::<
+                FLASH_START_ADDR,
+                FLASH_END_ADDR,
+                FLASH_ALLOCATOR_SCAN,
+                FLASH_TREE_MAX_LEVEL,
+            >::new(image);
+            let live: Vec<FlashBlock> = walker.collect();
+            let image: &FlashImage = image;
+            let mut blocks: Vec<ImageBlock> = Vec::new();
+            for b in live {
+                let header = utils::read_block_header::<FLASH_START_ADDR, FLASH_TREE_MAX_LEVEL>(
+                    image,
+                    b.get_nominal_base_address() - FLASH_START_ADDR,
+                );
+                blocks.push(ImageBlock {
+                    block: b,
+                    level: header.block_level(),
+                });
+            }
+            // Scan again for dismissed blocks, the walker skips them
+            let allocator_size = FLASH_END_ADDR - FLASH_START_ADDR + 1;
+            let mut offset = FLASH_ALLOCATOR_SCAN - FLASH_START_ADDR;
+            let mut dismissed: usize = 0;
+            while offset < allocator_size {
+                let header = utils::read_block_header::<FLASH_START_ADDR, FLASH_TREE_MAX_LEVEL>(
+                    image, offset,
+                );
+                if header.is_allocated() && header.is_dismissed() {
+                    dismissed += 1;
+                }
+                offset +=
+                    utils::get_block_size::<FLASH_START_ADDR, FLASH_END_ADDR>(&header) as u32;
+            }
+            (blocks, dismissed)
+        }};
+    }
+    match board.as_str() {
+        "stm32f303re" => walk!(stm32f303re),
+        "stm32l432kc" => walk!(stm32l432kc),
+        "stm32l476rg" => walk!(stm32l476rg),
+        _ => panic!("Unsupported board: {}", board),
+    }
+}
+
+/// The kernel is placed at the beginning of the flash, before the first block:
+/// consider used everything up to the last programmed byte.
+fn kernel_flash_usage(image: &FlashImage, layout: &BoardLayout) -> u32 {
+    let reserved = layout.allocator_scan - layout.allocator_start;
+    let kernel_area = image
+        .slice(layout.allocator_start, reserved as usize)
+        .expect("Kernel area outside the image");
+    match kernel_area.iter().rposition(|b| *b != 0xFF) {
+        Some(pos) => pos as u32 + 1,
+        None => 0,
+    }
+}
+
+/// Prints the block and returns its statistics
+fn inspect_block(
+    image: &FlashImage,
+    layout: &BoardLayout,
+    b: &ImageBlock,
+) -> Option<AllocStatEntry> {
+    let block = &b.block;
+    println!(
+        "Block {:#010x}: level {}, size {}, type {:?}, {}",
+        block.get_nominal_base_address(),
+        b.level,
+        block.get_nominal_size(),
+        block.get_type(),
+        if block.is_finalized() {
+            "finalized"
+        } else {
+            "NOT finalized"
+        }
+    );
+    match block.get_type() {
+        BlockType::COMPONENT => inspect_component(image, layout, block),
+        BlockType::COREDUMP => {
+            let magic = image.read_u32(block.get_base_address());
+            println!(
+                "\tCore dump: {}",
+                if magic == 0xFFFF_FFFF {
+                    "empty"
+                } else {
+                    "present"
+                }
+            );
+            Some(AllocStatEntry {
+                name: String::from("Core dump"),
+                component_id: 0,
+                flash_address: block.get_nominal_base_address(),
+                flash_size: block.get_nominal_size(),
+                flash_needed_size: block.get_nominal_size(),
+                ram_address: 0,
+                ram_size: 0,
+                ram_needed_size: 0,
+            })
+        }
+        _ => Some(AllocStatEntry {
+            name: format!("Block {:?}", block.get_type()),
+            component_id: 0,
+            flash_address: block.get_nominal_base_address(),
+            flash_size: block.get_nominal_size(),
+            flash_needed_size: block.get_nominal_size(),
+            ram_address: 0,
+            ram_size: 0,
+            ram_needed_size: 0,
+        }),
+    }
+}
+
+fn inspect_component(
+    image: &FlashImage,
+    layout: &BoardLayout,
+    block: &FlashBlock,
+) -> Option<AllocStatEntry> {
+    // Read the SRAM allocation, placed before the CBF
+    let sram_base = image.read_u32(block.get_base_address());
+    let sram_size = image.read_u32(block.get_base_address() + 4);
+    let sram_valid = sram_base >= layout.sram_start + layout.sram_reserved
+        && sram_base.checked_add(sram_size).map_or(false, |end| {
+            end <= layout.sram_start + layout.sram_size
+        });
+    println!(
+        "\tSRAM: {:#010x} [size: {}]{}",
+        sram_base,
+        sram_size,
+        if sram_valid { "" } else { " INVALID" }
+    );
+    if !block.is_finalized() {
+        // The component was still being written, the CBF is not complete
+        println!("\tCBF: incomplete, skipped");
+        return None;
+    }
+    let cbf_bytes = image
+        .slice(
+            block.get_base_address() + COMPONENT_PREFIX_SIZE,
+            block.get_size() as usize,
+        )
+        .expect("Block outside the image");
+    let cbf = match cbf_rs::parse_cbf(cbf_bytes) {
+        Ok(cbf) => cbf,
+        Err(e) => {
+            println!("\tCBF: cannot parse ({:?})", e);
+            return None;
+        }
+    };
+    let valid = cbf.validate();
+    println!(
+        "\tComponent {} [v {}]: CBF size {}, min RAM {}, checksum {}",
+        cbf.header_base().component_id(),
+        cbf.header_base().component_version(),
+        cbf.header_base().total_size(),
+        cbf.header_main().component_min_ram(),
+        if valid { "valid" } else { "INVALID" }
+    );
+    return Some(AllocStatEntry {
+        name: format!(
+            "Component: {} [v {}]",
+            cbf.header_base().component_id(),
+            cbf.header_base().component_version()
+        ),
+        component_id: cbf.header_base().component_id(),
+        flash_address: block.get_nominal_base_address(),
+        flash_size: block.get_nominal_size(),
+        flash_needed_size: cbf.header_base().total_size(),
+        ram_address: sram_base,
+        ram_size: sram_size,
+        ram_needed_size: cbf.header_main().component_min_ram(),
+    });
+}
+
+fn print_sram_allocations(stats: &AllocStats) {
+    println!("\n------- SRAM -------");
+    let mut entries: Vec<&AllocStatEntry> =
+        stats.entries.iter().filter(|e| e.ram_size > 0).collect();
+    entries.sort_by(|a, b| a.ram_address.cmp(&b.ram_address));
+    let mut prev_end: u32 = 0;
+    for e in entries {
+        println!(
+            "{:#010x} - {:#010x}: {}{}",
+            e.ram_address,
+            e.ram_address + e.ram_size,
+            e.name,
+            if e.ram_address < prev_end {
+                " OVERLAPPING"
+            } else {
+                ""
+            }
+        );
+        prev_end = e.ram_address + e.ram_size;
+    }
+}
+
+/// Decodes the swap page, to find interrupted swap operations.
+/// Layout: [target page (u16)][copy completed flag][fragments: target offset (u32), size (u32), data]
+fn print_swap_state(image: &FlashImage, page_num: u16) {
+    println!("\n------- Swap -------");
+    let page = image
+        .page_from_number(page_num)
+        .expect("Swap page outside the image");
+    let target_page = image.read_u16(page.base_address());
+    if target_page == 0xFFFF {
+        println!("Swap page {}: idle", page_num);
+        return;
+    }
+    let copy_completed = image
+        .slice(page.base_address() + SWAP_FLAG_BYTES, SWAP_FLAG_BYTES as usize)
+        .unwrap()
+        .iter()
+        .any(|b| *b != 0xFF);
+    // Count the fragments saved so far
+    let mut fragments: usize = 0;
+    let mut curr_pos = page.base_address() + 2 * SWAP_FLAG_BYTES;
+    while curr_pos + 8 <= page.end_address() {
+        let frgm_size = image.read_u32(curr_pos + 4);
+        if frgm_size == 0xFFFF_FFFF {
+            break;
+        }
+        fragments += 1;
+        curr_pos += 8 + frgm_size;
+    }
+    println!(
+        "Swap page {}: PENDING swap of page {} ({} fragment(s), copy {})",
+        page_num,
+        target_page,
+        fragments,
+        if copy_completed {
+            "completed, page will be restored at boot"
+        } else {
+            "not completed, swap will be discarded at boot"
+        }
+    );
+}
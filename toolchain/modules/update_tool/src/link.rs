@@ -0,0 +1,112 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use std::time::Duration;
+
+use crossbeam_channel::{Receiver, Sender};
+use update_link::{
+    is_control_frame, ControlFrame, LinkSender, CONTROL_FRAME_SIZE, MAX_PAYLOAD_SIZE,
+};
+
+use crate::common_messages::MessageError;
+use crate::utils::*;
+
+/// Time to wait for an answer, before sending again the oldest fragment
+const LINK_TIMEOUT: Duration = Duration::from_millis(500);
+/// Consecutive timeouts before giving up the transfer
+const LINK_MAX_TIMEOUTS: u32 = 5;
+
+#[derive(Debug)]
+pub enum LinkSendError {
+    /// The receiver stopped answering
+    Timeout,
+    /// The device aborted the operation
+    Device(MessageError),
+    /// The connection to the device is gone
+    Closed,
+}
+
+impl From<ChannelClosed> for LinkSendError {
+    fn from(_: ChannelClosed) -> Self {
+        LinkSendError::Closed
+    }
+}
+
+pub struct LinkStats {
+    pub frames: usize,
+    pub retransmissions: usize,
+}
+
+/// Sends data through the link layer, keeping up to window fragments in flight.
+/// The progress callback is given the number of bytes acknowledged so far.
+pub fn link_send<F: FnMut(usize)>(
+    channel_in_consumer: &Receiver<u8>,
+    channel_out_producer: &Sender<Vec<u8>>,
+    stream: u8,
+    data: &[u8],
+    window: usize,
+    mut progress: F,
+) -> Result<LinkStats, LinkSendError> {
+    let mut sender = LinkSender::new(stream, data, window);
+    let mut stats = LinkStats {
+        frames: 0,
+        retransmissions: 0,
+    };
+    let mut timeouts: u32 = 0;
+    while !sender.is_complete() {
+        // 1. Fill the window
+        while let Some(frame) = sender.next_frame() {
+            channel_write(channel_out_producer, &frame.encode())?;
+            stats.frames += 1;
+        }
+        // 2. Wait for an answer
+        let mut buff: [u8; CONTROL_FRAME_SIZE] = [0x00; CONTROL_FRAME_SIZE];
+        if !channel_read_timeout(channel_in_consumer, &mut buff[0..1], LINK_TIMEOUT) {
+            timeouts += 1;
+            if timeouts > LINK_MAX_TIMEOUTS {
+                return Err(LinkSendError::Timeout);
+            }
+            if let Some(frame) = sender.on_timeout() {
+                channel_write(channel_out_producer, &frame.encode())?;
+                stats.retransmissions += 1;
+            }
+            continue;
+        }
+        timeouts = 0;
+        // An error from the device is a single byte
+        if !is_control_frame(buff[0]) {
+            return Err(LinkSendError::Device(MessageError::from(buff[0])));
+        }
+        if !channel_read_timeout(channel_in_consumer, &mut buff[1..], LINK_TIMEOUT) {
+            continue;
+        }
+        // 3. Send again what the receiver is missing (a corrupted answer is
+        //    recovered by the timeout)
+        let control = match ControlFrame::decode(&buff) {
+            Ok(control) => control,
+            Err(_) => continue,
+        };
+        if let Some(frame) = sender.on_control(control) {
+            channel_write(channel_out_producer, &frame.encode())?;
+            stats.retransmissions += 1;
+        }
+        progress(core::cmp::min(
+            sender.acknowledged() as usize * MAX_PAYLOAD_SIZE,
+            data.len(),
+        ));
+    }
+    return Ok(stats);
+}
+
+/// Reads the next command of the device, skipping late answers of the link layer
+pub fn read_command(channel_in_consumer: &Receiver<u8>) -> Result<u8, ChannelClosed> {
+    loop {
+        let mut buff: [u8; CONTROL_FRAME_SIZE] = [0x00; CONTROL_FRAME_SIZE];
+        channel_read(channel_in_consumer, &mut buff[0..1])?;
+        if !is_control_frame(buff[0]) {
+            return Ok(buff[0]);
+        }
+        channel_read(channel_in_consumer, &mut buff[1..])?;
+    }
+}
@@ -0,0 +1,145 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use crate::{
+    common_messages::{MessageError, SerializableMessage},
+    crc::crc8_update,
+    utils::{u16_from_le_bytes, u32_from_le_bytes},
+};
+
+#[repr(u8)]
+pub enum LogReadCommand {
+    Status = 0x01,
+    SendRequest = 0x02,
+    Record = 0x03,
+    EndOfLog = 0x04,
+}
+
+#[repr(u8)]
+pub enum LogReadResponse {
+    Success = 0xFF,
+}
+
+/// Sequence numbers of the log, sent at the beginning
+pub struct LogStatusMessage<'a> {
+    buffer: &'a [u8],
+}
+
+impl<'a> LogStatusMessage<'a> {
+    pub fn from(buffer: &'a [u8]) -> Result<Self, MessageError> {
+        // Validate buffer
+        validate(buffer, Self::get_size())?;
+        // Return instance
+        Ok(Self { buffer: buffer })
+    }
+    pub const fn get_size() -> usize {
+        14
+    }
+    /// Oldest record stored
+    pub fn get_first(&self) -> u32 {
+        u32_from_le_bytes(&self.buffer[1..5])
+    }
+    /// First record not consumed
+    pub fn get_consumed(&self) -> u32 {
+        u32_from_le_bytes(&self.buffer[5..9])
+    }
+    /// Sequence number of the next record
+    pub fn get_next(&self) -> u32 {
+        u32_from_le_bytes(&self.buffer[9..13])
+    }
+}
+
+#[derive(Clone, Copy)]
+#[repr(u8)]
+pub enum LogRequestAction {
+    /// The first record from the cursor on
+    Read = 0x00,
+    /// The records before the value have been saved
+    Ack = 0x01,
+    /// End of the operation
+    Done = 0x02,
+}
+
+pub struct LogRequestMessage {
+    action: LogRequestAction,
+    value: u32,
+}
+
+impl LogRequestMessage {
+    pub fn new(action: LogRequestAction, value: u32) -> Self {
+        Self {
+            action: action,
+            value: value,
+        }
+    }
+}
+
+impl<'a> SerializableMessage<'a> for LogRequestMessage {
+    fn get_raw(&self) -> Vec<u8> {
+        let mut buffer = Vec::<u8>::new();
+        buffer.push(self.action as u8);
+        buffer.extend_from_slice(&self.value.to_le_bytes());
+        // Compute and append crc
+        let mut crc: u8 = 0x00;
+        for i in 0..buffer.len() {
+            crc8_update(&mut crc, buffer[i]);
+        }
+        buffer.push(crc);
+        buffer
+    }
+}
+
+/// A record of the log: the header, then the data and the crc
+pub struct LogRecordMessage<'a> {
+    buffer: &'a [u8],
+}
+
+impl<'a> LogRecordMessage<'a> {
+    pub fn from(buffer: &'a [u8]) -> Result<Self, MessageError> {
+        if buffer.len() < Self::get_header_size() + 1 {
+            return Err(MessageError::InvalidSize);
+        }
+        // Validate buffer
+        validate(buffer, Self::get_header_size() + buffer[15] as usize + 1)?;
+        // Return instance
+        Ok(Self { buffer: buffer })
+    }
+    /// Command, sequence number, source, timestamp and data length
+    pub const fn get_header_size() -> usize {
+        16
+    }
+    /// Length of the data, from the header
+    pub fn data_len(header: &[u8]) -> usize {
+        header[15] as usize
+    }
+    pub fn get_seq(&self) -> u32 {
+        u32_from_le_bytes(&self.buffer[1..5])
+    }
+    pub fn get_source(&self) -> u16 {
+        u16_from_le_bytes(&self.buffer[5..7])
+    }
+    pub fn get_timestamp(&self) -> u64 {
+        u64::from_le_bytes(self.buffer[7..15].try_into().unwrap())
+    }
+    pub fn get_data(&self) -> &[u8] {
+        &self.buffer[Self::get_header_size()..self.buffer.len() - 1]
+    }
+}
+
+fn validate(buffer: &[u8], size: usize) -> Result<(), MessageError> {
+    // Check message size
+    if buffer.len() != size {
+        return Err(MessageError::InvalidSize);
+    }
+    // Check CRC
+    let mut crc = 0x00;
+    for i in 0..(buffer.len() - 1) {
+        crc8_update(&mut crc, buffer[i]);
+    }
+    if crc != buffer[buffer.len() - 1] {
+        return Err(MessageError::InvalidCRC);
+    }
+    // Return
+    Ok(())
+}
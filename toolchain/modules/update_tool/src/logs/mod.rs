@@ -0,0 +1,213 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+mod messages;
+
+use std::fs;
+
+use crossbeam_channel::{Receiver, Sender};
+
+use self::messages::*;
+use crate::common_messages::*;
+use crate::utils::*;
+
+/// Record of the log store of the device
+struct LogRecord {
+    seq: u32,
+    source: u16,
+    timestamp: u64,
+    data: Vec<u8>,
+}
+
+/// Pulls the records of the log store, from the first one not consumed unless a
+/// cursor is given. With ack they are marked as consumed on the device, once
+/// they have been saved.
+pub fn logs(
+    channel_in_consumer: Receiver<u8>,
+    channel_out_producer: Sender<Vec<u8>>,
+    from: Option<u32>,
+    output: Option<String>,
+    ack: bool,
+    verbose: bool,
+) -> bool {
+    match pull_logs(
+        &channel_in_consumer,
+        &channel_out_producer,
+        from,
+        output,
+        ack,
+        verbose,
+    ) {
+        Ok(()) => true,
+        Err(e) => {
+            eprintln!("Cannot read the log: {}", e);
+            false
+        }
+    }
+}
+
+fn pull_logs(
+    channel_in_consumer: &Receiver<u8>,
+    channel_out_producer: &Sender<Vec<u8>>,
+    from: Option<u32>,
+    output: Option<String>,
+    ack: bool,
+    verbose: bool,
+) -> Result<(), MessageError> {
+    // Send hello message
+    let hello_msg = HelloMessage::new(OperationType::LogRead);
+    channel_write(&channel_out_producer, &hello_msg.get_raw()).unwrap();
+    // Read hello response
+    let mut buff: [u8; HelloResponseMessage::get_size()] = [0x00; HelloResponseMessage::get_size()];
+    channel_read(&channel_in_consumer, &mut buff).unwrap();
+    // Validate hello response
+    HelloResponseMessage::from(&buff).expect("Wrong response from device at HELLO");
+    if verbose {
+        println!("Got HELLO!");
+    }
+    // Step 1: read the status of the log
+    let mut buff: [u8; LogStatusMessage::get_size()] = [0x00; LogStatusMessage::get_size()];
+    channel_read(&channel_in_consumer, &mut buff[0..1]).unwrap();
+    if buff[0] != LogReadCommand::Status as u8 {
+        return Err(MessageError::from(buff[0]));
+    }
+    channel_read(&channel_in_consumer, &mut buff[1..]).unwrap();
+    let status = LogStatusMessage::from(&buff)?;
+    if verbose {
+        println!(
+            "Log: first {}, consumed up to {}, next {}",
+            status.get_first(),
+            status.get_consumed(),
+            status.get_next()
+        );
+    }
+    let mut cursor = from.unwrap_or(status.get_consumed());
+    // Step 2: read the records one at a time
+    let mut records: Vec<LogRecord> = Vec::new();
+    loop {
+        request(
+            channel_in_consumer,
+            channel_out_producer,
+            LogRequestAction::Read,
+            cursor,
+        )?;
+        let mut header: [u8; LogRecordMessage::get_header_size()] =
+            [0x00; LogRecordMessage::get_header_size()];
+        channel_read(&channel_in_consumer, &mut header[0..1]).unwrap();
+        if header[0] == LogReadCommand::EndOfLog as u8 {
+            break;
+        }
+        if header[0] != LogReadCommand::Record as u8 {
+            return Err(MessageError::from(header[0]));
+        }
+        channel_read(&channel_in_consumer, &mut header[1..]).unwrap();
+        let mut buffer = header.to_vec();
+        buffer.resize(header.len() + LogRecordMessage::data_len(&header) + 1, 0x00);
+        channel_read(&channel_in_consumer, &mut buffer[header.len()..]).unwrap();
+        let record = LogRecordMessage::from(&buffer)?;
+        if record.get_seq() > cursor {
+            eprintln!(
+                "Warning: records {}..{} were dropped before being read",
+                cursor,
+                record.get_seq()
+            );
+        }
+        records.push(LogRecord {
+            seq: record.get_seq(),
+            source: record.get_source(),
+            timestamp: record.get_timestamp(),
+            data: record.get_data().to_vec(),
+        });
+        cursor = record.get_seq() + 1;
+    }
+    for record in &records {
+        println!(
+            "{:>8}  {:>12}  {:>4}  {}",
+            record.seq,
+            record.timestamp,
+            record.source,
+            format_data(&record.data)
+        );
+    }
+    println!("{} records", records.len());
+    // Step 3: save them, and only then acknowledge them
+    if let Some(output) = output {
+        let mut content = String::from("seq,timestamp,source,data\n");
+        for record in &records {
+            content.push_str(&format!(
+                "{},{},{},{}\n",
+                record.seq,
+                record.timestamp,
+                record.source,
+                to_hex(&record.data)
+            ));
+        }
+        // Nothing is acknowledged if this fails, the device gives up waiting
+        fs::write(&output, content).unwrap_or_else(|e| panic!("Cannot write '{}': {}", output, e));
+        println!("Records saved in: {}", output);
+    }
+    if ack && !records.is_empty() {
+        request(
+            channel_in_consumer,
+            channel_out_producer,
+            LogRequestAction::Ack,
+            cursor,
+        )?;
+        expect_success(channel_in_consumer)?;
+        println!("Records before {} marked as consumed", cursor);
+    }
+    finish(channel_in_consumer, channel_out_producer)
+}
+
+/// Waits for the device to ask for the next request, then sends it
+fn request(
+    channel_in_consumer: &Receiver<u8>,
+    channel_out_producer: &Sender<Vec<u8>>,
+    action: LogRequestAction,
+    value: u32,
+) -> Result<(), MessageError> {
+    let mut buff: [u8; 1] = [0x00; 1];
+    channel_read(&channel_in_consumer, &mut buff).unwrap();
+    if buff[0] != LogReadCommand::SendRequest as u8 {
+        return Err(MessageError::from(buff[0]));
+    }
+    let request = LogRequestMessage::new(action, value);
+    channel_write(&channel_out_producer, &request.get_raw()).unwrap();
+    return Ok(());
+}
+
+fn expect_success(channel_in_consumer: &Receiver<u8>) -> Result<(), MessageError> {
+    let mut buff: [u8; 1] = [0x00; 1];
+    channel_read(&channel_in_consumer, &mut buff).unwrap();
+    if buff[0] != LogReadResponse::Success as u8 {
+        return Err(MessageError::from(buff[0]));
+    }
+    return Ok(());
+}
+
+/// Ends the operation
+fn finish(
+    channel_in_consumer: &Receiver<u8>,
+    channel_out_producer: &Sender<Vec<u8>>,
+) -> Result<(), MessageError> {
+    request(
+        channel_in_consumer,
+        channel_out_producer,
+        LogRequestAction::Done,
+        0,
+    )?;
+    expect_success(channel_in_consumer)
+}
+
+/// Text when the data is printable, hex otherwise
+fn format_data(data: &[u8]) -> String {
+    if !data.is_empty() && data.iter().all(|b| b.is_ascii_graphic() || *b == b' ') {
+        return format!("\"{}\"", String::from_utf8_lossy(data));
+    }
+    return to_hex(data);
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    return bytes.iter().map(|b| format!("{:02x}", b)).collect();
+}
@@ -4,50 +4,45 @@
 
 mod common_messages;
 mod crc;
+mod link;
 mod utils;
 
-cfg_if::cfg_if! {
-    // Check the features are in mutual exclusion
-    if #[cfg(all(feature = "uart", feature = "mqtt"))] {
-        compile_error!("Both features cannot be enabled at the same time!");
-    } else if #[cfg(feature = "uart")] {
-        mod debug;
-        mod gdb;
-        mod flash_system;
-    } else if #[cfg(not(feature = "mqtt"))] {
-        compile_error!("At least one feature must be enabled!");
-    }
-}
+mod transport;
+mod debug;
+mod gdb;
+mod flash_system;
 
+mod apply;
+mod benchmark;
+mod coredump;
+mod elf_store;
 mod flash_component;
+mod fleet;
 mod info;
+mod inspect_image;
+mod logs;
+mod make_delta;
+mod read_back;
 
 use std::{
     io::{self},
-    thread::{self, JoinHandle},
+    process,
+    str::FromStr,
+    thread,
 };
 
-cfg_if::cfg_if! {
-    if #[cfg(feature = "mqtt")] {
-        use std::{
-            time::Duration,
-            str::FromStr
-        };
-        use rumqttc::{Client, MqttOptions};
-    } else if #[cfg(feature = "uart")] {
-        use std::{
-            fs::File,
-            io::{BufReader, BufWriter, Read, Write},
-            os::unix::prelude::{FromRawFd, IntoRawFd, RawFd},
-        };
-    }
-}
-
-
 use clap::{Parser, Subcommand};
-use crossbeam_channel::{Receiver, Sender};
+use apply::apply;
+use benchmark::benchmark;
+use coredump::coredump;
 use flash_component::flash_component;
+use fleet::fleet;
 use info::info;
+use inspect_image::inspect_image;
+use logs::logs;
+use make_delta::make_delta;
+use read_back::{pull, verify};
+use transport::{serial_start, Transport};
 
 
 /**
@@ -63,20 +58,10 @@ pub struct Cli {
     #[clap(subcommand)]
     cmd: Commands,
 
-    #[cfg(feature = "mqtt")]
-    #[clap(short, long)]
-    #[clap(short = 'i')]
-    mqtt_ip: String,
-
-    #[cfg(feature = "mqtt")]
+    /// Link to the device: serial:<port>, mqtt://<host>:<port>/<root>, tcp://<host>:<port> or pty
     #[clap(short, long, value_parser)]
-    #[clap(short = 'p')]
-    mqtt_port: Option<String>,
-
-    #[cfg(feature = "mqtt")]
-    #[clap(short, long)]
     #[clap(short = 't')]
-    app_root_topic: String,
+    transport: Option<String>,
 
     #[clap(short, long, value_parser)]
     #[clap(short = 'v')]
@@ -85,19 +70,32 @@ pub struct Cli {
 
 #[derive(Subcommand)]
 enum Commands {
-    #[cfg(feature = "uart")]
     Debug {
         #[clap(short, long, value_parser)]
         #[clap(short = 'c')]
         app_config: String,
+        /// Query the device for the current placement of the components
+        #[clap(short, long)]
+        #[clap(short = 's')]
+        serial_port: Option<String>,
+        /// Serial port where the uart-channel sends the deferred logs
+        #[clap(short, long)]
+        #[clap(short = 'l')]
+        log_port: Option<String>,
     },
-    #[cfg(feature = "uart")]
     Gdb {
         #[clap(short, long, value_parser)]
         #[clap(short = 'g')]
         app_config: String,
+        /// Debug archive generated by the system builder (default: App_debug next to App.toml)
+        #[clap(short, long, value_parser)]
+        #[clap(short = 'a')]
+        debug_archive: Option<String>,
+        /// Query the device for the current placement of the components
+        #[clap(short, long)]
+        #[clap(short = 's')]
+        serial_port: Option<String>,
     },
-    #[cfg(feature = "uart")]
     FlashSystem {
         // Flash the whole image
         #[clap(short, long, value_parser)]
@@ -109,20 +107,190 @@ enum Commands {
     },
     /// Collect status on the system
     Info {
-        #[cfg(feature = "uart")]
+        /// Serial port of the device, same as --transport serial:<port>
         #[clap(short, long)]
         #[clap(short = 's')]
-        serial_port: String,
+        serial_port: Option<String>,
     },
     /// Updates/Insert a new component in the system
     FlashComponent {
-        #[cfg(feature = "uart")]
+        /// Serial port of the device, same as --transport serial:<port>
         #[clap(short, long)]
         #[clap(short = 's')]
-        serial_port: String,
+        serial_port: Option<String>,
         #[clap(short, long, value_parser)]
         #[clap(short = 'f')]
         cbf_file: String,
+        /// ELF of the component, saved to load its symbols when debugging
+        #[clap(short, long, value_parser)]
+        #[clap(short = 'e')]
+        elf_file: Option<String>,
+        /// Send the payload as a delta of the installed version (see make-delta)
+        #[clap(short, long, value_parser)]
+        #[clap(short = 'D')]
+        delta: Option<String>,
+        /// Continue an interrupted update of the same CBF, instead of starting over
+        #[clap(short, long)]
+        #[clap(short = 'r')]
+        resume: bool,
+        /// Fragments in flight before waiting for an acknowledgement
+        #[clap(short, long, value_parser)]
+        #[clap(short = 'w')]
+        window: Option<usize>,
+    },
+    /// Installs the components of a plan, in an order that keeps the dependencies valid
+    Apply {
+        /// The CBFs the device must have (TOML)
+        #[clap(value_parser)]
+        plan: String,
+        /// Serial port of the device, same as --transport serial:<port>
+        #[clap(short, long)]
+        #[clap(short = 's')]
+        serial_port: Option<String>,
+        /// Only show what would be installed, and in which order
+        #[clap(short, long)]
+        #[clap(short = 'n')]
+        #[clap(takes_value = false)]
+        dry_run: bool,
+        /// Fragments in flight before waiting for an acknowledgement
+        #[clap(short, long, value_parser)]
+        #[clap(short = 'w')]
+        window: Option<usize>,
+    },
+    /// Updates the devices of a manifest over MQTT, reporting the progress on a status topic
+    Fleet {
+        /// Devices, target components and MQTT settings (TOML)
+        #[clap(short, long, value_parser)]
+        #[clap(short = 'm')]
+        manifest: String,
+        /// Where to write the results (JSON)
+        #[clap(short, long, value_parser)]
+        #[clap(short = 'r')]
+        report: Option<String>,
+    },
+    /// Measures the effective throughput of the update link
+    Benchmark {
+        /// Serial port of the device, same as --transport serial:<port>
+        #[clap(short, long)]
+        #[clap(short = 's')]
+        serial_port: Option<String>,
+        /// Bytes to transfer
+        #[clap(short, long, value_parser)]
+        #[clap(short = 'n')]
+        size: Option<usize>,
+        /// Fragments in flight before waiting for an acknowledgement
+        #[clap(short, long, value_parser)]
+        #[clap(short = 'w')]
+        window: Option<usize>,
+        /// Other end of a loopback (i.e. a pty pair), answered by an emulated device
+        #[clap(short, long)]
+        #[clap(short = 'l')]
+        loopback_port: Option<String>,
+    },
+    /// Retrieves and decodes the core dump saved by the system
+    Coredump {
+        /// Serial port of the device, same as --transport serial:<port>
+        #[clap(short, long)]
+        #[clap(short = 's')]
+        serial_port: Option<String>,
+        /// App config, to resolve symbols from the last build
+        #[clap(short, long, value_parser)]
+        #[clap(short = 'c')]
+        app_config: Option<String>,
+        /// Where to save the raw dump
+        #[clap(short, long, value_parser)]
+        #[clap(short = 'o')]
+        output: Option<String>,
+        /// Erase the dump after reading it
+        #[clap(short, long)]
+        #[clap(short = 'e')]
+        #[clap(takes_value = false)]
+        erase: bool,
+    },
+    /// Saves the CBF of an installed component, for forensics or to flash it on other devices
+    Pull {
+        /// Serial port of the device, same as --transport serial:<port>
+        #[clap(short, long)]
+        #[clap(short = 's')]
+        serial_port: Option<String>,
+        /// ID of the component
+        #[clap(short, long, value_parser)]
+        #[clap(short = 'i')]
+        id: u16,
+        /// Where to save the CBF (default: component<id>_v<version>.cbf)
+        #[clap(short, long, value_parser)]
+        #[clap(short = 'o')]
+        output: Option<String>,
+        /// Save the image as it is stored in flash, without undoing the relocations
+        #[clap(short, long)]
+        #[clap(short = 'r')]
+        #[clap(takes_value = false)]
+        raw: bool,
+    },
+    /// Checks that the component installed on the device is exactly the one of a CBF
+    Verify {
+        /// Serial port of the device, same as --transport serial:<port>
+        #[clap(short, long)]
+        #[clap(short = 's')]
+        serial_port: Option<String>,
+        #[clap(short, long, value_parser)]
+        #[clap(short = 'f')]
+        cbf: String,
+        /// Only compare the SHA-256 computed by the device, instead of reading the whole image
+        #[clap(short, long)]
+        #[clap(short = 'H')]
+        #[clap(takes_value = false)]
+        hash: bool,
+    },
+    /// Pulls the records of the log store, and optionally marks them as consumed
+    Logs {
+        /// Serial port of the device, same as --transport serial:<port>
+        #[clap(short, long)]
+        #[clap(short = 's')]
+        serial_port: Option<String>,
+        /// Sequence number to start from (default: the first record not consumed)
+        #[clap(short, long, value_parser)]
+        #[clap(short = 'f')]
+        from: Option<u32>,
+        /// Where to save the records (CSV)
+        #[clap(short, long, value_parser)]
+        #[clap(short = 'o')]
+        output: Option<String>,
+        /// Mark the records read as consumed, after saving them
+        #[clap(short, long)]
+        #[clap(short = 'a')]
+        #[clap(takes_value = false)]
+        ack: bool,
+    },
+    /// Analyzes a flash dump (.bin or .ihex) offline, without a device
+    InspectImage {
+        #[clap(short, long, value_parser)]
+        #[clap(short = 'd')]
+        image_path: String,
+        /// Board the image was dumped from
+        #[clap(short, long, value_parser)]
+        #[clap(short = 'b')]
+        board: String,
+        /// Page used by the flash allocator for swap operations, to detect pending swaps
+        #[clap(short, long, value_parser)]
+        #[clap(short = 'w')]
+        swap_page: Option<u16>,
+        /// Where to generate the Flash/RAM reports (default: next to the image)
+        #[clap(short, long, value_parser)]
+        #[clap(short = 'o')]
+        output: Option<String>,
+    },
+    /// Generates the delta between two versions of a component, to update it with less data
+    MakeDelta {
+        /// CBF of the version installed on the devices
+        #[clap(value_parser)]
+        old_cbf: String,
+        /// CBF of the version to install
+        #[clap(value_parser)]
+        new_cbf: String,
+        /// Where to save the delta (default: the new CBF, with extension .cbfd)
+        #[clap(short, long, value_parser)]
+        output: Option<String>,
     },
 }
 
@@ -135,176 +303,169 @@ fn main() -> Result<(), io::Error> {
     let (channel_in_producer, channel_in_consumer) = crossbeam_channel::bounded::<u8>(100);
     let (channel_out_producer, channel_out_consumer) = crossbeam_channel::bounded::<Vec<u8>>(100);
 
-    cfg_if::cfg_if! {
-        if #[cfg(feature = "uart")] {
-            let port = match args.cmd {
-                Commands::Info { ref serial_port} => Some(serial_port.clone()),
-                Commands::FlashComponent { ref serial_port, cbf_file:_ } => Some(serial_port.clone()),
-                _ => None
-            };
-            if let Some(serial_port) = port {
-                serial_start(
-                    serial_port,
-                    channel_in_producer,
-                    channel_out_consumer,
-                );
+    // Connect to the device (the serial port of the command is a shortcut)
+    let serial_port = match args.cmd {
+        Commands::Info { ref serial_port } => serial_port.clone(),
+        Commands::FlashComponent { ref serial_port, .. } => serial_port.clone(),
+        Commands::Apply { ref serial_port, .. } => serial_port.clone(),
+        Commands::Benchmark { ref serial_port, .. } => serial_port.clone(),
+        Commands::Debug { ref serial_port, .. } => serial_port.clone(),
+        Commands::Gdb { ref serial_port, .. } => serial_port.clone(),
+        Commands::Coredump { ref serial_port, .. } => serial_port.clone(),
+        Commands::Pull { ref serial_port, .. } => serial_port.clone(),
+        Commands::Verify { ref serial_port, .. } => serial_port.clone(),
+        Commands::Logs { ref serial_port, .. } => serial_port.clone(),
+        _ => None
+    };
+    let transport = match (args.transport, serial_port) {
+        (Some(transport), _) => Some(Transport::from_str(&transport).unwrap_or_else(|e| panic!("{}", e))),
+        (None, Some(serial_port)) => Some(Transport::Serial(serial_port)),
+        (None, None) => None,
+    };
+    let live = transport.is_some();
+    match transport {
+        Some(transport) => {
+            if verbose {
+                println!("Connecting through {}", transport);
             }
-        } else if #[cfg(feature = "mqtt")] {
-            mqtt_start(
-                args.mqtt_ip,
-                args.mqtt_port.unwrap_or(String::from("1883")),
-                args.app_root_topic,
-                channel_in_producer.clone(),
-                channel_out_consumer.clone(),
+            transport.start(channel_in_producer, channel_out_consumer);
+        }
+        None => {
+            let needs_device = matches!(
+                args.cmd,
+                Commands::Info { .. } | Commands::FlashComponent { .. } | Commands::Apply { .. } | Commands::Benchmark { .. } | Commands::Coredump { .. } | Commands::Pull { .. } | Commands::Verify { .. } | Commands::Logs { .. }
             );
+            if needs_device {
+                panic!("No device to talk to, use --transport (or --serial-port)");
+            }
         }
     }
 
     // Execute command
     match args.cmd {
-        Commands::Info {#[cfg(feature = "uart")] serial_port: _} => info(channel_in_consumer, channel_out_producer, verbose),
-        Commands::FlashComponent { #[cfg(feature = "uart")] serial_port: _, cbf_file } => {
-            flash_component(channel_in_consumer, channel_out_producer, cbf_file, verbose)
+        Commands::Info {serial_port: _} => info(channel_in_consumer, channel_out_producer, verbose),
+        Commands::FlashComponent { serial_port: _, cbf_file, elf_file, delta, resume, window } => {
+            flash_component(channel_in_consumer, channel_out_producer, cbf_file, elf_file, delta, resume, window.unwrap_or(DEFAULT_LINK_WINDOW), verbose)
         }
-        #[cfg(feature = "uart")]
-        Commands::FlashSystem {
-            app_config,
-            image_path,
-        } => flash_system::flash_system(app_config, image_path, verbose),
-        #[cfg(feature = "uart")]
-        Commands::Debug { app_config } => debug::debug(app_config, verbose),
-        #[cfg(feature = "uart")]
-        Commands::Gdb { app_config } => gdb::gdb(app_config, verbose),
-    }
-
-    Ok(())
-}
-
-#[cfg(feature = "uart")]
-pub const SERIAL_BAUDRATE: u32 = 115_200;
-#[cfg(feature = "uart")]
-fn serial_start(
-    serial_port: String,
-    channel_in_producer: Sender<u8>,
-    channel_out_consumer: Receiver<Vec<u8>>,
-) -> (JoinHandle<()>, JoinHandle<()>) {
-    let serial_port = serialport::new(&serial_port.clone(), SERIAL_BAUDRATE)
-        .open_native()
-        .expect("Cannot open serial port");
-    // There is no method for waiting data
-    let raw_channel: RawFd = serial_port.into_raw_fd();
-    let in_raw_file = unsafe { File::from_raw_fd(raw_channel) };
-    let out_raw_file = in_raw_file.try_clone().unwrap();
-
-    let mut in_stream = BufReader::new(in_raw_file);
-    let mut out_stream = BufWriter::new(out_raw_file);
-
-    let send_handler = thread::spawn(move || {
-        loop {
-            // Wait for data
-            let data_res = channel_out_consumer.recv();
-            if data_res.is_err() {
-                return; // Exit thread
+        Commands::Apply { plan, serial_port: _, dry_run, window } => {
+            if !apply(channel_in_consumer, channel_out_producer, plan, dry_run, window.unwrap_or(DEFAULT_LINK_WINDOW), verbose) {
+                process::exit(1);
             }
-            // Write data
-            let data = data_res.unwrap();
-            out_stream.write_all(&data).unwrap();
-            out_stream.flush().unwrap();
         }
-    });
-    let receive_handle = thread::spawn(move || {
-        let mut data: [u8; 100] = [0x00; 100];
-        loop {
-            // Wait for data on the file
-            let read = in_stream.read(&mut data);
-            if read.is_err() {
-                return; // Exit thread
+        Commands::Fleet { manifest, report } => {
+            if !fleet(manifest, report, DEFAULT_LINK_WINDOW, verbose) {
+                process::exit(1);
             }
-            let to_read = read.unwrap();
-            // Copy all this data on the channel
-            for b in &data[0..to_read] {
-                channel_in_producer.send(*b).unwrap();
+        }
+        Commands::Benchmark {
+            serial_port: _,
+            size,
+            window,
+            loopback_port,
+        } => {
+            if let Some(loopback_port) = loopback_port {
+                let (device_in_producer, device_in_consumer) = crossbeam_channel::bounded::<u8>(100);
+                let (device_out_producer, device_out_consumer) = crossbeam_channel::bounded::<Vec<u8>>(100);
+                serial_start(loopback_port, device_in_producer, device_out_consumer);
+                thread::spawn(move || benchmark::emulate_device(device_in_consumer, device_out_producer));
             }
+            benchmark(
+                channel_in_consumer,
+                channel_out_producer,
+                size.unwrap_or(DEFAULT_BENCHMARK_SIZE),
+                window.unwrap_or(DEFAULT_LINK_WINDOW),
+                verbose,
+            )
         }
-    });
-    return (send_handler, receive_handle);
-}
-
-#[cfg(feature = "mqtt")]
-const UPDATE_COMPONENT_ID: u16 = 5;
-#[cfg(feature = "mqtt")]
-/// Connect to MQTT server
-fn mqtt_start(
-    server_ip: String,
-    server_port: String,
-    mqtt_root: String,
-    channel_in_producer: Sender<u8>,
-    channel_out_consumer: Receiver<Vec<u8>>,
-) -> (JoinHandle<()>, JoinHandle<()>) {
-    let mut mqtt_options = MqttOptions::new(
-        "update-tool",
-        server_ip,
-        u16::from_str(server_port.as_str()).unwrap(),
-    );
-    mqtt_options.set_keep_alive(Duration::from_secs(30));
-    // Create client and connect
-    let (mut client, mut connection) = Client::new(mqtt_options, 10);
-    // Now, first subscribe
-    client
-        .subscribe(
-            format!("{}/{}/out", mqtt_root, UPDATE_COMPONENT_ID),
-            rumqttc::QoS::ExactlyOnce,
-        )
-        .unwrap();
-    // Then create a new thread for sending data
-    let send_handle = thread::spawn(move || {
-        loop {
-            // Read new data
-            let result = channel_out_consumer.recv();
-            if result.is_err() {
-                //println!("Exiting MQTT sender");
-                return; // Exit the current thread
+        Commands::Coredump {
+            serial_port: _,
+            app_config,
+            output,
+            erase,
+        } => coredump(
+            channel_in_consumer,
+            channel_out_producer,
+            app_config,
+            output,
+            erase,
+            verbose,
+        ),
+        Commands::Pull {
+            serial_port: _,
+            id,
+            output,
+            raw,
+        } => {
+            if !pull(channel_in_consumer, channel_out_producer, id, output, raw, verbose) {
+                process::exit(1);
             }
-            // Send data
-            let data = result.unwrap();
-            client
-                .publish(
-                    format!("{}/{}/in", mqtt_root, UPDATE_COMPONENT_ID),
-                    rumqttc::QoS::ExactlyOnce,
-                    false,
-                    data,
-                )
-                .unwrap();
         }
-    });
-    // Finally, create another thread to receive data/send packets
-    let receive_handle = thread::spawn(move || {
-        for (_, notification) in connection.iter().enumerate() {
-            if notification.is_err() {
-                panic!("MQTT Connection failed!");
+        Commands::Verify {
+            serial_port: _,
+            cbf,
+            hash,
+        } => {
+            if !verify(channel_in_consumer, channel_out_producer, cbf, hash, verbose) {
+                process::exit(1);
             }
-            let event = notification.unwrap();
-            if let rumqttc::Event::Incoming(rumqttc::Packet::Publish(data)) = event {
-                // Add data to queue
-                for b in data.payload {
-                    if channel_in_producer.send(b).is_err() {
-                        //println!("Exiting MQTT receiver");
-                        return; // Exit thread
-                    }
-                }
+        }
+        Commands::Logs {
+            serial_port: _,
+            from,
+            output,
+            ack,
+        } => {
+            if !logs(channel_in_consumer, channel_out_producer, from, output, ack, verbose) {
+                process::exit(1);
             }
         }
-    });
-    return (send_handle, receive_handle);
+        Commands::InspectImage {
+            image_path,
+            board,
+            swap_page,
+            output,
+        } => inspect_image(image_path, board, swap_page, output, verbose),
+        Commands::MakeDelta {
+            old_cbf,
+            new_cbf,
+            output,
+        } => make_delta(old_cbf, new_cbf, output, verbose),
+        Commands::FlashSystem {
+            app_config,
+            image_path,
+        } => flash_system::flash_system(app_config, image_path, verbose),
+        Commands::Debug {
+            app_config,
+            serial_port: _,
+            log_port,
+        } => {
+            let live = live.then(|| (&channel_in_consumer, &channel_out_producer));
+            debug::debug(app_config, live, log_port, verbose)
+        }
+        Commands::Gdb {
+            app_config,
+            debug_archive,
+            serial_port: _,
+        } => {
+            let live = live.then(|| (&channel_in_consumer, &channel_out_producer));
+            gdb::gdb(app_config, debug_archive, live, verbose)
+        }
+    }
+
+    Ok(())
 }
 
+/// Same as the window of the update component
+const DEFAULT_LINK_WINDOW: usize = 4;
+const DEFAULT_BENCHMARK_SIZE: usize = 64 * 1024;
+
 #[cfg(test)]
 mod test {
     use std::path::PathBuf;
 
     use crossbeam_channel::{Receiver, Sender};
 
-    use crate::{flash_component::flash_component, info::info, serial_start};
+    use crate::{flash_component::flash_component, info::info, transport::serial_start};
 
     fn get_test_file_path(name: &str) -> String {
         let mut d = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
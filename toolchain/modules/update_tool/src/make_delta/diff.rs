@@ -0,0 +1,154 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use std::collections::HashMap;
+
+use delta::{OP_ADD, OP_COPY, OP_SKIP};
+
+/// Bytes used to look for matches in the source
+const BLOCK_SIZE: usize = 8;
+/// Shorter matches cost more than the bytes they save (a copy and a skip take 10 bytes)
+const MIN_COPY_SIZE: usize = 16;
+/// Positions of the source tried for each match
+const MAX_CANDIDATES: usize = 64;
+
+/// Generates the patch that turns source into target. The source is used only
+/// forward (a byte of the source is never used twice), so that the device can
+/// undo the relocations of the installed version while applying the patch.
+pub fn diff(source: &[u8], target: &[u8]) -> Vec<u8> {
+    // Index the blocks of the source (positions are in increasing order)
+    let mut index: HashMap<&[u8], Vec<usize>> = HashMap::new();
+    if source.len() >= BLOCK_SIZE {
+        for pos in 0..=source.len() - BLOCK_SIZE {
+            index.entry(&source[pos..pos + BLOCK_SIZE]).or_default().push(pos);
+        }
+    }
+    let mut patch: Vec<u8> = Vec::new();
+    let mut literals: Vec<u8> = Vec::new();
+    let mut source_pos: usize = 0;
+    let mut target_pos: usize = 0;
+    while target_pos < target.len() {
+        let (match_pos, match_len) = find_match(&index, source, source_pos, &target[target_pos..]);
+        if match_len < MIN_COPY_SIZE {
+            literals.push(target[target_pos]);
+            target_pos += 1;
+            continue;
+        }
+        push_literals(&mut patch, &mut literals);
+        if match_pos > source_pos {
+            push_op(&mut patch, OP_SKIP, match_pos - source_pos);
+        }
+        push_op(&mut patch, OP_COPY, match_len);
+        source_pos = match_pos + match_len;
+        target_pos += match_len;
+    }
+    push_literals(&mut patch, &mut literals);
+    patch
+}
+
+/// Returns the longest match of target in the source, from source_pos on.
+/// The closest one wins among matches of the same length.
+fn find_match(
+    index: &HashMap<&[u8], Vec<usize>>,
+    source: &[u8],
+    source_pos: usize,
+    target: &[u8],
+) -> (usize, usize) {
+    if target.len() < BLOCK_SIZE {
+        return (0, 0);
+    }
+    let candidates = match index.get(&target[0..BLOCK_SIZE]) {
+        Some(candidates) => candidates,
+        None => return (0, 0),
+    };
+    let first = candidates.partition_point(|pos| *pos < source_pos);
+    let mut best: (usize, usize) = (0, 0);
+    for pos in candidates[first..].iter().take(MAX_CANDIDATES) {
+        let len = source[*pos..]
+            .iter()
+            .zip(target)
+            .take_while(|(s, t)| s == t)
+            .count();
+        if len > best.1 {
+            best = (*pos, len);
+        }
+    }
+    best
+}
+
+fn push_op(patch: &mut Vec<u8>, op: u8, len: usize) {
+    patch.push(op);
+    patch.extend_from_slice(&(len as u32).to_le_bytes());
+}
+
+fn push_literals(patch: &mut Vec<u8>, literals: &mut Vec<u8>) {
+    if literals.is_empty() {
+        return;
+    }
+    push_op(patch, OP_ADD, literals.len());
+    patch.append(literals);
+}
+
+/*
+    Tests
+*/
+#[cfg(test)]
+mod test {
+    use super::*;
+    use delta::{PatchError, Patcher};
+
+    fn apply(source: &[u8], patch: &[u8], output_size: usize) -> Vec<u8> {
+        let mut patcher = Patcher::new(output_size);
+        let mut source_pos: usize = 0;
+        let mut output: Vec<u8> = Vec::new();
+        for chunk in patch.chunks(64) {
+            patcher
+                .sink(
+                    chunk,
+                    || -> Result<u8, PatchError> {
+                        source_pos += 1;
+                        Ok(source[source_pos - 1])
+                    },
+                    |b| {
+                        output.push(b);
+                        Ok(())
+                    },
+                )
+                .unwrap();
+        }
+        assert!(patcher.is_complete());
+        output
+    }
+
+    fn pseudo_random(size: usize, mut seed: u32) -> Vec<u8> {
+        (0..size)
+            .map(|_| {
+                seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12345);
+                (seed >> 16) as u8
+            })
+            .collect()
+    }
+
+    #[test]
+    fn small_change() {
+        let source = pseudo_random(16 * 1024, 1);
+        let mut target = source.clone();
+        // Change a function in the middle, and grow another one
+        target[5000..5040].copy_from_slice(&pseudo_random(40, 2));
+        target.splice(9000..9000, pseudo_random(300, 3));
+        target.drain(12000..12100);
+        let patch = diff(&source, &target);
+        assert_eq!(apply(&source, &patch, target.len()), target);
+        assert!(patch.len() < 500);
+    }
+
+    #[test]
+    fn unrelated_images() {
+        let source = pseudo_random(2048, 4);
+        let target = pseudo_random(3000, 5);
+        let patch = diff(&source, &target);
+        assert_eq!(apply(&source, &patch, target.len()), target);
+        assert_eq!(apply(&[], &diff(&[], &target), target.len()), target);
+    }
+}
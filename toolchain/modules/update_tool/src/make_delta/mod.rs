@@ -0,0 +1,149 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+mod diff;
+
+use cbf_rs::CbfFile;
+use delta::SourceHash;
+use std::path::PathBuf;
+
+use self::diff::diff;
+use crate::flash_component::{expand_cbf, extract_payload};
+use crate::utils::*;
+
+const DELTA_MAGIC: [u8; 4] = *b"CBFD";
+const DELTA_HEADER_SIZE: usize = 24;
+
+/// A version of a component, identified by the hash of its payload
+/// (before the relocation)
+#[derive(Clone, Copy, PartialEq)]
+pub struct DeltaImage {
+    pub component_id: u16,
+    pub component_version: u32,
+    pub payload_hash: u32,
+}
+
+/// The patch that turns the payload of source into the one of target
+pub struct DeltaFile {
+    pub source: DeltaImage,
+    pub target: DeltaImage,
+    pub patch: Vec<u8>,
+}
+
+impl DeltaImage {
+    fn from(cbf: &dyn CbfFile) -> Self {
+        let mut hash = SourceHash::new();
+        hash.update(&extract_payload(cbf));
+        Self {
+            component_id: cbf.header_base().component_id(),
+            component_version: cbf.header_base().component_version(),
+            payload_hash: hash.finish(),
+        }
+    }
+    fn write_to(&self, buffer: &mut Vec<u8>) {
+        buffer.extend_from_slice(&self.component_id.to_le_bytes());
+        buffer.extend_from_slice(&self.component_version.to_le_bytes());
+        buffer.extend_from_slice(&self.payload_hash.to_le_bytes());
+    }
+    fn read_from(buffer: &[u8]) -> Self {
+        Self {
+            component_id: u16_from_le_bytes(&buffer[0..2]),
+            component_version: u32_from_le_bytes(&buffer[2..6]),
+            payload_hash: u32_from_le_bytes(&buffer[6..10]),
+        }
+    }
+}
+
+impl DeltaFile {
+    pub fn load(delta_file: &str) -> Self {
+        let bytes =
+            std::fs::read(delta_file).expect(&format!("Cannot open the delta at '{}'", delta_file));
+        if bytes.len() < DELTA_HEADER_SIZE || bytes[0..4] != DELTA_MAGIC {
+            panic!("Delta file not valid!");
+        }
+        Self {
+            source: DeltaImage::read_from(&bytes[4..14]),
+            target: DeltaImage::read_from(&bytes[14..24]),
+            patch: bytes[DELTA_HEADER_SIZE..].to_vec(),
+        }
+    }
+    fn save(&self, delta_file: &PathBuf) {
+        let mut bytes = DELTA_MAGIC.to_vec();
+        self.source.write_to(&mut bytes);
+        self.target.write_to(&mut bytes);
+        bytes.extend_from_slice(&self.patch);
+        std::fs::write(delta_file, bytes).expect(&format!(
+            "Cannot write the delta at '{}'",
+            delta_file.display()
+        ));
+    }
+    /// True if the delta generates the payload of this CBF
+    pub fn targets(&self, cbf: &dyn CbfFile) -> bool {
+        self.target == DeltaImage::from(cbf)
+    }
+}
+
+/// Generates the delta between two versions of a component. The patch is computed
+/// on the payloads before the relocation, the headers are always sent as they are.
+pub fn make_delta(old_cbf: String, new_cbf: String, output: Option<String>, verbose: bool) {
+    let (old_bytes, _) = expand_cbf(read_cbf(&old_cbf));
+    let (new_bytes, _) = expand_cbf(read_cbf(&new_cbf));
+    let old = parse_cbf(&old_bytes, &old_cbf);
+    let new = parse_cbf(&new_bytes, &new_cbf);
+    // Same component, going forward
+    let source = DeltaImage::from(&old);
+    let target = DeltaImage::from(&new);
+    if source.component_id != target.component_id {
+        panic!("The two CBFs are different components");
+    }
+    if source.component_version >= target.component_version {
+        panic!("The new CBF must have a greater version than the old one");
+    }
+    // Generate the patch
+    let old_payload = extract_payload(&old);
+    let new_payload = extract_payload(&new);
+    let delta = DeltaFile {
+        source: source,
+        target: target,
+        patch: diff(&old_payload, &new_payload),
+    };
+    if verbose {
+        println!(
+            "Component {}: version {} -> {}",
+            target.component_id, source.component_version, target.component_version
+        );
+        println!(
+            "Payload: {} -> {} bytes",
+            old_payload.len(),
+            new_payload.len()
+        );
+    }
+    // Save it next to the new CBF, unless told otherwise
+    let delta_path = match output {
+        Some(output) => PathBuf::from(output),
+        None => PathBuf::from(&new_cbf).with_extension("cbfd"),
+    };
+    delta.save(&delta_path);
+    println!(
+        "Delta saved at '{}': {} bytes ({:.1}% of the payload)",
+        delta_path.display(),
+        delta.patch.len(),
+        100.0 * delta.patch.len() as f64 / new_payload.len() as f64
+    );
+}
+
+fn read_cbf(cbf_file: &str) -> Vec<u8> {
+    std::fs::read(cbf_file).expect(&format!("Cannot open the CBF at '{}'", cbf_file))
+}
+
+fn parse_cbf<'a>(cbf_bytes: &'a [u8], cbf_file: &str) -> impl CbfFile + 'a {
+    let cbf = match cbf_rs::parse_cbf(cbf_bytes) {
+        Ok(cbf) => cbf,
+        Err(_) => panic!("CBF file '{}' not valid!", cbf_file),
+    };
+    if !cbf.validate() {
+        panic!("CBF file '{}' integrity test failed!", cbf_file);
+    }
+    cbf
+}
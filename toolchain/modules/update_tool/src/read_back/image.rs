@@ -0,0 +1,259 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use std::fmt;
+
+use cbf_rs::{parse_cbf, CbfFile, FIXED_HEADER_SIZE};
+use relocator::{Relocator, RelocatorMethods};
+use sha2::{Digest, Sha256};
+
+use crate::flash_component::xor_checksum;
+
+const LINKED_FLASH_BASE: u32 = 0x0800_0000;
+const LINKED_SRAM_BASE: u32 = 0x2000_0000;
+/// Same buffers of the update component
+const BUFF_SIZE: usize = 128;
+const RELOC_BUFF_SIZE: usize = 16;
+
+type ImageRelocator = Relocator<LINKED_FLASH_BASE, LINKED_SRAM_BASE, BUFF_SIZE, RELOC_BUFF_SIZE>;
+
+#[derive(Debug)]
+pub enum ImageError {
+    InvalidCbf(cbf_rs::Error),
+    Relocation,
+}
+
+impl fmt::Display for ImageError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ImageError::InvalidCbf(e) => write!(f, "the image is not a valid CBF ({:?})", e),
+            ImageError::Relocation => write!(f, "cannot undo the relocations of the image"),
+        }
+    }
+}
+
+/// Relocations of the image, the output is the image itself
+struct ImageRelocations {
+    relocations: Vec<u32>,
+}
+
+impl RelocatorMethods<Vec<u8>> for ImageRelocations {
+    fn read_relocations(
+        &self,
+        start_index: usize,
+        dst: &mut [u32],
+        _image: &mut Vec<u8>,
+    ) -> Result<usize, ()> {
+        let src = self
+            .relocations
+            .get(start_index..start_index + dst.len())
+            .ok_or(())?;
+        dst.copy_from_slice(src);
+        return Ok(dst.len());
+    }
+    fn flush(&mut self, position: usize, src: &[u8], image: &mut Vec<u8>) -> Result<(), ()> {
+        image
+            .get_mut(position..position + src.len())
+            .ok_or(())?
+            .copy_from_slice(src);
+        return Ok(());
+    }
+}
+
+/// Brings an image installed at the given bases back to the linked addresses,
+/// fixing its checksum: the result is the CBF that was flashed.
+pub fn restore(
+    installed: &[u8],
+    block_base_address: u32,
+    sram_base_address: u32,
+) -> Result<Vec<u8>, ImageError> {
+    return relocate(installed, block_base_address, sram_base_address, true);
+}
+
+/// Same relocation of the update component (or its reverse) on the whole payload
+fn relocate(
+    image: &[u8],
+    block_base_address: u32,
+    sram_base_address: u32,
+    reverse: bool,
+) -> Result<Vec<u8>, ImageError> {
+    let cbf = parse_cbf(image).map_err(ImageError::InvalidCbf)?;
+    let payload_offset = cbf.read_only_section().offset();
+    let payload_end = (payload_offset + cbf.payload_size()) as usize;
+    let mut methods = ImageRelocations {
+        relocations: cbf.relocation_iter().map(|r| r.value()).collect(),
+    };
+    // The block starts with the header of the flash allocator
+    let flash_base = block_base_address + 8 + payload_offset;
+    let mut relocator = if reverse {
+        ImageRelocator::reverse(
+            flash_base,
+            sram_base_address,
+            payload_offset as usize,
+            methods.relocations.len(),
+        )
+    } else {
+        ImageRelocator::new(
+            flash_base,
+            sram_base_address,
+            payload_offset as usize,
+            methods.relocations.len(),
+        )
+    };
+    let mut output = image.to_vec();
+    relocator
+        .consume_current_buffer(
+            &image[payload_offset as usize..payload_end],
+            &mut methods,
+            &mut output,
+        )
+        .map_err(|_| ImageError::Relocation)?;
+    relocator
+        .finish(&mut methods, &mut output)
+        .map_err(|_| ImageError::Relocation)?;
+    // The checksum is computed with its own field zeroed
+    let checksum_offset = cbf.checksum_offset() as usize;
+    output[checksum_offset..checksum_offset + 4].fill(0x00);
+    let checksum = xor_checksum(&output);
+    output[checksum_offset..checksum_offset + 4].copy_from_slice(&checksum.to_le_bytes());
+    return Ok(output);
+}
+
+/// Hash of the CBF up to the trailer, as computed by the device
+pub fn image_hash(cbf: &dyn CbfFile) -> [u8; 32] {
+    let trailer = cbf.header_base().offset_trailer() as usize;
+    return Sha256::digest(&cbf.content()[..trailer]).into();
+}
+
+/// Bytes that differ between two images
+#[derive(Debug, PartialEq)]
+pub struct Mismatch {
+    pub start: usize,
+    pub end: usize,
+    /// Part of the CBF they fall in
+    pub section: &'static str,
+}
+
+/// Compares an image with the expected CBF
+pub fn compare(expected: &dyn CbfFile, image: &[u8]) -> Vec<Mismatch> {
+    let content = expected.content();
+    let payload_offset = expected.read_only_section().offset() as usize;
+    let trailer = expected.header_base().offset_trailer() as usize;
+    let section = |pos: usize| -> &'static str {
+        if pos < FIXED_HEADER_SIZE {
+            "fixed header"
+        } else if pos < payload_offset {
+            "variable header"
+        } else if pos < trailer {
+            "payload"
+        } else {
+            "trailer"
+        }
+    };
+    let mut mismatches: Vec<Mismatch> = Vec::new();
+    for pos in 0..std::cmp::max(content.len(), image.len()) {
+        if content.get(pos) == image.get(pos) {
+            continue;
+        }
+        match mismatches.last_mut() {
+            Some(last) if last.end == pos && last.section == section(pos) => last.end += 1,
+            _ => mismatches.push(Mismatch {
+                start: pos,
+                end: pos + 1,
+                section: section(pos),
+            }),
+        }
+    }
+    return mismatches;
+}
+
+#[cfg(test)]
+mod test {
+    use std::{fs, path::PathBuf};
+
+    use cbf_rs::{parse_cbf, CbfFile};
+
+    use super::*;
+
+    const BLOCK_BASE: u32 = 0x0800_8000;
+    const SRAM_BASE: u32 = 0x2000_1000;
+
+    fn example_cbf(name: &str) -> Vec<u8> {
+        let mut path = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        path.push("../elf2cbf/examples");
+        path.push(name);
+        path.push("output/component.cbf");
+        return fs::read(path).unwrap();
+    }
+
+    #[test]
+    fn test_restore() {
+        for name in ["component1", "component2", "component3", "component4"] {
+            let original = example_cbf(name);
+            // As the update component stores it
+            let installed = relocate(&original, BLOCK_BASE, SRAM_BASE, false).unwrap();
+            assert!(parse_cbf(&installed).unwrap().validate());
+            let cbf = parse_cbf(&original).unwrap();
+            if cbf.header_base().num_relocations() > 0 {
+                assert_ne!(installed, original);
+            }
+            assert_eq!(
+                restore(&installed, BLOCK_BASE, SRAM_BASE).unwrap(),
+                original
+            );
+        }
+    }
+
+    #[test]
+    fn test_hash() {
+        let original = example_cbf("component1");
+        let installed = relocate(&original, BLOCK_BASE, SRAM_BASE, false).unwrap();
+        let restored = restore(&installed, BLOCK_BASE, SRAM_BASE).unwrap();
+        assert_eq!(
+            image_hash(&parse_cbf(&restored).unwrap()),
+            image_hash(&parse_cbf(&original).unwrap())
+        );
+        // Computed up to the trailer
+        let mut other = original.clone();
+        let last = other.len() - 1;
+        other[last] ^= 0xFF;
+        assert_eq!(
+            image_hash(&parse_cbf(&other).unwrap()),
+            image_hash(&parse_cbf(&original).unwrap())
+        );
+    }
+
+    #[test]
+    fn test_compare() {
+        let original = example_cbf("component1");
+        let cbf = parse_cbf(&original).unwrap();
+        assert!(compare(&cbf, &original).is_empty());
+        let payload = cbf.read_only_section().offset() as usize;
+        let mut image = original.clone();
+        image[2] ^= 0xFF;
+        image[payload + 4] ^= 0xFF;
+        image[payload + 5] ^= 0xFF;
+        image.push(0x00);
+        assert_eq!(
+            compare(&cbf, &image),
+            vec![
+                Mismatch {
+                    start: 2,
+                    end: 3,
+                    section: "fixed header"
+                },
+                Mismatch {
+                    start: payload + 4,
+                    end: payload + 6,
+                    section: "payload"
+                },
+                Mismatch {
+                    start: original.len(),
+                    end: original.len() + 1,
+                    section: "trailer"
+                },
+            ]
+        );
+    }
+}
@@ -0,0 +1,118 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use crate::{
+    common_messages::{MessageError, SerializableMessage},
+    crc::crc8_update,
+    utils::u32_from_le_bytes,
+};
+
+#[repr(u8)]
+pub enum ComponentReadCommand {
+    SendReadRequest = 0x01,
+    ImageInfo = 0x02,
+    ImageHash = 0x03,
+}
+
+#[derive(Clone, Copy)]
+#[repr(u8)]
+pub enum ComponentReadMode {
+    /// The CBF as it is stored in flash
+    Image = 0x00,
+    /// The SHA-256 of the CBF before the relocation, up to the trailer
+    Hash = 0x01,
+}
+
+/// Component to read, and how
+pub struct ComponentReadRequestMessage {
+    component_id: u16,
+    mode: ComponentReadMode,
+}
+
+impl ComponentReadRequestMessage {
+    pub fn new(component_id: u16, mode: ComponentReadMode) -> Self {
+        Self {
+            component_id: component_id,
+            mode: mode,
+        }
+    }
+}
+
+impl<'a> SerializableMessage<'a> for ComponentReadRequestMessage {
+    fn get_raw(&self) -> Vec<u8> {
+        let mut buffer = Vec::<u8>::new();
+        buffer.extend_from_slice(&self.component_id.to_le_bytes());
+        buffer.push(self.mode as u8);
+        // Compute and append crc
+        let mut crc: u8 = 0x00;
+        for i in 0..buffer.len() {
+            crc8_update(&mut crc, buffer[i]);
+        }
+        buffer.push(crc);
+        buffer
+    }
+}
+
+/// Size of the CBF that is going to be sent, and the bases it was relocated at
+pub struct ImageInfoMessage<'a> {
+    buffer: &'a [u8],
+}
+
+impl<'a> ImageInfoMessage<'a> {
+    pub fn from(buffer: &'a [u8]) -> Result<Self, MessageError> {
+        // Validate buffer
+        validate(buffer, Self::get_size())?;
+        // Return instance
+        Ok(Self { buffer: buffer })
+    }
+    pub const fn get_size() -> usize {
+        14
+    }
+    pub fn get_image_size(&self) -> u32 {
+        u32_from_le_bytes(&self.buffer[1..5])
+    }
+    pub fn get_block_base_address(&self) -> u32 {
+        u32_from_le_bytes(&self.buffer[5..9])
+    }
+    pub fn get_sram_base_address(&self) -> u32 {
+        u32_from_le_bytes(&self.buffer[9..13])
+    }
+}
+
+/// Hash of the component, in place of the image
+pub struct ImageHashMessage<'a> {
+    buffer: &'a [u8],
+}
+
+impl<'a> ImageHashMessage<'a> {
+    pub fn from(buffer: &'a [u8]) -> Result<Self, MessageError> {
+        // Validate buffer
+        validate(buffer, Self::get_size())?;
+        // Return instance
+        Ok(Self { buffer: buffer })
+    }
+    pub const fn get_size() -> usize {
+        34
+    }
+    pub fn get_hash(&self) -> [u8; 32] {
+        self.buffer[1..33].try_into().unwrap()
+    }
+}
+
+fn validate(buffer: &[u8], size: usize) -> Result<(), MessageError> {
+    // Check message size
+    if buffer.len() != size {
+        return Err(MessageError::InvalidSize);
+    }
+    // Check CRC
+    let mut crc = 0x00;
+    for i in 0..(buffer.len() - 1) {
+        crc8_update(&mut crc, buffer[i]);
+    }
+    if crc != buffer[buffer.len() - 1] {
+        return Err(MessageError::InvalidCRC);
+    }
+    // Return
+    Ok(())
+}
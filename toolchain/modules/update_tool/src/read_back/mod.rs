@@ -0,0 +1,295 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+mod image;
+mod messages;
+
+use std::fs;
+
+use cbf_rs::{parse_cbf, CbfFile};
+use crossbeam_channel::{Receiver, Sender};
+
+use self::image::{compare, image_hash, restore};
+use self::messages::*;
+use crate::common_messages::*;
+use crate::crc::crc8_update;
+use crate::flash_component::expand_cbf;
+use crate::utils::*;
+
+const PACKET_BUFFER_SIZE: usize = 64;
+/// Mismatches listed by verify, the others are only counted
+const MAX_MISMATCHES: usize = 10;
+
+/// CBF of a component, as it is stored on the device
+struct InstalledImage {
+    content: Vec<u8>,
+    block_base_address: u32,
+    sram_base_address: u32,
+}
+
+/// Saves the image of an installed component. Unless raw, the relocations are
+/// undone, so that it can be flashed on other devices.
+pub fn pull(
+    channel_in_consumer: Receiver<u8>,
+    channel_out_producer: Sender<Vec<u8>>,
+    component_id: u16,
+    output: Option<String>,
+    raw: bool,
+    verbose: bool,
+) -> bool {
+    let installed = match read_image(
+        &channel_in_consumer,
+        &channel_out_producer,
+        component_id,
+        verbose,
+    ) {
+        Ok(installed) => installed,
+        Err(e) => {
+            eprintln!("Cannot read component {}: {}", component_id, e);
+            return false;
+        }
+    };
+    if verbose {
+        println!(
+            "Read {} bytes [block: {:#010x}, sram: {:#010x}]",
+            installed.content.len(),
+            installed.block_base_address,
+            installed.sram_base_address
+        );
+    }
+    let image = if raw {
+        installed.content
+    } else {
+        match restore(
+            &installed.content,
+            installed.block_base_address,
+            installed.sram_base_address,
+        ) {
+            Ok(image) => image,
+            Err(e) => {
+                eprintln!("Component {}: {}", component_id, e);
+                return false;
+            }
+        }
+    };
+    let cbf = match parse_cbf(&image) {
+        Ok(cbf) => cbf,
+        Err(e) => {
+            eprintln!(
+                "Component {}: the image is not a valid CBF ({:?})",
+                component_id, e
+            );
+            return false;
+        }
+    };
+    let version = cbf.header_base().component_version();
+    if !cbf.validate() {
+        eprintln!("Warning: the checksum of the image is not valid");
+    }
+    let output = output.unwrap_or(format!("component{}_v{}.cbf", component_id, version));
+    if let Err(e) = fs::write(&output, &image) {
+        eprintln!("Cannot write '{}': {}", output, e);
+        return false;
+    }
+    println!(
+        "Component {} v{} saved in: {}{}",
+        component_id,
+        version,
+        output,
+        if raw { " (as stored in flash)" } else { "" }
+    );
+    return true;
+}
+
+/// Checks that the device has exactly the component of the CBF. With hash only
+/// the hash of the image is transferred, without telling where they differ.
+pub fn verify(
+    channel_in_consumer: Receiver<u8>,
+    channel_out_producer: Sender<Vec<u8>>,
+    cbf_file: String,
+    hash: bool,
+    verbose: bool,
+) -> bool {
+    let content =
+        fs::read(&cbf_file).unwrap_or_else(|e| panic!("Cannot read '{}': {}", cbf_file, e));
+    // The device stores the payload uncompressed
+    let (content, _) = expand_cbf(content);
+    let cbf = parse_cbf(&content).unwrap_or_else(|e| panic!("Invalid CBF '{}': {:?}", cbf_file, e));
+    let component_id = cbf.header_base().component_id();
+    let component_version = cbf.header_base().component_version();
+    println!(
+        "Verifying component {} v{}",
+        component_id, component_version
+    );
+
+    if hash {
+        let device_hash = match read_hash(
+            &channel_in_consumer,
+            &channel_out_producer,
+            component_id,
+            verbose,
+        ) {
+            Ok(device_hash) => device_hash,
+            Err(e) => {
+                eprintln!("Cannot read component {}: {}", component_id, e);
+                return false;
+            }
+        };
+        if verbose {
+            println!("Device hash: {}", to_hex(&device_hash));
+        }
+        if device_hash != image_hash(&cbf) {
+            eprintln!("Mismatch: the device has a different image");
+            return false;
+        }
+        println!("Success! The image on the device matches (SHA-256)");
+        return true;
+    }
+
+    let installed = match read_image(
+        &channel_in_consumer,
+        &channel_out_producer,
+        component_id,
+        verbose,
+    ) {
+        Ok(installed) => installed,
+        Err(e) => {
+            eprintln!("Cannot read component {}: {}", component_id, e);
+            return false;
+        }
+    };
+    let image = match restore(
+        &installed.content,
+        installed.block_base_address,
+        installed.sram_base_address,
+    ) {
+        Ok(image) => image,
+        Err(e) => {
+            eprintln!("Component {}: {}", component_id, e);
+            return false;
+        }
+    };
+    let mismatches = compare(&cbf, &image);
+    if mismatches.is_empty() {
+        println!("Success! The image on the device matches");
+        return true;
+    }
+    let total: usize = mismatches.iter().map(|m| m.end - m.start).sum();
+    eprintln!(
+        "Mismatch: {} bytes differ (image of {} bytes, expected {})",
+        total,
+        image.len(),
+        content.len()
+    );
+    for mismatch in mismatches.iter().take(MAX_MISMATCHES) {
+        eprintln!(
+            "\t{:#08x}..{:#08x}\t{}",
+            mismatch.start, mismatch.end, mismatch.section
+        );
+    }
+    if mismatches.len() > MAX_MISMATCHES {
+        eprintln!("\t... and {} more", mismatches.len() - MAX_MISMATCHES);
+    }
+    return false;
+}
+
+/// Starts the operation, up to the request of the component to read
+fn request_component(
+    channel_in_consumer: &Receiver<u8>,
+    channel_out_producer: &Sender<Vec<u8>>,
+    component_id: u16,
+    mode: ComponentReadMode,
+    verbose: bool,
+) -> Result<(), MessageError> {
+    // Send hello message
+    let hello_msg = HelloMessage::new(OperationType::ComponentRead);
+    channel_write(&channel_out_producer, &hello_msg.get_raw()).unwrap();
+    // Read hello response
+    let mut buff: [u8; HelloResponseMessage::get_size()] = [0x00; HelloResponseMessage::get_size()];
+    channel_read(&channel_in_consumer, &mut buff).unwrap();
+    // Validate hello response
+    HelloResponseMessage::from(&buff).expect("Wrong response from device at HELLO");
+    if verbose {
+        println!("Got HELLO!");
+    }
+    // Wait for the request
+    let mut buff: [u8; 1] = [0x00; 1];
+    channel_read(&channel_in_consumer, &mut buff).unwrap();
+    if buff[0] != ComponentReadCommand::SendReadRequest as u8 {
+        return Err(MessageError::from(buff[0]));
+    }
+    let request = ComponentReadRequestMessage::new(component_id, mode);
+    channel_write(&channel_out_producer, &request.get_raw()).unwrap();
+    return Ok(());
+}
+
+fn read_image(
+    channel_in_consumer: &Receiver<u8>,
+    channel_out_producer: &Sender<Vec<u8>>,
+    component_id: u16,
+    verbose: bool,
+) -> Result<InstalledImage, MessageError> {
+    request_component(
+        channel_in_consumer,
+        channel_out_producer,
+        component_id,
+        ComponentReadMode::Image,
+        verbose,
+    )?;
+    // Step 1: read the size and the placement
+    let mut buff: [u8; ImageInfoMessage::get_size()] = [0x00; ImageInfoMessage::get_size()];
+    channel_read(&channel_in_consumer, &mut buff[0..1]).unwrap();
+    if buff[0] != ComponentReadCommand::ImageInfo as u8 {
+        return Err(MessageError::from(buff[0]));
+    }
+    channel_read(&channel_in_consumer, &mut buff[1..]).unwrap();
+    let info = ImageInfoMessage::from(&buff)?;
+    let image_size = info.get_image_size() as usize;
+    // Step 2: read the packets
+    let mut content: Vec<u8> = Vec::with_capacity(image_size);
+    let mut packet: [u8; PACKET_BUFFER_SIZE] = [0x00; PACKET_BUFFER_SIZE];
+    while content.len() < image_size {
+        let len = core::cmp::min(PACKET_BUFFER_SIZE - 1, image_size - content.len());
+        channel_read(&channel_in_consumer, &mut packet[0..len + 1]).unwrap();
+        let mut crc: u8 = 0x00;
+        for i in 0..len {
+            crc8_update(&mut crc, packet[i]);
+        }
+        if crc != packet[len] {
+            return Err(MessageError::InvalidCRC);
+        }
+        content.extend_from_slice(&packet[0..len]);
+    }
+    return Ok(InstalledImage {
+        content: content,
+        block_base_address: info.get_block_base_address(),
+        sram_base_address: info.get_sram_base_address(),
+    });
+}
+
+fn read_hash(
+    channel_in_consumer: &Receiver<u8>,
+    channel_out_producer: &Sender<Vec<u8>>,
+    component_id: u16,
+    verbose: bool,
+) -> Result<[u8; 32], MessageError> {
+    request_component(
+        channel_in_consumer,
+        channel_out_producer,
+        component_id,
+        ComponentReadMode::Hash,
+        verbose,
+    )?;
+    let mut buff: [u8; ImageHashMessage::get_size()] = [0x00; ImageHashMessage::get_size()];
+    channel_read(&channel_in_consumer, &mut buff[0..1]).unwrap();
+    if buff[0] != ComponentReadCommand::ImageHash as u8 {
+        return Err(MessageError::from(buff[0]));
+    }
+    channel_read(&channel_in_consumer, &mut buff[1..]).unwrap();
+    return Ok(ImageHashMessage::from(&buff)?.get_hash());
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    return bytes.iter().map(|b| format!("{:02x}", b)).collect();
+}
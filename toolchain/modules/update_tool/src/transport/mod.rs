@@ -0,0 +1,250 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+mod mqtt;
+mod serial;
+mod tcp;
+
+use std::{
+    fmt,
+    io::{Read, Write},
+    str::FromStr,
+    thread::{self, JoinHandle},
+};
+
+use crossbeam_channel::{Receiver, Sender};
+
+use self::mqtt::mqtt_start;
+pub use self::serial::{pty_start, serial_start, SERIAL_BAUDRATE};
+use self::tcp::tcp_start;
+
+const DEFAULT_MQTT_PORT: u16 = 1883;
+
+/// Link to the update component. Every transport moves the bytes between the
+/// crossbeam channels and the device, with a thread for each direction.
+#[derive(Debug, PartialEq)]
+pub enum Transport {
+    /// serial:/dev/ttyACM0
+    Serial(String),
+    /// mqtt://host:port/root, through the serial-to-MQTT bridge
+    Mqtt {
+        host: String,
+        port: u16,
+        root: String,
+    },
+    /// tcp://host:port, i.e. a simulator or a ser2net bridge
+    Tcp(String),
+    /// pty, a new pseudo-terminal for a simulator to attach to
+    Pty,
+}
+
+impl FromStr for Transport {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s == "pty" {
+            return Ok(Transport::Pty);
+        }
+        if let Some(port) = s.strip_prefix("serial:") {
+            if port.is_empty() {
+                return Err(String::from("Missing the serial port"));
+            }
+            return Ok(Transport::Serial(String::from(port)));
+        }
+        if let Some(address) = s.strip_prefix("tcp://") {
+            if !address.contains(':') {
+                return Err(format!("Missing the port in '{}'", address));
+            }
+            return Ok(Transport::Tcp(String::from(address)));
+        }
+        if let Some(url) = s.strip_prefix("mqtt://") {
+            // The root topic can contain more levels
+            let (address, root) = match url.split_once('/') {
+                Some((address, root)) if !root.is_empty() => (address, root),
+                _ => return Err(format!("Missing the root topic in '{}'", url)),
+            };
+            let (host, port) = match address.split_once(':') {
+                Some((host, port)) => (
+                    host,
+                    u16::from_str(port).map_err(|_| format!("Invalid port '{}'", port))?,
+                ),
+                None => (address, DEFAULT_MQTT_PORT),
+            };
+            return Ok(Transport::Mqtt {
+                host: String::from(host),
+                port: port,
+                root: String::from(root),
+            });
+        }
+        Err(format!(
+            "Unknown transport '{}' (serial:<port>, mqtt://<host>:<port>/<root>, tcp://<host>:<port>, pty)",
+            s
+        ))
+    }
+}
+
+impl fmt::Display for Transport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Transport::Serial(port) => write!(f, "serial:{}", port),
+            Transport::Mqtt { host, port, root } => write!(f, "mqtt://{}:{}/{}", host, port, root),
+            Transport::Tcp(address) => write!(f, "tcp://{}", address),
+            Transport::Pty => write!(f, "pty"),
+        }
+    }
+}
+
+impl Transport {
+    /// Starts moving data between the channels and the device
+    pub fn start(
+        &self,
+        channel_in_producer: Sender<u8>,
+        channel_out_consumer: Receiver<Vec<u8>>,
+    ) -> (JoinHandle<()>, JoinHandle<()>) {
+        match self {
+            Transport::Serial(port) => {
+                serial_start(port.clone(), channel_in_producer, channel_out_consumer)
+            }
+            Transport::Mqtt { host, port, root } => mqtt_start(
+                host.clone(),
+                *port,
+                root.clone(),
+                channel_in_producer,
+                channel_out_consumer,
+            ),
+            Transport::Tcp(address) => {
+                tcp_start(address.clone(), channel_in_producer, channel_out_consumer)
+            }
+            Transport::Pty => {
+                let (path, handles) = pty_start(channel_in_producer, channel_out_consumer);
+                println!("Waiting for the device on {}", path);
+                handles
+            }
+        }
+    }
+}
+
+/// Moves the bytes of a stream (i.e. a serial port or a socket) to and from the channels
+fn stream_start<R, W>(
+    mut in_stream: R,
+    mut out_stream: W,
+    channel_in_producer: Sender<u8>,
+    channel_out_consumer: Receiver<Vec<u8>>,
+) -> (JoinHandle<()>, JoinHandle<()>)
+where
+    R: Read + Send + 'static,
+    W: Write + Send + 'static,
+{
+    let send_handler = thread::spawn(move || {
+        loop {
+            // Wait for data
+            let data_res = channel_out_consumer.recv();
+            if data_res.is_err() {
+                return; // Exit thread
+            }
+            // Write data
+            let data = data_res.unwrap();
+            out_stream.write_all(&data).unwrap();
+            out_stream.flush().unwrap();
+        }
+    });
+    let receive_handle = thread::spawn(move || {
+        let mut data: [u8; 100] = [0x00; 100];
+        loop {
+            // Wait for data on the stream
+            let read = in_stream.read(&mut data);
+            if read.is_err() {
+                return; // Exit thread
+            }
+            let to_read = read.unwrap();
+            // Copy all this data on the channel
+            for b in &data[0..to_read] {
+                if channel_in_producer.send(*b).is_err() {
+                    return; // Exit thread
+                }
+            }
+        }
+    });
+    return (send_handler, receive_handle);
+}
+
+/*
+    Tests
+*/
+#[cfg(test)]
+mod test {
+    use std::fs::OpenOptions;
+    use std::io::{Read, Write};
+    use std::net::TcpListener;
+
+    use super::*;
+
+    #[test]
+    fn parse() {
+        assert_eq!(
+            Transport::from_str("serial:/dev/ttyACM0"),
+            Ok(Transport::Serial(String::from("/dev/ttyACM0")))
+        );
+        assert_eq!(
+            Transport::from_str("mqtt://broker:1884/cOS/app"),
+            Ok(Transport::Mqtt {
+                host: String::from("broker"),
+                port: 1884,
+                root: String::from("cOS/app")
+            })
+        );
+        assert_eq!(
+            Transport::from_str("mqtt://broker/cOS")
+                .unwrap()
+                .to_string(),
+            "mqtt://broker:1883/cOS"
+        );
+        assert_eq!(
+            Transport::from_str("tcp://localhost:4000"),
+            Ok(Transport::Tcp(String::from("localhost:4000")))
+        );
+        assert_eq!(Transport::from_str("pty"), Ok(Transport::Pty));
+        assert!(Transport::from_str("mqtt://broker:1883").is_err());
+        assert!(Transport::from_str("tcp://localhost").is_err());
+        assert!(Transport::from_str("/dev/ttyACM0").is_err());
+    }
+
+    #[test]
+    fn tcp_round_trip() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let transport = Transport::Tcp(listener.local_addr().unwrap().to_string());
+        let (channel_in_producer, channel_in_consumer) = crossbeam_channel::bounded::<u8>(100);
+        let (channel_out_producer, channel_out_consumer) =
+            crossbeam_channel::bounded::<Vec<u8>>(100);
+        transport.start(channel_in_producer, channel_out_consumer);
+        let (mut device, _) = listener.accept().unwrap();
+        // Tool -> device
+        channel_out_producer.send(vec![0x01, 0x02, 0x03]).unwrap();
+        let mut buff: [u8; 3] = [0x00; 3];
+        device.read_exact(&mut buff).unwrap();
+        assert_eq!(buff, [0x01, 0x02, 0x03]);
+        // Device -> tool
+        device.write_all(&[0xCA, 0xFE]).unwrap();
+        assert_eq!(channel_in_consumer.recv().unwrap(), 0xCA);
+        assert_eq!(channel_in_consumer.recv().unwrap(), 0xFE);
+    }
+
+    #[test]
+    fn pty_round_trip() {
+        let (channel_in_producer, channel_in_consumer) = crossbeam_channel::bounded::<u8>(100);
+        let (channel_out_producer, channel_out_consumer) =
+            crossbeam_channel::bounded::<Vec<u8>>(100);
+        let (path, _) = pty_start(channel_in_producer, channel_out_consumer);
+        let mut device = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open(path)
+            .unwrap();
+        channel_out_producer.send(vec![0xC8, 0x00]).unwrap();
+        let mut buff: [u8; 2] = [0x00; 2];
+        device.read_exact(&mut buff).unwrap();
+        assert_eq!(buff, [0xC8, 0x00]);
+        device.write_all(&[0xE7]).unwrap();
+        assert_eq!(channel_in_consumer.recv().unwrap(), 0xE7);
+    }
+}
@@ -0,0 +1,89 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use std::{
+    process,
+    sync::atomic::{AtomicUsize, Ordering},
+    thread::{self, JoinHandle},
+    time::Duration,
+};
+
+use crossbeam_channel::{Receiver, Sender};
+use rumqttc::{Client, MqttOptions};
+
+const UPDATE_COMPONENT_ID: u16 = 5;
+
+/// Connections opened by this process, to give each one its own client id
+static CONNECTIONS: AtomicUsize = AtomicUsize::new(0);
+
+/// Connect to MQTT server
+pub fn mqtt_start(
+    server_ip: String,
+    server_port: u16,
+    mqtt_root: String,
+    channel_in_producer: Sender<u8>,
+    channel_out_consumer: Receiver<Vec<u8>>,
+) -> (JoinHandle<()>, JoinHandle<()>) {
+    // The broker drops a client when another one connects with the same id
+    let client_id = format!(
+        "update-tool-{}-{}",
+        process::id(),
+        CONNECTIONS.fetch_add(1, Ordering::Relaxed)
+    );
+    let mut mqtt_options = MqttOptions::new(client_id, server_ip, server_port);
+    mqtt_options.set_keep_alive(Duration::from_secs(30));
+    // Create client and connect
+    let (mut client, mut connection) = Client::new(mqtt_options, 10);
+    // Now, first subscribe
+    client
+        .subscribe(
+            format!("{}/{}/out", mqtt_root, UPDATE_COMPONENT_ID),
+            rumqttc::QoS::ExactlyOnce,
+        )
+        .unwrap();
+    // Then create a new thread for sending data
+    let send_handle = thread::spawn(move || {
+        loop {
+            // Read new data
+            let result = channel_out_consumer.recv();
+            if result.is_err() {
+                //println!("Exiting MQTT sender");
+                return; // Exit the current thread
+            }
+            // Send data
+            let data = result.unwrap();
+            client
+                .publish(
+                    format!("{}/{}/in", mqtt_root, UPDATE_COMPONENT_ID),
+                    rumqttc::QoS::ExactlyOnce,
+                    false,
+                    data,
+                )
+                .unwrap();
+        }
+    });
+    // Finally, create another thread to receive data/send packets
+    let receive_handle = thread::spawn(move || {
+        for (_, notification) in connection.iter().enumerate() {
+            let event = match notification {
+                Ok(event) => event,
+                Err(e) => {
+                    // Dropping the channel fails whoever is waiting for the device
+                    eprintln!("MQTT Connection failed: {}", e);
+                    return;
+                }
+            };
+            if let rumqttc::Event::Incoming(rumqttc::Packet::Publish(data)) = event {
+                // Add data to queue
+                for b in data.payload {
+                    if channel_in_producer.send(b).is_err() {
+                        //println!("Exiting MQTT receiver");
+                        return; // Exit thread
+                    }
+                }
+            }
+        }
+    });
+    return (send_handle, receive_handle);
+}
@@ -0,0 +1,69 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use std::{
+    fs::File,
+    io::{BufReader, BufWriter},
+    os::unix::prelude::{FromRawFd, IntoRawFd, RawFd},
+    thread::JoinHandle,
+};
+
+use crossbeam_channel::{Receiver, Sender};
+use serialport::{SerialPort, TTYPort};
+
+use super::stream_start;
+
+pub const SERIAL_BAUDRATE: u32 = 115_200;
+
+pub fn serial_start(
+    serial_port: String,
+    channel_in_producer: Sender<u8>,
+    channel_out_consumer: Receiver<Vec<u8>>,
+) -> (JoinHandle<()>, JoinHandle<()>) {
+    let serial_port = serialport::new(&serial_port.clone(), SERIAL_BAUDRATE)
+        .open_native()
+        .expect("Cannot open serial port");
+    tty_start(serial_port, channel_in_producer, channel_out_consumer)
+}
+
+/// Creates a pseudo-terminal, returning the path the device must open
+pub fn pty_start(
+    channel_in_producer: Sender<u8>,
+    channel_out_consumer: Receiver<Vec<u8>>,
+) -> (String, (JoinHandle<()>, JoinHandle<()>)) {
+    let (master, slave) = TTYPort::pair().expect("Cannot create a pseudo-terminal");
+    let path = slave
+        .name()
+        .expect("Cannot get the name of the pseudo-terminal");
+    drop(slave);
+    // Opening the slave as a serial port puts it in raw mode. It stays open, otherwise the
+    // master would fail to read until the device opens it, but not exclusive.
+    let mut slave = serialport::new(&path, SERIAL_BAUDRATE)
+        .open_native()
+        .expect("Cannot open the pseudo-terminal");
+    slave
+        .set_exclusive(false)
+        .expect("Cannot share the pseudo-terminal");
+    let handles = tty_start(master, channel_in_producer, channel_out_consumer);
+    std::mem::forget(slave);
+    (path, handles)
+}
+
+fn tty_start(
+    port: TTYPort,
+    channel_in_producer: Sender<u8>,
+    channel_out_consumer: Receiver<Vec<u8>>,
+) -> (JoinHandle<()>, JoinHandle<()>) {
+    // There is no method for waiting data
+    let raw_channel: RawFd = port.into_raw_fd();
+    let in_raw_file = unsafe { File::from_raw_fd(raw_channel) };
+    let out_raw_file = in_raw_file.try_clone().unwrap();
+
+    stream_start(
+        BufReader::new(in_raw_file),
+        BufWriter::new(out_raw_file),
+        channel_in_producer,
+        channel_out_consumer,
+    )
+}
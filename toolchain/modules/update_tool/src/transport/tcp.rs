@@ -0,0 +1,44 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use std::{
+    io::{self, BufWriter, Read},
+    net::TcpStream,
+    thread::JoinHandle,
+};
+
+use crossbeam_channel::{Receiver, Sender};
+
+use super::stream_start;
+
+/// Connects to a raw TCP socket carrying the bytes of the serial line
+pub fn tcp_start(
+    address: String,
+    channel_in_producer: Sender<u8>,
+    channel_out_consumer: Receiver<Vec<u8>>,
+) -> (JoinHandle<()>, JoinHandle<()>) {
+    let stream = TcpStream::connect(&address)
+        .unwrap_or_else(|e| panic!("Cannot connect to '{}': {}", address, e));
+    // Control frames are small and latency bound
+    stream.set_nodelay(true).unwrap();
+    let out_stream = stream.try_clone().unwrap();
+    stream_start(
+        SocketReader(stream),
+        BufWriter::new(out_stream),
+        channel_in_producer,
+        channel_out_consumer,
+    )
+}
+
+/// A closed socket reads 0 bytes, it must stop the transport as an error does
+struct SocketReader(TcpStream);
+
+impl Read for SocketReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self.0.read(buf)? {
+            0 => Err(io::ErrorKind::UnexpectedEof.into()),
+            len => Ok(len),
+        }
+    }
+}
@@ -2,29 +2,57 @@
 // License, v. 2.0. If a copy of the MPL was not distributed with this
 // file, You can obtain one at https://mozilla.org/MPL/2.0/.
 
+use std::{fmt, time::Duration};
+
 use crossbeam_channel::{Sender, Receiver};
 
-pub fn channel_write(mqtt_out_producer: &Sender<Vec<u8>>, buffer: &[u8]) {
-    mqtt_out_producer.send(buffer.to_vec()).unwrap();
+/// The other end of a channel is gone: the device, or the connection to it
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ChannelClosed;
+
+impl fmt::Display for ChannelClosed {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Connection to the device lost")
+    }
+}
+
+pub fn channel_write(
+    mqtt_out_producer: &Sender<Vec<u8>>,
+    buffer: &[u8],
+) -> Result<(), ChannelClosed> {
+    mqtt_out_producer
+        .send(buffer.to_vec())
+        .map_err(|_| ChannelClosed)
 }
 
 pub fn channel_flush_read(mqtt_in_consumer: &Receiver<u8>) {
-    while !mqtt_in_consumer.is_empty() {
-        mqtt_in_consumer.recv().unwrap();
-    }
+    while mqtt_in_consumer.try_recv().is_ok() {}
 }
 
-pub fn channel_read(mqtt_in_consumer: &Receiver<u8>, buffer: &mut [u8]) {
+pub fn channel_read(
+    mqtt_in_consumer: &Receiver<u8>,
+    buffer: &mut [u8],
+) -> Result<(), ChannelClosed> {
     // Wait to have enough bytes
-    let mut pos: usize = 0;
-    loop {
-        let data = mqtt_in_consumer.recv().unwrap();
-        buffer[pos] = data;
-        pos += 1;
-        if pos == buffer.len() {
-            return;
+    for b in buffer.iter_mut() {
+        *b = mqtt_in_consumer.recv().map_err(|_| ChannelClosed)?;
+    }
+    Ok(())
+}
+
+/// Like channel_read, but gives up if a byte does not arrive in time
+pub fn channel_read_timeout(
+    mqtt_in_consumer: &Receiver<u8>,
+    buffer: &mut [u8],
+    timeout: Duration,
+) -> bool {
+    for pos in 0..buffer.len() {
+        match mqtt_in_consumer.recv_timeout(timeout) {
+            Ok(data) => buffer[pos] = data,
+            Err(_) => return false,
         }
     }
+    return true;
 }
 
 pub fn u32_from_le_bytes(buffer: &[u8]) -> u32 {
@@ -35,7 +63,6 @@ pub fn u16_from_le_bytes(buffer: &[u8]) -> u16 {
     return (buffer[0] as u16) | (buffer[1] as u16) << 8;
 }
 
-#[cfg(feature = "uart")]
 pub fn openocd_board_to_chip(board: &String) -> String {
     // Try to extract the type (stm32f303re -> stm32 f3 -> stm32f3x.cfg)
     let board_line = &board[5..5 + 2];
@@ -29,6 +29,18 @@ def save_toml(file_name: str, content: TomlConfig):
         file.write(to_toml(content))
 
 
+# Format strings of the deferred logs are never loaded,
+# their addresses are indices that must not be relocated
+LOG_SECTION = '.concept_log'
+
+
+def is_log_symbol(elf_file, symtab, sym_index):
+    symbol = symtab.get_symbol(sym_index)
+    if not isinstance(symbol['st_shndx'], int):
+        return False  # Special section index (i.e. SHN_UNDEF)
+    return elf_file.get_section(symbol['st_shndx']).name.startswith(LOG_SECTION)
+
+
 def read_relocations(elf_file, section_name):
     # Read the obj file
     relocs = []
@@ -41,7 +53,10 @@ def read_relocations(elf_file, section_name):
             if not isinstance(section, RelocationSection):
                 continue
             if section.name == '.rel' + section_name:
+                symtab = elf_file.get_section(section['sh_link'])
                 for rel in section.iter_relocations():
+                    if is_log_symbol(elf_file, symtab, rel['r_info_sym']):
+                        continue
                     if rel['r_info_type'] == 2:  # R_ARM_ABS32
                         relocs.append({
                             'r_type': 0,  # 'R_ARM_ABS32',
@@ -1,26 +0,0 @@
-import datetime
-
-from .client import Client, Message, Subscription
-from .mqtt import constants
-from .mqtt.protocol import BaseMQTTProtocol
-from .mqtt.handler import MQTTConnectError
-
-__author__ = "Mikhail Turchunovich"
-__email__ = 'mitu@gurtam.com'
-__copyright__ = ("Copyright 2013-%d, Gurtam; " % datetime.datetime.now().year,)
-
-__credits__ = [
-    "Mikhail Turchunovich",
-    "Elena Nikolaichik"
-]
-__version__ = "0.6.7"
-
-
-__all__ = [
-    'Client',
-    'Message',
-    'Subscription',
-    'BaseMQTTProtocol',
-    'MQTTConnectError',
-    'constants'
-]